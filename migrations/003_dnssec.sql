@@ -0,0 +1,19 @@
+-- DNSSEC online signing support
+
+ALTER TABLE dns_zones ADD COLUMN IF NOT EXISTS dnssec_enabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Signing keys per zone (KSK flags 257, ZSK flags 256)
+CREATE TABLE IF NOT EXISTS dnssec_keys (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    zone_id UUID NOT NULL REFERENCES dns_zones(id) ON DELETE CASCADE,
+    key_type VARCHAR(3) NOT NULL CHECK (key_type IN ('KSK', 'ZSK')),
+    algorithm SMALLINT NOT NULL DEFAULT 13,
+    flags INTEGER NOT NULL,
+    key_tag INTEGER NOT NULL,
+    private_key BYTEA NOT NULL,
+    public_key BYTEA NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_dnssec_keys_zone ON dnssec_keys(zone_id) WHERE active;
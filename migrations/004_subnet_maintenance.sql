@@ -0,0 +1,3 @@
+-- Maintenance mode: keep renewing existing leases but stop handing out new ones
+
+ALTER TABLE dhcp_subnets ADD COLUMN IF NOT EXISTS maintenance BOOLEAN NOT NULL DEFAULT FALSE;
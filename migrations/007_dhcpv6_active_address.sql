@@ -0,0 +1,4 @@
+-- An address can only be actively leased to one client at a time
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_dhcpv6_leases_active_address
+    ON dhcpv6_leases(ipv6_address) WHERE state = 'active';
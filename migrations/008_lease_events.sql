@@ -0,0 +1,16 @@
+-- Append-only history of DHCP lease state transitions.
+-- No foreign key on lease_id: the history has to outlive the lease row.
+
+CREATE TABLE IF NOT EXISTS lease_events (
+    id BIGSERIAL PRIMARY KEY,
+    lease_id UUID NOT NULL,
+    mac_address BYTEA NOT NULL,
+    ip_address INET NOT NULL,
+    old_state VARCHAR(20),
+    new_state VARCHAR(20) NOT NULL,
+    source VARCHAR(10) NOT NULL CHECK (source IN ('dhcp', 'api')),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_lease_events_lease ON lease_events(lease_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_lease_events_ip ON lease_events(ip_address, created_at);
@@ -0,0 +1,13 @@
+-- Addresses a client reported as already in use (DHCPDECLINE).
+-- They are skipped by allocation until dhcp.decline_time has passed since declined_at.
+
+CREATE TABLE IF NOT EXISTS declined_addresses (
+    subnet_id UUID NOT NULL REFERENCES dhcp_subnets(id) ON DELETE CASCADE,
+    ip_address INET NOT NULL,
+    mac_address BYTEA,
+    declined_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (subnet_id, ip_address)
+);
+
+CREATE INDEX IF NOT EXISTS idx_declined_addresses_declined_at ON declined_addresses(declined_at);
@@ -0,0 +1,7 @@
+-- Relayed DHCP clients whose option 82 circuit-id matches this value are served from the subnet,
+-- regardless of the relay's giaddr. Lets switches assign VLANs per port.
+
+ALTER TABLE dhcp_subnets ADD COLUMN IF NOT EXISTS relay_circuit_id VARCHAR(255);
+
+CREATE INDEX IF NOT EXISTS idx_dhcp_subnets_relay_circuit_id ON dhcp_subnets(relay_circuit_id)
+    WHERE relay_circuit_id IS NOT NULL;
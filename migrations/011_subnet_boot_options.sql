@@ -0,0 +1,8 @@
+-- Network boot (PXE) settings per subnet. boot_server is the next-server handed out in siaddr and
+-- option 66; boot_filename is option 67. boot_rules is a list of
+-- {"vendor_class": "...", "arch": N, "filename": "..."} objects tried in order before the default,
+-- so UEFI and BIOS clients (option 93) or particular vendor classes (option 60) get different files.
+
+ALTER TABLE dhcp_subnets ADD COLUMN IF NOT EXISTS boot_server INET;
+ALTER TABLE dhcp_subnets ADD COLUMN IF NOT EXISTS boot_filename VARCHAR(255);
+ALTER TABLE dhcp_subnets ADD COLUMN IF NOT EXISTS boot_rules JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,19 @@
+-- An IPv4 address can only be actively leased to one client at a time, so concurrent
+-- allocations can't hand out the same address
+
+-- Keep only the newest active lease where earlier races already produced duplicates
+UPDATE dhcp_leases
+SET state = 'expired', updated_at = NOW()
+WHERE id IN (
+    SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (
+            PARTITION BY subnet_id, ip_address ORDER BY lease_start DESC, id
+        ) AS position
+        FROM dhcp_leases
+        WHERE state = 'active'
+    ) ranked
+    WHERE position > 1
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_dhcp_leases_active_address
+    ON dhcp_leases(subnet_id, ip_address) WHERE state = 'active';
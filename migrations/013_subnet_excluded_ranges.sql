@@ -0,0 +1,4 @@
+-- Addresses inside the pool that are never handed out, as a list of strings: a single address
+-- ("10.0.0.5"), an inclusive range ("10.0.0.20-10.0.0.30") or a CIDR block ("10.0.0.64/28")
+
+ALTER TABLE dhcp_subnets ADD COLUMN IF NOT EXISTS excluded_ranges JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,13 @@
+-- A client holds at most one lease per subnet rather than one overall, so a device attached to
+-- several subnets (e.g. tagged and untagged VLANs on one NIC) keeps a lease in each. Renewing
+-- in one subnet no longer moves the lease it holds in another.
+
+ALTER TABLE dhcp_leases DROP CONSTRAINT IF EXISTS dhcp_leases_mac_address_key;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'dhcp_leases_subnet_id_mac_address_key') THEN
+        ALTER TABLE dhcp_leases
+            ADD CONSTRAINT dhcp_leases_subnet_id_mac_address_key UNIQUE (subnet_id, mac_address);
+    END IF;
+END $$;
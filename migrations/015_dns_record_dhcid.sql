@@ -0,0 +1,4 @@
+-- DHCID (RFC 4701, base64 RDATA) of the DHCP client that registered a dynamic record. A name
+-- owned by one client is not updated or removed on behalf of another (RFC 4703).
+
+ALTER TABLE dns_records ADD COLUMN IF NOT EXISTS dhcid TEXT;
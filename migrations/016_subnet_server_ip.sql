@@ -0,0 +1,4 @@
+-- Server identifier (option 54) handed to clients of the subnet. NULL derives it from the
+-- address the server listens on or the local address that reaches the subnet.
+
+ALTER TABLE dhcp_subnets ADD COLUMN IF NOT EXISTS server_ip INET;
@@ -0,0 +1,5 @@
+-- Extra DHCP options sent to the subnet's clients, keyed by option code, e.g.
+-- {"42": {"ips": ["192.168.1.1"]}, "252": {"string": "http://wpad/wpad.dat"}}.
+-- A custom option replaces the server's own option with the same code.
+
+ALTER TABLE dhcp_subnets ADD COLUMN IF NOT EXISTS dhcp_options JSONB NOT NULL DEFAULT '{}';
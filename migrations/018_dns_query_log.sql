@@ -0,0 +1,15 @@
+-- Sampled DNS queries, written in batches when dns.query_log.sample_rate is above zero.
+-- Rows are never pruned by the server; trim old ones by queried_at as needed.
+
+CREATE TABLE IF NOT EXISTS dns_query_log (
+    id BIGSERIAL PRIMARY KEY,
+    queried_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    client_ip INET NOT NULL,
+    qname TEXT NOT NULL,
+    qtype INTEGER NOT NULL,
+    rcode SMALLINT NOT NULL,
+    cached BOOLEAN NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_dns_query_log_time ON dns_query_log(queried_at);
+CREATE INDEX IF NOT EXISTS idx_dns_query_log_qname ON dns_query_log(qname, queried_at);
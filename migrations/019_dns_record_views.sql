@@ -0,0 +1,10 @@
+-- Split-horizon DNS: each record belongs to a view. Clients are mapped to views by
+-- source address (dns.views); records in the 'default' view are served to everyone.
+
+ALTER TABLE dns_records ADD COLUMN IF NOT EXISTS view VARCHAR(63) NOT NULL DEFAULT 'default';
+
+-- The same record may exist once per view
+ALTER TABLE dns_records DROP CONSTRAINT IF EXISTS dns_records_zone_id_name_record_type_value_key;
+ALTER TABLE dns_records DROP CONSTRAINT IF EXISTS dns_records_zone_id_name_record_type_value_view_key;
+ALTER TABLE dns_records ADD CONSTRAINT dns_records_zone_id_name_record_type_value_view_key
+    UNIQUE (zone_id, name, record_type, value, view);
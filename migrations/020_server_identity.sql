@@ -0,0 +1,8 @@
+-- Identifiers the server must keep across restarts, such as its DHCPv6 DUID (RFC 8415
+-- section 11: a server's DUID must not change).
+
+CREATE TABLE IF NOT EXISTS server_identity (
+    name VARCHAR(50) PRIMARY KEY,
+    value BYTEA NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+-- Answer DHCPv6 SOLICITs carrying Rapid Commit with an immediate REPLY (RFC 8415 section
+-- 18.3.1). Off by default: only enable it where a single DHCPv6 server serves the link.
+
+ALTER TABLE dhcp_subnets ADD COLUMN IF NOT EXISTS dhcpv6_rapid_commit BOOLEAN DEFAULT FALSE;
@@ -1,17 +1,35 @@
-use actix_web::{dev::ServiceRequest, Error, HttpMessage};
+use actix_web::{dev::Payload, dev::ServiceRequest, web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::error::{ErrorInternalServerError, InternalError};
 use actix_web_httpauth::extractors::bearer::{BearerAuth, Config};
 use actix_web_httpauth::extractors::AuthenticationError;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::future::{ready, Ready};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::api::server::ApiState;
+use crate::api::users;
+
+/// Shortest `api.jwt_secret` accepted for signing tokens
+pub const MIN_JWT_SECRET_LEN: usize = 32;
+
+/// Access tokens authorize API calls; refresh tokens are only accepted by /auth/refresh
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,        // Subject (user ID)
     pub exp: i64,           // Expiration time
     pub iat: i64,           // Issued at
     pub role: String,       // User role
+    pub jti: String,        // Token ID, used to revoke individual tokens
+    pub token_type: TokenType,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,7 +41,17 @@ pub struct TokenResponse {
 }
 
 impl Claims {
+    /// Claims for an access token
     pub fn new(user_id: Uuid, role: String, duration: Duration) -> Self {
+        Self::issue(user_id, role, duration, TokenType::Access)
+    }
+
+    /// Claims for a refresh token, which can only be exchanged for a new access token
+    pub fn refresh(user_id: Uuid, role: String, duration: Duration) -> Self {
+        Self::issue(user_id, role, duration, TokenType::Refresh)
+    }
+
+    fn issue(user_id: Uuid, role: String, duration: Duration, token_type: TokenType) -> Self {
         let now = Utc::now();
         let exp = now + duration;
 
@@ -32,8 +60,14 @@ impl Claims {
             exp: exp.timestamp(),
             iat: now.timestamp(),
             role,
+            jti: Uuid::new_v4().to_string(),
+            token_type,
         }
     }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.exp, 0).single().unwrap_or_else(Utc::now)
+    }
 }
 
 pub fn create_token(claims: &Claims, secret: &str) -> Result<String, jsonwebtoken::errors::Error> {
@@ -51,31 +85,162 @@ pub fn validate_token(token: &str, secret: &str) -> Result<Claims, jsonwebtoken:
     Ok(token_data.claims)
 }
 
+/// Returns the secret if it is long enough to sign tokens with, otherwise a 500
+pub fn checked_secret(secret: &str) -> Result<&str, Error> {
+    if secret.len() < MIN_JWT_SECRET_LEN {
+        return Err(ErrorInternalServerError(format!(
+            "JWT secret must be at least {} characters",
+            MIN_JWT_SECRET_LEN
+        )));
+    }
+    Ok(secret)
+}
+
 pub async fn validator(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
-    let config = Config::default();
+    let Some(state) = req.app_data::<web::Data<ApiState>>().cloned() else {
+        return Err((ErrorInternalServerError("API state not configured"), req));
+    };
+
+    let secret = match checked_secret(&state.settings.api.jwt_secret) {
+        Ok(secret) => secret,
+        Err(e) => return Err((e, req)),
+    };
 
-    // Get the JWT secret from app data (in production, this should come from config)
-    let secret = "your-secret-key"; // TODO: Get from settings
+    // Refresh tokens live longer and must not stand in for access tokens
+    let claims = match validate_token(credentials.token(), secret) {
+        Ok(claims) if claims.token_type == TokenType::Access => claims,
+        _ => {
+            let config = Config::default();
+            return Err((AuthenticationError::from(config).into(), req));
+        }
+    };
 
-    match validate_token(credentials.token(), secret) {
-        Ok(claims) => {
+    match users::is_token_revoked(&state.db, &claims.jti).await {
+        Ok(false) => {
             req.extensions_mut().insert(claims);
             Ok(req)
         }
-        Err(_) => {
+        Ok(true) => {
             let config = Config::default();
             Err((AuthenticationError::from(config).into(), req))
         }
+        Err(e) => Err((ErrorInternalServerError(format!("Database error: {}", e)), req)),
     }
 }
 
+pub const ROLE_ADMIN: &str = "admin";
+pub const ROLE_OPERATOR: &str = "operator";
+pub const ROLE_VIEWER: &str = "viewer";
+
+/// True when the request carries a valid token with one of `roles`
+pub fn has_role(req: &HttpRequest, roles: &[&str]) -> bool {
+    req.extensions()
+        .get::<Claims>()
+        .is_some_and(|claims| roles.contains(&claims.role.as_str()))
+}
+
+fn forbidden(message: &str) -> Error {
+    let response = HttpResponse::Forbidden().json(serde_json::json!({
+        "error": "forbidden",
+        "message": message
+    }));
+    InternalError::from_response(message.to_string(), response).into()
+}
+
+/// Extractor for destructive operations (deleting subnets, zones and records, creating
+/// subnets, backup and restore); only the admin role passes
+pub struct RequireAdmin;
+
+impl FromRequest for RequireAdmin {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(if has_role(req, &[ROLE_ADMIN]) {
+            Ok(RequireAdmin)
+        } else {
+            Err(forbidden("Admin role required"))
+        })
+    }
+}
+
+/// Extractor for every other change; admins and operators pass, while viewers and any
+/// other role are limited to GET requests
+pub struct RequireOperator;
+
+impl FromRequest for RequireOperator {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(if has_role(req, &[ROLE_ADMIN, ROLE_OPERATOR]) {
+            Ok(RequireOperator)
+        } else {
+            Err(forbidden("Read-only role; admin or operator role required"))
+        })
+    }
+}
+
+/// Hash of a throwaway password at `bcrypt::DEFAULT_COST`. Logins for unknown users are checked
+/// against it so they take as long as logins for real ones and don't reveal which names exist.
+pub const UNKNOWN_USER_HASH: &str = "$2b$12$qz2GLLX4AW58yVbkHVkT0eFebLfmnt5SFv8J8ed.i3sbw9jnuCaRG";
+
 pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
     bcrypt::hash(password, bcrypt::DEFAULT_COST)
 }
 
 pub fn verify_password(password: &str, hash: &str) -> Result<bool, bcrypt::BcryptError> {
     bcrypt::verify(password, hash)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_short_secrets() {
+        assert!(checked_secret("too-short").is_err());
+        assert!(checked_secret(&"x".repeat(MIN_JWT_SECRET_LEN)).is_ok());
+    }
+
+    #[test]
+    fn tokens_only_validate_with_the_signing_secret() {
+        let claims = Claims::new(Uuid::new_v4(), "admin".to_string(), Duration::hours(1));
+        let token = create_token(&claims, "first-secret-that-is-long-enough!").unwrap();
+
+        let decoded = validate_token(&token, "first-secret-that-is-long-enough!").unwrap();
+        assert_eq!(decoded.sub, claims.sub);
+        assert_eq!(decoded.jti, claims.jti);
+        assert!(validate_token(&token, "other-secret-that-is-long-enough!").is_err());
+    }
+
+    #[test]
+    fn each_token_gets_its_own_id() {
+        let user = Uuid::new_v4();
+        let first = Claims::new(user, "admin".to_string(), Duration::hours(1));
+        let second = Claims::new(user, "admin".to_string(), Duration::hours(1));
+        assert_ne!(first.jti, second.jti);
+    }
+
+    #[test]
+    fn unknown_user_hash_costs_as_much_as_real_ones() {
+        let cost: u32 = UNKNOWN_USER_HASH.split('$').nth(2).unwrap().parse().unwrap();
+        assert_eq!(cost, bcrypt::DEFAULT_COST);
+        assert!(!verify_password("", UNKNOWN_USER_HASH).unwrap());
+    }
+
+    #[test]
+    fn token_type_survives_signing() {
+        let secret = "first-secret-that-is-long-enough!";
+        let user = Uuid::new_v4();
+
+        let access = create_token(&Claims::new(user, "admin".to_string(), Duration::hours(1)), secret).unwrap();
+        let refresh = create_token(&Claims::refresh(user, "admin".to_string(), Duration::days(7)), secret).unwrap();
+
+        assert_eq!(validate_token(&access, secret).unwrap().token_type, TokenType::Access);
+        assert_eq!(validate_token(&refresh, secret).unwrap().token_type, TokenType::Refresh);
+    }
+}
@@ -0,0 +1,553 @@
+// Full configuration backup bundle and transactional restore
+use crate::database::models::{DhcpReservation, DhcpSubnet, DnsRecord, DnsZone};
+use crate::dhcp::lease_manager_queries;
+use crate::dns::zone_queries;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupSubnet {
+    #[serde(flatten)]
+    pub subnet: DhcpSubnet,
+    pub ipv6_enabled: Option<bool>,
+    pub ipv6_mode: Option<String>,
+    pub ra_managed: Option<bool>,
+    pub ra_other_config: Option<bool>,
+    #[serde(default)]
+    pub dhcpv6_rapid_commit: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupPrefixPool {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: IpAddr,
+    pub prefix_length: i16,
+    pub delegation_length: i16,
+    pub enabled: Option<bool>,
+}
+
+/// Everything needed to rebuild the server's configuration. Leases and dynamic DNS records are
+/// runtime state and aren't included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupBundle {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub subnets: Vec<BackupSubnet>,
+    pub reservations: Vec<DhcpReservation>,
+    pub zones: Vec<DnsZone>,
+    pub records: Vec<DnsRecord>,
+    pub prefix_pools: Vec<BackupPrefixPool>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreReport {
+    pub dry_run: bool,
+    pub subnets: usize,
+    pub reservations: usize,
+    pub zones: usize,
+    pub records: usize,
+    pub prefix_pools: usize,
+}
+
+pub async fn export(db: &PgPool) -> Result<BackupBundle> {
+    let subnet_rows = sqlx::query(
+        r#"
+        SELECT id, name, network, start_ip, end_ip, gateway,
+               dns_servers, domain_name, lease_duration, vlan_id,
+               ipv6_prefix, enabled, maintenance, relay_circuit_id, server_ip, boot_server, boot_filename,
+               boot_rules, excluded_ranges, dhcp_options, description, created_at, updated_at,
+               ipv6_enabled, ipv6_mode, ra_managed, ra_other_config, dhcpv6_rapid_commit
+        FROM dhcp_subnets
+        ORDER BY name
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut subnets = Vec::new();
+    for row in &subnet_rows {
+        subnets.push(BackupSubnet {
+            subnet: lease_manager_queries::subnet_from_row(row)?,
+            ipv6_enabled: row.get("ipv6_enabled"),
+            ipv6_mode: row.get("ipv6_mode"),
+            ra_managed: row.get("ra_managed"),
+            ra_other_config: row.get("ra_other_config"),
+            dhcpv6_rapid_commit: row.get("dhcpv6_rapid_commit"),
+        });
+    }
+
+    let reservation_rows = sqlx::query(
+        r#"
+        SELECT id, subnet_id, mac_address, ip_address, hostname, description, created_at
+        FROM dhcp_reservations
+        ORDER BY subnet_id, ip_address
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut reservations = Vec::new();
+    for row in reservation_rows {
+        reservations.push(DhcpReservation {
+            id: row.get("id"),
+            subnet_id: row.get("subnet_id"),
+            mac_address: row.get("mac_address"),
+            ip_address: row.get::<IpAddr, _>("ip_address").to_string().parse()?,
+            hostname: row.get("hostname"),
+            description: row.get("description"),
+            created_at: row.get("created_at"),
+        });
+    }
+
+    let zones = sqlx::query(
+        r#"
+        SELECT id, name, zone_type, primary_ns, admin_email, serial_number,
+               refresh_interval, retry_interval, expire_interval, minimum_ttl,
+               dnssec_enabled, created_at, updated_at
+        FROM dns_zones
+        ORDER BY name
+        "#
+    )
+    .fetch_all(db)
+    .await?
+    .iter()
+    .map(zone_queries::zone_from_row)
+    .collect();
+
+    let records = sqlx::query(
+        r#"
+        SELECT id, zone_id, name, record_type, value, ttl, priority, weight, port,
+               is_dynamic, view, created_at, updated_at
+        FROM dns_records
+        WHERE NOT is_dynamic
+        ORDER BY zone_id, name, record_type
+        "#
+    )
+    .fetch_all(db)
+    .await?
+    .iter()
+    .map(zone_queries::record_from_row)
+    .collect();
+
+    let prefix_pools = sqlx::query(
+        r#"
+        SELECT id, name, prefix, prefix_length, delegation_length, enabled
+        FROM ipv6_prefix_pools
+        ORDER BY name
+        "#
+    )
+    .fetch_all(db)
+    .await?
+    .iter()
+    .map(|row| BackupPrefixPool {
+        id: row.get("id"),
+        name: row.get("name"),
+        prefix: row.get("prefix"),
+        prefix_length: row.get("prefix_length"),
+        delegation_length: row.get("delegation_length"),
+        enabled: row.get("enabled"),
+    })
+    .collect();
+
+    Ok(BackupBundle {
+        version: BACKUP_FORMAT_VERSION,
+        created_at: Utc::now(),
+        subnets,
+        reservations,
+        zones,
+        records,
+        prefix_pools,
+    })
+}
+
+/// Checks the bundle for problems the database constraints wouldn't report clearly
+pub fn validate(bundle: &BackupBundle) -> Result<()> {
+    if bundle.version != BACKUP_FORMAT_VERSION {
+        return Err(anyhow!(
+            "Unsupported backup version {} (expected {})",
+            bundle.version,
+            BACKUP_FORMAT_VERSION
+        ));
+    }
+
+    for backup in &bundle.subnets {
+        let subnet = &backup.subnet;
+        for ip in [subnet.start_ip, subnet.end_ip, subnet.gateway] {
+            if !subnet.network.contains(IpAddr::V4(ip)) {
+                return Err(anyhow!("Subnet {}: {} is outside {}", subnet.name, ip, subnet.network));
+            }
+        }
+        if subnet.start_ip > subnet.end_ip {
+            return Err(anyhow!("Subnet {}: start_ip is after end_ip", subnet.name));
+        }
+        if let Err(e) = crate::config::check_excluded_ranges(&subnet.network, &subnet.excluded_ranges) {
+            return Err(anyhow!("Subnet {}: {}", subnet.name, e));
+        }
+        if let Err(e) = crate::config::check_dhcp_options(&subnet.dhcp_options) {
+            return Err(anyhow!("Subnet {}: {}", subnet.name, e));
+        }
+    }
+
+    for reservation in &bundle.reservations {
+        let subnet = bundle.subnets
+            .iter()
+            .find(|backup| backup.subnet.id == reservation.subnet_id)
+            .ok_or_else(|| anyhow!("Reservation {} refers to a subnet missing from the backup", reservation.id))?;
+        if !subnet.subnet.network.contains(IpAddr::V4(reservation.ip_address)) {
+            return Err(anyhow!(
+                "Reservation {} ({}) is outside subnet {}",
+                reservation.id, reservation.ip_address, subnet.subnet.name
+            ));
+        }
+    }
+
+    for record in &bundle.records {
+        if !bundle.zones.iter().any(|zone| zone.id == record.zone_id) {
+            return Err(anyhow!("Record {} refers to a zone missing from the backup", record.id));
+        }
+    }
+
+    Ok(())
+}
+
+/// Upserts every object in the bundle by id inside one transaction. With `dry_run` the
+/// transaction is rolled back, so constraint violations are still reported but nothing changes.
+pub async fn restore(db: &PgPool, bundle: &BackupBundle, dry_run: bool) -> Result<RestoreReport> {
+    validate(bundle)?;
+
+    let mut tx = db.begin().await?;
+
+    for zone in &bundle.zones {
+        restore_zone(&mut tx, zone).await?;
+    }
+    for record in &bundle.records {
+        restore_record(&mut tx, record).await?;
+    }
+    for subnet in &bundle.subnets {
+        restore_subnet(&mut tx, subnet).await?;
+    }
+    for reservation in &bundle.reservations {
+        restore_reservation(&mut tx, reservation).await?;
+    }
+    for pool in &bundle.prefix_pools {
+        restore_prefix_pool(&mut tx, pool).await?;
+    }
+
+    if dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+
+    Ok(RestoreReport {
+        dry_run,
+        subnets: bundle.subnets.len(),
+        reservations: bundle.reservations.len(),
+        zones: bundle.zones.len(),
+        records: bundle.records.len(),
+        prefix_pools: bundle.prefix_pools.len(),
+    })
+}
+
+async fn restore_zone(tx: &mut Transaction<'_, Postgres>, zone: &DnsZone) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO dns_zones (id, name, zone_type, primary_ns, admin_email, serial_number,
+                               refresh_interval, retry_interval, expire_interval, minimum_ttl,
+                               dnssec_enabled)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            zone_type = EXCLUDED.zone_type,
+            primary_ns = EXCLUDED.primary_ns,
+            admin_email = EXCLUDED.admin_email,
+            serial_number = GREATEST(dns_zones.serial_number + 1, EXCLUDED.serial_number),
+            refresh_interval = EXCLUDED.refresh_interval,
+            retry_interval = EXCLUDED.retry_interval,
+            expire_interval = EXCLUDED.expire_interval,
+            minimum_ttl = EXCLUDED.minimum_ttl,
+            dnssec_enabled = EXCLUDED.dnssec_enabled,
+            updated_at = NOW()
+        "#
+    )
+    .bind(zone.id)
+    .bind(&zone.name)
+    .bind(&zone.zone_type)
+    .bind(&zone.primary_ns)
+    .bind(&zone.admin_email)
+    .bind(zone.serial_number)
+    .bind(zone.refresh_interval)
+    .bind(zone.retry_interval)
+    .bind(zone.expire_interval)
+    .bind(zone.minimum_ttl)
+    .bind(zone.dnssec_enabled)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| anyhow!("Zone {}: {}", zone.name, e))?;
+
+    Ok(())
+}
+
+async fn restore_record(tx: &mut Transaction<'_, Postgres>, record: &DnsRecord) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO dns_records (id, zone_id, name, record_type, value, ttl, priority, weight, port, is_dynamic, view)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (id) DO UPDATE SET
+            zone_id = EXCLUDED.zone_id,
+            name = EXCLUDED.name,
+            record_type = EXCLUDED.record_type,
+            value = EXCLUDED.value,
+            ttl = EXCLUDED.ttl,
+            priority = EXCLUDED.priority,
+            weight = EXCLUDED.weight,
+            port = EXCLUDED.port,
+            is_dynamic = EXCLUDED.is_dynamic,
+            view = EXCLUDED.view,
+            updated_at = NOW()
+        "#
+    )
+    .bind(record.id)
+    .bind(record.zone_id)
+    .bind(&record.name)
+    .bind(&record.record_type)
+    .bind(&record.value)
+    .bind(record.ttl)
+    .bind(record.priority)
+    .bind(record.weight)
+    .bind(record.port)
+    .bind(record.is_dynamic)
+    .bind(&record.view)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| anyhow!("Record {} {}: {}", record.record_type, record.name, e))?;
+
+    Ok(())
+}
+
+async fn restore_subnet(tx: &mut Transaction<'_, Postgres>, backup: &BackupSubnet) -> Result<()> {
+    let subnet = &backup.subnet;
+
+    sqlx::query(
+        r#"
+        INSERT INTO dhcp_subnets (id, name, network, start_ip, end_ip, gateway, dns_servers,
+                                  domain_name, lease_duration, vlan_id, ipv6_prefix, enabled,
+                                  maintenance, description, ipv6_enabled, ipv6_mode, ra_managed,
+                                  ra_other_config, relay_circuit_id, server_ip, boot_server, boot_filename,
+                                  boot_rules, excluded_ranges, dhcp_options, dhcpv6_rapid_commit)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19,
+                $20, $21, $22, $23, $24, $25, $26)
+        ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            network = EXCLUDED.network,
+            start_ip = EXCLUDED.start_ip,
+            end_ip = EXCLUDED.end_ip,
+            gateway = EXCLUDED.gateway,
+            dns_servers = EXCLUDED.dns_servers,
+            domain_name = EXCLUDED.domain_name,
+            lease_duration = EXCLUDED.lease_duration,
+            vlan_id = EXCLUDED.vlan_id,
+            ipv6_prefix = EXCLUDED.ipv6_prefix,
+            enabled = EXCLUDED.enabled,
+            maintenance = EXCLUDED.maintenance,
+            description = EXCLUDED.description,
+            ipv6_enabled = EXCLUDED.ipv6_enabled,
+            ipv6_mode = EXCLUDED.ipv6_mode,
+            ra_managed = EXCLUDED.ra_managed,
+            ra_other_config = EXCLUDED.ra_other_config,
+            relay_circuit_id = EXCLUDED.relay_circuit_id,
+            server_ip = EXCLUDED.server_ip,
+            boot_server = EXCLUDED.boot_server,
+            boot_filename = EXCLUDED.boot_filename,
+            boot_rules = EXCLUDED.boot_rules,
+            excluded_ranges = EXCLUDED.excluded_ranges,
+            dhcp_options = EXCLUDED.dhcp_options,
+            dhcpv6_rapid_commit = EXCLUDED.dhcpv6_rapid_commit,
+            updated_at = NOW()
+        "#
+    )
+    .bind(subnet.id)
+    .bind(&subnet.name)
+    .bind(subnet.network)
+    .bind(IpAddr::V4(subnet.start_ip))
+    .bind(IpAddr::V4(subnet.end_ip))
+    .bind(IpAddr::V4(subnet.gateway))
+    .bind(serde_json::to_value(&subnet.dns_servers)?)
+    .bind(&subnet.domain_name)
+    .bind(subnet.lease_duration)
+    .bind(subnet.vlan_id)
+    .bind(subnet.ipv6_prefix)
+    .bind(subnet.enabled)
+    .bind(subnet.maintenance)
+    .bind(&subnet.description)
+    .bind(backup.ipv6_enabled)
+    .bind(&backup.ipv6_mode)
+    .bind(backup.ra_managed)
+    .bind(backup.ra_other_config)
+    .bind(&subnet.relay_circuit_id)
+    .bind(subnet.server_ip.map(IpAddr::V4))
+    .bind(subnet.boot_server.map(IpAddr::V4))
+    .bind(&subnet.boot_filename)
+    .bind(serde_json::to_value(&subnet.boot_rules)?)
+    .bind(serde_json::to_value(&subnet.excluded_ranges)?)
+    .bind(serde_json::to_value(&subnet.dhcp_options)?)
+    .bind(backup.dhcpv6_rapid_commit.unwrap_or(false))
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| anyhow!("Subnet {}: {}", subnet.name, e))?;
+
+    Ok(())
+}
+
+async fn restore_reservation(tx: &mut Transaction<'_, Postgres>, reservation: &DhcpReservation) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO dhcp_reservations (id, subnet_id, mac_address, ip_address, hostname, description)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (id) DO UPDATE SET
+            subnet_id = EXCLUDED.subnet_id,
+            mac_address = EXCLUDED.mac_address,
+            ip_address = EXCLUDED.ip_address,
+            hostname = EXCLUDED.hostname,
+            description = EXCLUDED.description
+        "#
+    )
+    .bind(reservation.id)
+    .bind(reservation.subnet_id)
+    .bind(&reservation.mac_address)
+    .bind(IpAddr::V4(reservation.ip_address))
+    .bind(&reservation.hostname)
+    .bind(&reservation.description)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| anyhow!("Reservation {}: {}", reservation.ip_address, e))?;
+
+    Ok(())
+}
+
+async fn restore_prefix_pool(tx: &mut Transaction<'_, Postgres>, pool: &BackupPrefixPool) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO ipv6_prefix_pools (id, name, prefix, prefix_length, delegation_length, enabled)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (id) DO UPDATE SET
+            name = EXCLUDED.name,
+            prefix = EXCLUDED.prefix,
+            prefix_length = EXCLUDED.prefix_length,
+            delegation_length = EXCLUDED.delegation_length,
+            enabled = EXCLUDED.enabled
+        "#
+    )
+    .bind(pool.id)
+    .bind(&pool.name)
+    .bind(pool.prefix)
+    .bind(pool.prefix_length)
+    .bind(pool.delegation_length)
+    .bind(pool.enabled)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| anyhow!("Prefix pool {}: {}", pool.name, e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::{BootRule, DhcpOptionValue};
+    use std::net::Ipv4Addr;
+
+    fn subnet(id: Uuid) -> BackupSubnet {
+        BackupSubnet {
+            subnet: DhcpSubnet {
+                id,
+                name: "office".to_string(),
+                network: "192.168.10.0/24".parse().unwrap(),
+                start_ip: Ipv4Addr::new(192, 168, 10, 100),
+                end_ip: Ipv4Addr::new(192, 168, 10, 200),
+                gateway: Ipv4Addr::new(192, 168, 10, 1),
+                dns_servers: vec![Ipv4Addr::new(192, 168, 10, 1)],
+                domain_name: Some("office.lan".to_string()),
+                lease_duration: 3600,
+                vlan_id: None,
+                ipv6_prefix: None,
+                enabled: true,
+                maintenance: false,
+                relay_circuit_id: None,
+                server_ip: Some(Ipv4Addr::new(192, 168, 10, 2)),
+                boot_server: Some(Ipv4Addr::new(192, 168, 10, 5)),
+                boot_filename: Some("pxelinux.0".to_string()),
+                boot_rules: vec![BootRule {
+                    vendor_class: None,
+                    arch: Some(7),
+                    filename: "efi/grubx64.efi".to_string(),
+                }],
+                excluded_ranges: vec!["192.168.10.150-192.168.10.159".parse().unwrap()],
+                dhcp_options: [(42, DhcpOptionValue::Ips(vec![Ipv4Addr::new(192, 168, 10, 1)]))].into(),
+                description: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            },
+            ipv6_enabled: Some(false),
+            ipv6_mode: Some("slaac".to_string()),
+            ra_managed: Some(false),
+            ra_other_config: Some(true),
+            dhcpv6_rapid_commit: Some(false),
+        }
+    }
+
+    fn bundle() -> BackupBundle {
+        BackupBundle {
+            version: BACKUP_FORMAT_VERSION,
+            created_at: Utc::now(),
+            subnets: vec![],
+            reservations: vec![],
+            zones: vec![],
+            records: vec![],
+            prefix_pools: vec![],
+        }
+    }
+
+    #[test]
+    fn test_bundle_json_round_trip() {
+        let mut original = bundle();
+        original.subnets.push(subnet(Uuid::new_v4()));
+
+        let json = serde_json::to_string(&original).unwrap();
+        let parsed: BackupBundle = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.subnets.len(), 1);
+        assert_eq!(parsed.subnets[0].subnet.name, "office");
+        assert_eq!(parsed.subnets[0].subnet.dns_servers, vec![Ipv4Addr::new(192, 168, 10, 1)]);
+        assert_eq!(parsed.subnets[0].ipv6_mode.as_deref(), Some("slaac"));
+        assert_eq!(parsed.subnets[0].subnet.boot_rules[0].arch, Some(7));
+        assert!(parsed.subnets[0].subnet.is_excluded(Ipv4Addr::new(192, 168, 10, 155)));
+        assert!(validate(&parsed).is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_dangling_references() {
+        let mut dangling = bundle();
+        dangling.reservations.push(DhcpReservation {
+            id: Uuid::new_v4(),
+            subnet_id: Uuid::new_v4(),
+            mac_address: vec![0, 1, 2, 3, 4, 5],
+            ip_address: Ipv4Addr::new(192, 168, 10, 5),
+            hostname: None,
+            description: None,
+            created_at: Utc::now(),
+        });
+        assert!(validate(&dangling).is_err());
+
+        let mut wrong_version = bundle();
+        wrong_version.version = BACKUP_FORMAT_VERSION + 1;
+        assert!(validate(&wrong_version).is_err());
+    }
+}
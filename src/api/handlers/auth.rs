@@ -1,55 +1,93 @@
-use actix_web::{web, HttpResponse};
-use crate::api::models::{LoginRequest, RefreshTokenRequest};
-use crate::api::auth::{Claims, TokenResponse, create_token, hash_password, verify_password};
+use actix_web::{http::header, web, HttpMessage, HttpRequest, HttpResponse};
+use crate::api::models::{LoginRequest, LogoutRequest, RefreshTokenRequest};
+use crate::api::auth::{Claims, TokenResponse, TokenType, UNKNOWN_USER_HASH, checked_secret, create_token, verify_password};
+use crate::api::users;
 use crate::api::server::ApiState;
 use uuid::Uuid;
 use chrono::Duration;
+use std::time::Instant;
 use tracing::{info, warn};
 
 pub async fn login(
     state: web::Data<ApiState>,
+    http_req: HttpRequest,
     req: web::Json<LoginRequest>,
 ) -> actix_web::Result<HttpResponse> {
-    // TODO: Implement proper user authentication from database
-    // For now, we'll use a hardcoded example
-
-    if req.username == "admin" && req.password == "admin123" {
-        // Create access token (expires in 1 hour)
-        let access_claims = Claims::new(
-            Uuid::new_v4(),
-            "admin".to_string(),
-            Duration::hours(1),
-        );
-
-        // Create refresh token (expires in 7 days)
-        let refresh_claims = Claims::new(
-            Uuid::new_v4(),
-            "admin".to_string(),
-            Duration::days(7),
-        );
-
-        let secret = "your-secret-key"; // TODO: Get from settings
-
-        let access_token = create_token(&access_claims, secret)
-            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to create token: {}", e)))?;
-
-        let refresh_token = create_token(&refresh_claims, secret)
-            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to create refresh token: {}", e)))?;
-
-        info!("User {} logged in successfully", req.username);
-
-        Ok(HttpResponse::Ok().json(TokenResponse {
-            access_token,
-            token_type: "Bearer".to_string(),
-            expires_in: 3600,
-            refresh_token: Some(refresh_token),
-        }))
-    } else {
-        warn!("Failed login attempt for user: {}", req.username);
-        Ok(HttpResponse::Unauthorized().json(serde_json::json!({
-            "error": "invalid_credentials",
-            "message": "Invalid username or password"
-        })))
+    // The peer address rather than X-Forwarded-For, which the client controls
+    let client_ip = http_req.peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let throttle_keys = [format!("ip:{}", client_ip), format!("user:{}", req.username)];
+
+    if let Some(retry_after) = state.login_throttle.retry_after(&throttle_keys, Instant::now()) {
+        warn!("Login for user {} from {} throttled", req.username, client_ip);
+        let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, seconds.to_string()))
+            .json(serde_json::json!({
+                "error": "too_many_attempts",
+                "message": format!("Too many failed logins, retry in {} seconds", seconds)
+            })));
+    }
+
+    let user = users::fetch_user_by_username(&state.db, &req.username)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    // Unknown users are checked against a dummy hash so they cost the same bcrypt round as
+    // known ones. bcrypt is deliberately slow, keep it off the async workers.
+    let password = req.password.clone();
+    let hash = user.as_ref()
+        .map(|user| user.password_hash.clone())
+        .unwrap_or_else(|| UNKNOWN_USER_HASH.to_string());
+    let verified = web::block(move || verify_password(&password, &hash))
+        .await?
+        .unwrap_or(false);
+    let authenticated = user.is_some() && verified;
+
+    match user.filter(|_| authenticated) {
+        Some(user) => {
+            let secret = checked_secret(&state.settings.api.jwt_secret)?;
+            let expires_in = state.settings.api.jwt_expiry as i64;
+
+            // Create access token (expires after api.jwt_expiry seconds)
+            let access_claims = Claims::new(
+                user.id,
+                user.role.clone(),
+                Duration::seconds(expires_in),
+            );
+
+            // Create refresh token (expires in 7 days)
+            let refresh_claims = Claims::refresh(
+                user.id,
+                user.role,
+                Duration::days(7),
+            );
+
+            let access_token = create_token(&access_claims, secret)
+                .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to create token: {}", e)))?;
+
+            let refresh_token = create_token(&refresh_claims, secret)
+                .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to create refresh token: {}", e)))?;
+
+            state.login_throttle.reset(&throttle_keys);
+            info!("User {} logged in successfully", req.username);
+
+            Ok(HttpResponse::Ok().json(TokenResponse {
+                access_token,
+                token_type: "Bearer".to_string(),
+                expires_in,
+                refresh_token: Some(refresh_token),
+            }))
+        }
+        None => {
+            state.login_throttle.record_failure(&throttle_keys, Instant::now());
+            warn!("Failed login attempt for user {} from {}", req.username, client_ip);
+            Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "invalid_credentials",
+                "message": "Invalid username or password"
+            })))
+        }
     }
 }
 
@@ -57,31 +95,50 @@ pub async fn refresh(
     state: web::Data<ApiState>,
     req: web::Json<RefreshTokenRequest>,
 ) -> actix_web::Result<HttpResponse> {
-    let secret = "your-secret-key"; // TODO: Get from settings
+    let secret = checked_secret(&state.settings.api.jwt_secret)?;
+    let expires_in = state.settings.api.jwt_expiry as i64;
 
-    // Validate refresh token
-    match crate::api::auth::validate_token(&req.refresh_token, secret) {
-        Ok(claims) => {
+    // Validate refresh token; access tokens are not accepted here
+    let claims = crate::api::auth::validate_token(&req.refresh_token, secret)
+        .ok()
+        .filter(|claims| claims.token_type == TokenType::Refresh);
+    let revoked = match &claims {
+        Some(claims) => users::is_token_revoked(&state.db, &claims.jti)
+            .await
+            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?,
+        None => false,
+    };
+
+    // The user may have been deleted or had their role changed since the token was issued
+    let user = match claims.filter(|_| !revoked).and_then(|claims| Uuid::parse_str(&claims.sub).ok()) {
+        Some(user_id) => users::fetch_user_by_id(&state.db, user_id)
+            .await
+            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?,
+        None => None,
+    };
+
+    match user {
+        Some(user) => {
             // Create new access token
             let new_claims = Claims::new(
-                Uuid::parse_str(&claims.sub).unwrap_or_else(|_| Uuid::new_v4()),
-                claims.role,
-                Duration::hours(1),
+                user.id,
+                user.role,
+                Duration::seconds(expires_in),
             );
 
             let access_token = create_token(&new_claims, secret)
                 .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to create token: {}", e)))?;
 
-            info!("Token refreshed for user: {}", claims.sub);
+            info!("Token refreshed for user: {}", user.username);
 
             Ok(HttpResponse::Ok().json(TokenResponse {
                 access_token,
                 token_type: "Bearer".to_string(),
-                expires_in: 3600,
+                expires_in,
                 refresh_token: None, // Don't issue new refresh token
             }))
         }
-        Err(_) => {
+        None => {
             warn!("Invalid refresh token attempted");
             Ok(HttpResponse::Unauthorized().json(serde_json::json!({
                 "error": "invalid_token",
@@ -89,4 +146,33 @@ pub async fn refresh(
             })))
         }
     }
-}
\ No newline at end of file
+}
+
+pub async fn logout(
+    state: web::Data<ApiState>,
+    http_req: HttpRequest,
+    req: Option<web::Json<LogoutRequest>>,
+) -> actix_web::Result<HttpResponse> {
+    // Set by auth::validator, which guards this route
+    let claims = http_req.extensions().get::<Claims>().cloned()
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing token"))?;
+
+    users::revoke_token(&state.db, &claims.jti, claims.expires_at())
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    if let Some(refresh_token) = req.and_then(|r| r.into_inner().refresh_token) {
+        let secret = checked_secret(&state.settings.api.jwt_secret)?;
+        match crate::api::auth::validate_token(&refresh_token, secret) {
+            Ok(refresh_claims) if refresh_claims.sub == claims.sub && refresh_claims.token_type == TokenType::Refresh => {
+                users::revoke_token(&state.db, &refresh_claims.jti, refresh_claims.expires_at())
+                    .await
+                    .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+            }
+            _ => warn!("Ignoring invalid refresh token on logout for user: {}", claims.sub),
+        }
+    }
+
+    info!("User {} logged out", claims.sub);
+    Ok(HttpResponse::NoContent().finish())
+}
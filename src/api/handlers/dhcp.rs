@@ -1,35 +1,170 @@
-// Simplified DHCP handlers that compile without database
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
+use crate::api::auth::{RequireAdmin, RequireOperator};
 use crate::api::models::*;
+use crate::api::queries::{self, CreateLeaseOutcome, CreateSubnetOutcome, ReserveLeaseOutcome, UpdateSubnetOutcome};
 use crate::api::server::ApiState;
 use crate::api::validators::*;
+use crate::database::models::DhcpLease;
+use crate::database::notify::{self, ConfigChange};
+use crate::dhcp::lease_events::{self, LeaseEventSource};
+use crate::dhcp::lease_manager_queries;
+use crate::dhcp::lease_migration::{self, ImportOutcome, MigrationEntry};
+use tokio::sync::broadcast::error::RecvError;
 use uuid::Uuid;
-use tracing::info;
+use tracing::{info, warn};
+
+/// Idle time after which the lease event stream sends a comment, so proxies keep it open
+const EVENT_STREAM_KEEPALIVE: std::time::Duration = std::time::Duration::from_secs(15);
 
 pub async fn list_leases(
-    _state: web::Data<ApiState>,
-    _query: web::Query<std::collections::HashMap<String, String>>,
+    state: web::Data<ApiState>,
+    query: web::Query<std::collections::HashMap<String, String>>,
 ) -> actix_web::Result<HttpResponse> {
-    // Simplified implementation - return empty list
-    let responses: Vec<LeaseResponse> = vec![];
-    Ok(HttpResponse::Ok().json(responses))
+    let filter = match parse_lease_filter(&query) {
+        Ok(filter) => filter,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_filter",
+            "message": message
+        }))),
+    };
+    let page = match parse_pagination(&query) {
+        Ok(page) => page,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_pagination",
+            "message": message
+        }))),
+    };
+
+    let (leases, total) = queries::fetch_active_leases(&state.db, &filter, page)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let responses: Vec<LeaseResponse> = leases
+        .into_iter()
+        .map(|lease| LeaseResponse {
+            id: lease.id,
+            subnet_id: lease.subnet_id,
+            mac_address: bytes_to_mac_string(&lease.mac_address),
+            ip_address: lease.ip_address,
+            hostname: lease.hostname,
+            lease_start: lease.lease_start,
+            lease_end: lease.lease_end,
+            state: lease.state,
+            client_identifier: lease.client_identifier,
+            vendor_class: lease.vendor_class,
+            user_class: lease.user_class,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .insert_header((TOTAL_COUNT_HEADER, total.to_string()))
+        .json(responses))
 }
 
 pub async fn get_lease(
-    _state: web::Data<ApiState>,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
 ) -> actix_web::Result<HttpResponse> {
     let lease_id = path.into_inner();
-    info!("Getting lease: {}", lease_id);
 
-    Ok(HttpResponse::NotFound().json(serde_json::json!({
-        "error": "not_found",
-        "message": "Lease not found"
-    })))
+    let lease = queries::fetch_lease_by_id(&state.db, lease_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match lease {
+        Some(lease) => Ok(HttpResponse::Ok().json(LeaseResponse {
+            id: lease.id,
+            subnet_id: lease.subnet_id,
+            mac_address: bytes_to_mac_string(&lease.mac_address),
+            ip_address: lease.ip_address,
+            hostname: lease.hostname,
+            lease_start: lease.lease_start,
+            lease_end: lease.lease_end,
+            state: lease.state,
+            client_identifier: lease.client_identifier,
+            vendor_class: lease.vendor_class,
+            user_class: lease.user_class,
+        })),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Lease not found"
+        }))),
+    }
+}
+
+/// Server-Sent Events stream of the lease transitions made by the DHCP server in this process
+pub async fn lease_event_stream(
+    state: web::Data<ApiState>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(lease_manager) = &state.lease_manager else {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "dhcp_disabled",
+            "message": "The DHCP server is not running in this process"
+        })));
+    };
+
+    let events = lease_manager.subscribe();
+    let stream = futures::stream::unfold(events, |mut events| async move {
+        let message = match tokio::time::timeout(EVENT_STREAM_KEEPALIVE, events.recv()).await {
+            Ok(Ok(event)) => event.to_sse(),
+            // The client fell behind; it should refetch the leases rather than trust its view
+            Ok(Err(RecvError::Lagged(missed))) => format!("event: lagged\ndata: {{\"missed\":{}}}\n\n", missed),
+            Ok(Err(RecvError::Closed)) => return None,
+            Err(_) => ": keep-alive\n\n".to_string(),
+        };
+        Some((Ok::<_, actix_web::Error>(web::Bytes::from(message)), events))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-cache"))
+        .streaming(stream))
+}
+
+pub async fn lease_history(
+    state: web::Data<ApiState>,
+    path: web::Path<Uuid>,
+) -> actix_web::Result<HttpResponse> {
+    let lease_id = path.into_inner();
+
+    let events = lease_events::fetch_history(&state.db, lease_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    // A lease turned into a reservation is gone but keeps its history
+    if events.is_empty() {
+        let lease = queries::fetch_lease_by_id(&state.db, lease_id)
+            .await
+            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+        if lease.is_none() {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "not_found",
+                "message": "Lease not found"
+            })));
+        }
+    }
+
+    let responses: Vec<LeaseEventResponse> = events
+        .into_iter()
+        .map(|event| LeaseEventResponse {
+            id: event.id,
+            lease_id: event.lease_id,
+            mac_address: bytes_to_mac_string(&event.mac_address),
+            ip_address: event.ip_address,
+            old_state: event.old_state,
+            new_state: event.new_state,
+            source: event.source,
+            created_at: event.created_at,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(responses))
 }
 
 pub async fn create_lease(
-    _state: web::Data<ApiState>,
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
     req: web::Json<CreateLeaseRequest>,
 ) -> actix_web::Result<HttpResponse> {
     if !validate_mac_address(&req.mac_address) {
@@ -39,48 +174,442 @@ pub async fn create_lease(
         })));
     }
 
-    info!("Creating lease for MAC: {}", req.mac_address);
+    let mac_bytes = mac_string_to_bytes(&req.mac_address)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid MAC address"))?;
 
-    Ok(HttpResponse::Created().json(serde_json::json!({
-        "message": "Lease creation initiated",
-        "mac_address": req.mac_address
-    })))
+    let outcome = queries::create_lease(
+        &state.db,
+        req.subnet_id,
+        &mac_bytes,
+        req.ip_address,
+        req.hostname.clone(),
+    )
+    .await
+    .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match outcome {
+        CreateLeaseOutcome::Created(lease) => {
+            info!("Created lease {} for MAC {} -> {}", lease.id, req.mac_address, lease.ip_address);
+            Ok(HttpResponse::Created().json(LeaseResponse {
+                id: lease.id,
+                subnet_id: lease.subnet_id,
+                mac_address: bytes_to_mac_string(&lease.mac_address),
+                ip_address: lease.ip_address,
+                hostname: lease.hostname,
+                lease_start: lease.lease_start,
+                lease_end: lease.lease_end,
+                state: lease.state,
+                client_identifier: lease.client_identifier,
+                vendor_class: lease.vendor_class,
+                user_class: lease.user_class,
+            }))
+        }
+        CreateLeaseOutcome::SubnetNotFound => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Subnet not found or disabled"
+        }))),
+        CreateLeaseOutcome::OutOfRange { start, end } => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "ip_out_of_range",
+            "message": format!("Requested address is outside the subnet range {} - {}", start, end)
+        }))),
+        CreateLeaseOutcome::Excluded => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "ip_excluded",
+            "message": "Requested address is in one of the subnet's excluded ranges"
+        }))),
+        CreateLeaseOutcome::InUse => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "ip_in_use",
+            "message": "Requested address is already leased or reserved"
+        }))),
+        CreateLeaseOutcome::Exhausted => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "subnet_exhausted",
+            "message": "No free addresses left in the subnet"
+        }))),
+    }
 }
 
 pub async fn release_lease(
-    _state: web::Data<ApiState>,
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
 ) -> actix_web::Result<HttpResponse> {
     let lease_id = path.into_inner();
-    info!("Released lease: {}", lease_id);
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Lease released successfully"
-    })))
+    let released = queries::release_lease(&state.db, lease_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    if released > 0 {
+        info!("Released lease: {}", lease_id);
+        Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Lease released successfully"
+        })))
+    } else {
+        Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Lease not found or already released"
+        })))
+    }
+}
+
+/// Ends an active lease now so the device has to ask for an address again
+pub async fn expire_lease(
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
+    path: web::Path<Uuid>,
+) -> actix_web::Result<HttpResponse> {
+    let lease_id = path.into_inner();
+
+    let lease = match &state.lease_manager {
+        Some(lease_manager) => lease_manager.expire_lease(lease_id, LeaseEventSource::Api).await,
+        None => lease_manager_queries::expire_lease(&state.db, lease_id, LeaseEventSource::Api).await,
+    }
+    .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match lease {
+        Some(lease) => {
+            info!("Expired lease: {}", lease_id);
+            Ok(HttpResponse::Ok().json(lease_response(lease)))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Lease not found or not active"
+        }))),
+    }
+}
+
+/// Keeps an active lease for `duration` more seconds
+pub async fn extend_lease(
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
+    path: web::Path<Uuid>,
+    req: web::Json<ExtendLeaseRequest>,
+) -> actix_web::Result<HttpResponse> {
+    let lease_id = path.into_inner();
+
+    if req.duration == 0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_duration",
+            "message": "duration must be at least one second"
+        })));
+    }
+
+    let lease = match &state.lease_manager {
+        Some(lease_manager) => lease_manager.extend_lease(lease_id, req.duration, LeaseEventSource::Api).await,
+        None => lease_manager_queries::extend_lease(&state.db, lease_id, req.duration, LeaseEventSource::Api).await,
+    }
+    .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match lease {
+        Some(lease) => {
+            info!("Extended lease {} until {}", lease_id, lease.lease_end);
+            Ok(HttpResponse::Ok().json(lease_response(lease)))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Lease not found or not active"
+        }))),
+    }
+}
+
+fn lease_response(lease: DhcpLease) -> LeaseResponse {
+    LeaseResponse {
+        id: lease.id,
+        subnet_id: lease.subnet_id,
+        mac_address: bytes_to_mac_string(&lease.mac_address),
+        ip_address: lease.ip_address,
+        hostname: lease.hostname,
+        lease_start: lease.lease_start,
+        lease_end: lease.lease_end,
+        state: lease.state,
+        client_identifier: lease.client_identifier,
+        vendor_class: lease.vendor_class,
+        user_class: lease.user_class,
+    }
+}
+
+pub async fn reserve_lease(
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
+    path: web::Path<Uuid>,
+    body: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let lease_id = path.into_inner();
+
+    // The body is optional, but one that's there has to parse; a typo in delete_lease must not
+    // quietly fall back to the defaults
+    let req: ReserveLeaseRequest = if body.is_empty() {
+        ReserveLeaseRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(req) => req,
+            Err(e) => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "invalid_body",
+                    "message": format!("Invalid request body: {}", e)
+                })));
+            }
+        }
+    };
+
+    let outcome = queries::reserve_lease(&state.db, lease_id, req.description, req.delete_lease)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match outcome {
+        ReserveLeaseOutcome::Reserved(res) => {
+            info!("Converted lease {} into reservation {}", lease_id, res.id);
+
+            Ok(HttpResponse::Created().json(ReservationResponse {
+                id: res.id,
+                subnet_id: res.subnet_id,
+                mac_address: bytes_to_mac_string(&res.mac_address),
+                ip_address: res.ip_address,
+                hostname: res.hostname,
+                description: res.description,
+                created_at: res.created_at,
+            }))
+        }
+        ReserveLeaseOutcome::LeaseNotFound => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Lease not found"
+        }))),
+        ReserveLeaseOutcome::Conflict(message) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "reservation_conflict",
+            "message": message
+        }))),
+    }
 }
 
 pub async fn list_subnets(
-    _state: web::Data<ApiState>,
+    state: web::Data<ApiState>,
+    query: web::Query<std::collections::HashMap<String, String>>,
 ) -> actix_web::Result<HttpResponse> {
-    let responses: Vec<SubnetResponse> = vec![];
-    Ok(HttpResponse::Ok().json(responses))
+    let page = match parse_pagination(&query) {
+        Ok(page) => page,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_pagination",
+            "message": message
+        }))),
+    };
+
+    let (subnets, total) = queries::fetch_all_subnets(&state.db, page)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let responses: Vec<SubnetResponse> = subnets
+        .into_iter()
+        .map(|subnet| SubnetResponse {
+            id: subnet.id,
+            name: subnet.name,
+            network: subnet.network,
+            start_ip: subnet.start_ip,
+            end_ip: subnet.end_ip,
+            gateway: subnet.gateway,
+            dns_servers: subnet.dns_servers,
+            domain_name: subnet.domain_name,
+            lease_duration: subnet.lease_duration,
+            vlan_id: subnet.vlan_id,
+            enabled: subnet.enabled,
+            maintenance: subnet.maintenance,
+            relay_circuit_id: subnet.relay_circuit_id,
+            server_ip: subnet.server_ip,
+            boot_server: subnet.boot_server,
+            boot_filename: subnet.boot_filename,
+            boot_rules: subnet.boot_rules,
+            excluded_ranges: subnet.excluded_ranges,
+            dhcp_options: subnet.dhcp_options,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .insert_header((TOTAL_COUNT_HEADER, total.to_string()))
+        .json(responses))
 }
 
 pub async fn get_subnet(
-    _state: web::Data<ApiState>,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
 ) -> actix_web::Result<HttpResponse> {
     let subnet_id = path.into_inner();
-    info!("Getting subnet: {}", subnet_id);
 
-    Ok(HttpResponse::NotFound().json(serde_json::json!({
-        "error": "not_found",
-        "message": "Subnet not found"
-    })))
+    let subnet = queries::fetch_subnet_by_id(&state.db, subnet_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match subnet {
+        Some(subnet) => Ok(HttpResponse::Ok().json(SubnetResponse {
+            id: subnet.id,
+            name: subnet.name,
+            network: subnet.network,
+            start_ip: subnet.start_ip,
+            end_ip: subnet.end_ip,
+            gateway: subnet.gateway,
+            dns_servers: subnet.dns_servers,
+            domain_name: subnet.domain_name,
+            lease_duration: subnet.lease_duration,
+            vlan_id: subnet.vlan_id,
+            enabled: subnet.enabled,
+            maintenance: subnet.maintenance,
+            relay_circuit_id: subnet.relay_circuit_id,
+            server_ip: subnet.server_ip,
+            boot_server: subnet.boot_server,
+            boot_filename: subnet.boot_filename,
+            boot_rules: subnet.boot_rules,
+            excluded_ranges: subnet.excluded_ranges,
+            dhcp_options: subnet.dhcp_options,
+        })),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Subnet not found"
+        }))),
+    }
+}
+
+pub async fn subnet_stats(
+    state: web::Data<ApiState>,
+    path: web::Path<Uuid>,
+) -> actix_web::Result<HttpResponse> {
+    let subnet_id = path.into_inner();
+
+    let stats = queries::get_subnet_stats(&state.db, subnet_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match stats {
+        Some(stats) => Ok(HttpResponse::Ok().json(stats)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Subnet not found"
+        }))),
+    }
+}
+
+/// Reservations and unexpired leases of a subnet as JSON, or CSV with `?format=csv`
+pub async fn export_subnet_leases(
+    state: web::Data<ApiState>,
+    path: web::Path<Uuid>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> actix_web::Result<HttpResponse> {
+    let subnet_id = path.into_inner();
+
+    let format = match migration_format(&query, None) {
+        Ok(format) => format,
+        Err(response) => return Ok(*response),
+    };
+
+    let subnet = lease_manager_queries::fetch_subnet(&state.db, subnet_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+    let Some(subnet) = subnet else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Subnet not found"
+        })));
+    };
+
+    let entries = lease_migration::export_subnet(&state.db, subnet_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    if format == MigrationFormat::Csv {
+        return Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}-leases.csv\"", subnet.name),
+            ))
+            .body(lease_migration::to_csv(&entries)));
+    }
+
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// Imports leases and reservations exported by `export_subnet_leases` (or converted from another
+/// server) into a subnet. Nothing is stored unless every entry is valid and free of clashes.
+pub async fn import_subnet_leases(
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
+    path: web::Path<Uuid>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+    http_req: HttpRequest,
+    body: String,
+) -> actix_web::Result<HttpResponse> {
+    let subnet_id = path.into_inner();
+
+    let format = match migration_format(&query, Some(&http_req)) {
+        Ok(format) => format,
+        Err(response) => return Ok(*response),
+    };
+
+    let parsed = match format {
+        MigrationFormat::Csv => lease_migration::parse_csv(&body),
+        MigrationFormat::Json => serde_json::from_str::<Vec<MigrationEntry>>(&body).map_err(anyhow::Error::from),
+    };
+    let entries = match parsed {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid_import",
+                "message": e.to_string()
+            })))
+        }
+    };
+
+    let outcome = lease_migration::import_subnet(&state.db, subnet_id, &entries)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match outcome {
+        ImportOutcome::Imported(report) => {
+            info!(
+                "Imported {} leases and {} reservations into subnet {}",
+                report.leases, report.reservations, subnet_id
+            );
+            Ok(HttpResponse::Ok().json(report))
+        }
+        ImportOutcome::SubnetNotFound => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Subnet not found"
+        }))),
+        ImportOutcome::Rejected(problems) => Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "import_rejected",
+            "message": "No entries were imported",
+            "problems": problems
+        }))),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum MigrationFormat {
+    Json,
+    Csv,
+}
+
+/// `?format=` wins; otherwise a `text/csv` request body selects CSV
+fn migration_format(
+    query: &std::collections::HashMap<String, String>,
+    req: Option<&HttpRequest>,
+) -> Result<MigrationFormat, Box<HttpResponse>> {
+    let csv_body = req
+        .and_then(|req| req.headers().get(actix_web::http::header::CONTENT_TYPE))
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/csv"));
+
+    match query.get("format").map(|f| f.to_ascii_lowercase()).as_deref() {
+        Some("csv") => Ok(MigrationFormat::Csv),
+        Some("json") => Ok(MigrationFormat::Json),
+        None if csv_body => Ok(MigrationFormat::Csv),
+        None => Ok(MigrationFormat::Json),
+        Some(_) => Err(Box::new(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_format",
+            "message": "format must be json or csv"
+        })))),
+    }
 }
 
 pub async fn create_subnet(
-    _state: web::Data<ApiState>,
+    _role: RequireAdmin,
+    state: web::Data<ApiState>,
     req: web::Json<CreateSubnetRequest>,
 ) -> actix_web::Result<HttpResponse> {
     if !validate_ipv4_network(&req.network) {
@@ -90,48 +619,148 @@ pub async fn create_subnet(
         })));
     }
 
-    info!("Created subnet: {}", req.name);
+    let outcome = queries::create_subnet(&state.db, &req)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let subnet_id = match outcome {
+        CreateSubnetOutcome::Created(id) => id,
+        CreateSubnetOutcome::InvalidRange(message) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid_range",
+                "message": message
+            })));
+        }
+        CreateSubnetOutcome::Overlaps(message) => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "subnet_overlap",
+                "message": message
+            })));
+        }
+    };
+
+    info!("Created subnet: {} ({})", req.name, subnet_id);
+    reload_subnet(&state, subnet_id).await;
 
     Ok(HttpResponse::Created().json(serde_json::json!({
-        "id": Uuid::new_v4(),
+        "id": subnet_id,
         "message": "Subnet created successfully"
     })))
 }
 
 pub async fn update_subnet(
-    _state: web::Data<ApiState>,
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
-    _req: web::Json<UpdateSubnetRequest>,
+    req: web::Json<UpdateSubnetRequest>,
 ) -> actix_web::Result<HttpResponse> {
     let subnet_id = path.into_inner();
-    info!("Updating subnet: {}", subnet_id);
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Subnet updated successfully"
-    })))
+    let outcome = queries::update_subnet(&state.db, subnet_id, &req)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match outcome {
+        UpdateSubnetOutcome::Updated(subnet) => {
+            info!("Updated subnet: {} ({})", subnet.name, subnet.id);
+            reload_subnet(&state, subnet.id).await;
+
+            Ok(HttpResponse::Ok().json(SubnetResponse {
+                id: subnet.id,
+                name: subnet.name,
+                network: subnet.network.to_string(),
+                start_ip: subnet.start_ip,
+                end_ip: subnet.end_ip,
+                gateway: subnet.gateway,
+                dns_servers: subnet.dns_servers,
+                domain_name: subnet.domain_name,
+                lease_duration: subnet.lease_duration,
+                vlan_id: subnet.vlan_id,
+                enabled: subnet.enabled,
+                maintenance: subnet.maintenance,
+                relay_circuit_id: subnet.relay_circuit_id,
+                server_ip: subnet.server_ip,
+                boot_server: subnet.boot_server,
+                boot_filename: subnet.boot_filename,
+                boot_rules: subnet.boot_rules,
+                excluded_ranges: subnet.excluded_ranges,
+                dhcp_options: subnet.dhcp_options,
+            }))
+        }
+        UpdateSubnetOutcome::NotFound => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Subnet not found"
+        }))),
+        UpdateSubnetOutcome::InvalidRange(message) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_range",
+            "message": message
+        }))),
+    }
 }
 
 pub async fn delete_subnet(
-    _state: web::Data<ApiState>,
+    _role: RequireAdmin,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
 ) -> actix_web::Result<HttpResponse> {
     let subnet_id = path.into_inner();
-    info!("Deleted subnet: {}", subnet_id);
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Subnet deleted successfully"
-    })))
+    let deleted = queries::delete_subnet(&state.db, subnet_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    if deleted > 0 {
+        info!("Deleted subnet: {}", subnet_id);
+        reload_subnet(&state, subnet_id).await;
+
+        Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Subnet deleted successfully"
+        })))
+    } else {
+        Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Subnet not found"
+        })))
+    }
 }
 
 pub async fn list_reservations(
-    _state: web::Data<ApiState>,
+    state: web::Data<ApiState>,
+    query: web::Query<std::collections::HashMap<String, String>>,
 ) -> actix_web::Result<HttpResponse> {
-    let responses: Vec<ReservationResponse> = vec![];
-    Ok(HttpResponse::Ok().json(responses))
+    let page = match parse_pagination(&query) {
+        Ok(page) => page,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_pagination",
+            "message": message
+        }))),
+    };
+
+    let (reservations, total) = queries::fetch_all_reservations(&state.db, page)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let responses: Vec<ReservationResponse> = reservations
+        .into_iter()
+        .map(|res| ReservationResponse {
+            id: res.id,
+            subnet_id: res.subnet_id,
+            mac_address: bytes_to_mac_string(&res.mac_address),
+            ip_address: res.ip_address,
+            hostname: res.hostname,
+            description: res.description,
+            created_at: res.created_at,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .insert_header((TOTAL_COUNT_HEADER, total.to_string()))
+        .json(responses))
 }
 
 pub async fn create_reservation(
-    _state: web::Data<ApiState>,
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
     req: web::Json<CreateReservationRequest>,
 ) -> actix_web::Result<HttpResponse> {
     if !validate_mac_address(&req.mac_address) {
@@ -141,33 +770,68 @@ pub async fn create_reservation(
         })));
     }
 
+    let mac_bytes = mac_string_to_bytes(&req.mac_address)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid MAC address"))?;
+
+    let reservation_id = queries::create_reservation(&state.db, &req, &mac_bytes)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
     info!("Created reservation: {} -> {}", req.mac_address, req.ip_address);
 
     Ok(HttpResponse::Created().json(serde_json::json!({
-        "id": Uuid::new_v4(),
+        "id": reservation_id,
         "message": "Reservation created successfully"
     })))
 }
 
 pub async fn delete_reservation(
-    _state: web::Data<ApiState>,
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
 ) -> actix_web::Result<HttpResponse> {
     let reservation_id = path.into_inner();
-    info!("Deleted reservation: {}", reservation_id);
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Reservation deleted successfully"
-    })))
+    let deleted = queries::delete_reservation(&state.db, reservation_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    if deleted > 0 {
+        info!("Deleted reservation: {}", reservation_id);
+        Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Reservation deleted successfully"
+        })))
+    } else {
+        Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Reservation not found"
+        })))
+    }
 }
 
 pub async fn get_stats(
-    _state: web::Data<ApiState>,
+    state: web::Data<ApiState>,
 ) -> actix_web::Result<HttpResponse> {
+    let (total_subnets, active_leases, expired_leases, total_reservations) =
+        queries::get_dhcp_stats(&state.db)
+            .await
+            .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "total_subnets": 0,
-        "active_leases": 0,
-        "expired_leases": 0,
-        "total_reservations": 0
+        "total_subnets": total_subnets,
+        "active_leases": active_leases,
+        "expired_leases": expired_leases,
+        "total_reservations": total_reservations
     })))
+}
+
+/// Lets a DHCP server running in this process pick up a subnet change without a restart, and
+/// tells DHCP servers in other processes over `flowdns_config`
+async fn reload_subnet(state: &ApiState, subnet_id: Uuid) {
+    if let Some(lease_manager) = &state.lease_manager {
+        if let Err(e) = lease_manager.reload_subnet(subnet_id).await {
+            warn!("Failed to reload DHCP subnet {}: {}", subnet_id, e);
+        }
+    }
+    notify::announce(&state.db, ConfigChange::Subnet(subnet_id)).await;
 }
\ No newline at end of file
@@ -1,33 +1,82 @@
-// Simplified DNS handlers that compile without database
 use actix_web::{web, HttpResponse};
+use crate::api::auth::{RequireAdmin, RequireOperator};
 use crate::api::models::*;
+use crate::api::queries::{self, CreateRecordOutcome, ImportRecordsOutcome};
 use crate::api::server::ApiState;
 use crate::api::validators::*;
+use crate::database::models::DEFAULT_VIEW;
+use crate::database::notify::{self as config_notify, ConfigChange};
+use crate::dns::notify;
+use crate::dns::query_log;
+use crate::dns::record_types::DnsRecord;
+use crate::dns::zone_file;
+use crate::dns::zone_queries;
 use uuid::Uuid;
 use tracing::info;
 
 pub async fn list_zones(
-    _state: web::Data<ApiState>,
+    state: web::Data<ApiState>,
 ) -> actix_web::Result<HttpResponse> {
-    let responses: Vec<ZoneResponse> = vec![];
+    let zones = queries::list_zones(&state.db)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let responses: Vec<ZoneResponse> = zones
+        .into_iter()
+        .map(|zone| ZoneResponse {
+            id: zone.id,
+            name: zone.name,
+            zone_type: zone.zone_type,
+            serial_number: zone.serial_number,
+            refresh_interval: zone.refresh_interval,
+            retry_interval: zone.retry_interval,
+            expire_interval: zone.expire_interval,
+            minimum_ttl: zone.minimum_ttl,
+            primary_ns: zone.primary_ns,
+            admin_email: zone.admin_email,
+            created_at: zone.created_at,
+            updated_at: zone.updated_at,
+        })
+        .collect();
+
     Ok(HttpResponse::Ok().json(responses))
 }
 
 pub async fn get_zone(
-    _state: web::Data<ApiState>,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
 ) -> actix_web::Result<HttpResponse> {
     let zone_id = path.into_inner();
-    info!("Getting zone: {}", zone_id);
 
-    Ok(HttpResponse::NotFound().json(serde_json::json!({
-        "error": "not_found",
-        "message": "Zone not found"
-    })))
+    let zone = zone_queries::fetch_zone(&state.db, zone_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match zone {
+        Some(zone) => Ok(HttpResponse::Ok().json(ZoneResponse {
+            id: zone.id,
+            name: zone.name,
+            zone_type: zone.zone_type,
+            serial_number: zone.serial_number,
+            refresh_interval: zone.refresh_interval,
+            retry_interval: zone.retry_interval,
+            expire_interval: zone.expire_interval,
+            minimum_ttl: zone.minimum_ttl,
+            primary_ns: zone.primary_ns,
+            admin_email: zone.admin_email,
+            created_at: zone.created_at,
+            updated_at: zone.updated_at,
+        })),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Zone not found"
+        }))),
+    }
 }
 
 pub async fn create_zone(
-    _state: web::Data<ApiState>,
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
     req: web::Json<CreateZoneRequest>,
 ) -> actix_web::Result<HttpResponse> {
     if !validate_domain_name(&req.name) {
@@ -37,57 +86,248 @@ pub async fn create_zone(
         })));
     }
 
-    info!("Created DNS zone: {}", req.name);
+    if !["master", "slave", "forward"].contains(&req.zone_type.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_zone_type",
+            "message": "Invalid zone type. Must be 'master', 'slave', or 'forward'"
+        })));
+    }
+
+    let zone_id = queries::create_zone(&state.db, &req)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    info!("Created DNS zone: {} ({})", req.name, zone_id);
+    config_notify::announce(&state.db, ConfigChange::Zone(zone_id)).await;
 
     Ok(HttpResponse::Created().json(serde_json::json!({
-        "id": Uuid::new_v4(),
+        "id": zone_id,
         "message": "Zone created successfully"
     })))
 }
 
 pub async fn update_zone(
-    _state: web::Data<ApiState>,
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
-    _req: web::Json<UpdateZoneRequest>,
+    req: web::Json<UpdateZoneRequest>,
 ) -> actix_web::Result<HttpResponse> {
     let zone_id = path.into_inner();
-    info!("Updating zone: {}", zone_id);
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Zone updated successfully"
-    })))
+    let zone = queries::update_zone(&state.db, zone_id, &req)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match zone {
+        Some(zone) => {
+            info!("Updated zone: {} (serial {})", zone.name, zone.serial_number);
+            notify::notify_secondaries(state.db.clone(), state.settings.clone(), zone.id);
+            config_notify::announce(&state.db, ConfigChange::Zone(zone.id)).await;
+            Ok(HttpResponse::Ok().json(ZoneResponse {
+                id: zone.id,
+                name: zone.name,
+                zone_type: zone.zone_type,
+                serial_number: zone.serial_number,
+                refresh_interval: zone.refresh_interval,
+                retry_interval: zone.retry_interval,
+                expire_interval: zone.expire_interval,
+                minimum_ttl: zone.minimum_ttl,
+                primary_ns: zone.primary_ns,
+                admin_email: zone.admin_email,
+                created_at: zone.created_at,
+                updated_at: zone.updated_at,
+            }))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Zone not found"
+        }))),
+    }
+}
+
+/// Renders the zone as a BIND-style master file for backup or migration to another server
+pub async fn export_zone(
+    state: web::Data<ApiState>,
+    path: web::Path<Uuid>,
+) -> actix_web::Result<HttpResponse> {
+    let zone_id = path.into_inner();
+
+    let zone = match zone_queries::fetch_zone(&state.db, zone_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?
+    {
+        Some(zone) => zone,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "not_found",
+                "message": "Zone not found"
+            })))
+        }
+    };
+
+    // A zone file has no notion of views, so it holds what unmatched clients are served
+    let records: Vec<_> = zone_queries::fetch_zone_records(&state.db, zone_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?
+        .into_iter()
+        .filter(|record| record.view == DEFAULT_VIEW)
+        .collect();
+
+    let body = zone_file::render_zone(&zone, &records)
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to render zone: {}", e)))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/dns; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.zone\"", zone.name.trim_end_matches('.')),
+        ))
+        .body(body))
+}
+
+/// Imports the records of an uploaded BIND master file. Nothing is stored unless every record
+/// parses, validates and fits alongside the zone's existing records.
+pub async fn import_zone(
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
+    path: web::Path<Uuid>,
+    body: String,
+) -> actix_web::Result<HttpResponse> {
+    let zone_id = path.into_inner();
+
+    let zone = match zone_queries::fetch_zone(&state.db, zone_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?
+    {
+        Some(zone) => zone,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "not_found",
+                "message": "Zone not found"
+            })))
+        }
+    };
+
+    let records = match zone_file::parse_zone(&body, &zone.name) {
+        Ok(records) => records,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid_zone_file",
+                "message": e.to_string()
+            })))
+        }
+    };
+
+    let outcome = queries::import_records(&state.db, zone_id, &records)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match outcome {
+        ImportRecordsOutcome::Imported(imported) => {
+            info!("Imported {} DNS records into zone {}", imported, zone.name);
+            if imported > 0 {
+                notify::notify_secondaries(state.db.clone(), state.settings.clone(), zone_id);
+                config_notify::announce(&state.db, ConfigChange::Zone(zone_id)).await;
+            }
+
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "imported": imported,
+                "message": format!("Imported {} records", imported)
+            })))
+        }
+        ImportRecordsOutcome::ZoneNotFound => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Zone not found"
+        }))),
+        ImportRecordsOutcome::Conflict(message) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "record_conflict",
+            "message": message
+        }))),
+    }
 }
 
 pub async fn delete_zone(
-    _state: web::Data<ApiState>,
+    _role: RequireAdmin,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
 ) -> actix_web::Result<HttpResponse> {
     let zone_id = path.into_inner();
-    info!("Deleted zone: {}", zone_id);
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Zone deleted successfully"
-    })))
+    let deleted = queries::delete_zone(&state.db, zone_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    if deleted {
+        info!("Deleted zone: {}", zone_id);
+        config_notify::announce(&state.db, ConfigChange::Zone(zone_id)).await;
+        Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Zone deleted successfully"
+        })))
+    } else {
+        Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Zone not found"
+        })))
+    }
 }
 
 pub async fn list_records(
-    _state: web::Data<ApiState>,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
+    query: web::Query<std::collections::HashMap<String, String>>,
 ) -> actix_web::Result<HttpResponse> {
     let zone_id = path.into_inner();
-    info!("Listing records for zone: {}", zone_id);
+    let page = match parse_pagination(&query) {
+        Ok(page) => page,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_pagination",
+            "message": message
+        }))),
+    };
 
-    let responses: Vec<RecordResponse> = vec![];
-    Ok(HttpResponse::Ok().json(responses))
+    let (records, total) = queries::list_records(&state.db, zone_id, page)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let responses: Vec<RecordResponse> = records
+        .into_iter()
+        .map(|record| RecordResponse {
+            id: record.id,
+            zone_id: record.zone_id,
+            name: record.name,
+            record_type: record.record_type,
+            value: record.value,
+            ttl: record.ttl,
+            priority: record.priority,
+            weight: record.weight,
+            port: record.port,
+            is_dynamic: record.is_dynamic,
+            view: record.view,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .insert_header((TOTAL_COUNT_HEADER, total.to_string()))
+        .json(responses))
 }
 
 pub async fn create_record(
-    _state: web::Data<ApiState>,
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
     req: web::Json<CreateRecordRequest>,
 ) -> actix_web::Result<HttpResponse> {
     let zone_id = path.into_inner();
 
+    if !validate_record_name(&req.name) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_record_name",
+            "message": "Invalid record name; a wildcard \"*\" may only be the leftmost label"
+        })));
+    }
+
     if !validate_dns_record_type(&req.record_type) {
         return Ok(HttpResponse::BadRequest().json(serde_json::json!({
             "error": "invalid_record_type",
@@ -95,35 +335,176 @@ pub async fn create_record(
         })));
     }
 
-    info!("Created DNS record: {} {} in zone {}", req.record_type, req.name, zone_id);
+    if let Some(ttl) = req.ttl {
+        if !validate_ttl(ttl) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid_ttl",
+                "message": "Invalid TTL value"
+            })));
+        }
+    }
 
-    Ok(HttpResponse::Created().json(serde_json::json!({
-        "id": Uuid::new_v4(),
-        "message": "Record created successfully"
-    })))
+    if let Some(view) = req.view.as_deref() {
+        if view != DEFAULT_VIEW && !state.settings.dns.views.iter().any(|configured| configured.name == view) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "unknown_view",
+                "message": format!("View '{}' is not configured in dns.views", view)
+            })));
+        }
+    }
+
+    // Garbage stored here would only surface later as a record the server can't serve
+    let typed = DnsRecord::from_parts(&req.name, &req.record_type, &req.value, req.ttl, req.priority, req.weight, req.port)
+        .and_then(|record| record.validate());
+    if let Err(e) = typed {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_record_value",
+            "message": e.to_string()
+        })));
+    }
+
+    let outcome = queries::create_record(&state.db, zone_id, &req)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match outcome {
+        CreateRecordOutcome::Created(record_id) => {
+            info!("Created DNS record: {} {} in zone {}", req.record_type, req.name, zone_id);
+            notify::notify_secondaries(state.db.clone(), state.settings.clone(), zone_id);
+            config_notify::announce(&state.db, ConfigChange::Zone(zone_id)).await;
+
+            Ok(HttpResponse::Created().json(serde_json::json!({
+                "id": record_id,
+                "message": "Record created successfully"
+            })))
+        }
+        CreateRecordOutcome::ZoneNotFound => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Zone not found"
+        }))),
+        CreateRecordOutcome::Conflict(message) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "record_conflict",
+            "message": message
+        }))),
+    }
 }
 
 pub async fn update_record(
-    _state: web::Data<ApiState>,
+    _role: RequireOperator,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
-    _req: web::Json<UpdateRecordRequest>,
+    req: web::Json<UpdateRecordRequest>,
 ) -> actix_web::Result<HttpResponse> {
     let record_id = path.into_inner();
-    info!("Updating record: {}", record_id);
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Record updated successfully"
-    })))
+    // Validate TTL if provided
+    if let Some(ttl) = req.ttl {
+        if !validate_ttl(ttl) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid_ttl",
+                "message": "Invalid TTL value"
+            })));
+        }
+    }
+
+    let existing = zone_queries::fetch_record(&state.db, record_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    if let Some(existing) = existing {
+        let typed = DnsRecord::from_parts(
+            &existing.name,
+            &existing.record_type,
+            req.value.as_deref().unwrap_or(&existing.value),
+            req.ttl.or(Some(existing.ttl)),
+            req.priority.or(existing.priority),
+            req.weight.or(existing.weight),
+            req.port.or(existing.port),
+        )
+        .and_then(|record| record.validate());
+
+        if let Err(e) = typed {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid_record_value",
+                "message": e.to_string()
+            })));
+        }
+    }
+
+    let record = queries::update_record(&state.db, record_id, &req)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    match record {
+        Some(record) => {
+            info!("Updated record: {} {} ({})", record.record_type, record.name, record.id);
+            notify::notify_secondaries(state.db.clone(), state.settings.clone(), record.zone_id);
+            config_notify::announce(&state.db, ConfigChange::Zone(record.zone_id)).await;
+            Ok(HttpResponse::Ok().json(RecordResponse {
+                id: record.id,
+                zone_id: record.zone_id,
+                name: record.name,
+                record_type: record.record_type,
+                value: record.value,
+                ttl: record.ttl,
+                priority: record.priority,
+                weight: record.weight,
+                port: record.port,
+                is_dynamic: record.is_dynamic,
+                view: record.view,
+                created_at: record.created_at,
+                updated_at: record.updated_at,
+            }))
+        }
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Record not found"
+        }))),
+    }
 }
 
 pub async fn delete_record(
-    _state: web::Data<ApiState>,
+    _role: RequireAdmin,
+    state: web::Data<ApiState>,
     path: web::Path<Uuid>,
 ) -> actix_web::Result<HttpResponse> {
     let record_id = path.into_inner();
-    info!("Deleted record: {}", record_id);
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Record deleted successfully"
-    })))
-}
\ No newline at end of file
+    let zone_id = queries::delete_record(&state.db, record_id)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    if let Some(zone_id) = zone_id {
+        info!("Deleted record: {}", record_id);
+        notify::notify_secondaries(state.db.clone(), state.settings.clone(), zone_id);
+        config_notify::announce(&state.db, ConfigChange::Zone(zone_id)).await;
+        Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Record deleted successfully"
+        })))
+    } else {
+        Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "not_found",
+            "message": "Record not found"
+        })))
+    }
+}
+/// The most recent queries answered by the DNS server, newest first
+pub async fn recent_queries(
+    query: web::Query<std::collections::HashMap<String, String>>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(log) = query_log::shared_log() else {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "query_log_disabled",
+            "message": "The DNS query log is not enabled in this process"
+        })));
+    };
+    let page = match parse_pagination(&query) {
+        Ok(page) => page,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_pagination",
+            "message": message
+        }))),
+    };
+
+    Ok(HttpResponse::Ok().json(log.recent(page.limit as usize)))
+}
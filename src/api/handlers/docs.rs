@@ -1,5 +1,5 @@
 use actix_web::{HttpResponse, web};
-use serde_json::json;
+use serde_json::{json, Map, Value};
 
 pub async fn openapi_spec() -> HttpResponse {
     let spec = json!({
@@ -15,389 +15,953 @@ pub async fn openapi_spec() -> HttpResponse {
                 "description": "Local development server"
             }
         ],
-        "components": {
-            "securitySchemes": {
-                "bearerAuth": {
-                    "type": "http",
-                    "scheme": "bearer",
-                    "bearerFormat": "JWT"
+        "components": components(),
+        "paths": paths()
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .json(spec)
+}
+
+fn components() -> Value {
+    json!({
+        "securitySchemes": {
+            "bearerAuth": {
+                "type": "http",
+                "scheme": "bearer",
+                "bearerFormat": "JWT"
+            }
+        },
+        "parameters": {
+            "Limit": {
+                "name": "limit",
+                "in": "query",
+                "description": "Page size; values above 1000 are capped",
+                "schema": {"type": "integer", "minimum": 0, "default": 100}
+            },
+            "Offset": {
+                "name": "offset",
+                "in": "query",
+                "schema": {"type": "integer", "minimum": 0, "default": 0}
+            }
+        },
+        "headers": {
+            "TotalCount": {
+                "description": "Number of matching rows across all pages",
+                "schema": {"type": "integer"}
+            }
+        },
+        "schemas": {
+            "LoginRequest": {
+                "type": "object",
+                "required": ["username", "password"],
+                "properties": {
+                    "username": {"type": "string"},
+                    "password": {"type": "string"}
                 }
             },
-            "schemas": {
-                "LoginRequest": {
-                    "type": "object",
-                    "required": ["username", "password"],
-                    "properties": {
-                        "username": {"type": "string"},
-                        "password": {"type": "string"}
-                    }
-                },
-                "LoginResponse": {
-                    "type": "object",
-                    "properties": {
-                        "token": {"type": "string"},
-                        "expires_in": {"type": "integer"}
+            "LoginResponse": {
+                "type": "object",
+                "properties": {
+                    "token": {"type": "string"},
+                    "expires_in": {"type": "integer"}
+                }
+            },
+            "Lease": {
+                "type": "object",
+                "properties": {
+                    "id": {"type": "string", "format": "uuid"},
+                    "subnet_id": {"type": "string", "format": "uuid"},
+                    "mac_address": {"type": "string"},
+                    "ip_address": {"type": "string", "format": "ipv4"},
+                    "hostname": {"type": "string"},
+                    "lease_start": {"type": "string", "format": "date-time"},
+                    "lease_end": {"type": "string", "format": "date-time"},
+                    "state": {"type": "string", "enum": ["active", "expired", "released"]},
+                    "client_identifier": {"type": "string", "nullable": true, "description": "Option 61 as colon-separated hex"},
+                    "vendor_class": {"type": "string", "nullable": true, "description": "Option 60"},
+                    "user_class": {"type": "string", "nullable": true, "description": "Option 77, comma-separated"}
+                }
+            },
+            "LeaseEvent": {
+                "type": "object",
+                "properties": {
+                    "id": {"type": "integer"},
+                    "lease_id": {"type": "string", "format": "uuid"},
+                    "mac_address": {"type": "string"},
+                    "ip_address": {"type": "string", "format": "ipv4"},
+                    "old_state": {"type": "string", "nullable": true},
+                    "new_state": {"type": "string"},
+                    "source": {"type": "string", "enum": ["dhcp", "api"]},
+                    "created_at": {"type": "string", "format": "date-time"}
+                }
+            },
+            "Subnet": {
+                "type": "object",
+                "properties": {
+                    "id": {"type": "string", "format": "uuid"},
+                    "name": {"type": "string"},
+                    "network": {"type": "string"},
+                    "start_ip": {"type": "string", "format": "ipv4"},
+                    "end_ip": {"type": "string", "format": "ipv4"},
+                    "gateway": {"type": "string", "format": "ipv4"},
+                    "dns_servers": {"type": "array", "items": {"type": "string"}},
+                    "domain_name": {"type": "string"},
+                    "vlan_id": {"type": "integer"},
+                    "enabled": {"type": "boolean"},
+                    "maintenance": {"type": "boolean"},
+                    "relay_circuit_id": {"type": "string", "description": "Option 82 circuit-id that selects this subnet for relayed clients; hex for binary ids"},
+                    "server_ip": {"type": "string", "format": "ipv4", "description": "Server identifier (option 54) sent to this subnet's clients; derived from the listening or routing address when unset"},
+                    "boot_server": {"type": "string", "format": "ipv4", "description": "Next-server (siaddr and option 66) for network booting clients"},
+                    "boot_filename": {"type": "string", "description": "Boot file (option 67) when no boot rule matches"},
+                    "boot_rules": {"type": "array", "items": {"$ref": "#/components/schemas/BootRule"}},
+                    "excluded_ranges": {"type": "array", "items": {"type": "string"}, "description": "Addresses never handed out: single IPs, start-end ranges or CIDR blocks inside the network", "example": ["192.168.1.5", "192.168.1.20-192.168.1.30", "192.168.1.64/28"]},
+                    "dhcp_options": {"type": "object", "additionalProperties": {"$ref": "#/components/schemas/DhcpOptionValue"}, "description": "Extra options keyed by option code, replacing the server's own option with the same code", "example": {"42": {"ips": ["192.168.1.1"]}, "252": {"string": "http://wpad/wpad.dat"}}},
+                    "dhcpv6_rapid_commit": {"type": "boolean", "writeOnly": true, "default": false, "description": "Answer DHCPv6 SOLICITs carrying Rapid Commit with an immediate REPLY; only enable it where this is the link's only DHCPv6 server"}
+                }
+            },
+            "LiveLeaseEvent": {
+                "type": "object",
+                "properties": {
+                    "event": {"type": "string", "enum": ["created", "renewed", "released", "expired"]},
+                    "lease_id": {"type": "string", "format": "uuid"},
+                    "subnet_id": {"type": "string", "format": "uuid"},
+                    "mac_address": {"type": "string"},
+                    "ip_address": {"type": "string", "format": "ipv4"},
+                    "hostname": {"type": "string"},
+                    "lease_end": {"type": "string", "format": "date-time"},
+                    "timestamp": {"type": "string", "format": "date-time"}
+                }
+            },
+            "QueryLogEntry": {
+                "type": "object",
+                "properties": {
+                    "timestamp": {"type": "string", "format": "date-time"},
+                    "client": {"type": "string", "description": "IPv4 or IPv6 address of the querying client"},
+                    "qname": {"type": "string"},
+                    "qtype": {"type": "integer", "description": "Numeric query type, e.g. 1 for A or 28 for AAAA"},
+                    "rcode": {"type": "integer", "description": "Response code, e.g. 0 for NOERROR or 3 for NXDOMAIN"},
+                    "cached": {"type": "boolean", "description": "Answered from the cache of upstream responses"}
+                }
+            },
+            "DhcpOptionValue": {
+                "type": "object",
+                "description": "Exactly one of the encodings",
+                "properties": {
+                    "ips": {"type": "array", "items": {"type": "string", "format": "ipv4"}},
+                    "string": {"type": "string"},
+                    "hex": {"type": "string", "description": "Raw option bytes as hex digits, optionally separated by colons"}
+                }
+            },
+            "BootRule": {
+                "type": "object",
+                "required": ["filename"],
+                "properties": {
+                    "vendor_class": {"type": "string", "description": "Matches clients whose option 60 starts with this text"},
+                    "arch": {"type": "integer", "description": "Matches the client architecture in option 93 (0 BIOS, 7/9 x86-64 UEFI)"},
+                    "filename": {"type": "string"}
+                }
+            },
+            "MigrationEntry": {
+                "type": "object",
+                "description": "A lease or reservation for subnet export/import. The CSV form has a header row with the columns kind,mac_address,ip_address,hostname,lease_start,lease_end,state,description (any order; empty fields are absent).",
+                "required": ["kind", "mac_address", "ip_address"],
+                "properties": {
+                    "kind": {"type": "string", "enum": ["lease", "reservation"]},
+                    "mac_address": {"type": "string", "description": "Six hex octets separated by : or -"},
+                    "ip_address": {"type": "string", "format": "ipv4", "description": "Must be inside the subnet's network"},
+                    "hostname": {"type": "string"},
+                    "lease_start": {"type": "string", "format": "date-time", "description": "Leases only; defaults to the import time"},
+                    "lease_end": {"type": "string", "format": "date-time", "description": "Required for leases"},
+                    "state": {"type": "string", "enum": ["active", "expired", "released"], "description": "Leases only; defaults to active"},
+                    "description": {"type": "string", "description": "Reservations only"}
+                }
+            },
+            "SubnetStats": {
+                "type": "object",
+                "properties": {
+                    "subnet_id": {"type": "string", "format": "uuid"},
+                    "subnet_name": {"type": "string"},
+                    "total_addresses": {"type": "integer"},
+                    "active_leases": {"type": "integer"},
+                    "reserved_addresses": {"type": "integer"},
+                    "available_addresses": {"type": "integer"},
+                    "utilization_percent": {"type": "number"}
+                }
+            },
+            "DnsZone": {
+                "type": "object",
+                "properties": {
+                    "id": {"type": "string", "format": "uuid"},
+                    "name": {"type": "string"},
+                    "type": {"type": "string", "enum": ["forward", "reverse"]},
+                    "ttl": {"type": "integer"},
+                    "soa_serial": {"type": "integer"},
+                    "enabled": {"type": "boolean"}
+                }
+            },
+            "DnsRecord": {
+                "type": "object",
+                "properties": {
+                    "id": {"type": "string", "format": "uuid"},
+                    "zone_id": {"type": "string", "format": "uuid"},
+                    "name": {"type": "string", "description": "Owner relative to the zone (\"@\" for the apex) or absolute with a trailing dot; a leftmost \"*\" label makes a wildcard", "example": "*.apps"},
+                    "type": {"type": "string", "enum": ["A", "AAAA", "CNAME", "MX", "TXT", "PTR", "NS", "SOA", "SRV", "CAA", "ALIAS"], "description": "ALIAS answers A and AAAA queries with the addresses its target has when queried, so it can stand in for a CNAME at the zone apex"},
+                    "value": {"type": "string", "description": "CAA values use zone-file form, e.g. 0 issue \"letsencrypt.org\""},
+                    "ttl": {"type": "integer"},
+                    "priority": {"type": "integer", "description": "Required for MX and SRV"},
+                    "weight": {"type": "integer", "description": "Required for SRV"},
+                    "port": {"type": "integer", "description": "Required for SRV"},
+                    "is_dynamic": {"type": "boolean"},
+                    "view": {"type": "string", "default": "default", "description": "Split-horizon view from dns.views; served instead of the default records at the same name to that view's clients"}
+                }
+            }
+        }
+    })
+}
+
+/// Each API scope is its own `json!` call; one literal for every path exceeds the macro
+/// recursion limit.
+fn paths() -> Value {
+    let mut paths = Map::new();
+    for scope in [auth_paths(), dhcp_paths(), dns_paths(), system_paths(), ipv6_paths()] {
+        if let Value::Object(scope) = scope {
+            paths.extend(scope);
+        }
+    }
+    Value::Object(paths)
+}
+
+fn auth_paths() -> Value {
+    json!({
+        "/auth/login": {
+            "post": {
+                "summary": "Login to get JWT token",
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"$ref": "#/components/schemas/LoginRequest"}
+                        }
                     }
                 },
-                "Lease": {
-                    "type": "object",
-                    "properties": {
-                        "id": {"type": "string", "format": "uuid"},
-                        "subnet_id": {"type": "string", "format": "uuid"},
-                        "mac_address": {"type": "string"},
-                        "ip_address": {"type": "string", "format": "ipv4"},
-                        "hostname": {"type": "string"},
-                        "lease_start": {"type": "string", "format": "date-time"},
-                        "lease_end": {"type": "string", "format": "date-time"},
-                        "state": {"type": "string", "enum": ["active", "expired", "released"]}
+                "responses": {
+                    "200": {
+                        "description": "Login successful",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/LoginResponse"}
+                            }
+                        }
                     }
-                },
-                "Subnet": {
-                    "type": "object",
-                    "properties": {
-                        "id": {"type": "string", "format": "uuid"},
-                        "name": {"type": "string"},
-                        "network": {"type": "string"},
-                        "start_ip": {"type": "string", "format": "ipv4"},
-                        "end_ip": {"type": "string", "format": "ipv4"},
-                        "gateway": {"type": "string", "format": "ipv4"},
-                        "dns_servers": {"type": "array", "items": {"type": "string"}},
-                        "domain_name": {"type": "string"},
-                        "vlan_id": {"type": "integer"},
-                        "enabled": {"type": "boolean"}
+                }
+            }
+        },
+        "/auth/logout": {
+            "post": {
+                "summary": "Revoke the current token (and optionally a refresh token)",
+                "security": [{"bearerAuth": []}],
+                "requestBody": {
+                    "required": false,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "refresh_token": {"type": "string"}
+                                }
+                            }
+                        }
                     }
                 },
-                "DnsZone": {
-                    "type": "object",
-                    "properties": {
-                        "id": {"type": "string", "format": "uuid"},
-                        "name": {"type": "string"},
-                        "type": {"type": "string", "enum": ["forward", "reverse"]},
-                        "ttl": {"type": "integer"},
-                        "soa_serial": {"type": "integer"},
-                        "enabled": {"type": "boolean"}
+                "responses": {
+                    "204": {"description": "Token revoked"},
+                    "401": {"description": "Missing, invalid or already revoked token"}
+                }
+            }
+        }
+    })
+}
+
+fn dhcp_paths() -> Value {
+    json!({
+        "/dhcp/leases": {
+            "get": {
+                "summary": "List all DHCP leases",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "state",
+                        "in": "query",
+                        "schema": {"type": "string", "enum": ["active", "expired", "released"]}
+                    },
+                    {
+                        "name": "mac",
+                        "in": "query",
+                        "schema": {"type": "string"},
+                        "description": "Client MAC address, colon or dash separated"
+                    },
+                    {
+                        "name": "ip",
+                        "in": "query",
+                        "schema": {"type": "string", "format": "ipv4"}
+                    },
+                    {
+                        "name": "hostname",
+                        "in": "query",
+                        "schema": {"type": "string"},
+                        "description": "Case-insensitive substring of the hostname"
+                    },
+                    {
+                        "name": "subnet_id",
+                        "in": "query",
+                        "schema": {"type": "string", "format": "uuid"}
+                    },
+                    {"$ref": "#/components/parameters/Limit"},
+                    {"$ref": "#/components/parameters/Offset"}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "List of leases",
+                        "headers": {"X-Total-Count": {"$ref": "#/components/headers/TotalCount"}},
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "items": {"$ref": "#/components/schemas/Lease"}
+                                }
+                            }
+                        }
+                    },
+                    "400": {"description": "A malformed mac, ip or subnet_id filter, or limit or offset is not a non-negative integer"}
+                }
+            },
+            "post": {
+                "summary": "Create a new DHCP lease",
+                "security": [{"bearerAuth": []}],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"$ref": "#/components/schemas/Lease"}
+                        }
                     }
                 },
-                "DnsRecord": {
-                    "type": "object",
-                    "properties": {
-                        "id": {"type": "string", "format": "uuid"},
-                        "zone_id": {"type": "string", "format": "uuid"},
-                        "name": {"type": "string"},
-                        "type": {"type": "string", "enum": ["A", "AAAA", "CNAME", "MX", "TXT", "PTR", "NS", "SOA"]},
-                        "value": {"type": "string"},
-                        "ttl": {"type": "integer"},
-                        "priority": {"type": "integer"},
-                        "is_dynamic": {"type": "boolean"}
-                    }
+                "responses": {
+                    "201": {
+                        "description": "Lease created",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/Lease"}
+                            }
+                        }
+                    },
+                    "403": {"description": "Read-only role; admin or operator role required"}
                 }
             }
         },
-        "paths": {
-            "/auth/login": {
-                "post": {
-                    "summary": "Login to get JWT token",
-                    "requestBody": {
+        "/dhcp/leases/{id}": {
+            "get": {
+                "summary": "Get a specific lease",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "id",
+                        "in": "path",
                         "required": true,
+                        "schema": {"type": "string", "format": "uuid"}
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Lease details",
                         "content": {
                             "application/json": {
-                                "schema": {"$ref": "#/components/schemas/LoginRequest"}
+                                "schema": {"$ref": "#/components/schemas/Lease"}
                             }
                         }
+                    }
+                }
+            },
+            "delete": {
+                "summary": "Release a DHCP lease",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string", "format": "uuid"}
+                    }
+                ],
+                "responses": {
+                    "204": {
+                        "description": "Lease released"
                     },
-                    "responses": {
-                        "200": {
-                            "description": "Login successful",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"$ref": "#/components/schemas/LoginResponse"}
+                    "403": {"description": "Read-only role; admin or operator role required"}
+                }
+            }
+        },
+        "/dhcp/leases/{id}/reserve": {
+            "post": {
+                "summary": "Convert a lease into a static reservation",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string", "format": "uuid"}
+                    }
+                ],
+                "requestBody": {
+                    "required": false,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "description": {"type": "string"},
+                                    "delete_lease": {"type": "boolean", "default": false}
                                 }
                             }
                         }
                     }
+                },
+                "responses": {
+                    "201": {
+                        "description": "Reservation created from lease"
+                    },
+                    "400": {"description": "Request body present but not valid"},
+                    "403": {"description": "Read-only role; admin or operator role required"},
+                    "404": {
+                        "description": "Lease not found"
+                    },
+                    "409": {
+                        "description": "MAC or IP already reserved"
+                    }
                 }
-            },
-            "/dhcp/leases": {
-                "get": {
-                    "summary": "List all DHCP leases",
-                    "security": [{"bearerAuth": []}],
-                    "parameters": [
-                        {
-                            "name": "state",
-                            "in": "query",
-                            "schema": {"type": "string", "enum": ["active", "expired", "released"]}
+            }
+        },
+        "/dhcp/leases/{id}/expire": {
+            "post": {
+                "summary": "End an active lease now, freeing its address",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string", "format": "uuid"}
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "The expired lease",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/Lease"}
+                            }
                         }
-                    ],
-                    "responses": {
-                        "200": {
-                            "description": "List of leases",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "array",
-                                        "items": {"$ref": "#/components/schemas/Lease"}
-                                    }
+                    },
+                    "403": {"description": "Read-only role; admin or operator role required"},
+                    "404": {"description": "Lease not found or not active"}
+                }
+            }
+        },
+        "/dhcp/leases/{id}/extend": {
+            "post": {
+                "summary": "Keep an active lease longer",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string", "format": "uuid"}
+                    }
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "required": ["duration"],
+                                "properties": {
+                                    "duration": {"type": "integer", "minimum": 1, "description": "Seconds added to the lease end, counted from now if the lease has already run out"}
                                 }
                             }
                         }
                     }
                 },
-                "post": {
-                    "summary": "Create a new DHCP lease",
-                    "security": [{"bearerAuth": []}],
-                    "requestBody": {
-                        "required": true,
+                "responses": {
+                    "200": {
+                        "description": "The extended lease",
                         "content": {
                             "application/json": {
                                 "schema": {"$ref": "#/components/schemas/Lease"}
                             }
                         }
                     },
-                    "responses": {
-                        "201": {
-                            "description": "Lease created",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"$ref": "#/components/schemas/Lease"}
-                                }
+                    "400": {"description": "duration is zero"},
+                    "403": {"description": "Read-only role; admin or operator role required"},
+                    "404": {"description": "Lease not found or not active"}
+                }
+            }
+        },
+        "/dhcp/events": {
+            "get": {
+                "summary": "Server-Sent Events stream of lease transitions as they happen",
+                "description": "Each message is named created, renewed, released or expired and carries a LiveLeaseEvent as JSON. A lagged message reports how many events a slow client missed. Comments are sent every 15 seconds while idle.",
+                "security": [{"bearerAuth": []}],
+                "responses": {
+                    "200": {
+                        "description": "Event stream",
+                        "content": {
+                            "text/event-stream": {
+                                "schema": {"$ref": "#/components/schemas/LiveLeaseEvent"}
                             }
                         }
-                    }
+                    },
+                    "503": {"description": "The DHCP server is not running in the API's process"}
                 }
-            },
-            "/dhcp/leases/{id}": {
-                "get": {
-                    "summary": "Get a specific lease",
-                    "security": [{"bearerAuth": []}],
-                    "parameters": [
-                        {
-                            "name": "id",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string", "format": "uuid"}
+            }
+        },
+        "/dhcp/leases/{id}/history": {
+            "get": {
+                "summary": "State transitions of a lease, oldest first",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string", "format": "uuid"}
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Lease events",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "items": {"$ref": "#/components/schemas/LeaseEvent"}
+                                }
+                            }
                         }
-                    ],
-                    "responses": {
-                        "200": {
-                            "description": "Lease details",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"$ref": "#/components/schemas/Lease"}
+                    },
+                    "404": {"description": "Lease not found and no history recorded"}
+                }
+            }
+        },
+        "/dhcp/subnets": {
+            "get": {
+                "summary": "List all subnets",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {"$ref": "#/components/parameters/Limit"},
+                    {"$ref": "#/components/parameters/Offset"}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "List of subnets",
+                        "headers": {"X-Total-Count": {"$ref": "#/components/headers/TotalCount"}},
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "items": {"$ref": "#/components/schemas/Subnet"}
                                 }
                             }
                         }
+                    },
+                    "400": {"description": "limit or offset is not a non-negative integer"}
+                }
+            },
+            "post": {
+                "summary": "Create a new subnet",
+                "security": [{"bearerAuth": []}],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"$ref": "#/components/schemas/Subnet"}
+                        }
                     }
                 },
-                "delete": {
-                    "summary": "Release a DHCP lease",
-                    "security": [{"bearerAuth": []}],
-                    "parameters": [
-                        {
-                            "name": "id",
-                            "in": "path",
-                            "required": true,
-                            "schema": {"type": "string", "format": "uuid"}
-                        }
-                    ],
-                    "responses": {
-                        "204": {
-                            "description": "Lease released"
+                "responses": {
+                    "201": {
+                        "description": "Subnet created",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/Subnet"}
+                            }
                         }
-                    }
+                    },
+                    "400": {"description": "Invalid network, or start_ip/end_ip/gateway outside the network"},
+                    "403": {"description": "Admin role required"},
+                    "409": {"description": "Network overlaps an existing subnet"}
                 }
-            },
-            "/dhcp/subnets": {
-                "get": {
-                    "summary": "List all subnets",
-                    "security": [{"bearerAuth": []}],
-                    "responses": {
-                        "200": {
-                            "description": "List of subnets",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "array",
-                                        "items": {"$ref": "#/components/schemas/Subnet"}
-                                    }
-                                }
+            }
+        },
+        "/dhcp/subnets/{id}/stats": {
+            "get": {
+                "summary": "Address utilization of a subnet's pool",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": {"type": "string", "format": "uuid"}
+                    }
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Subnet statistics",
+                        "content": {
+                            "application/json": {
+                                "schema": {"$ref": "#/components/schemas/SubnetStats"}
                             }
                         }
+                    },
+                    "404": {"description": "Subnet not found"}
+                }
+            }
+        },
+        "/dhcp/subnets/{id}/leases/export": {
+            "get": {
+                "summary": "Export a subnet's reservations and unexpired leases for migration",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {"name": "id", "in": "path", "required": true, "schema": {"type": "string", "format": "uuid"}},
+                    {"name": "format", "in": "query", "schema": {"type": "string", "enum": ["json", "csv"], "default": "json"}}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Reservations first, then leases",
+                        "content": {
+                            "application/json": {
+                                "schema": {"type": "array", "items": {"$ref": "#/components/schemas/MigrationEntry"}}
+                            },
+                            "text/csv": {"schema": {"type": "string"}}
+                        }
+                    },
+                    "400": {"description": "format is not json or csv"},
+                    "404": {"description": "Subnet not found"}
+                }
+            }
+        },
+        "/dhcp/subnets/{id}/leases/import": {
+            "post": {
+                "summary": "Import leases and reservations into a subnet in one transaction",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {"name": "id", "in": "path", "required": true, "schema": {"type": "string", "format": "uuid"}},
+                    {"name": "format", "in": "query", "schema": {"type": "string", "enum": ["json", "csv"]}, "description": "Defaults to csv for a text/csv body, json otherwise"}
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"type": "array", "items": {"$ref": "#/components/schemas/MigrationEntry"}}
+                        },
+                        "text/csv": {"schema": {"type": "string"}}
                     }
                 },
-                "post": {
-                    "summary": "Create a new subnet",
-                    "security": [{"bearerAuth": []}],
-                    "requestBody": {
-                        "required": true,
+                "responses": {
+                    "200": {
+                        "description": "Number of imported leases and reservations",
                         "content": {
                             "application/json": {
-                                "schema": {"$ref": "#/components/schemas/Subnet"}
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "leases": {"type": "integer"},
+                                        "reservations": {"type": "integer"}
+                                    }
+                                }
                             }
                         }
                     },
-                    "responses": {
-                        "201": {
-                            "description": "Subnet created",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"$ref": "#/components/schemas/Subnet"}
+                    "400": {"description": "Body does not parse as the chosen format"},
+                    "403": {"description": "Read-only role; admin or operator role required"},
+                    "404": {"description": "Subnet not found"},
+                    "422": {"description": "Invalid MACs, addresses outside the subnet, duplicates or clashes with existing leases/reservations; nothing was imported and `problems` lists each entry"}
+                }
+            }
+        }
+    })
+}
+
+fn dns_paths() -> Value {
+    json!({
+        "/dns/zones": {
+            "get": {
+                "summary": "List all DNS zones",
+                "security": [{"bearerAuth": []}],
+                "responses": {
+                    "200": {
+                        "description": "List of zones",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "items": {"$ref": "#/components/schemas/DnsZone"}
                                 }
                             }
                         }
                     }
                 }
             },
-            "/dns/zones": {
-                "get": {
-                    "summary": "List all DNS zones",
-                    "security": [{"bearerAuth": []}],
-                    "responses": {
-                        "200": {
-                            "description": "List of zones",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "array",
-                                        "items": {"$ref": "#/components/schemas/DnsZone"}
-                                    }
-                                }
-                            }
+            "post": {
+                "summary": "Create a new DNS zone",
+                "security": [{"bearerAuth": []}],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"$ref": "#/components/schemas/DnsZone"}
                         }
                     }
                 },
-                "post": {
-                    "summary": "Create a new DNS zone",
-                    "security": [{"bearerAuth": []}],
-                    "requestBody": {
-                        "required": true,
+                "responses": {
+                    "201": {
+                        "description": "Zone created",
                         "content": {
                             "application/json": {
                                 "schema": {"$ref": "#/components/schemas/DnsZone"}
                             }
                         }
                     },
-                    "responses": {
-                        "201": {
-                            "description": "Zone created",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"$ref": "#/components/schemas/DnsZone"}
+                    "403": {"description": "Read-only role; admin or operator role required"}
+                }
+            }
+        },
+        "/dns/zones/{id}/export": {
+            "get": {
+                "summary": "Export a zone as a BIND master file ($ORIGIN, $TTL, SOA, then records sorted by owner and type)",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {"name": "id", "in": "path", "required": true, "schema": {"type": "string", "format": "uuid"}}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Zone file",
+                        "content": {"text/dns": {"schema": {"type": "string"}}}
+                    },
+                    "404": {"description": "Zone not found"}
+                }
+            }
+        },
+        "/dns/zones/{id}/import": {
+            "post": {
+                "summary": "Import the records of a BIND master file; any parse, validation or conflict error rejects the whole file",
+                "description": "Supports $ORIGIN, $TTL, relative and absolute names, parentheses and comments. SOA records are skipped because the zone's own fields provide the SOA; $INCLUDE and $GENERATE are rejected.",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {"name": "id", "in": "path", "required": true, "schema": {"type": "string", "format": "uuid"}}
+                ],
+                "requestBody": {
+                    "required": true,
+                    "content": {"text/plain": {"schema": {"type": "string"}}}
+                },
+                "responses": {
+                    "200": {
+                        "description": "Number of imported records",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "imported": {"type": "integer"},
+                                        "message": {"type": "string"}
+                                    }
                                 }
                             }
                         }
-                    }
+                    },
+                    "400": {"description": "Zone file does not parse or a record is invalid; the message names the line"},
+                    "403": {"description": "Read-only role; admin or operator role required"},
+                    "404": {"description": "Zone not found"},
+                    "409": {"description": "A record conflicts with the zone's records or an earlier record in the file"}
                 }
-            },
-            "/dns/records": {
-                "get": {
-                    "summary": "List all DNS records",
-                    "security": [{"bearerAuth": []}],
-                    "parameters": [
-                        {
-                            "name": "zone_id",
-                            "in": "query",
-                            "schema": {"type": "string", "format": "uuid"}
+            }
+        },
+        "/dns/queries/recent": {
+            "get": {
+                "summary": "Most recent DNS queries, newest first",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {"$ref": "#/components/parameters/Limit"}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "Queries from the in-memory query log",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "items": {"$ref": "#/components/schemas/QueryLogEntry"}
+                                }
+                            }
                         }
-                    ],
-                    "responses": {
-                        "200": {
-                            "description": "List of records",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "array",
-                                        "items": {"$ref": "#/components/schemas/DnsRecord"}
+                    },
+                    "400": {"description": "limit is not a non-negative integer"},
+                    "503": {"description": "dns.query_log is disabled or the DNS server is not running in this process"}
+                }
+            }
+        },
+        "/dns/records": {
+            "get": {
+                "summary": "List all DNS records",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "zone_id",
+                        "in": "query",
+                        "schema": {"type": "string", "format": "uuid"}
+                    },
+                    {"$ref": "#/components/parameters/Limit"},
+                    {"$ref": "#/components/parameters/Offset"}
+                ],
+                "responses": {
+                    "200": {
+                        "description": "List of records",
+                        "headers": {"X-Total-Count": {"$ref": "#/components/headers/TotalCount"}},
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "items": {"$ref": "#/components/schemas/DnsRecord"}
+                                }
+                            }
+                        }
+                    },
+                    "400": {"description": "limit or offset is not a non-negative integer"}
+                }
+            },
+            "post": {
+                "summary": "Create a new DNS record",
+                "security": [{"bearerAuth": []}],
+                "requestBody": {
+                    "required": true,
+                    "content": {
+                        "application/json": {
+                            "schema": {"$ref": "#/components/schemas/DnsRecord"},
+                            "examples": {
+                                "srv": {
+                                    "summary": "SIP over UDP service record",
+                                    "value": {
+                                        "name": "_sip._udp",
+                                        "record_type": "SRV",
+                                        "value": "sip1.example.com",
+                                        "ttl": 3600,
+                                        "priority": 10,
+                                        "weight": 60,
+                                        "port": 5060
                                     }
                                 }
                             }
                         }
                     }
                 },
-                "post": {
-                    "summary": "Create a new DNS record",
-                    "security": [{"bearerAuth": []}],
-                    "requestBody": {
-                        "required": true,
+                "responses": {
+                    "201": {
+                        "description": "Record created",
                         "content": {
                             "application/json": {
                                 "schema": {"$ref": "#/components/schemas/DnsRecord"}
                             }
                         }
                     },
-                    "responses": {
-                        "201": {
-                            "description": "Record created",
-                            "content": {
-                                "application/json": {
-                                    "schema": {"$ref": "#/components/schemas/DnsRecord"}
-                                }
-                            }
-                        }
-                    }
+                    "400": {"description": "Value does not match the record type, or MX/SRV fields are missing"},
+                    "403": {"description": "Read-only role; admin or operator role required"},
+                    "409": {"description": "Record conflicts with an existing record at the same name"}
                 }
-            },
-            "/system/health": {
-                "get": {
-                    "summary": "Health check endpoint",
-                    "responses": {
-                        "200": {
-                            "description": "System health status",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "status": {"type": "string"},
-                                            "database": {"type": "string"},
-                                            "dhcp_server": {"type": "string"},
-                                            "dns_server": {"type": "string"},
-                                            "api_server": {"type": "string"},
-                                            "timestamp": {"type": "string", "format": "date-time"}
-                                        }
+            }
+        }
+    })
+}
+
+fn system_paths() -> Value {
+    json!({
+        "/system/health": {
+            "get": {
+                "summary": "Health check endpoint",
+                "responses": {
+                    "200": {
+                        "description": "System health status",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "status": {"type": "string"},
+                                        "database": {"type": "string"},
+                                        "dhcp_server": {"type": "string"},
+                                        "dns_server": {"type": "string"},
+                                        "api_server": {"type": "string"},
+                                        "timestamp": {"type": "string", "format": "date-time"}
                                     }
                                 }
                             }
                         }
                     }
                 }
-            },
-            "/system/metrics": {
-                "get": {
-                    "summary": "System metrics",
-                    "security": [{"bearerAuth": []}],
-                    "responses": {
-                        "200": {
-                            "description": "System metrics",
-                            "content": {
-                                "application/json": {
-                                    "schema": {
-                                        "type": "object",
-                                        "properties": {
-                                            "dhcp": {
-                                                "type": "object",
-                                                "properties": {
-                                                    "total_subnets": {"type": "integer"},
-                                                    "active_leases": {"type": "integer"},
-                                                    "expired_leases": {"type": "integer"},
-                                                    "reserved_addresses": {"type": "integer"},
-                                                    "available_addresses": {"type": "integer"}
-                                                }
-                                            },
-                                            "dns": {
-                                                "type": "object",
-                                                "properties": {
-                                                    "total_zones": {"type": "integer"},
-                                                    "total_records": {"type": "integer"},
-                                                    "dynamic_records": {"type": "integer"}
-                                                }
-                                            },
-                                            "system": {
-                                                "type": "object",
-                                                "properties": {
-                                                    "uptime_seconds": {"type": "integer"},
-                                                    "memory_usage_mb": {"type": "number"},
-                                                    "cpu_usage_percent": {"type": "number"}
-                                                }
+            }
+        },
+        "/system/metrics": {
+            "get": {
+                "summary": "System metrics",
+                "security": [{"bearerAuth": []}],
+                "responses": {
+                    "200": {
+                        "description": "System metrics",
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "dhcp": {
+                                            "type": "object",
+                                            "properties": {
+                                                "total_subnets": {"type": "integer"},
+                                                "active_leases": {"type": "integer"},
+                                                "expired_leases": {"type": "integer"},
+                                                "reserved_addresses": {"type": "integer"},
+                                                "available_addresses": {"type": "integer"},
+                                                "discovers": {"type": "integer"},
+                                                "offers": {"type": "integer"},
+                                                "requests": {"type": "integer"},
+                                                "acks": {"type": "integer"},
+                                                "naks": {"type": "integer"},
+                                                "parse_failures": {"type": "integer"},
+                                                "errors": {"type": "integer"},
+                                                "dropped": {"type": "integer"}
+                                            }
+                                        },
+                                        "dns": {
+                                            "type": "object",
+                                            "properties": {
+                                                "total_zones": {"type": "integer"},
+                                                "total_records": {"type": "integer"},
+                                                "dynamic_records": {"type": "integer"},
+                                                "cache_hits": {"type": "integer"},
+                                                "cache_misses": {"type": "integer"},
+                                                "cache_entries": {"type": "integer"},
+                                                "dropped": {"type": "integer"}
+                                            }
+                                        },
+                                        "system": {
+                                            "type": "object",
+                                            "properties": {
+                                                "uptime_seconds": {"type": "integer"},
+                                                "memory_usage_mb": {"type": "number"},
+                                                "cpu_usage_percent": {"type": "number"}
                                             }
                                         }
                                     }
@@ -407,12 +971,89 @@ pub async fn openapi_spec() -> HttpResponse {
                     }
                 }
             }
+        },
+        "/system/backup": {
+            "get": {
+                "summary": "Export subnets, reservations, zones, records and prefix pools as one JSON bundle (admin)",
+                "security": [{"bearerAuth": []}],
+                "responses": {
+                    "200": {"description": "Backup bundle"},
+                    "403": {"description": "Admin role required"}
+                }
+            }
+        },
+        "/system/restore": {
+            "post": {
+                "summary": "Restore a backup bundle in a single transaction (admin)",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {"name": "dry_run", "in": "query", "schema": {"type": "boolean"}, "description": "Validate and roll back without changing anything"}
+                ],
+                "responses": {
+                    "200": {"description": "Restore report with object counts"},
+                    "403": {"description": "Admin role required"},
+                    "422": {"description": "Bundle rejected; nothing was changed"}
+                }
+            }
         }
-    });
+    })
+}
 
-    HttpResponse::Ok()
-        .content_type("application/json")
-        .json(spec)
+fn ipv6_paths() -> Value {
+    json!({
+        "/ipv6/leases": {
+            "get": {
+                "summary": "List DHCPv6 address leases",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {"name": "state", "in": "query", "schema": {"type": "string"}}
+                ],
+                "responses": {
+                    "200": {"description": "List of DHCPv6 leases"}
+                }
+            }
+        },
+        "/ipv6/prefixes": {
+            "get": {
+                "summary": "List delegated prefixes",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {
+                        "name": "state",
+                        "in": "query",
+                        "schema": {"type": "string", "enum": ["available", "delegated", "reserved", "expired"]}
+                    }
+                ],
+                "responses": {
+                    "200": {"description": "List of delegated prefixes"},
+                    "400": {"description": "Unknown state"}
+                }
+            }
+        },
+        "/ipv6/prefixes/stats": {
+            "get": {
+                "summary": "Prefix delegation pool statistics",
+                "security": [{"bearerAuth": []}],
+                "responses": {
+                    "200": {"description": "Counts of delegations by state"},
+                    "503": {"description": "IPv6 is not enabled"}
+                }
+            }
+        },
+        "/ipv6/slaac": {
+            "get": {
+                "summary": "List SLAAC addresses registered for a MAC address",
+                "security": [{"bearerAuth": []}],
+                "parameters": [
+                    {"name": "mac", "in": "query", "required": true, "schema": {"type": "string"}}
+                ],
+                "responses": {
+                    "200": {"description": "SLAAC addresses, most recently seen first"},
+                    "400": {"description": "Invalid MAC address"}
+                }
+            }
+        }
+    })
 }
 
 pub async fn swagger_ui() -> HttpResponse {
@@ -0,0 +1,123 @@
+use actix_web::{web, HttpResponse};
+use crate::api::models::*;
+use crate::api::server::ApiState;
+use crate::api::validators::*;
+use crate::ipv6::dhcpv6_leases;
+use crate::ipv6::prefix_delegation::{PrefixDelegationManager, PrefixState};
+use crate::ipv6::slaac::SlaacManager;
+
+pub async fn list_leases(
+    state: web::Data<ApiState>,
+    query: web::Query<StateQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let leases = dhcpv6_leases::list_leases(&state.db, query.state.as_deref())
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let responses: Vec<Dhcpv6LeaseResponse> = leases.into_iter()
+        .map(|lease| Dhcpv6LeaseResponse {
+            id: lease.id,
+            subnet_id: lease.subnet_id,
+            duid: bytes_to_mac_string(&lease.duid),
+            iaid: lease.iaid,
+            ipv6_address: lease.ipv6_address,
+            hostname: lease.hostname,
+            lease_start: lease.lease_start,
+            lease_end: lease.lease_end,
+            preferred_lifetime: lease.preferred_lifetime,
+            valid_lifetime: lease.valid_lifetime,
+            state: lease.state,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(responses))
+}
+
+pub async fn list_prefixes(
+    state: web::Data<ApiState>,
+    query: web::Query<StateQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let state_filter = match query.state.as_deref().map(str::parse::<PrefixState>).transpose() {
+        Ok(state_filter) => state_filter,
+        Err(_) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_state",
+            "message": "State must be one of available, delegated, reserved, expired"
+        }))),
+    };
+
+    let delegations = PrefixDelegationManager::list_delegations(&state.db, state_filter.as_ref())
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let responses: Vec<DelegatedPrefixResponse> = delegations.into_iter()
+        .map(|delegation| DelegatedPrefixResponse {
+            id: delegation.id,
+            client_duid: bytes_to_mac_string(&delegation.client_duid),
+            iaid: delegation.iaid,
+            prefix: delegation.prefix,
+            delegated_length: delegation.delegated_length,
+            lease_start: delegation.lease_start,
+            lease_end: delegation.lease_end,
+            preferred_lifetime: delegation.preferred_lifetime,
+            valid_lifetime: delegation.valid_lifetime,
+            state: delegation.state.as_str().to_string(),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(responses))
+}
+
+pub async fn prefix_stats(
+    state: web::Data<ApiState>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(prefix_delegation) = &state.prefix_delegation else {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "ipv6_disabled",
+            "message": "Prefix delegation is not running in this process"
+        })));
+    };
+
+    let stats = prefix_delegation.get_statistics()
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(PrefixStatsResponse {
+        total_pools: stats.total_pools,
+        delegated_prefixes: stats.delegated_prefixes,
+        available_prefixes: stats.available_prefixes,
+        reserved_prefixes: stats.reserved_prefixes,
+        expired_prefixes: stats.expired_prefixes,
+    }))
+}
+
+pub async fn list_slaac_addresses(
+    state: web::Data<ApiState>,
+    query: web::Query<SlaacQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(mac_bytes) = mac_string_to_bytes(&query.mac) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid_mac",
+            "message": "Invalid MAC address format"
+        })));
+    };
+
+    let addresses = SlaacManager::new(state.db.clone())
+        .get_addresses_by_mac(&mac_bytes)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let responses: Vec<SlaacAddressResponse> = addresses.into_iter()
+        .map(|address| SlaacAddressResponse {
+            id: address.id,
+            mac_address: bytes_to_mac_string(&address.mac_address),
+            ipv6_address: address.ipv6_address,
+            prefix: address.prefix,
+            prefix_length: address.prefix_length,
+            hostname: address.hostname,
+            created_at: address.created_at,
+            last_seen: address.last_seen,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(responses))
+}
@@ -1,5 +1,6 @@
 pub mod auth;
 pub mod dhcp;
 pub mod dns;
+pub mod ipv6;
 pub mod system;
-pub mod docs;
\ No newline at end of file
+pub mod docs;
@@ -1,14 +1,24 @@
 use actix_web::{web, HttpResponse};
-use crate::api::models::{HealthResponse, MetricsResponse, DhcpMetrics, DnsMetrics, SystemMetrics};
+use crate::api::auth;
+use crate::api::backup::{self, BackupBundle};
+use crate::api::queries;
+use crate::api::models::{HealthResponse, MetricsResponse, DhcpMetrics, DnsMetrics, SystemMetrics, RestoreQuery};
 use crate::api::server::ApiState;
+use crate::database::notify::{self, ConfigChange};
+use crate::metrics::{self, PrometheusText};
 use chrono::Utc;
-use tracing::info;
+use std::sync::atomic::Ordering;
+use tracing::{info, warn};
+
+/// How long the health check waits for the database before reporting it unhealthy
+const HEALTH_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
 
 pub async fn health(
     state: web::Data<ApiState>,
 ) -> actix_web::Result<HttpResponse> {
-    // Check database connection (simplified - skip actual query for now)
-    let db_status = "healthy";
+    // Check database connection
+    let db_healthy = queries::check_database(&state.db, HEALTH_CHECK_TIMEOUT).await;
+    let db_status = if db_healthy { "healthy" } else { "unhealthy" };
 
     // Check service status
     let dhcp_status = if state.settings.dhcp.enabled {
@@ -24,7 +34,7 @@ pub async fn health(
     };
 
     let response = HealthResponse {
-        status: "healthy".to_string(),
+        status: db_status.to_string(),
         database: db_status.to_string(),
         dhcp_server: dhcp_status.to_string(),
         dns_server: dns_status.to_string(),
@@ -32,32 +42,62 @@ pub async fn health(
         timestamp: Utc::now(),
     };
 
-    Ok(HttpResponse::Ok().json(response))
+    // Let load balancers take us out of rotation while the database is unreachable
+    if db_healthy {
+        Ok(HttpResponse::Ok().json(response))
+    } else {
+        warn!("Health check failed: database unreachable");
+        Ok(HttpResponse::ServiceUnavailable().json(response))
+    }
 }
 
 pub async fn metrics(
-    _state: web::Data<ApiState>,
+    state: web::Data<ApiState>,
 ) -> actix_web::Result<HttpResponse> {
-    // Return realistic mock data for demo purposes
+    let (total_subnets, active_leases, expired_leases, reserved_addresses) = queries::get_dhcp_stats(&state.db)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+    let available_addresses = queries::get_available_addresses(&state.db)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    let counters = metrics::counters();
     let dhcp_metrics = DhcpMetrics {
-        total_subnets: 2,
-        active_leases: 15,
-        expired_leases: 3,
-        reserved_addresses: 10,
-        available_addresses: 180,
+        total_subnets,
+        active_leases,
+        expired_leases,
+        reserved_addresses,
+        available_addresses,
+        discovers: counters.dhcp_discover_total.load(Ordering::Relaxed),
+        offers: counters.dhcp_offer_total.load(Ordering::Relaxed),
+        requests: counters.dhcp_request_total.load(Ordering::Relaxed),
+        acks: counters.dhcp_ack_total.load(Ordering::Relaxed),
+        naks: counters.dhcp_nak_total.load(Ordering::Relaxed),
+        parse_failures: counters.dhcp_parse_failures_total.load(Ordering::Relaxed),
+        errors: counters.dhcp_errors_total.load(Ordering::Relaxed),
+        dropped: counters.dhcp_dropped_total.load(Ordering::Relaxed),
     };
 
+    let (total_zones, total_records, dynamic_records) = queries::get_dns_stats(&state.db)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+    let cache_stats = crate::dns::cache::shared_stats();
+
     let dns_metrics = DnsMetrics {
-        total_zones: 3,
-        total_records: 42,
-        dynamic_records: 15,
+        total_zones,
+        total_records,
+        dynamic_records,
+        cache_hits: cache_stats.map(|s| s.hits).unwrap_or(0),
+        cache_misses: cache_stats.map(|s| s.misses).unwrap_or(0),
+        cache_entries: cache_stats.map(|s| s.entries).unwrap_or(0),
+        dropped: counters.dns_dropped_total.load(Ordering::Relaxed),
     };
 
-    // Get system metrics (simplified - mock data for now)
+    let (memory_usage_mb, cpu_usage_percent) = process_usage(&state);
     let system_metrics = SystemMetrics {
-        uptime_seconds: 3600,  // 1 hour uptime
-        memory_usage_mb: 256.5,  // Mock memory usage
-        cpu_usage_percent: 12.5,  // Mock CPU usage
+        uptime_seconds: state.started_at.elapsed().as_secs() as i64,
+        memory_usage_mb,
+        cpu_usage_percent,
     };
 
     let response = MetricsResponse {
@@ -69,6 +109,139 @@ pub async fn metrics(
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Prometheus text exposition of the same figures as `metrics`, plus the packet counters
+/// and per-subnet utilization
+pub async fn prometheus_metrics(
+    state: web::Data<ApiState>,
+) -> actix_web::Result<HttpResponse> {
+    let (total_subnets, active_leases, expired_leases, reserved_addresses) = queries::get_dhcp_stats(&state.db)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+    let subnet_stats = queries::get_all_subnet_stats(&state.db)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+    let cache_stats = crate::dns::cache::shared_stats();
+    let counters = metrics::counters();
+
+    let mut text = PrometheusText::new();
+    text.gauge("flowdns_subnets", "Configured DHCP subnets", total_subnets as f64)
+        .gauge("flowdns_active_leases", "DHCP leases in the active state", active_leases as f64)
+        .gauge("flowdns_expired_leases", "DHCP leases in the expired state", expired_leases as f64)
+        .gauge("flowdns_reservations", "Static DHCP reservations", reserved_addresses as f64)
+        .counter(
+            "flowdns_dhcp_discover_total",
+            "DHCPDISCOVER messages received",
+            counters.dhcp_discover_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dhcp_request_total",
+            "DHCPREQUEST messages received",
+            counters.dhcp_request_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dhcp_offer_total",
+            "DHCPOFFER messages sent",
+            counters.dhcp_offer_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dhcp_ack_total",
+            "DHCPACK messages sent",
+            counters.dhcp_ack_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dhcp_nak_total",
+            "DHCPNAK messages sent",
+            counters.dhcp_nak_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dhcp_parse_failures_total",
+            "Received packets that could not be parsed as DHCP",
+            counters.dhcp_parse_failures_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dhcp_errors_total",
+            "DHCP packets whose handling failed",
+            counters.dhcp_errors_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dhcp_dropped_total",
+            "DHCP packets dropped because the work queue was full",
+            counters.dhcp_dropped_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dns_queries_total",
+            "DNS queries received",
+            counters.dns_queries_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dns_dropped_total",
+            "DNS queries and TCP connections dropped because too many were in flight",
+            counters.dns_dropped_total.load(Ordering::Relaxed),
+        )
+        .counter(
+            "flowdns_dns_cache_hits_total",
+            "Upstream answers served from the DNS cache",
+            cache_stats.map(|s| s.hits).unwrap_or(0),
+        )
+        .counter(
+            "flowdns_dns_cache_misses_total",
+            "DNS cache lookups that had to go upstream",
+            cache_stats.map(|s| s.misses).unwrap_or(0),
+        )
+        .gauge(
+            "flowdns_dns_cache_entries",
+            "Answers currently held in the DNS cache",
+            cache_stats.map(|s| s.entries).unwrap_or(0) as f64,
+        )
+        .labeled_gauge(
+            "flowdns_subnet_active_leases",
+            "Active leases per subnet",
+            "subnet",
+            subnet_stats.iter().map(|s| (s.subnet_name.as_str(), s.active_leases as f64)),
+        )
+        .labeled_gauge(
+            "flowdns_subnet_available_addresses",
+            "Pool addresses neither leased nor reserved, per subnet",
+            "subnet",
+            subnet_stats.iter().map(|s| (s.subnet_name.as_str(), s.available_addresses as f64)),
+        )
+        .labeled_gauge(
+            "flowdns_subnet_utilization_percent",
+            "Share of each subnet's pool that is leased or reserved",
+            "subnet",
+            subnet_stats.iter().map(|s| (s.subnet_name.as_str(), s.utilization_percent as f64)),
+        )
+        .gauge(
+            "flowdns_uptime_seconds",
+            "Seconds since the API server started",
+            state.started_at.elapsed().as_secs_f64(),
+        );
+
+    Ok(HttpResponse::Ok()
+        .content_type(PrometheusText::CONTENT_TYPE)
+        .body(text.finish()))
+}
+
+/// Resident memory (MB) and CPU usage (%) of this process. CPU is averaged since the
+/// previous call, so the first sample after startup reads 0.
+fn process_usage(state: &ApiState) -> (f64, f64) {
+    let Ok(pid) = sysinfo::get_current_pid() else {
+        return (0.0, 0.0);
+    };
+
+    let mut system = state.system.lock().unwrap_or_else(|e| e.into_inner());
+    if !system.refresh_process(pid) {
+        return (0.0, 0.0);
+    }
+
+    system.process(pid)
+        .map(|process| (
+            process.memory() as f64 / (1024.0 * 1024.0),
+            f64::from(process.cpu_usage()),
+        ))
+        .unwrap_or((0.0, 0.0))
+}
+
 pub async fn get_config(
     state: web::Data<ApiState>,
 ) -> actix_web::Result<HttpResponse> {
@@ -97,4 +270,56 @@ pub async fn get_config(
     info!("Configuration requested via API");
 
     Ok(HttpResponse::Ok().json(config))
-}
\ No newline at end of file
+}
+
+pub async fn backup(
+    _admin: auth::RequireAdmin,
+    state: web::Data<ApiState>,
+) -> actix_web::Result<HttpResponse> {
+    let bundle = backup::export(&state.db)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Database error: {}", e)))?;
+
+    info!(
+        "Backup exported: {} subnets, {} reservations, {} zones, {} records, {} prefix pools",
+        bundle.subnets.len(),
+        bundle.reservations.len(),
+        bundle.zones.len(),
+        bundle.records.len(),
+        bundle.prefix_pools.len()
+    );
+
+    let filename = format!("flowdns-backup-{}.json", bundle.created_at.format("%Y%m%dT%H%M%SZ"));
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .json(bundle))
+}
+
+pub async fn restore(
+    _admin: auth::RequireAdmin,
+    state: web::Data<ApiState>,
+    query: web::Query<RestoreQuery>,
+    bundle: web::Json<BackupBundle>,
+) -> actix_web::Result<HttpResponse> {
+    match backup::restore(&state.db, &bundle, query.dry_run).await {
+        Ok(report) => {
+            if !report.dry_run {
+                info!("Configuration restored from backup taken at {}", bundle.created_at);
+                if let Some(lease_manager) = &state.lease_manager {
+                    if let Err(e) = lease_manager.reload_subnets().await {
+                        warn!("Failed to reload DHCP subnets after restore: {}", e);
+                    }
+                }
+                notify::announce(&state.db, ConfigChange::All).await;
+            }
+            Ok(HttpResponse::Ok().json(report))
+        }
+        Err(e) => {
+            warn!("Restore rejected: {}", e);
+            Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": "restore_failed",
+                "message": e.to_string()
+            })))
+        }
+    }
+}
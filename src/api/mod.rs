@@ -3,4 +3,8 @@ pub mod auth;
 pub mod handlers;
 pub mod models;
 pub mod validators;
-pub mod queries;
\ No newline at end of file
+pub mod queries;
+pub mod backup;
+pub mod users;
+pub mod rate_limit;
+pub mod request_id;
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
-use std::net::Ipv4Addr;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use crate::database::models::{BootRule, DhcpOptionValue, ExcludedRange};
 
 // Authentication models
 #[derive(Debug, Deserialize)]
@@ -15,6 +17,12 @@ pub struct RefreshTokenRequest {
     pub refresh_token: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LogoutRequest {
+    /// Also revoke this refresh token if it belongs to the same user
+    pub refresh_token: Option<String>,
+}
+
 // DHCP models
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LeaseResponse {
@@ -26,6 +34,21 @@ pub struct LeaseResponse {
     pub lease_start: DateTime<Utc>,
     pub lease_end: DateTime<Utc>,
     pub state: String,
+    pub client_identifier: Option<String>,
+    pub vendor_class: Option<String>,
+    pub user_class: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeaseEventResponse {
+    pub id: i64,
+    pub lease_id: Uuid,
+    pub mac_address: String,
+    pub ip_address: Ipv4Addr,
+    pub old_state: Option<String>,
+    pub new_state: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -49,6 +72,14 @@ pub struct SubnetResponse {
     pub lease_duration: i32,
     pub vlan_id: Option<i32>,
     pub enabled: bool,
+    pub maintenance: bool,
+    pub relay_circuit_id: Option<String>,
+    pub server_ip: Option<Ipv4Addr>,
+    pub boot_server: Option<Ipv4Addr>,
+    pub boot_filename: Option<String>,
+    pub boot_rules: Vec<BootRule>,
+    pub excluded_ranges: Vec<ExcludedRange>,
+    pub dhcp_options: BTreeMap<u8, DhcpOptionValue>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -62,6 +93,25 @@ pub struct CreateSubnetRequest {
     pub domain_name: Option<String>,
     pub lease_duration: Option<i32>,
     pub vlan_id: Option<i32>,
+    /// Option 82 circuit-id that selects this subnet for relayed clients
+    pub relay_circuit_id: Option<String>,
+    /// Server identifier (option 54) clients of this subnet see; derived when omitted
+    pub server_ip: Option<Ipv4Addr>,
+    /// Next-server for network booting clients
+    pub boot_server: Option<Ipv4Addr>,
+    pub boot_filename: Option<String>,
+    /// Per vendor class / architecture boot files, tried in order before `boot_filename`
+    #[serde(default)]
+    pub boot_rules: Vec<BootRule>,
+    /// Addresses never handed out: single IPs, `start-end` ranges or CIDR blocks
+    #[serde(default)]
+    pub excluded_ranges: Vec<ExcludedRange>,
+    /// Extra options by code, replacing the server's own option with the same code
+    #[serde(default)]
+    pub dhcp_options: BTreeMap<u8, DhcpOptionValue>,
+    /// Answer DHCPv6 SOLICITs carrying Rapid Commit with an immediate REPLY
+    #[serde(default)]
+    pub dhcpv6_rapid_commit: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -74,6 +124,15 @@ pub struct UpdateSubnetRequest {
     pub domain_name: Option<String>,
     pub lease_duration: Option<i32>,
     pub enabled: Option<bool>,
+    pub maintenance: Option<bool>,
+    pub relay_circuit_id: Option<String>,
+    pub server_ip: Option<Ipv4Addr>,
+    pub boot_server: Option<Ipv4Addr>,
+    pub boot_filename: Option<String>,
+    pub boot_rules: Option<Vec<BootRule>>,
+    pub excluded_ranges: Option<Vec<ExcludedRange>>,
+    pub dhcp_options: Option<BTreeMap<u8, DhcpOptionValue>>,
+    pub dhcpv6_rapid_commit: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -96,6 +155,19 @@ pub struct CreateReservationRequest {
     pub description: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ExtendLeaseRequest {
+    /// Seconds added to the lease, counted from now if it has already run out
+    pub duration: u32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReserveLeaseRequest {
+    pub description: Option<String>,
+    #[serde(default)]
+    pub delete_lease: bool,
+}
+
 // DNS models
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ZoneResponse {
@@ -143,6 +215,7 @@ pub struct RecordResponse {
     pub weight: Option<i32>,
     pub port: Option<i32>,
     pub is_dynamic: bool,
+    pub view: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -156,6 +229,9 @@ pub struct CreateRecordRequest {
     pub priority: Option<i32>,
     pub weight: Option<i32>,
     pub port: Option<i32>,
+    /// One of the configured `dns.views`; defaults to "default"
+    #[serde(default)]
+    pub view: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -167,7 +243,116 @@ pub struct UpdateRecordRequest {
     pub port: Option<i32>,
 }
 
+// IPv6 models
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dhcpv6LeaseResponse {
+    pub id: Uuid,
+    pub subnet_id: Uuid,
+    pub duid: String,
+    pub iaid: u32,
+    pub ipv6_address: Ipv6Addr,
+    pub hostname: Option<String>,
+    pub lease_start: DateTime<Utc>,
+    pub lease_end: DateTime<Utc>,
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+    pub state: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DelegatedPrefixResponse {
+    pub id: Uuid,
+    pub client_duid: String,
+    pub iaid: u32,
+    pub prefix: Ipv6Addr,
+    pub delegated_length: u8,
+    pub lease_start: DateTime<Utc>,
+    pub lease_end: DateTime<Utc>,
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+    pub state: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SlaacAddressResponse {
+    pub id: Uuid,
+    pub mac_address: String,
+    pub ipv6_address: Ipv6Addr,
+    pub prefix: Ipv6Addr,
+    pub prefix_length: u8,
+    pub hostname: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrefixStatsResponse {
+    pub total_pools: usize,
+    pub delegated_prefixes: u32,
+    pub available_prefixes: u32,
+    pub reserved_prefixes: u32,
+    pub expired_prefixes: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StateQuery {
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SlaacQuery {
+    pub mac: String,
+}
+
+// Pagination models
+pub const DEFAULT_PAGE_LIMIT: i64 = 100;
+pub const MAX_PAGE_LIMIT: i64 = 1000;
+/// Response header carrying the number of rows across all pages
+pub const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self { limit: DEFAULT_PAGE_LIMIT, offset: 0 }
+    }
+}
+
+/// Which leases a listing returns; every filter that is set must match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseFilter {
+    pub state: String,
+    pub mac_address: Option<Vec<u8>>,
+    pub ip_address: Option<Ipv4Addr>,
+    /// Matched case-insensitively anywhere in the hostname
+    pub hostname: Option<String>,
+    pub subnet_id: Option<Uuid>,
+}
+
+impl Default for LeaseFilter {
+    fn default() -> Self {
+        Self {
+            state: "active".to_string(),
+            mac_address: None,
+            ip_address: None,
+            hostname: None,
+            subnet_id: None,
+        }
+    }
+}
+
 // System models
+#[derive(Debug, Default, Deserialize)]
+pub struct RestoreQuery {
+    /// Validate and apply the bundle inside a transaction that is rolled back
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct HealthResponse {
     pub status: String,
@@ -192,6 +377,15 @@ pub struct DhcpMetrics {
     pub expired_leases: i64,
     pub reserved_addresses: i64,
     pub available_addresses: i64,
+    /// Packet counters since the server started
+    pub discovers: u64,
+    pub offers: u64,
+    pub requests: u64,
+    pub acks: u64,
+    pub naks: u64,
+    pub parse_failures: u64,
+    pub errors: u64,
+    pub dropped: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -199,6 +393,10 @@ pub struct DnsMetrics {
     pub total_zones: i64,
     pub total_records: i64,
     pub dynamic_records: i64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub cache_entries: usize,
+    pub dropped: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -4,6 +4,11 @@ use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use anyhow::Result;
 use std::net::Ipv4Addr;
+use crate::api::models::{LeaseFilter, Pagination};
+use std::collections::BTreeMap;
+use crate::database::models::{BootRule, DhcpOptionValue, ExcludedRange, DEFAULT_VIEW};
+use crate::dhcp::lease_events::LeaseEventSource;
+use crate::dhcp::packet::ClientInfo;
 
 pub struct LeaseRow {
     pub id: Uuid,
@@ -14,22 +19,35 @@ pub struct LeaseRow {
     pub lease_start: DateTime<Utc>,
     pub lease_end: DateTime<Utc>,
     pub state: String,
+    pub client_identifier: Option<String>,
+    pub vendor_class: Option<String>,
+    pub user_class: Option<String>,
 }
 
-pub async fn fetch_active_leases(db: &PgPool, state_filter: &str) -> Result<Vec<LeaseRow>> {
-    let rows = sqlx::query(
+/// One page of the leases matching `filter`, plus the number of such leases across all pages
+pub async fn fetch_active_leases(
+    db: &PgPool,
+    filter: &LeaseFilter,
+    page: Pagination,
+) -> Result<(Vec<LeaseRow>, i64)> {
+    let mut count = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT COUNT(*) as total FROM dhcp_leases");
+    push_lease_filter(&mut count, filter);
+    let total: i64 = count.build()
+        .fetch_one(db)
+        .await?
+        .get("total");
+
+    let mut select = sqlx::QueryBuilder::<sqlx::Postgres>::new(
         r#"
         SELECT id, subnet_id, mac_address, ip_address, hostname,
-               lease_start, lease_end, state
-        FROM dhcp_leases
-        WHERE state = $1
-        ORDER BY lease_start DESC
-        LIMIT 100
-        "#
-    )
-    .bind(state_filter)
-    .fetch_all(db)
-    .await?;
+               lease_start, lease_end, state,
+               client_identifier, vendor_class, user_class
+        FROM dhcp_leases"#
+    );
+    push_lease_filter(&mut select, filter);
+    select.push(" ORDER BY lease_start DESC, id LIMIT ").push_bind(page.limit);
+    select.push(" OFFSET ").push_bind(page.offset);
+    let rows = select.build().fetch_all(db).await?;
 
     let mut leases = Vec::new();
     for row in rows {
@@ -42,18 +60,41 @@ pub async fn fetch_active_leases(db: &PgPool, state_filter: &str) -> Result<Vec<
             lease_start: row.get("lease_start"),
             lease_end: row.get("lease_end"),
             state: row.get("state"),
+            client_identifier: row.get("client_identifier"),
+            vendor_class: row.get("vendor_class"),
+            user_class: row.get("user_class"),
         };
         leases.push(lease);
     }
 
-    Ok(leases)
+    Ok((leases, total))
+}
+
+/// Appends the WHERE clause for `filter`, every value a bound parameter
+fn push_lease_filter(builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>, filter: &LeaseFilter) {
+    builder.push(" WHERE state = ").push_bind(filter.state.clone());
+    if let Some(mac) = &filter.mac_address {
+        builder.push(" AND mac_address = ").push_bind(mac.clone());
+    }
+    if let Some(ip) = filter.ip_address {
+        builder.push(" AND ip_address = ").push_bind(std::net::IpAddr::V4(ip));
+    }
+    if let Some(hostname) = &filter.hostname {
+        // Wildcards in the search text match themselves
+        let escaped = hostname.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        builder.push(" AND hostname ILIKE ").push_bind(format!("%{}%", escaped));
+    }
+    if let Some(subnet_id) = filter.subnet_id {
+        builder.push(" AND subnet_id = ").push_bind(subnet_id);
+    }
 }
 
 pub async fn fetch_lease_by_id(db: &PgPool, lease_id: Uuid) -> Result<Option<LeaseRow>> {
     let row = sqlx::query(
         r#"
         SELECT id, subnet_id, mac_address, ip_address, hostname,
-               lease_start, lease_end, state
+               lease_start, lease_end, state,
+               client_identifier, vendor_class, user_class
         FROM dhcp_leases
         WHERE id = $1
         "#
@@ -72,6 +113,9 @@ pub async fn fetch_lease_by_id(db: &PgPool, lease_id: Uuid) -> Result<Option<Lea
             lease_start: row.get("lease_start"),
             lease_end: row.get("lease_end"),
             state: row.get("state"),
+            client_identifier: row.get("client_identifier"),
+            vendor_class: row.get("vendor_class"),
+            user_class: row.get("user_class"),
         })),
         None => Ok(None),
     }
@@ -80,12 +124,19 @@ pub async fn fetch_lease_by_id(db: &PgPool, lease_id: Uuid) -> Result<Option<Lea
 pub async fn release_lease(db: &PgPool, lease_id: Uuid) -> Result<u64> {
     let result = sqlx::query(
         r#"
-        UPDATE dhcp_leases
-        SET state = 'released', updated_at = NOW()
-        WHERE id = $1 AND state = 'active'
+        WITH released AS (
+            UPDATE dhcp_leases
+            SET state = 'released', updated_at = NOW()
+            WHERE id = $1 AND state = 'active'
+            RETURNING id, mac_address, ip_address
+        )
+        INSERT INTO lease_events (lease_id, mac_address, ip_address, old_state, new_state, source)
+        SELECT id, mac_address, ip_address, 'active', 'released', $2
+        FROM released
         "#
     )
     .bind(lease_id)
+    .bind(LeaseEventSource::Api.as_str())
     .execute(db)
     .await?;
 
@@ -99,32 +150,194 @@ pub struct SubnetRow {
     pub start_ip: Ipv4Addr,
     pub end_ip: Ipv4Addr,
     pub gateway: Ipv4Addr,
-    pub dns_servers: serde_json::Value,
+    pub dns_servers: Vec<Ipv4Addr>,
     pub domain_name: Option<String>,
     pub lease_duration: i32,
     pub vlan_id: Option<i32>,
     pub enabled: bool,
+    pub maintenance: bool,
+    pub relay_circuit_id: Option<String>,
+    pub server_ip: Option<Ipv4Addr>,
+    pub boot_server: Option<Ipv4Addr>,
+    pub boot_filename: Option<String>,
+    pub boot_rules: Vec<BootRule>,
+    pub excluded_ranges: Vec<ExcludedRange>,
+    pub dhcp_options: BTreeMap<u8, DhcpOptionValue>,
 }
 
-pub async fn fetch_all_subnets(db: &PgPool) -> Result<Vec<SubnetRow>> {
+/// One page of subnets ordered by name, plus the total number of subnets
+pub async fn fetch_all_subnets(db: &PgPool, page: Pagination) -> Result<(Vec<SubnetRow>, i64)> {
+    let total: i64 = sqlx::query("SELECT COUNT(*) as total FROM dhcp_subnets")
+        .fetch_one(db)
+        .await?
+        .get("total");
+
     let rows = sqlx::query(
         r#"
         SELECT id, name, network, start_ip, end_ip, gateway,
-               dns_servers, domain_name, lease_duration, vlan_id, enabled
+               dns_servers, domain_name, lease_duration, vlan_id, enabled,
+               maintenance, relay_circuit_id, server_ip, boot_server, boot_filename, boot_rules,
+               excluded_ranges, dhcp_options
         FROM dhcp_subnets
-        ORDER BY name
+        ORDER BY name, id
+        LIMIT $1 OFFSET $2
         "#
     )
+    .bind(page.limit)
+    .bind(page.offset)
     .fetch_all(db)
     .await?;
 
     let mut subnets = Vec::new();
     for row in rows {
-        // For now, just return empty list to get compilation working
-        // Full database integration will be implemented when database is properly configured
+        subnets.push(subnet_row_from(&row)?);
+    }
+
+    Ok((subnets, total))
+}
+
+pub async fn fetch_subnet_by_id(db: &PgPool, subnet_id: Uuid) -> Result<Option<SubnetRow>> {
+    let row = sqlx::query(
+        r#"
+        SELECT id, name, network, start_ip, end_ip, gateway,
+               dns_servers, domain_name, lease_duration, vlan_id, enabled,
+               maintenance, relay_circuit_id, server_ip, boot_server, boot_filename, boot_rules,
+               excluded_ranges, dhcp_options
+        FROM dhcp_subnets
+        WHERE id = $1
+        "#
+    )
+    .bind(subnet_id)
+    .fetch_optional(db)
+    .await?;
+
+    match row {
+        Some(row) => Ok(Some(subnet_row_from(&row)?)),
+        None => Ok(None),
+    }
+}
+
+fn subnet_row_from(row: &sqlx::postgres::PgRow) -> Result<SubnetRow> {
+    Ok(SubnetRow {
+        id: row.get("id"),
+        name: row.get("name"),
+        network: row.get::<ipnetwork::IpNetwork, _>("network").to_string(),
+        start_ip: row.get::<std::net::IpAddr, _>("start_ip").to_string().parse()?,
+        end_ip: row.get::<std::net::IpAddr, _>("end_ip").to_string().parse()?,
+        gateway: row.get::<std::net::IpAddr, _>("gateway").to_string().parse()?,
+        dns_servers: serde_json::from_value(row.get("dns_servers"))?,
+        domain_name: row.get("domain_name"),
+        lease_duration: row.get("lease_duration"),
+        vlan_id: row.get("vlan_id"),
+        enabled: row.get("enabled"),
+        maintenance: row.get("maintenance"),
+        relay_circuit_id: row.get("relay_circuit_id"),
+        server_ip: row.get::<Option<std::net::IpAddr>, _>("server_ip")
+            .map(|ip| ip.to_string().parse())
+            .transpose()?,
+        boot_server: row.get::<Option<std::net::IpAddr>, _>("boot_server")
+            .map(|ip| ip.to_string().parse())
+            .transpose()?,
+        boot_filename: row.get("boot_filename"),
+        boot_rules: serde_json::from_value(row.get("boot_rules"))?,
+        excluded_ranges: serde_json::from_value(row.get("excluded_ranges"))?,
+        dhcp_options: serde_json::from_value(row.get("dhcp_options"))?,
+    })
+}
+
+pub enum CreateSubnetOutcome {
+    Created(Uuid),
+    InvalidRange(String),
+    /// The new network overlaps the named existing subnet
+    Overlaps(String),
+}
+
+pub async fn create_subnet(
+    db: &PgPool,
+    req: &crate::api::models::CreateSubnetRequest,
+) -> Result<CreateSubnetOutcome> {
+    let network: ipnetwork::IpNetwork = req.network.parse()?;
+    if let Err(e) = crate::config::check_subnet_range(&network, req.start_ip, req.end_ip, req.gateway) {
+        return Ok(CreateSubnetOutcome::InvalidRange(e.to_string()));
+    }
+    if let Err(e) = crate::config::check_excluded_ranges(&network, &req.excluded_ranges) {
+        return Ok(CreateSubnetOutcome::InvalidRange(e.to_string()));
+    }
+    if let Err(e) = crate::config::check_dhcp_options(&req.dhcp_options) {
+        return Ok(CreateSubnetOutcome::InvalidRange(e.to_string()));
+    }
+    let new_net: ipnet::IpNet = req.network.parse()?;
+
+    let mut tx = db.begin().await?;
+
+    // Serialise subnet creation so two overlapping requests can't both pass the check below
+    sqlx::query("LOCK TABLE dhcp_subnets IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+
+    let existing = sqlx::query("SELECT name, network FROM dhcp_subnets")
+        .fetch_all(&mut *tx)
+        .await?;
+
+    for row in existing {
+        let network: ipnetwork::IpNetwork = row.get("network");
+        let existing_net: ipnet::IpNet = network.to_string().parse()?;
+        let existing_net = existing_net.trunc();
+        if existing_net.contains(&new_net.network()) || new_net.contains(&existing_net.network()) {
+            let name: String = row.get("name");
+            return Ok(CreateSubnetOutcome::Overlaps(
+                format!("{} overlaps subnet {} ({})", req.network, name, existing_net),
+            ));
+        }
     }
 
-    Ok(subnets)
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dhcp_subnets (name, network, start_ip, end_ip, gateway,
+                                 dns_servers, domain_name, lease_duration, vlan_id,
+                                 relay_circuit_id, server_ip, boot_server, boot_filename, boot_rules,
+                                 excluded_ranges, dhcp_options, dhcpv6_rapid_commit)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
+        RETURNING id
+        "#
+    )
+    .bind(&req.name)
+    .bind(network)
+    .bind(std::net::IpAddr::V4(req.start_ip))
+    .bind(std::net::IpAddr::V4(req.end_ip))
+    .bind(std::net::IpAddr::V4(req.gateway))
+    .bind(serde_json::to_value(&req.dns_servers)?)
+    .bind(&req.domain_name)
+    .bind(req.lease_duration.unwrap_or(86400))
+    .bind(req.vlan_id)
+    .bind(&req.relay_circuit_id)
+    .bind(req.server_ip.map(std::net::IpAddr::V4))
+    .bind(req.boot_server.map(std::net::IpAddr::V4))
+    .bind(&req.boot_filename)
+    .bind(serde_json::to_value(&req.boot_rules)?)
+    .bind(serde_json::to_value(&req.excluded_ranges)?)
+    .bind(serde_json::to_value(&req.dhcp_options)?)
+    .bind(req.dhcpv6_rapid_commit)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(CreateSubnetOutcome::Created(row.get("id")))
+}
+
+pub async fn delete_subnet(db: &PgPool, subnet_id: Uuid) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM dhcp_subnets
+        WHERE id = $1
+        "#
+    )
+    .bind(subnet_id)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
 }
 
 pub async fn get_dhcp_stats(db: &PgPool) -> Result<(i64, i64, i64, i64)> {
@@ -148,6 +361,102 @@ pub async fn get_dhcp_stats(db: &PgPool) -> Result<(i64, i64, i64, i64)> {
     ))
 }
 
+/// Per-subnet counts behind `SubnetStats`; `$1` is the current time.
+/// Reservations inside the pool only reduce availability while no active lease holds the address,
+/// so a reserved client that currently has its lease isn't counted twice.
+const SUBNET_STATS_SELECT: &str = r#"
+    SELECT
+        s.id,
+        s.name,
+        (s.end_ip - s.start_ip + 1)::BIGINT AS total_addresses,
+        (SELECT COUNT(*) FROM dhcp_leases l
+         WHERE l.subnet_id = s.id AND l.state = 'active' AND l.lease_end > $1) AS active_leases,
+        (SELECT COUNT(*) FROM dhcp_reservations r
+         WHERE r.subnet_id = s.id) AS reserved_addresses,
+        (SELECT COUNT(*) FROM dhcp_reservations r
+         WHERE r.subnet_id = s.id
+             AND r.ip_address BETWEEN s.start_ip AND s.end_ip
+             AND NOT EXISTS (
+                 SELECT 1 FROM dhcp_leases l
+                 WHERE l.ip_address = r.ip_address AND l.state = 'active' AND l.lease_end > $1
+             )) AS idle_reservations
+    FROM dhcp_subnets s
+"#;
+
+/// Pool size, lease and reservation counts for one subnet, or None if it doesn't exist
+pub async fn get_subnet_stats(db: &PgPool, subnet_id: Uuid) -> Result<Option<crate::database::models::SubnetStats>> {
+    let row = sqlx::query(&format!("{} WHERE s.id = $2", SUBNET_STATS_SELECT))
+        .bind(Utc::now())
+        .bind(subnet_id)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(row.as_ref().map(subnet_stats_from))
+}
+
+/// `SubnetStats` for every subnet, ordered by name
+pub async fn get_all_subnet_stats(db: &PgPool) -> Result<Vec<crate::database::models::SubnetStats>> {
+    let rows = sqlx::query(&format!("{} ORDER BY s.name, s.id", SUBNET_STATS_SELECT))
+        .bind(Utc::now())
+        .fetch_all(db)
+        .await?;
+
+    Ok(rows.iter().map(subnet_stats_from).collect())
+}
+
+fn subnet_stats_from(row: &sqlx::postgres::PgRow) -> crate::database::models::SubnetStats {
+    let total: i64 = row.get("total_addresses");
+    let active: i64 = row.get("active_leases");
+    let reserved: i64 = row.get("reserved_addresses");
+    let idle_reservations: i64 = row.get("idle_reservations");
+    let available = (total - active - idle_reservations).max(0);
+    let utilization_percent = if total > 0 {
+        ((total - available) as f64 / total as f64 * 100.0) as f32
+    } else {
+        0.0
+    };
+
+    crate::database::models::SubnetStats {
+        subnet_id: row.get("id"),
+        subnet_name: row.get("name"),
+        total_addresses: u32::try_from(total).unwrap_or(u32::MAX),
+        active_leases: u32::try_from(active).unwrap_or(u32::MAX),
+        reserved_addresses: u32::try_from(reserved).unwrap_or(u32::MAX),
+        available_addresses: u32::try_from(available).unwrap_or(u32::MAX),
+        utilization_percent,
+    }
+}
+
+/// True when `SELECT 1` succeeds within `timeout`
+pub async fn check_database(db: &PgPool, timeout: std::time::Duration) -> bool {
+    matches!(
+        tokio::time::timeout(timeout, sqlx::query("SELECT 1").execute(db)).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Addresses left in the ranges of enabled subnets after unexpired active leases
+pub async fn get_available_addresses(db: &PgPool) -> Result<i64> {
+    let row = sqlx::query(
+        r#"
+        SELECT GREATEST(
+            (SELECT COALESCE(SUM(end_ip - start_ip + 1), 0) FROM dhcp_subnets WHERE enabled = true)::BIGINT
+            - (SELECT COUNT(*)
+               FROM dhcp_leases l
+               JOIN dhcp_subnets s ON s.id = l.subnet_id
+               WHERE s.enabled = true
+                   AND l.state = 'active'
+                   AND l.lease_end > NOW()),
+            0
+        ) as available_addresses
+        "#
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(row.get::<Option<i64>, _>("available_addresses").unwrap_or(0))
+}
+
 pub async fn get_dns_stats(db: &PgPool) -> Result<(i64, i64, i64)> {
     let row = sqlx::query(
         r#"
@@ -165,4 +474,767 @@ pub async fn get_dns_stats(db: &PgPool) -> Result<(i64, i64, i64)> {
         row.get::<Option<i64>, _>("total_records").unwrap_or(0),
         row.get::<Option<i64>, _>("dynamic_records").unwrap_or(0),
     ))
-}
\ No newline at end of file
+}
+
+pub struct ReservationRow {
+    pub id: Uuid,
+    pub subnet_id: Uuid,
+    pub mac_address: Vec<u8>,
+    pub ip_address: Ipv4Addr,
+    pub hostname: Option<String>,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One page of reservations, newest first, plus the total number of reservations
+pub async fn fetch_all_reservations(db: &PgPool, page: Pagination) -> Result<(Vec<ReservationRow>, i64)> {
+    let total: i64 = sqlx::query("SELECT COUNT(*) as total FROM dhcp_reservations")
+        .fetch_one(db)
+        .await?
+        .get("total");
+
+    let rows = sqlx::query(
+        r#"
+        SELECT id, subnet_id, mac_address, ip_address, hostname, description, created_at
+        FROM dhcp_reservations
+        ORDER BY created_at DESC, id
+        LIMIT $1 OFFSET $2
+        "#
+    )
+    .bind(page.limit)
+    .bind(page.offset)
+    .fetch_all(db)
+    .await?;
+
+    let mut reservations = Vec::new();
+    for row in rows {
+        reservations.push(ReservationRow {
+            id: row.get("id"),
+            subnet_id: row.get("subnet_id"),
+            mac_address: row.get("mac_address"),
+            ip_address: row.get::<std::net::IpAddr, _>("ip_address").to_string().parse()?,
+            hostname: row.get("hostname"),
+            description: row.get("description"),
+            created_at: row.get("created_at"),
+        });
+    }
+
+    Ok((reservations, total))
+}
+
+pub async fn create_reservation(
+    db: &PgPool,
+    req: &crate::api::models::CreateReservationRequest,
+    mac_address: &[u8],
+) -> Result<Uuid> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dhcp_reservations (subnet_id, mac_address, ip_address, hostname, description)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#
+    )
+    .bind(req.subnet_id)
+    .bind(mac_address)
+    .bind(std::net::IpAddr::V4(req.ip_address))
+    .bind(&req.hostname)
+    .bind(&req.description)
+    .fetch_one(db)
+    .await?;
+
+    Ok(row.get("id"))
+}
+
+pub async fn delete_reservation(db: &PgPool, reservation_id: Uuid) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM dhcp_reservations
+        WHERE id = $1
+        "#
+    )
+    .bind(reservation_id)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub enum ReserveLeaseOutcome {
+    Reserved(ReservationRow),
+    LeaseNotFound,
+    Conflict(String),
+}
+
+/// Turns a lease into a static reservation for the same subnet/MAC/IP/hostname.
+/// Runs in a single transaction so the lease is only removed once the reservation exists.
+pub async fn reserve_lease(
+    db: &PgPool,
+    lease_id: Uuid,
+    description: Option<String>,
+    delete_lease: bool,
+) -> Result<ReserveLeaseOutcome> {
+    let mut tx = db.begin().await?;
+
+    let lease = sqlx::query(
+        r#"
+        SELECT subnet_id, mac_address, ip_address, hostname
+        FROM dhcp_leases
+        WHERE id = $1
+        FOR UPDATE
+        "#
+    )
+    .bind(lease_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let lease = match lease {
+        Some(row) => row,
+        None => return Ok(ReserveLeaseOutcome::LeaseNotFound),
+    };
+
+    let subnet_id: Uuid = lease.get("subnet_id");
+    let mac_address: Vec<u8> = lease.get("mac_address");
+    let ip_address: std::net::IpAddr = lease.get("ip_address");
+    let hostname: Option<String> = lease.get("hostname");
+
+    let reserved_ip = match ip_address {
+        std::net::IpAddr::V4(ip) => ip,
+        std::net::IpAddr::V6(ip) => anyhow::bail!("unexpected IPv6 address {} in DHCPv4 table", ip),
+    };
+
+    let conflict = sqlx::query(
+        r#"
+        SELECT mac_address = $2 as same_mac
+        FROM dhcp_reservations
+        WHERE mac_address = $2 OR (subnet_id = $1 AND ip_address = $3)
+        LIMIT 1
+        "#
+    )
+    .bind(subnet_id)
+    .bind(&mac_address)
+    .bind(ip_address)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(row) = conflict {
+        let message = if row.get::<bool, _>("same_mac") {
+            "A reservation already exists for this MAC address"
+        } else {
+            "IP address is already reserved for another client"
+        };
+        return Ok(ReserveLeaseOutcome::Conflict(message.to_string()));
+    }
+
+    // A released or expired lease may name an address another client has been given since
+    let taken: Option<Uuid> = sqlx::query_scalar(
+        r#"
+        SELECT id FROM dhcp_leases
+        WHERE subnet_id = $1 AND ip_address = $2 AND state = 'active' AND mac_address <> $3
+        LIMIT 1
+        "#
+    )
+    .bind(subnet_id)
+    .bind(ip_address)
+    .bind(&mac_address)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if taken.is_some() {
+        return Ok(ReserveLeaseOutcome::Conflict("IP address is leased to another client".to_string()));
+    }
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dhcp_reservations (subnet_id, mac_address, ip_address, hostname, description)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, subnet_id, mac_address, ip_address, hostname, description, created_at
+        "#
+    )
+    .bind(subnet_id)
+    .bind(&mac_address)
+    .bind(ip_address)
+    .bind(&hostname)
+    .bind(description)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if delete_lease {
+        sqlx::query("DELETE FROM dhcp_leases WHERE id = $1")
+            .bind(lease_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(ReserveLeaseOutcome::Reserved(ReservationRow {
+        id: row.get("id"),
+        subnet_id: row.get("subnet_id"),
+        mac_address: row.get("mac_address"),
+        ip_address: reserved_ip,
+        hostname: row.get("hostname"),
+        description: row.get("description"),
+        created_at: row.get("created_at"),
+    }))
+}
+
+pub enum CreateLeaseOutcome {
+    Created(Box<crate::database::models::DhcpLease>),
+    SubnetNotFound,
+    OutOfRange { start: Ipv4Addr, end: Ipv4Addr },
+    Excluded,
+    InUse,
+    Exhausted,
+}
+
+/// Creates an active lease for `mac_address`, using `requested_ip` or the first free
+/// address in the subnet's range
+pub async fn create_lease(
+    db: &PgPool,
+    subnet_id: Uuid,
+    mac_address: &[u8],
+    requested_ip: Option<Ipv4Addr>,
+    hostname: Option<String>,
+) -> Result<CreateLeaseOutcome> {
+    use crate::dhcp::lease_manager_queries;
+
+    let subnet = lease_manager_queries::fetch_all_subnets(db)
+        .await?
+        .into_iter()
+        .find(|subnet| subnet.id == subnet_id);
+
+    let Some(subnet) = subnet else {
+        return Ok(CreateLeaseOutcome::SubnetNotFound);
+    };
+
+    let used_ips = fetch_used_ips(db, subnet.id, mac_address).await?;
+
+    let candidates: Vec<Ipv4Addr> = match requested_ip {
+        Some(ip) if ip < subnet.start_ip || ip > subnet.end_ip => {
+            return Ok(CreateLeaseOutcome::OutOfRange { start: subnet.start_ip, end: subnet.end_ip });
+        }
+        Some(ip) if subnet.is_excluded(ip) => return Ok(CreateLeaseOutcome::Excluded),
+        Some(ip) if used_ips.contains(&ip) => return Ok(CreateLeaseOutcome::InUse),
+        Some(ip) => vec![ip],
+        None => (u32::from(subnet.start_ip)..=u32::from(subnet.end_ip))
+            .map(Ipv4Addr::from)
+            .filter(|ip| !used_ips.contains(ip) && !subnet.is_excluded(*ip))
+            .collect(),
+    };
+
+    let lease_start = Utc::now();
+    let lease_end = lease_start + chrono::Duration::seconds(subnet.lease_duration as i64);
+
+    // Another allocation can take an address between the read above and the insert; the
+    // active-address index turns that into a conflict and the next free address is tried
+    for ip_address in candidates {
+        let result = lease_manager_queries::insert_or_update_lease(
+            db,
+            subnet.id,
+            mac_address,
+            ip_address,
+            hostname.clone(),
+            ClientInfo::default(),
+            lease_start,
+            lease_end,
+            LeaseEventSource::Api,
+        )
+        .await;
+
+        match result {
+            Ok(lease) => return Ok(CreateLeaseOutcome::Created(Box::new(lease))),
+            Err(e) if lease_manager_queries::is_address_conflict(&e) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(match requested_ip {
+        Some(_) => CreateLeaseOutcome::InUse,
+        None => CreateLeaseOutcome::Exhausted,
+    })
+}
+
+/// Addresses in a subnet held by an active lease or reservation belonging to a different MAC
+pub async fn fetch_used_ips(
+    db: &PgPool,
+    subnet_id: Uuid,
+    mac_address: &[u8],
+) -> Result<std::collections::HashSet<Ipv4Addr>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT ip_address FROM dhcp_leases
+        WHERE subnet_id = $1 AND mac_address <> $2
+          AND state = 'active' AND lease_end > NOW()
+        UNION
+        SELECT ip_address FROM dhcp_reservations
+        WHERE subnet_id = $1 AND mac_address <> $2
+        "#
+    )
+    .bind(subnet_id)
+    .bind(mac_address)
+    .fetch_all(db)
+    .await?;
+
+    let mut used = std::collections::HashSet::new();
+    for row in rows {
+        if let std::net::IpAddr::V4(ip) = row.get::<std::net::IpAddr, _>("ip_address") {
+            used.insert(ip);
+        }
+    }
+
+    Ok(used)
+}
+
+pub enum UpdateSubnetOutcome {
+    Updated(Box<crate::database::models::DhcpSubnet>),
+    NotFound,
+    InvalidRange(String),
+}
+
+/// Applies the fields of `req` that are set, leaving the rest of the subnet untouched
+pub async fn update_subnet(
+    db: &PgPool,
+    subnet_id: Uuid,
+    req: &crate::api::models::UpdateSubnetRequest,
+) -> Result<UpdateSubnetOutcome> {
+    use crate::dhcp::lease_manager_queries;
+    use std::net::IpAddr;
+
+    let Some(current) = lease_manager_queries::fetch_subnet(db, subnet_id).await? else {
+        return Ok(UpdateSubnetOutcome::NotFound);
+    };
+
+    let start_ip = req.start_ip.unwrap_or(current.start_ip);
+    let end_ip = req.end_ip.unwrap_or(current.end_ip);
+    let gateway = req.gateway.unwrap_or(current.gateway);
+
+    if let Err(e) = crate::config::check_subnet_range(&current.network, start_ip, end_ip, gateway) {
+        return Ok(UpdateSubnetOutcome::InvalidRange(e.to_string()));
+    }
+    if let Some(ranges) = &req.excluded_ranges {
+        if let Err(e) = crate::config::check_excluded_ranges(&current.network, ranges) {
+            return Ok(UpdateSubnetOutcome::InvalidRange(e.to_string()));
+        }
+    }
+    if let Some(options) = &req.dhcp_options {
+        if let Err(e) = crate::config::check_dhcp_options(options) {
+            return Ok(UpdateSubnetOutcome::InvalidRange(e.to_string()));
+        }
+    }
+
+    let mut builder = sqlx::QueryBuilder::<sqlx::Postgres>::new("UPDATE dhcp_subnets SET ");
+    let mut fields = builder.separated(", ");
+
+    if let Some(name) = &req.name {
+        fields.push("name = ").push_bind_unseparated(name.clone());
+    }
+    if let Some(ip) = req.start_ip {
+        fields.push("start_ip = ").push_bind_unseparated(IpAddr::V4(ip));
+    }
+    if let Some(ip) = req.end_ip {
+        fields.push("end_ip = ").push_bind_unseparated(IpAddr::V4(ip));
+    }
+    if let Some(ip) = req.gateway {
+        fields.push("gateway = ").push_bind_unseparated(IpAddr::V4(ip));
+    }
+    if let Some(dns_servers) = &req.dns_servers {
+        fields.push("dns_servers = ").push_bind_unseparated(serde_json::to_value(dns_servers)?);
+    }
+    if let Some(domain_name) = &req.domain_name {
+        fields.push("domain_name = ").push_bind_unseparated(domain_name.clone());
+    }
+    if let Some(lease_duration) = req.lease_duration {
+        fields.push("lease_duration = ").push_bind_unseparated(lease_duration);
+    }
+    if let Some(enabled) = req.enabled {
+        fields.push("enabled = ").push_bind_unseparated(enabled);
+    }
+    if let Some(maintenance) = req.maintenance {
+        fields.push("maintenance = ").push_bind_unseparated(maintenance);
+    }
+    if let Some(circuit_id) = &req.relay_circuit_id {
+        fields.push("relay_circuit_id = ").push_bind_unseparated(circuit_id.clone());
+    }
+    if let Some(ip) = req.server_ip {
+        fields.push("server_ip = ").push_bind_unseparated(IpAddr::V4(ip));
+    }
+    if let Some(ip) = req.boot_server {
+        fields.push("boot_server = ").push_bind_unseparated(IpAddr::V4(ip));
+    }
+    if let Some(filename) = &req.boot_filename {
+        fields.push("boot_filename = ").push_bind_unseparated(filename.clone());
+    }
+    if let Some(rules) = &req.boot_rules {
+        fields.push("boot_rules = ").push_bind_unseparated(serde_json::to_value(rules)?);
+    }
+    if let Some(ranges) = &req.excluded_ranges {
+        fields.push("excluded_ranges = ").push_bind_unseparated(serde_json::to_value(ranges)?);
+    }
+    if let Some(options) = &req.dhcp_options {
+        fields.push("dhcp_options = ").push_bind_unseparated(serde_json::to_value(options)?);
+    }
+    if let Some(rapid_commit) = req.dhcpv6_rapid_commit {
+        fields.push("dhcpv6_rapid_commit = ").push_bind_unseparated(rapid_commit);
+    }
+    fields.push("updated_at = NOW()");
+
+    builder.push(" WHERE id = ").push_bind(subnet_id);
+
+    let result = builder.build().execute(db).await?;
+    if result.rows_affected() == 0 {
+        // Deleted between the lookup and the update
+        return Ok(UpdateSubnetOutcome::NotFound);
+    }
+
+    match lease_manager_queries::fetch_subnet(db, subnet_id).await? {
+        Some(subnet) => Ok(UpdateSubnetOutcome::Updated(Box::new(subnet))),
+        None => Ok(UpdateSubnetOutcome::NotFound),
+    }
+}
+
+pub async fn list_zones(db: &PgPool) -> Result<Vec<crate::database::models::DnsZone>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, name, zone_type, primary_ns, admin_email, serial_number,
+               refresh_interval, retry_interval, expire_interval, minimum_ttl,
+               dnssec_enabled, created_at, updated_at
+        FROM dns_zones
+        ORDER BY name
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.iter().map(crate::dns::zone_queries::zone_from_row).collect())
+}
+
+pub async fn create_zone(db: &PgPool, req: &crate::api::models::CreateZoneRequest) -> Result<Uuid> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dns_zones (name, zone_type, serial_number, primary_ns, admin_email)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#
+    )
+    .bind(&req.name)
+    .bind(&req.zone_type)
+    .bind(Utc::now().timestamp())
+    .bind(&req.primary_ns)
+    .bind(&req.admin_email)
+    .fetch_one(db)
+    .await?;
+
+    Ok(row.get("id"))
+}
+
+/// Deletes the zone together with its records; false if the zone doesn't exist
+pub async fn delete_zone(db: &PgPool, zone_id: Uuid) -> Result<bool> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query("DELETE FROM dns_records WHERE zone_id = $1")
+        .bind(zone_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let result = sqlx::query("DELETE FROM dns_zones WHERE id = $1")
+        .bind(zone_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// One page of the zone's records, plus the number of records in the zone
+pub async fn list_records(
+    db: &PgPool,
+    zone_id: Uuid,
+    page: Pagination,
+) -> Result<(Vec<crate::database::models::DnsRecord>, i64)> {
+    let total: i64 = sqlx::query("SELECT COUNT(*) as total FROM dns_records WHERE zone_id = $1")
+        .bind(zone_id)
+        .fetch_one(db)
+        .await?
+        .get("total");
+
+    let rows = sqlx::query(
+        r#"
+        SELECT id, zone_id, name, record_type, value, ttl, priority, weight, port,
+               is_dynamic, view, created_at, updated_at
+        FROM dns_records
+        WHERE zone_id = $1
+        ORDER BY name, record_type, id
+        LIMIT $2 OFFSET $3
+        "#
+    )
+    .bind(zone_id)
+    .bind(page.limit)
+    .bind(page.offset)
+    .fetch_all(db)
+    .await?;
+
+    Ok((rows.iter().map(crate::dns::zone_queries::record_from_row).collect(), total))
+}
+
+pub enum CreateRecordOutcome {
+    Created(Uuid),
+    ZoneNotFound,
+    Conflict(String),
+}
+
+/// Inserts a static record and bumps the zone serial, unless it conflicts with the zone's records.
+/// The zone row stays locked until commit so concurrent creates can't both pass the check.
+pub async fn create_record(
+    db: &PgPool,
+    zone_id: Uuid,
+    req: &crate::api::models::CreateRecordRequest,
+) -> Result<CreateRecordOutcome> {
+    use crate::dns::{record_conflicts, zone_queries};
+
+    let mut tx = db.begin().await?;
+
+    let zone = sqlx::query("SELECT name FROM dns_zones WHERE id = $1 FOR UPDATE")
+        .bind(zone_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(zone) = zone else {
+        return Ok(CreateRecordOutcome::ZoneNotFound);
+    };
+    let zone_name: String = zone.get("name");
+
+    let view = req.view.as_deref().unwrap_or(DEFAULT_VIEW);
+
+    // Views replace whole names, so only records of the same view can conflict
+    let existing: Vec<_> = sqlx::query(
+        r#"
+        SELECT id, zone_id, name, record_type, value, ttl, priority, weight, port,
+               is_dynamic, view, created_at, updated_at
+        FROM dns_records
+        WHERE zone_id = $1 AND view = $2
+        "#
+    )
+    .bind(zone_id)
+    .bind(view)
+    .fetch_all(&mut *tx)
+    .await?
+    .iter()
+    .map(zone_queries::record_from_row)
+    .collect();
+
+    if let Some(message) = record_conflicts::find_conflict(&zone_name, &existing, &req.name, &req.record_type, &req.value) {
+        return Ok(CreateRecordOutcome::Conflict(message));
+    }
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dns_records (zone_id, name, record_type, value, ttl, priority, weight, port, is_dynamic, view)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, false, $9)
+        RETURNING id
+        "#
+    )
+    .bind(zone_id)
+    .bind(&req.name)
+    .bind(&req.record_type)
+    .bind(&req.value)
+    .bind(req.ttl.unwrap_or(3600))
+    .bind(req.priority)
+    .bind(req.weight)
+    .bind(req.port)
+    .bind(view)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    zone_queries::bump_zone_serial(&mut *tx, zone_id).await?;
+
+    tx.commit().await?;
+
+    Ok(CreateRecordOutcome::Created(row.get("id")))
+}
+
+pub enum ImportRecordsOutcome {
+    Imported(usize),
+    ZoneNotFound,
+    Conflict(String),
+}
+
+/// Inserts a parsed zone file in one transaction and bumps the serial once. Each record is checked
+/// against the zone and the records imported before it, and any conflict rolls back the batch.
+pub async fn import_records(
+    db: &PgPool,
+    zone_id: Uuid,
+    records: &[crate::dns::record_types::DnsRecord],
+) -> Result<ImportRecordsOutcome> {
+    use crate::dns::{record_conflicts, zone_queries};
+
+    let mut tx = db.begin().await?;
+
+    let zone = sqlx::query("SELECT name FROM dns_zones WHERE id = $1 FOR UPDATE")
+        .bind(zone_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(zone) = zone else {
+        return Ok(ImportRecordsOutcome::ZoneNotFound);
+    };
+    let zone_name: String = zone.get("name");
+
+    // Zone files carry no view, so imported records join the default one
+    let mut existing: Vec<_> = sqlx::query(
+        r#"
+        SELECT id, zone_id, name, record_type, value, ttl, priority, weight, port,
+               is_dynamic, view, created_at, updated_at
+        FROM dns_records
+        WHERE zone_id = $1 AND view = $2
+        "#
+    )
+    .bind(zone_id)
+    .bind(DEFAULT_VIEW)
+    .fetch_all(&mut *tx)
+    .await?
+    .iter()
+    .map(zone_queries::record_from_row)
+    .collect();
+
+    for record in records {
+        let record_type = record.record_type.to_string();
+        if let Some(message) = record_conflicts::find_conflict(&zone_name, &existing, &record.name, &record_type, &record.value) {
+            return Ok(ImportRecordsOutcome::Conflict(message));
+        }
+
+        let row = sqlx::query(
+            r#"
+            INSERT INTO dns_records (zone_id, name, record_type, value, ttl, priority, weight, port, is_dynamic)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, false)
+            RETURNING *
+            "#
+        )
+        .bind(zone_id)
+        .bind(&record.name)
+        .bind(&record_type)
+        .bind(&record.value)
+        .bind(record.ttl.map(|ttl| ttl as i32).unwrap_or(3600))
+        .bind(record.priority.map(i32::from))
+        .bind(record.weight.map(i32::from))
+        .bind(record.port.map(i32::from))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        existing.push(zone_queries::record_from_row(&row));
+    }
+
+    if !records.is_empty() {
+        zone_queries::bump_zone_serial(&mut *tx, zone_id).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(ImportRecordsOutcome::Imported(records.len()))
+}
+
+/// Deletes the record and bumps the serial of its zone. Returns the zone id, or `None` if the
+/// record doesn't exist.
+pub async fn delete_record(db: &PgPool, record_id: Uuid) -> Result<Option<Uuid>> {
+    use crate::dns::zone_queries;
+
+    let Some(record) = zone_queries::fetch_record(db, record_id).await? else {
+        return Ok(None);
+    };
+
+    if !zone_queries::delete_dns_record(db, record_id).await? {
+        return Ok(None);
+    }
+
+    zone_queries::bump_zone_serial(db, record.zone_id).await?;
+
+    Ok(Some(record.zone_id))
+}
+
+/// Applies the SOA fields of `req` that are set and bumps the zone serial; `None` if the zone doesn't exist
+pub async fn update_zone(
+    db: &PgPool,
+    zone_id: Uuid,
+    req: &crate::api::models::UpdateZoneRequest,
+) -> Result<Option<crate::database::models::DnsZone>> {
+    use crate::dns::zone_queries;
+
+    let mut builder = sqlx::QueryBuilder::<sqlx::Postgres>::new("UPDATE dns_zones SET ");
+    let mut fields = builder.separated(", ");
+
+    if let Some(primary_ns) = &req.primary_ns {
+        fields.push("primary_ns = ").push_bind_unseparated(primary_ns.clone());
+    }
+    if let Some(admin_email) = &req.admin_email {
+        fields.push("admin_email = ").push_bind_unseparated(admin_email.clone());
+    }
+    if let Some(refresh_interval) = req.refresh_interval {
+        fields.push("refresh_interval = ").push_bind_unseparated(refresh_interval);
+    }
+    if let Some(retry_interval) = req.retry_interval {
+        fields.push("retry_interval = ").push_bind_unseparated(retry_interval);
+    }
+    if let Some(expire_interval) = req.expire_interval {
+        fields.push("expire_interval = ").push_bind_unseparated(expire_interval);
+    }
+    if let Some(minimum_ttl) = req.minimum_ttl {
+        fields.push("minimum_ttl = ").push_bind_unseparated(minimum_ttl);
+    }
+    fields.push("updated_at = NOW()");
+
+    builder.push(" WHERE id = ").push_bind(zone_id);
+
+    let result = builder.build().execute(db).await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    // Secondaries only pick up SOA changes when the serial moves forward
+    zone_queries::bump_zone_serial(db, zone_id).await?;
+
+    zone_queries::fetch_zone(db, zone_id).await
+}
+
+/// Applies the fields of `req` that are set and bumps the serial of the record's zone;
+/// `None` if the record doesn't exist
+pub async fn update_record(
+    db: &PgPool,
+    record_id: Uuid,
+    req: &crate::api::models::UpdateRecordRequest,
+) -> Result<Option<crate::database::models::DnsRecord>> {
+    use crate::dns::zone_queries;
+
+    let Some(record) = zone_queries::fetch_record(db, record_id).await? else {
+        return Ok(None);
+    };
+
+    let mut builder = sqlx::QueryBuilder::<sqlx::Postgres>::new("UPDATE dns_records SET ");
+    let mut fields = builder.separated(", ");
+
+    if let Some(value) = &req.value {
+        fields.push("value = ").push_bind_unseparated(value.clone());
+    }
+    if let Some(ttl) = req.ttl {
+        fields.push("ttl = ").push_bind_unseparated(ttl);
+    }
+    if let Some(priority) = req.priority {
+        fields.push("priority = ").push_bind_unseparated(priority);
+    }
+    if let Some(weight) = req.weight {
+        fields.push("weight = ").push_bind_unseparated(weight);
+    }
+    if let Some(port) = req.port {
+        fields.push("port = ").push_bind_unseparated(port);
+    }
+    fields.push("updated_at = NOW()");
+
+    builder.push(" WHERE id = ").push_bind(record_id);
+
+    let result = builder.build().execute(db).await?;
+    if result.rows_affected() == 0 {
+        // Deleted between the lookup and the update
+        return Ok(None);
+    }
+
+    zone_queries::bump_zone_serial(db, record.zone_id).await?;
+
+    zone_queries::fetch_record(db, record_id).await
+}
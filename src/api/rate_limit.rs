@@ -0,0 +1,113 @@
+// In-memory brute-force protection for /auth/login
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Failed logins in a sliding window, counted separately per key (client IP, username).
+/// Once any key reaches `max_failures` within `window`, further attempts are refused until
+/// enough failures age out.
+pub struct LoginThrottle {
+    max_failures: usize,
+    window: Duration,
+    failures: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl LoginThrottle {
+    pub fn new(max_failures: u32, window: Duration) -> Self {
+        Self {
+            max_failures: max_failures.max(1) as usize,
+            window,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How long to wait before the next attempt, if any of `keys` is over the limit
+    pub fn retry_after(&self, keys: &[String], now: Instant) -> Option<Duration> {
+        let mut failures = self.failures.lock().unwrap();
+
+        keys.iter()
+            .filter_map(|key| {
+                let times = failures.get_mut(key)?;
+                self.prune(times, now);
+                if times.len() < self.max_failures {
+                    return None;
+                }
+                // Below the limit again once this failure leaves the window
+                let freed_at = times[times.len() - self.max_failures] + self.window;
+                Some(freed_at.saturating_duration_since(now))
+            })
+            .max()
+    }
+
+    pub fn record_failure(&self, keys: &[String], now: Instant) {
+        let mut failures = self.failures.lock().unwrap();
+
+        // Forget keys whose failures have all aged out so the map doesn't grow without bound
+        failures.retain(|_, times| {
+            self.prune(times, now);
+            !times.is_empty()
+        });
+
+        for key in keys {
+            failures.entry(key.clone()).or_default().push_back(now);
+        }
+    }
+
+    pub fn reset(&self, keys: &[String]) {
+        let mut failures = self.failures.lock().unwrap();
+        for key in keys {
+            failures.remove(key);
+        }
+    }
+
+    fn prune(&self, times: &mut VecDeque<Instant>, now: Instant) {
+        while times.front().is_some_and(|&at| now.duration_since(at) >= self.window) {
+            times.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(ip: &str, username: &str) -> Vec<String> {
+        vec![format!("ip:{}", ip), format!("user:{}", username)]
+    }
+
+    #[test]
+    fn test_blocks_after_max_failures_until_window_passes() {
+        let throttle = LoginThrottle::new(3, Duration::from_secs(60));
+        let start = Instant::now();
+        let attacker = keys("192.0.2.1", "admin");
+
+        for i in 0..3 {
+            assert_eq!(throttle.retry_after(&attacker, start), None);
+            throttle.record_failure(&attacker, start + Duration::from_secs(i * 10));
+        }
+
+        let now = start + Duration::from_secs(25);
+        assert_eq!(throttle.retry_after(&attacker, now), Some(Duration::from_secs(35)));
+
+        // The same username from another address is also blocked, an unrelated login isn't
+        assert!(throttle.retry_after(&keys("198.51.100.7", "admin"), now).is_some());
+        assert_eq!(throttle.retry_after(&keys("198.51.100.7", "operator"), now), None);
+
+        // The oldest failure ages out and one more attempt is allowed
+        assert_eq!(throttle.retry_after(&attacker, start + Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn test_reset_clears_failures() {
+        let throttle = LoginThrottle::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        let client = keys("192.0.2.1", "alice");
+
+        throttle.record_failure(&client, now);
+        throttle.record_failure(&client, now);
+        assert!(throttle.retry_after(&client, now).is_some());
+
+        throttle.reset(&client);
+        assert_eq!(throttle.retry_after(&client, now), None);
+    }
+}
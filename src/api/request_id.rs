@@ -0,0 +1,116 @@
+// Per-request correlation ids for the API
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{Error, HttpMessage};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest client-supplied id that is passed through rather than replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// The id of the request being handled, kept in the request extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// The caller's `X-Request-Id` when it is safe to log and echo back, otherwise a new UUID
+    pub fn from_header(value: Option<&HeaderValue>) -> Self {
+        let supplied = value
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+            .filter(|id| id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c)));
+
+        match supplied {
+            Some(id) => Self(id.to_string()),
+            None => Self(Uuid::new_v4().to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Middleware that tags every request with a `RequestId`, runs the rest of the chain inside a
+/// `request` span carrying it, and returns it in the `X-Request-Id` response header
+pub struct RequestIdMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdService { service }))
+    }
+}
+
+pub struct RequestIdService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let id = RequestId::from_header(req.headers().get(REQUEST_ID_HEADER));
+        let span = tracing::info_span!(
+            "request",
+            request_id = %id.as_str(),
+            method = %req.method(),
+            path = %req.path(),
+        );
+        req.extensions_mut().insert(id.clone());
+
+        let fut = span.in_scope(|| self.service.call(req));
+        Box::pin(
+            async move {
+                let mut response = fut.await?;
+                // Validated above, so it is always a legal header value
+                if let Ok(value) = HeaderValue::from_str(id.as_str()) {
+                    response.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+                }
+                Ok(response)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supplied_ids_are_kept_only_when_safe() {
+        let id = RequestId::from_header(Some(&HeaderValue::from_static("abc-123_x.y")));
+        assert_eq!(id.as_str(), "abc-123_x.y");
+
+        // Missing, empty, oversized or odd ids get a fresh UUID
+        for value in [None, Some(HeaderValue::from_static("")), Some(HeaderValue::from_static("a b\"c"))] {
+            let id = RequestId::from_header(value.as_ref());
+            assert!(Uuid::parse_str(id.as_str()).is_ok());
+        }
+        let long = HeaderValue::from_str(&"a".repeat(MAX_REQUEST_ID_LEN + 1)).unwrap();
+        assert!(Uuid::parse_str(RequestId::from_header(Some(&long)).as_str()).is_ok());
+    }
+}
@@ -1,106 +1,111 @@
-use crate::config::Settings;
+use crate::config::{ApiConfig, Settings};
+use crate::dhcp::lease_manager::LeaseManager;
+use crate::ipv6::prefix_delegation::PrefixDelegationManager;
 use sqlx::PgPool;
-use actix_web::{web, App, HttpServer, middleware};
+use actix_cors::Cors;
+use actix_web::{http::header, web, App, HttpServer, middleware};
 use actix_web_httpauth::middleware::HttpAuthentication;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use anyhow::Result;
+use tokio_util::sync::CancellationToken;
 use tracing::{info, error};
 
 use crate::api::{auth, handlers, models, validators};
+use crate::api::rate_limit::LoginThrottle;
+use crate::api::request_id::{self, RequestIdMiddleware};
 
 pub struct ApiState {
     pub db: PgPool,
     pub settings: Arc<Settings>,
+    /// Present when the DHCP server is running in this process
+    pub lease_manager: Option<Arc<LeaseManager>>,
+    /// Present when the DHCPv6 server is running in this process
+    pub prefix_delegation: Option<Arc<PrefixDelegationManager>>,
+    pub started_at: Instant,
+    /// Kept between requests so CPU usage is measured since the previous sample
+    pub system: Mutex<sysinfo::System>,
+    pub login_throttle: LoginThrottle,
 }
 
-pub async fn start(settings: Arc<Settings>, db: PgPool) -> Result<()> {
+pub async fn start(
+    settings: Arc<Settings>,
+    db: PgPool,
+    lease_manager: Option<Arc<LeaseManager>>,
+    prefix_delegation: Option<Arc<PrefixDelegationManager>>,
+    shutdown: CancellationToken,
+) -> Result<()> {
     let api_addr = SocketAddr::new(
         IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
         settings.api.port,
     );
 
+    // Refuse to start rather than sign tokens with a weak key
+    if settings.api.jwt_secret.len() < auth::MIN_JWT_SECRET_LEN {
+        anyhow::bail!(
+            "api.jwt_secret must be at least {} characters",
+            auth::MIN_JWT_SECRET_LEN
+        );
+    }
+
     info!("Starting API server on {}", api_addr);
 
+    // Revoked tokens only need to be remembered until they expire
+    let prune_db = db.clone();
+    let prune_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        let mut prune_interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            tokio::select! {
+                _ = prune_shutdown.cancelled() => break,
+                _ = prune_interval.tick() => {}
+            }
+            match crate::api::users::prune_revoked_tokens(&prune_db).await {
+                Ok(0) => {}
+                Ok(pruned) => info!("Pruned {} expired revoked tokens", pruned),
+                Err(e) => error!("Failed to prune revoked tokens: {}", e),
+            }
+        }
+    });
+
     let state = web::Data::new(ApiState {
         db: db.clone(),
         settings: settings.clone(),
+        lease_manager,
+        prefix_delegation,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(
+            settings.api.login_max_failures,
+            std::time::Duration::from_secs(settings.api.login_window_secs),
+        ),
     });
 
     let server = HttpServer::new(move || {
-        let auth_middleware = HttpAuthentication::bearer(auth::validator);
-
         App::new()
             .app_data(state.clone())
-            .wrap(middleware::Logger::default())
+            .wrap(cors(&settings.api))
+            .wrap(RequestIdMiddleware)
+            // Wrapped after (so around) the request id middleware to see its response header
+            .wrap(middleware::Logger::new(r#"%a "%r" %s %b %T request_id=%{X-Request-Id}o"#))
             .wrap(middleware::NormalizePath::trim())
-            .service(
-                web::scope("/api/v1")
-                    .service(
-                        // Public endpoints (no auth required)
-                        web::scope("/auth")
-                            .route("/login", web::post().to(handlers::auth::login))
-                            .route("/refresh", web::post().to(handlers::auth::refresh))
-                    )
-                    .service(
-                        // API Documentation endpoints (no auth required)
-                        web::scope("/docs")
-                            .route("/openapi.json", web::get().to(handlers::docs::openapi_spec))
-                            .route("", web::get().to(handlers::docs::swagger_ui))
-                    )
-                    .service(
-                        // System health and metrics endpoints (no auth required for monitoring)
-                        web::scope("/system")
-                            .route("/health", web::get().to(handlers::system::health))
-                            .route("/metrics", web::get().to(handlers::system::metrics))
-                    )
-                    .service(
-                        // Protected endpoints (auth required)
-                        web::scope("")
-                            .wrap(auth_middleware)
-                            // DHCP endpoints
-                            .service(
-                                web::scope("/dhcp")
-                                    .route("/leases", web::get().to(handlers::dhcp::list_leases))
-                                    .route("/leases", web::post().to(handlers::dhcp::create_lease))
-                                    .route("/leases/{id}", web::get().to(handlers::dhcp::get_lease))
-                                    .route("/leases/{id}", web::delete().to(handlers::dhcp::release_lease))
-                                    .route("/subnets", web::get().to(handlers::dhcp::list_subnets))
-                                    .route("/subnets", web::post().to(handlers::dhcp::create_subnet))
-                                    .route("/subnets/{id}", web::get().to(handlers::dhcp::get_subnet))
-                                    .route("/subnets/{id}", web::put().to(handlers::dhcp::update_subnet))
-                                    .route("/subnets/{id}", web::delete().to(handlers::dhcp::delete_subnet))
-                                    .route("/reservations", web::get().to(handlers::dhcp::list_reservations))
-                                    .route("/reservations", web::post().to(handlers::dhcp::create_reservation))
-                                    .route("/reservations/{id}", web::delete().to(handlers::dhcp::delete_reservation))
-                                    .route("/stats", web::get().to(handlers::dhcp::get_stats))
-                            )
-                            // DNS endpoints
-                            .service(
-                                web::scope("/dns")
-                                    .route("/zones", web::get().to(handlers::dns::list_zones))
-                                    .route("/zones", web::post().to(handlers::dns::create_zone))
-                                    .route("/zones/{id}", web::get().to(handlers::dns::get_zone))
-                                    .route("/zones/{id}", web::put().to(handlers::dns::update_zone))
-                                    .route("/zones/{id}", web::delete().to(handlers::dns::delete_zone))
-                                    .route("/zones/{zone_id}/records", web::get().to(handlers::dns::list_records))
-                                    .route("/zones/{zone_id}/records", web::post().to(handlers::dns::create_record))
-                                    .route("/records/{id}", web::put().to(handlers::dns::update_record))
-                                    .route("/records/{id}", web::delete().to(handlers::dns::delete_record))
-                            )
-                            // Protected system endpoints
-                            .service(
-                                web::scope("/system")
-                                    .route("/config", web::get().to(handlers::system::get_config))
-                            )
-                    )
-            )
+            .configure(routes)
     })
     .bind(&api_addr)?
+    // Shutdown is driven by `shutdown` so every service stops together
+    .disable_signals()
     .run();
 
     info!("API server listening on {}", api_addr);
 
+    // Stops accepting connections and lets in-flight requests finish
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown.cancelled().await;
+        handle.stop(true).await;
+    });
+
     match server.await {
         Ok(_) => {
             info!("API server shutdown gracefully");
@@ -111,4 +116,145 @@ pub async fn start(settings: Arc<Settings>, db: PgPool) -> Result<()> {
             Err(anyhow::anyhow!("API server failed: {}", e))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Cross-origin access for browser-based admin UIs. Origins come from `api.cors_origins`, where
+/// `*` allows any origin; with `api.cors_enabled` off no CORS headers are sent at all.
+pub fn cors(config: &ApiConfig) -> middleware::Condition<Cors> {
+    let mut cors = Cors::default()
+        .allowed_methods(["GET", "POST", "PUT", "DELETE"])
+        .allowed_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::ACCEPT,
+            header::HeaderName::from_static("x-request-id"),
+        ])
+        .expose_headers([models::TOTAL_COUNT_HEADER, request_id::REQUEST_ID_HEADER])
+        .max_age(3600);
+
+    // Adding a specific origin after allow_any_origin would narrow the set again
+    if config.cors_origins.iter().any(|origin| origin == "*") {
+        cors = cors.allow_any_origin();
+    } else {
+        for origin in &config.cors_origins {
+            cors = cors.allowed_origin(origin);
+        }
+    }
+
+    middleware::Condition::new(config.cors_enabled, cors)
+}
+
+/// The `/api/v1` route table plus the Prometheus scrape endpoint at `/metrics`;
+/// the app must carry `web::Data<ApiState>`
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    // Unauthenticated like /system/health so scrapers don't need a token
+    cfg.route("/metrics", web::get().to(handlers::system::prometheus_metrics));
+
+    cfg.service(
+        web::scope("/api/v1")
+            .service(
+                // Public endpoints (no auth required)
+                web::scope("/auth")
+                    .route("/login", web::post().to(handlers::auth::login))
+                    .route("/refresh", web::post().to(handlers::auth::refresh))
+                    .service(
+                        web::resource("/logout")
+                            .wrap(HttpAuthentication::bearer(auth::validator))
+                            .route(web::post().to(handlers::auth::logout))
+                    )
+            )
+            .service(
+                // API Documentation endpoints (no auth required)
+                web::scope("/docs")
+                    .route("/openapi.json", web::get().to(handlers::docs::openapi_spec))
+                    .route("", web::get().to(handlers::docs::swagger_ui))
+            )
+            .service(
+                // System health and metrics endpoints (no auth required for monitoring).
+                // Other /system resources carry their own auth; a second "/system" scope would be shadowed by this one.
+                web::scope("/system")
+                    .route("/health", web::get().to(handlers::system::health))
+                    .route("/metrics", web::get().to(handlers::system::metrics))
+                    .service(
+                        web::resource("/config")
+                            .wrap(HttpAuthentication::bearer(auth::validator))
+                            .route(web::get().to(handlers::system::get_config))
+                    )
+                    .service(
+                        web::resource("/backup")
+                            .wrap(HttpAuthentication::bearer(auth::validator))
+                            .route(web::get().to(handlers::system::backup))
+                    )
+                    .service(
+                        web::resource("/restore")
+                            .wrap(HttpAuthentication::bearer(auth::validator))
+                            // Bundles of large installations exceed the default 32 KiB JSON limit
+                            .app_data(web::JsonConfig::default().limit(64 * 1024 * 1024))
+                            .route(web::post().to(handlers::system::restore))
+                    )
+            )
+            .service(
+                // Protected endpoints (auth required)
+                web::scope("")
+                    .wrap(HttpAuthentication::bearer(auth::validator))
+                    // DHCP endpoints
+                    .service(
+                        web::scope("/dhcp")
+                            .route("/leases", web::get().to(handlers::dhcp::list_leases))
+                            .route("/leases", web::post().to(handlers::dhcp::create_lease))
+                            .route("/leases/{id}", web::get().to(handlers::dhcp::get_lease))
+                            .route("/leases/{id}", web::delete().to(handlers::dhcp::release_lease))
+                            .route("/leases/{id}/reserve", web::post().to(handlers::dhcp::reserve_lease))
+                            .route("/leases/{id}/expire", web::post().to(handlers::dhcp::expire_lease))
+                            .route("/leases/{id}/extend", web::post().to(handlers::dhcp::extend_lease))
+                            .route("/leases/{id}/history", web::get().to(handlers::dhcp::lease_history))
+                            .route("/events", web::get().to(handlers::dhcp::lease_event_stream))
+                            .route("/subnets", web::get().to(handlers::dhcp::list_subnets))
+                            .route("/subnets", web::post().to(handlers::dhcp::create_subnet))
+                            .route("/subnets/{id}", web::get().to(handlers::dhcp::get_subnet))
+                            .route("/subnets/{id}", web::put().to(handlers::dhcp::update_subnet))
+                            .route("/subnets/{id}", web::delete().to(handlers::dhcp::delete_subnet))
+                            .route("/subnets/{id}/stats", web::get().to(handlers::dhcp::subnet_stats))
+                            .route("/subnets/{id}/leases/export", web::get().to(handlers::dhcp::export_subnet_leases))
+                            .service(
+                                web::resource("/subnets/{id}/leases/import")
+                                    .app_data(web::PayloadConfig::new(16 * 1024 * 1024))
+                                    .route(web::post().to(handlers::dhcp::import_subnet_leases))
+                            )
+                            .route("/reservations", web::get().to(handlers::dhcp::list_reservations))
+                            .route("/reservations", web::post().to(handlers::dhcp::create_reservation))
+                            .route("/reservations/{id}", web::delete().to(handlers::dhcp::delete_reservation))
+                            .route("/stats", web::get().to(handlers::dhcp::get_stats))
+                    )
+                    // DNS endpoints
+                    .service(
+                        web::scope("/dns")
+                            .route("/zones", web::get().to(handlers::dns::list_zones))
+                            .route("/zones", web::post().to(handlers::dns::create_zone))
+                            .route("/zones/{id}", web::get().to(handlers::dns::get_zone))
+                            .route("/zones/{id}", web::put().to(handlers::dns::update_zone))
+                            .route("/zones/{id}", web::delete().to(handlers::dns::delete_zone))
+                            .route("/zones/{id}/export", web::get().to(handlers::dns::export_zone))
+                            .service(
+                                web::resource("/zones/{id}/import")
+                                    // Zone files of large zones exceed the default 256 KiB body limit
+                                    .app_data(web::PayloadConfig::new(16 * 1024 * 1024))
+                                    .route(web::post().to(handlers::dns::import_zone))
+                            )
+                            .route("/zones/{zone_id}/records", web::get().to(handlers::dns::list_records))
+                            .route("/zones/{zone_id}/records", web::post().to(handlers::dns::create_record))
+                            .route("/records/{id}", web::put().to(handlers::dns::update_record))
+                            .route("/records/{id}", web::delete().to(handlers::dns::delete_record))
+                            .route("/queries/recent", web::get().to(handlers::dns::recent_queries))
+                    )
+                    // IPv6 endpoints
+                    .service(
+                        web::scope("/ipv6")
+                            .route("/leases", web::get().to(handlers::ipv6::list_leases))
+                            .route("/prefixes", web::get().to(handlers::ipv6::list_prefixes))
+                            .route("/prefixes/stats", web::get().to(handlers::ipv6::prefix_stats))
+                            .route("/slaac", web::get().to(handlers::ipv6::list_slaac_addresses))
+                    )
+            )
+    );
+}
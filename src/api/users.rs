@@ -0,0 +1,147 @@
+// Runtime SQL queries for API users and revoked tokens
+use sqlx::{postgres::PgRow, PgPool, Row};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use anyhow::Result;
+
+use crate::api::auth::hash_password;
+
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+    pub password_hash: String,
+    pub role: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+fn user_from_row(row: &PgRow) -> User {
+    User {
+        id: row.get("id"),
+        username: row.get("username"),
+        password_hash: row.get("password_hash"),
+        role: row.get("role"),
+        created_at: row.get("created_at"),
+    }
+}
+
+pub async fn fetch_user_by_username(db: &PgPool, username: &str) -> Result<Option<User>> {
+    let row = sqlx::query(
+        "SELECT id, username, password_hash, role, created_at FROM users WHERE username = $1"
+    )
+    .bind(username)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.as_ref().map(user_from_row))
+}
+
+pub async fn fetch_user_by_id(db: &PgPool, id: Uuid) -> Result<Option<User>> {
+    let row = sqlx::query(
+        "SELECT id, username, password_hash, role, created_at FROM users WHERE id = $1"
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.as_ref().map(user_from_row))
+}
+
+/// Creates a user, or resets the password and role if the username already exists
+pub async fn upsert_user(db: &PgPool, username: &str, password: &str, role: &str) -> Result<User> {
+    let password_hash = hash_password(password)?;
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO users (username, password_hash, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (username) DO UPDATE
+        SET password_hash = EXCLUDED.password_hash,
+            role = EXCLUDED.role,
+            updated_at = NOW()
+        RETURNING id, username, password_hash, role, created_at
+        "#
+    )
+    .bind(username)
+    .bind(&password_hash)
+    .bind(role)
+    .fetch_one(db)
+    .await?;
+
+    Ok(user_from_row(&row))
+}
+
+/// Creates a user; `None` if the username is already taken
+pub async fn create_user(db: &PgPool, username: &str, password: &str, role: &str) -> Result<Option<User>> {
+    let password_hash = hash_password(password)?;
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO users (username, password_hash, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (username) DO NOTHING
+        RETURNING id, username, password_hash, role, created_at
+        "#
+    )
+    .bind(username)
+    .bind(&password_hash)
+    .bind(role)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.as_ref().map(user_from_row))
+}
+
+/// Replaces a user's password; false if there is no such user
+pub async fn set_password(db: &PgPool, username: &str, password: &str) -> Result<bool> {
+    let password_hash = hash_password(password)?;
+
+    let result = sqlx::query(
+        "UPDATE users SET password_hash = $2, updated_at = NOW() WHERE username = $1"
+    )
+    .bind(username)
+    .bind(&password_hash)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_user(db: &PgPool, username: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM users WHERE username = $1")
+        .bind(username)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Adds a token to the denylist until it would have expired
+pub async fn revoke_token(db: &PgPool, jti: &str, expires_at: DateTime<Utc>) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2) ON CONFLICT (jti) DO NOTHING"
+    )
+    .bind(jti)
+    .bind(expires_at)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn is_token_revoked(db: &PgPool, jti: &str) -> Result<bool> {
+    let row = sqlx::query("SELECT 1 FROM revoked_tokens WHERE jti = $1")
+        .bind(jti)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(row.is_some())
+}
+
+/// Drops denylist entries for tokens that have expired on their own
+pub async fn prune_revoked_tokens(db: &PgPool) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM revoked_tokens WHERE expires_at < NOW()")
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
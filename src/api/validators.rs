@@ -1,4 +1,6 @@
+use crate::api::models::{LeaseFilter, Pagination, MAX_PAGE_LIMIT};
 use regex::Regex;
+use std::collections::HashMap;
 use std::net::Ipv4Addr;
 use std::str::FromStr;
 
@@ -41,6 +43,17 @@ pub fn validate_domain_name(domain: &str) -> bool {
     true
 }
 
+/// Owner name of a zone record: "@", a relative or absolute name, or a wildcard whose `*` is
+/// the whole leftmost label (RFC 4592), like "*" or "*.apps"
+pub fn validate_record_name(name: &str) -> bool {
+    if name == "@" || name == "*" {
+        return true;
+    }
+
+    let name = name.strip_prefix("*.").unwrap_or(name);
+    crate::dns::record_types::is_valid_domain_name(name)
+}
+
 pub fn validate_ipv4_network(network: &str) -> bool {
     if let Ok(net) = ipnet::Ipv4Net::from_str(network) {
         net.prefix_len() >= 8 && net.prefix_len() <= 30
@@ -56,7 +69,7 @@ pub fn validate_ip_in_range(ip: Ipv4Addr, start: Ipv4Addr, end: Ipv4Addr) -> boo
 pub fn validate_dns_record_type(record_type: &str) -> bool {
     matches!(
         record_type.to_uppercase().as_str(),
-        "A" | "AAAA" | "CNAME" | "MX" | "TXT" | "PTR" | "NS" | "SOA" | "SRV"
+        "A" | "AAAA" | "CNAME" | "MX" | "TXT" | "PTR" | "NS" | "SOA" | "SRV" | "CAA" | "ALIAS"
     )
 }
 
@@ -90,10 +103,88 @@ pub fn bytes_to_mac_string(bytes: &[u8]) -> String {
         .join(":")
 }
 
+/// Reads `limit` and `offset` from the query string; limits above MAX_PAGE_LIMIT are capped
+pub fn parse_pagination(query: &HashMap<String, String>) -> Result<Pagination, String> {
+    let mut page = Pagination::default();
+
+    if let Some(limit) = query.get("limit") {
+        let limit: u64 = limit.parse()
+            .map_err(|_| "limit must be a non-negative integer".to_string())?;
+        page.limit = limit.min(MAX_PAGE_LIMIT as u64) as i64;
+    }
+
+    if let Some(offset) = query.get("offset") {
+        page.offset = offset.parse::<u64>()
+            .ok()
+            .and_then(|offset| i64::try_from(offset).ok())
+            .ok_or_else(|| "offset must be a non-negative integer".to_string())?;
+    }
+
+    Ok(page)
+}
+
+/// Reads the `state`, `mac`, `ip`, `hostname` and `subnet_id` lease filters from the query string
+pub fn parse_lease_filter(query: &HashMap<String, String>) -> Result<LeaseFilter, String> {
+    let mut filter = LeaseFilter::default();
+
+    if let Some(state) = query.get("state") {
+        filter.state = state.clone();
+    }
+
+    if let Some(mac) = query.get("mac") {
+        if !validate_mac_address(mac) {
+            return Err(format!("mac {} is not a MAC address", mac));
+        }
+        filter.mac_address = mac_string_to_bytes(mac);
+    }
+
+    if let Some(ip) = query.get("ip") {
+        filter.ip_address = Some(ip.parse().map_err(|_| format!("ip {} is not an IPv4 address", ip))?);
+    }
+
+    if let Some(hostname) = query.get("hostname").filter(|hostname| !hostname.is_empty()) {
+        filter.hostname = Some(hostname.clone());
+    }
+
+    if let Some(subnet_id) = query.get("subnet_id") {
+        filter.subnet_id = Some(subnet_id.parse().map_err(|_| format!("subnet_id {} is not a UUID", subnet_id))?);
+    }
+
+    Ok(filter)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_lease_filter() {
+        let query = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+
+        assert_eq!(parse_lease_filter(&query(&[])).unwrap(), LeaseFilter::default());
+
+        let filter = parse_lease_filter(&query(&[
+            ("state", "expired"),
+            ("mac", "00-11-22-AA-bb-cc"),
+            ("ip", "192.168.1.50"),
+            ("hostname", "printer"),
+            ("subnet_id", "6f1c2a4e-3b7d-4c1e-9a53-2f0d8e7b6c41"),
+        ])).unwrap();
+        assert_eq!(filter.state, "expired");
+        assert_eq!(filter.mac_address, Some(vec![0x00, 0x11, 0x22, 0xaa, 0xbb, 0xcc]));
+        assert_eq!(filter.ip_address, Some(Ipv4Addr::new(192, 168, 1, 50)));
+        assert_eq!(filter.hostname.as_deref(), Some("printer"));
+        assert!(filter.subnet_id.is_some());
+
+        assert!(parse_lease_filter(&query(&[("mac", "00:11:22")])).is_err());
+        assert!(parse_lease_filter(&query(&[("ip", "192.168.1.256")])).is_err());
+        assert!(parse_lease_filter(&query(&[("ip", "fd00::1")])).is_err());
+        assert!(parse_lease_filter(&query(&[("subnet_id", "office")])).is_err());
+        assert_eq!(parse_lease_filter(&query(&[("hostname", "")])).unwrap().hostname, None);
+    }
+
     #[test]
     fn test_validate_mac_address() {
         assert!(validate_mac_address("00:11:22:33:44:55"));
@@ -116,6 +207,23 @@ mod tests {
         assert!(!validate_hostname(""));
     }
 
+    #[test]
+    fn test_validate_record_name() {
+        assert!(validate_record_name("@"));
+        assert!(validate_record_name("www"));
+        assert!(validate_record_name("_sip._udp"));
+        assert!(validate_record_name("host.example.com."));
+        assert!(validate_record_name("*"));
+        assert!(validate_record_name("*.apps"));
+        assert!(validate_record_name("*.apps.example.com."));
+        assert!(!validate_record_name("apps.*"));
+        assert!(!validate_record_name("*.*.apps"));
+        assert!(!validate_record_name("web*.apps"));
+        assert!(!validate_record_name("*apps"));
+        assert!(!validate_record_name("*."));
+        assert!(!validate_record_name(""));
+    }
+
     #[test]
     fn test_validate_domain_name() {
         assert!(validate_domain_name("example.com"));
@@ -135,4 +243,22 @@ mod tests {
         assert!(!validate_ipv4_network("192.168.1.0/7"));
         assert!(!validate_ipv4_network("invalid"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_pagination() {
+        let query = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+
+        assert_eq!(parse_pagination(&query(&[])), Ok(Pagination::default()));
+        assert_eq!(
+            parse_pagination(&query(&[("limit", "25"), ("offset", "50")])),
+            Ok(Pagination { limit: 25, offset: 50 })
+        );
+        assert_eq!(parse_pagination(&query(&[("limit", "100000")])).unwrap().limit, MAX_PAGE_LIMIT);
+        assert!(parse_pagination(&query(&[("limit", "-1")])).is_err());
+        assert!(parse_pagination(&query(&[("limit", "ten")])).is_err());
+        assert!(parse_pagination(&query(&[("offset", "-5")])).is_err());
+        assert!(parse_pagination(&query(&[("offset", "1.5")])).is_err());
+    }
+}
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::net::Ipv4Addr;
-use std::collections::HashMap;
-use anyhow::Result;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::collections::{BTreeMap, HashMap};
+use anyhow::{Context, Result};
+use crate::database::models::{BootRule, DhcpOptionValue, ExcludedRange, DEFAULT_VIEW};
+use ipnetwork::IpNetwork;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
@@ -41,6 +43,165 @@ pub struct DnsConfig {
     pub hostname_template: String,
     pub ttl_default: u32,
     pub cache_size: usize,
+    #[serde(default)]
+    pub default_zones: Vec<DefaultZoneConfig>,
+    /// Peers allowed to AXFR any of our zones
+    #[serde(default)]
+    pub allow_transfer: Vec<IpAddr>,
+    #[serde(default)]
+    pub zone_transfers: Vec<ZoneTransferConfig>,
+    /// Primaries whose NOTIFY messages we accept
+    #[serde(default)]
+    pub allow_notify: Vec<IpAddr>,
+    /// Clients allowed to send RFC 2136 UPDATEs for any of our zones
+    #[serde(default)]
+    pub allow_update: Vec<IpAddr>,
+    #[serde(default)]
+    pub tsig_keys: Vec<TsigKeyConfig>,
+    /// Refuse unsigned transfers and updates even from allow-listed addresses
+    #[serde(default)]
+    pub require_tsig: bool,
+    #[serde(default)]
+    pub query_log: QueryLogConfig,
+    /// Split-horizon views, checked in order; clients matching none get the default view
+    #[serde(default)]
+    pub views: Vec<DnsViewConfig>,
+}
+
+impl DnsConfig {
+    /// Whether `peer` may transfer `zone`: globally, through the zone's own allow-list,
+    /// or as one of the zone's secondaries
+    pub fn transfer_allowed(&self, zone: &str, peer: IpAddr) -> bool {
+        let peer = peer.to_canonical();
+
+        self.allow_transfer.contains(&peer)
+            || self.zone_transfer(zone).is_some_and(|transfer| {
+                transfer.allow_transfer.contains(&peer)
+                    || transfer.secondaries.iter().any(|secondary| secondary.ip().to_canonical() == peer)
+            })
+    }
+
+    /// Whether `peer` may send dynamic UPDATEs for `zone`
+    pub fn update_allowed(&self, zone: &str, peer: IpAddr) -> bool {
+        let peer = peer.to_canonical();
+
+        self.allow_update.contains(&peer)
+            || self.zone_transfer(zone).is_some_and(|transfer| transfer.allow_update.contains(&peer))
+    }
+
+    /// Whether TSIG key `key_name` may sign transfers and updates of `zone`: any configured
+    /// key, unless the zone lists its own
+    pub fn tsig_key_allowed(&self, zone: &str, key_name: &str) -> bool {
+        let key_name = key_name.trim_end_matches('.');
+        match self.zone_transfer(zone) {
+            Some(transfer) if !transfer.tsig_keys.is_empty() => transfer
+                .tsig_keys
+                .iter()
+                .any(|allowed| allowed.trim_end_matches('.').eq_ignore_ascii_case(key_name)),
+            _ => true,
+        }
+    }
+
+    /// Secondaries to NOTIFY when `zone` changes
+    pub fn secondaries(&self, zone: &str) -> &[SocketAddr] {
+        self.zone_transfer(zone)
+            .map(|transfer| transfer.secondaries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Name of the view whose records `client` is answered from
+    pub fn view_for(&self, client: IpAddr) -> &str {
+        let client = client.to_canonical();
+        self.views
+            .iter()
+            .find(|view| view.match_clients.iter().any(|network| network.contains(client)))
+            .map(|view| view.name.as_str())
+            .unwrap_or(DEFAULT_VIEW)
+    }
+
+    fn zone_transfer(&self, zone: &str) -> Option<&ZoneTransferConfig> {
+        let zone = zone.trim_end_matches('.');
+        self.zone_transfers
+            .iter()
+            .find(|transfer| transfer.zone.trim_end_matches('.').eq_ignore_ascii_case(zone))
+    }
+}
+
+/// Records tagged with `name` are served to clients from `match_clients`, in place of the
+/// default records at the same owner names
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsViewConfig {
+    pub name: String,
+    pub match_clients: Vec<IpNetwork>,
+}
+
+/// Recent queries kept in memory for the API, with a sample of them written to `dns_query_log`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryLogConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How many of the most recent queries are kept in memory
+    #[serde(default = "default_query_log_size")]
+    pub buffer_size: usize,
+    /// Fraction of queries (0.0 to 1.0) also stored in the database
+    #[serde(default)]
+    pub sample_rate: f64,
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            buffer_size: default_query_log_size(),
+            sample_rate: 0.0,
+        }
+    }
+}
+
+fn default_query_log_size() -> usize {
+    1000
+}
+
+/// Transfer, NOTIFY and dynamic update access for one zone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneTransferConfig {
+    pub zone: String,
+    /// Peers allowed to AXFR this zone, in addition to `dns.allow_transfer`
+    #[serde(default)]
+    pub allow_transfer: Vec<IpAddr>,
+    /// Secondaries ("address:port") sent a NOTIFY whenever the zone serial changes.
+    /// They are also allowed to transfer the zone.
+    #[serde(default)]
+    pub secondaries: Vec<SocketAddr>,
+    /// Clients allowed to send RFC 2136 UPDATEs for this zone, in addition to `dns.allow_update`
+    #[serde(default)]
+    pub allow_update: Vec<IpAddr>,
+    /// Names of the TSIG keys accepted for this zone; empty accepts any key in `dns.tsig_keys`
+    #[serde(default)]
+    pub tsig_keys: Vec<String>,
+}
+
+/// Shared secret for TSIG-signed transfers and updates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TsigKeyConfig {
+    pub name: String,
+    #[serde(default = "default_tsig_algorithm")]
+    pub algorithm: String,
+    /// Base64-encoded secret, e.g. from `tsig-keygen` or `openssl rand -base64 32`
+    pub secret: String,
+}
+
+fn default_tsig_algorithm() -> String {
+    "hmac-sha256".to_string()
+}
+
+/// Catch-all answer for names under `suffix` that have no specific record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DefaultZoneConfig {
+    pub suffix: String,
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+    pub ttl: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,21 +209,70 @@ pub struct DhcpConfig {
     pub enabled: bool,
     pub bind_address: String,
     pub port: u16,
+    /// Listen on each of these interfaces separately instead of on `bind_address`, so directly
+    /// connected clients are matched to the subnet of the interface their broadcast arrived on
+    #[serde(default)]
+    pub interfaces: Vec<String>,
     pub default_lease_time: u32,
+    /// Shortest lease granted when a client asks for one (option 51)
+    #[serde(default = "default_min_lease_time")]
+    pub min_lease_time: u32,
+    /// Longest lease granted when a client asks for one (option 51)
     pub max_lease_time: u32,
     pub renewal_time: u32,
     pub rebind_time: u32,
     pub decline_time: u32,
+    /// NAK requests we won't serve instead of staying silent so another server can answer
+    #[serde(default)]
+    pub authoritative: bool,
+    /// Ping free addresses before offering them and decline any that answer
+    #[serde(default)]
+    pub ping_check: bool,
+    #[serde(default = "default_ping_timeout_ms")]
+    pub ping_timeout_ms: u64,
+}
+
+fn default_min_lease_time() -> u32 {
+    300
+}
+
+fn default_ping_timeout_ms() -> u64 {
+    500
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IPv6Config {
     pub enabled: bool,
     pub radvd_config_path: String,
+    /// Where radvd writes its pid; its presence means radvd is running and can be reloaded
+    #[serde(default = "default_radvd_pid_path")]
+    pub radvd_pid_path: String,
+    /// Who sends Router Advertisements for subnets with an IPv6 prefix
+    #[serde(default)]
+    pub router_advertisements: RouterAdvertisements,
     pub prefix_length: u8,
     pub router_lifetime: u32,
     pub reachable_time: u32,
     pub retransmit_time: u32,
+    /// Resolvers offered to DHCPv6 clients (option 23); the option is left out when empty
+    #[serde(default)]
+    pub dns_servers: Vec<Ipv6Addr>,
+}
+
+fn default_radvd_pid_path() -> String {
+    "/var/run/radvd.pid".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouterAdvertisements {
+    /// Left to the network's routers
+    #[default]
+    None,
+    /// radvd, configured from the subnets and managed through systemctl
+    Radvd,
+    /// Sent by FlowDNS itself over a raw ICMPv6 socket (needs CAP_NET_RAW)
+    Native,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,6 +292,19 @@ pub struct ApiConfig {
     pub cors_origins: Vec<String>,
     pub jwt_secret: String,
     pub jwt_expiry: u64,
+    /// Failed logins allowed per client IP and per username within `login_window_secs`
+    #[serde(default = "default_login_max_failures")]
+    pub login_max_failures: u32,
+    #[serde(default = "default_login_window_secs")]
+    pub login_window_secs: u64,
+}
+
+fn default_login_max_failures() -> u32 {
+    5
+}
+
+fn default_login_window_secs() -> u64 {
+    60
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,16 +320,35 @@ pub struct SubnetConfig {
     pub vlan_id: Option<u16>,
     pub description: String,
     pub enabled: bool,
+    /// Server identifier (option 54) for this subnet's clients; derived when unset
+    #[serde(default)]
+    pub server_ip: Option<Ipv4Addr>,
+    /// Next-server for network booting clients (siaddr and option 66)
+    #[serde(default)]
+    pub boot_server: Option<Ipv4Addr>,
+    /// Boot file (option 67) when no boot rule matches
+    #[serde(default)]
+    pub boot_filename: Option<String>,
+    #[serde(default)]
+    pub boot_rules: Vec<BootRule>,
+    /// Addresses never handed out: single IPs, `start-end` ranges or CIDR blocks
+    #[serde(default)]
+    pub excluded_ranges: Vec<ExcludedRange>,
 }
 
 impl Settings {
+    /// Reads `config_path` with `FLOWDNS__SECTION__KEY` environment overrides on top. String
+    /// values may reference `${VAR}`, and any `key_file` setting is replaced by a `key` read
+    /// from that file, so secrets can come from the environment or mounted files.
     pub fn load(config_path: &str) -> Result<Self> {
         let settings = config::Config::builder()
             .add_source(config::File::with_name(config_path).required(false))
             .add_source(config::Environment::with_prefix("FLOWDNS").separator("__"))
             .build()?;
 
-        Ok(settings.try_deserialize()?)
+        let mut root: config::Value = settings.try_deserialize()?;
+        resolve_secrets(&mut root, "")?;
+        Ok(root.try_deserialize()?)
     }
 
     pub fn validate(&self) -> Result<()> {
@@ -114,27 +356,374 @@ impl Settings {
         if self.database.url.is_empty() {
             anyhow::bail!("Database URL is required");
         }
+        if let Err(e) = self.database.url.parse::<sqlx::postgres::PgConnectOptions>() {
+            anyhow::bail!("database.url is not a valid PostgreSQL URL: {}", e);
+        }
+
+        for (service, enabled, port) in [
+            ("dhcp", self.dhcp.enabled, self.dhcp.port),
+            ("dns", self.dns.enabled, self.dns.port),
+            ("api", self.api.enabled, self.api.port),
+        ] {
+            if enabled && port == 0 {
+                anyhow::bail!("{}.port must be set when {} is enabled", service, service);
+            }
+        }
+
+        let query_log = &self.dns.query_log;
+        if query_log.enabled && query_log.buffer_size == 0 {
+            anyhow::bail!("dns.query_log.buffer_size must be at least 1");
+        }
+        if !(0.0..=1.0).contains(&query_log.sample_rate) {
+            anyhow::bail!("dns.query_log.sample_rate must be between 0.0 and 1.0");
+        }
+
+        let mut view_names = std::collections::HashSet::new();
+        for view in &self.dns.views {
+            if view.name.is_empty() || view.name.len() > 63 {
+                anyhow::bail!("dns.views: view names must be 1 to 63 characters");
+            }
+            if view.name == DEFAULT_VIEW {
+                anyhow::bail!("dns.views: '{}' is the implicit view for unmatched clients", DEFAULT_VIEW);
+            }
+            if !view_names.insert(view.name.as_str()) {
+                anyhow::bail!("dns.views: view '{}' is defined twice", view.name);
+            }
+        }
 
         if self.api.enabled && self.api.jwt_secret.len() < 32 {
             anyhow::bail!("JWT secret must be at least 32 characters");
         }
+        if let Err(e) = check_cors_origins(&self.api.cors_origins) {
+            anyhow::bail!("api.cors_origins: {}", e);
+        }
 
         for (name, subnet) in &self.subnets {
             let network: ipnetwork::IpNetwork = subnet.network.parse()?;
 
-            if !network.contains(std::net::IpAddr::V4(subnet.start_ip)) {
-                anyhow::bail!("Subnet {}: start_ip not in network", name);
+            if let Err(e) = check_subnet_range(&network, subnet.start_ip, subnet.end_ip, subnet.gateway) {
+                anyhow::bail!("Subnet {}: {}", name, e);
+            }
+            if let Err(e) = check_excluded_ranges(&network, &subnet.excluded_ranges) {
+                anyhow::bail!("Subnet {}: {}", name, e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Expands `${VAR}` in every string below `value` and swaps each `key_file` for `key` holding
+/// the file's contents, less a trailing newline. `path` names `value` in error messages.
+fn resolve_secrets(value: &mut config::Value, path: &str) -> Result<()> {
+    match &mut value.kind {
+        config::ValueKind::String(s) => {
+            *s = interpolate_env(s).with_context(|| format!("in {}", path))?;
+        }
+        config::ValueKind::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                resolve_secrets(item, &format!("{}[{}]", path, i))?;
+            }
+        }
+        config::ValueKind::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                resolve_secrets(item, &join_key(path, key))?;
             }
 
-            if !network.contains(std::net::IpAddr::V4(subnet.end_ip)) {
-                anyhow::bail!("Subnet {}: end_ip not in network", name);
+            let file_keys: Vec<String> = table.keys().filter(|key| key.ends_with("_file")).cloned().collect();
+            for file_key in file_keys {
+                let key = &file_key[..file_key.len() - "_file".len()];
+                if table.contains_key(key) {
+                    anyhow::bail!("Set either {} or {}, not both", join_key(path, key), join_key(path, &file_key));
+                }
+
+                let file = table.remove(&file_key).unwrap().into_string()?;
+                let contents = std::fs::read_to_string(&file)
+                    .with_context(|| format!("Failed to read {} from {}", join_key(path, key), file))?;
+                let secret = contents.trim_end_matches(['\r', '\n']).to_string();
+                table.insert(key.to_string(), config::Value::new(None, secret));
             }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn join_key(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Replaces each `${VAR}` in `s` with the variable's value; an unset variable is an error
+/// rather than an empty string so a missing secret can't go unnoticed
+fn interpolate_env(s: &str) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow::anyhow!("unterminated ${{ in \"{}\"", s))?;
+        let name = &rest[start + 2..start + end];
+        let value = std::env::var(name)
+            .map_err(|_| anyhow::anyhow!("environment variable {} is not set", name))?;
+        out.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
 
-            if subnet.start_ip > subnet.end_ip {
-                anyhow::bail!("Subnet {}: start_ip must be less than end_ip", name);
+/// Checks that the pool bounds and gateway sit inside `network` and that the pool isn't inverted.
+/// Shared by config validation and the subnet API so both reject the same misconfigurations.
+pub fn check_subnet_range(
+    network: &ipnetwork::IpNetwork,
+    start_ip: Ipv4Addr,
+    end_ip: Ipv4Addr,
+    gateway: Ipv4Addr,
+) -> Result<()> {
+    for (label, ip) in [("start_ip", start_ip), ("end_ip", end_ip), ("gateway", gateway)] {
+        if !network.contains(std::net::IpAddr::V4(ip)) {
+            anyhow::bail!("{} {} is outside network {}", label, ip, network);
+        }
+    }
+
+    if start_ip > end_ip {
+        anyhow::bail!("start_ip {} is after end_ip {}", start_ip, end_ip);
+    }
+
+    Ok(())
+}
+
+/// Checks that every CORS origin is `*` or a `scheme://host[:port]` origin, which the CORS
+/// middleware otherwise only rejects when the API workers start
+fn check_cors_origins(origins: &[String]) -> Result<()> {
+    for origin in origins.iter().filter(|origin| *origin != "*") {
+        let uri: actix_web::http::Uri = origin.parse()
+            .with_context(|| format!("'{}' is not a valid origin", origin))?;
+        let has_path = origin.ends_with('/') || uri.path_and_query().is_some_and(|path| path != "/");
+        if uri.scheme().is_none() || uri.host().is_none() || has_path {
+            anyhow::bail!("'{}' is not an origin; use scheme://host[:port]", origin);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every excluded range lies inside `network`
+pub fn check_excluded_ranges(network: &ipnetwork::IpNetwork, ranges: &[ExcludedRange]) -> Result<()> {
+    for range in ranges {
+        for ip in [range.start, range.end] {
+            if !network.contains(std::net::IpAddr::V4(ip)) {
+                anyhow::bail!("excluded range {} is outside network {}", range, network);
             }
         }
+    }
+    Ok(())
+}
 
-        Ok(())
+/// Options the server fills in itself for every reply and won't take from a subnet's
+/// `dhcp_options`: pad, lease/renewal/rebinding times, message type, server identifier,
+/// client identifier, relay agent information and end
+const SERVER_MANAGED_OPTIONS: [u8; 10] = [0, 51, 52, 53, 54, 58, 59, 61, 82, 255];
+
+/// Checks that every custom option has a code the server lets subnets set and a value that
+/// encodes to a valid option payload
+pub fn check_dhcp_options(options: &BTreeMap<u8, DhcpOptionValue>) -> Result<()> {
+    for (code, value) in options {
+        if SERVER_MANAGED_OPTIONS.contains(code) {
+            anyhow::bail!("DHCP option {} is set by the server and can't be overridden", code);
+        }
+        if let Err(e) = value.encode() {
+            anyhow::bail!("DHCP option {}: {}", code, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_subnet_range() {
+        let network: ipnetwork::IpNetwork = "192.168.1.0/24".parse().unwrap();
+        let ip = |last: u8| Ipv4Addr::new(192, 168, 1, last);
+
+        assert!(check_subnet_range(&network, ip(100), ip(200), ip(1)).is_ok());
+        assert!(check_subnet_range(&network, ip(200), ip(100), ip(1)).is_err());
+        assert!(check_subnet_range(&network, ip(100), Ipv4Addr::new(192, 168, 2, 10), ip(1)).is_err());
+        assert!(check_subnet_range(&network, ip(100), ip(200), Ipv4Addr::new(10, 0, 0, 1)).is_err());
+    }
+
+    #[test]
+    fn test_excluded_ranges() {
+        let network: ipnetwork::IpNetwork = "192.168.1.0/24".parse().unwrap();
+        let ip = |last: u8| Ipv4Addr::new(192, 168, 1, last);
+        let parse = |s: &str| s.parse::<ExcludedRange>();
+
+        assert_eq!(parse("192.168.1.5").unwrap(), ExcludedRange { start: ip(5), end: ip(5) });
+        assert_eq!(parse("192.168.1.20 - 192.168.1.30").unwrap(), ExcludedRange { start: ip(20), end: ip(30) });
+        assert_eq!(parse("192.168.1.64/28").unwrap(), ExcludedRange { start: ip(64), end: ip(79) });
+        assert!(parse("192.168.1.30-192.168.1.20").is_err());
+        assert!(parse("printer").is_err());
+        assert_eq!(parse("192.168.1.64/28").unwrap().to_string(), "192.168.1.64-192.168.1.79");
+
+        let ranges = vec![parse("192.168.1.5").unwrap(), parse("192.168.1.64/28").unwrap()];
+        assert!(check_excluded_ranges(&network, &ranges).is_ok());
+        assert!(check_excluded_ranges(&network, &[parse("192.168.1.250-192.168.2.5").unwrap()]).is_err());
+    }
+
+    #[test]
+    fn test_check_cors_origins() {
+        let origins = |list: &[&str]| list.iter().map(|origin| origin.to_string()).collect::<Vec<_>>();
+
+        assert!(check_cors_origins(&origins(&["*", "https://admin.example", "http://localhost:3000"])).is_ok());
+        assert!(check_cors_origins(&origins(&["https://admin example"])).is_err());
+        assert!(check_cors_origins(&origins(&["localhost:3000"])).is_err());
+        assert!(check_cors_origins(&origins(&["https://admin.example/ui"])).is_err());
+        assert!(check_cors_origins(&origins(&["https://admin.example/"])).is_err());
+    }
+
+    #[test]
+    fn test_check_dhcp_options() {
+        let mut options = BTreeMap::new();
+        options.insert(42, DhcpOptionValue::Ips(vec![Ipv4Addr::new(192, 168, 1, 1)]));
+        options.insert(252, DhcpOptionValue::String("http://wpad/wpad.dat".to_string()));
+        options.insert(43, DhcpOptionValue::Hex("01:04:c0:a8:01:01".to_string()));
+        assert!(check_dhcp_options(&options).is_ok());
+
+        let single = |code: u8, value: DhcpOptionValue| BTreeMap::from([(code, value)]);
+        assert!(check_dhcp_options(&single(51, DhcpOptionValue::Hex("00000e10".to_string()))).is_err());
+        assert!(check_dhcp_options(&single(42, DhcpOptionValue::Ips(vec![]))).is_err());
+        assert!(check_dhcp_options(&single(43, DhcpOptionValue::Hex("abc".to_string()))).is_err());
+        assert!(check_dhcp_options(&single(43, DhcpOptionValue::Hex("zz".to_string()))).is_err());
+        assert!(check_dhcp_options(&single(252, DhcpOptionValue::String("x".repeat(256)))).is_err());
+    }
+
+    #[test]
+    fn test_interpolate_env() {
+        std::env::set_var("FLOWDNS_TEST_DB_PASSWORD", "s3cret");
+        assert_eq!(
+            interpolate_env("postgresql://flowdns:${FLOWDNS_TEST_DB_PASSWORD}@db/flowdns").unwrap(),
+            "postgresql://flowdns:s3cret@db/flowdns"
+        );
+        assert_eq!(interpolate_env("no $variables here").unwrap(), "no $variables here");
+        assert!(interpolate_env("${FLOWDNS_TEST_UNSET_VARIABLE}").is_err());
+        assert!(interpolate_env("${FLOWDNS_TEST_DB_PASSWORD").is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_secret_files() {
+        let mut secret = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut secret, b"from-a-mounted-secret\n").unwrap();
+        let path = secret.path().to_str().unwrap();
+
+        let config = |toml: &str| -> config::Value {
+            config::Config::builder()
+                .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+                .build()
+                .unwrap()
+                .try_deserialize()
+                .unwrap()
+        };
+
+        let mut root = config(&format!("[api]\njwt_secret_file = \"{}\"", path));
+        resolve_secrets(&mut root, "").unwrap();
+        let api = root.into_table().unwrap().remove("api").unwrap().into_table().unwrap();
+        assert!(!api.contains_key("jwt_secret_file"));
+        assert_eq!(api["jwt_secret"].clone().into_string().unwrap(), "from-a-mounted-secret");
+
+        let mut both = config(&format!("[api]\njwt_secret = \"x\"\njwt_secret_file = \"{}\"", path));
+        assert!(resolve_secrets(&mut both, "").unwrap_err().to_string().contains("api.jwt_secret"));
+
+        let mut missing = config("[api]\njwt_secret_file = \"/nonexistent/flowdns-secret\"");
+        assert!(resolve_secrets(&mut missing, "").is_err());
+    }
+
+    #[test]
+    fn test_transfer_allowed() {
+        let dns = DnsConfig {
+            enabled: true,
+            bind_address: "0.0.0.0".to_string(),
+            port: 53,
+            forward_servers: Vec::new(),
+            domain_suffix: "local".to_string(),
+            dynamic_updates: false,
+            hostname_template: "host-{ip_dash}".to_string(),
+            ttl_default: 3600,
+            cache_size: 0,
+            default_zones: Vec::new(),
+            allow_transfer: vec!["192.0.2.53".parse().unwrap()],
+            zone_transfers: vec![ZoneTransferConfig {
+                zone: "Example.com.".to_string(),
+                allow_transfer: vec!["198.51.100.53".parse().unwrap()],
+                secondaries: vec!["203.0.113.9:53".parse().unwrap()],
+                allow_update: vec!["192.0.2.77".parse().unwrap()],
+                tsig_keys: vec!["xfr-key.".to_string()],
+            }],
+            allow_notify: Vec::new(),
+            allow_update: Vec::new(),
+            tsig_keys: Vec::new(),
+            require_tsig: false,
+            query_log: QueryLogConfig::default(),
+            views: Vec::new(),
+        };
+
+        assert!(dns.transfer_allowed("other.org", "192.0.2.53".parse().unwrap()));
+        assert!(dns.transfer_allowed("example.com", "198.51.100.53".parse().unwrap()));
+        assert!(dns.transfer_allowed("example.com", "::ffff:198.51.100.53".parse().unwrap()));
+        assert!(!dns.transfer_allowed("other.org", "198.51.100.53".parse().unwrap()));
+        assert!(!dns.transfer_allowed("example.com", "203.0.113.1".parse().unwrap()));
+
+        // Secondaries may transfer the zone they are notified about, and only that one
+        assert!(dns.transfer_allowed("example.com", "203.0.113.9".parse().unwrap()));
+        assert!(!dns.transfer_allowed("other.org", "203.0.113.9".parse().unwrap()));
+        assert_eq!(dns.secondaries("example.com").len(), 1);
+        assert!(dns.secondaries("other.org").is_empty());
+
+        assert!(dns.update_allowed("example.com", "192.0.2.77".parse().unwrap()));
+        assert!(!dns.update_allowed("other.org", "192.0.2.77".parse().unwrap()));
+        assert!(!dns.update_allowed("example.com", "192.0.2.53".parse().unwrap()));
+
+        assert!(dns.tsig_key_allowed("example.com", "xfr-key"));
+        assert!(!dns.tsig_key_allowed("example.com", "other-key"));
+        assert!(dns.tsig_key_allowed("other.org", "other-key"));
+    }
+
+    #[test]
+    fn test_view_for() {
+        let view = |name: &str, networks: &[&str]| DnsViewConfig {
+            name: name.to_string(),
+            match_clients: networks.iter().map(|n| n.parse().unwrap()).collect(),
+        };
+        let mut dns: DnsConfig = toml::from_str(
+            r#"
+            enabled = true
+            bind_address = "0.0.0.0"
+            port = 53
+            forward_servers = []
+            domain_suffix = "local"
+            dynamic_updates = false
+            hostname_template = "host-{ip_dash}"
+            ttl_default = 3600
+            cache_size = 0
+            "#,
+        )
+        .unwrap();
+        assert!(dns.views.is_empty());
+        assert_eq!(dns.view_for("192.168.1.10".parse().unwrap()), DEFAULT_VIEW);
+
+        dns.views = vec![
+            view("lab", &["192.168.50.0/24"]),
+            view("internal", &["192.168.0.0/16", "fd00::/8"]),
+        ];
+        assert_eq!(dns.view_for("192.168.50.7".parse().unwrap()), "lab");
+        assert_eq!(dns.view_for("192.168.1.10".parse().unwrap()), "internal");
+        assert_eq!(dns.view_for("::ffff:192.168.1.10".parse().unwrap()), "internal");
+        assert_eq!(dns.view_for("fd12::1".parse().unwrap()), "internal");
+        assert_eq!(dns.view_for("203.0.113.1".parse().unwrap()), DEFAULT_VIEW);
+    }
+}
@@ -1,9 +1,11 @@
 pub mod models;
+pub mod notify;
 pub mod schema;
 
 use anyhow::Result;
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use sqlx::{migrate::Migrate, postgres::PgPoolOptions, PgPool};
 use crate::config::DatabaseConfig;
+use std::collections::HashMap;
 use std::time::Duration;
 
 pub async fn init_pool(config: &DatabaseConfig) -> Result<PgPool> {
@@ -23,4 +25,57 @@ pub async fn run_migrations(pool: &PgPool) -> Result<()> {
         .run(pool)
         .await?;
     Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// Applied, but the file has changed since
+    ChecksumMismatch,
+    /// Recorded in the database but not shipped with this binary, e.g. after a downgrade
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+}
+
+/// Every migration this binary ships and every one the database has applied, in version order
+pub async fn migration_status(pool: &PgPool) -> Result<Vec<MigrationStatus>> {
+    let migrator = sqlx::migrate!("./migrations");
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+
+    let mut applied: HashMap<i64, Vec<u8>> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| (migration.version, migration.checksum.into_owned()))
+        .collect();
+
+    let mut status: Vec<MigrationStatus> = migrator
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            state: match applied.remove(&migration.version) {
+                None => MigrationState::Pending,
+                Some(checksum) if checksum == *migration.checksum => MigrationState::Applied,
+                Some(_) => MigrationState::ChecksumMismatch,
+            },
+        })
+        .collect();
+
+    status.extend(applied.into_keys().map(|version| MigrationStatus {
+        version,
+        description: String::new(),
+        state: MigrationState::Unknown,
+    }));
+    status.sort_by_key(|migration| migration.version);
+    Ok(status)
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::collections::BTreeMap;
 use std::net::Ipv4Addr;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
@@ -20,11 +21,183 @@ pub struct DhcpSubnet {
     pub vlan_id: Option<i32>,
     pub ipv6_prefix: Option<IpNetwork>,
     pub enabled: bool,
+    /// Existing leases are renewed but no new addresses are handed out
+    pub maintenance: bool,
+    /// Option 82 circuit-id (text, or hex for binary ids) that selects this subnet for relayed clients
+    #[serde(default)]
+    pub relay_circuit_id: Option<String>,
+    /// Server identifier (option 54) for this subnet's clients; derived when unset
+    #[serde(default)]
+    pub server_ip: Option<Ipv4Addr>,
+    /// Next-server for network booting clients (siaddr and option 66)
+    #[serde(default)]
+    pub boot_server: Option<Ipv4Addr>,
+    /// Boot file (option 67) for clients no boot rule matches
+    #[serde(default)]
+    pub boot_filename: Option<String>,
+    #[serde(default)]
+    #[sqlx(json)]
+    pub boot_rules: Vec<BootRule>,
+    /// Addresses inside the pool that are never handed out, e.g. statically configured printers
+    #[serde(default)]
+    #[sqlx(json)]
+    pub excluded_ranges: Vec<ExcludedRange>,
+    /// Extra options sent to the subnet's clients by code, replacing any built-in option with the same code
+    #[serde(default, deserialize_with = "deserialize_option_codes")]
+    #[sqlx(json)]
+    pub dhcp_options: BTreeMap<u8, DhcpOptionValue>,
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Boot file for clients whose vendor class (option 60) starts with `vendor_class` and whose
+/// architecture (option 93) is `arch`. A rule without conditions matches every client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BootRule {
+    #[serde(default)]
+    pub vendor_class: Option<String>,
+    #[serde(default)]
+    pub arch: Option<u16>,
+    pub filename: String,
+}
+
+impl BootRule {
+    pub fn matches(&self, vendor_class: Option<&str>, arch: Option<u16>) -> bool {
+        let vendor_matches = match &self.vendor_class {
+            Some(prefix) => vendor_class.is_some_and(|class| class.starts_with(prefix.as_str())),
+            None => true,
+        };
+        let arch_matches = self.arch.is_none() || self.arch == arch;
+
+        vendor_matches && arch_matches
+    }
+}
+
+/// An inclusive run of IPv4 addresses, written as a single address (`10.0.0.5`), a range
+/// (`10.0.0.20-10.0.0.30`) or a CIDR block (`10.0.0.64/28`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ExcludedRange {
+    pub start: Ipv4Addr,
+    pub end: Ipv4Addr,
+}
+
+impl ExcludedRange {
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        ip >= self.start && ip <= self.end
+    }
+}
+
+impl std::str::FromStr for ExcludedRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let range = if let Some((start, end)) = s.split_once('-') {
+            Self { start: start.trim().parse()?, end: end.trim().parse()? }
+        } else if s.contains('/') {
+            let block: ipnetwork::Ipv4Network = s.parse()?;
+            Self { start: block.network(), end: block.broadcast() }
+        } else {
+            let ip = s.parse()?;
+            Self { start: ip, end: ip }
+        };
+
+        if range.start > range.end {
+            anyhow::bail!("excluded range {} starts after it ends", s);
+        }
+        Ok(range)
+    }
+}
+
+impl std::fmt::Display for ExcludedRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl TryFrom<String> for ExcludedRange {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<ExcludedRange> for String {
+    fn from(range: ExcludedRange) -> Self {
+        range.to_string()
+    }
+}
+
+/// Value of a custom DHCP option, written as `{"ips": [...]}`, `{"string": "..."}` or
+/// `{"hex": "..."}` depending on how the option is encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DhcpOptionValue {
+    /// IPv4 addresses, e.g. option 42 NTP servers
+    Ips(Vec<Ipv4Addr>),
+    /// Text without a terminating NUL, e.g. option 252 WPAD URL
+    String(String),
+    /// Raw bytes as hex digits, optionally separated by colons or spaces, e.g. an
+    /// option 119 search list already in wire format
+    Hex(String),
+}
+
+impl DhcpOptionValue {
+    /// The option payload as sent on the wire
+    pub fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        let data = match self {
+            Self::Ips(ips) if ips.is_empty() => anyhow::bail!("no addresses given"),
+            Self::Ips(ips) => ips.iter().flat_map(|ip| ip.octets()).collect(),
+            Self::String(text) => text.as_bytes().to_vec(),
+            Self::Hex(hex) => decode_hex(hex)?,
+        };
+
+        if data.is_empty() || data.len() > 255 {
+            anyhow::bail!("value is {} bytes, options hold 1 to 255", data.len());
+        }
+        Ok(data)
+    }
+}
+
+/// Reads option codes from their string form, as JSON object keys always are. Serde can't
+/// parse integer keys itself once the subnet is flattened into another struct.
+fn deserialize_option_codes<'de, D>(deserializer: D) -> Result<BTreeMap<u8, DhcpOptionValue>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    BTreeMap::<String, DhcpOptionValue>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(code, value)| {
+            code.parse()
+                .map(|code| (code, value))
+                .map_err(|_| serde::de::Error::custom(format!("invalid DHCP option code {}", code)))
+        })
+        .collect()
+}
+
+fn decode_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    let digits: Vec<u8> = hex.bytes().filter(|b| *b != b':' && !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        anyhow::bail!("hex value {} has an odd number of digits", hex);
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| anyhow::anyhow!("invalid hex value {}", hex))
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct DhcpLease {
     pub id: Uuid,
@@ -65,10 +238,18 @@ pub struct DnsZone {
     pub minimum_ttl: i32,
     pub primary_ns: Option<String>,
     pub admin_email: Option<String>,
+    pub dnssec_enabled: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// View of records served to clients that match no configured `dns.views` entry
+pub const DEFAULT_VIEW: &str = "default";
+
+fn default_view() -> String {
+    DEFAULT_VIEW.to_string()
+}
+
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct DnsRecord {
     pub id: Uuid,
@@ -81,6 +262,9 @@ pub struct DnsRecord {
     pub weight: Option<i32>,
     pub port: Option<i32>,
     pub is_dynamic: bool,
+    /// Split-horizon view the record is served in; "default" records are served to every client
+    #[serde(default = "default_view")]
+    pub view: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -124,4 +308,17 @@ impl DhcpSubnet {
     pub fn contains_ip(&self, ip: Ipv4Addr) -> bool {
         ip >= self.start_ip && ip <= self.end_ip
     }
+
+    pub fn is_excluded(&self, ip: Ipv4Addr) -> bool {
+        self.excluded_ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// The first matching boot rule's file, falling back to `boot_filename`
+    pub fn boot_filename_for(&self, vendor_class: Option<&str>, arch: Option<u16>) -> Option<&str> {
+        self.boot_rules
+            .iter()
+            .find(|rule| rule.matches(vendor_class, arch))
+            .map(|rule| rule.filename.as_str())
+            .or(self.boot_filename.as_deref())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,97 @@
+// Config change notifications between processes sharing the database, over LISTEN/NOTIFY
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use anyhow::Result;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Channel the API announces subnet, zone and record changes on
+pub const CHANNEL: &str = "flowdns_config";
+
+/// What changed. Record changes are announced as a change to their zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum ConfigChange {
+    Subnet(Uuid),
+    Zone(Uuid),
+    /// Too much changed to name, e.g. a restored backup; reload everything
+    All,
+}
+
+impl ConfigChange {
+    fn payload(&self) -> String {
+        serde_json::to_string(self).expect("config change serializes")
+    }
+
+    fn from_payload(payload: &str) -> Option<Self> {
+        serde_json::from_str(payload).ok()
+    }
+}
+
+/// Sends `change` to every listener, including ones in this process
+pub async fn publish(db: &PgPool, change: ConfigChange) -> Result<()> {
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(CHANNEL)
+        .bind(change.payload())
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Like `publish`, but a failure is only logged; the change is already stored and other
+/// processes still pick it up on their next full reload
+pub async fn announce(db: &PgPool, change: ConfigChange) {
+    if let Err(e) = publish(db, change).await {
+        warn!("Failed to announce {:?} on {}: {}", change, CHANNEL, e);
+    }
+}
+
+pub struct ConfigListener {
+    listener: PgListener,
+}
+
+impl ConfigListener {
+    /// Opens a dedicated connection and starts listening on `CHANNEL`
+    pub async fn connect(db: &PgPool) -> Result<Self> {
+        let mut listener = PgListener::connect_with(db).await?;
+        listener.listen(CHANNEL).await?;
+        Ok(Self { listener })
+    }
+
+    /// Waits for the next change. `All` is returned after the connection was lost and
+    /// re-established, since notifications sent in between never arrive.
+    pub async fn recv(&mut self) -> Result<ConfigChange> {
+        loop {
+            let Some(notification) = self.listener.try_recv().await? else {
+                warn!("Lost the {} listener connection, reloading everything", CHANNEL);
+                return Ok(ConfigChange::All);
+            };
+
+            match ConfigChange::from_payload(notification.payload()) {
+                Some(change) => return Ok(change),
+                None => warn!("Ignoring unrecognised {} payload: {}", CHANNEL, notification.payload()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let id = Uuid::new_v4();
+        for change in [ConfigChange::Subnet(id), ConfigChange::Zone(id), ConfigChange::All] {
+            assert_eq!(ConfigChange::from_payload(&change.payload()), Some(change));
+        }
+
+        assert_eq!(
+            ConfigChange::Subnet(id).payload(),
+            format!(r#"{{"kind":"subnet","id":"{}"}}"#, id)
+        );
+        assert_eq!(ConfigChange::All.payload(), r#"{"kind":"all"}"#);
+        assert_eq!(ConfigChange::from_payload("reload"), None);
+    }
+}
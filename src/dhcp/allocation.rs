@@ -0,0 +1,143 @@
+// In-memory record of which pool addresses are taken, so allocation doesn't query every address
+use std::net::Ipv4Addr;
+
+/// One bit per address from `start` to `end`; a set bit means leased, reserved, excluded or
+/// otherwise unusable. Declined addresses are left clear, since their decline expires. It is a
+/// hint: a free bit is still checked against the database and set if that finds the address
+/// taken, while a stale set bit only clears when the map is rebuilt.
+#[derive(Debug, Clone)]
+pub struct AllocationMap {
+    start: u32,
+    len: u32,
+    words: Vec<u64>,
+}
+
+impl AllocationMap {
+    pub fn new(start: Ipv4Addr, end: Ipv4Addr) -> Self {
+        let (start, end) = (u32::from(start), u32::from(end));
+        let len = if end >= start { end - start + 1 } else { 0 };
+        let mut map = Self {
+            start,
+            len,
+            words: vec![0; (len as usize).div_ceil(64)],
+        };
+
+        // Bits past the end of the pool read as taken, so searches never return them
+        let tail = len % 64;
+        if tail != 0 {
+            if let Some(last) = map.words.last_mut() {
+                *last = !0u64 << tail;
+            }
+        }
+        map
+    }
+
+    /// Addresses outside the pool are ignored
+    pub fn mark_used(&mut self, ip: Ipv4Addr) {
+        if let Some((word, bit)) = self.position(ip) {
+            self.words[word] |= 1 << bit;
+        }
+    }
+
+    pub fn mark_free(&mut self, ip: Ipv4Addr) {
+        if let Some((word, bit)) = self.position(ip) {
+            self.words[word] &= !(1 << bit);
+        }
+    }
+
+    pub fn is_used(&self, ip: Ipv4Addr) -> bool {
+        self.position(ip)
+            .map(|(word, bit)| self.words[word] & (1 << bit) != 0)
+            .unwrap_or(true)
+    }
+
+    /// Lowest free address at or after `from`, skipping 64 taken addresses per step
+    pub fn next_free(&self, from: Ipv4Addr) -> Option<Ipv4Addr> {
+        let from = u32::from(from).max(self.start);
+        let offset = from.checked_sub(self.start)?;
+        if offset >= self.len {
+            return None;
+        }
+
+        let mut word = (offset / 64) as usize;
+        // Treat the bits before `from` in its word as taken
+        let mut taken = self.words[word] | ((1u64 << (offset % 64)) - 1);
+        loop {
+            if taken != u64::MAX {
+                let bit = (!taken).trailing_zeros();
+                return Some(Ipv4Addr::from(self.start + word as u32 * 64 + bit));
+            }
+            word += 1;
+            taken = *self.words.get(word)?;
+        }
+    }
+
+    pub fn free_count(&self) -> u32 {
+        self.words.iter().map(|word| word.count_zeros()).sum()
+    }
+
+    fn position(&self, ip: Ipv4Addr) -> Option<(usize, u32)> {
+        let offset = u32::from(ip).checked_sub(self.start)?;
+        (offset < self.len).then_some(((offset / 64) as usize, offset % 64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u32) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(Ipv4Addr::new(10, 0, 0, 0)) + last)
+    }
+
+    #[test]
+    fn test_next_free() {
+        let mut map = AllocationMap::new(ip(10), ip(20));
+        assert_eq!(map.free_count(), 11);
+        assert_eq!(map.next_free(ip(0)), Some(ip(10)));
+
+        map.mark_used(ip(10));
+        map.mark_used(ip(11));
+        assert_eq!(map.next_free(ip(0)), Some(ip(12)));
+        assert_eq!(map.next_free(ip(15)), Some(ip(15)));
+        assert!(map.is_used(ip(11)));
+        assert!(!map.is_used(ip(12)));
+
+        map.mark_free(ip(10));
+        assert_eq!(map.next_free(ip(0)), Some(ip(10)));
+        assert_eq!(map.next_free(ip(11)), Some(ip(12)));
+
+        // Outside the pool
+        assert_eq!(map.next_free(ip(21)), None);
+        assert!(map.is_used(ip(9)));
+        map.mark_used(ip(30));
+        assert_eq!(map.free_count(), 10);
+    }
+
+    #[test]
+    fn test_full_pool_across_words() {
+        // A /22 pool spans 16 words
+        let mut map = AllocationMap::new(ip(1), ip(1022));
+        for n in 1..=1022 {
+            map.mark_used(ip(n));
+        }
+        assert_eq!(map.free_count(), 0);
+        assert_eq!(map.next_free(ip(0)), None);
+
+        map.mark_free(ip(1000));
+        assert_eq!(map.next_free(ip(0)), Some(ip(1000)));
+        assert_eq!(map.next_free(ip(1001)), None);
+    }
+
+    #[test]
+    fn test_empty_and_single_address_pools() {
+        let map = AllocationMap::new(ip(20), ip(10));
+        assert_eq!(map.next_free(ip(10)), None);
+        assert_eq!(map.free_count(), 0);
+
+        let mut map = AllocationMap::new(ip(5), ip(5));
+        assert_eq!(map.next_free(ip(0)), Some(ip(5)));
+        map.mark_used(ip(5));
+        assert_eq!(map.next_free(ip(0)), None);
+    }
+}
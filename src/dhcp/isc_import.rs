@@ -0,0 +1,295 @@
+// Import static host declarations from an ISC dhcpd.conf into dhcp_reservations
+use sqlx::{PgPool, Row};
+use std::net::{IpAddr, Ipv4Addr};
+use anyhow::Result;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IscHost {
+    pub name: String,
+    pub mac_address: Vec<u8>,
+    pub fixed_address: Ipv4Addr,
+    pub hostname: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedHosts {
+    pub hosts: Vec<IscHost>,
+    /// Host declarations that couldn't be used, with the reason
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    /// Hosts whose fixed-address is outside every configured subnet
+    pub unmatched: Vec<String>,
+    /// Hosts that clash with an existing reservation for the same MAC or IP
+    pub conflicts: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    OpenBrace,
+    CloseBrace,
+    Semicolon,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '{' => { chars.next(); tokens.push(Token::OpenBrace); }
+            '}' => { chars.next(); tokens.push(Token::CloseBrace); }
+            ';' => { chars.next(); tokens.push(Token::Semicolon); }
+            ',' => { chars.next(); tokens.push(Token::Comma); }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => value.extend(chars.next()),
+                        _ => value.push(c),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            c if c.is_whitespace() => { chars.next(); }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{};,\"#".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    tokens
+}
+
+pub(crate) fn parse_mac(value: &str) -> Option<Vec<u8>> {
+    let bytes: Vec<u8> = value
+        .split(':')
+        .map(|part| u8::from_str_radix(part, 16))
+        .collect::<std::result::Result<_, _>>()
+        .ok()?;
+    (bytes.len() == 6).then_some(bytes)
+}
+
+/// Extracts every `host` block, including ones nested in subnet/group/shared-network blocks
+pub fn parse_hosts(config: &str) -> ParsedHosts {
+    let tokens = tokenize(config);
+    let mut parsed = ParsedHosts::default();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let is_host = matches!(&tokens[i], Token::Word(w) if w == "host")
+            && matches!(tokens.get(i + 2), Some(Token::OpenBrace));
+        if !is_host {
+            i += 1;
+            continue;
+        }
+
+        let name = match &tokens[i + 1] {
+            Token::Word(name) | Token::Quoted(name) => name.clone(),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        // Collect the host body's statements up to the matching close brace
+        let mut statements: Vec<Vec<&Token>> = Vec::new();
+        let mut current = Vec::new();
+        let mut depth = 1;
+        i += 3;
+        while i < tokens.len() && depth > 0 {
+            match &tokens[i] {
+                Token::OpenBrace => depth += 1,
+                Token::CloseBrace => depth -= 1,
+                Token::Semicolon if depth == 1 => statements.push(std::mem::take(&mut current)),
+                token if depth == 1 => current.push(token),
+                _ => {}
+            }
+            i += 1;
+        }
+
+        let mut mac_address = None;
+        let mut fixed_address = None;
+        let mut hostname = None;
+
+        for statement in &statements {
+            let words: Vec<&str> = statement
+                .iter()
+                .filter_map(|t| match t {
+                    Token::Word(w) | Token::Quoted(w) => Some(w.as_str()),
+                    _ => None,
+                })
+                .collect();
+
+            match words.as_slice() {
+                ["hardware", "ethernet", mac] => mac_address = parse_mac(mac),
+                // Only the first address of a list is imported
+                ["fixed-address", address, ..] => fixed_address = Some(address.to_string()),
+                ["option", "host-name", value] => hostname = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        let mac_address = match mac_address {
+            Some(mac) => mac,
+            None => {
+                parsed.skipped.push(format!("{}: missing or invalid hardware ethernet", name));
+                continue;
+            }
+        };
+
+        let fixed_address = match fixed_address {
+            Some(address) => match address.parse::<Ipv4Addr>() {
+                Ok(ip) => ip,
+                Err(_) => {
+                    parsed.skipped.push(format!("{}: fixed-address {} is not an IPv4 literal", name, address));
+                    continue;
+                }
+            },
+            None => {
+                parsed.skipped.push(format!("{}: no fixed-address", name));
+                continue;
+            }
+        };
+
+        parsed.hosts.push(IscHost {
+            name,
+            mac_address,
+            fixed_address,
+            hostname,
+        });
+    }
+
+    parsed
+}
+
+/// Inserts reservations for the hosts, matching each to the subnet containing its fixed-address
+pub async fn import_reservations(db: &PgPool, hosts: &[IscHost]) -> Result<ImportReport> {
+    let rows = sqlx::query("SELECT id, network FROM dhcp_subnets")
+        .fetch_all(db)
+        .await?;
+
+    let subnets: Vec<(uuid::Uuid, ipnetwork::IpNetwork)> = rows
+        .iter()
+        .map(|row| (row.get("id"), row.get("network")))
+        .collect();
+
+    let mut report = ImportReport::default();
+
+    for host in hosts {
+        let ip = IpAddr::V4(host.fixed_address);
+        let label = format!("{} ({})", host.name, host.fixed_address);
+
+        // Most specific subnet wins if networks are nested
+        let subnet = subnets
+            .iter()
+            .filter(|(_, network)| network.contains(ip))
+            .max_by_key(|(_, network)| network.prefix());
+
+        let Some((subnet_id, _)) = subnet else {
+            warn!("No subnet for ISC host {}", label);
+            report.unmatched.push(label);
+            continue;
+        };
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO dhcp_reservations (subnet_id, mac_address, ip_address, hostname, description)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(subnet_id)
+        .bind(&host.mac_address)
+        .bind(ip)
+        .bind(host.hostname.as_deref().unwrap_or(&host.name))
+        .bind("Imported from dhcpd.conf")
+        .execute(db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            warn!("Reservation already exists for ISC host {}", label);
+            report.conflicts.push(label);
+        } else {
+            report.imported.push(label);
+        }
+    }
+
+    info!(
+        "ISC import: {} imported, {} unmatched, {} conflicts",
+        report.imported.len(),
+        report.unmatched.len(),
+        report.conflicts.len()
+    );
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+        # Office printers
+        subnet 192.168.1.0 netmask 255.255.255.0 {
+            range 192.168.1.100 192.168.1.200;
+
+            host printer-1 {
+                hardware ethernet 00:11:22:33:44:55;
+                fixed-address 192.168.1.10;
+            }
+
+            group {
+                host nas { hardware ethernet AA:BB:CC:DD:EE:FF; fixed-address 192.168.1.20, 192.168.1.21; option host-name "storage"; }
+            }
+        }
+
+        host no-address { hardware ethernet 00:11:22:33:44:66; }
+        host by-name { hardware ethernet 00:11:22:33:44:77; fixed-address server.example.com; }
+    "#;
+
+    #[test]
+    fn test_parse_host_declarations() {
+        let parsed = parse_hosts(SAMPLE);
+
+        assert_eq!(parsed.hosts.len(), 2);
+        assert_eq!(parsed.hosts[0], IscHost {
+            name: "printer-1".to_string(),
+            mac_address: vec![0x00, 0x11, 0x22, 0x33, 0x44, 0x55],
+            fixed_address: Ipv4Addr::new(192, 168, 1, 10),
+            hostname: None,
+        });
+        assert_eq!(parsed.hosts[1].name, "nas");
+        assert_eq!(parsed.hosts[1].mac_address, vec![0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+        assert_eq!(parsed.hosts[1].fixed_address, Ipv4Addr::new(192, 168, 1, 20));
+        assert_eq!(parsed.hosts[1].hostname.as_deref(), Some("storage"));
+
+        assert_eq!(parsed.skipped.len(), 2);
+        assert!(parsed.skipped[0].starts_with("no-address"));
+        assert!(parsed.skipped[1].starts_with("by-name"));
+    }
+}
@@ -0,0 +1,193 @@
+// Append-only lease history, written alongside every lease state change, and the live feed of
+// the same transitions for API subscribers
+
+use crate::database::models::DhcpLease;
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool, Row};
+use std::net::Ipv4Addr;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use anyhow::Result;
+
+/// Who caused a lease transition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseEventSource {
+    Dhcp,
+    Api,
+}
+
+impl LeaseEventSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LeaseEventSource::Dhcp => "dhcp",
+            LeaseEventSource::Api => "api",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LeaseEvent {
+    pub id: i64,
+    pub lease_id: Uuid,
+    pub mac_address: Vec<u8>,
+    pub ip_address: Ipv4Addr,
+    /// `None` when the lease row was created by this event
+    pub old_state: Option<String>,
+    pub new_state: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Live events a subscriber may fall behind by before it starts missing some
+pub const LIVE_EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LiveEventKind {
+    Created,
+    Renewed,
+    Released,
+    Expired,
+}
+
+impl LiveEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LiveEventKind::Created => "created",
+            LiveEventKind::Renewed => "renewed",
+            LiveEventKind::Released => "released",
+            LiveEventKind::Expired => "expired",
+        }
+    }
+}
+
+/// A lease transition as it happens, broadcast by the lease manager
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveLeaseEvent {
+    pub event: LiveEventKind,
+    pub lease_id: Uuid,
+    pub subnet_id: Uuid,
+    pub mac_address: String,
+    pub ip_address: Ipv4Addr,
+    pub hostname: Option<String>,
+    pub lease_end: DateTime<Utc>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl LiveLeaseEvent {
+    pub fn new(event: LiveEventKind, lease: &DhcpLease) -> Self {
+        Self {
+            event,
+            lease_id: lease.id,
+            subnet_id: lease.subnet_id,
+            mac_address: lease.mac_address
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+            ip_address: lease.ip_address,
+            hostname: lease.hostname.clone(),
+            lease_end: lease.lease_end,
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// The event as one Server-Sent Events message, named after its kind
+    pub fn to_sse(&self) -> String {
+        let data = serde_json::to_string(self).unwrap_or_default();
+        format!("event: {}\ndata: {}\n\n", self.event.as_str(), data)
+    }
+}
+
+/// Appends one event; run it on the transaction that changes the lease
+pub async fn record_event(
+    conn: &mut PgConnection,
+    lease_id: Uuid,
+    mac_address: &[u8],
+    ip_address: Ipv4Addr,
+    old_state: Option<&str>,
+    new_state: &str,
+    source: LeaseEventSource,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO lease_events (lease_id, mac_address, ip_address, old_state, new_state, source)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#
+    )
+    .bind(lease_id)
+    .bind(mac_address)
+    .bind(std::net::IpAddr::V4(ip_address))
+    .bind(old_state)
+    .bind(new_state)
+    .bind(source.as_str())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Events of one lease, oldest first
+pub async fn fetch_history(db: &PgPool, lease_id: Uuid) -> Result<Vec<LeaseEvent>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, lease_id, mac_address, ip_address, old_state, new_state, source, created_at
+        FROM lease_events
+        WHERE lease_id = $1
+        ORDER BY created_at, id
+        "#
+    )
+    .bind(lease_id)
+    .fetch_all(db)
+    .await?;
+
+    let mut events = Vec::new();
+    for row in rows {
+        events.push(LeaseEvent {
+            id: row.get("id"),
+            lease_id: row.get("lease_id"),
+            mac_address: row.get("mac_address"),
+            ip_address: row.get::<std::net::IpAddr, _>("ip_address").to_string().parse()?,
+            old_state: row.get("old_state"),
+            new_state: row.get("new_state"),
+            source: row.get("source"),
+            created_at: row.get("created_at"),
+        });
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_live_event_as_sse() {
+        let lease = DhcpLease {
+            id: Uuid::new_v4(),
+            subnet_id: Uuid::new_v4(),
+            mac_address: vec![0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e],
+            ip_address: Ipv4Addr::new(192, 168, 1, 50),
+            hostname: Some("laptop".to_string()),
+            lease_start: Utc::now(),
+            lease_end: Utc::now(),
+            state: "active".to_string(),
+            client_identifier: None,
+            vendor_class: None,
+            user_class: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        let message = LiveLeaseEvent::new(LiveEventKind::Released, &lease).to_sse();
+        assert!(message.starts_with("event: released\ndata: {"));
+        assert!(message.ends_with("}\n\n"));
+
+        let data: serde_json::Value = serde_json::from_str(message.lines().nth(1).unwrap().trim_start_matches("data: ")).unwrap();
+        assert_eq!(data["event"], "released");
+        assert_eq!(data["mac_address"], "00:1a:2b:3c:4d:5e");
+        assert_eq!(data["ip_address"], "192.168.1.50");
+        assert_eq!(data["subnet_id"], lease.subnet_id.to_string());
+    }
+}
@@ -1,55 +1,134 @@
 use crate::database::models::{DhcpSubnet, DhcpLease, DhcpReservation};
 use crate::config::Settings;
+use crate::dhcp::allocation::AllocationMap;
+use crate::dhcp::lease_events::{LeaseEventSource, LiveEventKind, LiveLeaseEvent, LIVE_EVENT_CAPACITY};
+use crate::dhcp::packet::{ClientInfo, RelayAgentInfo};
 use sqlx::PgPool;
 use std::net::Ipv4Addr;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use uuid::Uuid;
 use chrono::{Utc, Duration};
 use anyhow::{Result, anyhow};
 use tracing::{info, warn, debug};
 
+/// Stop probing after this many free addresses answer pings, so one DISCOVER can't stall for long
+const MAX_PING_CONFLICTS: u32 = 3;
+
 pub struct LeaseManager {
     db: PgPool,
     subnets: Arc<RwLock<HashMap<Uuid, DhcpSubnet>>>,
+    /// Which pool addresses of each loaded subnet are taken
+    allocations: Arc<RwLock<HashMap<Uuid, AllocationMap>>>,
     settings: Arc<Settings>,
+    /// Lease transitions for live subscribers such as the API's event stream
+    events: broadcast::Sender<LiveLeaseEvent>,
 }
 
 impl LeaseManager {
     pub async fn new(db: PgPool, settings: Arc<Settings>) -> Result<Self> {
-        let mut manager = Self {
+        let manager = Self {
             db,
             subnets: Arc::new(RwLock::new(HashMap::new())),
+            allocations: Arc::new(RwLock::new(HashMap::new())),
             settings,
+            events: broadcast::channel(LIVE_EVENT_CAPACITY).0,
         };
 
-        manager.load_subnets().await?;
+        manager.reload_subnets().await?;
         Ok(manager)
     }
 
-    async fn load_subnets(&mut self) -> Result<()> {
+    /// Replaces the in-memory subnets with the enabled subnets currently in the database. If they
+    /// can't all be read the current subnets stay in service. Leases live in the database and are
+    /// unaffected.
+    pub async fn reload_subnets(&self) -> Result<()> {
         use super::lease_manager_queries;
 
         let subnets = lease_manager_queries::fetch_all_subnets(&self.db).await?;
+        let mut allocations = HashMap::new();
+        for subnet in &subnets {
+            allocations.insert(subnet.id, self.load_allocations(subnet).await?);
+        }
+        let subnets: HashMap<Uuid, DhcpSubnet> = subnets.into_iter().map(|subnet| (subnet.id, subnet)).collect();
 
         let mut subnet_map = self.subnets.write().await;
-        for subnet in subnets {
-            subnet_map.insert(subnet.id, subnet);
-        }
+        *self.allocations.write().await = allocations;
+        let previous = std::mem::replace(&mut *subnet_map, subnets);
         info!("Loaded {} subnets", subnet_map.len());
 
+        if !previous.is_empty() {
+            log_subnet_changes(&previous, &subnet_map);
+        }
+
         Ok(())
     }
 
+    /// Re-reads one subnet after it was created, changed or deleted. A subnet that is gone or
+    /// disabled stops being served; the others are left alone.
+    pub async fn reload_subnet(&self, subnet_id: Uuid) -> Result<()> {
+        use super::lease_manager_queries;
+
+        let subnet = lease_manager_queries::fetch_subnet(&self.db, subnet_id)
+            .await?
+            .filter(|subnet| subnet.enabled);
+        let loaded = match subnet {
+            Some(subnet) => {
+                let allocations = self.load_allocations(&subnet).await?;
+                Some((subnet, allocations))
+            }
+            None => None,
+        };
+
+        let mut subnet_map = self.subnets.write().await;
+        let mut allocation_map = self.allocations.write().await;
+        match loaded {
+            Some((subnet, allocations)) => {
+                allocation_map.insert(subnet_id, allocations);
+                match subnet_map.get(&subnet_id) {
+                    None => info!("Subnet added: {} ({})", subnet.name, subnet.network),
+                    Some(old) if old.updated_at != subnet.updated_at => {
+                        info!("Subnet changed: {} ({})", subnet.name, subnet.network)
+                    }
+                    Some(_) => {}
+                }
+                subnet_map.insert(subnet_id, subnet);
+            }
+            None => {
+                allocation_map.remove(&subnet_id);
+                if let Some(old) = subnet_map.remove(&subnet_id) {
+                    info!("Subnet removed: {} ({})", old.name, old.network);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A subnet whose `relay_circuit_id` matches the option 82 circuit-id wins; otherwise the
+    /// subnet containing `relay_agent_ip` (the relay, or the receiving interface for directly
+    /// connected clients) or, failing that, the client address
     pub async fn find_subnet_for_client(
         &self,
         client_ip: Ipv4Addr,
-        relay_agent_ip: Option<Ipv4Addr>
+        relay_agent_ip: Option<Ipv4Addr>,
+        relay_info: Option<&RelayAgentInfo>,
     ) -> Option<DhcpSubnet> {
-        let target_ip = relay_agent_ip.unwrap_or(client_ip);
         let subnets = self.subnets.read().await;
 
+        if let Some(circuit_id) = relay_info.and_then(RelayAgentInfo::circuit_id_text) {
+            let matched = subnets
+                .values()
+                .find(|subnet| subnet.relay_circuit_id.as_deref() == Some(circuit_id.as_str()));
+            if let Some(subnet) = matched {
+                debug!("Circuit-id {} selects subnet {}", circuit_id, subnet.name);
+                return Some(subnet.clone());
+            }
+        }
+
+        let target_ip = relay_agent_ip.unwrap_or(client_ip);
+
         for subnet in subnets.values() {
             if subnet.network.contains(std::net::IpAddr::V4(target_ip)) {
                 return Some(subnet.clone());
@@ -64,61 +143,231 @@ impl LeaseManager {
         subnet_id: Uuid,
         mac_address: &[u8]
     ) -> Result<Option<Ipv4Addr>> {
-        let subnets = self.subnets.read().await;
-        let subnet = subnets.get(&subnet_id)
+        self.select_ip(subnet_id, mac_address, false).await
+    }
+
+    /// Like `find_available_ip`, but with `dhcp.ping_check` on, a free address that answers a ping
+    /// is declined and the next one is tried
+    pub async fn find_ip_to_offer(
+        &self,
+        subnet_id: Uuid,
+        mac_address: &[u8]
+    ) -> Result<Option<Ipv4Addr>> {
+        self.select_ip(subnet_id, mac_address, self.settings.dhcp.ping_check).await
+    }
+
+    async fn select_ip(
+        &self,
+        subnet_id: Uuid,
+        mac_address: &[u8],
+        ping_check: bool,
+    ) -> Result<Option<Ipv4Addr>> {
+        // Cloned so the lock isn't held while probing addresses
+        let subnet = self.get_subnet(subnet_id).await
             .ok_or_else(|| anyhow!("Subnet not found: {}", subnet_id))?;
 
-        // Check for existing reservation
+        // In maintenance only clients that already hold a lease here are served
+        if subnet.maintenance {
+            let lease = self.get_active_lease_by_mac(subnet_id, mac_address).await?;
+            if lease.is_none() {
+                debug!("Subnet {} is in maintenance, not allocating to MAC {}",
+                       subnet.name, format_mac(mac_address));
+            }
+            return Ok(lease.map(|lease| lease.ip_address));
+        }
+
+        // Check for existing reservation. Fixed addresses may sit outside the dynamic range,
+        // but one outside the subnet can't be served here.
         if let Some(reservation) = self.get_reservation(subnet_id, mac_address).await? {
-            debug!("Found reservation for MAC {}: {}",
-                   format_mac(mac_address), reservation.ip_address);
-            return Ok(Some(reservation.ip_address));
+            if subnet.network.contains(std::net::IpAddr::V4(reservation.ip_address)) {
+                debug!("Found reservation for MAC {}: {}",
+                       format_mac(mac_address), reservation.ip_address);
+                return Ok(Some(reservation.ip_address));
+            }
+            warn!("Ignoring reservation {} for MAC {}: outside subnet {}",
+                  reservation.ip_address, format_mac(mac_address), subnet.network);
         }
 
-        // Check for existing active lease
-        if let Some(lease) = self.get_active_lease_by_mac(mac_address).await? {
-            if lease.subnet_id == subnet_id {
+        // Every other client's reservation, fetched once rather than per candidate
+        let reserved: HashSet<Ipv4Addr> = {
+            use super::lease_manager_queries;
+            lease_manager_queries::fetch_reserved_addresses(&self.db, subnet_id).await?
+                .into_iter()
+                .collect()
+        };
+
+        // Check for existing active lease, unless its address has since been reserved for
+        // another client
+        if let Some(lease) = self.get_active_lease_by_mac(subnet_id, mac_address).await? {
+            if !reserved.contains(&lease.ip_address) {
                 debug!("Found existing lease for MAC {}: {}",
                        format_mac(mac_address), lease.ip_address);
                 return Ok(Some(lease.ip_address));
             }
+            debug!("Existing lease {} for MAC {} is reserved for another client",
+                   lease.ip_address, format_mac(mac_address));
         }
 
-        // Find next available IP in range
-        let start = u32::from(subnet.start_ip);
-        let end = u32::from(subnet.end_ip);
-
-        for ip_num in start..=end {
-            let ip = Ipv4Addr::from(ip_num);
+        // Lowest address the allocation map says is free, confirmed against the database. A
+        // map that looks full is reloaded once, since leases expired or released elsewhere
+        // only reach it on reload.
+        let mut from = subnet.start_ip;
+        let mut reloaded = false;
+        let mut conflicts = 0;
+
+        loop {
+            let candidate = self.allocations.read().await
+                .get(&subnet_id)
+                .and_then(|allocations| allocations.next_free(from));
+
+            let ip = match candidate {
+                Some(ip) => ip,
+                None if !reloaded => {
+                    let allocations = self.load_allocations(&subnet).await?;
+                    self.allocations.write().await.insert(subnet_id, allocations);
+                    reloaded = true;
+                    from = subnet.start_ip;
+                    continue;
+                }
+                None => break,
+            };
+            from = Ipv4Addr::from(u32::from(ip).saturating_add(1));
+
+            if subnet.is_excluded(ip) || reserved.contains(&ip) || self.is_ip_leased(subnet_id, ip).await? {
+                self.mark_allocated(subnet_id, ip).await;
+                continue;
+            }
 
-            // Skip network and broadcast addresses
-            let network = subnet.network.ip();
-            let broadcast = subnet.network.broadcast();
-            if std::net::IpAddr::V4(ip) == network || std::net::IpAddr::V4(ip) == broadcast {
+            // Declined addresses stay free in the map so they come back once the decline ages out
+            if self.is_ip_declined(subnet_id, ip).await? {
                 continue;
             }
 
-            // Check if IP is available
-            if !self.is_ip_in_use(subnet_id, ip).await? {
-                debug!("Found available IP: {}", ip);
-                return Ok(Some(ip));
+            if ping_check && self.answers_ping(ip).await {
+                warn!("Free address {} answers ping, declining it", ip);
+                self.decline_address(subnet_id, ip, None).await?;
+                conflicts += 1;
+                if conflicts >= MAX_PING_CONFLICTS {
+                    warn!("{} addresses in subnet {} answered pings, not offering one this time",
+                          conflicts, subnet.name);
+                    return Ok(None);
+                }
+                continue;
             }
+
+            debug!("Found available IP: {}", ip);
+            return Ok(Some(ip));
         }
 
         warn!("No available IPs in subnet {}", subnet.name);
         Ok(None)
     }
 
-    async fn is_ip_in_use(&self, subnet_id: Uuid, ip: Ipv4Addr) -> Result<bool> {
+    /// Builds the subnet's allocation map from the database. The network and broadcast
+    /// addresses and the subnet's excluded ranges are never handed out.
+    async fn load_allocations(&self, subnet: &DhcpSubnet) -> Result<AllocationMap> {
+        use super::lease_manager_queries;
+
+        let mut allocations = AllocationMap::new(subnet.start_ip, subnet.end_ip);
+        for ip in [subnet.network.ip(), subnet.network.broadcast()] {
+            if let std::net::IpAddr::V4(ip) = ip {
+                allocations.mark_used(ip);
+            }
+        }
+
+        for range in &subnet.excluded_ranges {
+            for ip in u32::from(range.start)..=u32::from(range.end) {
+                allocations.mark_used(Ipv4Addr::from(ip));
+            }
+        }
+
+        let used = lease_manager_queries::fetch_used_addresses(&self.db, subnet.id).await?;
+        for ip in used {
+            allocations.mark_used(ip);
+        }
+        Ok(allocations)
+    }
+
+    /// Rebuilds every subnet's allocation map, e.g. after expired leases freed addresses
+    async fn reload_allocations(&self) -> Result<()> {
+        let subnets: Vec<DhcpSubnet> = self.subnets.read().await.values().cloned().collect();
+        let mut allocations = HashMap::new();
+        for subnet in &subnets {
+            allocations.insert(subnet.id, self.load_allocations(subnet).await?);
+        }
+        self.allocations.write().await.extend(allocations);
+        Ok(())
+    }
+
+    async fn mark_allocated(&self, subnet_id: Uuid, ip: Ipv4Addr) {
+        if let Some(allocations) = self.allocations.write().await.get_mut(&subnet_id) {
+            allocations.mark_used(ip);
+        }
+    }
+
+    async fn mark_released(&self, subnet_id: Uuid, ip: Ipv4Addr) {
+        if let Some(allocations) = self.allocations.write().await.get_mut(&subnet_id) {
+            allocations.mark_free(ip);
+        }
+    }
+
+    /// Active leases only; `select_ip` checks reservations against the set it fetched
+    async fn is_ip_leased(&self, subnet_id: Uuid, ip: Ipv4Addr) -> Result<bool> {
         use super::lease_manager_queries;
 
         let lease_count = lease_manager_queries::count_active_leases(&self.db, subnet_id, ip).await?;
-        if lease_count > 0 {
-            return Ok(true);
+        Ok(lease_count > 0)
+    }
+
+    /// Declined within the last `dhcp.decline_time` seconds
+    async fn is_ip_declined(&self, subnet_id: Uuid, ip: Ipv4Addr) -> Result<bool> {
+        use super::lease_manager_queries;
+
+        let decline_count = lease_manager_queries::count_declines_since(
+            &self.db,
+            subnet_id,
+            ip,
+            self.decline_cutoff(),
+        )
+        .await?;
+        Ok(decline_count > 0)
+    }
+
+    /// Probe failures (e.g. no permission to open an ICMP socket) count as no answer so DHCP keeps working
+    async fn answers_ping(&self, ip: Ipv4Addr) -> bool {
+        let timeout = std::time::Duration::from_millis(self.settings.dhcp.ping_timeout_ms);
+        match super::ping_check::address_responds(ip, timeout).await {
+            Ok(answered) => answered,
+            Err(e) => {
+                warn!("Ping check of {} failed: {}", ip, e);
+                false
+            }
         }
+    }
+
+    /// Keeps `ip` out of allocation for `dhcp.decline_time` seconds after a client reports a conflict
+    pub async fn decline_address(&self, subnet_id: Uuid, ip_address: Ipv4Addr, mac_address: Option<&[u8]>) -> Result<()> {
+        use super::lease_manager_queries;
 
-        let reservation_count = lease_manager_queries::count_reservations(&self.db, subnet_id, ip).await?;
-        Ok(reservation_count > 0)
+        lease_manager_queries::record_decline(&self.db, subnet_id, ip_address, mac_address, Utc::now()).await?;
+        warn!("Address {} marked as declined for {}s", ip_address, self.settings.dhcp.decline_time);
+
+        Ok(())
+    }
+
+    /// Declines recorded before this instant no longer block allocation
+    fn decline_cutoff(&self) -> chrono::DateTime<Utc> {
+        Utc::now() - Duration::seconds(self.settings.dhcp.decline_time as i64)
+    }
+
+    /// Lease length for a client on `subnet` that asked for `requested` seconds (option 51)
+    pub fn lease_time_for(&self, subnet: &DhcpSubnet, requested: Option<u32>) -> u32 {
+        granted_lease_time(
+            requested,
+            subnet.lease_duration,
+            self.settings.dhcp.min_lease_time,
+            self.settings.dhcp.max_lease_time,
+        )
     }
 
     pub async fn create_lease(
@@ -126,7 +375,9 @@ impl LeaseManager {
         subnet_id: Uuid,
         mac_address: &[u8],
         ip_address: Ipv4Addr,
-        hostname: Option<String>
+        hostname: Option<String>,
+        client: ClientInfo,
+        requested_lease_time: Option<u32>,
     ) -> Result<DhcpLease> {
         use super::lease_manager_queries;
 
@@ -135,25 +386,41 @@ impl LeaseManager {
             .ok_or_else(|| anyhow!("Subnet not found"))?;
 
         let lease_start = Utc::now();
-        let lease_end = lease_start + Duration::seconds(subnet.lease_duration as i64);
+        let lease_end = lease_start + Duration::seconds(self.lease_time_for(subnet, requested_lease_time) as i64);
 
         let final_hostname = hostname.or_else(|| {
             self.generate_hostname(ip_address)
         });
 
-        let lease = lease_manager_queries::insert_or_update_lease(
+        let result = lease_manager_queries::insert_or_update_lease(
             &self.db,
             subnet_id,
             mac_address,
             ip_address,
             final_hostname,
+            client,
             lease_start,
             lease_end,
+            super::lease_events::LeaseEventSource::Dhcp,
         )
-        .await?;
+        .await;
+
+        drop(subnets);
+        let lease = match result {
+            Ok(lease) => lease,
+            Err(e) => {
+                // Whoever won the address holds it now, so don't offer it again
+                if lease_manager_queries::is_address_conflict(&e) {
+                    self.mark_allocated(subnet_id, ip_address).await;
+                }
+                return Err(e);
+            }
+        };
+        self.mark_allocated(subnet_id, ip_address).await;
 
         info!("Created lease: MAC {} -> IP {} (expires: {})",
              format_mac(mac_address), ip_address, lease_end);
+        self.publish(LiveEventKind::Created, &lease);
 
         Ok(lease)
     }
@@ -161,7 +428,8 @@ impl LeaseManager {
     pub async fn renew_lease(
         &self,
         mac_address: &[u8],
-        requested_ip: Ipv4Addr
+        requested_ip: Ipv4Addr,
+        requested_lease_time: Option<u32>,
     ) -> Result<Option<DhcpLease>> {
         use super::lease_manager_queries;
 
@@ -177,7 +445,7 @@ impl LeaseManager {
             let subnet = subnets.get(&lease.subnet_id)
                 .ok_or_else(|| anyhow!("Subnet not found"))?;
 
-            let new_lease_end = Utc::now() + Duration::seconds(subnet.lease_duration as i64);
+            let new_lease_end = Utc::now() + Duration::seconds(self.lease_time_for(subnet, requested_lease_time) as i64);
 
             let renewed_lease = lease_manager_queries::update_lease_end(
                 &self.db,
@@ -188,6 +456,7 @@ impl LeaseManager {
 
             info!("Renewed lease: MAC {} -> IP {} (new expiry: {})",
                  format_mac(mac_address), requested_ip, new_lease_end);
+            self.publish(LiveEventKind::Renewed, &renewed_lease);
 
             return Ok(Some(renewed_lease));
         }
@@ -195,13 +464,21 @@ impl LeaseManager {
         Ok(None)
     }
 
+    /// Releases the client's active lease on `ip_address`, returning it if there was one
     pub async fn release_lease(
         &self,
         mac_address: &[u8],
         ip_address: Ipv4Addr
-    ) -> Result<bool> {
+    ) -> Result<Option<DhcpLease>> {
         use super::lease_manager_queries;
 
+        let lease = lease_manager_queries::find_active_lease_by_mac_and_ip(
+            &self.db,
+            mac_address,
+            ip_address,
+        )
+        .await?;
+
         let released = lease_manager_queries::release_lease(
             &self.db,
             mac_address,
@@ -209,12 +486,61 @@ impl LeaseManager {
         )
         .await?;
 
-        if released {
-            info!("Released lease: MAC {} -> IP {}",
-                 format_mac(mac_address), ip_address);
+        if !released {
+            return Ok(None);
+        }
+        if let Some(lease) = &lease {
+            self.mark_released(lease.subnet_id, lease.ip_address).await;
+            self.publish(LiveEventKind::Released, lease);
+        }
+
+        info!("Released lease: MAC {} -> IP {}",
+             format_mac(mac_address), ip_address);
+
+        Ok(lease)
+    }
+
+    /// Ends an active lease ahead of time, freeing its address for other clients
+    pub async fn expire_lease(&self, lease_id: Uuid, source: LeaseEventSource) -> Result<Option<DhcpLease>> {
+        use super::lease_manager_queries;
+
+        let lease = lease_manager_queries::expire_lease(&self.db, lease_id, source).await?;
+        if let Some(lease) = &lease {
+            self.mark_released(lease.subnet_id, lease.ip_address).await;
+            info!("Expired lease: MAC {} -> IP {}", format_mac(&lease.mac_address), lease.ip_address);
+            self.publish(LiveEventKind::Expired, lease);
+        }
+
+        Ok(lease)
+    }
+
+    /// Keeps an active lease `seconds` longer than it would otherwise last
+    pub async fn extend_lease(&self, lease_id: Uuid, seconds: u32, source: LeaseEventSource) -> Result<Option<DhcpLease>> {
+        use super::lease_manager_queries;
+
+        let lease = lease_manager_queries::extend_lease(&self.db, lease_id, seconds, source).await?;
+        if let Some(lease) = &lease {
+            info!("Extended lease: MAC {} -> IP {} (new expiry: {})",
+                 format_mac(&lease.mac_address), lease.ip_address, lease.lease_end);
+            self.publish(LiveEventKind::Renewed, lease);
         }
 
-        Ok(released)
+        Ok(lease)
+    }
+
+    /// Lease transitions from now on. A receiver that falls more than `LIVE_EVENT_CAPACITY`
+    /// events behind skips the oldest.
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveLeaseEvent> {
+        self.events.subscribe()
+    }
+
+    fn publish(&self, kind: LiveEventKind, lease: &DhcpLease) {
+        // Sending only fails when nobody is listening
+        let _ = self.events.send(LiveLeaseEvent::new(kind, lease));
+    }
+
+    pub async fn get_subnet(&self, subnet_id: Uuid) -> Option<DhcpSubnet> {
+        self.subnets.read().await.get(&subnet_id).cloned()
     }
 
     async fn get_reservation(
@@ -234,12 +560,14 @@ impl LeaseManager {
 
     async fn get_active_lease_by_mac(
         &self,
+        subnet_id: Uuid,
         mac_address: &[u8]
     ) -> Result<Option<DhcpLease>> {
         use super::lease_manager_queries;
 
         lease_manager_queries::get_active_lease_by_mac(
             &self.db,
+            subnet_id,
             mac_address,
         )
         .await
@@ -261,18 +589,77 @@ impl LeaseManager {
     pub async fn cleanup_expired_leases(&self) -> Result<u64> {
         use super::lease_manager_queries;
 
-        let count = lease_manager_queries::expire_old_leases(&self.db).await?;
+        let expired = lease_manager_queries::expire_old_leases(&self.db).await?;
+        if !expired.is_empty() {
+            info!("Cleaned up {} expired leases", expired.len());
+            if let Err(e) = self.reload_allocations().await {
+                warn!("Failed to reload address allocations: {}", e);
+            }
+        }
+        for lease in &expired {
+            self.publish(LiveEventKind::Expired, lease);
+        }
+
+        Ok(expired.len() as u64)
+    }
+
+    pub async fn cleanup_expired_declines(&self) -> Result<u64> {
+        use super::lease_manager_queries;
+
+        let count = lease_manager_queries::delete_declines_before(&self.db, self.decline_cutoff()).await?;
         if count > 0 {
-            info!("Cleaned up {} expired leases", count);
+            info!("Cleared {} expired address declines", count);
         }
 
         Ok(count)
     }
 }
 
+/// Logs subnets that appeared, disappeared (deleted or disabled) or were edited since the last load
+fn log_subnet_changes(previous: &HashMap<Uuid, DhcpSubnet>, current: &HashMap<Uuid, DhcpSubnet>) {
+    for (id, subnet) in current {
+        match previous.get(id) {
+            None => info!("Subnet added: {} ({})", subnet.name, subnet.network),
+            Some(old) if old.updated_at != subnet.updated_at => {
+                info!("Subnet changed: {} ({})", subnet.name, subnet.network)
+            }
+            Some(_) => {}
+        }
+    }
+    for (id, subnet) in previous {
+        if !current.contains_key(id) {
+            info!("Subnet removed: {} ({})", subnet.name, subnet.network);
+        }
+    }
+}
+
+/// The client's requested lease time clamped to `[min, max]`, or the subnet default when it didn't ask
+pub fn granted_lease_time(requested: Option<u32>, subnet_default: i32, min: u32, max: u32) -> u32 {
+    match requested {
+        Some(seconds) => seconds.clamp(min, max.max(min)),
+        None => subnet_default.max(0) as u32,
+    }
+}
+
 fn format_mac(mac: &[u8]) -> String {
     mac.iter()
         .map(|b| format!("{:02x}", b))
         .collect::<Vec<_>>()
         .join(":")
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_granted_lease_time() {
+        // No option 51: subnet default, even outside the bounds
+        assert_eq!(granted_lease_time(None, 86400, 300, 3600), 86400);
+        assert_eq!(granted_lease_time(Some(1800), 86400, 300, 3600), 1800);
+        assert_eq!(granted_lease_time(Some(10), 86400, 300, 3600), 300);
+        assert_eq!(granted_lease_time(Some(u32::MAX), 86400, 300, 3600), 3600);
+        // A max below the min doesn't panic; the min wins
+        assert_eq!(granted_lease_time(Some(100), 86400, 300, 60), 300);
+    }
+}
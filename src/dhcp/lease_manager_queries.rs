@@ -2,6 +2,8 @@
 // Using runtime queries instead of compile-time checked macros
 
 use crate::database::models::{DhcpSubnet, DhcpLease, DhcpReservation};
+use super::lease_events::{self, LeaseEventSource};
+use super::packet::ClientInfo;
 use sqlx::{PgPool, Row};
 use std::net::Ipv4Addr;
 use uuid::Uuid;
@@ -14,7 +16,8 @@ pub async fn fetch_all_subnets(db: &PgPool) -> Result<Vec<DhcpSubnet>> {
         SELECT
             id, name, network, start_ip, end_ip, gateway,
             dns_servers, domain_name, lease_duration, vlan_id,
-            ipv6_prefix, enabled, description, created_at, updated_at
+            ipv6_prefix, enabled, maintenance, relay_circuit_id, server_ip, boot_server, boot_filename,
+            boot_rules, excluded_ranges, dhcp_options, description, created_at, updated_at
         FROM dhcp_subnets
         WHERE enabled = true
         "#
@@ -22,29 +25,59 @@ pub async fn fetch_all_subnets(db: &PgPool) -> Result<Vec<DhcpSubnet>> {
     .fetch_all(db)
     .await?;
 
-    let mut subnets = Vec::new();
-    for row in rows {
-        let subnet = DhcpSubnet {
-            id: row.get("id"),
-            name: row.get("name"),
-            network: row.get("network"),
-            start_ip: row.get::<std::net::IpAddr, _>("start_ip").to_string().parse()?,
-            end_ip: row.get::<std::net::IpAddr, _>("end_ip").to_string().parse()?,
-            gateway: row.get::<std::net::IpAddr, _>("gateway").to_string().parse()?,
-            dns_servers: serde_json::from_value(row.get("dns_servers"))?,
-            domain_name: row.get("domain_name"),
-            lease_duration: row.get("lease_duration"),
-            vlan_id: row.get("vlan_id"),
-            ipv6_prefix: row.get("ipv6_prefix"),
-            enabled: row.get("enabled"),
-            description: row.get("description"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        };
-        subnets.push(subnet);
-    }
+    rows.iter().map(subnet_from_row).collect()
+}
+
+/// Looks up a subnet by id regardless of whether it is enabled
+pub async fn fetch_subnet(db: &PgPool, subnet_id: Uuid) -> Result<Option<DhcpSubnet>> {
+    let row = sqlx::query(
+        r#"
+        SELECT
+            id, name, network, start_ip, end_ip, gateway,
+            dns_servers, domain_name, lease_duration, vlan_id,
+            ipv6_prefix, enabled, maintenance, relay_circuit_id, server_ip, boot_server, boot_filename,
+            boot_rules, excluded_ranges, dhcp_options, description, created_at, updated_at
+        FROM dhcp_subnets
+        WHERE id = $1
+        "#
+    )
+    .bind(subnet_id)
+    .fetch_optional(db)
+    .await?;
+
+    row.as_ref().map(subnet_from_row).transpose()
+}
 
-    Ok(subnets)
+pub fn subnet_from_row(row: &sqlx::postgres::PgRow) -> Result<DhcpSubnet> {
+    Ok(DhcpSubnet {
+        id: row.get("id"),
+        name: row.get("name"),
+        network: row.get("network"),
+        start_ip: row.get::<std::net::IpAddr, _>("start_ip").to_string().parse()?,
+        end_ip: row.get::<std::net::IpAddr, _>("end_ip").to_string().parse()?,
+        gateway: row.get::<std::net::IpAddr, _>("gateway").to_string().parse()?,
+        dns_servers: serde_json::from_value(row.get("dns_servers"))?,
+        domain_name: row.get("domain_name"),
+        lease_duration: row.get("lease_duration"),
+        vlan_id: row.get("vlan_id"),
+        ipv6_prefix: row.get("ipv6_prefix"),
+        enabled: row.get("enabled"),
+        maintenance: row.get("maintenance"),
+        relay_circuit_id: row.get("relay_circuit_id"),
+        server_ip: row.get::<Option<std::net::IpAddr>, _>("server_ip")
+            .map(|ip| ip.to_string().parse())
+            .transpose()?,
+        boot_server: row.get::<Option<std::net::IpAddr>, _>("boot_server")
+            .map(|ip| ip.to_string().parse())
+            .transpose()?,
+        boot_filename: row.get("boot_filename"),
+        boot_rules: serde_json::from_value(row.get("boot_rules"))?,
+        excluded_ranges: serde_json::from_value(row.get("excluded_ranges"))?,
+        dhcp_options: serde_json::from_value(row.get("dhcp_options"))?,
+        description: row.get("description"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
 }
 
 pub async fn count_active_leases(db: &PgPool, subnet_id: Uuid, ip: Ipv4Addr) -> Result<i64> {
@@ -82,32 +115,161 @@ pub async fn count_reservations(db: &PgPool, subnet_id: Uuid, ip: Ipv4Addr) -> R
     Ok(row.get("count"))
 }
 
+/// Addresses reserved in the subnet, whichever MAC they belong to
+pub async fn fetch_reserved_addresses(db: &PgPool, subnet_id: Uuid) -> Result<Vec<Ipv4Addr>> {
+    let rows = sqlx::query("SELECT ip_address FROM dhcp_reservations WHERE subnet_id = $1")
+        .bind(subnet_id)
+        .fetch_all(db)
+        .await?;
+
+    Ok(rows.iter()
+        .filter_map(|row| match row.get::<std::net::IpAddr, _>("ip_address") {
+            std::net::IpAddr::V4(ip) => Some(ip),
+            std::net::IpAddr::V6(_) => None,
+        })
+        .collect())
+}
+
+/// Every address in the subnet held by an active lease or a reservation. Declines expire on
+/// their own, so allocation checks them per address instead.
+pub async fn fetch_used_addresses(db: &PgPool, subnet_id: Uuid) -> Result<Vec<Ipv4Addr>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT ip_address FROM dhcp_leases
+        WHERE subnet_id = $1 AND state = 'active' AND lease_end > NOW()
+        UNION
+        SELECT ip_address FROM dhcp_reservations
+        WHERE subnet_id = $1
+        "#
+    )
+    .bind(subnet_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.iter()
+        .filter_map(|row| match row.get::<std::net::IpAddr, _>("ip_address") {
+            std::net::IpAddr::V4(ip) => Some(ip),
+            std::net::IpAddr::V6(_) => None,
+        })
+        .collect())
+}
+
+/// Marks `ip` as declined, restarting the decline window if it was already declined
+pub async fn record_decline(
+    db: &PgPool,
+    subnet_id: Uuid,
+    ip: Ipv4Addr,
+    mac_address: Option<&[u8]>,
+    declined_at: DateTime<Utc>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO declined_addresses (subnet_id, ip_address, mac_address, declined_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (subnet_id, ip_address)
+        DO UPDATE SET mac_address = EXCLUDED.mac_address, declined_at = EXCLUDED.declined_at
+        "#
+    )
+    .bind(subnet_id)
+    .bind(std::net::IpAddr::V4(ip))
+    .bind(mac_address)
+    .bind(declined_at)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn count_declines_since(db: &PgPool, subnet_id: Uuid, ip: Ipv4Addr, since: DateTime<Utc>) -> Result<i64> {
+    let row = sqlx::query(
+        r#"
+        SELECT COUNT(*) as count
+        FROM declined_addresses
+        WHERE subnet_id = $1 AND ip_address = $2 AND declined_at > $3
+        "#
+    )
+    .bind(subnet_id)
+    .bind(std::net::IpAddr::V4(ip))
+    .bind(since)
+    .fetch_one(db)
+    .await?;
+
+    Ok(row.get("count"))
+}
+
+pub async fn delete_declines_before(db: &PgPool, cutoff: DateTime<Utc>) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM declined_addresses WHERE declined_at <= $1")
+        .bind(cutoff)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Gives `mac_address` an active lease on `ip_address`. A client has at most one lease per
+/// subnet: an existing lease in the same subnet is updated in place, while leases the client
+/// holds in other subnets are left alone.
+#[allow(clippy::too_many_arguments)]
 pub async fn insert_or_update_lease(
     db: &PgPool,
     subnet_id: Uuid,
     mac_address: &[u8],
     ip_address: Ipv4Addr,
     hostname: Option<String>,
+    client: ClientInfo,
     lease_start: DateTime<Utc>,
     lease_end: DateTime<Utc>,
+    source: LeaseEventSource,
 ) -> Result<DhcpLease> {
+    let mut tx = db.begin().await?;
+
+    // A lapsed lease that expire_old_leases hasn't reached yet would otherwise hold the
+    // address in the active-address unique index
+    sqlx::query(
+        r#"
+        WITH expired AS (
+            UPDATE dhcp_leases
+            SET state = 'expired', updated_at = NOW()
+            WHERE subnet_id = $1 AND ip_address = $2 AND mac_address <> $3
+                AND state = 'active' AND lease_end <= NOW()
+            RETURNING id, mac_address, ip_address
+        )
+        INSERT INTO lease_events (lease_id, mac_address, ip_address, old_state, new_state, source)
+        SELECT id, mac_address, ip_address, 'active', 'expired', $4
+        FROM expired
+        "#
+    )
+    .bind(subnet_id)
+    .bind(std::net::IpAddr::V4(ip_address))
+    .bind(mac_address)
+    .bind(LeaseEventSource::Dhcp.as_str())
+    .execute(&mut *tx)
+    .await?;
+
+    // The CTE reads the row as it was before the upsert, so the event knows the old state
     let row = sqlx::query(
         r#"
+        WITH previous AS (
+            SELECT state FROM dhcp_leases WHERE subnet_id = $1 AND mac_address = $2
+        )
         INSERT INTO dhcp_leases (
             subnet_id, mac_address, ip_address, hostname,
-            lease_start, lease_end, state
+            lease_start, lease_end, state,
+            client_identifier, vendor_class, user_class
         )
-        VALUES ($1, $2, $3, $4, $5, $6, 'active')
-        ON CONFLICT (mac_address)
+        VALUES ($1, $2, $3, $4, $5, $6, 'active', $7, $8, $9)
+        ON CONFLICT (subnet_id, mac_address)
         DO UPDATE SET
-            subnet_id = $1,
             ip_address = $3,
             lease_start = $5,
             lease_end = $6,
             state = 'active',
             hostname = $4,
+            client_identifier = $7,
+            vendor_class = $8,
+            user_class = $9,
             updated_at = NOW()
-        RETURNING *
+        RETURNING *, (SELECT state FROM previous) as previous_state
         "#
     )
     .bind(subnet_id)
@@ -116,9 +278,26 @@ pub async fn insert_or_update_lease(
     .bind(hostname)
     .bind(lease_start)
     .bind(lease_end)
-    .fetch_one(db)
+    .bind(client.client_identifier)
+    .bind(client.vendor_class)
+    .bind(client.user_class)
+    .fetch_one(&mut *tx)
     .await?;
 
+    let previous_state: Option<String> = row.get("previous_state");
+    lease_events::record_event(
+        &mut tx,
+        row.get("id"),
+        mac_address,
+        ip_address,
+        previous_state.as_deref(),
+        "active",
+        source,
+    )
+    .await?;
+
+    tx.commit().await?;
+
     Ok(DhcpLease {
         id: row.get("id"),
         subnet_id: row.get("subnet_id"),
@@ -136,6 +315,15 @@ pub async fn insert_or_update_lease(
     })
 }
 
+/// True when another client was given the same address first; the active-address unique
+/// index rejects the second insert
+pub fn is_address_conflict(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<sqlx::Error>()
+        .and_then(|e| e.as_database_error())
+        .map(|e| e.is_unique_violation())
+        .unwrap_or(false)
+}
+
 pub async fn find_active_lease_by_mac_and_ip(
     db: &PgPool,
     mac_address: &[u8],
@@ -175,7 +363,10 @@ pub async fn find_active_lease_by_mac_and_ip(
     }
 }
 
+/// Extends a lease on renewal; recorded as an active -> active event
 pub async fn update_lease_end(db: &PgPool, lease_id: Uuid, new_lease_end: DateTime<Utc>) -> Result<DhcpLease> {
+    let mut tx = db.begin().await?;
+
     let row = sqlx::query(
         r#"
         UPDATE dhcp_leases
@@ -186,9 +377,103 @@ pub async fn update_lease_end(db: &PgPool, lease_id: Uuid, new_lease_end: DateTi
     )
     .bind(new_lease_end)
     .bind(lease_id)
-    .fetch_one(db)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let mac_address: Vec<u8> = row.get("mac_address");
+    let ip_address: Ipv4Addr = row.get::<std::net::IpAddr, _>("ip_address").to_string().parse()?;
+
+    lease_events::record_event(
+        &mut tx,
+        lease_id,
+        &mac_address,
+        ip_address,
+        Some("active"),
+        "active",
+        LeaseEventSource::Dhcp,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(DhcpLease {
+        id: row.get("id"),
+        subnet_id: row.get("subnet_id"),
+        mac_address,
+        ip_address,
+        hostname: row.get("hostname"),
+        lease_start: row.get("lease_start"),
+        lease_end: row.get("lease_end"),
+        state: row.get("state"),
+        client_identifier: row.get("client_identifier"),
+        vendor_class: row.get("vendor_class"),
+        user_class: row.get("user_class"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+/// Ends an active lease now, recorded as an active -> expired event. `None` when the lease
+/// doesn't exist or isn't active.
+pub async fn expire_lease(db: &PgPool, lease_id: Uuid, source: LeaseEventSource) -> Result<Option<DhcpLease>> {
+    let mut tx = db.begin().await?;
+
+    let row = sqlx::query(
+        r#"
+        UPDATE dhcp_leases
+        SET state = 'expired', lease_end = LEAST(lease_end, NOW()), updated_at = NOW()
+        WHERE id = $1 AND state = 'active'
+        RETURNING *
+        "#
+    )
+    .bind(lease_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let lease = lease_from_row(&row)?;
+
+    lease_events::record_event(&mut tx, lease.id, &lease.mac_address, lease.ip_address, Some("active"), "expired", source)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(lease))
+}
+
+/// Pushes the end of an active lease `seconds` further out, counting from now if it has
+/// already passed. Recorded as an active -> active event; `None` when the lease doesn't exist
+/// or isn't active.
+pub async fn extend_lease(db: &PgPool, lease_id: Uuid, seconds: u32, source: LeaseEventSource) -> Result<Option<DhcpLease>> {
+    let mut tx = db.begin().await?;
+
+    let row = sqlx::query(
+        r#"
+        UPDATE dhcp_leases
+        SET lease_end = GREATEST(lease_end, NOW()) + make_interval(secs => $2), updated_at = NOW()
+        WHERE id = $1 AND state = 'active'
+        RETURNING *
+        "#
+    )
+    .bind(lease_id)
+    .bind(seconds as f64)
+    .fetch_optional(&mut *tx)
     .await?;
 
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let lease = lease_from_row(&row)?;
+
+    lease_events::record_event(&mut tx, lease.id, &lease.mac_address, lease.ip_address, Some("active"), "active", source)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(lease))
+}
+
+fn lease_from_row(row: &sqlx::postgres::PgRow) -> Result<DhcpLease> {
     Ok(DhcpLease {
         id: row.get("id"),
         subnet_id: row.get("subnet_id"),
@@ -209,15 +494,22 @@ pub async fn update_lease_end(db: &PgPool, lease_id: Uuid, new_lease_end: DateTi
 pub async fn release_lease(db: &PgPool, mac_address: &[u8], ip_address: Ipv4Addr) -> Result<bool> {
     let result = sqlx::query(
         r#"
-        UPDATE dhcp_leases
-        SET state = 'released', updated_at = NOW()
-        WHERE mac_address = $1
-            AND ip_address = $2
-            AND state = 'active'
+        WITH released AS (
+            UPDATE dhcp_leases
+            SET state = 'released', updated_at = NOW()
+            WHERE mac_address = $1
+                AND ip_address = $2
+                AND state = 'active'
+            RETURNING id, mac_address, ip_address
+        )
+        INSERT INTO lease_events (lease_id, mac_address, ip_address, old_state, new_state, source)
+        SELECT id, mac_address, ip_address, 'active', 'released', $3
+        FROM released
         "#
     )
     .bind(mac_address)
     .bind(std::net::IpAddr::V4(ip_address))
+    .bind(LeaseEventSource::Dhcp.as_str())
     .execute(db)
     .await?;
 
@@ -251,18 +543,21 @@ pub async fn get_reservation(db: &PgPool, subnet_id: Uuid, mac_address: &[u8]) -
     }
 }
 
-pub async fn get_active_lease_by_mac(db: &PgPool, mac_address: &[u8]) -> Result<Option<DhcpLease>> {
+/// The client's active lease in `subnet_id`; it may hold others in other subnets
+pub async fn get_active_lease_by_mac(db: &PgPool, subnet_id: Uuid, mac_address: &[u8]) -> Result<Option<DhcpLease>> {
     let row = sqlx::query(
         r#"
         SELECT *
         FROM dhcp_leases
-        WHERE mac_address = $1
+        WHERE subnet_id = $1
+            AND mac_address = $2
             AND state = 'active'
             AND lease_end > NOW()
         ORDER BY lease_end DESC
         LIMIT 1
         "#
     )
+    .bind(subnet_id)
     .bind(mac_address)
     .fetch_optional(db)
     .await?;
@@ -287,17 +582,27 @@ pub async fn get_active_lease_by_mac(db: &PgPool, mac_address: &[u8]) -> Result<
     }
 }
 
-pub async fn expire_old_leases(db: &PgPool) -> Result<u64> {
-    let result = sqlx::query(
+/// Marks every lapsed active lease expired, returning the leases it expired
+pub async fn expire_old_leases(db: &PgPool) -> Result<Vec<DhcpLease>> {
+    let rows = sqlx::query(
         r#"
-        UPDATE dhcp_leases
-        SET state = 'expired'
-        WHERE state = 'active'
-            AND lease_end < NOW()
+        WITH expired AS (
+            UPDATE dhcp_leases
+            SET state = 'expired'
+            WHERE state = 'active'
+                AND lease_end < NOW()
+            RETURNING *
+        ), logged AS (
+            INSERT INTO lease_events (lease_id, mac_address, ip_address, old_state, new_state, source)
+            SELECT id, mac_address, ip_address, 'active', 'expired', $1
+            FROM expired
+        )
+        SELECT * FROM expired
         "#
     )
-    .execute(db)
+    .bind(LeaseEventSource::Dhcp.as_str())
+    .fetch_all(db)
     .await?;
 
-    Ok(result.rows_affected())
+    rows.iter().map(lease_from_row).collect()
 }
\ No newline at end of file
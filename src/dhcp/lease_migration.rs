@@ -0,0 +1,513 @@
+// Export and import of a subnet's leases and reservations (CSV or JSON) for migrating
+// between FlowDNS instances or from dnsmasq/ISC
+use crate::dhcp::isc_import::parse_mac;
+use crate::dhcp::lease_events::{self, LeaseEventSource};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
+use uuid::Uuid;
+use anyhow::{Result, anyhow, bail};
+
+/// Column order of the CSV form; a header row is required on import but may order columns freely
+pub const CSV_COLUMNS: [&str; 8] = [
+    "kind",
+    "mac_address",
+    "ip_address",
+    "hostname",
+    "lease_start",
+    "lease_end",
+    "state",
+    "description",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Lease,
+    Reservation,
+}
+
+/// One lease or reservation. Leases need `lease_end`; `lease_start` defaults to the import time
+/// and `state` to "active". `description` only applies to reservations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationEntry {
+    pub kind: EntryKind,
+    pub mac_address: String,
+    pub ip_address: Ipv4Addr,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    #[serde(default)]
+    pub lease_start: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub lease_end: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct MigrationReport {
+    pub leases: usize,
+    pub reservations: usize,
+}
+
+pub enum ImportOutcome {
+    Imported(MigrationReport),
+    SubnetNotFound,
+    /// Entries that are invalid or clash with existing data; nothing was stored
+    Rejected(Vec<String>),
+}
+
+/// Reservations and unexpired leases of the subnet, reservations first
+pub async fn export_subnet(db: &PgPool, subnet_id: Uuid) -> Result<Vec<MigrationEntry>> {
+    let reservations = sqlx::query(
+        r#"
+        SELECT mac_address, ip_address, hostname, description
+        FROM dhcp_reservations
+        WHERE subnet_id = $1
+        ORDER BY ip_address
+        "#
+    )
+    .bind(subnet_id)
+    .fetch_all(db)
+    .await?;
+
+    let leases = sqlx::query(
+        r#"
+        SELECT mac_address, ip_address, hostname, lease_start, lease_end, state
+        FROM dhcp_leases
+        WHERE subnet_id = $1 AND state = 'active' AND lease_end > NOW()
+        ORDER BY ip_address
+        "#
+    )
+    .bind(subnet_id)
+    .fetch_all(db)
+    .await?;
+
+    let mut entries = Vec::with_capacity(reservations.len() + leases.len());
+    for row in &reservations {
+        entries.push(MigrationEntry {
+            kind: EntryKind::Reservation,
+            mac_address: format_mac(row.get("mac_address")),
+            ip_address: row_ipv4(row)?,
+            hostname: row.get("hostname"),
+            lease_start: None,
+            lease_end: None,
+            state: None,
+            description: row.get("description"),
+        });
+    }
+    for row in &leases {
+        entries.push(MigrationEntry {
+            kind: EntryKind::Lease,
+            mac_address: format_mac(row.get("mac_address")),
+            ip_address: row_ipv4(row)?,
+            hostname: row.get("hostname"),
+            lease_start: Some(row.get("lease_start")),
+            lease_end: Some(row.get("lease_end")),
+            state: row.get("state"),
+            description: None,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Checks every entry against the subnet and the existing data, then inserts them all in one
+/// transaction. Any problem rejects the whole batch with one message per bad entry.
+pub async fn import_subnet(db: &PgPool, subnet_id: Uuid, entries: &[MigrationEntry]) -> Result<ImportOutcome> {
+    let mut tx = db.begin().await?;
+
+    let subnet = sqlx::query("SELECT network FROM dhcp_subnets WHERE id = $1 FOR UPDATE")
+        .bind(subnet_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(subnet) = subnet else {
+        return Ok(ImportOutcome::SubnetNotFound);
+    };
+    let network: ipnetwork::IpNetwork = subnet.get("network");
+
+    let mut problems = Vec::new();
+    let mut valid = Vec::with_capacity(entries.len());
+    let mut seen = HashSet::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let label = format!("entry {} ({} {})", index + 1, entry.mac_address, entry.ip_address);
+        let mac = match check_entry(entry, network) {
+            Ok(mac) => mac,
+            Err(e) => {
+                problems.push(format!("{}: {}", label, e));
+                continue;
+            }
+        };
+
+        if !seen.insert((entry.kind, mac.clone())) || !seen.insert((entry.kind, entry.ip_address.octets().to_vec())) {
+            problems.push(format!("{}: duplicates an earlier entry", label));
+            continue;
+        }
+
+        if let Some(clash) = existing_clash(&mut tx, subnet_id, entry, &mac).await? {
+            problems.push(format!("{}: {}", label, clash));
+            continue;
+        }
+
+        valid.push((entry, mac));
+    }
+
+    if !problems.is_empty() {
+        return Ok(ImportOutcome::Rejected(problems));
+    }
+
+    let mut report = MigrationReport::default();
+    let now = Utc::now();
+
+    for (entry, mac) in valid {
+        let ip = IpAddr::V4(entry.ip_address);
+        match entry.kind {
+            EntryKind::Reservation => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO dhcp_reservations (subnet_id, mac_address, ip_address, hostname, description)
+                    VALUES ($1, $2, $3, $4, $5)
+                    "#
+                )
+                .bind(subnet_id)
+                .bind(&mac)
+                .bind(ip)
+                .bind(&entry.hostname)
+                .bind(&entry.description)
+                .execute(&mut *tx)
+                .await?;
+                report.reservations += 1;
+            }
+            EntryKind::Lease => {
+                let state = entry.state.as_deref().unwrap_or("active");
+                let row = sqlx::query(
+                    r#"
+                    INSERT INTO dhcp_leases (subnet_id, mac_address, ip_address, hostname,
+                                             lease_start, lease_end, state)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7)
+                    RETURNING id
+                    "#
+                )
+                .bind(subnet_id)
+                .bind(&mac)
+                .bind(ip)
+                .bind(&entry.hostname)
+                .bind(entry.lease_start.unwrap_or(now))
+                .bind(entry.lease_end)
+                .bind(state)
+                .fetch_one(&mut *tx)
+                .await?;
+
+                lease_events::record_event(
+                    &mut tx,
+                    row.get("id"),
+                    &mac,
+                    entry.ip_address,
+                    None,
+                    state,
+                    LeaseEventSource::Api,
+                )
+                .await?;
+                report.leases += 1;
+            }
+        }
+    }
+
+    tx.commit().await?;
+    Ok(ImportOutcome::Imported(report))
+}
+
+/// Validates the fields of one entry and returns its MAC address as bytes
+fn check_entry(entry: &MigrationEntry, network: ipnetwork::IpNetwork) -> Result<Vec<u8>> {
+    let mac = parse_mac(&entry.mac_address.replace('-', ":"))
+        .ok_or_else(|| anyhow!("invalid MAC address"))?;
+
+    if !network.contains(IpAddr::V4(entry.ip_address)) {
+        bail!("address is outside {}", network);
+    }
+
+    if entry.kind == EntryKind::Lease {
+        let end = entry.lease_end.ok_or_else(|| anyhow!("lease_end is required for leases"))?;
+        if entry.lease_start.is_some_and(|start| start > end) {
+            bail!("lease_start is after lease_end");
+        }
+        if let Some(state) = entry.state.as_deref() {
+            if !["active", "expired", "released"].contains(&state) {
+                bail!("unknown lease state {}", state);
+            }
+        }
+    }
+
+    Ok(mac)
+}
+
+/// Why the entry can't be stored next to the existing leases and reservations, if it can't
+async fn existing_clash(
+    conn: &mut sqlx::PgConnection,
+    subnet_id: Uuid,
+    entry: &MigrationEntry,
+    mac: &[u8],
+) -> Result<Option<String>> {
+    let ip = IpAddr::V4(entry.ip_address);
+    let query = match entry.kind {
+        // Reservation MACs are unique across all subnets, addresses within the subnet
+        EntryKind::Reservation => {
+            "SELECT 1 FROM dhcp_reservations WHERE mac_address = $1 OR (subnet_id = $2 AND ip_address = $3)"
+        }
+        // A client has at most one lease per subnet
+        EntryKind::Lease => {
+            "SELECT 1 FROM dhcp_leases WHERE subnet_id = $2 \
+             AND (mac_address = $1 OR (ip_address = $3 AND state = 'active' AND lease_end > NOW()))"
+        }
+    };
+
+    let clash = sqlx::query(query)
+        .bind(mac)
+        .bind(subnet_id)
+        .bind(ip)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    Ok(clash.map(|_| match entry.kind {
+        EntryKind::Reservation => "a reservation already exists for this MAC or address".to_string(),
+        EntryKind::Lease => "a lease already exists for this MAC or address".to_string(),
+    }))
+}
+
+/// Renders entries as CSV with a header row (RFC 4180 quoting)
+pub fn to_csv(entries: &[MigrationEntry]) -> String {
+    let mut out = CSV_COLUMNS.join(",");
+    out.push('\n');
+
+    for entry in entries {
+        let kind = match entry.kind {
+            EntryKind::Lease => "lease",
+            EntryKind::Reservation => "reservation",
+        };
+        let fields = [
+            kind.to_string(),
+            entry.mac_address.clone(),
+            entry.ip_address.to_string(),
+            entry.hostname.clone().unwrap_or_default(),
+            entry.lease_start.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            entry.lease_end.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            entry.state.clone().unwrap_or_default(),
+            entry.description.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Parses CSV with a header naming the columns in `CSV_COLUMNS`. `kind`, `mac_address` and
+/// `ip_address` are required; empty fields read as absent. Errors name the offending line.
+pub fn parse_csv(text: &str) -> Result<Vec<MigrationEntry>> {
+    let mut rows = csv_rows(text)?.into_iter();
+    let (_, header) = rows.next().ok_or_else(|| anyhow!("CSV is empty"))?;
+
+    let column = |name: &str| header.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    for name in &header {
+        if !CSV_COLUMNS.iter().any(|c| c.eq_ignore_ascii_case(name.trim())) {
+            bail!("line 1: unknown column {}", name);
+        }
+    }
+    let required = |name: &str| column(name).ok_or_else(|| anyhow!("line 1: missing column {}", name));
+    let (kind_col, mac_col, ip_col) = (required("kind")?, required("mac_address")?, required("ip_address")?);
+    let optional: Vec<Option<usize>> = CSV_COLUMNS[3..].iter().map(|name| column(name)).collect();
+
+    let mut entries = Vec::new();
+    for (line, fields) in rows {
+        if fields.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let get = |index: usize| fields.get(index).map(|f| f.trim()).unwrap_or_default();
+        let opt = |index: Option<usize>| index.map(get).filter(|f| !f.is_empty()).map(str::to_string);
+        let time = |index: Option<usize>, name: &str| {
+            opt(index)
+                .map(|value| {
+                    DateTime::parse_from_rfc3339(&value)
+                        .map(|t| t.with_timezone(&Utc))
+                        .map_err(|_| anyhow!("line {}: invalid {} {}", line, name, value))
+                })
+                .transpose()
+        };
+
+        let kind = match get(kind_col).to_ascii_lowercase().as_str() {
+            "lease" => EntryKind::Lease,
+            "reservation" => EntryKind::Reservation,
+            other => bail!("line {}: kind must be lease or reservation, not {:?}", line, other),
+        };
+        let ip_address = get(ip_col)
+            .parse()
+            .map_err(|_| anyhow!("line {}: invalid IPv4 address {:?}", line, get(ip_col)))?;
+
+        entries.push(MigrationEntry {
+            kind,
+            mac_address: get(mac_col).to_string(),
+            ip_address,
+            hostname: opt(optional[0]),
+            lease_start: time(optional[1], "lease_start")?,
+            lease_end: time(optional[2], "lease_end")?,
+            state: opt(optional[3]),
+            description: opt(optional[4]),
+        });
+    }
+
+    Ok(entries)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Splits CSV into rows of fields, each with the line number it starts on
+fn csv_rows(text: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut rows = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut row_line = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if in_quotes => in_quotes = false,
+            '"' if field.is_empty() => in_quotes = true,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                fields.push(std::mem::take(&mut field));
+                rows.push((row_line, std::mem::take(&mut fields)));
+                line += 1;
+                row_line = line;
+            }
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+
+    if in_quotes {
+        bail!("line {}: unterminated quoted field", row_line);
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        rows.push((row_line, fields));
+    }
+    Ok(rows)
+}
+
+fn format_mac(bytes: Vec<u8>) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+}
+
+fn row_ipv4(row: &sqlx::postgres::PgRow) -> Result<Ipv4Addr> {
+    match row.get::<IpAddr, _>("ip_address") {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(ip) => Err(anyhow!("unexpected IPv6 address {} in DHCPv4 table", ip)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn network() -> ipnetwork::IpNetwork {
+        "192.168.10.0/24".parse().unwrap()
+    }
+
+    fn lease(mac: &str, ip: Ipv4Addr) -> MigrationEntry {
+        MigrationEntry {
+            kind: EntryKind::Lease,
+            mac_address: mac.to_string(),
+            ip_address: ip,
+            hostname: Some("laptop".to_string()),
+            lease_start: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+            lease_end: Some(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap()),
+            state: Some("active".to_string()),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let entries = vec![
+            MigrationEntry {
+                kind: EntryKind::Reservation,
+                mac_address: "00:11:22:33:44:55".to_string(),
+                ip_address: Ipv4Addr::new(192, 168, 10, 5),
+                hostname: Some("printer".to_string()),
+                lease_start: None,
+                lease_end: None,
+                state: None,
+                description: Some("2nd floor, \"colour\"".to_string()),
+            },
+            lease("00:11:22:33:44:66", Ipv4Addr::new(192, 168, 10, 100)),
+        ];
+
+        let csv = to_csv(&entries);
+        assert!(csv.starts_with("kind,mac_address,ip_address,hostname,lease_start,lease_end,state,description\n"));
+        assert!(csv.contains("\"2nd floor, \"\"colour\"\"\""));
+        assert_eq!(parse_csv(&csv).unwrap(), entries);
+    }
+
+    #[test]
+    fn test_parse_csv_accepts_reordered_and_missing_columns() {
+        let csv = "ip_address,mac_address,kind\r\n192.168.10.7,AA-BB-CC-DD-EE-FF,Reservation\r\n\r\n";
+        let entries = parse_csv(csv).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kind, EntryKind::Reservation);
+        assert_eq!(entries[0].mac_address, "AA-BB-CC-DD-EE-FF");
+        assert_eq!(entries[0].hostname, None);
+        assert_eq!(check_entry(&entries[0], network()).unwrap(), vec![0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+    }
+
+    #[test]
+    fn test_parse_csv_reports_line_numbers() {
+        let error = |csv: &str| parse_csv(csv).unwrap_err().to_string();
+
+        assert!(error("kind,mac_address\n").contains("missing column ip_address"));
+        assert!(error("kind,mac_address,ip_address,colour\n").contains("unknown column colour"));
+        assert!(error("kind,mac_address,ip_address\nlease,00:11:22:33:44:55,10.0.0.1\nhost,x,10.0.0.2\n").starts_with("line 3:"));
+        assert!(error("kind,mac_address,ip_address,lease_end\nlease,00:11:22:33:44:55,10.0.0.1,tomorrow\n").contains("invalid lease_end"));
+        assert!(error("kind,mac_address,ip_address,description\nreservation,00:11:22:33:44:55,10.0.0.1,\"open\n").contains("unterminated"));
+    }
+
+    #[test]
+    fn test_check_entry_validates_fields() {
+        let ip = Ipv4Addr::new(192, 168, 10, 100);
+        assert!(check_entry(&lease("00:11:22:33:44:55", ip), network()).is_ok());
+        assert!(check_entry(&lease("00:11:22:33:44", ip), network()).is_err());
+        assert!(check_entry(&lease("00:11:22:33:44:55", Ipv4Addr::new(10, 0, 0, 1)), network()).is_err());
+
+        let mut open_ended = lease("00:11:22:33:44:55", ip);
+        open_ended.lease_end = None;
+        assert!(check_entry(&open_ended, network()).is_err());
+
+        let mut backwards = lease("00:11:22:33:44:55", ip);
+        backwards.lease_start = Some(Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap());
+        assert!(check_entry(&backwards, network()).is_err());
+    }
+}
@@ -1,5 +1,11 @@
+pub mod allocation;
 pub mod packet;
 pub mod server;
+pub mod socket;
 pub mod lease_manager;
 pub mod lease_manager_queries;
-pub mod options;
\ No newline at end of file
+pub mod lease_events;
+pub mod options;
+pub mod ping_check;
+pub mod isc_import;
+pub mod lease_migration;
@@ -9,6 +9,7 @@ pub const OPTION_DOMAIN_NAME: u8 = 15;
 pub const OPTION_BROADCAST: u8 = 28;
 pub const OPTION_REQUESTED_IP: u8 = 50;
 pub const OPTION_LEASE_TIME: u8 = 51;
+pub const OPTION_OVERLOAD: u8 = 52;
 pub const OPTION_MESSAGE_TYPE: u8 = 53;
 pub const OPTION_SERVER_ID: u8 = 54;
 pub const OPTION_PARAMETER_LIST: u8 = 55;
@@ -18,7 +19,11 @@ pub const OPTION_RENEWAL_TIME: u8 = 58;
 pub const OPTION_REBIND_TIME: u8 = 59;
 pub const OPTION_VENDOR_CLASS: u8 = 60;
 pub const OPTION_CLIENT_ID: u8 = 61;
+pub const OPTION_TFTP_SERVER: u8 = 66;
+pub const OPTION_BOOTFILE: u8 = 67;
 pub const OPTION_USER_CLASS: u8 = 77;
+pub const OPTION_CLIENT_FQDN: u8 = 81;
+pub const OPTION_CLIENT_ARCH: u8 = 93;
 
 pub struct DhcpOptionsBuilder {
     options: Vec<DhcpOption>,
@@ -1,6 +1,11 @@
 use std::net::Ipv4Addr;
+use crate::dhcp::options::{
+    OPTION_CLIENT_ARCH, OPTION_CLIENT_FQDN, OPTION_CLIENT_ID, OPTION_MAX_MESSAGE_SIZE, OPTION_MESSAGE_TYPE,
+    OPTION_OVERLOAD, OPTION_USER_CLASS, OPTION_VENDOR_CLASS,
+};
 use anyhow::{anyhow, Result};
 use bytes::{BytesMut, BufMut};
+use tracing::warn;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DhcpMessageType {
@@ -57,6 +62,181 @@ pub struct DhcpOption {
     pub data: Vec<u8>,
 }
 
+pub const OPTION_RELAY_AGENT_INFO: u8 = 82;
+
+/// Largest message every client must accept (RFC 2131 section 2), IP and UDP headers included
+pub const MIN_MESSAGE_SIZE: usize = 576;
+const IP_UDP_HEADER_SIZE: usize = 28;
+
+// Values of the option overload option: which BOOTP fields hold options too
+const OVERLOAD_FILE: u8 = 1;
+const OVERLOAD_SNAME: u8 = 2;
+
+/// What a client reports about itself, stored on its lease
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientInfo {
+    pub client_identifier: Option<String>,
+    pub vendor_class: Option<String>,
+    pub user_class: Option<String>,
+}
+
+/// Option 82 as inserted by a relay agent (RFC 3046)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RelayAgentInfo {
+    /// Sub-option 1: identifies the port or VLAN the request arrived on
+    pub circuit_id: Option<Vec<u8>>,
+    /// Sub-option 2: identifies the relay agent itself
+    pub remote_id: Option<Vec<u8>>,
+}
+
+impl RelayAgentInfo {
+    pub fn parse(data: &[u8]) -> Self {
+        let mut info = Self::default();
+        let mut i = 0;
+
+        while i + 2 <= data.len() {
+            let code = data[i];
+            let len = data[i + 1] as usize;
+            let Some(value) = data.get(i + 2..i + 2 + len) else {
+                break;
+            };
+            match code {
+                1 => info.circuit_id = Some(value.to_vec()),
+                2 => info.remote_id = Some(value.to_vec()),
+                _ => {}
+            }
+            i += 2 + len;
+        }
+
+        info
+    }
+
+    /// Circuit-id as configured on a subnet: the text itself when it is printable ASCII, hex otherwise
+    pub fn circuit_id_text(&self) -> Option<String> {
+        self.circuit_id.as_deref().map(printable_or_hex)
+    }
+}
+
+/// Option 81 (RFC 4702): the name a client wants registered and who should update DNS for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientFqdn {
+    /// S flag: the client wants the server to update the A record
+    pub server_updates_forward: bool,
+    /// N flag: the client wants the server to make no DNS updates at all
+    pub no_server_updates: bool,
+    /// E flag: the name is in DNS wire format rather than ASCII
+    pub wire_encoded: bool,
+    /// Without a trailing dot; may be a bare host name the server qualifies
+    pub name: String,
+}
+
+const FQDN_FLAG_S: u8 = 0x01;
+const FQDN_FLAG_E: u8 = 0x04;
+const FQDN_FLAG_N: u8 = 0x08;
+
+impl ClientFqdn {
+    /// Flags, two deprecated rcode bytes, then the name
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let (&flags, rest) = data.split_first()?;
+        let name = rest.get(2..)?;
+        let wire_encoded = flags & FQDN_FLAG_E != 0;
+
+        let name = if wire_encoded {
+            decode_wire_name(name)?
+        } else {
+            String::from_utf8(name.to_vec()).ok()?
+        };
+
+        Some(Self {
+            server_updates_forward: flags & FQDN_FLAG_S != 0,
+            no_server_updates: flags & FQDN_FLAG_N != 0,
+            wire_encoded,
+            name: name.trim_end_matches('.').to_string(),
+        })
+    }
+
+    /// The option for the server's reply, telling the client what the server will update for
+    /// `name`. The client's flags are honoured as sent, so the O (override) flag is never set.
+    pub fn reply_data(&self, name: &str) -> Vec<u8> {
+        let mut flags = 0;
+        if self.no_server_updates {
+            flags |= FQDN_FLAG_N;
+        } else if self.server_updates_forward {
+            flags |= FQDN_FLAG_S;
+        }
+        if self.wire_encoded {
+            flags |= FQDN_FLAG_E;
+        }
+
+        // Servers send 255 in both rcode fields (RFC 4702 section 4)
+        let mut data = vec![flags, 255, 255];
+        if self.wire_encoded {
+            data.extend(encode_wire_name(name));
+        } else {
+            data.extend_from_slice(name.as_bytes());
+        }
+        data
+    }
+}
+
+/// Length-prefixed labels; a partial name simply ends without the root label
+pub(crate) fn decode_wire_name(data: &[u8]) -> Option<String> {
+    let mut labels = Vec::new();
+    let mut i = 0;
+
+    while let Some(&len) = data.get(i) {
+        if len == 0 {
+            break;
+        }
+        if len > 63 {
+            return None;
+        }
+        let label = data.get(i + 1..i + 1 + len as usize)?;
+        labels.push(std::str::from_utf8(label).ok()?);
+        i += 1 + len as usize;
+    }
+
+    Some(labels.join("."))
+}
+
+/// `name` as length-prefixed labels ending in the root label, overlong labels truncated
+pub(crate) fn encode_wire_name(name: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    for label in name.trim_end_matches('.').split('.').filter(|label| !label.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        data.push(label.len() as u8);
+        data.extend_from_slice(label);
+    }
+    data.push(0);
+    data
+}
+
+/// RFC 3004 user class data is a list of length-prefixed instances. Some clients (Windows among
+/// them) send a bare string instead, so anything that doesn't split cleanly is taken whole.
+fn user_class_text(data: &[u8]) -> String {
+    let mut classes = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let len = data[i] as usize;
+        match data.get(i + 1..i + 1 + len) {
+            Some(class) if len > 0 => classes.push(printable_or_hex(class)),
+            _ => return printable_or_hex(data),
+        }
+        i += 1 + len;
+    }
+
+    classes.join(",")
+}
+
+fn printable_or_hex(bytes: &[u8]) -> String {
+    if !bytes.is_empty() && bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        String::from_utf8_lossy(bytes).into_owned()
+    } else {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
 impl DhcpPacket {
     const MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
     const MIN_PACKET_SIZE: usize = 236;
@@ -100,24 +280,39 @@ impl DhcpPacket {
         packet.siaddr = Ipv4Addr::from([data[20], data[21], data[22], data[23]]);
         packet.giaddr = Ipv4Addr::from([data[24], data[25], data[26], data[27]]);
 
+        // All within MIN_PACKET_SIZE, checked above
         packet.chaddr.copy_from_slice(&data[28..44]);
         packet.sname.copy_from_slice(&data[44..108]);
         packet.file.copy_from_slice(&data[108..236]);
 
         // Parse options if present
-        if data.len() > 240 && &data[236..240] == &Self::MAGIC_COOKIE {
+        if data.get(236..240) == Some(&Self::MAGIC_COOKIE[..]) {
             packet.options = Self::parse_options(&data[240..])?;
         }
 
+        // Option overload: the file field, then sname, carry more options (RFC 2131 section 4.1)
+        let overload = packet.get_option(OPTION_OVERLOAD).and_then(|opt| opt.data.first().copied());
+        if let Some(overload) = overload {
+            if overload & OVERLOAD_FILE != 0 {
+                let more = Self::parse_options(&packet.file)?;
+                packet.options.extend(more);
+            }
+            if overload & OVERLOAD_SNAME != 0 {
+                let more = Self::parse_options(&packet.sname)?;
+                packet.options.extend(more);
+            }
+        }
+
         Ok(packet)
     }
 
+    /// Options up to the END option or the end of the packet. An option whose length byte is
+    /// missing or runs past the packet makes the whole packet malformed.
     fn parse_options(data: &[u8]) -> Result<Vec<DhcpOption>> {
         let mut options = Vec::new();
         let mut i = 0;
 
-        while i < data.len() {
-            let code = data[i];
+        while let Some(&code) = data.get(i) {
             i += 1;
 
             if code == 255 {
@@ -128,18 +323,15 @@ impl DhcpPacket {
                 continue;
             }
 
-            if i >= data.len() {
-                break;
-            }
-
-            let len = data[i] as usize;
+            let len = *data.get(i)
+                .ok_or_else(|| anyhow!("DHCP option {} has no length", code))? as usize;
             i += 1;
 
-            if i + len > data.len() {
-                break;
-            }
-
-            let option_data = data[i..i + len].to_vec();
+            let option_data = data.get(i..i + len)
+                .ok_or_else(|| anyhow!(
+                    "DHCP option {} claims {} bytes but only {} remain", code, len, data.len() - i
+                ))?
+                .to_vec();
             i += len;
 
             options.push(DhcpOption {
@@ -152,6 +344,92 @@ impl DhcpPacket {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
+        let options: Vec<&DhcpOption> = self.options.iter().collect();
+        self.encode(&options, &self.sname, &self.file)
+    }
+
+    /// Serializes the packet to fit a client that accepts `max_message_size` bytes (option 57),
+    /// or 576 when it doesn't say. Options that don't fit the options field move into the
+    /// `file` and then `sname` fields with option overload, as long as those are unused; any
+    /// that still don't fit are dropped. The message type and relay agent information always
+    /// stay in the options field.
+    pub fn to_bytes_within(&self, max_message_size: Option<u16>) -> Vec<u8> {
+        let max_message_size = max_message_size.map_or(MIN_MESSAGE_SIZE, usize::from).max(MIN_MESSAGE_SIZE);
+        let limit = max_message_size - IP_UDP_HEADER_SIZE;
+        let options_len: usize = self.options.iter().map(|opt| 2 + opt.data.len()).sum();
+        // Options plus END after the header and magic cookie
+        if 240 + options_len < limit {
+            return self.to_bytes();
+        }
+
+        let stays = |opt: &DhcpOption| opt.code == OPTION_MESSAGE_TYPE || opt.code == OPTION_RELAY_AGENT_INFO;
+        let unused = |field: &[u8]| field.iter().all(|&b| b == 0);
+
+        // Room left in each area once END, and in the options field the overload option and
+        // the options that have to stay there, are accounted for
+        let pinned: usize = self.options.iter().filter(|opt| stays(opt)).map(|opt| 2 + opt.data.len()).sum();
+        let mut main_free = limit.saturating_sub(240 + 3 + 1 + pinned);
+        let mut file_free = if unused(&self.file[..]) { self.file.len() - 1 } else { 0 };
+        let mut sname_free = if unused(&self.sname[..]) { self.sname.len() - 1 } else { 0 };
+
+        let (mut main, mut file, mut sname) = (Vec::new(), Vec::new(), Vec::new());
+        for option in &self.options {
+            let len = 2 + option.data.len();
+            if stays(option) {
+                main.push(option);
+            } else if len <= main_free {
+                main_free -= len;
+                main.push(option);
+            } else if len <= file_free {
+                file_free -= len;
+                file.push(option);
+            } else if len <= sname_free {
+                sname_free -= len;
+                sname.push(option);
+            } else {
+                warn!("Dropping DHCP option {} ({} bytes) that doesn't fit a {}-byte message",
+                      option.code, option.data.len(), max_message_size);
+            }
+        }
+
+        let mut overload = 0;
+        if !file.is_empty() {
+            overload |= OVERLOAD_FILE;
+        }
+        if !sname.is_empty() {
+            overload |= OVERLOAD_SNAME;
+        }
+        if overload == 0 {
+            return self.encode(&main, &self.sname, &self.file);
+        }
+
+        // Relay agent information has to remain the last option in the options field
+        let overload_option = DhcpOption { code: OPTION_OVERLOAD, data: vec![overload] };
+        let position = main.iter()
+            .position(|opt| opt.code == OPTION_RELAY_AGENT_INFO)
+            .unwrap_or(main.len());
+        main.insert(position, &overload_option);
+
+        let file = if file.is_empty() { self.file } else { Self::overload_field(&file) };
+        let sname = if sname.is_empty() { self.sname } else { Self::overload_field(&sname) };
+        self.encode(&main, &sname, &file)
+    }
+
+    /// A `file` or `sname` field holding `options`, END and padding
+    fn overload_field<const N: usize>(options: &[&DhcpOption]) -> [u8; N] {
+        let mut field = [0; N];
+        let mut i = 0;
+        for option in options {
+            field[i] = option.code;
+            field[i + 1] = option.data.len() as u8;
+            field[i + 2..i + 2 + option.data.len()].copy_from_slice(&option.data);
+            i += 2 + option.data.len();
+        }
+        field[i] = 255;
+        field
+    }
+
+    fn encode(&self, options: &[&DhcpOption], sname: &[u8; 64], file: &[u8; 128]) -> Vec<u8> {
         let mut buffer = BytesMut::with_capacity(576);
 
         buffer.put_u8(self.op);
@@ -166,14 +444,14 @@ impl DhcpPacket {
         buffer.put_slice(&self.siaddr.octets());
         buffer.put_slice(&self.giaddr.octets());
         buffer.put_slice(&self.chaddr);
-        buffer.put_slice(&self.sname);
-        buffer.put_slice(&self.file);
+        buffer.put_slice(sname);
+        buffer.put_slice(file);
 
         // Magic cookie
         buffer.put_slice(&Self::MAGIC_COOKIE);
 
         // Options
-        for option in &self.options {
+        for option in options {
             buffer.put_u8(option.code);
             buffer.put_u8(option.data.len() as u8);
             buffer.put_slice(&option.data);
@@ -216,6 +494,13 @@ impl DhcpPacket {
             .map(|opt| Ipv4Addr::from([opt.data[0], opt.data[1], opt.data[2], opt.data[3]]))
     }
 
+    /// Option 57: the largest message the client accepts, IP and UDP headers included
+    pub fn get_max_message_size(&self) -> Option<u16> {
+        self.get_option(OPTION_MAX_MESSAGE_SIZE)
+            .and_then(|opt| opt.data.get(..2))
+            .map(|size| u16::from_be_bytes([size[0], size[1]]))
+    }
+
     pub fn set_requested_ip(&mut self, ip: Ipv4Addr) {
         self.set_option(50, ip.octets().to_vec());
     }
@@ -249,6 +534,73 @@ impl DhcpPacket {
         self.set_option(51, seconds.to_be_bytes().to_vec());
     }
 
+    pub fn get_relay_agent_info(&self) -> Option<RelayAgentInfo> {
+        self.get_option(OPTION_RELAY_AGENT_INFO)
+            .map(|opt| RelayAgentInfo::parse(&opt.data))
+    }
+
+    /// Option 60, e.g. "MSFT 5.0" or "PXEClient:Arch:00007:UNDI:003016"
+    pub fn get_vendor_class(&self) -> Option<String> {
+        self.get_option(OPTION_VENDOR_CLASS)
+            .filter(|opt| !opt.data.is_empty())
+            .map(|opt| printable_or_hex(&opt.data))
+    }
+
+    /// Option 77, with multiple classes joined by commas
+    pub fn get_user_class(&self) -> Option<String> {
+        self.get_option(OPTION_USER_CLASS)
+            .filter(|opt| !opt.data.is_empty())
+            .map(|opt| user_class_text(&opt.data))
+    }
+
+    /// Option 61 as colon-separated hex, type byte first (01:aa:bb:cc:dd:ee:ff for a MAC)
+    pub fn get_client_identifier(&self) -> Option<String> {
+        self.get_option(OPTION_CLIENT_ID)
+            .filter(|opt| !opt.data.is_empty())
+            .map(|opt| {
+                opt.data.iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<Vec<_>>()
+                    .join(":")
+            })
+    }
+
+    pub fn get_client_fqdn(&self) -> Option<ClientFqdn> {
+        self.get_option(OPTION_CLIENT_FQDN)
+            .and_then(|opt| ClientFqdn::parse(&opt.data))
+    }
+
+    /// Name for the lease: option 81's FQDN when the client sent one, otherwise option 12
+    pub fn get_lease_hostname(&self) -> Option<String> {
+        self.get_client_fqdn()
+            .map(|fqdn| fqdn.name)
+            .filter(|name| !name.is_empty())
+            .or_else(|| self.get_hostname())
+    }
+
+    /// Option 93 (RFC 4578): 0 is BIOS, 7 and 9 are x86-64 UEFI. Only the first listed type is used.
+    pub fn get_client_arch(&self) -> Option<u16> {
+        self.get_option(OPTION_CLIENT_ARCH)
+            .filter(|opt| opt.data.len() >= 2)
+            .map(|opt| u16::from_be_bytes([opt.data[0], opt.data[1]]))
+    }
+
+    /// Copies `filename` into the BOOTP `file` field, truncated so it stays NUL-terminated
+    pub fn set_boot_file(&mut self, filename: &str) {
+        let bytes = filename.as_bytes();
+        let len = bytes.len().min(self.file.len() - 1);
+        self.file = [0; 128];
+        self.file[..len].copy_from_slice(&bytes[..len]);
+    }
+
+    pub fn get_client_info(&self) -> ClientInfo {
+        ClientInfo {
+            client_identifier: self.get_client_identifier(),
+            vendor_class: self.get_vendor_class(),
+            user_class: self.get_user_class(),
+        }
+    }
+
     pub fn get_option(&self, code: u8) -> Option<&DhcpOption> {
         self.options.iter().find(|opt| opt.code == code)
     }
@@ -268,4 +620,262 @@ impl DhcpPacket {
     pub fn is_broadcast(&self) -> bool {
         (self.flags & 0x8000) != 0
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relay_agent_info_parsing() {
+        let mut packet = DhcpPacket::new();
+        assert!(packet.get_relay_agent_info().is_none());
+
+        // circuit-id "Gi1/0/5", an unknown sub-option, then a binary remote-id
+        let mut data = vec![1, 7];
+        data.extend_from_slice(b"Gi1/0/5");
+        data.extend_from_slice(&[9, 1, 0xff]);
+        data.extend_from_slice(&[2, 3, 0x00, 0x1a, 0x2b]);
+        packet.set_option(OPTION_RELAY_AGENT_INFO, data);
+
+        let info = packet.get_relay_agent_info().unwrap();
+        assert_eq!(info.circuit_id.as_deref(), Some(&b"Gi1/0/5"[..]));
+        assert_eq!(info.remote_id.as_deref(), Some(&[0x00, 0x1a, 0x2b][..]));
+        assert_eq!(info.circuit_id_text().as_deref(), Some("Gi1/0/5"));
+
+        let binary = RelayAgentInfo { circuit_id: Some(vec![0x00, 0x04, 0x00, 0x0a]), remote_id: None };
+        assert_eq!(binary.circuit_id_text().as_deref(), Some("0004000a"));
+
+        // A truncated sub-option is ignored rather than read past the end
+        assert_eq!(RelayAgentInfo::parse(&[1, 10, b'x']), RelayAgentInfo::default());
+    }
+
+    #[test]
+    fn test_client_info_options() {
+        let mut packet = DhcpPacket::new();
+        assert_eq!(packet.get_client_info(), ClientInfo::default());
+
+        packet.set_option(OPTION_VENDOR_CLASS, b"MSFT 5.0".to_vec());
+        packet.set_option(OPTION_CLIENT_ID, vec![1, 0xaa, 0xbb, 0xcc, 0x00, 0x11, 0x22]);
+        packet.set_option(OPTION_USER_CLASS, vec![3, b'l', b'a', b'b', 4, b'i', b'p', b'x', b'e']);
+
+        let info = packet.get_client_info();
+        assert_eq!(info.vendor_class.as_deref(), Some("MSFT 5.0"));
+        assert_eq!(info.client_identifier.as_deref(), Some("01:aa:bb:cc:00:11:22"));
+        assert_eq!(info.user_class.as_deref(), Some("lab,ipxe"));
+
+        // A bare string that doesn't parse as RFC 3004 instances is kept as-is
+        packet.set_option(OPTION_USER_CLASS, b"iPXE".to_vec());
+        assert_eq!(packet.get_user_class().as_deref(), Some("iPXE"));
+    }
+
+    #[test]
+    fn test_client_fqdn() {
+        let mut packet = DhcpPacket::new();
+        packet.set_hostname("laptop");
+        assert_eq!(packet.get_lease_hostname().as_deref(), Some("laptop"));
+
+        // ASCII, asking the server to update the A record
+        packet.set_option(OPTION_CLIENT_FQDN, b"\x01\0\0laptop.example.com.".to_vec());
+        let fqdn = packet.get_client_fqdn().unwrap();
+        assert!(fqdn.server_updates_forward && !fqdn.no_server_updates && !fqdn.wire_encoded);
+        assert_eq!(fqdn.name, "laptop.example.com");
+        assert_eq!(packet.get_lease_hostname().as_deref(), Some("laptop.example.com"));
+        assert_eq!(fqdn.reply_data("laptop.example.com"), b"\x01\xff\xfflaptop.example.com".to_vec());
+
+        // Wire format, the client updating its own A record
+        let mut data = vec![0x04, 0, 0, 6];
+        data.extend_from_slice(b"laptop");
+        data.push(7);
+        data.extend_from_slice(b"example");
+        data.extend_from_slice(&[3, b'c', b'o', b'm', 0]);
+        packet.set_option(OPTION_CLIENT_FQDN, data.clone());
+        let fqdn = packet.get_client_fqdn().unwrap();
+        assert!(!fqdn.server_updates_forward && fqdn.wire_encoded);
+        assert_eq!(fqdn.name, "laptop.example.com");
+        let mut reply = data.clone();
+        reply[1] = 255;
+        reply[2] = 255;
+        assert_eq!(fqdn.reply_data("laptop.example.com."), reply);
+
+        // A partial wire name has no root label
+        packet.set_option(OPTION_CLIENT_FQDN, vec![0x05, 0, 0, 2, b'p', b'c']);
+        assert_eq!(packet.get_client_fqdn().unwrap().name, "pc");
+
+        // N asks for no updates; S is cleared in the reply
+        let fqdn = ClientFqdn::parse(&[0x09, 0, 0]).unwrap();
+        assert!(fqdn.no_server_updates);
+        assert_eq!(fqdn.reply_data("pc")[0], 0x08);
+
+        // An empty name falls back to option 12; malformed options are ignored
+        packet.set_option(OPTION_CLIENT_FQDN, vec![0x01, 0, 0]);
+        assert_eq!(packet.get_lease_hostname().as_deref(), Some("laptop"));
+        packet.set_option(OPTION_CLIENT_FQDN, vec![0x04, 0, 0, 9, b'x']);
+        assert!(packet.get_client_fqdn().is_none());
+        packet.set_option(OPTION_CLIENT_FQDN, vec![0x01, 0]);
+        assert!(packet.get_client_fqdn().is_none());
+    }
+
+    fn sample_packet() -> Vec<u8> {
+        let mut packet = DhcpPacket::new();
+        packet.xid = 0x1234_5678;
+        packet.set_client_mac(&[0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]);
+        packet.set_message_type(DhcpMessageType::Request);
+        packet.set_requested_ip(Ipv4Addr::new(192, 168, 1, 50));
+        packet.set_hostname("laptop");
+        packet.set_option(OPTION_RELAY_AGENT_INFO, vec![1, 3, b'p', b'1', b'0']);
+        packet.to_bytes()
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_options() {
+        let header = &sample_packet()[..240];
+        let with_options = |options: &[u8]| {
+            let mut data = header.to_vec();
+            data.extend_from_slice(options);
+            DhcpPacket::parse(&data)
+        };
+
+        // Length running past the end of the packet
+        assert!(with_options(&[53, 1, 3, 12, 200, b'x']).is_err());
+        // Code with no length byte
+        assert!(with_options(&[53, 1, 3, 12]).is_err());
+        // Maximum length with nothing behind it
+        assert!(with_options(&[12, 255]).is_err());
+
+        // Padding, a zero-length option and a missing END are fine
+        let packet = with_options(&[0, 0, 53, 1, 3, 60, 0]).unwrap();
+        assert_eq!(packet.get_message_type(), Some(DhcpMessageType::Request));
+        assert_eq!(packet.get_option(OPTION_VENDOR_CLASS).map(|opt| opt.data.len()), Some(0));
+
+        // Anything after END is ignored, even garbage
+        let packet = with_options(&[53, 1, 3, 255, 12, 200]).unwrap();
+        assert_eq!(packet.options.len(), 1);
+
+        // Exactly the fixed header plus cookie, and a missing cookie, have no options
+        assert!(with_options(&[]).unwrap().options.is_empty());
+        let mut no_cookie = header.to_vec();
+        no_cookie[236] = 0;
+        no_cookie.extend_from_slice(&[53, 1, 3]);
+        assert!(DhcpPacket::parse(&no_cookie).unwrap().options.is_empty());
+    }
+
+    /// A reply whose options take about 480 bytes, more than the 308 left for them in 576
+    fn large_reply() -> DhcpPacket {
+        let mut reply = DhcpPacket::new();
+        reply.op = 2;
+        reply.set_message_type(DhcpMessageType::Offer);
+        reply.set_server_id(Ipv4Addr::new(10, 0, 0, 1));
+        reply.set_lease_time(3600);
+        for code in [119u8, 120, 121, 125, 43, 42, 150] {
+            reply.set_option(code, vec![code; 60]);
+        }
+        reply.set_option(15, vec![b'x'; 20]);
+        reply.set_option(OPTION_RELAY_AGENT_INFO, vec![1, 3, b'p', b'1', b'0']);
+        reply
+    }
+
+    #[test]
+    fn test_small_replies_are_unchanged() {
+        let packet = DhcpPacket::parse(&sample_packet()).unwrap();
+        assert_eq!(packet.to_bytes_within(None), packet.to_bytes());
+
+        // A client accepting larger messages gets everything in the options field
+        let reply = large_reply();
+        assert_eq!(reply.to_bytes_within(Some(1500)), reply.to_bytes());
+        assert!(reply.to_bytes().len() > MIN_MESSAGE_SIZE);
+    }
+
+    #[test]
+    fn test_option_overload() {
+        let reply = large_reply();
+        let data = reply.to_bytes_within(None);
+        assert!(data.len() <= MIN_MESSAGE_SIZE - IP_UDP_HEADER_SIZE);
+
+        let parsed = DhcpPacket::parse(&data).unwrap();
+        assert_eq!(parsed.get_option(OPTION_OVERLOAD).unwrap().data, vec![OVERLOAD_FILE | OVERLOAD_SNAME]);
+        for option in &reply.options {
+            assert_eq!(parsed.get_option(option.code).map(|opt| &opt.data), Some(&option.data), "option {}", option.code);
+        }
+
+        // The message type and relay agent information stay in the options field, the latter last
+        let main = DhcpPacket::parse_options(&data[240..]).unwrap();
+        assert_eq!(main.first().map(|opt| opt.code), Some(OPTION_MESSAGE_TYPE));
+        assert_eq!(main.last().map(|opt| opt.code), Some(OPTION_RELAY_AGENT_INFO));
+        assert!(main.len() < reply.options.len());
+
+        // A boot file keeps the file field to itself, so only sname takes options and the rest
+        // are dropped
+        let mut booting = large_reply();
+        booting.set_boot_file("pxelinux.0");
+        let parsed = DhcpPacket::parse(&booting.to_bytes_within(None)).unwrap();
+        assert_eq!(parsed.get_option(OPTION_OVERLOAD).unwrap().data, vec![OVERLOAD_SNAME]);
+        assert_eq!(&parsed.file[..10], b"pxelinux.0");
+        assert!(parsed.get_option(43).is_some());
+        assert!(parsed.get_option(42).is_none());
+        assert!(parsed.get_message_type().is_some());
+    }
+
+    #[test]
+    fn test_max_message_size() {
+        let mut request = DhcpPacket::new();
+        assert_eq!(request.get_max_message_size(), None);
+        request.set_option(OPTION_MAX_MESSAGE_SIZE, 1500u16.to_be_bytes().to_vec());
+        assert_eq!(request.get_max_message_size(), Some(1500));
+
+        // Values below the minimum are raised to it
+        let reply = large_reply();
+        assert_eq!(reply.to_bytes_within(Some(300)), reply.to_bytes_within(None));
+    }
+
+    #[test]
+    fn test_parse_never_panics() {
+        let valid = sample_packet();
+        let parsed = DhcpPacket::parse(&valid).unwrap();
+        assert_eq!(parsed.get_hostname().as_deref(), Some("laptop"));
+
+        // Every truncation of a valid packet
+        for len in 0..=valid.len() {
+            let _ = DhcpPacket::parse(&valid[..len]);
+        }
+
+        // Every possible length byte for every option, at every truncation of the option area
+        let header = &valid[..240];
+        for code in [1u8, 12, 50, 53, 61, 77, 81, 82, 93] {
+            for len in 0..=255u8 {
+                let mut data = header.to_vec();
+                data.extend_from_slice(&[code, len]);
+                data.extend(std::iter::repeat_n(0xab, len as usize / 2));
+                for end in 240..=data.len() {
+                    if let Ok(packet) = DhcpPacket::parse(&data[..end]) {
+                        let _ = packet.get_client_info();
+                        let _ = packet.get_relay_agent_info();
+                        let _ = packet.get_requested_ip();
+                        let _ = packet.get_lease_time();
+                        let _ = packet.get_client_arch();
+                        let _ = packet.get_client_fqdn();
+                        let _ = packet.get_message_type();
+                    }
+                }
+            }
+        }
+
+        // Pseudo-random option areas, including oversized ones
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2000 {
+            let mut data = header.to_vec();
+            let extra = (next() % 1500) as usize;
+            data.extend((0..extra).map(|_| next() as u8));
+            if let Ok(packet) = DhcpPacket::parse(&data) {
+                let _ = packet.get_client_info();
+                let _ = packet.get_relay_agent_info();
+                let _ = DhcpPacket::parse(&packet.to_bytes());
+            }
+        }
+    }
+}
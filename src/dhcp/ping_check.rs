@@ -0,0 +1,119 @@
+// ICMP echo probe used to detect addresses that are configured statically before offering them
+use anyhow::Result;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const IDENTIFIER: u16 = 0x464c; // "FL"
+
+static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+/// True if `ip` answers an ICMP echo within `timeout`.
+///
+/// Uses an unprivileged ICMP datagram socket where the kernel allows it
+/// (net.ipv4.ping_group_range) and falls back to a raw socket, which needs CAP_NET_RAW.
+pub async fn address_responds(ip: Ipv4Addr, timeout: Duration) -> Result<bool> {
+    let socket = open_socket()?;
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let target = SocketAddr::new(IpAddr::V4(ip), 0);
+
+    socket.send_to(&echo_request(IDENTIFIER, sequence), target).await?;
+
+    let wait = async {
+        let mut buf = [0u8; 1500];
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await?;
+            if from.ip() == IpAddr::V4(ip) && is_echo_reply(&buf[..len], sequence) {
+                return Ok::<_, anyhow::Error>(true);
+            }
+        }
+    };
+
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(result) => result,
+        Err(_) => Ok(false),
+    }
+}
+
+fn open_socket() -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::ICMPV4))
+        .or_else(|_| Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)))?;
+    socket.set_nonblocking(true)?;
+
+    let std_socket: std::net::UdpSocket = socket.into();
+    Ok(UdpSocket::from_std(std_socket)?)
+}
+
+fn echo_request(identifier: u16, sequence: u16) -> Vec<u8> {
+    let mut packet = vec![ICMP_ECHO_REQUEST, 0, 0, 0];
+    packet.extend_from_slice(&identifier.to_be_bytes());
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(b"flowdns-ping-check");
+
+    let checksum = checksum(&packet);
+    packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    packet
+}
+
+/// Raw sockets deliver the IP header too; datagram sockets start at the ICMP header.
+/// The identifier isn't compared because datagram sockets replace it with the local port.
+fn is_echo_reply(data: &[u8], sequence: u16) -> bool {
+    let icmp = match data.first() {
+        Some(first) if first >> 4 == 4 => data.get(((first & 0x0f) as usize) * 4..),
+        _ => Some(data),
+    };
+
+    match icmp {
+        Some(icmp) if icmp.len() >= 8 => {
+            icmp[0] == ICMP_ECHO_REPLY && u16::from_be_bytes([icmp[6], icmp[7]]) == sequence
+        }
+        _ => false,
+    }
+}
+
+/// RFC 1071 internet checksum
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo_request_checksum() {
+        let packet = echo_request(0x1234, 7);
+        assert_eq!(packet[0], ICMP_ECHO_REQUEST);
+        assert_eq!(&packet[4..8], &[0x12, 0x34, 0, 7]);
+        // A packet carrying its own checksum sums to zero
+        assert_eq!(checksum(&packet), 0);
+    }
+
+    #[test]
+    fn test_echo_reply_matching() {
+        let mut reply = echo_request(0x1234, 7);
+        reply[0] = ICMP_ECHO_REPLY;
+        assert!(is_echo_reply(&reply, 7));
+        assert!(!is_echo_reply(&reply, 8));
+
+        // Same reply behind a 20-byte IPv4 header, as a raw socket sees it
+        let mut with_header = vec![0x45];
+        with_header.extend_from_slice(&[0; 19]);
+        with_header.extend_from_slice(&reply);
+        assert!(is_echo_reply(&with_header, 7));
+
+        assert!(!is_echo_reply(&echo_request(0x1234, 7), 7));
+        assert!(!is_echo_reply(&[0, 0, 0], 7));
+    }
+}
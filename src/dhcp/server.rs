@@ -1,68 +1,118 @@
 use crate::config::Settings;
-use crate::database::models::DhcpSubnet;
+use crate::database::models::{DhcpLease, DhcpSubnet};
 use crate::dhcp::lease_manager::LeaseManager;
+use crate::dhcp::lease_manager_queries;
+use crate::dns::dhcid::ClientIdentity;
+use crate::dns::dynamic_updates::{DhcpDnsIntegration, DnsUpdateScope};
 use crate::dhcp::packet::{DhcpPacket, DhcpMessageType};
-use crate::dhcp::packet::DhcpOption;
+use crate::dhcp::packet::{DhcpOption, OPTION_RELAY_AGENT_INFO};
 use crate::dhcp::options::{self, DhcpOptionsBuilder};
+use crate::dhcp::socket::{self, Interface};
+use crate::metrics;
 use anyhow::{Result, anyhow};
 use std::net::{SocketAddr, Ipv4Addr, IpAddr};
 use std::sync::Arc;
 use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, Mutex};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinSet;
 use tokio::time::{interval, Duration};
+use tokio_util::sync::CancellationToken;
 use tracing::{info, warn, error, debug};
-use sqlx::PgPool;
 use ipnet::Ipv4Net;
 
+/// Workers per server when `server.threads` isn't set
+const DEFAULT_WORKERS: usize = 4;
+/// Received packets that may wait per worker before new ones are dropped
+const QUEUE_PER_WORKER: usize = 64;
+
 pub struct DhcpServer {
     socket: UdpSocket,
     lease_manager: Arc<LeaseManager>,
     settings: Arc<Settings>,
+    /// The address the socket is bound to; 0.0.0.0 when listening on every address
     server_ip: Ipv4Addr,
+    /// The interface this server's socket is bound to, from `dhcp.interfaces`
+    interface: Option<Interface>,
+    /// Set when `dns.dynamic_updates` is on
+    dns_updates: Option<Arc<DhcpDnsIntegration>>,
 }
 
 impl DhcpServer {
-    pub async fn new(settings: Arc<Settings>, db: PgPool) -> Result<Self> {
-        let bind_addr = format!("{}:{}", settings.dhcp.bind_address, settings.dhcp.port);
-        let socket = UdpSocket::bind(&bind_addr).await?;
-
-        // Enable broadcast
-        socket.set_broadcast(true)?;
-
-        info!("DHCP server listening on {}", bind_addr);
-
-        let lease_manager = Arc::new(LeaseManager::new(db, Arc::clone(&settings)).await?);
-
-        // Parse server IP from bind address
-        let server_ip = settings.dhcp.bind_address.parse::<Ipv4Addr>()
-            .unwrap_or(Ipv4Addr::new(0, 0, 0, 0));
+    /// Listens on `bind_address`, or on every address of `interface` when one is given
+    pub async fn new(
+        settings: Arc<Settings>,
+        lease_manager: Arc<LeaseManager>,
+        dns_updates: Option<Arc<DhcpDnsIntegration>>,
+        interface: Option<Interface>,
+    ) -> Result<Self> {
+        let (socket, server_ip) = match &interface {
+            Some(interface) => {
+                let bind_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), settings.dhcp.port);
+                let socket = socket::bind(bind_addr, Some(&interface.name))?;
+                info!("DHCP server listening on {} ({}, port {})", interface.name, interface.address, settings.dhcp.port);
+                (socket, interface.address)
+            }
+            None => {
+                let bind_addr: SocketAddr = format!("{}:{}", settings.dhcp.bind_address, settings.dhcp.port).parse()?;
+                let socket = socket::bind(bind_addr, None)?;
+                info!("DHCP server listening on {}", bind_addr);
+
+                // Parse server IP from bind address
+                let server_ip = settings.dhcp.bind_address.parse::<Ipv4Addr>()
+                    .unwrap_or(Ipv4Addr::new(0, 0, 0, 0));
+                (socket, server_ip)
+            }
+        };
 
         Ok(Self {
             socket,
             lease_manager,
             settings,
             server_ip,
+            interface,
+            dns_updates,
         })
     }
 
-    pub async fn run(&mut self) -> Result<()> {
-        let mut buf = vec![0u8; 1500];
-
-        // Start cleanup task
-        let cleanup_manager = Arc::clone(&self.lease_manager);
-        tokio::spawn(async move {
-            let mut cleanup_interval = interval(Duration::from_secs(300));
-            loop {
-                cleanup_interval.tick().await;
-                if let Err(e) = cleanup_manager.cleanup_expired_leases().await {
-                    error!("Failed to cleanup expired leases: {}", e);
+    /// Serves packets until `shutdown` is cancelled. Received packets are queued for a pool of
+    /// `server.threads` workers so a slow database doesn't stall reception; when the queue is
+    /// full new packets are dropped and counted, and clients retransmit. Packets already queued
+    /// are still handled before this returns.
+    pub async fn run(self: Arc<Self>, shutdown: CancellationToken) -> Result<()> {
+        let workers = worker_count(self.settings.server.threads);
+        let (queue, receiver) = mpsc::channel::<(DhcpPacket, SocketAddr)>(workers * QUEUE_PER_WORKER);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut pool = JoinSet::new();
+        for _ in 0..workers {
+            let server = Arc::clone(&self);
+            let receiver = Arc::clone(&receiver);
+            pool.spawn(async move {
+                loop {
+                    // The lock is only held while waiting, so other workers keep handling packets
+                    let next = receiver.lock().await.recv().await;
+                    let Some((packet, src)) = next else { break };
+
+                    if let Err(e) = server.handle_packet(packet, src).await {
+                        metrics::inc(&metrics::counters().dhcp_errors_total);
+                        error!("Error handling DHCP packet: {}", e);
+                    }
                 }
-            }
-        });
+            });
+        }
+
+        let mut buf = vec![0u8; 1500];
 
-        info!("DHCP server started successfully");
+        info!("DHCP server started with {} workers", workers);
 
         loop {
-            match self.socket.recv_from(&mut buf).await {
+            let received = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                received = self.socket.recv_from(&mut buf) => received,
+            };
+
+            match received {
                 Ok((size, src)) => {
                     let packet_data = &buf[..size];
 
@@ -71,11 +121,17 @@ impl DhcpServer {
                             debug!("Received DHCP packet from {}: {:?}",
                                   src, packet.get_message_type());
 
-                            if let Err(e) = self.handle_packet(packet, src).await {
-                                error!("Error handling DHCP packet: {}", e);
+                            match queue.try_send((packet, src)) {
+                                Ok(()) => {}
+                                Err(TrySendError::Full(_)) => {
+                                    metrics::inc(&metrics::counters().dhcp_dropped_total);
+                                    debug!("DHCP queue full, dropping packet from {}", src);
+                                }
+                                Err(TrySendError::Closed(_)) => break,
                             }
                         }
                         Err(e) => {
+                            metrics::inc(&metrics::counters().dhcp_parse_failures_total);
                             warn!("Failed to parse DHCP packet from {}: {}", src, e);
                         }
                     }
@@ -85,6 +141,13 @@ impl DhcpServer {
                 }
             }
         }
+
+        // Workers finish what is already queued, then stop
+        drop(queue);
+        while pool.join_next().await.is_some() {}
+
+        info!("DHCP server stopped");
+        Ok(())
     }
 
     async fn handle_packet(&self, packet: DhcpPacket, src: SocketAddr) -> Result<()> {
@@ -92,10 +155,16 @@ impl DhcpServer {
             .ok_or_else(|| anyhow!("No message type in DHCP packet"))?;
 
         match msg_type {
-            DhcpMessageType::Discover => self.handle_discover(packet, src).await,
-            DhcpMessageType::Request => self.handle_request(packet, src).await,
+            DhcpMessageType::Discover => {
+                metrics::inc(&metrics::counters().dhcp_discover_total);
+                self.handle_discover(packet, src).await
+            }
+            DhcpMessageType::Request => {
+                metrics::inc(&metrics::counters().dhcp_request_total);
+                self.handle_request(packet).await
+            }
             DhcpMessageType::Release => self.handle_release(packet).await,
-            DhcpMessageType::Inform => self.handle_inform(packet, src).await,
+            DhcpMessageType::Inform => self.handle_inform(packet).await,
             DhcpMessageType::Decline => self.handle_decline(packet).await,
             _ => {
                 debug!("Ignoring DHCP message type: {:?}", msg_type);
@@ -110,7 +179,7 @@ impl DhcpServer {
 
         // Find subnet for client
         let subnet = self.lease_manager
-            .find_subnet_for_client(src.ip().to_string().parse()?, packet.giaddr.into())
+            .find_subnet_for_client(src.ip().to_string().parse()?, self.subnet_gateway(&packet), packet.get_relay_agent_info().as_ref())
             .await;
 
         let subnet = match subnet {
@@ -122,7 +191,7 @@ impl DhcpServer {
         };
 
         // Find available IP
-        let ip = match self.lease_manager.find_available_ip(subnet.id, &mac).await? {
+        let ip = match self.lease_manager.find_ip_to_offer(subnet.id, &mac).await? {
             Some(ip) => ip,
             None => {
                 warn!("No available IP addresses in subnet {}", subnet.name);
@@ -131,22 +200,25 @@ impl DhcpServer {
         };
 
         // Create OFFER packet
-        let mut reply = self.create_reply_packet(&packet, DhcpMessageType::Offer);
+        let mut reply = self.create_reply_packet(&packet, DhcpMessageType::Offer, Some(&subnet));
         reply.yiaddr = ip;
 
         // Add DHCP options
-        let options = self.build_subnet_options(&subnet, ip)?;
+        let lease_time = self.lease_manager.lease_time_for(&subnet, packet.get_lease_time());
+        let options = self.build_subnet_options(&subnet, Some(lease_time))?;
         reply.options.extend(options);
+        add_boot_options(&mut reply, &subnet, &packet);
 
         // Send OFFER
-        self.send_reply(reply, packet.is_broadcast(), src).await?;
+        self.send_reply(reply, &packet).await?;
         info!("OFFER sent: MAC {} -> IP {}", format_mac(&mac), ip);
 
         Ok(())
     }
 
-    async fn handle_request(&self, packet: DhcpPacket, src: SocketAddr) -> Result<()> {
+    async fn handle_request(&self, packet: DhcpPacket) -> Result<()> {
         let mac = packet.get_client_mac();
+
         let requested_ip = packet.get_requested_ip()
             .or(Some(packet.ciaddr))
             .filter(|&ip| ip != Ipv4Addr::UNSPECIFIED);
@@ -155,69 +227,131 @@ impl DhcpServer {
             Some(ip) => ip,
             None => {
                 warn!("REQUEST from {} with no requested IP", format_mac(&mac));
-                return self.send_nak(packet, src).await;
+                return self.send_nak(packet).await;
             }
         };
 
+        let subnet = self.lease_manager
+            .find_subnet_for_client(requested_ip, self.subnet_gateway(&packet), packet.get_relay_agent_info().as_ref())
+            .await;
+
+        // The identifier we'd have put in our own OFFER for this client
+        if selects_other_server(&packet, self.server_id(subnet.as_ref(), &packet)) {
+            debug!("REQUEST from {} is for server {:?}, ignoring",
+                   format_mac(&mac), packet.get_server_id());
+            return Ok(());
+        }
+
         info!("REQUEST from MAC: {} for IP: {}", format_mac(&mac), requested_ip);
 
+        let requested_lease_time = packet.get_lease_time();
+
         // Try to renew existing lease
-        if let Some(lease) = self.lease_manager.renew_lease(&mac, requested_ip).await? {
+        if let Some(lease) = self.lease_manager.renew_lease(&mac, requested_ip, requested_lease_time).await? {
             // Send ACK
-            let mut reply = self.create_reply_packet(&packet, DhcpMessageType::Ack);
+            let mut reply = self.create_reply_packet(&packet, DhcpMessageType::Ack, subnet.as_ref());
             reply.yiaddr = lease.ip_address;
 
-            // Get subnet for options
-            if let Some(subnet) = self.lease_manager
-                .find_subnet_for_client(requested_ip, packet.giaddr.into())
-                .await {
-                let options = self.build_subnet_options(&subnet, requested_ip)?;
+            if let Some(subnet) = &subnet {
+                let lease_time = self.lease_manager.lease_time_for(subnet, requested_lease_time);
+                let options = self.build_subnet_options(subnet, Some(lease_time))?;
                 reply.options.extend(options);
+                add_boot_options(&mut reply, subnet, &packet);
             }
+            self.add_client_fqdn(&mut reply, &packet, lease.hostname.as_deref());
 
-            self.send_reply(reply, packet.is_broadcast(), src).await?;
+            self.send_reply(reply, &packet).await?;
             info!("ACK sent (renewal): MAC {} -> IP {}", format_mac(&mac), requested_ip);
+
+            if let Some(dns) = &self.dns_updates {
+                let scope = dns_update_scope(&packet);
+                let client_id = client_id_bytes(&lease);
+                let client = dns_client(&lease.mac_address, client_id.as_deref());
+                if let Err(e) = dns.on_lease_renewed(lease.hostname, IpAddr::V4(lease.ip_address), subnet.as_ref(), scope, client).await {
+                    warn!("Dynamic DNS update failed for {}: {}", lease.ip_address, e);
+                }
+            }
             return Ok(());
         }
 
         // Try to create new lease
-        let subnet = match self.lease_manager
-            .find_subnet_for_client(requested_ip, packet.giaddr.into())
-            .await {
+        let subnet = match subnet {
             Some(s) => s,
             None => {
                 warn!("No subnet found for requested IP {}", requested_ip);
-                return self.send_nak(packet, src).await;
+                return self.send_nak(packet).await;
             }
         };
 
+        if subnet.maintenance {
+            info!("Subnet {} is in maintenance, refusing new lease for MAC {}",
+                  subnet.name, format_mac(&mac));
+            if self.settings.dhcp.authoritative {
+                return self.send_nak(packet).await;
+            }
+            return Ok(());
+        }
+
         // Verify IP is available
         let available_ip = self.lease_manager.find_available_ip(subnet.id, &mac).await?;
         if available_ip != Some(requested_ip) {
             warn!("Requested IP {} not available for MAC {}",
                   requested_ip, format_mac(&mac));
-            return self.send_nak(packet, src).await;
+            return self.send_nak(packet).await;
         }
 
         // Create lease
-        let hostname = packet.get_hostname();
-        let lease = self.lease_manager
-            .create_lease(subnet.id, &mac, requested_ip, hostname)
-            .await?;
+        let hostname = packet.get_lease_hostname();
+        let lease = match self.lease_manager
+            .create_lease(subnet.id, &mac, requested_ip, hostname, packet.get_client_info(), requested_lease_time)
+            .await {
+            Ok(lease) => lease,
+            // Another client's REQUEST for the same address committed first
+            Err(e) if lease_manager_queries::is_address_conflict(&e) => {
+                warn!("Requested IP {} was leased concurrently, NAK to MAC {}",
+                      requested_ip, format_mac(&mac));
+                return self.send_nak(packet).await;
+            }
+            Err(e) => return Err(e),
+        };
 
         // Send ACK
-        let mut reply = self.create_reply_packet(&packet, DhcpMessageType::Ack);
+        let mut reply = self.create_reply_packet(&packet, DhcpMessageType::Ack, Some(&subnet));
         reply.yiaddr = lease.ip_address;
 
-        let options = self.build_subnet_options(&subnet, requested_ip)?;
+        let lease_time = self.lease_manager.lease_time_for(&subnet, requested_lease_time);
+        let options = self.build_subnet_options(&subnet, Some(lease_time))?;
         reply.options.extend(options);
+        add_boot_options(&mut reply, &subnet, &packet);
+        self.add_client_fqdn(&mut reply, &packet, lease.hostname.as_deref());
 
-        self.send_reply(reply, packet.is_broadcast(), src).await?;
+        self.send_reply(reply, &packet).await?;
         info!("ACK sent (new): MAC {} -> IP {}", format_mac(&mac), requested_ip);
 
+        if let Some(dns) = &self.dns_updates {
+            let scope = dns_update_scope(&packet);
+            let client_id = client_id_bytes(&lease);
+            let client = dns_client(&lease.mac_address, client_id.as_deref());
+            if let Err(e) = dns.on_lease_created(lease.hostname, IpAddr::V4(lease.ip_address), Some(&subnet), scope, client).await {
+                warn!("Dynamic DNS update failed for {}: {}", lease.ip_address, e);
+            }
+        }
+
         Ok(())
     }
 
+    /// Answers option 81 with what the server will update, but only when it updates DNS at all;
+    /// a server that doesn't leaves the option out (RFC 4702 section 4)
+    fn add_client_fqdn(&self, reply: &mut DhcpPacket, request: &DhcpPacket, hostname: Option<&str>) {
+        if self.dns_updates.is_none() {
+            return;
+        }
+        if let Some(fqdn) = request.get_client_fqdn() {
+            let name = hostname.unwrap_or(&fqdn.name);
+            reply.set_option(options::OPTION_CLIENT_FQDN, fqdn.reply_data(name));
+        }
+    }
+
     async fn handle_release(&self, packet: DhcpPacket) -> Result<()> {
         let mac = packet.get_client_mac();
         let ip = packet.ciaddr;
@@ -229,30 +363,34 @@ impl DhcpServer {
 
         info!("RELEASE from MAC: {} for IP: {}", format_mac(&mac), ip);
 
-        if self.lease_manager.release_lease(&mac, ip).await? {
+        if let Some(lease) = self.lease_manager.release_lease(&mac, ip).await? {
             info!("Lease released: MAC {} -> IP {}", format_mac(&mac), ip);
+            self.remove_dns_records(lease).await;
         }
 
         Ok(())
     }
 
-    async fn handle_inform(&self, packet: DhcpPacket, src: SocketAddr) -> Result<()> {
+    async fn handle_inform(&self, packet: DhcpPacket) -> Result<()> {
         let mac = packet.get_client_mac();
         info!("INFORM from MAC: {}", format_mac(&mac));
 
+        let subnet = self.lease_manager
+            .find_subnet_for_client(packet.ciaddr, self.subnet_gateway(&packet), packet.get_relay_agent_info().as_ref())
+            .await;
+
         // Send ACK with configuration only (no IP assignment)
-        let mut reply = self.create_reply_packet(&packet, DhcpMessageType::Ack);
+        let mut reply = self.create_reply_packet(&packet, DhcpMessageType::Ack, subnet.as_ref());
         reply.yiaddr = Ipv4Addr::UNSPECIFIED;
 
         // Add configuration options if we can find the subnet
-        if let Some(subnet) = self.lease_manager
-            .find_subnet_for_client(packet.ciaddr, packet.giaddr.into())
-            .await {
-            let options = self.build_subnet_options(&subnet, packet.ciaddr)?;
+        if let Some(subnet) = subnet {
+            // INFORM clients configured their own address, so no lease times (RFC 2131 section 3.4)
+            let options = self.build_subnet_options(&subnet, None)?;
             reply.options.extend(options);
         }
 
-        self.send_reply(reply, packet.is_broadcast(), src).await?;
+        self.send_reply(reply, &packet).await?;
 
         Ok(())
     }
@@ -264,23 +402,68 @@ impl DhcpServer {
 
         warn!("DECLINE from MAC: {} for IP: {}", format_mac(&mac), ip);
 
-        // Mark IP as declined (could implement IP blacklist here)
-        // For now, just release the lease
-        if ip != Ipv4Addr::UNSPECIFIED {
-            self.lease_manager.release_lease(&mac, ip).await?;
+        if ip == Ipv4Addr::UNSPECIFIED {
+            return Ok(());
+        }
+
+        // The client found the address in use, so nobody should be offered it for a while
+        let subnet_id = match self.lease_manager.release_lease(&mac, ip).await? {
+            Some(lease) => {
+                let subnet_id = lease.subnet_id;
+                self.remove_dns_records(lease).await;
+                Some(subnet_id)
+            }
+            None => self.lease_manager
+                .find_subnet_for_client(ip, self.subnet_gateway(&packet), packet.get_relay_agent_info().as_ref())
+                .await
+                .map(|subnet| subnet.id),
+        };
+
+        match subnet_id {
+            Some(subnet_id) => self.lease_manager.decline_address(subnet_id, ip, Some(&mac)).await?,
+            None => warn!("DECLINE for {} outside any subnet ignored", ip),
         }
 
         Ok(())
     }
 
-    async fn send_nak(&self, packet: DhcpPacket, src: SocketAddr) -> Result<()> {
-        let reply = self.create_reply_packet(&packet, DhcpMessageType::Nak);
-        self.send_reply(reply, packet.is_broadcast(), src).await?;
+    /// Drops the dynamic DNS records of a lease that is no longer active
+    async fn remove_dns_records(&self, lease: DhcpLease) {
+        let Some(dns) = &self.dns_updates else { return };
+
+        let subnet = self.lease_manager.get_subnet(lease.subnet_id).await;
+        let client_id = client_id_bytes(&lease);
+        let client = dns_client(&lease.mac_address, client_id.as_deref());
+        if let Err(e) = dns.on_lease_released(lease.hostname, IpAddr::V4(lease.ip_address), subnet.as_ref(), client).await {
+            warn!("Dynamic DNS removal failed for {}: {}", lease.ip_address, e);
+        }
+    }
+
+    async fn send_nak(&self, packet: DhcpPacket) -> Result<()> {
+        let mut reply = self.create_reply_packet(&packet, DhcpMessageType::Nak, None);
+        // The relay has to broadcast a NAK since the client may have moved (RFC 2131 section 4.3.2)
+        if reply.giaddr != Ipv4Addr::UNSPECIFIED {
+            reply.flags |= 0x8000;
+        }
+        self.send_reply(reply, &packet).await?;
         warn!("NAK sent to {}", format_mac(&packet.get_client_mac()));
         Ok(())
     }
 
-    fn create_reply_packet(&self, request: &DhcpPacket, msg_type: DhcpMessageType) -> DhcpPacket {
+    /// The address that places the client in a subnet: the relay agent's for relayed packets,
+    /// otherwise the address of the interface the packet arrived on. With neither the client's
+    /// own address is used.
+    fn subnet_gateway(&self, packet: &DhcpPacket) -> Option<Ipv4Addr> {
+        relay_or_interface(packet, self.interface.as_ref())
+    }
+
+    /// Our server identifier for replies to `request`, see `server_identifier`
+    fn server_id(&self, subnet: Option<&DhcpSubnet>, request: &DhcpPacket) -> Ipv4Addr {
+        server_identifier(self.server_ip, subnet, request)
+    }
+
+    fn create_reply_packet(&self, request: &DhcpPacket, msg_type: DhcpMessageType, subnet: Option<&DhcpSubnet>) -> DhcpPacket {
+        let server_id = self.server_id(subnet, request);
         let mut reply = DhcpPacket::new();
         reply.op = 2; // BOOTREPLY
         reply.htype = request.htype;
@@ -289,18 +472,23 @@ impl DhcpServer {
         reply.flags = request.flags;
         reply.giaddr = request.giaddr;
         reply.chaddr = request.chaddr;
-        reply.siaddr = self.server_ip;
+        reply.siaddr = server_id;
 
         // Add message type
         reply.set_message_type(msg_type);
 
         // Add server identifier
-        reply.set_server_id(self.server_ip);
+        reply.set_server_id(server_id);
+
+        // Relay agents expect their information echoed back unchanged (RFC 3046 section 2.2)
+        if let Some(relay_info) = request.get_option(OPTION_RELAY_AGENT_INFO) {
+            reply.options.push(relay_info.clone());
+        }
 
         reply
     }
 
-    fn build_subnet_options(&self, subnet: &DhcpSubnet, _ip: Ipv4Addr) -> Result<Vec<DhcpOption>> {
+    fn build_subnet_options(&self, subnet: &DhcpSubnet, lease_time: Option<u32>) -> Result<Vec<DhcpOption>> {
         // Convert ipnetwork to ipnet for compatibility
         let network_str = format!("{}/{}", subnet.network.ip(), subnet.network.prefix());
         let network: Ipv4Net = network_str.parse()?;
@@ -310,10 +498,14 @@ impl DhcpServer {
         builder = builder
             .add_subnet_mask(options::calculate_subnet_mask(&network))
             .add_router(subnet.gateway)
-            .add_broadcast(options::calculate_broadcast(&network))
-            .add_lease_time(subnet.lease_duration as u32)
-            .add_renewal_time((subnet.lease_duration / 2) as u32)
-            .add_rebind_time((subnet.lease_duration * 7 / 8) as u32);
+            .add_broadcast(options::calculate_broadcast(&network));
+
+        if let Some(lease_time) = lease_time {
+            builder = builder
+                .add_lease_time(lease_time)
+                .add_renewal_time(lease_time / 2)
+                .add_rebind_time((lease_time as u64 * 7 / 8) as u32);
+        }
 
         if !subnet.dns_servers.is_empty() {
             builder = builder.add_dns_servers(subnet.dns_servers.clone());
@@ -323,28 +515,214 @@ impl DhcpServer {
             builder = builder.add_domain_name(domain);
         }
 
-        Ok(builder.build())
+        let mut options = builder.build();
+        apply_custom_options(&mut options, subnet);
+        Ok(options)
     }
 
-    async fn send_reply(&self, reply: DhcpPacket, broadcast: bool, _src: SocketAddr) -> Result<()> {
-        let data = reply.to_bytes();
+    async fn send_reply(&self, mut reply: DhcpPacket, request: &DhcpPacket) -> Result<()> {
+        // Option 82 goes after all other options; the sort is stable so the rest keep their order
+        reply.options.sort_by_key(|opt| opt.code == OPTION_RELAY_AGENT_INFO);
+        let data = reply.to_bytes_within(request.get_max_message_size());
 
-        let dest = if broadcast || reply.giaddr == Ipv4Addr::UNSPECIFIED {
-            SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), 68)
-        } else {
-            SocketAddr::new(IpAddr::V4(reply.giaddr), 67)
-        };
+        let dest = reply_destination(&reply, request);
 
         self.socket.send_to(&data, dest).await?;
         debug!("Sent DHCP reply to {}", dest);
 
+        let counters = metrics::counters();
+        match reply.get_message_type() {
+            Some(DhcpMessageType::Offer) => metrics::inc(&counters.dhcp_offer_total),
+            Some(DhcpMessageType::Ack) => metrics::inc(&counters.dhcp_ack_total),
+            Some(DhcpMessageType::Nak) => metrics::inc(&counters.dhcp_nak_total),
+            _ => {}
+        }
+
         Ok(())
     }
 }
 
-pub async fn start(settings: Arc<Settings>, db: PgPool) -> Result<()> {
-    let mut server = DhcpServer::new(settings, db).await?;
-    server.run().await
+/// Runs one server on `bind_address`, or one per entry in `dhcp.interfaces`, until `shutdown`
+pub async fn start(
+    settings: Arc<Settings>,
+    lease_manager: Arc<LeaseManager>,
+    dns_updates: Option<Arc<DhcpDnsIntegration>>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let interfaces = settings.dhcp.interfaces
+        .iter()
+        .map(|name| Interface::lookup(name).map(Some))
+        .collect::<Result<Vec<_>>>()?;
+    let interfaces = if interfaces.is_empty() { vec![None] } else { interfaces };
+
+    // One interface failing stops the others, as a single socket failing always did
+    let servers_shutdown = shutdown.child_token();
+    let mut servers = JoinSet::new();
+    for interface in interfaces {
+        let server = Arc::new(DhcpServer::new(
+            Arc::clone(&settings),
+            Arc::clone(&lease_manager),
+            dns_updates.clone(),
+            interface,
+        ).await?);
+        let server_shutdown = servers_shutdown.clone();
+        servers.spawn(async move { server.run(server_shutdown).await });
+    }
+
+    // Start cleanup task
+    let cleanup_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        let mut cleanup_interval = interval(Duration::from_secs(300));
+        loop {
+            tokio::select! {
+                _ = cleanup_shutdown.cancelled() => break,
+                _ = cleanup_interval.tick() => {}
+            }
+            if let Err(e) = lease_manager.cleanup_expired_leases().await {
+                error!("Failed to cleanup expired leases: {}", e);
+            }
+            if let Err(e) = lease_manager.cleanup_expired_declines().await {
+                error!("Failed to cleanup expired declines: {}", e);
+            }
+        }
+    });
+
+    while let Some(result) = servers.join_next().await {
+        if let Err(e) = result? {
+            servers_shutdown.cancel();
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+fn worker_count(threads: Option<usize>) -> usize {
+    threads.unwrap_or(DEFAULT_WORKERS).max(1)
+}
+
+fn relay_or_interface(packet: &DhcpPacket, interface: Option<&Interface>) -> Option<Ipv4Addr> {
+    if packet.giaddr != Ipv4Addr::UNSPECIFIED {
+        Some(packet.giaddr)
+    } else {
+        interface.map(|interface| interface.address)
+    }
+}
+
+/// A REQUEST in selecting state names the server whose offer the client took. Any other server
+/// must stay silent, not NAK (RFC 2131 section 4.3.2). Renewing and rebinding clients omit
+/// option 54 and are always answered.
+fn selects_other_server(request: &DhcpPacket, server_ip: Ipv4Addr) -> bool {
+    request.get_server_id().is_some_and(|server_id| server_id != server_ip)
+}
+
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_CLIENT_PORT: u16 = 68;
+
+/// Where a reply to `request` is sent (RFC 2131 section 4.1): relayed requests go back to the
+/// relay, configured clients (ciaddr set) are unicast, and everything else is broadcast. A
+/// client without an address can't answer the ARP query a unicast to yiaddr would need, and
+/// the server doesn't install ARP entries itself, so the broadcast flag is not consulted.
+fn reply_destination(reply: &DhcpPacket, request: &DhcpPacket) -> SocketAddr {
+    let broadcast = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), DHCP_CLIENT_PORT);
+
+    if request.giaddr != Ipv4Addr::UNSPECIFIED {
+        return SocketAddr::new(IpAddr::V4(request.giaddr), DHCP_SERVER_PORT);
+    }
+    if reply.get_message_type() == Some(DhcpMessageType::Nak) {
+        return broadcast;
+    }
+    if request.ciaddr != Ipv4Addr::UNSPECIFIED {
+        return SocketAddr::new(IpAddr::V4(request.ciaddr), DHCP_CLIENT_PORT);
+    }
+
+    broadcast
+}
+
+/// The server identifier (option 54) clients of `subnet` should see: the subnet's `server_ip`,
+/// else the address the socket is bound to, else the local address the kernel would send from
+/// toward the relay, the subnet's gateway or the client. Clients ignore replies identified as
+/// 0.0.0.0, which a server listening on every address would otherwise send.
+fn server_identifier(bound: Ipv4Addr, subnet: Option<&DhcpSubnet>, request: &DhcpPacket) -> Ipv4Addr {
+    if let Some(server_ip) = subnet.and_then(|subnet| subnet.server_ip) {
+        return server_ip;
+    }
+    if !bound.is_unspecified() {
+        return bound;
+    }
+
+    [Some(request.giaddr), subnet.map(|subnet| subnet.gateway), Some(request.ciaddr)]
+        .into_iter()
+        .flatten()
+        .filter(|target| !target.is_unspecified())
+        .find_map(local_address_toward)
+        .unwrap_or(bound)
+}
+
+/// The source address the routing table picks for packets to `target`. Connecting a UDP socket
+/// only does the route lookup; nothing is sent.
+fn local_address_toward(target: Ipv4Addr) -> Option<Ipv4Addr> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((target, DHCP_SERVER_PORT)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+        _ => None,
+    }
+}
+
+/// Clients without option 81 get both records; with it, the S and N flags decide
+fn dns_update_scope(request: &DhcpPacket) -> DnsUpdateScope {
+    match request.get_client_fqdn() {
+        None => DnsUpdateScope::ALL,
+        Some(fqdn) if fqdn.no_server_updates => DnsUpdateScope::NONE,
+        Some(fqdn) if fqdn.server_updates_forward => DnsUpdateScope::ALL,
+        Some(_) => DnsUpdateScope::REVERSE_ONLY,
+    }
+}
+
+/// Option 61 as stored on the lease, back to bytes
+fn client_id_bytes(lease: &DhcpLease) -> Option<Vec<u8>> {
+    lease.client_identifier.as_deref()?
+        .split(':')
+        .map(|b| u8::from_str_radix(b, 16).ok())
+        .collect()
+}
+
+/// The identity a lease's DNS names are tied to: option 61 when the client sent one,
+/// otherwise its Ethernet address (RFC 4701 section 3.3)
+fn dns_client<'a>(mac: &'a [u8], client_id: Option<&'a [u8]>) -> ClientIdentity<'a> {
+    match client_id {
+        Some(id) if !id.is_empty() => ClientIdentity::ClientId(id),
+        _ => ClientIdentity::Hardware { htype: 1, chaddr: mac },
+    }
+}
+
+/// Next-server and boot file for network booting clients, in both the BOOTP header
+/// (siaddr/file) and options 66/67 since PXE ROMs differ in which they read
+fn add_boot_options(reply: &mut DhcpPacket, subnet: &DhcpSubnet, request: &DhcpPacket) {
+    if let Some(boot_server) = subnet.boot_server {
+        reply.siaddr = boot_server;
+        reply.set_option(options::OPTION_TFTP_SERVER, boot_server.to_string().into_bytes());
+    }
+
+    let vendor_class = request.get_vendor_class();
+    if let Some(filename) = subnet.boot_filename_for(vendor_class.as_deref(), request.get_client_arch()) {
+        reply.set_boot_file(filename);
+        reply.set_option(options::OPTION_BOOTFILE, filename.as_bytes().to_vec());
+    }
+}
+
+/// Appends the subnet's `dhcp_options`, each replacing a built-in option with the same code.
+/// Values that don't encode are logged and skipped rather than failing the reply.
+fn apply_custom_options(options: &mut Vec<DhcpOption>, subnet: &DhcpSubnet) {
+    for (&code, value) in &subnet.dhcp_options {
+        match value.encode() {
+            Ok(data) => {
+                options.retain(|opt| opt.code != code);
+                options.push(DhcpOption { code, data });
+            }
+            Err(e) => warn!("Skipping DHCP option {} of subnet {}: {}", code, subnet.name, e),
+        }
+    }
 }
 
 fn format_mac(mac: &[u8]) -> String {
@@ -353,4 +731,238 @@ fn format_mac(mac: &[u8]) -> String {
         .map(|b| format!("{:02x}", b))
         .collect::<Vec<_>>()
         .join(":")
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::{BootRule, DhcpOptionValue};
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn pxe_subnet() -> DhcpSubnet {
+        DhcpSubnet {
+            id: Uuid::new_v4(),
+            name: "lab".to_string(),
+            network: "10.0.0.0/24".parse().unwrap(),
+            start_ip: Ipv4Addr::new(10, 0, 0, 100),
+            end_ip: Ipv4Addr::new(10, 0, 0, 200),
+            gateway: Ipv4Addr::new(10, 0, 0, 1),
+            dns_servers: vec![],
+            domain_name: None,
+            lease_duration: 3600,
+            vlan_id: None,
+            ipv6_prefix: None,
+            enabled: true,
+            maintenance: false,
+            relay_circuit_id: None,
+            server_ip: None,
+            boot_server: Some(Ipv4Addr::new(10, 0, 0, 5)),
+            boot_filename: Some("pxelinux.0".to_string()),
+            boot_rules: vec![
+                BootRule { vendor_class: Some("PXEClient".to_string()), arch: Some(7), filename: "efi/grubx64.efi".to_string() },
+                BootRule { vendor_class: Some("HTTPClient".to_string()), arch: None, filename: "http://10.0.0.5/boot.efi".to_string() },
+            ],
+            excluded_ranges: vec![],
+            dhcp_options: Default::default(),
+            description: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn boot_file(packet: &DhcpPacket) -> Option<String> {
+        packet.get_option(options::OPTION_BOOTFILE)
+            .map(|opt| String::from_utf8(opt.data.clone()).unwrap())
+    }
+
+    #[test]
+    fn test_boot_options_in_offer_and_ack() {
+        let subnet = pxe_subnet();
+
+        // A BIOS PXE client gets the default file
+        let mut request = DhcpPacket::new();
+        request.set_option(options::OPTION_VENDOR_CLASS, b"PXEClient:Arch:00000:UNDI:002001".to_vec());
+        request.set_option(options::OPTION_CLIENT_ARCH, vec![0, 0]);
+        let mut offer = DhcpPacket::new();
+        offer.set_message_type(DhcpMessageType::Offer);
+        add_boot_options(&mut offer, &subnet, &request);
+
+        assert_eq!(offer.siaddr, Ipv4Addr::new(10, 0, 0, 5));
+        assert_eq!(offer.get_option(options::OPTION_TFTP_SERVER).unwrap().data, b"10.0.0.5");
+        assert_eq!(boot_file(&offer).as_deref(), Some("pxelinux.0"));
+        assert_eq!(&offer.file[..11], b"pxelinux.0\0");
+
+        // An x86-64 UEFI client gets its rule's file, and it survives serializing the ACK
+        request.set_option(options::OPTION_CLIENT_ARCH, vec![0, 7]);
+        let mut ack = DhcpPacket::new();
+        ack.set_message_type(DhcpMessageType::Ack);
+        add_boot_options(&mut ack, &subnet, &request);
+        let ack = DhcpPacket::parse(&ack.to_bytes()).unwrap();
+        assert_eq!(boot_file(&ack).as_deref(), Some("efi/grubx64.efi"));
+        assert!(ack.file.starts_with(b"efi/grubx64.efi\0"));
+
+        // A rule without an architecture matches on vendor class alone
+        request.set_option(options::OPTION_VENDOR_CLASS, b"HTTPClient:Arch:00016".to_vec());
+        let mut ack = DhcpPacket::new();
+        add_boot_options(&mut ack, &subnet, &request);
+        assert_eq!(boot_file(&ack).as_deref(), Some("http://10.0.0.5/boot.efi"));
+    }
+
+    #[test]
+    fn test_server_identifier() {
+        let mut subnet = pxe_subnet();
+        let request = DhcpPacket::new();
+        let bound = Ipv4Addr::new(10, 0, 0, 2);
+
+        // A server listening on one address identifies itself by it
+        assert_eq!(server_identifier(bound, Some(&subnet), &request), bound);
+
+        // Listening on every address, the route toward the subnet decides
+        subnet.gateway = Ipv4Addr::LOCALHOST;
+        assert_eq!(server_identifier(Ipv4Addr::UNSPECIFIED, Some(&subnet), &request), Ipv4Addr::LOCALHOST);
+
+        // A configured address always wins
+        subnet.server_ip = Some(Ipv4Addr::new(10, 0, 0, 3));
+        assert_eq!(server_identifier(bound, Some(&subnet), &request), Ipv4Addr::new(10, 0, 0, 3));
+    }
+
+    #[test]
+    fn test_worker_count() {
+        assert_eq!(worker_count(None), DEFAULT_WORKERS);
+        assert_eq!(worker_count(Some(8)), 8);
+        assert_eq!(worker_count(Some(0)), 1);
+    }
+
+    #[test]
+    fn test_relay_or_interface() {
+        let interface = Interface { name: "eth1".to_string(), address: Ipv4Addr::new(10, 0, 1, 1) };
+        let mut packet = DhcpPacket::new();
+
+        // Direct clients are placed by the interface, or by their own address without one
+        assert_eq!(relay_or_interface(&packet, Some(&interface)), Some(Ipv4Addr::new(10, 0, 1, 1)));
+        assert_eq!(relay_or_interface(&packet, None), None);
+
+        // A relay address always wins
+        packet.giaddr = Ipv4Addr::new(10, 0, 2, 1);
+        assert_eq!(relay_or_interface(&packet, Some(&interface)), Some(Ipv4Addr::new(10, 0, 2, 1)));
+        assert_eq!(relay_or_interface(&packet, None), Some(Ipv4Addr::new(10, 0, 2, 1)));
+    }
+
+    #[test]
+    fn test_request_for_other_server() {
+        let server_ip = Ipv4Addr::new(10, 0, 0, 2);
+
+        let mut request = DhcpPacket::new();
+        request.set_message_type(DhcpMessageType::Request);
+        assert!(!selects_other_server(&request, server_ip));
+
+        request.set_server_id(server_ip);
+        assert!(!selects_other_server(&request, server_ip));
+
+        request.set_server_id(Ipv4Addr::new(10, 0, 0, 3));
+        assert!(selects_other_server(&request, server_ip));
+    }
+
+    fn reply_to(request: &DhcpPacket, msg_type: DhcpMessageType, yiaddr: Ipv4Addr) -> DhcpPacket {
+        let mut reply = DhcpPacket::new();
+        reply.set_message_type(msg_type);
+        reply.giaddr = request.giaddr;
+        reply.flags = request.flags;
+        reply.yiaddr = yiaddr;
+        reply
+    }
+
+    #[test]
+    fn test_reply_destination() {
+        let offered = Ipv4Addr::new(10, 0, 0, 150);
+        let broadcast: SocketAddr = "255.255.255.255:68".parse().unwrap();
+
+        // Relayed: back to the relay agent on the server port, whatever the message
+        let mut relayed = DhcpPacket::new();
+        relayed.giaddr = Ipv4Addr::new(10, 1, 0, 1);
+        relayed.ciaddr = Ipv4Addr::new(10, 1, 0, 50);
+        for msg_type in [DhcpMessageType::Offer, DhcpMessageType::Ack, DhcpMessageType::Nak] {
+            let reply = reply_to(&relayed, msg_type, offered);
+            assert_eq!(reply_destination(&reply, &relayed), "10.1.0.1:67".parse().unwrap());
+        }
+
+        // Renewing client that already has its address: unicast to ciaddr
+        let mut renewing = DhcpPacket::new();
+        renewing.ciaddr = Ipv4Addr::new(10, 0, 0, 42);
+        let reply = reply_to(&renewing, DhcpMessageType::Ack, renewing.ciaddr);
+        assert_eq!(reply_destination(&reply, &renewing), "10.0.0.42:68".parse().unwrap());
+
+        // A NAK is always broadcast when not relayed
+        let reply = reply_to(&renewing, DhcpMessageType::Nak, Ipv4Addr::UNSPECIFIED);
+        assert_eq!(reply_destination(&reply, &renewing), broadcast);
+
+        // A client that can't take unicast before it's configured sets the broadcast flag
+        let mut discovering = DhcpPacket::new();
+        discovering.flags = 0x8000;
+        let reply = reply_to(&discovering, DhcpMessageType::Offer, offered);
+        assert_eq!(reply_destination(&reply, &discovering), broadcast);
+
+        // Broadcast flag clear: still broadcast, the client can't answer ARP for yiaddr yet
+        discovering.flags = 0;
+        for msg_type in [DhcpMessageType::Offer, DhcpMessageType::Ack] {
+            let reply = reply_to(&discovering, msg_type, offered);
+            assert_eq!(reply_destination(&reply, &discovering), broadcast);
+        }
+
+        // Nothing to unicast to: broadcast
+        let reply = reply_to(&discovering, DhcpMessageType::Ack, Ipv4Addr::UNSPECIFIED);
+        assert_eq!(reply_destination(&reply, &discovering), broadcast);
+    }
+
+    #[test]
+    fn test_no_boot_options_without_configuration() {
+        let subnet = DhcpSubnet { boot_server: None, boot_filename: None, boot_rules: vec![], ..pxe_subnet() };
+        let mut reply = DhcpPacket::new();
+        reply.siaddr = Ipv4Addr::new(10, 0, 0, 1);
+        add_boot_options(&mut reply, &subnet, &DhcpPacket::new());
+
+        assert_eq!(reply.siaddr, Ipv4Addr::new(10, 0, 0, 1));
+        assert!(reply.get_option(options::OPTION_TFTP_SERVER).is_none());
+        assert!(reply.get_option(options::OPTION_BOOTFILE).is_none());
+        assert_eq!(reply.file, [0; 128]);
+    }
+
+    #[test]
+    fn test_custom_options_replace_built_in_ones() {
+        let ntp = Ipv4Addr::new(10, 0, 0, 2);
+        let mut subnet = pxe_subnet();
+        subnet.dhcp_options.insert(42, DhcpOptionValue::Ips(vec![ntp]));
+        subnet.dhcp_options.insert(options::OPTION_DNS_SERVERS, DhcpOptionValue::Hex("0a:00:00:35".to_string()));
+        subnet.dhcp_options.insert(43, DhcpOptionValue::Hex("odd".to_string()));
+
+        let mut built = vec![
+            DhcpOption { code: options::OPTION_DNS_SERVERS, data: vec![10, 0, 0, 1] },
+            DhcpOption { code: options::OPTION_DOMAIN_NAME, data: b"example.com".to_vec() },
+        ];
+        apply_custom_options(&mut built, &subnet);
+
+        let find = |code: u8| built.iter().filter(|opt| opt.code == code).collect::<Vec<_>>();
+        assert_eq!(find(options::OPTION_DNS_SERVERS).len(), 1);
+        assert_eq!(find(options::OPTION_DNS_SERVERS)[0].data, vec![10, 0, 0, 53]);
+        assert_eq!(find(42)[0].data, ntp.octets().to_vec());
+        assert_eq!(find(options::OPTION_DOMAIN_NAME).len(), 1);
+        assert!(find(43).is_empty());
+    }
+
+    #[test]
+    fn test_dns_update_scope() {
+        let mut request = DhcpPacket::new();
+        assert_eq!(dns_update_scope(&request), DnsUpdateScope::ALL);
+
+        request.set_option(options::OPTION_CLIENT_FQDN, b"\x01\0\0pc.example.com".to_vec());
+        assert_eq!(dns_update_scope(&request), DnsUpdateScope::ALL);
+
+        // The client updates its own A record
+        request.set_option(options::OPTION_CLIENT_FQDN, b"\x00\0\0pc.example.com".to_vec());
+        assert_eq!(dns_update_scope(&request), DnsUpdateScope::REVERSE_ONLY);
+
+        request.set_option(options::OPTION_CLIENT_FQDN, b"\x08\0\0pc.example.com".to_vec());
+        assert_eq!(dns_update_scope(&request), DnsUpdateScope::NONE);
+    }
+}
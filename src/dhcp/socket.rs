@@ -0,0 +1,94 @@
+// UDP sockets for the DHCP server, optionally bound to a single interface
+use anyhow::{Result, anyhow};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::net::UdpSocket;
+
+/// An interface from `dhcp.interfaces` and the address the server answers from on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    pub name: String,
+    pub address: Ipv4Addr,
+}
+
+impl Interface {
+    /// Looks the interface up and takes its first IPv4 address
+    pub fn lookup(name: &str) -> Result<Self> {
+        let interface = pnet::datalink::interfaces()
+            .into_iter()
+            .find(|interface| interface.name == name)
+            .ok_or_else(|| anyhow!("No such network interface: {}", name))?;
+
+        let address = first_ipv4(interface.ips.iter().map(|network| network.ip()))
+            .ok_or_else(|| anyhow!("Interface {} has no IPv4 address", name))?;
+
+        Ok(Self { name: name.to_string(), address })
+    }
+}
+
+fn first_ipv4(addresses: impl IntoIterator<Item = IpAddr>) -> Option<Ipv4Addr> {
+    addresses.into_iter().find_map(|address| match address {
+        IpAddr::V4(address) if !address.is_loopback() && !address.is_link_local() => Some(address),
+        _ => None,
+    })
+}
+
+/// A broadcast-capable socket on `addr`. SO_REUSEADDR and SO_REUSEPORT let several sockets
+/// share port 67, one per interface or per worker. With an interface the socket only sees
+/// packets that arrived on it (SO_BINDTODEVICE, Linux only), which is how a broadcast from a
+/// directly connected client is tied to a subnet.
+pub fn bind(addr: SocketAddr, interface: Option<&str>) -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_broadcast(true)?;
+
+    if let Some(interface) = interface {
+        bind_device(&socket, interface)?;
+    }
+
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+
+    let std_socket: std::net::UdpSocket = socket.into();
+    Ok(UdpSocket::from_std(std_socket)?)
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+pub(crate) fn bind_device(socket: &Socket, interface: &str) -> Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+        .map_err(|e| anyhow!("Failed to bind to interface {} (needs CAP_NET_RAW): {}", interface, e))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+pub(crate) fn bind_device(_socket: &Socket, interface: &str) -> Result<()> {
+    Err(anyhow!("Binding to interface {} is only supported on Linux", interface))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_ipv4() {
+        let addresses: Vec<IpAddr> = vec![
+            "fe80::1".parse().unwrap(),
+            "169.254.10.1".parse().unwrap(),
+            "192.168.10.1".parse().unwrap(),
+            "192.168.20.1".parse().unwrap(),
+        ];
+        assert_eq!(first_ipv4(addresses), Some(Ipv4Addr::new(192, 168, 10, 1)));
+        assert_eq!(first_ipv4(vec!["127.0.0.1".parse().unwrap()]), None);
+    }
+
+    #[tokio::test]
+    async fn test_sockets_share_a_port() {
+        let first = bind("127.0.0.1:0".parse().unwrap(), None).unwrap();
+        let addr = first.local_addr().unwrap();
+
+        // A second worker can bind the same port
+        let second = bind(addr, None).unwrap();
+        assert_eq!(second.local_addr().unwrap(), addr);
+    }
+}
@@ -0,0 +1,246 @@
+// LRU cache of upstream DNS answers, honoring record TTLs
+use crate::dns::message::{self, DnsResourceRecord};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+impl CacheKey {
+    pub fn new(name: &str, qtype: u16, qclass: u16) -> Self {
+        Self {
+            name: message::normalize_name(name),
+            qtype,
+            qclass,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+struct CacheEntry {
+    answers: Vec<DnsResourceRecord>,
+    inserted_at: Instant,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    entries: HashMap<CacheKey, CacheEntry>,
+    // Recency order: access tick -> key, oldest first
+    recency: BTreeMap<u64, CacheKey>,
+    tick: u64,
+}
+
+pub struct DnsCache {
+    capacity: usize,
+    inner: Mutex<CacheInner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl DnsCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(CacheInner::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Vec<DnsResourceRecord>> {
+        self.get_at(key, Instant::now())
+    }
+
+    pub fn insert(&self, key: CacheKey, answers: Vec<DnsResourceRecord>) {
+        self.insert_at(key, answers, Instant::now())
+    }
+
+    /// Cached answers with TTLs reduced by the time spent in the cache.
+    /// An entry is expired from the instant its lowest TTL has fully elapsed.
+    pub fn get_at(&self, key: &CacheKey, now: Instant) -> Option<Vec<DnsResourceRecord>> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+
+        let expired = match inner.entries.get(key) {
+            Some(entry) => now >= entry.expires_at,
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+
+        if expired {
+            if let Some(entry) = inner.entries.remove(key) {
+                inner.recency.remove(&entry.last_used);
+            }
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(key)?;
+        inner.recency.remove(&entry.last_used);
+        inner.recency.insert(tick, key.clone());
+        entry.last_used = tick;
+
+        let elapsed = now.duration_since(entry.inserted_at).as_secs() as u32;
+        let answers = entry.answers
+            .iter()
+            .map(|rr| DnsResourceRecord { ttl: rr.ttl.saturating_sub(elapsed), ..rr.clone() })
+            .collect();
+
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(answers)
+    }
+
+    /// Stores an answer section until its lowest TTL runs out; zero-TTL or empty answers aren't cached
+    pub fn insert_at(&self, key: CacheKey, answers: Vec<DnsResourceRecord>, now: Instant) {
+        let ttl = match answers.iter().map(|rr| rr.ttl).min() {
+            Some(ttl) if ttl > 0 => ttl,
+            _ => return,
+        };
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+
+        if let Some(old) = inner.entries.remove(&key) {
+            inner.recency.remove(&old.last_used);
+        }
+
+        while inner.entries.len() >= self.capacity {
+            let Some((_, oldest)) = inner.recency.pop_first() else { break };
+            inner.entries.remove(&oldest);
+        }
+
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.recency.insert(tick, key.clone());
+        inner.entries.insert(key, CacheEntry {
+            answers,
+            inserted_at: now,
+            expires_at: now + Duration::from_secs(ttl as u64),
+            last_used: tick,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+static SHARED_CACHE: OnceLock<Arc<DnsCache>> = OnceLock::new();
+
+/// Cache used by the running DNS server, created on first use with `capacity` entries
+pub fn shared(capacity: usize) -> Arc<DnsCache> {
+    SHARED_CACHE.get_or_init(|| Arc::new(DnsCache::new(capacity))).clone()
+}
+
+/// Counters for the running DNS server's cache, if the server has started
+pub fn shared_stats() -> Option<CacheStats> {
+    SHARED_CACHE.get().map(|cache| cache.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{TYPE_A, CLASS_IN};
+    use std::net::Ipv4Addr;
+
+    fn answer(name: &str, ttl: u32) -> Vec<DnsResourceRecord> {
+        vec![DnsResourceRecord::new(name, TYPE_A, ttl, message::rdata_a(Ipv4Addr::new(192, 0, 2, 1)))]
+    }
+
+    #[test]
+    fn test_entry_expires_exactly_at_ttl() {
+        let cache = DnsCache::new(10);
+        let key = CacheKey::new("example.org", TYPE_A, CLASS_IN);
+        let start = Instant::now();
+
+        cache.insert_at(key.clone(), answer("example.org", 30), start);
+
+        let just_before = start + Duration::from_secs(30) - Duration::from_nanos(1);
+        let hit = cache.get_at(&key, just_before).unwrap();
+        assert_eq!(hit[0].ttl, 1);
+
+        assert!(cache.get_at(&key, start + Duration::from_secs(30)).is_none());
+        assert!(cache.is_empty());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+    }
+
+    #[test]
+    fn test_ttl_counts_down() {
+        let cache = DnsCache::new(10);
+        let key = CacheKey::new("Example.ORG.", TYPE_A, CLASS_IN);
+        let start = Instant::now();
+
+        cache.insert_at(key, answer("example.org", 300), start);
+
+        let lookup = CacheKey::new("example.org", TYPE_A, CLASS_IN);
+        let hit = cache.get_at(&lookup, start + Duration::from_secs(100)).unwrap();
+        assert_eq!(hit[0].ttl, 200);
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let cache = DnsCache::new(2);
+        let start = Instant::now();
+        let a = CacheKey::new("a.example", TYPE_A, CLASS_IN);
+        let b = CacheKey::new("b.example", TYPE_A, CLASS_IN);
+        let c = CacheKey::new("c.example", TYPE_A, CLASS_IN);
+
+        cache.insert_at(a.clone(), answer("a.example", 60), start);
+        cache.insert_at(b.clone(), answer("b.example", 60), start);
+        assert!(cache.get_at(&a, start).is_some());
+
+        cache.insert_at(c.clone(), answer("c.example", 60), start);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get_at(&a, start).is_some());
+        assert!(cache.get_at(&b, start).is_none());
+        assert!(cache.get_at(&c, start).is_some());
+    }
+
+    #[test]
+    fn test_zero_ttl_is_not_cached() {
+        let cache = DnsCache::new(10);
+        let key = CacheKey::new("example.org", TYPE_A, CLASS_IN);
+        cache.insert_at(key.clone(), answer("example.org", 0), Instant::now());
+        assert!(cache.is_empty());
+    }
+}
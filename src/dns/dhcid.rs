@@ -0,0 +1,92 @@
+// DHCID (RFC 4701) values recording which DHCP client owns a dynamically registered name
+use crate::dns::message;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use sha2::{Digest, Sha256};
+use anyhow::Result;
+
+const DIGEST_SHA256: u8 = 1;
+
+/// What identifies the client, in order of preference (RFC 4701 section 3.3)
+#[derive(Debug, Clone, Copy)]
+pub enum ClientIdentity<'a> {
+    /// Option 61 contents, type byte included
+    ClientId(&'a [u8]),
+    /// Hardware type and client hardware address, for clients without option 61
+    Hardware { htype: u8, chaddr: &'a [u8] },
+    /// DHCPv6 DUID
+    Duid(&'a [u8]),
+}
+
+impl ClientIdentity<'_> {
+    fn type_code(&self) -> u16 {
+        match self {
+            Self::Hardware { .. } => 0x0000,
+            Self::ClientId(_) => 0x0001,
+            Self::Duid(_) => 0x0002,
+        }
+    }
+}
+
+/// The base64 DHCID RDATA for `client` registering `fqdn`: identifier type, digest type and
+/// SHA-256 over the identifier and the lowercased name in wire format. Two clients only share
+/// a value if they share an identifier, so it can stand in for the client when deciding who
+/// may update a name (RFC 4703).
+pub fn dhcid(client: ClientIdentity<'_>, fqdn: &str) -> Result<String> {
+    let mut name = Vec::new();
+    message::encode_name(&message::normalize_name(fqdn), &mut name)?;
+
+    let mut hasher = Sha256::new();
+    match client {
+        ClientIdentity::Hardware { htype, chaddr } => {
+            hasher.update([htype]);
+            hasher.update(chaddr);
+        }
+        ClientIdentity::ClientId(id) | ClientIdentity::Duid(id) => hasher.update(id),
+    }
+    hasher.update(&name);
+
+    let mut rdata = client.type_code().to_be_bytes().to_vec();
+    rdata.push(DIGEST_SHA256);
+    rdata.extend_from_slice(&hasher.finalize());
+    Ok(BASE64.encode(rdata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc4701_examples() {
+        // Section 3.6: a DUID, a client identifier and a hardware address
+        let duid = [0x00, 0x01, 0x00, 0x06, 0x41, 0x2d, 0xf1, 0x66, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+        assert_eq!(
+            dhcid(ClientIdentity::Duid(&duid), "chi6.example.com").unwrap(),
+            "AAIBY2/AuCccgoJbsaxcQc9TUapptP69lOjxfNuVAA2kjEA="
+        );
+
+        let client_id = [0x01, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c];
+        assert_eq!(
+            dhcid(ClientIdentity::ClientId(&client_id), "chi.example.com.").unwrap(),
+            "AAEBOSD+XR3Os/0LozeXVqcNc7FwCfQdWL3b/NaiUDlW2No="
+        );
+
+        let chaddr = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+        assert_eq!(
+            dhcid(ClientIdentity::Hardware { htype: 1, chaddr: &chaddr }, "client.example.com").unwrap(),
+            "AAABxLmlskllE0MVjd57zHcWmEH3pCQ6VytcKD//7es/deY="
+        );
+    }
+
+    #[test]
+    fn test_name_case_is_ignored() {
+        let chaddr = [0x02, 0, 0, 0, 0, 1];
+        let client = ClientIdentity::Hardware { htype: 1, chaddr: &chaddr };
+        assert_eq!(dhcid(client, "PC.Example.com").unwrap(), dhcid(client, "pc.example.com.").unwrap());
+
+        let other = [0x02, 0, 0, 0, 0, 2];
+        assert_ne!(
+            dhcid(client, "pc.example.com").unwrap(),
+            dhcid(ClientIdentity::Hardware { htype: 1, chaddr: &other }, "pc.example.com").unwrap()
+        );
+    }
+}
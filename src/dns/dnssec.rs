@@ -0,0 +1,526 @@
+// Online DNSSEC signing (RFC 4033-4035) with ECDSA P-256/SHA-256 (algorithm 13)
+use crate::dns::message::{
+    self, DnsResourceRecord, TYPE_CNAME, TYPE_DNSKEY, TYPE_MX, TYPE_NS, TYPE_NSEC, TYPE_PTR,
+    TYPE_RRSIG, TYPE_SOA, TYPE_SRV,
+};
+use anyhow::{anyhow, Result};
+use ring::rand::SystemRandom;
+use ring::signature::{
+    EcdsaKeyPair, KeyPair, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED,
+    ECDSA_P256_SHA256_FIXED_SIGNING,
+};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub const ALGORITHM_ECDSAP256SHA256: u8 = 13;
+pub const DIGEST_SHA256: u8 = 2;
+pub const FLAGS_ZSK: u16 = 256;
+pub const FLAGS_KSK: u16 = 257;
+
+/// Signatures are valid for two weeks and refreshed once half of that has elapsed
+pub const SIGNATURE_VALIDITY: u32 = 14 * 24 * 3600;
+/// Back-date inception to tolerate resolvers with slightly slow clocks
+const INCEPTION_SKEW: u32 = 3600;
+
+pub struct ZoneKey {
+    pub flags: u16,
+    pkcs8: Vec<u8>,
+    key_pair: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl ZoneKey {
+    pub fn generate(flags: u16) -> Result<Self> {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .map_err(|_| anyhow!("Failed to generate DNSSEC key"))?;
+        Self::from_pkcs8(flags, pkcs8.as_ref())
+    }
+
+    pub fn from_pkcs8(flags: u16, pkcs8: &[u8]) -> Result<Self> {
+        let rng = SystemRandom::new();
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &rng)
+            .map_err(|e| anyhow!("Invalid DNSSEC private key: {}", e))?;
+
+        Ok(Self {
+            flags,
+            pkcs8: pkcs8.to_vec(),
+            key_pair,
+            rng,
+        })
+    }
+
+    pub fn is_ksk(&self) -> bool {
+        self.flags & 0x0001 != 0
+    }
+
+    pub fn pkcs8(&self) -> &[u8] {
+        &self.pkcs8
+    }
+
+    /// Public key as carried in DNSKEY: the uncompressed point without its 0x04 prefix
+    pub fn public_key(&self) -> &[u8] {
+        &self.key_pair.public_key().as_ref()[1..]
+    }
+
+    pub fn dnskey_rdata(&self) -> Vec<u8> {
+        let mut rdata = self.flags.to_be_bytes().to_vec();
+        rdata.push(3); // protocol
+        rdata.push(ALGORITHM_ECDSAP256SHA256);
+        rdata.extend_from_slice(self.public_key());
+        rdata
+    }
+
+    pub fn key_tag(&self) -> u16 {
+        key_tag(&self.dnskey_rdata())
+    }
+
+    /// DS RDATA (SHA-256 digest) to publish in the parent zone
+    pub fn ds_rdata(&self, zone: &str) -> Result<Vec<u8>> {
+        let mut hasher = Sha256::new();
+        hasher.update(canonical_name(zone)?);
+        hasher.update(self.dnskey_rdata());
+
+        let mut rdata = self.key_tag().to_be_bytes().to_vec();
+        rdata.push(ALGORITHM_ECDSAP256SHA256);
+        rdata.push(DIGEST_SHA256);
+        rdata.extend_from_slice(&hasher.finalize());
+        Ok(rdata)
+    }
+
+    pub fn sign_rrset(
+        &self,
+        signer: &str,
+        rrset: &[DnsResourceRecord],
+        inception: u32,
+        expiration: u32,
+    ) -> Result<DnsResourceRecord> {
+        let first = rrset.first().ok_or_else(|| anyhow!("Cannot sign an empty RRset"))?;
+
+        let mut rdata = rrsig_header(first, signer, self.key_tag(), inception, expiration)?;
+        let data = signed_data(&rdata, rrset)?;
+        let signature = self.key_pair
+            .sign(&self.rng, &data)
+            .map_err(|_| anyhow!("DNSSEC signing failed for {}", first.name))?;
+        rdata.extend_from_slice(signature.as_ref());
+
+        Ok(DnsResourceRecord::new(&first.name, TYPE_RRSIG, first.ttl, rdata))
+    }
+}
+
+/// Key tag calculation from RFC 4034 Appendix B
+pub fn key_tag(dnskey_rdata: &[u8]) -> u16 {
+    let mut ac: u32 = 0;
+    for (i, byte) in dnskey_rdata.iter().enumerate() {
+        ac += if i & 1 == 1 { *byte as u32 } else { (*byte as u32) << 8 };
+    }
+    ac += (ac >> 16) & 0xFFFF;
+    (ac & 0xFFFF) as u16
+}
+
+/// Checks an RRSIG over `rrset` against an algorithm 13 DNSKEY
+pub fn verify_rrsig(
+    dnskey_rdata: &[u8],
+    rrsig: &DnsResourceRecord,
+    rrset: &[DnsResourceRecord],
+) -> Result<()> {
+    if dnskey_rdata.len() != 68 || dnskey_rdata[3] != ALGORITHM_ECDSAP256SHA256 {
+        return Err(anyhow!("Unsupported DNSKEY"));
+    }
+    if rrsig.rdata.len() < 18 {
+        return Err(anyhow!("Truncated RRSIG"));
+    }
+
+    let signature_start = 18 + name_wire_len(&rrsig.rdata[18..])?;
+    let (header, signature) = rrsig.rdata.split_at(signature_start);
+    let data = signed_data(header, rrset)?;
+
+    let mut public_key = vec![0x04];
+    public_key.extend_from_slice(&dnskey_rdata[4..]);
+
+    UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, &public_key)
+        .verify(&data, signature)
+        .map_err(|_| anyhow!("DNSSEC signature verification failed"))
+}
+
+/// Signatures and NSEC chain for a zone, rebuilt whenever the zone's contents change
+pub struct SignedZone {
+    pub dnskeys: Vec<DnsResourceRecord>,
+    pub serial: u32,
+    pub signed_at: u32,
+    signatures: HashMap<(String, u16), Vec<DnsResourceRecord>>,
+    nsec: Vec<DnsResourceRecord>,
+}
+
+impl SignedZone {
+    /// Signs every RRset in `records` (which must include the SOA) and builds the NSEC chain.
+    /// The DNSKEY RRset is signed by KSKs, everything else by ZSKs; a lone key signs both.
+    pub fn sign(
+        zone: &str,
+        keys: &[ZoneKey],
+        records: &[DnsResourceRecord],
+        nsec_ttl: u32,
+        now: u32,
+    ) -> Result<Self> {
+        if keys.is_empty() {
+            return Err(anyhow!("No DNSSEC keys for zone {}", zone));
+        }
+
+        let zone = message::normalize_name(zone);
+        let serial = records
+            .iter()
+            .find(|rr| rr.rtype == TYPE_SOA && message::normalize_name(&rr.name) == zone)
+            .and_then(|soa| soa_serial(&soa.rdata))
+            .ok_or_else(|| anyhow!("Zone {} has no SOA record", zone))?;
+
+        let dnskeys: Vec<DnsResourceRecord> = keys
+            .iter()
+            .map(|key| DnsResourceRecord::new(&zone, TYPE_DNSKEY, nsec_ttl, key.dnskey_rdata()))
+            .collect();
+
+        let mut rrsets: BTreeMap<(String, u16), Vec<DnsResourceRecord>> = BTreeMap::new();
+        for rr in records.iter().chain(&dnskeys) {
+            rrsets
+                .entry((message::normalize_name(&rr.name), rr.rtype))
+                .or_default()
+                .push(rr.clone());
+        }
+
+        let nsec = build_nsec_chain(&zone, &rrsets, nsec_ttl)?;
+        for rr in &nsec {
+            rrsets.insert((rr.name.clone(), TYPE_NSEC), vec![rr.clone()]);
+        }
+
+        let ksks: Vec<&ZoneKey> = keys.iter().filter(|k| k.is_ksk()).collect();
+        let zsks: Vec<&ZoneKey> = keys.iter().filter(|k| !k.is_ksk()).collect();
+        let inception = now.saturating_sub(INCEPTION_SKEW);
+        let expiration = now.saturating_add(SIGNATURE_VALIDITY);
+
+        let mut signatures = HashMap::new();
+        for ((owner, rtype), rrset) in &rrsets {
+            let signers = match (*rtype == TYPE_DNSKEY, ksks.is_empty(), zsks.is_empty()) {
+                (true, false, _) | (false, _, true) => &ksks,
+                _ => &zsks,
+            };
+
+            let mut sigs = Vec::with_capacity(signers.len());
+            for key in signers {
+                sigs.push(key.sign_rrset(&zone, rrset, inception, expiration)?);
+            }
+            signatures.insert((owner.clone(), *rtype), sigs);
+        }
+
+        Ok(Self {
+            dnskeys,
+            serial,
+            signed_at: now,
+            signatures,
+            nsec,
+        })
+    }
+
+    pub fn rrsigs(&self, owner: &str, rtype: u16) -> &[DnsResourceRecord] {
+        self.signatures
+            .get(&(message::normalize_name(owner), rtype))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    pub fn nsec_at(&self, owner: &str) -> Option<&DnsResourceRecord> {
+        let owner = message::normalize_name(owner);
+        self.nsec.iter().find(|rr| rr.name == owner)
+    }
+
+    /// The NSEC whose owner..next interval covers `name` (the last owner sorting at or before it)
+    pub fn covering_nsec(&self, name: &str) -> Option<&DnsResourceRecord> {
+        self.nsec
+            .iter()
+            .take_while(|rr| canonical_cmp(&rr.name, name) != Ordering::Greater)
+            .last()
+            .or_else(|| self.nsec.last())
+    }
+
+    /// The NSEC chain and every RRSIG, for a full zone transfer
+    pub fn records(&self) -> impl Iterator<Item = &DnsResourceRecord> {
+        self.nsec.iter().chain(self.signatures.values().flatten())
+    }
+
+    /// Owner names present in the zone, in canonical order
+    pub fn owner_names(&self) -> impl Iterator<Item = &str> {
+        self.nsec.iter().map(|rr| rr.name.as_str())
+    }
+
+    pub fn needs_refresh(&self, serial: u32, now: u32) -> bool {
+        serial != self.serial || now >= self.signed_at.saturating_add(SIGNATURE_VALIDITY / 2)
+    }
+}
+
+fn build_nsec_chain(
+    zone: &str,
+    rrsets: &BTreeMap<(String, u16), Vec<DnsResourceRecord>>,
+    ttl: u32,
+) -> Result<Vec<DnsResourceRecord>> {
+    let mut types_by_owner: BTreeMap<String, BTreeSet<u16>> = BTreeMap::new();
+    for (owner, rtype) in rrsets.keys() {
+        types_by_owner.entry(owner.clone()).or_default().insert(*rtype);
+    }
+
+    let mut owners: Vec<&String> = types_by_owner.keys().collect();
+    owners.sort_by(|a, b| canonical_cmp(a, b));
+
+    let mut chain = Vec::with_capacity(owners.len());
+    for (i, owner) in owners.iter().enumerate() {
+        let next = owners.get(i + 1).map(|s| s.as_str()).unwrap_or(zone);
+
+        let mut types = types_by_owner[*owner].clone();
+        types.insert(TYPE_RRSIG);
+        types.insert(TYPE_NSEC);
+
+        let mut rdata = canonical_name(next)?;
+        rdata.extend_from_slice(&type_bitmap(&types));
+        chain.push(DnsResourceRecord::new(owner, TYPE_NSEC, ttl, rdata));
+    }
+
+    Ok(chain)
+}
+
+/// Type bit maps field shared by NSEC and NSEC3 (RFC 4034 section 4.1.2)
+pub fn type_bitmap(types: &BTreeSet<u16>) -> Vec<u8> {
+    let mut windows: BTreeMap<u8, [u8; 32]> = BTreeMap::new();
+    for rtype in types {
+        let bitmap = windows.entry((rtype >> 8) as u8).or_insert([0; 32]);
+        let low = (rtype & 0xFF) as usize;
+        bitmap[low / 8] |= 0x80 >> (low % 8);
+    }
+
+    let mut out = Vec::new();
+    for (window, bitmap) in windows {
+        let len = bitmap.iter().rposition(|b| *b != 0).map(|i| i + 1).unwrap_or(0);
+        out.push(window);
+        out.push(len as u8);
+        out.extend_from_slice(&bitmap[..len]);
+    }
+    out
+}
+
+/// Canonical DNS name order (RFC 4034 section 6.1): compare labels right to left
+pub fn canonical_cmp(a: &str, b: &str) -> Ordering {
+    let a = message::normalize_name(a);
+    let b = message::normalize_name(b);
+    let a_labels = a.split('.').filter(|l| !l.is_empty()).rev().map(str::as_bytes);
+    let b_labels = b.split('.').filter(|l| !l.is_empty()).rev().map(str::as_bytes);
+    a_labels.cmp(b_labels)
+}
+
+fn canonical_name(name: &str) -> Result<Vec<u8>> {
+    message::rdata_name(&message::normalize_name(name))
+}
+
+fn rrsig_header(
+    first: &DnsResourceRecord,
+    signer: &str,
+    key_tag: u16,
+    inception: u32,
+    expiration: u32,
+) -> Result<Vec<u8>> {
+    let owner = message::normalize_name(&first.name);
+    let labels = owner
+        .split('.')
+        .filter(|l| !l.is_empty())
+        .count()
+        - usize::from(owner.starts_with("*."));
+
+    let mut rdata = first.rtype.to_be_bytes().to_vec();
+    rdata.push(ALGORITHM_ECDSAP256SHA256);
+    rdata.push(labels as u8);
+    rdata.extend_from_slice(&first.ttl.to_be_bytes());
+    rdata.extend_from_slice(&expiration.to_be_bytes());
+    rdata.extend_from_slice(&inception.to_be_bytes());
+    rdata.extend_from_slice(&key_tag.to_be_bytes());
+    rdata.extend_from_slice(&canonical_name(signer)?);
+    Ok(rdata)
+}
+
+/// RRSIG RDATA (minus signature) followed by the RRset in canonical form (RFC 4034 section 3.1.8.1)
+fn signed_data(rrsig_header: &[u8], rrset: &[DnsResourceRecord]) -> Result<Vec<u8>> {
+    let original_ttl = &rrsig_header[4..8];
+
+    let mut rdatas: Vec<Vec<u8>> = rrset
+        .iter()
+        .map(|rr| canonical_rdata(rr.rtype, &rr.rdata))
+        .collect::<Result<_>>()?;
+    rdatas.sort();
+    rdatas.dedup();
+
+    let first = &rrset[0];
+    let owner = canonical_name(&first.name)?;
+
+    let mut data = rrsig_header.to_vec();
+    for rdata in rdatas {
+        data.extend_from_slice(&owner);
+        data.extend_from_slice(&first.rtype.to_be_bytes());
+        data.extend_from_slice(&first.class.to_be_bytes());
+        data.extend_from_slice(original_ttl);
+        data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        data.extend_from_slice(&rdata);
+    }
+    Ok(data)
+}
+
+/// Lowercases the domain names embedded in RDATA for the types listed in RFC 4034 section 6.2.
+/// Label length octets are always below 0x40, so ASCII lowercasing leaves them untouched.
+fn canonical_rdata(rtype: u16, rdata: &[u8]) -> Result<Vec<u8>> {
+    let mut out = rdata.to_vec();
+
+    let name_ranges: Vec<(usize, usize)> = match rtype {
+        TYPE_NS | TYPE_CNAME | TYPE_PTR => vec![(0, name_wire_len(rdata)?)],
+        TYPE_MX => vec![(2, 2 + name_wire_len(rdata.get(2..).unwrap_or(&[]))?)],
+        TYPE_SRV => vec![(6, 6 + name_wire_len(rdata.get(6..).unwrap_or(&[]))?)],
+        TYPE_SOA => {
+            let mname_end = name_wire_len(rdata)?;
+            let rname_end = mname_end + name_wire_len(&rdata[mname_end..])?;
+            vec![(0, rname_end)]
+        }
+        _ => Vec::new(),
+    };
+
+    for (start, end) in name_ranges {
+        out[start..end].make_ascii_lowercase();
+    }
+    Ok(out)
+}
+
+/// Length of an uncompressed wire-format name at the start of `data`
+fn name_wire_len(data: &[u8]) -> Result<usize> {
+    let mut pos = 0;
+    loop {
+        let len = *data.get(pos).ok_or_else(|| anyhow!("Truncated name in RDATA"))? as usize;
+        if len & 0xC0 != 0 {
+            return Err(anyhow!("Compressed name in RDATA"));
+        }
+        pos += len + 1;
+        if len == 0 {
+            return Ok(pos);
+        }
+    }
+}
+
+fn soa_serial(rdata: &[u8]) -> Option<u32> {
+    let mname_end = name_wire_len(rdata).ok()?;
+    let rname_end = mname_end + name_wire_len(rdata.get(mname_end..)?).ok()?;
+    let serial = rdata.get(rname_end..rname_end + 4)?;
+    Some(u32::from_be_bytes([serial[0], serial[1], serial[2], serial[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::TYPE_A;
+    use std::net::Ipv4Addr;
+
+    const NOW: u32 = 1_700_000_000;
+
+    fn small_zone() -> Vec<DnsResourceRecord> {
+        let soa = message::rdata_soa("ns1.example.com", "hostmaster.example.com", 42, 3600, 900, 604800, 300)
+            .unwrap();
+        vec![
+            DnsResourceRecord::new("example.com", TYPE_SOA, 300, soa),
+            DnsResourceRecord::new("example.com", TYPE_NS, 3600, message::rdata_name("ns1.example.com").unwrap()),
+            DnsResourceRecord::new("ns1.example.com", TYPE_A, 3600, message::rdata_a(Ipv4Addr::new(192, 0, 2, 1))),
+            DnsResourceRecord::new("www.example.com", TYPE_A, 3600, message::rdata_a(Ipv4Addr::new(192, 0, 2, 10))),
+            DnsResourceRecord::new("www.example.com", TYPE_A, 3600, message::rdata_a(Ipv4Addr::new(192, 0, 2, 11))),
+        ]
+    }
+
+    fn keys() -> Vec<ZoneKey> {
+        vec![ZoneKey::generate(FLAGS_KSK).unwrap(), ZoneKey::generate(FLAGS_ZSK).unwrap()]
+    }
+
+    #[test]
+    fn test_signed_rrset_validates() {
+        let keys = keys();
+        let records = small_zone();
+        let signed = SignedZone::sign("example.com", &keys, &records, 300, NOW).unwrap();
+
+        let www: Vec<_> = records.iter().filter(|rr| rr.name == "www.example.com").cloned().collect();
+        let sigs = signed.rrsigs("www.example.com", TYPE_A);
+        assert_eq!(sigs.len(), 1);
+        verify_rrsig(&keys[1].dnskey_rdata(), &sigs[0], &www).unwrap();
+
+        // The KSK must not validate a ZSK signature
+        assert!(verify_rrsig(&keys[0].dnskey_rdata(), &sigs[0], &www).is_err());
+
+        // Record order inside the RRset doesn't matter
+        let reversed: Vec<_> = www.iter().rev().cloned().collect();
+        verify_rrsig(&keys[1].dnskey_rdata(), &sigs[0], &reversed).unwrap();
+    }
+
+    #[test]
+    fn test_dnskey_rrset_is_signed_by_ksk() {
+        let keys = keys();
+        let signed = SignedZone::sign("example.com", &keys, &small_zone(), 300, NOW).unwrap();
+
+        let sigs = signed.rrsigs("example.com", TYPE_DNSKEY);
+        assert_eq!(sigs.len(), 1);
+        verify_rrsig(&keys[0].dnskey_rdata(), &sigs[0], &signed.dnskeys).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_rrset_fails_validation() {
+        let keys = keys();
+        let records = small_zone();
+        let signed = SignedZone::sign("example.com", &keys, &records, 300, NOW).unwrap();
+
+        let mut forged = records[3].clone();
+        forged.rdata = message::rdata_a(Ipv4Addr::new(203, 0, 113, 66));
+        let sig = &signed.rrsigs("www.example.com", TYPE_A)[0];
+        assert!(verify_rrsig(&keys[1].dnskey_rdata(), sig, &[forged, records[4].clone()]).is_err());
+    }
+
+    #[test]
+    fn test_nsec_chain_covers_zone() {
+        let signed = SignedZone::sign("example.com", &keys(), &small_zone(), 300, NOW).unwrap();
+
+        let owners: Vec<&str> = signed.owner_names().collect();
+        assert_eq!(owners, vec!["example.com", "ns1.example.com", "www.example.com"]);
+
+        // Last NSEC wraps back to the apex
+        let last = signed.nsec_at("www.example.com").unwrap();
+        assert!(last.rdata.starts_with(&message::rdata_name("example.com").unwrap()));
+
+        // "mail" sorts between the apex and ns1
+        assert_eq!(signed.covering_nsec("mail.example.com").unwrap().name, "example.com");
+
+        // The apex bitmap lists SOA, NS, DNSKEY, RRSIG and NSEC
+        let apex = signed.nsec_at("example.com").unwrap();
+        let bitmap = &apex.rdata[message::rdata_name("ns1.example.com").unwrap().len()..];
+        let expected: BTreeSet<u16> = [TYPE_NS, TYPE_SOA, TYPE_RRSIG, TYPE_NSEC, TYPE_DNSKEY].into_iter().collect();
+        assert_eq!(bitmap, type_bitmap(&expected).as_slice());
+        assert_eq!(signed.rrsigs("example.com", TYPE_NSEC).len(), 1);
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        assert_eq!(canonical_cmp("example.com", "a.example.com"), Ordering::Less);
+        assert_eq!(canonical_cmp("z.example.com", "a.b.example.com"), Ordering::Greater);
+        assert_eq!(canonical_cmp("WWW.Example.com", "www.example.com"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_key_round_trips_through_pkcs8() {
+        let key = ZoneKey::generate(FLAGS_ZSK).unwrap();
+        let restored = ZoneKey::from_pkcs8(FLAGS_ZSK, key.pkcs8()).unwrap();
+        assert_eq!(key.key_tag(), restored.key_tag());
+        assert_eq!(key.public_key().len(), 64);
+        assert_eq!(key.ds_rdata("example.com").unwrap().len(), 36);
+    }
+
+    #[test]
+    fn test_resign_needed_after_serial_bump() {
+        let signed = SignedZone::sign("example.com", &keys(), &small_zone(), 300, NOW).unwrap();
+        assert!(!signed.needs_refresh(42, NOW + 60));
+        assert!(signed.needs_refresh(43, NOW + 60));
+        assert!(signed.needs_refresh(42, NOW + SIGNATURE_VALIDITY));
+    }
+}
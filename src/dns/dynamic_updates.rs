@@ -1,9 +1,22 @@
+use crate::database::models::DhcpSubnet;
+use crate::dns::dhcid::{self, ClientIdentity};
+use crate::dns::record_types;
 use crate::dns::simple_zone_manager::SimpleZoneManager;
+use ipnet::Ipv4Net;
 use std::sync::Arc;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv6Addr};
 use anyhow::{Result, anyhow};
 use tracing::{info, warn, debug};
 
+/// `hostname` as is if it already has a domain, otherwise under `domain`
+fn qualify(hostname: &str, domain: &str) -> String {
+    if hostname.contains('.') {
+        hostname.to_string()
+    } else {
+        format!("{}.{}", hostname, domain)
+    }
+}
+
 pub struct DynamicUpdater {
     zone_manager: Arc<SimpleZoneManager>,
 }
@@ -13,55 +26,111 @@ impl DynamicUpdater {
         Self { zone_manager }
     }
 
-    /// Add or update a DNS record when a DHCP lease is created or renewed
+    /// Add or update a DNS record (and its PTR) when a DHCP lease is created or renewed.
+    /// `network` picks the reverse zone to create if none covers the address yet. With a
+    /// `client`, the name is tagged with its DHCID and left alone if another client holds it.
     pub async fn add_dhcp_record(
         &self,
         hostname: &str,
         ip: IpAddr,
         domain: &str,
         ttl: u32,
+        network: Option<&Ipv4Net>,
+        client: Option<ClientIdentity<'_>>,
     ) -> Result<()> {
         if hostname.is_empty() {
             return Err(anyhow!("Hostname cannot be empty"));
         }
 
-        // Create FQDN if not already
-        let fqdn = if hostname.contains('.') {
-            hostname.to_string()
-        } else {
-            format!("{}.{}", hostname, domain)
-        };
+        let fqdn = qualify(hostname, domain);
+
+        let dhcid = client.map(|c| dhcid::dhcid(c, &fqdn)).transpose()?;
 
         debug!("Adding dynamic DNS record: {} -> {}", fqdn, ip);
 
         // Add the A or AAAA record
         self.zone_manager
-            .add_dynamic_record(domain, &fqdn, ip, ttl)
+            .add_dynamic_record(domain, &fqdn, ip, ttl, dhcid.as_deref())
             .await?;
 
         info!("Successfully added DNS record: {} -> {}", fqdn, ip);
+
+        self.add_reverse_record(ip, &fqdn, ttl, network).await
+    }
+
+    /// Creates the PTR for `ip` in the reverse zone covering it, creating that zone if needed
+    pub async fn add_reverse_record(
+        &self,
+        ip: IpAddr,
+        fqdn: &str,
+        ttl: u32,
+        network: Option<&Ipv4Net>,
+    ) -> Result<()> {
+        let ptr_name = match ip {
+            IpAddr::V4(v4) => record_types::ipv4_to_ptr_name(v4),
+            IpAddr::V6(v6) => record_types::ipv6_to_ptr_name(v6),
+        };
+
+        let zone = match (self.zone_manager.zone_containing(&ptr_name).await, ip) {
+            (Some(zone), _) => zone,
+            (None, IpAddr::V4(v4)) => {
+                let zone = record_types::reverse_zone_for(v4, network);
+                self.zone_manager.create_dynamic_zone(&zone).await?;
+                zone
+            }
+            (None, IpAddr::V6(_)) => {
+                debug!("No reverse zone for {}, skipping PTR", ip);
+                return Ok(());
+            }
+        };
+
+        self.zone_manager
+            .add_dynamic_ptr(&zone, &ptr_name, fqdn, ttl)
+            .await?;
+
+        debug!("Added PTR record: {} -> {}", ptr_name, fqdn);
+        Ok(())
+    }
+
+    /// Removes the dynamic PTR for `ip`; static PTR records are left alone
+    pub async fn remove_reverse_record(&self, ip: IpAddr) -> Result<()> {
+        let ptr_name = match ip {
+            IpAddr::V4(v4) => record_types::ipv4_to_ptr_name(v4),
+            IpAddr::V6(v6) => record_types::ipv6_to_ptr_name(v6),
+        };
+
+        if let Some(zone) = self.zone_manager.zone_containing(&ptr_name).await {
+            self.zone_manager.remove_dynamic_record(&zone, &ptr_name, None).await?;
+            debug!("Removed PTR record: {}", ptr_name);
+        }
         Ok(())
     }
 
-    /// Remove a DNS record when a DHCP lease expires or is released
-    pub async fn remove_dhcp_record(&self, hostname: &str, domain: &str) -> Result<()> {
+    /// Remove the A or AAAA record for `ip` when a DHCP lease expires or is released; the
+    /// host's addresses of the other family stay. With a `client`, a name another client has
+    /// since claimed is kept.
+    pub async fn remove_dhcp_record(
+        &self,
+        hostname: &str,
+        ip: IpAddr,
+        domain: &str,
+        client: Option<ClientIdentity<'_>>,
+    ) -> Result<()> {
         if hostname.is_empty() {
             return Err(anyhow!("Hostname cannot be empty"));
         }
 
-        let fqdn = if hostname.contains('.') {
-            hostname.to_string()
-        } else {
-            format!("{}.{}", hostname, domain)
-        };
+        let fqdn = qualify(hostname, domain);
+
+        let dhcid = client.map(|c| dhcid::dhcid(c, &fqdn)).transpose()?;
 
-        debug!("Removing dynamic DNS record: {}", fqdn);
+        debug!("Removing dynamic DNS record: {} -> {}", fqdn, ip);
 
         self.zone_manager
-            .remove_dynamic_record(domain, &fqdn)
+            .remove_dynamic_address(domain, &fqdn, ip, dhcid.as_deref())
             .await?;
 
-        info!("Successfully removed DNS record: {}", fqdn);
+        info!("Successfully removed DNS record: {} -> {}", fqdn, ip);
         Ok(())
     }
 
@@ -80,10 +149,11 @@ impl DynamicUpdater {
         }
 
         // Remove old record
-        self.remove_dhcp_record(hostname, domain).await?;
+        self.remove_dhcp_record(hostname, old_ip, domain, None).await?;
 
         // Add new record
-        self.add_dhcp_record(hostname, new_ip, domain, ttl).await?;
+        self.remove_reverse_record(old_ip).await?;
+        self.add_dhcp_record(hostname, new_ip, domain, ttl, None, None).await?;
 
         info!("Updated DNS record: {} from {} to {}", hostname, old_ip, new_ip);
         Ok(())
@@ -102,7 +172,7 @@ impl DynamicUpdater {
         let mut error_count = 0;
 
         for (hostname, ip) in records {
-            match self.add_dhcp_record(&hostname, ip, domain, ttl).await {
+            match self.add_dhcp_record(&hostname, ip, domain, ttl, None, None).await {
                 Ok(_) => success_count += 1,
                 Err(e) => {
                     warn!("Failed to sync record {} -> {}: {}", hostname, ip, e);
@@ -125,6 +195,20 @@ impl DynamicUpdater {
 }
 
 /// Integration point for DHCP server to update DNS
+/// Which records the server maintains for a lease. Option 81 lets a client keep its A record
+/// to itself or opt out of server updates altogether (RFC 4702).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DnsUpdateScope {
+    pub forward: bool,
+    pub reverse: bool,
+}
+
+impl DnsUpdateScope {
+    pub const ALL: Self = Self { forward: true, reverse: true };
+    pub const REVERSE_ONLY: Self = Self { forward: false, reverse: true };
+    pub const NONE: Self = Self { forward: false, reverse: false };
+}
+
 pub struct DhcpDnsIntegration {
     updater: Arc<DynamicUpdater>,
     default_domain: String,
@@ -140,45 +224,105 @@ impl DhcpDnsIntegration {
         }
     }
 
+    /// The subnet's domain if it has one, otherwise the configured default
+    fn domain<'a>(&'a self, subnet: Option<&'a DhcpSubnet>) -> &'a str {
+        subnet
+            .and_then(|s| s.domain_name.as_deref())
+            .filter(|d| !d.is_empty())
+            .unwrap_or(&self.default_domain)
+    }
+
     pub async fn on_lease_created(
         &self,
         hostname: Option<String>,
         ip: IpAddr,
+        subnet: Option<&DhcpSubnet>,
+        scope: DnsUpdateScope,
+        client: ClientIdentity<'_>,
     ) -> Result<()> {
-        if let Some(hostname) = hostname {
-            self.updater
-                .add_dhcp_record(&hostname, ip, &self.default_domain, self.default_ttl)
-                .await?;
+        let Some(hostname) = hostname else { return Ok(()) };
+        let network = subnet.and_then(|s| match s.network {
+            ipnetwork::IpNetwork::V4(net) => Ipv4Net::new(net.network(), net.prefix()).ok(),
+            ipnetwork::IpNetwork::V6(_) => None,
+        });
+        let domain = self.domain(subnet);
+
+        match scope {
+            DnsUpdateScope { forward: true, .. } => {
+                self.updater
+                    .add_dhcp_record(&hostname, ip, domain, self.default_ttl, network.as_ref(), Some(client))
+                    .await
+            }
+            DnsUpdateScope { reverse: true, .. } => {
+                // The client registers its own A record; the PTR still points at its name
+                let fqdn = qualify(&hostname, domain);
+                self.updater
+                    .add_reverse_record(ip, &fqdn, self.default_ttl, network.as_ref())
+                    .await
+            }
+            _ => {
+                debug!("Client asked for no DNS updates for {}", hostname);
+                Ok(())
+            }
         }
-        Ok(())
     }
 
     pub async fn on_lease_renewed(
         &self,
         hostname: Option<String>,
         ip: IpAddr,
+        subnet: Option<&DhcpSubnet>,
+        scope: DnsUpdateScope,
+        client: ClientIdentity<'_>,
     ) -> Result<()> {
         // Same as created for now, but could have different logic
-        self.on_lease_created(hostname, ip).await
+        self.on_lease_created(hostname, ip, subnet, scope, client).await
     }
 
     pub async fn on_lease_released(
         &self,
         hostname: Option<String>,
+        ip: IpAddr,
+        subnet: Option<&DhcpSubnet>,
+        client: ClientIdentity<'_>,
     ) -> Result<()> {
         if let Some(hostname) = hostname {
             self.updater
-                .remove_dhcp_record(&hostname, &self.default_domain)
+                .remove_dhcp_record(&hostname, ip, self.domain(subnet), Some(client))
                 .await?;
         }
-        Ok(())
+        self.updater.remove_reverse_record(ip).await
     }
 
     pub async fn on_lease_expired(
         &self,
         hostname: Option<String>,
+        ip: IpAddr,
+        subnet: Option<&DhcpSubnet>,
+        client: ClientIdentity<'_>,
     ) -> Result<()> {
         // Same as released
-        self.on_lease_released(hostname).await
+        self.on_lease_released(hostname, ip, subnet, client).await
+    }
+
+    /// Registers the AAAA record and ip6.arpa PTR for a DHCPv6 lease or SLAAC address. The
+    /// PTR is only written when a reverse zone for the address is already configured.
+    pub async fn on_v6_lease_created(
+        &self,
+        hostname: Option<String>,
+        ip: Ipv6Addr,
+        scope: DnsUpdateScope,
+        client: ClientIdentity<'_>,
+    ) -> Result<()> {
+        self.on_lease_created(hostname, IpAddr::V6(ip), None, scope, client).await
+    }
+
+    pub async fn on_v6_lease_released(
+        &self,
+        hostname: Option<String>,
+        ip: Ipv6Addr,
+        client: ClientIdentity<'_>,
+    ) -> Result<()> {
+        self.on_lease_released(hostname, IpAddr::V6(ip), None, client).await
     }
-}
\ No newline at end of file
+}
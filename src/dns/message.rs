@@ -0,0 +1,638 @@
+// DNS wire format encoding/decoding (RFC 1035)
+use anyhow::{anyhow, Result};
+use bytes::{BufMut, BytesMut};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_MX: u16 = 15;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_OPT: u16 = 41;
+pub const TYPE_DS: u16 = 43;
+pub const TYPE_RRSIG: u16 = 46;
+pub const TYPE_NSEC: u16 = 47;
+pub const TYPE_DNSKEY: u16 = 48;
+pub const TYPE_TSIG: u16 = 250;
+pub const TYPE_AXFR: u16 = 252;
+pub const TYPE_ANY: u16 = 255;
+pub const TYPE_CAA: u16 = 257;
+
+pub const CLASS_IN: u16 = 1;
+/// RFC 2136 UPDATE uses NONE and ANY in prerequisite and update records
+pub const CLASS_NONE: u16 = 254;
+pub const CLASS_ANY: u16 = 255;
+
+pub const OPCODE_QUERY: u8 = 0;
+pub const OPCODE_NOTIFY: u8 = 4;
+pub const OPCODE_UPDATE: u8 = 5;
+
+pub const RCODE_NOERROR: u8 = 0;
+pub const RCODE_FORMERR: u8 = 1;
+pub const RCODE_SERVFAIL: u8 = 2;
+pub const RCODE_NXDOMAIN: u8 = 3;
+pub const RCODE_NOTIMP: u8 = 4;
+pub const RCODE_REFUSED: u8 = 5;
+pub const RCODE_YXDOMAIN: u8 = 6;
+pub const RCODE_YXRRSET: u8 = 7;
+pub const RCODE_NXRRSET: u8 = 8;
+pub const RCODE_NOTAUTH: u8 = 9;
+pub const RCODE_NOTZONE: u8 = 10;
+/// Extended RCODE (RFC 6891): the upper eight bits travel in the OPT record
+pub const RCODE_BADVERS: u16 = 16;
+
+/// Largest UDP response sent to clients that don't advertise an EDNS buffer size
+pub const MAX_UDP_PAYLOAD: usize = 512;
+/// Buffer size we advertise in our own OPT records
+pub const EDNS_UDP_PAYLOAD: u16 = 1232;
+
+const HEADER_SIZE: usize = 12;
+const MAX_POINTER_JUMPS: usize = 32;
+const MAX_NAME_LENGTH: usize = 255;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsResourceRecord {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub rdata: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DnsMessage {
+    pub id: u16,
+    pub is_response: bool,
+    pub opcode: u8,
+    pub authoritative: bool,
+    pub truncated: bool,
+    pub recursion_desired: bool,
+    pub recursion_available: bool,
+    pub authentic_data: bool,
+    pub checking_disabled: bool,
+    pub rcode: u8,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsResourceRecord>,
+    pub authority: Vec<DnsResourceRecord>,
+    pub additional: Vec<DnsResourceRecord>,
+}
+
+impl DnsMessage {
+    pub fn new(id: u16) -> Self {
+        Self {
+            id,
+            is_response: false,
+            opcode: OPCODE_QUERY,
+            authoritative: false,
+            truncated: false,
+            recursion_desired: false,
+            recursion_available: false,
+            authentic_data: false,
+            checking_disabled: false,
+            rcode: RCODE_NOERROR,
+            questions: Vec::new(),
+            answers: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+        }
+    }
+
+    /// Empty response echoing the query's id, opcode, RD flag and question section
+    pub fn response_to(query: &DnsMessage) -> Self {
+        let mut response = Self::new(query.id);
+        response.is_response = true;
+        response.opcode = query.opcode;
+        response.recursion_desired = query.recursion_desired;
+        response.checking_disabled = query.checking_disabled;
+        response.questions = query.questions.clone();
+        response
+    }
+
+    pub fn error_response(query: &DnsMessage, rcode: u8) -> Self {
+        let mut response = Self::response_to(query);
+        response.rcode = rcode;
+        response
+    }
+
+    /// The EDNS OPT pseudo-record, if the sender included one
+    pub fn edns(&self) -> Option<&DnsResourceRecord> {
+        self.additional.iter().find(|rr| rr.rtype == TYPE_OPT)
+    }
+
+    /// Whether the sender set the DNSSEC OK bit (RFC 3225)
+    pub fn dnssec_ok(&self) -> bool {
+        self.edns().map(|opt| opt.ttl & 0x8000 != 0).unwrap_or(false)
+    }
+
+    /// EDNS version from the OPT record (RFC 6891 section 6.1.3); we implement version 0
+    pub fn edns_version(&self) -> Option<u8> {
+        self.edns().map(|opt| (opt.ttl >> 16) as u8)
+    }
+
+    /// Largest UDP response to send: the sender's advertised buffer size, capped at our own
+    /// so large answers fall back to TCP instead of being fragmented
+    pub fn max_udp_payload(&self) -> usize {
+        self.edns()
+            .map(|opt| (opt.class as usize).clamp(MAX_UDP_PAYLOAD, EDNS_UDP_PAYLOAD as usize))
+            .unwrap_or(MAX_UDP_PAYLOAD)
+    }
+
+    /// Drops every record but the OPT and sets TC, telling the client to retry over TCP
+    pub fn truncate(&mut self) {
+        self.truncated = true;
+        self.answers.clear();
+        self.authority.clear();
+        self.additional.retain(|rr| rr.rtype == TYPE_OPT);
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_SIZE {
+            return Err(anyhow!("DNS message too short: {} bytes", data.len()));
+        }
+
+        let flags = u16::from_be_bytes([data[2], data[3]]);
+        let qdcount = u16::from_be_bytes([data[4], data[5]]);
+        let ancount = u16::from_be_bytes([data[6], data[7]]);
+        let nscount = u16::from_be_bytes([data[8], data[9]]);
+        let arcount = u16::from_be_bytes([data[10], data[11]]);
+
+        let mut message = DnsMessage::new(u16::from_be_bytes([data[0], data[1]]));
+        message.is_response = flags & 0x8000 != 0;
+        message.opcode = ((flags >> 11) & 0x0F) as u8;
+        message.authoritative = flags & 0x0400 != 0;
+        message.truncated = flags & 0x0200 != 0;
+        message.recursion_desired = flags & 0x0100 != 0;
+        message.recursion_available = flags & 0x0080 != 0;
+        message.authentic_data = flags & 0x0020 != 0;
+        message.checking_disabled = flags & 0x0010 != 0;
+        message.rcode = (flags & 0x000F) as u8;
+
+        let mut offset = HEADER_SIZE;
+
+        for _ in 0..qdcount {
+            let (name, next) = read_name(data, offset)?;
+            if next + 4 > data.len() {
+                return Err(anyhow!("Truncated DNS question"));
+            }
+            message.questions.push(DnsQuestion {
+                name,
+                qtype: u16::from_be_bytes([data[next], data[next + 1]]),
+                qclass: u16::from_be_bytes([data[next + 2], data[next + 3]]),
+            });
+            offset = next + 4;
+        }
+
+        message.answers = read_records(data, &mut offset, ancount)?;
+        message.authority = read_records(data, &mut offset, nscount)?;
+        message.additional = read_records(data, &mut offset, arcount)?;
+
+        Ok(message)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buffer = BytesMut::with_capacity(512);
+
+        let mut flags: u16 = ((self.opcode as u16 & 0x0F) << 11) | (self.rcode as u16 & 0x0F);
+        if self.is_response { flags |= 0x8000; }
+        if self.authoritative { flags |= 0x0400; }
+        if self.truncated { flags |= 0x0200; }
+        if self.recursion_desired { flags |= 0x0100; }
+        if self.recursion_available { flags |= 0x0080; }
+        if self.authentic_data { flags |= 0x0020; }
+        if self.checking_disabled { flags |= 0x0010; }
+
+        buffer.put_u16(self.id);
+        buffer.put_u16(flags);
+        buffer.put_u16(self.questions.len() as u16);
+        buffer.put_u16(self.answers.len() as u16);
+        buffer.put_u16(self.authority.len() as u16);
+        buffer.put_u16(self.additional.len() as u16);
+
+        let mut out = buffer.to_vec();
+
+        for question in &self.questions {
+            encode_name(&question.name, &mut out)?;
+            out.extend_from_slice(&question.qtype.to_be_bytes());
+            out.extend_from_slice(&question.qclass.to_be_bytes());
+        }
+
+        for record in self.answers.iter().chain(&self.authority).chain(&self.additional) {
+            record.write_to(&mut out)?;
+        }
+
+        Ok(out)
+    }
+}
+
+impl DnsResourceRecord {
+    pub fn new(name: &str, rtype: u16, ttl: u32, rdata: Vec<u8>) -> Self {
+        Self {
+            name: name.to_string(),
+            rtype,
+            class: CLASS_IN,
+            ttl,
+            rdata,
+        }
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) -> Result<()> {
+        if self.rdata.len() > u16::MAX as usize {
+            return Err(anyhow!("RDATA too long for {}", self.name));
+        }
+
+        encode_name(&self.name, out)?;
+        out.extend_from_slice(&self.rtype.to_be_bytes());
+        out.extend_from_slice(&self.class.to_be_bytes());
+        out.extend_from_slice(&self.ttl.to_be_bytes());
+        out.extend_from_slice(&(self.rdata.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.rdata);
+        Ok(())
+    }
+}
+
+/// OPT pseudo-record advertising our UDP payload size and echoing the DO bit
+pub fn opt_record(dnssec_ok: bool) -> DnsResourceRecord {
+    opt_record_with_rcode(dnssec_ok, 0)
+}
+
+/// OPT pseudo-record carrying the upper bits of an extended RCODE such as BADVERS; the
+/// header keeps the lower four
+pub fn opt_record_with_rcode(dnssec_ok: bool, extended_rcode: u16) -> DnsResourceRecord {
+    let mut ttl = ((extended_rcode >> 4) as u32 & 0xFF) << 24;
+    if dnssec_ok {
+        ttl |= 0x8000;
+    }
+
+    DnsResourceRecord {
+        name: String::new(),
+        rtype: TYPE_OPT,
+        class: EDNS_UDP_PAYLOAD,
+        ttl,
+        rdata: Vec::new(),
+    }
+}
+
+/// Lowercased name without the trailing root dot, the form used for zone lookups
+pub fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+pub fn encode_name(name: &str, out: &mut Vec<u8>) -> Result<()> {
+    let name = name.trim_end_matches('.');
+    let start = out.len();
+
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(anyhow!("Invalid DNS label in name: {}", name));
+            }
+            out.push(label.len() as u8);
+            out.extend_from_slice(label.as_bytes());
+        }
+    }
+    out.push(0);
+
+    if out.len() - start > MAX_NAME_LENGTH {
+        return Err(anyhow!("DNS name too long: {}", name));
+    }
+
+    Ok(())
+}
+
+/// Reads a possibly-compressed name starting at `offset`.
+/// Returns the dotted name and the offset just past the name in the original position.
+pub fn read_name(data: &[u8], offset: usize) -> Result<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut pos = offset;
+    let mut next = None;
+    let mut jumps = 0;
+    let mut length = 0;
+
+    loop {
+        let len = *data.get(pos).ok_or_else(|| anyhow!("Truncated DNS name"))? as usize;
+
+        match len & 0xC0 {
+            0x00 => {
+                if len == 0 {
+                    pos += 1;
+                    break;
+                }
+                let label = data.get(pos + 1..pos + 1 + len)
+                    .ok_or_else(|| anyhow!("Truncated DNS label"))?;
+                length += len + 1;
+                if length > MAX_NAME_LENGTH {
+                    return Err(anyhow!("DNS name too long"));
+                }
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += len + 1;
+            }
+            0xC0 => {
+                let low = *data.get(pos + 1).ok_or_else(|| anyhow!("Truncated DNS pointer"))? as usize;
+                if next.is_none() {
+                    next = Some(pos + 2);
+                }
+                jumps += 1;
+                if jumps > MAX_POINTER_JUMPS {
+                    return Err(anyhow!("Too many DNS compression pointers"));
+                }
+                pos = ((len & 0x3F) << 8) | low;
+            }
+            _ => return Err(anyhow!("Unsupported DNS label type: {:#x}", len)),
+        }
+    }
+
+    Ok((labels.join("."), next.unwrap_or(pos)))
+}
+
+fn read_records(data: &[u8], offset: &mut usize, count: u16) -> Result<Vec<DnsResourceRecord>> {
+    let mut records = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        let (name, next) = read_name(data, *offset)?;
+        if next + 10 > data.len() {
+            return Err(anyhow!("Truncated DNS resource record"));
+        }
+
+        let rtype = u16::from_be_bytes([data[next], data[next + 1]]);
+        let class = u16::from_be_bytes([data[next + 2], data[next + 3]]);
+        let ttl = u32::from_be_bytes([data[next + 4], data[next + 5], data[next + 6], data[next + 7]]);
+        let rdlength = u16::from_be_bytes([data[next + 8], data[next + 9]]) as usize;
+        let rdata_start = next + 10;
+
+        if rdata_start + rdlength > data.len() {
+            return Err(anyhow!("Truncated DNS RDATA"));
+        }
+
+        let rdata = expand_rdata(data, rdata_start, rdlength, rtype)?;
+        records.push(DnsResourceRecord { name, rtype, class, ttl, rdata });
+        *offset = rdata_start + rdlength;
+    }
+
+    Ok(records)
+}
+
+/// Copies RDATA out of a message, decompressing any embedded names so the record
+/// can be re-encoded on its own (e.g. when served from cache).
+fn expand_rdata(data: &[u8], start: usize, len: usize, rtype: u16) -> Result<Vec<u8>> {
+    let end = start + len;
+    let mut out = Vec::with_capacity(len);
+
+    // Empty RDATA is legal in UPDATE messages (RFC 2136 section 2.4) whatever the type
+    if len == 0 {
+        return Ok(out);
+    }
+
+    let fixed_prefix = match rtype {
+        TYPE_NS | TYPE_CNAME | TYPE_PTR => 0,
+        TYPE_MX => 2,
+        TYPE_SRV => 6,
+        TYPE_SOA => {
+            let (mname, next) = read_name(data, start)?;
+            let (rname, next) = read_name(data, next)?;
+            if next + 20 > end {
+                return Err(anyhow!("Truncated SOA RDATA"));
+            }
+            encode_name(&mname, &mut out)?;
+            encode_name(&rname, &mut out)?;
+            out.extend_from_slice(&data[next..next + 20]);
+            return Ok(out);
+        }
+        _ => return Ok(data[start..end].to_vec()),
+    };
+
+    if start + fixed_prefix > end {
+        return Err(anyhow!("Truncated RDATA for type {}", rtype));
+    }
+    out.extend_from_slice(&data[start..start + fixed_prefix]);
+    let (name, next) = read_name(data, start + fixed_prefix)?;
+    if next > end {
+        return Err(anyhow!("RDATA name overruns record for type {}", rtype));
+    }
+    encode_name(&name, &mut out)?;
+
+    Ok(out)
+}
+
+pub fn rdata_a(ip: Ipv4Addr) -> Vec<u8> {
+    ip.octets().to_vec()
+}
+
+pub fn rdata_aaaa(ip: Ipv6Addr) -> Vec<u8> {
+    ip.octets().to_vec()
+}
+
+/// RDATA for NS, CNAME and PTR records
+pub fn rdata_name(name: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    encode_name(name, &mut out)?;
+    Ok(out)
+}
+
+pub fn rdata_mx(preference: u16, exchange: &str) -> Result<Vec<u8>> {
+    let mut out = preference.to_be_bytes().to_vec();
+    encode_name(exchange, &mut out)?;
+    Ok(out)
+}
+
+/// RDATA for SRV records (RFC 2782). A target of "." means the service is not offered.
+pub fn rdata_srv(priority: u16, weight: u16, port: u16, target: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(6 + target.len() + 2);
+    out.extend_from_slice(&priority.to_be_bytes());
+    out.extend_from_slice(&weight.to_be_bytes());
+    out.extend_from_slice(&port.to_be_bytes());
+    encode_name(target, &mut out)?;
+    Ok(out)
+}
+
+/// RDATA for CAA records (RFC 8659 section 4.1); the value runs to the end of the RDATA
+pub fn rdata_caa(flags: u8, tag: &str, value: &str) -> Result<Vec<u8>> {
+    if tag.is_empty() || tag.len() > 15 {
+        return Err(anyhow!("Invalid CAA tag: {}", tag));
+    }
+    let mut out = Vec::with_capacity(2 + tag.len() + value.len());
+    out.push(flags);
+    out.push(tag.len() as u8);
+    out.extend_from_slice(tag.as_bytes());
+    out.extend_from_slice(value.as_bytes());
+    Ok(out)
+}
+
+pub fn rdata_txt(text: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() + 1);
+    if text.is_empty() {
+        out.push(0);
+    }
+    for chunk in text.as_bytes().chunks(255) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn rdata_soa(
+    mname: &str,
+    rname: &str,
+    serial: u32,
+    refresh: u32,
+    retry: u32,
+    expire: u32,
+    minimum: u32,
+) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    encode_name(mname, &mut out)?;
+    encode_name(rname, &mut out)?;
+    for value in [serial, refresh, retry, expire, minimum] {
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_round_trip() {
+        let mut query = DnsMessage::new(0x1234);
+        query.recursion_desired = true;
+        query.questions.push(DnsQuestion {
+            name: "www.example.com".to_string(),
+            qtype: TYPE_A,
+            qclass: CLASS_IN,
+        });
+
+        let parsed = DnsMessage::parse(&query.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.id, 0x1234);
+        assert!(parsed.recursion_desired);
+        assert!(!parsed.is_response);
+        assert_eq!(parsed.questions, query.questions);
+    }
+
+    #[test]
+    fn test_compressed_names_are_expanded() {
+        let mut response = DnsMessage::new(1);
+        response.is_response = true;
+        response.questions.push(DnsQuestion {
+            name: "example.com".to_string(),
+            qtype: TYPE_MX,
+            qclass: CLASS_IN,
+        });
+        let mut data = response.to_bytes().unwrap();
+        data[7] = 1; // ANCOUNT
+
+        // Owner and exchange both point back at the question name (offset 12)
+        data.extend_from_slice(&[0xC0, 12, 0, 15, 0, 1, 0, 0, 0x0E, 0x10, 0, 4, 0, 10, 0xC0, 12]);
+
+        let parsed = DnsMessage::parse(&data).unwrap();
+        let answer = &parsed.answers[0];
+        assert_eq!(answer.name, "example.com");
+        assert_eq!(answer.ttl, 3600);
+        assert_eq!(answer.rdata, rdata_mx(10, "example.com").unwrap());
+    }
+
+    #[test]
+    fn test_empty_rdata_is_kept_empty() {
+        let mut update = DnsMessage::new(2);
+        update.authority.push(DnsResourceRecord {
+            name: "www.example.com".to_string(),
+            rtype: TYPE_CNAME,
+            class: CLASS_ANY,
+            ttl: 0,
+            rdata: Vec::new(),
+        });
+        update.additional.push(opt_record(false));
+
+        let parsed = DnsMessage::parse(&update.to_bytes().unwrap()).unwrap();
+        assert!(parsed.authority[0].rdata.is_empty());
+        assert_eq!(parsed.additional[0].rtype, TYPE_OPT);
+    }
+
+    #[test]
+    fn test_edns_negotiation() {
+        let mut query = DnsMessage::new(3);
+        query.questions.push(DnsQuestion {
+            name: "example.com".to_string(),
+            qtype: TYPE_DNSKEY,
+            qclass: CLASS_IN,
+        });
+        assert_eq!(query.max_udp_payload(), MAX_UDP_PAYLOAD);
+        assert_eq!(query.edns_version(), None);
+        assert!(!query.dnssec_ok());
+
+        // EDNS buffer 4096, version 0, DO set
+        let mut data = query.to_bytes().unwrap();
+        data[11] = 1; // ARCOUNT
+        data.extend_from_slice(&[0, 0, 41, 0x10, 0x00, 0, 0, 0x80, 0, 0, 0]);
+        let parsed = DnsMessage::parse(&data).unwrap();
+        assert_eq!(parsed.edns_version(), Some(0));
+        assert!(parsed.dnssec_ok());
+        assert_eq!(parsed.max_udp_payload(), EDNS_UDP_PAYLOAD as usize);
+
+        // Sizes below 512 are treated as 512
+        let len = data.len();
+        data[len - 8] = 0x01;
+        data[len - 7] = 0x00;
+        assert_eq!(DnsMessage::parse(&data).unwrap().max_udp_payload(), MAX_UDP_PAYLOAD);
+
+        data[len - 5] = 1; // version 1
+        assert_eq!(DnsMessage::parse(&data).unwrap().edns_version(), Some(1));
+    }
+
+    #[test]
+    fn test_extended_rcode_in_opt_record() {
+        let opt = opt_record_with_rcode(true, RCODE_BADVERS);
+        assert_eq!(opt.ttl, 0x0100_8000);
+        assert_eq!(opt.class, EDNS_UDP_PAYLOAD);
+        assert_eq!(opt_record(false).ttl, 0);
+    }
+
+    #[test]
+    fn test_truncate_keeps_opt_record() {
+        let mut response = DnsMessage::new(4);
+        response.is_response = true;
+        response.answers.push(DnsResourceRecord::new("example.com", TYPE_A, 60, rdata_a(Ipv4Addr::new(192, 0, 2, 1))));
+        response.authority.push(DnsResourceRecord::new("example.com", TYPE_NS, 60, rdata_name("ns.example.com").unwrap()));
+        response.additional.push(DnsResourceRecord::new("ns.example.com", TYPE_A, 60, rdata_a(Ipv4Addr::new(192, 0, 2, 53))));
+        response.additional.push(opt_record(false));
+
+        response.truncate();
+        assert!(response.truncated);
+        assert!(response.answers.is_empty() && response.authority.is_empty());
+        assert_eq!(response.additional.len(), 1);
+        assert_eq!(response.additional[0].rtype, TYPE_OPT);
+    }
+
+    #[test]
+    fn test_pointer_loop_is_rejected() {
+        let mut data = vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1]);
+        assert!(DnsMessage::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_truncated_message_is_rejected() {
+        let mut query = DnsMessage::new(7);
+        query.questions.push(DnsQuestion {
+            name: "example.com".to_string(),
+            qtype: TYPE_A,
+            qclass: CLASS_IN,
+        });
+        let data = query.to_bytes().unwrap();
+
+        for len in 0..data.len() {
+            assert!(DnsMessage::parse(&data[..len]).is_err());
+        }
+    }
+}
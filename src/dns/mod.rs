@@ -2,6 +2,16 @@ pub mod server;
 pub mod zone_manager;
 pub mod zone_queries;
 pub mod dynamic_updates;
+pub mod dhcid;
 pub mod record_types;
+pub mod record_conflicts;
+pub mod message;
+pub mod dnssec;
+pub mod cache;
+pub mod query_log;
+pub mod notify;
+pub mod tsig;
+pub mod update;
+pub mod zone_file;
 pub mod simple_server;
-pub mod simple_zone_manager;
\ No newline at end of file
+pub mod simple_zone_manager;
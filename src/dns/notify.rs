@@ -0,0 +1,139 @@
+// DNS NOTIFY (RFC 1996) to secondaries when a zone's serial changes
+use crate::config::Settings;
+use crate::dns::message::{DnsMessage, DnsQuestion, CLASS_IN, OPCODE_NOTIFY, RCODE_NOERROR, TYPE_SOA};
+use crate::dns::simple_server::exchange_udp;
+use crate::dns::zone_queries;
+use ring::rand::{self, SystemRandom};
+use sqlx::PgPool;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(2);
+const NOTIFY_ATTEMPTS: usize = 3;
+
+/// Tells each peer that `zone` changed. Every peer is tried even if an earlier one fails;
+/// the error lists the peers that never acknowledged.
+pub async fn send_notify(zone: &str, peers: &[SocketAddr]) -> Result<()> {
+    let mut failed = Vec::new();
+
+    for &peer in peers {
+        match notify_peer(zone, peer, NOTIFY_TIMEOUT).await {
+            Ok(()) => info!("Sent NOTIFY for {} to {}", zone, peer),
+            Err(e) => {
+                warn!("NOTIFY for {} to {} failed: {}", zone, peer, e);
+                failed.push(peer.to_string());
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("NOTIFY for {} not acknowledged by {}", zone, failed.join(", ")))
+    }
+}
+
+/// Notifies the zone's configured secondaries in the background after its serial was bumped
+pub fn notify_secondaries(db: PgPool, settings: Arc<Settings>, zone_id: Uuid) {
+    if settings.dns.zone_transfers.iter().all(|transfer| transfer.secondaries.is_empty()) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let zone = match zone_queries::fetch_zone(&db, zone_id).await {
+            Ok(Some(zone)) => zone,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Failed to load zone {} for NOTIFY: {}", zone_id, e);
+                return;
+            }
+        };
+
+        let peers = settings.dns.secondaries(&zone.name);
+        if !peers.is_empty() {
+            // Failures are already logged per peer
+            let _ = send_notify(&zone.name, peers).await;
+        }
+    });
+}
+
+fn notify_message(zone: &str) -> Result<DnsMessage> {
+    let id: [u8; 2] = rand::generate(&SystemRandom::new())
+        .map_err(|_| anyhow!("Failed to generate NOTIFY id"))?
+        .expose();
+
+    let mut message = DnsMessage::new(u16::from_be_bytes(id));
+    message.opcode = OPCODE_NOTIFY;
+    message.authoritative = true;
+    message.questions.push(DnsQuestion {
+        name: zone.to_string(),
+        qtype: TYPE_SOA,
+        qclass: CLASS_IN,
+    });
+    Ok(message)
+}
+
+/// Sends the NOTIFY until the peer acknowledges it, retrying on timeouts
+async fn notify_peer(zone: &str, peer: SocketAddr, timeout: Duration) -> Result<()> {
+    let query = notify_message(zone)?.to_bytes()?;
+    let mut last_error = anyhow!("no attempts made");
+
+    for _ in 0..NOTIFY_ATTEMPTS {
+        match exchange_udp(&query, peer, timeout).await {
+            Ok(response) => {
+                let response = DnsMessage::parse(&response)?;
+                if !response.is_response || response.opcode != OPCODE_NOTIFY {
+                    return Err(anyhow!("unexpected reply to NOTIFY"));
+                }
+                if response.rcode != RCODE_NOERROR {
+                    return Err(anyhow!("peer answered with rcode {}", response.rcode));
+                }
+                return Ok(());
+            }
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UdpSocket;
+
+    #[tokio::test]
+    async fn test_send_notify_waits_for_acknowledgement() {
+        let secondary = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let secondary_addr = secondary.local_addr().unwrap();
+
+        let received = tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (size, src) = secondary.recv_from(&mut buf).await.unwrap();
+            let notify = DnsMessage::parse(&buf[..size]).unwrap();
+            let mut ack = DnsMessage::response_to(&notify);
+            ack.authoritative = true;
+            secondary.send_to(&ack.to_bytes().unwrap(), src).await.unwrap();
+            notify
+        });
+
+        send_notify("example.com", &[secondary_addr]).await.unwrap();
+
+        let notify = received.await.unwrap();
+        assert_eq!(notify.opcode, OPCODE_NOTIFY);
+        assert!(notify.authoritative);
+        assert_eq!(notify.questions[0].name, "example.com");
+        assert_eq!(notify.questions[0].qtype, TYPE_SOA);
+    }
+
+    #[tokio::test]
+    async fn test_notify_peer_reports_silent_secondary() {
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let result = notify_peer("example.com", silent.local_addr().unwrap(), Duration::from_millis(50)).await;
+        assert!(result.is_err());
+    }
+}
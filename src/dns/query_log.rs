@@ -0,0 +1,193 @@
+// Recent DNS queries in a bounded ring buffer, with a sample persisted to dns_query_log
+use crate::config::QueryLogConfig;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryLogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub client: IpAddr,
+    pub qname: String,
+    pub qtype: u16,
+    pub rcode: u8,
+    /// Answered from the cache of upstream responses
+    pub cached: bool,
+}
+
+pub struct QueryLog {
+    capacity: usize,
+    sample_rate: f64,
+    recent: Mutex<VecDeque<QueryLogEntry>>,
+    /// Sampled entries waiting for the next flush, bounded by `capacity`
+    pending: Mutex<Vec<QueryLogEntry>>,
+    seen: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl QueryLog {
+    pub fn new(capacity: usize, sample_rate: f64) -> Self {
+        Self {
+            capacity,
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            recent: Mutex::new(VecDeque::with_capacity(capacity)),
+            pending: Mutex::new(Vec::new()),
+            seen: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, entry: QueryLogEntry) {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed);
+        if self.sampled(n) {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.len() < self.capacity {
+                pending.push(entry.clone());
+            } else {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(entry);
+    }
+
+    /// Up to `limit` of the most recent queries, newest first
+    pub fn recent(&self, limit: usize) -> Vec<QueryLogEntry> {
+        self.recent.lock().unwrap().iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Whether the `n`th query is stored. Spreads the sample evenly instead of at random,
+    /// so a rate of 0.25 keeps exactly every fourth query.
+    fn sampled(&self, n: u64) -> bool {
+        (((n + 1) as f64) * self.sample_rate).floor() > ((n as f64) * self.sample_rate).floor()
+    }
+
+    fn take_pending(&self) -> Vec<QueryLogEntry> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+
+    /// Writes sampled queries to the database every few seconds until `shutdown` is cancelled
+    pub async fn persist(self: Arc<Self>, db: PgPool, shutdown: CancellationToken) {
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            let stopping = tokio::select! {
+                _ = shutdown.cancelled() => true,
+                _ = interval.tick() => false,
+            };
+
+            let dropped = self.dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                warn!("Dropped {} sampled DNS queries: the database is not keeping up", dropped);
+            }
+            let entries = self.take_pending();
+            if !entries.is_empty() {
+                match insert_entries(&db, &entries).await {
+                    Ok(()) => debug!("Stored {} sampled DNS queries", entries.len()),
+                    Err(e) => warn!("Failed to store sampled DNS queries: {}", e),
+                }
+            }
+
+            if stopping {
+                break;
+            }
+        }
+    }
+}
+
+async fn insert_entries(db: &PgPool, entries: &[QueryLogEntry]) -> anyhow::Result<()> {
+    let mut query: QueryBuilder<Postgres> =
+        QueryBuilder::new("INSERT INTO dns_query_log (queried_at, client_ip, qname, qtype, rcode, cached) ");
+    query.push_values(entries, |mut row, entry| {
+        row.push_bind(entry.timestamp)
+            .push_bind(ipnetwork::IpNetwork::from(entry.client))
+            .push_bind(&entry.qname)
+            .push_bind(entry.qtype as i32)
+            .push_bind(entry.rcode as i16)
+            .push_bind(entry.cached);
+    });
+    query.build().execute(db).await?;
+    Ok(())
+}
+
+static SHARED_LOG: OnceLock<Arc<QueryLog>> = OnceLock::new();
+
+/// Log used by the running DNS server, created on first use from `config`
+pub fn shared(config: &QueryLogConfig) -> Arc<QueryLog> {
+    SHARED_LOG
+        .get_or_init(|| Arc::new(QueryLog::new(config.buffer_size, config.sample_rate)))
+        .clone()
+}
+
+/// The running DNS server's log, if it has started with the query log enabled
+pub fn shared_log() -> Option<Arc<QueryLog>> {
+    SHARED_LOG.get().cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(qname: &str) -> QueryLogEntry {
+        QueryLogEntry {
+            timestamp: Utc::now(),
+            client: "192.0.2.10".parse().unwrap(),
+            qname: qname.to_string(),
+            qtype: 1,
+            rcode: 0,
+            cached: false,
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_newest() {
+        let log = QueryLog::new(3, 0.0);
+        for name in ["a", "b", "c", "d", "e"] {
+            log.record(entry(name));
+        }
+
+        let names: Vec<String> = log.recent(10).into_iter().map(|e| e.qname).collect();
+        assert_eq!(names, vec!["e", "d", "c"]);
+        assert_eq!(log.recent(1)[0].qname, "e");
+        assert!(log.take_pending().is_empty());
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let quarter = QueryLog::new(100, 0.25);
+        for i in 0..40 {
+            quarter.record(entry(&format!("q{}", i)));
+        }
+        assert_eq!(quarter.take_pending().len(), 10);
+
+        let all = QueryLog::new(100, 1.0);
+        for i in 0..7 {
+            all.record(entry(&format!("q{}", i)));
+        }
+        assert_eq!(all.take_pending().len(), 7);
+
+        // Pending writes are bounded like the buffer
+        let full = QueryLog::new(2, 1.0);
+        for i in 0..5 {
+            full.record(entry(&format!("q{}", i)));
+        }
+        assert_eq!(full.take_pending().len(), 2);
+        assert_eq!(full.dropped.load(Ordering::Relaxed), 3);
+    }
+}
@@ -0,0 +1,150 @@
+// RFC 1034 / RFC 2181 rules for which records may be added next to the existing ones
+
+use crate::database::models::DnsRecord;
+use crate::dns::simple_zone_manager::record_owner;
+
+/// Reason why adding `record_type`/`value` at `name` would break the zone, or `None` if it can be added.
+/// `existing` are the records of the zone named `zone_name`; names may be relative, absolute or "@".
+pub fn find_conflict(
+    zone_name: &str,
+    existing: &[DnsRecord],
+    name: &str,
+    record_type: &str,
+    value: &str,
+) -> Option<String> {
+    let zone_name = zone_name.trim_end_matches('.').to_ascii_lowercase();
+    let owner = record_owner(name, &zone_name);
+    let is_type = |record: &DnsRecord, wanted: &str| record.record_type.eq_ignore_ascii_case(wanted);
+
+    if record_type.eq_ignore_ascii_case("SOA") && existing.iter().any(|r| is_type(r, "SOA")) {
+        return Some(format!("Zone {} already has an SOA record", zone_name));
+    }
+
+    let at_owner: Vec<&DnsRecord> = existing
+        .iter()
+        .filter(|record| record_owner(&record.name, &zone_name) == owner)
+        .collect();
+
+    if record_type.eq_ignore_ascii_case("CNAME") {
+        if let Some(other) = at_owner.first() {
+            return Some(format!(
+                "A CNAME cannot coexist with other records; {} already has a {} record",
+                owner, other.record_type.to_uppercase()
+            ));
+        }
+    } else if at_owner.iter().any(|r| is_type(r, "CNAME")) {
+        return Some(format!(
+            "{} already has a CNAME record, which cannot coexist with a {} record",
+            owner, record_type.to_uppercase()
+        ));
+    }
+
+    // An ALIAS supplies the name's addresses, so it can't share the name with its own
+    let is_address = |wanted: &str| ["A", "AAAA", "ALIAS"].iter().any(|t| wanted.eq_ignore_ascii_case(t));
+    if is_address(record_type) {
+        let alias = at_owner.iter().find(|r| is_type(r, "ALIAS"));
+        let address = at_owner.iter().find(|r| is_address(&r.record_type));
+        if let Some(other) = if record_type.eq_ignore_ascii_case("ALIAS") { address } else { alias } {
+            return Some(format!(
+                "{} already has a {} record, which cannot coexist with a {} record",
+                owner, other.record_type.to_uppercase(), record_type.to_uppercase()
+            ));
+        }
+    }
+
+    let duplicate = at_owner.iter().any(|record| {
+        record.record_type.eq_ignore_ascii_case(record_type)
+            && record.value.trim().trim_end_matches('.').eq_ignore_ascii_case(value.trim().trim_end_matches('.'))
+    });
+    if duplicate {
+        return Some(format!("{} {} {} already exists", owner, record_type.to_uppercase(), value.trim()));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::DEFAULT_VIEW;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn record(name: &str, record_type: &str, value: &str) -> DnsRecord {
+        DnsRecord {
+            id: Uuid::new_v4(),
+            zone_id: Uuid::nil(),
+            name: name.to_string(),
+            record_type: record_type.to_string(),
+            value: value.to_string(),
+            ttl: 3600,
+            priority: None,
+            weight: None,
+            port: None,
+            is_dynamic: false,
+            view: DEFAULT_VIEW.to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn cname_rejected_next_to_other_records() {
+        let existing = vec![
+            record("www", "A", "192.0.2.1"),
+            record("mail.example.com.", "MX", "mx.example.com"),
+            record("v6", "AAAA", "2001:db8::1"),
+        ];
+
+        assert!(find_conflict("example.com", &existing, "www", "CNAME", "web.example.net").is_some());
+        assert!(find_conflict("example.com", &existing, "mail", "CNAME", "web.example.net").is_some());
+        assert!(find_conflict("example.com", &existing, "V6.example.com", "CNAME", "web.example.net").is_some());
+        assert!(find_conflict("example.com", &existing, "ftp", "CNAME", "web.example.net").is_none());
+    }
+
+    #[test]
+    fn records_rejected_next_to_cname() {
+        let existing = vec![record("alias", "CNAME", "target.example.net")];
+
+        for record_type in ["A", "AAAA", "MX", "TXT", "CNAME"] {
+            assert!(
+                find_conflict("example.com", &existing, "alias", record_type, "192.0.2.1").is_some(),
+                "{} next to a CNAME must be rejected",
+                record_type
+            );
+        }
+        assert!(find_conflict("example.com", &existing, "other", "A", "192.0.2.1").is_none());
+    }
+
+    #[test]
+    fn alias_rejected_next_to_addresses() {
+        let existing = vec![
+            record("@", "ALIAS", "cdn.example.net"),
+            record("@", "MX", "mx.example.com"),
+            record("www", "AAAA", "2001:db8::1"),
+        ];
+
+        assert!(find_conflict("example.com", &existing, "@", "A", "192.0.2.1").is_some());
+        assert!(find_conflict("example.com", &existing, "example.com.", "ALIAS", "other.example.net").is_some());
+        assert!(find_conflict("example.com", &existing, "www", "ALIAS", "cdn.example.net").is_some());
+        assert!(find_conflict("example.com", &existing, "@", "TXT", "v=spf1 -all").is_none());
+        assert!(find_conflict("example.com", &existing, "shop", "ALIAS", "cdn.example.net").is_none());
+    }
+
+    #[test]
+    fn second_soa_rejected() {
+        let existing = vec![record("@", "SOA", "ns1.example.com. admin.example.com. 1 3600 600 86400 300")];
+        assert!(find_conflict("example.com", &existing, "@", "SOA", "ns2.example.com. admin.example.com. 2 3600 600 86400 300").is_some());
+        assert!(find_conflict("example.com", &[], "@", "SOA", "ns1.example.com. admin.example.com. 1 3600 600 86400 300").is_none());
+    }
+
+    #[test]
+    fn identical_record_rejected() {
+        let existing = vec![record("www", "A", "192.0.2.1"), record("@", "NS", "ns1.example.com.")];
+
+        assert!(find_conflict("example.com", &existing, "www.example.com.", "a", "192.0.2.1").is_some());
+        assert!(find_conflict("example.com", &existing, "example.com", "NS", "NS1.example.com").is_some());
+        // Additional records of the same RRset are fine
+        assert!(find_conflict("example.com", &existing, "www", "A", "192.0.2.2").is_none());
+    }
+}
@@ -15,6 +15,10 @@ pub enum DnsRecordType {
     NS,
     SOA,
     SRV,
+    CAA,
+    /// Apex-safe CNAME stand-in: A and AAAA queries are answered with the target's addresses,
+    /// looked up when the query arrives
+    ALIAS,
 }
 
 impl FromStr for DnsRecordType {
@@ -31,6 +35,8 @@ impl FromStr for DnsRecordType {
             "NS" => Ok(DnsRecordType::NS),
             "SOA" => Ok(DnsRecordType::SOA),
             "SRV" => Ok(DnsRecordType::SRV),
+            "CAA" => Ok(DnsRecordType::CAA),
+            "ALIAS" => Ok(DnsRecordType::ALIAS),
             _ => Err(anyhow!("Unknown DNS record type: {}", s)),
         }
     }
@@ -48,10 +54,50 @@ impl ToString for DnsRecordType {
             DnsRecordType::NS => "NS",
             DnsRecordType::SOA => "SOA",
             DnsRecordType::SRV => "SRV",
+            DnsRecordType::CAA => "CAA",
+            DnsRecordType::ALIAS => "ALIAS",
         }.to_string()
     }
 }
 
+impl DnsRecordType {
+    /// Numeric TYPE value used on the wire (RFC 1035 / RFC 3596 / RFC 2782 / RFC 8659).
+    /// ALIAS is never sent; it takes the private-use number PowerDNS uses for it.
+    pub fn code(&self) -> u16 {
+        match self {
+            DnsRecordType::A => 1,
+            DnsRecordType::NS => 2,
+            DnsRecordType::CNAME => 5,
+            DnsRecordType::SOA => 6,
+            DnsRecordType::PTR => 12,
+            DnsRecordType::MX => 15,
+            DnsRecordType::TXT => 16,
+            DnsRecordType::AAAA => 28,
+            DnsRecordType::SRV => 33,
+            DnsRecordType::CAA => 257,
+            DnsRecordType::ALIAS => 65401,
+        }
+    }
+
+    /// The type sent on the wire as `code`; ALIAS is resolved by the server and has none
+    pub fn from_code(code: u16) -> Option<Self> {
+        [
+            DnsRecordType::A,
+            DnsRecordType::NS,
+            DnsRecordType::CNAME,
+            DnsRecordType::SOA,
+            DnsRecordType::PTR,
+            DnsRecordType::MX,
+            DnsRecordType::TXT,
+            DnsRecordType::AAAA,
+            DnsRecordType::SRV,
+            DnsRecordType::CAA,
+        ]
+        .into_iter()
+        .find(|record_type| record_type.code() == code)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DnsRecord {
     pub name: String,
@@ -59,6 +105,8 @@ pub struct DnsRecord {
     pub value: String,
     pub ttl: Option<u32>,
     pub priority: Option<u16>,  // For MX and SRV records
+    pub weight: Option<u16>,    // For SRV records
+    pub port: Option<u16>,      // For SRV records
 }
 
 impl DnsRecord {
@@ -69,6 +117,8 @@ impl DnsRecord {
             value: ip.to_string(),
             ttl,
             priority: None,
+            weight: None,
+            port: None,
         }
     }
 
@@ -79,6 +129,8 @@ impl DnsRecord {
             value: ip.to_string(),
             ttl,
             priority: None,
+            weight: None,
+            port: None,
         }
     }
 
@@ -89,6 +141,8 @@ impl DnsRecord {
             value: target,
             ttl,
             priority: None,
+            weight: None,
+            port: None,
         }
     }
 
@@ -99,6 +153,8 @@ impl DnsRecord {
             value: exchange,
             ttl,
             priority: Some(priority),
+            weight: None,
+            port: None,
         }
     }
 
@@ -109,6 +165,8 @@ impl DnsRecord {
             value: text,
             ttl,
             priority: None,
+            weight: None,
+            port: None,
         }
     }
 
@@ -119,9 +177,54 @@ impl DnsRecord {
             value: target,
             ttl,
             priority: None,
+            weight: None,
+            port: None,
         }
     }
 
+    pub fn new_srv(name: String, target: String, priority: u16, weight: u16, port: u16, ttl: Option<u32>) -> Self {
+        Self {
+            name,
+            record_type: DnsRecordType::SRV,
+            value: target,
+            ttl,
+            priority: Some(priority),
+            weight: Some(weight),
+            port: Some(port),
+        }
+    }
+
+    /// Builds a typed record from the loosely typed columns used by the API and the database
+    pub fn from_parts(
+        name: &str,
+        record_type: &str,
+        value: &str,
+        ttl: Option<i32>,
+        priority: Option<i32>,
+        weight: Option<i32>,
+        port: Option<i32>,
+    ) -> Result<Self> {
+        let record_type = DnsRecordType::from_str(record_type)?;
+        let ttl = ttl
+            .map(|ttl| u32::try_from(ttl).map_err(|_| anyhow!("TTL must not be negative")))
+            .transpose()?;
+        let field = |value: Option<i32>, field: &str| {
+            value
+                .map(|v| u16::try_from(v).map_err(|_| anyhow!("{} must be between 0 and 65535", field)))
+                .transpose()
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            record_type,
+            value: value.trim().to_string(),
+            ttl,
+            priority: field(priority, "priority")?,
+            weight: field(weight, "weight")?,
+            port: field(port, "port")?,
+        })
+    }
+
     pub fn validate(&self) -> Result<()> {
         match self.record_type {
             DnsRecordType::A => {
@@ -132,17 +235,116 @@ impl DnsRecord {
                 Ipv6Addr::from_str(&self.value)
                     .map_err(|_| anyhow!("Invalid IPv6 address for AAAA record"))?;
             },
+            DnsRecordType::CNAME | DnsRecordType::PTR => {
+                if !is_valid_domain_name(&self.value) {
+                    return Err(anyhow!("Invalid target hostname for {} record", self.record_type.to_string()));
+                }
+            },
+            DnsRecordType::NS => {
+                // A delegation has to name a server; glue addresses belong in A/AAAA records
+                if self.value.parse::<IpAddr>().is_ok() || !is_valid_domain_name(&self.value) {
+                    return Err(anyhow!("NS record must name a nameserver host, not an address"));
+                }
+            },
             DnsRecordType::MX => {
                 if self.priority.is_none() {
                     return Err(anyhow!("MX record requires priority"));
                 }
+                if !is_valid_domain_name(&self.value) {
+                    return Err(anyhow!("Invalid mail exchange hostname for MX record"));
+                }
+            },
+            DnsRecordType::SRV => {
+                if self.priority.is_none() || self.weight.is_none() || self.port.is_none() {
+                    return Err(anyhow!("SRV record requires priority, weight and port"));
+                }
+                // "." means the service is decidedly not available (RFC 2782)
+                if self.value != "." && !is_valid_domain_name(&self.value) {
+                    return Err(anyhow!("Invalid target hostname for SRV record"));
+                }
+            },
+            DnsRecordType::TXT => {
+                if self.value.is_empty() {
+                    return Err(anyhow!("TXT record requires a value"));
+                }
+            },
+            DnsRecordType::CAA => {
+                CaaValue::parse(&self.value)?;
+            },
+            DnsRecordType::ALIAS => {
+                if self.value.parse::<IpAddr>().is_ok() || !is_valid_domain_name(&self.value) {
+                    return Err(anyhow!("ALIAS record must name a target host, not an address"));
+                }
+            },
+            DnsRecordType::SOA => {
+                let fields: Vec<&str> = self.value.split_whitespace().collect();
+                let valid = fields.len() == 7
+                    && is_valid_domain_name(fields[0])
+                    && is_valid_domain_name(fields[1])
+                    && fields[2..].iter().all(|field| field.parse::<u32>().is_ok());
+                if !valid {
+                    return Err(anyhow!("SOA record must be \"mname rname serial refresh retry expire minimum\""));
+                }
             },
-            _ => {}
         }
         Ok(())
     }
 }
 
+/// CAA record data in zone-file form: `<flags> <tag> "<value>"`, e.g. `0 issue "letsencrypt.org"`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaaValue {
+    pub flags: u8,
+    pub tag: String,
+    pub value: String,
+}
+
+impl CaaValue {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parts = text.trim().splitn(3, char::is_whitespace);
+        let flags = parts.next().unwrap_or_default();
+        let tag = parts.next().unwrap_or_default();
+        let value = parts.next().map(str::trim).unwrap_or_default();
+
+        let flags = flags
+            .parse::<u8>()
+            .map_err(|_| anyhow!("CAA flags must be between 0 and 255"))?;
+        // RFC 8659 section 4.1: tags are 1-15 ASCII letters and digits
+        if tag.is_empty() || tag.len() > 15 || !tag.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow!("CAA tag must be 1-15 letters or digits"));
+        }
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        if value.is_empty() {
+            return Err(anyhow!("CAA record requires a value"));
+        }
+
+        Ok(Self {
+            flags,
+            tag: tag.to_ascii_lowercase(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Relative or absolute domain name; underscores are allowed for service labels like _sip._tcp
+pub fn is_valid_domain_name(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.is_empty() || name.len() > 253 {
+        return false;
+    }
+
+    name.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    })
+}
+
 /// Helper functions for PTR record generation
 pub fn ipv4_to_ptr_name(ip: Ipv4Addr) -> String {
     let octets = ip.octets();
@@ -186,6 +388,20 @@ pub fn network_to_reverse_zone(network: &ipnet::Ipv4Net) -> String {
     }
 }
 
+/// Reverse zone that should hold the PTR for `ip`. Octet-aligned networks (/8, /16, /24) get
+/// their own zone; any other prefix falls back to the /24 containing the address.
+pub fn reverse_zone_for(ip: Ipv4Addr, network: Option<&ipnet::Ipv4Net>) -> String {
+    match network {
+        Some(net) if matches!(net.prefix_len(), 8 | 16 | 24) && net.contains(&ip) => {
+            network_to_reverse_zone(net)
+        }
+        _ => {
+            let containing = ipnet::Ipv4Net::new(ip, 24).expect("24 is a valid IPv4 prefix").trunc();
+            network_to_reverse_zone(&containing)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -196,6 +412,20 @@ mod tests {
         assert_eq!(ipv4_to_ptr_name(ip), "100.1.168.192.in-addr.arpa");
     }
 
+    #[test]
+    fn test_reverse_zone_for() {
+        let ip = Ipv4Addr::new(10, 20, 30, 40);
+        let net = |s: &str| s.parse::<ipnet::Ipv4Net>().unwrap();
+
+        assert_eq!(reverse_zone_for(ip, Some(&net("10.20.30.0/24"))), "30.20.10.in-addr.arpa");
+        assert_eq!(reverse_zone_for(ip, Some(&net("10.20.0.0/16"))), "20.10.in-addr.arpa");
+        assert_eq!(reverse_zone_for(ip, Some(&net("10.0.0.0/8"))), "10.in-addr.arpa");
+        // Non-octet prefixes use the /24 holding the address, not the network's first /24
+        assert_eq!(reverse_zone_for(ip, Some(&net("10.20.16.0/20"))), "30.20.10.in-addr.arpa");
+        assert_eq!(reverse_zone_for(ip, Some(&net("10.20.30.32/27"))), "30.20.10.in-addr.arpa");
+        assert_eq!(reverse_zone_for(ip, None), "30.20.10.in-addr.arpa");
+    }
+
     #[test]
     fn test_record_validation() {
         let valid_a = DnsRecord::new_a("test".to_string(), Ipv4Addr::new(192, 168, 1, 1), None);
@@ -207,7 +437,48 @@ mod tests {
             value: "not-an-ip".to_string(),
             ttl: None,
             priority: None,
+            weight: None,
+            port: None,
         };
         assert!(invalid_a.validate().is_err());
     }
+
+    #[test]
+    fn test_record_validation_by_type() {
+        let record = |record_type: &str, value: &str, priority: Option<i32>| {
+            DnsRecord::from_parts("test", record_type, value, None, priority, None, None).unwrap()
+        };
+
+        assert!(record("AAAA", "2001:db8::1", None).validate().is_ok());
+        assert!(record("AAAA", "192.0.2.1", None).validate().is_err());
+        assert!(record("CNAME", "www.example.com.", None).validate().is_ok());
+        assert!(record("CNAME", "not a host", None).validate().is_err());
+        assert!(record("PTR", "-bad.example.com", None).validate().is_err());
+        assert!(record("MX", "mail.example.com", Some(10)).validate().is_ok());
+        assert!(record("MX", "mail.example.com", None).validate().is_err());
+        assert!(record("MX", "mail..example.com", Some(10)).validate().is_err());
+        assert!(record("TXT", "", None).validate().is_err());
+        assert!(record("SOA", "ns1.example.com. admin.example.com. 1 3600 600 86400 300", None).validate().is_ok());
+        assert!(record("SOA", "ns1.example.com.", None).validate().is_err());
+
+        let srv = DnsRecord::new_srv("_sip._tcp".to_string(), "sip.example.com".to_string(), 10, 5, 5060, None);
+        assert!(srv.validate().is_ok());
+        let srv = DnsRecord::from_parts("_sip._tcp", "SRV", "sip.example.com", None, Some(10), None, Some(5060)).unwrap();
+        assert!(srv.validate().is_err());
+
+        assert!(record("NS", "ns1.example.com", None).validate().is_ok());
+        assert!(record("NS", "192.0.2.53", None).validate().is_err());
+        assert!(record("ALIAS", "cdn.example.net.", None).validate().is_ok());
+        assert!(record("ALIAS", "192.0.2.80", None).validate().is_err());
+        assert!(DnsRecordType::from_code(DnsRecordType::ALIAS.code()).is_none());
+
+        assert!(record("CAA", "0 issue \"letsencrypt.org\"", None).validate().is_ok());
+        assert!(record("CAA", "128 iodef \"mailto:security@example.com\"", None).validate().is_ok());
+        assert!(record("CAA", "256 issue \"letsencrypt.org\"", None).validate().is_err());
+        assert!(record("CAA", "0 is-sue \"letsencrypt.org\"", None).validate().is_err());
+        assert!(record("CAA", "0 issue", None).validate().is_err());
+
+        assert!(DnsRecord::from_parts("test", "MX", "mail.example.com", None, Some(70000), None, None).is_err());
+        assert!(DnsRecord::from_parts("test", "BOGUS", "x", None, None, None, None).is_err());
+    }
 }
\ No newline at end of file
@@ -3,9 +3,12 @@
 use anyhow::Result;
 use std::sync::Arc;
 use sqlx::PgPool;
+use tokio_util::sync::CancellationToken;
 use crate::config::Settings;
+use crate::dns::simple_zone_manager::SimpleZoneManager;
 
-pub async fn start(settings: Arc<Settings>, db: PgPool) -> Result<()> {
+pub async fn start(settings: Arc<Settings>, db: PgPool, shutdown: CancellationToken) -> Result<()> {
     // Using simplified implementation for now
-    crate::dns::simple_server::start(settings, db).await
+    let zone_manager = Arc::new(SimpleZoneManager::new(db.clone(), settings.clone()).await?);
+    crate::dns::simple_server::start(settings, zone_manager, db, shutdown).await
 }
\ No newline at end of file
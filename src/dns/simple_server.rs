@@ -1,48 +1,899 @@
 // Simplified DNS server for initial implementation
 use crate::config::Settings;
-use crate::dns::simple_zone_manager::SimpleZoneManager;
+use crate::dns::cache::{self, CacheKey, DnsCache};
+use crate::dns::message::{
+    self, DnsMessage, DnsResourceRecord, OPCODE_NOTIFY, OPCODE_QUERY, OPCODE_UPDATE, RCODE_FORMERR, RCODE_NOTAUTH, RCODE_NOTIMP, RCODE_NXDOMAIN,
+    RCODE_REFUSED, RCODE_SERVFAIL, TYPE_AXFR,
+};
+use crate::dns::query_log::{self, QueryLog, QueryLogEntry};
+use crate::dns::simple_zone_manager::{self as zones, AliasTarget, SimpleZoneManager, ZoneLookup};
+use crate::dns::tsig::{self, RequestSignature, TsigCheck, TsigKey};
+use crate::metrics;
 use sqlx::PgPool;
 use std::sync::Arc;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use anyhow::Result;
-use tracing::{info, warn};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use anyhow::{Result, Context, anyhow};
+use tracing::{info, warn, debug, error};
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_UDP_SIZE: usize = 4096;
+/// DNS messages over TCP carry a two-byte length prefix (RFC 1035 section 4.2.2)
+const MAX_TCP_MESSAGE: usize = u16::MAX as usize;
+/// Zone transfer messages leave room for a TSIG record
+const AXFR_MESSAGE_SIZE: usize = MAX_TCP_MESSAGE - 512;
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+const SIGNATURE_REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+/// How long open TCP connections get to finish their current message on shutdown
+const TCP_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// UDP queries answered at once; datagrams arriving beyond this are dropped and clients retry
+const MAX_UDP_IN_FLIGHT: usize = 1024;
+/// Open TCP connections; further connections are closed as soon as they are accepted
+const MAX_TCP_CONNECTIONS: usize = 128;
 
 pub struct SimpleDnsServer {
     zone_manager: Arc<SimpleZoneManager>,
     settings: Arc<Settings>,
+    forwarder: Forwarder,
+    cache: Arc<DnsCache>,
+    tsig_keys: Vec<TsigKey>,
+    /// Present when `dns.query_log` is enabled
+    query_log: Option<Arc<QueryLog>>,
+}
+
+/// Whether a zone transfer or UPDATE may go ahead
+enum Access {
+    /// Allowed; responses must be signed if the request was
+    Granted(Option<RequestSignature>),
+    /// Refused, with the response to send
+    Denied(Vec<u8>),
 }
 
 impl SimpleDnsServer {
-    pub async fn new(db: PgPool, settings: Arc<Settings>) -> Result<Self> {
-        let zone_manager = Arc::new(SimpleZoneManager::new(db, settings.clone()).await?);
+    pub fn new(zone_manager: Arc<SimpleZoneManager>, settings: Arc<Settings>) -> Result<Self> {
+        let forwarder = Forwarder::from_config(&settings.dns.forward_servers)?;
+        let cache = cache::shared(settings.dns.cache_size);
+        let tsig_keys = TsigKey::from_config(&settings.dns.tsig_keys)?;
+        let query_log = settings
+            .dns
+            .query_log
+            .enabled
+            .then(|| query_log::shared(&settings.dns.query_log));
 
         Ok(Self {
             zone_manager,
             settings,
+            forwarder,
+            cache,
+            tsig_keys,
+            query_log,
         })
     }
 
-    pub async fn start(self) -> Result<()> {
-        let dns_addr = SocketAddr::new(
-            IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
-            self.settings.dns.port,
+    /// Serves UDP and TCP until `shutdown` is cancelled, then stops accepting queries and waits
+    /// for the ones already being answered. Queries and connections over the limits are dropped
+    /// and counted rather than queued, so a flood can't grow the task count without bound.
+    pub async fn start(self, shutdown: CancellationToken) -> Result<()> {
+        let bind_addr = format!("{}:{}", self.settings.dns.bind_address, self.settings.dns.port);
+
+        let socket = Arc::new(
+            UdpSocket::bind(&bind_addr)
+                .await
+                .with_context(|| format!("Failed to bind DNS socket on {}", bind_addr))?,
         );
 
-        info!("DNS server would start on {} (simplified implementation)", dns_addr);
-        warn!("DNS server is using a simplified implementation - full Hickory DNS integration pending");
+        let listener = TcpListener::bind(&bind_addr)
+            .await
+            .with_context(|| format!("Failed to bind DNS TCP listener on {}", bind_addr))?;
+
+        info!("DNS server listening on {} (UDP and TCP)", bind_addr);
+        if self.forwarder.is_empty() {
+            warn!("No DNS forward_servers configured - queries outside local zones will be refused");
+        }
+
+        let zone_manager = self.zone_manager.clone();
+        let refresh_shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SIGNATURE_REFRESH_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = refresh_shutdown.cancelled() => break,
+                    _ = interval.tick() => zone_manager.refresh_signatures().await,
+                }
+            }
+        });
+
+        let server = Arc::new(self);
+
+        let tcp_server = Arc::clone(&server);
+        let tcp_shutdown = shutdown.clone();
+        let tcp = tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            let slots = Arc::new(Semaphore::new(MAX_TCP_CONNECTIONS));
+            loop {
+                let accepted = tokio::select! {
+                    _ = tcp_shutdown.cancelled() => break,
+                    accepted = listener.accept() => accepted,
+                };
+                while connections.try_join_next().is_some() {}
+
+                match accepted {
+                    Ok((stream, src)) => {
+                        let Ok(slot) = Arc::clone(&slots).try_acquire_owned() else {
+                            metrics::inc(&metrics::counters().dns_dropped_total);
+                            debug!("Too many DNS TCP connections, closing connection from {}", src);
+                            continue;
+                        };
+                        let server = Arc::clone(&tcp_server);
+                        let shutdown = tcp_shutdown.clone();
+                        connections.spawn(async move {
+                            if let Err(e) = server.serve_tcp(stream, src, shutdown).await {
+                                debug!("DNS TCP connection from {} closed: {}", src, e);
+                            }
+                            drop(slot);
+                        });
+                    }
+                    Err(e) => {
+                        error!("Error accepting DNS TCP connection: {}", e);
+                    }
+                }
+            }
+
+            // Connections close after their current message; stragglers are aborted on drop
+            let drained = tokio::time::timeout(TCP_DRAIN_TIMEOUT, async {
+                while connections.join_next().await.is_some() {}
+            });
+            if drained.await.is_err() {
+                warn!("Closing {} DNS TCP connections that did not finish in time", connections.len());
+            }
+        });
 
-        // TODO: Implement actual DNS server with Hickory DNS
-        // For now, just log that we would start the server
+        let mut buf = vec![0u8; MAX_UDP_SIZE];
+        let mut in_flight = JoinSet::new();
+        let slots = Arc::new(Semaphore::new(MAX_UDP_IN_FLIGHT));
 
+        loop {
+            let received = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                received = socket.recv_from(&mut buf) => received,
+            };
+            while in_flight.try_join_next().is_some() {}
+
+            match received {
+                Ok((size, src)) => {
+                    let Ok(slot) = Arc::clone(&slots).try_acquire_owned() else {
+                        metrics::inc(&metrics::counters().dns_dropped_total);
+                        debug!("Too many DNS queries in flight, dropping query from {}", src);
+                        continue;
+                    };
+                    let query = buf[..size].to_vec();
+                    let server = Arc::clone(&server);
+                    let socket = Arc::clone(&socket);
+
+                    in_flight.spawn(async move {
+                        if let Some(response) = server.handle_query(&query, src).await {
+                            if let Err(e) = socket.send_to(&response, src).await {
+                                error!("Failed to send DNS response to {}: {}", src, e);
+                            }
+                        }
+                        drop(slot);
+                    });
+                }
+                Err(e) => {
+                    error!("Error receiving DNS packet: {}", e);
+                }
+            }
+        }
+
+        // Answers are bounded by the upstream timeout, so this doesn't hang
+        while in_flight.join_next().await.is_some() {}
+        if let Err(e) = tcp.await {
+            warn!("DNS TCP listener task failed: {}", e);
+        }
+
+        info!("DNS server stopped");
         Ok(())
     }
 
+    async fn handle_query(&self, data: &[u8], src: SocketAddr) -> Option<Vec<u8>> {
+        let query = parse_query(data, src)?;
+        self.answer(&query, data, query.max_udp_payload(), src).await
+    }
+
+    /// Answers length-prefixed queries on one TCP connection until the client closes it or goes idle
+    async fn serve_tcp(&self, mut stream: TcpStream, src: SocketAddr, shutdown: CancellationToken) -> Result<()> {
+        loop {
+            let mut length = [0u8; 2];
+            let read = tokio::select! {
+                // Only between messages, so a query that was read is always answered
+                _ = shutdown.cancelled() => return Ok(()),
+                read = tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_exact(&mut length)) => read,
+            };
+            match read {
+                Ok(Ok(_)) => {}
+                // Idle timeout or clean close between messages
+                Err(_) => return Ok(()),
+                Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Ok(Err(e)) => return Err(e.into()),
+            }
+
+            let mut data = vec![0u8; u16::from_be_bytes(length) as usize];
+            tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_exact(&mut data))
+                .await
+                .map_err(|_| anyhow!("timed out reading query"))??;
+
+            let Some(query) = parse_query(&data, src) else {
+                return Ok(());
+            };
+
+            let responses = if is_transfer(&query) {
+                metrics::inc(&metrics::counters().dns_queries_total);
+                self.transfer(&query, &data, src).await
+            } else {
+                self.answer(&query, &data, MAX_TCP_MESSAGE, src).await.into_iter().collect()
+            };
+
+            for response in responses {
+                stream.write_all(&(response.len() as u16).to_be_bytes()).await?;
+                stream.write_all(&response).await?;
+            }
+        }
+    }
+
+    async fn answer(&self, query: &DnsMessage, raw: &[u8], max_size: usize, src: SocketAddr) -> Option<Vec<u8>> {
+        metrics::inc(&metrics::counters().dns_queries_total);
+
+        if let Some(response) = edns_error(query) {
+            return response.to_bytes().ok();
+        }
+
+        let response = match query.opcode {
+            OPCODE_NOTIFY => self.accept_notify(query, src).await,
+            OPCODE_UPDATE => self.accept_update(query, raw, src).await,
+            _ => {
+                let view = self.settings.dns.view_for(src.ip());
+                let resolved = self.resolve(query, raw, max_size, view).await;
+                if let Ok((response, cached)) = &resolved {
+                    self.log_query(query, response, *cached, src);
+                }
+                resolved.map(|(response, _)| response)
+            }
+        };
+
+        let response = response.or_else(|e| {
+            warn!("Failed to answer DNS query from {}: {}", src, e);
+            DnsMessage::error_response(query, RCODE_SERVFAIL).to_bytes()
+        });
+
+        response.ok()
+    }
+
+    /// Acknowledges a NOTIFY (RFC 1996) from an allowed primary and reloads the zone from the database
+    async fn accept_notify(&self, query: &DnsMessage, src: SocketAddr) -> Result<Vec<u8>> {
+        if query.questions.len() != 1 {
+            return DnsMessage::error_response(query, RCODE_FORMERR).to_bytes();
+        }
+
+        let zone_name = message::normalize_name(&query.questions[0].name);
+        if !self.settings.dns.allow_notify.contains(&src.ip().to_canonical()) {
+            warn!("Refused NOTIFY for {} from {}", zone_name, src);
+            return DnsMessage::error_response(query, RCODE_REFUSED).to_bytes();
+        }
+
+        if !self.zone_manager.reload_named_zone(&zone_name).await? {
+            return DnsMessage::error_response(query, RCODE_NOTAUTH).to_bytes();
+        }
+
+        info!("Reloaded {} after NOTIFY from {}", zone_name, src);
+        let mut response = DnsMessage::response_to(query);
+        response.authoritative = true;
+        response.to_bytes()
+    }
+
+    /// Applies a dynamic UPDATE (RFC 2136) from an allowed client
+    async fn accept_update(&self, query: &DnsMessage, raw: &[u8], src: SocketAddr) -> Result<Vec<u8>> {
+        if query.questions.len() != 1 || query.questions[0].qtype != message::TYPE_SOA {
+            return DnsMessage::error_response(query, RCODE_FORMERR).to_bytes();
+        }
+
+        let zone_name = message::normalize_name(&query.questions[0].name);
+        let ip_allowed = self.settings.dns.update_allowed(&zone_name, src.ip());
+        let signature = match self.authorize(query, raw, &zone_name, ip_allowed, "UPDATE", src)? {
+            Access::Granted(signature) => signature,
+            Access::Denied(response) => return Ok(response),
+        };
+
+        // The TSIG, if any, is the last additional record and not part of the update
+        let rcode = self.zone_manager.apply_update(&zone_name, &query.answers, &query.authority).await?;
+        let mut response = DnsMessage::error_response(query, rcode).to_bytes()?;
+        if let Some(signature) = signature {
+            signature.sign_response(&mut response, unix_now())?;
+        }
+        Ok(response)
+    }
+
+    /// Streams a whole zone to an allowed secondary (RFC 5936)
+    async fn transfer(&self, query: &DnsMessage, raw: &[u8], src: SocketAddr) -> Vec<Vec<u8>> {
+        let zone_name = message::normalize_name(&query.questions[0].name);
+
+        let ip_allowed = self.settings.dns.transfer_allowed(&zone_name, src.ip());
+        let signature = match self.authorize(query, raw, &zone_name, ip_allowed, "AXFR", src) {
+            Ok(Access::Granted(signature)) => signature,
+            Ok(Access::Denied(response)) => return vec![response],
+            Err(e) => {
+                warn!("Failed to answer AXFR of {} to {}: {}", zone_name, src, e);
+                return Vec::new();
+            }
+        };
+
+        let mut messages = match self.zone_messages(query, &zone_name).await {
+            Ok(messages) => {
+                info!("AXFR of {} to {} ({} messages)", zone_name, src, messages.len());
+                messages
+            }
+            Err(rcode) => DnsMessage::error_response(query, rcode).to_bytes().into_iter().collect(),
+        };
+
+        if let Some(signature) = signature {
+            if let Err(e) = signature.sign_stream(&mut messages, unix_now()) {
+                warn!("Failed to sign AXFR of {}: {}", zone_name, e);
+                return Vec::new();
+            }
+        }
+        messages
+    }
+
+    /// The encoded transfer of a zone, or the RCODE to answer with instead
+    async fn zone_messages(&self, query: &DnsMessage, zone_name: &str) -> std::result::Result<Vec<Vec<u8>>, u8> {
+        match self.zone_manager.transfer_records(zone_name).await {
+            Ok(Some(records)) => axfr_messages(query, records).map_err(|e| {
+                warn!("Failed to encode AXFR of {}: {}", zone_name, e);
+                RCODE_SERVFAIL
+            }),
+            Ok(None) => Err(RCODE_NOTAUTH),
+            Err(e) => {
+                warn!("Failed to load {} for AXFR: {}", zone_name, e);
+                Err(RCODE_SERVFAIL)
+            }
+        }
+    }
+
+    /// Checks a transfer or UPDATE request. One signed with a TSIG key allowed for the zone is
+    /// accepted; unsigned ones fall back to the IP allow-list unless `dns.require_tsig` is set.
+    fn authorize(
+        &self,
+        query: &DnsMessage,
+        raw: &[u8],
+        zone_name: &str,
+        ip_allowed: bool,
+        operation: &str,
+        src: SocketAddr,
+    ) -> Result<Access> {
+        let now = unix_now();
+        let denied = |rcode: u8| -> Result<Access> {
+            warn!("Refused {} for {} from {}", operation, zone_name, src);
+            Ok(Access::Denied(DnsMessage::error_response(query, rcode).to_bytes()?))
+        };
+
+        match tsig::verify_request(&self.tsig_keys, raw, now) {
+            Ok(TsigCheck::Unsigned) if self.settings.dns.require_tsig => denied(RCODE_NOTAUTH),
+            Ok(TsigCheck::Unsigned) if !ip_allowed => denied(RCODE_REFUSED),
+            Ok(TsigCheck::Unsigned) => Ok(Access::Granted(None)),
+            Ok(TsigCheck::Valid(signature)) if self.settings.dns.tsig_key_allowed(zone_name, signature.key_name()) => {
+                Ok(Access::Granted(Some(signature)))
+            }
+            Ok(TsigCheck::Valid(_)) => denied(RCODE_NOTAUTH),
+            Ok(TsigCheck::Invalid { tsig, error }) => {
+                warn!("Bad TSIG on {} for {} from {} (key {}, error {})", operation, zone_name, src, tsig.key_name, error);
+                Ok(Access::Denied(tsig::error_response(query, &tsig, error, now)?))
+            }
+            Err(e) => {
+                debug!("Malformed TSIG from {}: {}", src, e);
+                denied(RCODE_FORMERR)
+            }
+        }
+    }
+
+    /// Encodes a locally generated answer, adding our OPT record for EDNS clients and
+    /// falling back to an empty truncated response when it won't fit in `max_size`
+    fn finish(&self, query: &DnsMessage, mut response: DnsMessage, max_size: usize) -> Result<Vec<u8>> {
+        response.recursion_available = !self.forwarder.is_empty();
+        if query.edns().is_some() {
+            response.additional.push(message::opt_record(query.dnssec_ok()));
+        }
+
+        let bytes = response.to_bytes()?;
+        if bytes.len() <= max_size {
+            return Ok(bytes);
+        }
+
+        response.truncate();
+        response.to_bytes()
+    }
+
+    fn log_query(&self, query: &DnsMessage, response: &[u8], cached: bool, src: SocketAddr) {
+        let (Some(log), Some(question)) = (&self.query_log, query.questions.first()) else {
+            return;
+        };
+
+        log.record(QueryLogEntry {
+            timestamp: chrono::Utc::now(),
+            client: src.ip().to_canonical(),
+            qname: message::normalize_name(&question.name),
+            qtype: question.qtype,
+            // The RCODE is the low nibble of the fourth header byte
+            rcode: response.get(3).map_or(RCODE_SERVFAIL, |flags| flags & 0x0f),
+            cached,
+        });
+    }
+
+    /// The encoded response for a client of `view`, and whether it was answered from the cache
+    async fn resolve(&self, query: &DnsMessage, raw: &[u8], max_size: usize, view: &str) -> Result<(Vec<u8>, bool)> {
+        let uncached = |response: Result<Vec<u8>>| response.map(|bytes| (bytes, false));
+
+        if query.opcode != OPCODE_QUERY || query.questions.len() != 1 {
+            return uncached(self.finish(query, DnsMessage::error_response(query, RCODE_NOTIMP), max_size));
+        }
+        // Zone transfers are only defined over TCP
+        if is_transfer(query) {
+            return uncached(self.finish(query, DnsMessage::error_response(query, RCODE_FORMERR), max_size));
+        }
+
+        let question = &query.questions[0];
+        debug!("DNS query: {} type {}", question.name, question.qtype);
+
+        match self.zone_manager.lookup_in_view(&question.name, question.qtype, query.dnssec_ok(), view).await {
+            ZoneLookup::Found(records) => {
+                let mut response = DnsMessage::response_to(query);
+                response.authoritative = true;
+                response.answers = records;
+                uncached(self.finish(query, response, max_size))
+            }
+            ZoneLookup::Synthesized { answers, authority } => {
+                let mut response = DnsMessage::response_to(query);
+                response.authoritative = true;
+                response.answers = answers;
+                response.authority = authority;
+                uncached(self.finish(query, response, max_size))
+            }
+            ZoneLookup::Alias { target, ttl, authority } => {
+                let mut response = DnsMessage::response_to(query);
+                response.authoritative = true;
+                match self.alias_answers(&question.name, question.qtype, &target, ttl, query.id, view).await {
+                    Ok(answers) if answers.is_empty() => response.authority = authority,
+                    Ok(answers) => response.answers = answers,
+                    Err(e) => {
+                        warn!("Resolving ALIAS {} -> {} failed: {}", question.name, target, e);
+                        response = DnsMessage::error_response(query, RCODE_SERVFAIL);
+                    }
+                }
+                uncached(self.finish(query, response, max_size))
+            }
+            ZoneLookup::NoData(authority) => {
+                let mut response = DnsMessage::response_to(query);
+                response.authoritative = true;
+                response.authority = authority;
+                uncached(self.finish(query, response, max_size))
+            }
+            ZoneLookup::NameError(authority) => {
+                let mut response = DnsMessage::error_response(query, RCODE_NXDOMAIN);
+                response.authoritative = true;
+                response.authority = authority;
+                uncached(self.finish(query, response, max_size))
+            }
+            ZoneLookup::NotAuthoritative => {
+                if self.forwarder.is_empty() {
+                    return uncached(self.finish(query, DnsMessage::error_response(query, RCODE_REFUSED), max_size));
+                }
+
+                let key = CacheKey::new(&question.name, question.qtype, question.qclass);
+                if let Some(mut answers) = self.cache.get(&key) {
+                    if !query.dnssec_ok() {
+                        answers.retain(|rr| rr.rtype != message::TYPE_RRSIG);
+                    }
+                    let mut response = DnsMessage::response_to(query);
+                    response.answers = answers;
+                    return Ok((self.finish(query, response, max_size)?, true));
+                }
+
+                match self.forwarder.forward_query(raw, UPSTREAM_TIMEOUT).await {
+                    Ok(response) => {
+                        self.cache_upstream_answer(key, &response);
+                        uncached(fit_response(response, max_size))
+                    }
+                    Err(e) => {
+                        warn!("Forwarding {} failed: {}", question.name, e);
+                        uncached(self.finish(query, DnsMessage::error_response(query, RCODE_SERVFAIL), max_size))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Addresses for the ALIAS at `qname`: from the local zones, or else looked up through the
+    /// forwarders (and their cache)
+    async fn alias_answers(
+        &self,
+        qname: &str,
+        qtype: u16,
+        target: &str,
+        ttl: u32,
+        id: u16,
+        view: &str,
+    ) -> Result<Vec<DnsResourceRecord>> {
+        let (target, ttl) = match self.zone_manager.follow_alias(qname, qtype, target, ttl, view).await? {
+            AliasTarget::Local(answers) => return Ok(answers),
+            AliasTarget::External { target, ttl } => (target, ttl),
+        };
+        if self.forwarder.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let key = CacheKey::new(&target, qtype, message::CLASS_IN);
+        let answers = match self.cache.get(&key) {
+            Some(answers) => answers,
+            None => {
+                let mut lookup = DnsMessage::new(id);
+                lookup.recursion_desired = true;
+                lookup.questions.push(message::DnsQuestion {
+                    name: target.clone(),
+                    qtype,
+                    qclass: message::CLASS_IN,
+                });
+                lookup.additional.push(message::opt_record(false));
+
+                let response = self.forwarder.forward_query(&lookup.to_bytes()?, UPSTREAM_TIMEOUT).await?;
+                self.cache_upstream_answer(key, &response);
+                let response = DnsMessage::parse(&response)?;
+                if response.rcode != message::RCODE_NOERROR && response.rcode != RCODE_NXDOMAIN {
+                    return Err(anyhow!("upstream answered {} with RCODE {}", target, response.rcode));
+                }
+                response.answers
+            }
+        };
+
+        // Upstream answers lead with any CNAMEs on the way to the addresses
+        let qname = message::normalize_name(qname);
+        Ok(zones::flatten_alias(answers.iter().filter(|rr| rr.rtype == qtype), &qname, ttl))
+    }
+
+    fn cache_upstream_answer(&self, key: CacheKey, response: &[u8]) {
+        match DnsMessage::parse(response) {
+            Ok(parsed) if parsed.rcode == message::RCODE_NOERROR && !parsed.truncated => {
+                self.cache.insert(key, parsed.answers);
+            }
+            Ok(_) => {}
+            Err(e) => debug!("Not caching unparseable upstream response: {}", e),
+        }
+    }
+
     pub fn get_zone_manager(&self) -> Arc<SimpleZoneManager> {
         self.zone_manager.clone()
     }
 }
 
-pub async fn start(settings: Arc<Settings>, db: PgPool) -> Result<()> {
-    let server = SimpleDnsServer::new(db, settings).await?;
-    server.start().await
-}
\ No newline at end of file
+/// Relays queries to the configured upstream resolvers, rotating the starting server
+pub struct Forwarder {
+    upstreams: Vec<SocketAddr>,
+    next: AtomicUsize,
+}
+
+impl Forwarder {
+    pub fn new(upstreams: Vec<SocketAddr>) -> Self {
+        Self {
+            upstreams,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Accepts "8.8.8.8", "8.8.8.8:5353" or "[2001:4860:4860::8888]:53"
+    pub fn from_config(servers: &[String]) -> Result<Self> {
+        let upstreams = servers
+            .iter()
+            .map(|server| {
+                if let Ok(addr) = server.parse::<SocketAddr>() {
+                    return Ok(addr);
+                }
+                server
+                    .parse::<IpAddr>()
+                    .map(|ip| SocketAddr::new(ip, 53))
+                    .map_err(|_| anyhow!("Invalid DNS forward server: {}", server))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self::new(upstreams))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.upstreams.is_empty()
+    }
+
+    /// Sends the raw query to each upstream in turn until one answers within `timeout`
+    pub async fn forward_query(&self, query: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        if query.len() < 12 {
+            return Err(anyhow!("DNS query too short to forward"));
+        }
+        if self.upstreams.is_empty() {
+            return Err(anyhow!("No upstream DNS servers configured"));
+        }
+
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for i in 0..self.upstreams.len() {
+            let upstream = self.upstreams[(start + i) % self.upstreams.len()];
+            match exchange_udp(query, upstream, timeout).await {
+                Ok(response) => return Ok(response),
+                Err(e) => debug!("Upstream DNS server {} failed: {}", upstream, e),
+            }
+        }
+
+        Err(anyhow!("All {} upstream DNS servers failed", self.upstreams.len()))
+    }
+}
+
+pub(crate) async fn exchange_udp(query: &[u8], upstream: SocketAddr, timeout: Duration) -> Result<Vec<u8>> {
+    let local: SocketAddr = if upstream.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse()?;
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(upstream).await?;
+    socket.send(query).await?;
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut buf = vec![0u8; MAX_UDP_SIZE];
+
+    loop {
+        let size = tokio::time::timeout_at(deadline, socket.recv(&mut buf))
+            .await
+            .map_err(|_| anyhow!("timed out after {:?}", timeout))??;
+
+        // Ignore stray datagrams that don't carry our query ID
+        if size >= 12 && buf[..2] == query[..2] {
+            return Ok(buf[..size].to_vec());
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+fn parse_query(data: &[u8], src: SocketAddr) -> Option<DnsMessage> {
+    match DnsMessage::parse(data) {
+        Ok(query) if !query.is_response => Some(query),
+        Ok(_) => None,
+        Err(e) => {
+            debug!("Dropping malformed DNS query from {}: {}", src, e);
+            None
+        }
+    }
+}
+
+/// The response to a query whose EDNS we can't handle: FORMERR for more than one OPT record
+/// and BADVERS for versions above 0 (RFC 6891 sections 6.1.1 and 6.1.3)
+fn edns_error(query: &DnsMessage) -> Option<DnsMessage> {
+    let opt_records = query.additional.iter().filter(|rr| rr.rtype == message::TYPE_OPT).count();
+    if opt_records > 1 {
+        return Some(DnsMessage::error_response(query, RCODE_FORMERR));
+    }
+
+    match query.edns_version() {
+        Some(version) if version > 0 => {
+            let mut response = DnsMessage::error_response(query, (message::RCODE_BADVERS & 0x0F) as u8);
+            response
+                .additional
+                .push(message::opt_record_with_rcode(query.dnssec_ok(), message::RCODE_BADVERS));
+            Some(response)
+        }
+        _ => None,
+    }
+}
+
+/// Truncates an upstream response that exceeds what the client can receive over UDP
+fn fit_response(response: Vec<u8>, max_size: usize) -> Result<Vec<u8>> {
+    if response.len() <= max_size {
+        return Ok(response);
+    }
+
+    let mut message = DnsMessage::parse(&response)?;
+    message.truncate();
+    message.to_bytes()
+}
+
+fn is_transfer(query: &DnsMessage) -> bool {
+    query.opcode == OPCODE_QUERY
+        && query.questions.len() == 1
+        && query.questions[0].qtype == TYPE_AXFR
+}
+
+/// Packs transfer records into as few TCP-sized messages as possible. Only the first
+/// message repeats the question (RFC 5936 section 2.2).
+fn axfr_messages(query: &DnsMessage, records: Vec<DnsResourceRecord>) -> Result<Vec<Vec<u8>>> {
+    let mut messages = Vec::new();
+    let mut current = DnsMessage::response_to(query);
+    current.authoritative = true;
+    let mut size = current.to_bytes()?.len();
+
+    for record in records {
+        let mut encoded = Vec::new();
+        record.write_to(&mut encoded)?;
+
+        if size + encoded.len() > AXFR_MESSAGE_SIZE && !current.answers.is_empty() {
+            messages.push(current.to_bytes()?);
+            current = DnsMessage::response_to(query);
+            current.authoritative = true;
+            current.questions.clear();
+            size = current.to_bytes()?.len();
+        }
+
+        size += encoded.len();
+        current.answers.push(record);
+    }
+
+    messages.push(current.to_bytes()?);
+    Ok(messages)
+}
+
+pub async fn start(
+    settings: Arc<Settings>,
+    zone_manager: Arc<SimpleZoneManager>,
+    db: PgPool,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let server = SimpleDnsServer::new(zone_manager, settings)?;
+    if let Some(log) = &server.query_log {
+        tokio::spawn(Arc::clone(log).persist(db, shutdown.clone()));
+    }
+    server.start(shutdown).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{DnsQuestion, CLASS_IN, TYPE_A};
+
+    fn test_query() -> Vec<u8> {
+        let mut query = DnsMessage::new(0xBEEF);
+        query.recursion_desired = true;
+        query.questions.push(DnsQuestion {
+            name: "example.org".to_string(),
+            qtype: TYPE_A,
+            qclass: CLASS_IN,
+        });
+        query.to_bytes().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_forward_query_falls_back_to_next_upstream() {
+        // First upstream accepts packets but never answers
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let responder_addr = responder.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (size, src) = responder.recv_from(&mut buf).await.unwrap();
+            let query = DnsMessage::parse(&buf[..size]).unwrap();
+            let response = DnsMessage::response_to(&query);
+            responder.send_to(&response.to_bytes().unwrap(), src).await.unwrap();
+        });
+
+        let forwarder = Forwarder::new(vec![silent.local_addr().unwrap(), responder_addr]);
+        let response = forwarder
+            .forward_query(&test_query(), Duration::from_millis(200))
+            .await
+            .unwrap();
+
+        let parsed = DnsMessage::parse(&response).unwrap();
+        assert_eq!(parsed.id, 0xBEEF);
+        assert!(parsed.is_response);
+    }
+
+    #[tokio::test]
+    async fn test_forward_query_fails_when_all_upstreams_time_out() {
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let forwarder = Forwarder::new(vec![silent.local_addr().unwrap()]);
+
+        let result = forwarder.forward_query(&test_query(), Duration::from_millis(100)).await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_axfr_messages_split_at_tcp_limit() {
+        let mut query = DnsMessage::new(0x0AF5);
+        query.questions.push(DnsQuestion {
+            name: "example.com".to_string(),
+            qtype: TYPE_AXFR,
+            qclass: CLASS_IN,
+        });
+
+        // ~20 bytes each, so a few thousand records overflow one 64 KiB message
+        let records: Vec<DnsResourceRecord> = (0..5000u32)
+            .map(|i| DnsResourceRecord::new("h.example.com", TYPE_A, 300, i.to_be_bytes().to_vec()))
+            .collect();
+
+        let messages = axfr_messages(&query, records).unwrap();
+        assert!(messages.len() > 1);
+
+        let mut total = 0;
+        for (i, bytes) in messages.iter().enumerate() {
+            assert!(bytes.len() <= AXFR_MESSAGE_SIZE);
+            let parsed = DnsMessage::parse(bytes).unwrap();
+            assert_eq!(parsed.id, 0x0AF5);
+            assert!(parsed.authoritative);
+            assert_eq!(parsed.questions.len(), usize::from(i == 0));
+            total += parsed.answers.len();
+        }
+        assert_eq!(total, 5000);
+    }
+
+    fn edns_query(version: u8, dnssec_ok: bool) -> DnsMessage {
+        let mut query = DnsMessage::parse(&test_query()).unwrap();
+        let mut opt = message::opt_record(dnssec_ok);
+        opt.ttl |= (version as u32) << 16;
+        query.additional.push(opt);
+        query
+    }
+
+    #[test]
+    fn test_edns_errors() {
+        assert!(edns_error(&DnsMessage::parse(&test_query()).unwrap()).is_none());
+        assert!(edns_error(&edns_query(0, true)).is_none());
+
+        let response = edns_error(&edns_query(1, true)).unwrap();
+        let parsed = DnsMessage::parse(&response.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.rcode, 0);
+        let opt = parsed.edns().unwrap();
+        assert_eq!(opt.ttl >> 24, 1, "BADVERS upper bits");
+        assert!(parsed.dnssec_ok());
+
+        let mut doubled = edns_query(0, false);
+        doubled.additional.push(message::opt_record(false));
+        assert_eq!(edns_error(&doubled).unwrap().rcode, RCODE_FORMERR);
+    }
+
+    #[test]
+    fn test_oversized_upstream_response_is_truncated() {
+        let query = DnsMessage::parse(&test_query()).unwrap();
+        let mut response = DnsMessage::response_to(&query);
+        response.recursion_available = true;
+        for i in 0..40 {
+            response.answers.push(DnsResourceRecord::new(
+                "example.org",
+                TYPE_A,
+                300,
+                message::rdata_a(std::net::Ipv4Addr::new(192, 0, 2, i)),
+            ));
+        }
+        let bytes = response.to_bytes().unwrap();
+        assert!(bytes.len() > message::MAX_UDP_PAYLOAD);
+
+        // Small enough responses pass through untouched
+        assert_eq!(fit_response(bytes.clone(), bytes.len()).unwrap(), bytes);
+
+        let fitted = fit_response(bytes, message::MAX_UDP_PAYLOAD).unwrap();
+        assert!(fitted.len() <= message::MAX_UDP_PAYLOAD);
+        let parsed = DnsMessage::parse(&fitted).unwrap();
+        assert!(parsed.truncated);
+        assert!(parsed.answers.is_empty());
+        assert_eq!(parsed.id, query.id);
+    }
+
+    #[test]
+    fn test_forwarder_parses_config() {
+        let forwarder = Forwarder::from_config(&[
+            "8.8.8.8".to_string(),
+            "1.1.1.1:5353".to_string(),
+        ]).unwrap();
+
+        assert_eq!(forwarder.upstreams[0], "8.8.8.8:53".parse().unwrap());
+        assert_eq!(forwarder.upstreams[1], "1.1.1.1:5353".parse().unwrap());
+        assert!(Forwarder::from_config(&["not-an-ip".to_string()]).is_err());
+    }
+}
@@ -1,38 +1,1351 @@
 // Simplified zone manager for initial implementation
-use crate::config::Settings;
+use crate::config::{DefaultZoneConfig, Settings};
+use crate::database::models::{DnsRecord, DnsZone, DEFAULT_VIEW};
+use crate::dns::dnssec::{self, SignedZone, ZoneKey};
+use crate::dns::notify;
+use crate::dns::message::{self, DnsResourceRecord, TYPE_A, TYPE_AAAA, TYPE_ANY, TYPE_CNAME, TYPE_NSEC, TYPE_SOA};
+use crate::dns::record_conflicts;
+use crate::dns::record_types::{CaaValue, DnsRecordType};
+use crate::dns::update;
+use crate::dns::zone_queries;
 use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::Arc;
-use anyhow::Result;
-use tracing::info;
+use tokio::sync::RwLock;
+use chrono::Utc;
+use uuid::Uuid;
+use anyhow::{Result, anyhow};
+use tracing::{info, warn};
+
+/// A zone and its records, held in memory for answering queries
+pub struct LoadedZone {
+    pub zone: DnsZone,
+    pub records: Vec<DnsRecord>,
+    keys: Vec<ZoneKey>,
+    resource_records: Vec<DnsResourceRecord>,
+    /// ALIAS owner -> (target, TTL); resolved by the server at query time
+    aliases: HashMap<String, (String, u32)>,
+    /// What clients of each other view see: the default records, with every name the view
+    /// has records for replaced by the view's own
+    views: HashMap<String, ViewRecords>,
+    signed: Option<SignedZone>,
+}
+
+struct ViewRecords {
+    resource_records: Vec<DnsResourceRecord>,
+    aliases: HashMap<String, (String, u32)>,
+}
+
+/// Outcome of looking a query name up in the local zones
+#[derive(Debug)]
+pub enum ZoneLookup {
+    /// No local zone covers the name; the query should be forwarded
+    NotAuthoritative,
+    Found(Vec<DnsResourceRecord>),
+    /// Answer expanded from a wildcard (RFC 4592), with the NSEC proving no closer name exists
+    /// in the authority section when signed
+    Synthesized {
+        answers: Vec<DnsResourceRecord>,
+        authority: Vec<DnsResourceRecord>,
+    },
+    /// Name exists but has no records of the requested type (NOERROR, empty answer)
+    NoData(Vec<DnsResourceRecord>),
+    /// Name does not exist (NXDOMAIN)
+    NameError(Vec<DnsResourceRecord>),
+    /// Addresses of the name are those of `target` (ALIAS record), capped at `ttl`. `authority`
+    /// holds the zone SOA in case the target has none.
+    Alias {
+        target: String,
+        ttl: u32,
+        authority: Vec<DnsResourceRecord>,
+    },
+}
+
+/// Where an ALIAS chain ends
+#[derive(Debug)]
+pub enum AliasTarget {
+    /// Addresses from the local zones, already renamed to the ALIAS owner (empty if the target
+    /// has none of the requested type)
+    Local(Vec<DnsResourceRecord>),
+    /// A name outside the local zones, to be resolved through the forwarders
+    External { target: String, ttl: u32 },
+}
+
+/// ALIAS and CNAME hops followed before giving up on a chain
+const MAX_ALIAS_CHAIN: usize = 8;
 
 pub struct SimpleZoneManager {
     db: PgPool,
     settings: Arc<Settings>,
+    zones: Arc<RwLock<HashMap<String, LoadedZone>>>,
 }
 
 impl SimpleZoneManager {
     pub async fn new(db: PgPool, settings: Arc<Settings>) -> Result<Self> {
-        Ok(Self {
+        let manager = Self {
             db,
             settings,
-        })
+            zones: Arc::new(RwLock::new(HashMap::new())),
+        };
+
+        manager.load_zones().await?;
+        Ok(manager)
+    }
+
+    /// Loads every zone from the database. All zones are built before they replace the current
+    /// set, so one that fails to load leaves the zones being served untouched.
+    pub async fn load_zones(&self) -> Result<()> {
+        let zones = zone_queries::fetch_all_zones(&self.db).await?;
+
+        let mut loaded = HashMap::new();
+        for zone in zones {
+            let zone = self.build_zone(zone).await?;
+            loaded.insert(zone.name(), zone);
+        }
+
+        info!("Loaded {} DNS zones", loaded.len());
+        let mut zones = self.zones.write().await;
+        let previous = std::mem::replace(&mut *zones, loaded);
+        if !previous.is_empty() {
+            log_zone_changes(&previous, &zones);
+        }
+        Ok(())
+    }
+
+    /// Re-reads a single zone from the database, re-signing it if DNSSEC is enabled. A zone that
+    /// was deleted stops being served.
+    pub async fn reload_zone(&self, zone_id: Uuid) -> Result<()> {
+        let zone = zone_queries::fetch_all_zones(&self.db)
+            .await?
+            .into_iter()
+            .find(|zone| zone.id == zone_id);
+
+        let Some(zone) = zone else {
+            self.zones.write().await.retain(|name, existing| {
+                let keep = existing.zone.id != zone_id;
+                if !keep {
+                    info!("Zone removed: {}", name);
+                }
+                keep
+            });
+            return Ok(());
+        };
+
+        let zone = self.build_zone(zone).await?;
+        let mut zones = self.zones.write().await;
+        zones.retain(|_, existing| existing.zone.id != zone_id);
+        zones.insert(zone.name(), zone);
+        Ok(())
+    }
+
+    /// Re-signs zones whose serial changed or whose signatures are past half their validity
+    pub async fn refresh_signatures(&self) {
+        let now = Utc::now().timestamp() as u32;
+        let mut zones = self.zones.write().await;
+
+        for zone in zones.values_mut() {
+            let stale = zone.signed.as_ref()
+                .map(|signed| signed.needs_refresh(zone.zone.serial_number as u32, now))
+                .unwrap_or(false);
+            if stale {
+                zone.sign(now);
+                info!("Re-signed DNS zone {}", zone.zone.name);
+            }
+        }
+    }
+
+    pub async fn lookup(&self, qname: &str, qtype: u16, dnssec_ok: bool) -> ZoneLookup {
+        self.lookup_in_view(qname, qtype, dnssec_ok, DEFAULT_VIEW).await
+    }
+
+    /// Looks `qname` up as seen by clients of `view`
+    pub async fn lookup_in_view(&self, qname: &str, qtype: u16, dnssec_ok: bool, view: &str) -> ZoneLookup {
+        let qname = message::normalize_name(qname);
+        let zones = self.zones.read().await;
+
+        let result = match find_zone(&zones, &qname) {
+            Some(zone) => zone.lookup(&qname, qtype, dnssec_ok, view),
+            None => ZoneLookup::NotAuthoritative,
+        };
+
+        match result {
+            ZoneLookup::NotAuthoritative | ZoneLookup::NameError(_) => {
+                default_answer(&self.settings.dns.default_zones, &qname, qtype)
+                    .map(ZoneLookup::Found)
+                    .unwrap_or(result)
+            }
+            _ => result,
+        }
+    }
+
+    /// Follows the ALIAS at `qname` to `target` through further ALIAS and CNAME records in the
+    /// local zones, as seen by clients of `view`. TTLs are capped at the lowest along the chain;
+    /// loops are an error.
+    pub async fn follow_alias(&self, qname: &str, qtype: u16, target: &str, ttl: u32, view: &str) -> Result<AliasTarget> {
+        let qname = message::normalize_name(qname);
+        let mut target = message::normalize_name(target);
+        let mut ttl = ttl;
+        let mut visited = vec![qname.clone()];
+
+        for _ in 0..MAX_ALIAS_CHAIN {
+            if visited.contains(&target) {
+                return Err(anyhow!("ALIAS for {} loops back to {}", qname, target));
+            }
+            visited.push(target.clone());
+
+            let records = match self.lookup_in_view(&target, qtype, false, view).await {
+                ZoneLookup::Found(records) | ZoneLookup::Synthesized { answers: records, .. } => records,
+                ZoneLookup::NoData(_) | ZoneLookup::NameError(_) => return Ok(AliasTarget::Local(Vec::new())),
+                ZoneLookup::NotAuthoritative => return Ok(AliasTarget::External { target, ttl }),
+                ZoneLookup::Alias { target: next, ttl: next_ttl, .. } => {
+                    target = next;
+                    ttl = ttl.min(next_ttl);
+                    continue;
+                }
+            };
+
+            let addresses: Vec<&DnsResourceRecord> = records.iter().filter(|rr| rr.rtype == qtype).collect();
+            if !addresses.is_empty() {
+                return Ok(AliasTarget::Local(flatten_alias(addresses, &qname, ttl)));
+            }
+            match records.iter().find(|rr| rr.rtype == TYPE_CNAME) {
+                Some(cname) => {
+                    ttl = ttl.min(cname.ttl);
+                    target = message::normalize_name(&message::read_name(&cname.rdata, 0)?.0);
+                }
+                None => return Ok(AliasTarget::Local(Vec::new())),
+            }
+        }
+
+        Err(anyhow!("ALIAS chain for {} is longer than {} names", qname, MAX_ALIAS_CHAIN))
     }
 
+    async fn build_zone(&self, zone: DnsZone) -> Result<LoadedZone> {
+        let records = zone_queries::fetch_zone_records(&self.db, zone.id).await?;
+        let keys = self.load_zone_keys(&zone).await?;
+        info!("Loaded DNS zone: {} ({} records{})", zone.name, records.len(),
+              if keys.is_empty() { "" } else { ", DNSSEC signed" });
+
+        Ok(LoadedZone::new(zone, records, keys))
+    }
+
+    /// Loads the zone's signing keys, generating a KSK/ZSK pair the first time DNSSEC is enabled
+    async fn load_zone_keys(&self, zone: &DnsZone) -> Result<Vec<ZoneKey>> {
+        if !zone.dnssec_enabled {
+            return Ok(Vec::new());
+        }
+
+        let mut keys = zone_queries::fetch_dnssec_keys(&self.db, zone.id)
+            .await?
+            .iter()
+            .map(|row| ZoneKey::from_pkcs8(row.flags as u16, &row.private_key))
+            .collect::<Result<Vec<_>>>()?;
+
+        if keys.is_empty() {
+            for flags in [dnssec::FLAGS_KSK, dnssec::FLAGS_ZSK] {
+                let key = ZoneKey::generate(flags)?;
+                let key_type = if key.is_ksk() { "KSK" } else { "ZSK" };
+                zone_queries::insert_dnssec_key(
+                    &self.db,
+                    zone.id,
+                    key_type,
+                    dnssec::ALGORITHM_ECDSAP256SHA256,
+                    flags,
+                    key.key_tag(),
+                    key.pkcs8(),
+                    key.public_key(),
+                ).await?;
+                info!("Generated DNSSEC {} for zone {} (key tag {})", key_type, zone.name, key.key_tag());
+                keys.push(key);
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Points `hostname` at `ip` (A or AAAA) in the zone, replacing the host's previous dynamic
+    /// address of the same family. Re-adding an unchanged address leaves the zone serial alone.
+    /// With a `dhcid` the name is claimed for that client, and an update for a name another
+    /// client's DHCID holds is refused (RFC 4703). Ownership is per record type: a dual-stack
+    /// host identifies itself by DUID over DHCPv6 but not over DHCPv4, so its A and AAAA
+    /// records carry different DHCIDs.
     pub async fn add_dynamic_record(
         &self,
-        _zone_name: &str,
+        zone_name: &str,
         hostname: &str,
         ip: std::net::IpAddr,
-        _ttl: u32,
+        ttl: u32,
+        dhcid: Option<&str>,
     ) -> Result<()> {
-        info!("Would add DNS record: {} -> {}", hostname, ip);
-        // TODO: Implement actual DNS record management
+        let record_type = if ip.is_ipv4() { "A" } else { "AAAA" };
+        self.replace_dynamic_record(zone_name, hostname, record_type, &ip.to_string(), ttl, dhcid).await
+    }
+
+    /// Points the reverse name `ptr_name` at `target`, replacing any previous dynamic PTR
+    pub async fn add_dynamic_ptr(&self, zone_name: &str, ptr_name: &str, target: &str, ttl: u32) -> Result<()> {
+        let target = format!("{}.", message::normalize_name(target));
+        self.replace_dynamic_record(zone_name, ptr_name, "PTR", &target, ttl, None).await
+    }
+
+    async fn replace_dynamic_record(
+        &self,
+        zone_name: &str,
+        hostname: &str,
+        record_type: &str,
+        value: &str,
+        ttl: u32,
+        dhcid: Option<&str>,
+    ) -> Result<()> {
+        let (zone_id, zone_name) = self.zone_id(zone_name).await?;
+        let owner = record_owner(hostname, &zone_name);
+        let name = relative_name(&owner, &zone_name)
+            .ok_or_else(|| anyhow!("{} is not inside zone {}", hostname, zone_name))?;
+
+        let existing: Vec<DnsRecord> = self.dynamic_records(zone_id, &zone_name, &owner)
+            .await?
+            .into_iter()
+            .filter(|record| record.record_type == record_type)
+            .collect();
+        let claimed = self.check_dhcid(&existing, &owner, dhcid).await?;
+
+        if existing.len() == 1 && existing[0].value == value && existing[0].ttl == ttl as i32 {
+            // Records from before DHCIDs were stored are claimed by the first client to renew them
+            if let (Some(dhcid), false) = (dhcid, claimed) {
+                zone_queries::set_dhcid(&self.db, &[existing[0].id], dhcid).await?;
+            }
+            return Ok(());
+        }
+
+        // The records being replaced don't count; anything else at the name (e.g. a static CNAME) does
+        let remaining: Vec<DnsRecord> = zone_queries::fetch_zone_records(&self.db, zone_id)
+            .await?
+            .into_iter()
+            .filter(|record| record.view == DEFAULT_VIEW && !existing.iter().any(|replaced| replaced.id == record.id))
+            .collect();
+        if let Some(conflict) = record_conflicts::find_conflict(&zone_name, &remaining, &name, record_type, value) {
+            return Err(anyhow!("Refusing dynamic update for {}: {}", owner, conflict));
+        }
+
+        for record in &existing {
+            zone_queries::delete_dns_record(&self.db, record.id).await?;
+        }
+        zone_queries::insert_dns_record(&self.db, zone_id, &name, record_type, value, Some(ttl as i32), None, dhcid)
+            .await?;
+
+        zone_queries::bump_zone_serial(&self.db, zone_id).await?;
+        self.reload_zone(zone_id).await?;
+        notify::notify_secondaries(self.db.clone(), self.settings.clone(), zone_id);
+
+        info!("Added dynamic DNS record: {} {} {}", owner, record_type, value);
         Ok(())
     }
 
-    pub async fn remove_dynamic_record(&self, _zone_name: &str, hostname: &str) -> Result<()> {
-        info!("Would remove DNS record: {}", hostname);
-        // TODO: Implement actual DNS record removal
+    /// Deletes every dynamic record owned by `hostname` in the zone. With a `dhcid`, records
+    /// another client registered are left in place and an error is returned.
+    pub async fn remove_dynamic_record(&self, zone_name: &str, hostname: &str, dhcid: Option<&str>) -> Result<()> {
+        self.remove_dynamic(zone_name, hostname, dhcid, |_| true).await
+    }
+
+    /// Deletes only the A or AAAA record pointing `hostname` at `ip`, so releasing one address
+    /// of a dual-stack host leaves the other family's record alone
+    pub async fn remove_dynamic_address(
+        &self,
+        zone_name: &str,
+        hostname: &str,
+        ip: std::net::IpAddr,
+        dhcid: Option<&str>,
+    ) -> Result<()> {
+        let record_type = if ip.is_ipv4() { "A" } else { "AAAA" };
+        self.remove_dynamic(zone_name, hostname, dhcid, |record| {
+            record.record_type == record_type
+                && record.value.parse::<std::net::IpAddr>().is_ok_and(|value| value == ip)
+        })
+        .await
+    }
+
+    async fn remove_dynamic(
+        &self,
+        zone_name: &str,
+        hostname: &str,
+        dhcid: Option<&str>,
+        matches: impl Fn(&DnsRecord) -> bool,
+    ) -> Result<()> {
+        let (zone_id, zone_name) = self.zone_id(zone_name).await?;
+        let owner = record_owner(hostname, &zone_name);
+
+        let existing: Vec<DnsRecord> = self.dynamic_records(zone_id, &zone_name, &owner)
+            .await?
+            .into_iter()
+            .filter(|record| matches(record))
+            .collect();
+        if existing.is_empty() {
+            return Ok(());
+        }
+        self.check_dhcid(&existing, &owner, dhcid).await?;
+
+        for record in &existing {
+            zone_queries::delete_dns_record(&self.db, record.id).await?;
+        }
+
+        zone_queries::bump_zone_serial(&self.db, zone_id).await?;
+        self.reload_zone(zone_id).await?;
+        notify::notify_secondaries(self.db.clone(), self.settings.clone(), zone_id);
+
+        info!("Removed {} dynamic DNS record(s) for {}", existing.len(), owner);
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Full contents of a zone we're authoritative for, in AXFR order. The zone is re-read from
+    /// the database so secondaries never transfer a serial older than the one stored.
+    pub async fn transfer_records(&self, zone_name: &str) -> Result<Option<Vec<DnsResourceRecord>>> {
+        let zone_name = message::normalize_name(zone_name);
+        let Some(zone_id) = self.zones.read().await.get(&zone_name).map(|zone| zone.zone.id) else {
+            return Ok(None);
+        };
+        let Some(zone) = zone_queries::fetch_zone(&self.db, zone_id).await? else {
+            return Ok(None);
+        };
+
+        let zone = self.build_zone(zone).await?;
+        zone.transfer_records().map(Some)
+    }
+
+    /// Applies an RFC 2136 UPDATE to a zone we serve and returns the RCODE for the response.
+    /// The zone is read fresh from the database so prerequisites see every earlier change.
+    pub async fn apply_update(
+        &self,
+        zone_name: &str,
+        prerequisites: &[DnsResourceRecord],
+        updates: &[DnsResourceRecord],
+    ) -> Result<u8> {
+        let zone_name = message::normalize_name(zone_name);
+        let Some(zone_id) = self.zones.read().await.get(&zone_name).map(|zone| zone.zone.id) else {
+            return Ok(message::RCODE_NOTAUTH);
+        };
+        let Some(zone) = zone_queries::fetch_zone(&self.db, zone_id).await? else {
+            return Ok(message::RCODE_NOTAUTH);
+        };
+        // UPDATE edits the default view; records of other views are out of its reach
+        let records: Vec<DnsRecord> = zone_queries::fetch_zone_records(&self.db, zone_id)
+            .await?
+            .into_iter()
+            .filter(|record| record.view == DEFAULT_VIEW)
+            .collect();
+        let current = LoadedZone::new(zone, records.clone(), Vec::new());
+
+        let rcode = update::check_prerequisites(&zone_name, &current.resource_records, prerequisites);
+        if rcode != message::RCODE_NOERROR {
+            return Ok(rcode);
+        }
+        let rcode = update::prescan(&zone_name, updates);
+        if rcode != message::RCODE_NOERROR {
+            return Ok(rcode);
+        }
+
+        let plan = match update::plan_updates(&zone_name, zone_id, &records, updates) {
+            Ok(plan) => plan,
+            Err(e) => {
+                warn!("Rejecting UPDATE for {}: {}", zone_name, e);
+                return Ok(message::RCODE_FORMERR);
+            }
+        };
+        if plan.is_empty() {
+            return Ok(message::RCODE_NOERROR);
+        }
+
+        for record_id in &plan.removed {
+            zone_queries::delete_dns_record(&self.db, *record_id).await?;
+        }
+        for record in &plan.added {
+            zone_queries::insert_record(&self.db, record).await?;
+        }
+
+        zone_queries::bump_zone_serial(&self.db, zone_id).await?;
+        self.reload_zone(zone_id).await?;
+        notify::notify_secondaries(self.db.clone(), self.settings.clone(), zone_id);
+
+        info!("Applied UPDATE to {}: {} added, {} removed", zone_name, plan.added.len(), plan.removed.len());
+        Ok(message::RCODE_NOERROR)
+    }
+
+    /// Re-reads a loaded zone by name; false if we don't serve it
+    pub async fn reload_named_zone(&self, zone_name: &str) -> Result<bool> {
+        let zone_id = self.zones.read().await
+            .get(&message::normalize_name(zone_name))
+            .map(|zone| zone.zone.id);
+
+        match zone_id {
+            Some(zone_id) => self.reload_zone(zone_id).await.map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Name of the most specific loaded zone containing `name`
+    pub async fn zone_containing(&self, name: &str) -> Option<String> {
+        let name = message::normalize_name(name);
+        let zones = self.zones.read().await;
+        find_zone(&zones, &name).map(|zone| zone.name())
+    }
+
+    /// Creates an empty master zone for dynamic records, if dynamic updates are enabled
+    pub async fn create_dynamic_zone(&self, zone_name: &str) -> Result<()> {
+        if !self.settings.dns.dynamic_updates {
+            return Err(anyhow!("Zone {} does not exist and dynamic updates are disabled", zone_name));
+        }
+
+        let suffix = message::normalize_name(&self.settings.dns.domain_suffix);
+        let zone_id = zone_queries::insert_zone(
+            &self.db,
+            &message::normalize_name(zone_name),
+            &format!("ns.{}", suffix),
+            &format!("hostmaster.{}", suffix),
+        )
+        .await?;
+        self.reload_zone(zone_id).await?;
+
+        info!("Created DNS zone {} for dynamic records", zone_name);
+        Ok(())
+    }
+
+    /// Id and normalized name of a loaded zone
+    async fn zone_id(&self, zone_name: &str) -> Result<(Uuid, String)> {
+        let zone_name = message::normalize_name(zone_name);
+        let zones = self.zones.read().await;
+        let zone = zones.get(&zone_name)
+            .ok_or_else(|| anyhow!("Zone not found: {}", zone_name))?;
+        Ok((zone.zone.id, zone_name))
+    }
+
+    /// Fails when `records` carry a DHCID other than `dhcid`, i.e. the name belongs to a
+    /// different client. Returns whether `dhcid` already holds the name.
+    async fn check_dhcid(&self, records: &[DnsRecord], owner: &str, dhcid: Option<&str>) -> Result<bool> {
+        let Some(dhcid) = dhcid else { return Ok(false) };
+        let ids: Vec<Uuid> = records.iter().map(|record| record.id).collect();
+        let holders = zone_queries::fetch_dhcids(&self.db, &ids).await?;
+
+        if holders.iter().any(|holder| holder != dhcid) {
+            return Err(anyhow!("Refusing dynamic update for {}: the name belongs to another DHCP client", owner));
+        }
+        Ok(!holders.is_empty())
+    }
+
+    /// Dynamic records currently stored for `owner`, however their names were written
+    async fn dynamic_records(&self, zone_id: Uuid, zone_name: &str, owner: &str) -> Result<Vec<DnsRecord>> {
+        Ok(zone_queries::fetch_zone_records(&self.db, zone_id)
+            .await?
+            .into_iter()
+            .filter(|record| record.is_dynamic && record_owner(&record.name, zone_name) == owner)
+            .collect())
+    }
+}
+
+impl LoadedZone {
+    pub fn new(zone: DnsZone, records: Vec<DnsRecord>, keys: Vec<ZoneKey>) -> Self {
+        let mut loaded = Self {
+            zone,
+            records,
+            keys,
+            resource_records: Vec::new(),
+            aliases: HashMap::new(),
+            views: HashMap::new(),
+            signed: None,
+        };
+        loaded.rebuild();
+        loaded
+    }
+
+    pub fn name(&self) -> String {
+        message::normalize_name(&self.zone.name)
+    }
+
+    /// Converts the stored records to wire form and re-signs the zone
+    pub fn rebuild(&mut self) {
+        let (resource_records, aliases) = self.convert(self.records.iter().filter(|record| record.view == DEFAULT_VIEW));
+        self.resource_records = resource_records;
+        self.aliases = aliases;
+        self.views = self.build_views();
+        self.sign(Utc::now().timestamp() as u32);
+    }
+
+    /// The SOA plus `records` in wire form, and the ALIAS records among them
+    fn convert<'a>(
+        &self,
+        records: impl Iterator<Item = &'a DnsRecord>,
+    ) -> (Vec<DnsResourceRecord>, HashMap<String, (String, u32)>) {
+        let zone_name = self.name();
+        let mut rrs = Vec::with_capacity(self.records.len() + 1);
+        let mut aliases = HashMap::new();
+
+        match self.soa_record() {
+            Ok(soa) => rrs.push(soa),
+            Err(e) => warn!("Invalid SOA for zone {}: {}", zone_name, e),
+        }
+
+        for record in records {
+            let owner = record_owner(&record.name, &zone_name);
+            if record.record_type.eq_ignore_ascii_case("ALIAS") {
+                // Answers synthesized per query can't be covered by the zone's precomputed signatures
+                if self.keys.is_empty() {
+                    aliases.insert(owner, (message::normalize_name(&record.value), record.ttl.max(0) as u32));
+                } else {
+                    warn!("Skipping ALIAS record {} in DNSSEC-signed zone {}", record.name, zone_name);
+                }
+                continue;
+            }
+            match record_to_rr(record, &owner) {
+                Ok(rr) => rrs.push(rr),
+                Err(e) => warn!("Skipping DNS record {} ({}): {}", record.name, record.record_type, e),
+            }
+        }
+
+        (rrs, aliases)
+    }
+
+    /// Records served to each view other than the default one. A name with records in the view
+    /// is answered from those alone; every other name falls through to the default records.
+    fn build_views(&self) -> HashMap<String, ViewRecords> {
+        let zone_name = self.name();
+        let mut by_view: HashMap<&str, Vec<&DnsRecord>> = HashMap::new();
+        for record in self.records.iter().filter(|record| record.view != DEFAULT_VIEW) {
+            by_view.entry(record.view.as_str()).or_default().push(record);
+        }
+        if by_view.is_empty() {
+            return HashMap::new();
+        }
+
+        // A signed zone has one NSEC chain, which would prove view-only names don't exist
+        if !self.keys.is_empty() {
+            let mut names: Vec<&str> = by_view.into_keys().collect();
+            names.sort();
+            warn!("Skipping records of view(s) {} in DNSSEC-signed zone {}", names.join(", "), zone_name);
+            return HashMap::new();
+        }
+
+        by_view
+            .into_iter()
+            .map(|(view, records)| {
+                let owners: HashSet<String> = records.iter().map(|record| record_owner(&record.name, &zone_name)).collect();
+                let defaults = self.records.iter().filter(|record| {
+                    record.view == DEFAULT_VIEW && !owners.contains(&record_owner(&record.name, &zone_name))
+                });
+                let (resource_records, aliases) = self.convert(defaults.chain(records.iter().copied()));
+                (view.to_string(), ViewRecords { resource_records, aliases })
+            })
+            .collect()
+    }
+
+    /// Records and ALIAS targets answered to clients of `view`
+    fn view_records(&self, view: &str) -> (&[DnsResourceRecord], &HashMap<String, (String, u32)>) {
+        match self.views.get(view) {
+            Some(records) => (&records.resource_records, &records.aliases),
+            None => (&self.resource_records, &self.aliases),
+        }
+    }
+
+    fn sign(&mut self, now: u32) {
+        if self.keys.is_empty() {
+            self.signed = None;
+            return;
+        }
+
+        match SignedZone::sign(&self.name(), &self.keys, &self.resource_records, self.zone.minimum_ttl as u32, now) {
+            Ok(signed) => self.signed = Some(signed),
+            Err(e) => {
+                warn!("Failed to sign DNS zone {}: {}", self.zone.name, e);
+                self.signed = None;
+            }
+        }
+    }
+
+    fn lookup(&self, qname: &str, qtype: u16, dnssec_ok: bool, view: &str) -> ZoneLookup {
+        // An existing name, even one that only holds other types, always wins over a wildcard
+        if !self.name_exists(qname, view) {
+            if let Some(lookup) = self.wildcard_lookup(qname, qtype, dnssec_ok, view) {
+                return lookup;
+            }
+        }
+
+        let (resource_records, aliases) = self.view_records(view);
+        let mut at_name: Vec<&DnsResourceRecord> = resource_records
+            .iter()
+            .filter(|rr| rr.name == qname)
+            .collect();
+
+        if let Some(signed) = &self.signed {
+            if qname == self.name() {
+                at_name.extend(&signed.dnskeys);
+            }
+            at_name.extend(signed.nsec_at(qname));
+        }
+
+        let mut answers: Vec<DnsResourceRecord> = at_name
+            .into_iter()
+            .filter(|rr| rr.rtype == qtype || qtype == TYPE_ANY || rr.rtype == TYPE_CNAME)
+            .cloned()
+            .collect();
+
+        if answers.is_empty() {
+            if let (Some((target, ttl)), true) = (aliases.get(qname), qtype == TYPE_A || qtype == TYPE_AAAA) {
+                return ZoneLookup::Alias {
+                    target: target.clone(),
+                    ttl: *ttl,
+                    authority: self.soa_authority(None),
+                };
+            }
+            return self.negative_answer(qname, dnssec_ok, view);
+        }
+
+        if let (Some(signed), true) = (&self.signed, dnssec_ok) {
+            let mut covered: Vec<(String, u16)> = answers.iter().map(|rr| (rr.name.clone(), rr.rtype)).collect();
+            covered.sort();
+            covered.dedup();
+            for (owner, rtype) in covered {
+                answers.extend_from_slice(signed.rrsigs(&owner, rtype));
+            }
+        }
+
+        ZoneLookup::Found(answers)
+    }
+
+    /// Answer synthesized from `*.<closest encloser>` for a name that doesn't exist, or `None`
+    /// when there is no such wildcard
+    fn wildcard_lookup(&self, qname: &str, qtype: u16, dnssec_ok: bool, view: &str) -> Option<ZoneLookup> {
+        let mut encloser = qname;
+        while !self.name_exists(encloser, view) {
+            let pos = encloser.find('.')?;
+            encloser = &encloser[pos + 1..];
+        }
+        let wildcard = format!("*.{}", encloser);
+
+        let (resource_records, _) = self.view_records(view);
+        let source: Vec<&DnsResourceRecord> = resource_records.iter().filter(|rr| rr.name == wildcard).collect();
+        if source.is_empty() {
+            return None;
+        }
+
+        let signed = self.signed.as_ref().filter(|_| dnssec_ok);
+        let mut answers: Vec<DnsResourceRecord> = source
+            .into_iter()
+            .filter(|rr| rr.rtype == qtype || qtype == TYPE_ANY || rr.rtype == TYPE_CNAME)
+            .map(|rr| DnsResourceRecord { name: qname.to_string(), ..rr.clone() })
+            .collect();
+
+        if answers.is_empty() {
+            let mut authority = self.soa_authority(signed);
+            if let Some(signed) = signed {
+                // qname doesn't exist, and the wildcard that matches it lacks the type (RFC 4035 section 3.1.3.4)
+                let proofs = signed.covering_nsec(qname).into_iter().chain(signed.nsec_at(&wildcard));
+                push_nsec_proofs(&mut authority, signed, proofs);
+            }
+            return Some(ZoneLookup::NoData(authority));
+        }
+
+        let mut authority = Vec::new();
+        if let Some(signed) = signed {
+            // Signatures keep the wildcard's label count, which tells validators the answer was expanded
+            let mut covered: Vec<u16> = answers.iter().map(|rr| rr.rtype).collect();
+            covered.sort();
+            covered.dedup();
+            for rtype in covered {
+                answers.extend(
+                    signed
+                        .rrsigs(&wildcard, rtype)
+                        .iter()
+                        .map(|sig| DnsResourceRecord { name: qname.to_string(), ..sig.clone() }),
+                );
+            }
+            push_nsec_proofs(&mut authority, signed, signed.covering_nsec(qname));
+        }
+
+        Some(ZoneLookup::Synthesized { answers, authority })
+    }
+
+    /// True if `qname` owns records or is an empty non-terminal above records in `view`
+    fn name_exists(&self, qname: &str, view: &str) -> bool {
+        let (resource_records, aliases) = self.view_records(view);
+        let suffix = format!(".{}", qname);
+        qname == self.name()
+            || resource_records
+                .iter()
+                .map(|rr| rr.name.as_str())
+                .chain(aliases.keys().map(String::as_str))
+                .any(|name| name == qname || name.ends_with(&suffix))
+    }
+
+    /// NODATA or NXDOMAIN with the zone SOA in authority (RFC 2308), plus NSEC proofs when signed
+    fn negative_answer(&self, qname: &str, dnssec_ok: bool, view: &str) -> ZoneLookup {
+        let exists = self.name_exists(qname, view);
+        let signed = self.signed.as_ref().filter(|_| dnssec_ok);
+        let mut authority = self.soa_authority(signed);
+
+        if let Some(signed) = signed {
+            let proofs = if exists {
+                signed.nsec_at(qname).or_else(|| signed.covering_nsec(qname)).into_iter().collect()
+            } else {
+                self.nonexistence_proofs(signed, qname)
+            };
+            push_nsec_proofs(&mut authority, signed, proofs);
+        }
+
+        if exists {
+            ZoneLookup::NoData(authority)
+        } else {
+            ZoneLookup::NameError(authority)
+        }
+    }
+
+    /// The zone SOA for a negative answer, signed when `signed` is given
+    fn soa_authority(&self, signed: Option<&SignedZone>) -> Vec<DnsResourceRecord> {
+        let mut authority = Vec::new();
+        if let Ok(soa) = self.soa_record() {
+            authority.push(soa);
+            if let Some(signed) = signed {
+                authority.extend_from_slice(signed.rrsigs(&self.name(), TYPE_SOA));
+            }
+        }
+        authority
+    }
+
+    /// NSEC records proving neither `qname` nor a wildcard that could cover it exists
+    fn nonexistence_proofs<'a>(&self, signed: &'a SignedZone, qname: &str) -> Vec<&'a DnsResourceRecord> {
+        let zone_name = self.name();
+
+        // Closest existing ancestor of qname; a wildcard directly below it would have matched
+        let mut encloser = qname;
+        while encloser != zone_name && signed.nsec_at(encloser).is_none() {
+            match encloser.find('.') {
+                Some(pos) => encloser = &encloser[pos + 1..],
+                None => break,
+            }
+        }
+        let wildcard = format!("*.{}", encloser);
+
+        let mut proofs: Vec<&DnsResourceRecord> = Vec::new();
+        for name in [qname, wildcard.as_str()] {
+            if let Some(nsec) = signed.covering_nsec(name) {
+                if !proofs.iter().any(|p| p.name == nsec.name) {
+                    proofs.push(nsec);
+                }
+            }
+        }
+
+        proofs
+    }
+
+    /// SOA, every other record of the default view (with DNSKEY, NSEC and RRSIG when signed),
+    /// then the SOA again (RFC 5936 section 2.2)
+    pub fn transfer_records(&self) -> Result<Vec<DnsResourceRecord>> {
+        let soa = self.soa_record()?;
+        let mut records = vec![soa.clone()];
+
+        records.extend(self.resource_records.iter().filter(|rr| rr.rtype != TYPE_SOA).cloned());
+        if let Some(signed) = &self.signed {
+            records.extend(signed.dnskeys.iter().cloned());
+            records.extend(signed.records().cloned());
+        }
+
+        records.push(soa);
+        Ok(records)
+    }
+
+    pub fn soa_record(&self) -> Result<DnsResourceRecord> {
+        let zone_name = self.name();
+        let (mname, rname) = soa_names(&self.zone);
+
+        let rdata = message::rdata_soa(
+            &mname,
+            &rname,
+            self.zone.serial_number as u32,
+            self.zone.refresh_interval as u32,
+            self.zone.retry_interval as u32,
+            self.zone.expire_interval as u32,
+            self.zone.minimum_ttl as u32,
+        )?;
+
+        Ok(DnsResourceRecord::new(&zone_name, TYPE_SOA, self.zone.minimum_ttl as u32, rdata))
+    }
+}
+
+/// `records` served as the ALIAS owner's own, with TTLs no longer than the ALIAS record's
+pub fn flatten_alias<'a>(
+    records: impl IntoIterator<Item = &'a DnsResourceRecord>,
+    owner: &str,
+    ttl: u32,
+) -> Vec<DnsResourceRecord> {
+    records
+        .into_iter()
+        .map(|rr| DnsResourceRecord {
+            name: owner.to_string(),
+            ttl: rr.ttl.min(ttl),
+            ..rr.clone()
+        })
+        .collect()
+}
+
+/// Appends each NSEC with its signatures, skipping one already present
+fn push_nsec_proofs<'a>(
+    authority: &mut Vec<DnsResourceRecord>,
+    signed: &SignedZone,
+    proofs: impl IntoIterator<Item = &'a DnsResourceRecord>,
+) {
+    for nsec in proofs {
+        if authority.iter().any(|rr| rr.rtype == TYPE_NSEC && rr.name == nsec.name) {
+            continue;
+        }
+        authority.push(nsec.clone());
+        authority.extend_from_slice(signed.rrsigs(&nsec.name, TYPE_NSEC));
+    }
+}
+
+/// Primary nameserver and responsible mailbox for the zone SOA, defaulting to `ns1.<zone>`
+/// and `hostmaster.<zone>`
+pub(crate) fn soa_names(zone: &DnsZone) -> (String, String) {
+    let zone_name = message::normalize_name(&zone.name);
+    let mname = zone.primary_ns.clone()
+        .unwrap_or_else(|| format!("ns1.{}", zone_name));
+    let rname = zone.admin_email.as_deref()
+        .unwrap_or("hostmaster")
+        .replace('@', ".");
+    let rname = if rname.contains('.') { rname } else { format!("{}.{}", rname, zone_name) };
+    (mname, rname)
+}
+
+/// Logs zones that appeared, disappeared or whose serial or record count moved since the last load
+fn log_zone_changes(previous: &HashMap<String, LoadedZone>, current: &HashMap<String, LoadedZone>) {
+    for (name, zone) in current {
+        match previous.get(name) {
+            None => info!("DNS zone added: {}", name),
+            Some(old) if old.zone.serial_number != zone.zone.serial_number || old.records.len() != zone.records.len() => {
+                info!(
+                    "DNS zone changed: {} (serial {} -> {}, {} -> {} records)",
+                    name, old.zone.serial_number, zone.zone.serial_number, old.records.len(), zone.records.len()
+                )
+            }
+            Some(_) => {}
+        }
+    }
+    for name in previous.keys() {
+        if !current.contains_key(name) {
+            info!("DNS zone removed: {}", name);
+        }
+    }
+}
+
+/// Finds the most specific loaded zone that contains `qname`
+fn find_zone<'a>(zones: &'a HashMap<String, LoadedZone>, qname: &str) -> Option<&'a LoadedZone> {
+    let mut candidate = qname;
+    loop {
+        if let Some(zone) = zones.get(candidate) {
+            return Some(zone);
+        }
+        let pos = candidate.find('.')?;
+        candidate = &candidate[pos + 1..];
+    }
+}
+
+/// Catch-all answer from the most specific `default_zones` entry covering `qname`.
+/// Other query types get an empty (NODATA) answer since the name is treated as existing.
+pub fn default_answer(
+    defaults: &[DefaultZoneConfig],
+    qname: &str,
+    qtype: u16,
+) -> Option<Vec<DnsResourceRecord>> {
+    let qname = message::normalize_name(qname);
+
+    let default = defaults
+        .iter()
+        .filter(|d| {
+            let suffix = message::normalize_name(&d.suffix);
+            qname == suffix || qname.ends_with(&format!(".{}", suffix))
+        })
+        .max_by_key(|d| d.suffix.trim_end_matches('.').len())?;
+
+    let ttl = default.ttl.unwrap_or(300);
+    let mut answers = Vec::new();
+
+    if let (Some(ip), true) = (default.ipv4, qtype == TYPE_A || qtype == TYPE_ANY) {
+        answers.push(DnsResourceRecord::new(&qname, TYPE_A, ttl, message::rdata_a(ip)));
+    }
+    if let (Some(ip), true) = (default.ipv6, qtype == TYPE_AAAA || qtype == TYPE_ANY) {
+        answers.push(DnsResourceRecord::new(&qname, TYPE_AAAA, ttl, message::rdata_aaaa(ip)));
+    }
+
+    Some(answers)
+}
+
+/// Expands a stored record name ("@", relative label or FQDN) into the normalized owner name
+pub fn record_owner(name: &str, zone_name: &str) -> String {
+    let name = name.trim();
+    if name.is_empty() || name == "@" {
+        return zone_name.to_string();
+    }
+
+    let lowered = name.to_ascii_lowercase();
+    if let Some(absolute) = lowered.strip_suffix('.') {
+        return absolute.to_string();
+    }
+    if lowered == zone_name || lowered.ends_with(&format!(".{}", zone_name)) {
+        return lowered;
+    }
+
+    format!("{}.{}", lowered, zone_name)
+}
+
+/// Name of `owner` relative to the zone apex ("@" for the apex), or `None` if it is outside the zone
+pub fn relative_name(owner: &str, zone_name: &str) -> Option<String> {
+    if owner == zone_name {
+        return Some("@".to_string());
+    }
+    owner
+        .strip_suffix(zone_name)
+        .and_then(|prefix| prefix.strip_suffix('.'))
+        .filter(|label| !label.is_empty())
+        .map(str::to_string)
+}
+
+pub(crate) fn record_to_rr(record: &DnsRecord, owner: &str) -> Result<DnsResourceRecord> {
+    let record_type = DnsRecordType::from_str(&record.record_type)?;
+    let value = record.value.trim();
+
+    let rdata = match record_type {
+        DnsRecordType::A => message::rdata_a(value.parse()?),
+        DnsRecordType::AAAA => message::rdata_aaaa(value.parse()?),
+        DnsRecordType::CNAME | DnsRecordType::NS | DnsRecordType::PTR => message::rdata_name(value)?,
+        DnsRecordType::MX => message::rdata_mx(record.priority.unwrap_or(10) as u16, value)?,
+        DnsRecordType::SRV => message::rdata_srv(
+            srv_field(record.priority, "priority")?,
+            srv_field(record.weight, "weight")?,
+            srv_field(record.port, "port")?,
+            value,
+        )?,
+        DnsRecordType::CAA => {
+            let caa = CaaValue::parse(value)?;
+            message::rdata_caa(caa.flags, &caa.tag, &caa.value)?
+        }
+        DnsRecordType::TXT => message::rdata_txt(value),
+        other => return Err(anyhow!("{} records are not served yet", other.to_string())),
+    };
+
+    Ok(DnsResourceRecord::new(owner, record_type.code(), record.ttl.max(0) as u32, rdata))
+}
+
+fn srv_field(value: Option<i32>, field: &str) -> Result<u16> {
+    let value = value.ok_or_else(|| anyhow!("SRV record is missing its {}", field))?;
+    u16::try_from(value).map_err(|_| anyhow!("SRV {} {} is out of range", field, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn internal_default() -> Vec<DefaultZoneConfig> {
+        vec![DefaultZoneConfig {
+            suffix: "internal".to_string(),
+            ipv4: Some(Ipv4Addr::new(10, 0, 0, 80)),
+            ipv6: None,
+            ttl: Some(60),
+        }]
+    }
+
+    #[test]
+    fn test_default_zone_answers_unmatched_names() {
+        let answers = default_answer(&internal_default(), "anything.internal.", TYPE_A).unwrap();
+
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].name, "anything.internal");
+        assert_eq!(answers[0].rtype, TYPE_A);
+        assert_eq!(answers[0].ttl, 60);
+        assert_eq!(answers[0].rdata, vec![10, 0, 0, 80]);
+    }
+
+    #[test]
+    fn test_default_zone_ignores_other_suffixes() {
+        assert!(default_answer(&internal_default(), "example.com", TYPE_A).is_none());
+        assert!(default_answer(&internal_default(), "notinternal", TYPE_A).is_none());
+
+        // Name is covered but there is no AAAA default
+        let answers = default_answer(&internal_default(), "host.internal", TYPE_AAAA).unwrap();
+        assert!(answers.is_empty());
+    }
+
+    fn test_zone(records: &[(&str, &str, &str)]) -> LoadedZone {
+        let now = Utc::now();
+        let zone = DnsZone {
+            id: Uuid::new_v4(),
+            name: "example.com".to_string(),
+            zone_type: "master".to_string(),
+            serial_number: 1,
+            refresh_interval: 3600,
+            retry_interval: 900,
+            expire_interval: 604800,
+            minimum_ttl: 300,
+            primary_ns: Some("ns1.example.com".to_string()),
+            admin_email: Some("admin@example.com".to_string()),
+            dnssec_enabled: false,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let records = records
+            .iter()
+            .map(|(name, record_type, value)| DnsRecord {
+                id: Uuid::new_v4(),
+                zone_id: zone.id,
+                name: name.to_string(),
+                record_type: record_type.to_string(),
+                value: value.to_string(),
+                ttl: 3600,
+                priority: None,
+                weight: None,
+                port: None,
+                is_dynamic: false,
+                view: DEFAULT_VIEW.to_string(),
+                created_at: now,
+                updated_at: now,
+            })
+            .collect();
+
+        LoadedZone::new(zone, records, Vec::new())
+    }
+
+    #[test]
+    fn test_existing_name_without_type_is_nodata() {
+        let zone = test_zone(&[("host", "A", "192.0.2.10")]);
+
+        match zone.lookup("host.example.com", TYPE_AAAA, false, DEFAULT_VIEW) {
+            ZoneLookup::NoData(authority) => {
+                assert_eq!(authority.len(), 1);
+                assert_eq!(authority[0].rtype, TYPE_SOA);
+                assert_eq!(authority[0].name, "example.com");
+            }
+            other => panic!("expected NODATA, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_missing_name_is_nxdomain() {
+        let zone = test_zone(&[("host", "A", "192.0.2.10")]);
+
+        match zone.lookup("missing.example.com", TYPE_A, false, DEFAULT_VIEW) {
+            ZoneLookup::NameError(authority) => {
+                assert_eq!(authority.len(), 1);
+                assert_eq!(authority[0].rtype, TYPE_SOA);
+            }
+            other => panic!("expected NXDOMAIN, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_non_terminal_is_nodata() {
+        let zone = test_zone(&[("www.lab", "A", "192.0.2.20")]);
+
+        assert!(matches!(zone.lookup("lab.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::NoData(_)));
+        assert!(matches!(zone.lookup("www.lab.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::Found(_)));
+    }
+
+    #[test]
+    fn test_views_replace_names() {
+        let mut zone = test_zone(&[
+            ("www", "A", "203.0.113.10"),
+            ("www", "TXT", "public"),
+            ("mail", "A", "203.0.113.20"),
+        ]);
+        for (name, value) in [("www", "10.0.0.10"), ("intranet", "10.0.0.5")] {
+            let record = DnsRecord {
+                id: Uuid::new_v4(),
+                name: name.to_string(),
+                record_type: "A".to_string(),
+                value: value.to_string(),
+                view: "internal".to_string(),
+                ..zone.records[0].clone()
+            };
+            zone.records.push(record);
+        }
+        zone.rebuild();
+
+        let address = |lookup: ZoneLookup| match lookup {
+            ZoneLookup::Found(answers) => answers[0].rdata.clone(),
+            other => panic!("expected an answer, got {:?}", other),
+        };
+
+        assert_eq!(address(zone.lookup("www.example.com", TYPE_A, false, DEFAULT_VIEW)), vec![203, 0, 113, 10]);
+        assert_eq!(address(zone.lookup("www.example.com", TYPE_A, false, "internal")), vec![10, 0, 0, 10]);
+        // The view's www replaces the whole name, TXT included
+        assert!(matches!(zone.lookup("www.example.com", message::TYPE_TXT, false, "internal"), ZoneLookup::NoData(_)));
+        // Names the view doesn't mention fall through to the default records
+        assert_eq!(address(zone.lookup("mail.example.com", TYPE_A, false, "internal")), vec![203, 0, 113, 20]);
+
+        // View-only names don't exist for anyone else
+        assert!(matches!(zone.lookup("intranet.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::NameError(_)));
+        assert!(matches!(zone.lookup("intranet.example.com", TYPE_A, false, "lab"), ZoneLookup::NameError(_)));
+        assert_eq!(address(zone.lookup("intranet.example.com", TYPE_A, false, "internal")), vec![10, 0, 0, 5]);
+
+        // Transfers carry the default view only
+        let transferred = zone.transfer_records().unwrap();
+        assert!(!transferred.iter().any(|rr| rr.rdata == vec![10, 0, 0, 10] || rr.rdata == vec![10, 0, 0, 5]));
+    }
+
+    #[test]
+    fn test_wildcard_answers_missing_names() {
+        let zone = test_zone(&[
+            ("*.apps", "A", "192.0.2.50"),
+            ("*.apps", "TXT", "wildcard"),
+            ("api.apps", "A", "192.0.2.60"),
+            ("db.apps", "AAAA", "2001:db8::60"),
+        ]);
+
+        match zone.lookup("shop.apps.example.com", TYPE_A, false, DEFAULT_VIEW) {
+            ZoneLookup::Synthesized { answers, authority } => {
+                assert_eq!(answers.len(), 1);
+                assert_eq!(answers[0].name, "shop.apps.example.com");
+                assert_eq!(answers[0].rdata, vec![192, 0, 2, 50]);
+                assert!(authority.is_empty());
+            }
+            other => panic!("expected wildcard answer, got {:?}", other),
+        }
+
+        // Deeper names are covered too, since nothing exists between them and apps
+        assert!(matches!(zone.lookup("a.b.apps.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::Synthesized { .. }));
+
+        // The wildcard exists but has no AAAA
+        assert!(matches!(zone.lookup("shop.apps.example.com", TYPE_AAAA, false, DEFAULT_VIEW), ZoneLookup::NoData(_)));
+    }
+
+    #[test]
+    fn test_exact_name_wins_over_wildcard() {
+        let zone = test_zone(&[
+            ("*.apps", "A", "192.0.2.50"),
+            ("api.apps", "A", "192.0.2.60"),
+            ("db.apps", "AAAA", "2001:db8::60"),
+            ("www.sub.apps", "A", "192.0.2.70"),
+        ]);
+
+        match zone.lookup("api.apps.example.com", TYPE_A, false, DEFAULT_VIEW) {
+            ZoneLookup::Found(answers) => assert_eq!(answers[0].rdata, vec![192, 0, 2, 60]),
+            other => panic!("expected exact answer, got {:?}", other),
+        }
+
+        // An existing name without the type is NODATA, not the wildcard's A record
+        assert!(matches!(zone.lookup("db.apps.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::NoData(_)));
+        // Empty non-terminals block the wildcard as well (RFC 4592 section 2.2.2)
+        assert!(matches!(zone.lookup("sub.apps.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::NoData(_)));
+        assert!(matches!(zone.lookup("x.sub.apps.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::NameError(_)));
+    }
+
+    #[test]
+    fn test_wildcard_does_not_match_other_branches() {
+        let zone = test_zone(&[("*.apps", "A", "192.0.2.50"), ("host", "A", "192.0.2.10")]);
+
+        assert!(matches!(zone.lookup("missing.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::NameError(_)));
+        assert!(matches!(zone.lookup("x.host.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::NameError(_)));
+        // The wildcard doesn't cover the name it sits below
+        assert!(matches!(zone.lookup("apps.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::NoData(_)));
+    }
+
+    #[test]
+    fn test_alias_answers_address_queries() {
+        let zone = test_zone(&[("@", "ALIAS", "cdn.example.net."), ("@", "MX", "mail.example.com."), ("edge", "ALIAS", "cdn.example.net")]);
+
+        match zone.lookup("example.com", TYPE_AAAA, false, DEFAULT_VIEW) {
+            ZoneLookup::Alias { target, ttl, authority } => {
+                assert_eq!(target, "cdn.example.net");
+                assert_eq!(ttl, 3600);
+                assert_eq!(authority[0].rtype, TYPE_SOA);
+            }
+            other => panic!("expected ALIAS, got {:?}", other),
+        }
+
+        // Other types at the name are served as usual
+        assert!(matches!(zone.lookup("example.com", message::TYPE_MX, false, DEFAULT_VIEW), ZoneLookup::Found(_)));
+        // A name holding only an ALIAS still exists
+        assert!(matches!(zone.lookup("edge.example.com", message::TYPE_TXT, false, DEFAULT_VIEW), ZoneLookup::NoData(_)));
+        assert!(matches!(zone.lookup("edge.example.com", TYPE_A, false, DEFAULT_VIEW), ZoneLookup::Alias { .. }));
+        // The ALIAS itself is never served
+        assert!(!zone.transfer_records().unwrap().iter().any(|rr| rr.rtype == DnsRecordType::ALIAS.code()));
+    }
+
+    #[test]
+    fn test_flatten_alias_renames_and_caps_ttl() {
+        let records = vec![
+            DnsResourceRecord::new("edge.cdn.example.net", TYPE_A, 60, vec![192, 0, 2, 1]),
+            DnsResourceRecord::new("edge.cdn.example.net", TYPE_A, 7200, vec![192, 0, 2, 2]),
+        ];
+
+        let flattened = flatten_alias(&records, "example.com", 300);
+        assert!(flattened.iter().all(|rr| rr.name == "example.com"));
+        assert_eq!(flattened[0].ttl, 60);
+        assert_eq!(flattened[1].ttl, 300);
+        assert_eq!(flattened[1].rdata, vec![192, 0, 2, 2]);
+    }
+
+    #[test]
+    fn test_transfer_is_bracketed_by_soa() {
+        let zone = test_zone(&[("@", "NS", "ns1.example.com."), ("host", "A", "192.0.2.10")]);
+        let records = zone.transfer_records().unwrap();
+
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].rtype, TYPE_SOA);
+        assert_eq!(records[3], records[0]);
+        assert_eq!(records.iter().filter(|rr| rr.rtype == TYPE_SOA).count(), 2);
+        assert!(records.iter().any(|rr| rr.name == "host.example.com" && rr.rtype == TYPE_A));
+    }
+
+    #[test]
+    fn test_srv_record_rdata() {
+        let mut zone = test_zone(&[("_sip._udp", "SRV", "sip1.example.com")]);
+        zone.records[0].priority = Some(10);
+        zone.records[0].weight = Some(60);
+        zone.records[0].port = Some(5060);
+        zone.rebuild();
+
+        match zone.lookup("_sip._udp.example.com", message::TYPE_SRV, false, DEFAULT_VIEW) {
+            ZoneLookup::Found(answers) => {
+                assert_eq!(answers.len(), 1);
+                let rdata = &answers[0].rdata;
+                assert_eq!(&rdata[..6], &[0, 10, 0, 60, 0x13, 0xc4]);
+                let (target, _) = message::read_name(rdata, 6).unwrap();
+                assert_eq!(target, "sip1.example.com");
+            }
+            other => panic!("expected SRV answer, got {:?}", other),
+        }
+
+        // Without weight/port the record cannot be served
+        zone.records[0].weight = None;
+        assert!(record_to_rr(&zone.records[0], "_sip._udp.example.com").is_err());
+    }
+
+    #[test]
+    fn test_caa_record_rdata() {
+        let zone = test_zone(&[("@", "CAA", "0 issue \"letsencrypt.org\"")]);
+
+        match zone.lookup("example.com", message::TYPE_CAA, false, DEFAULT_VIEW) {
+            ZoneLookup::Found(answers) => {
+                assert_eq!(answers.len(), 1);
+                let mut expected = vec![0, 5];
+                expected.extend_from_slice(b"issue");
+                expected.extend_from_slice(b"letsencrypt.org");
+                assert_eq!(answers[0].rdata, expected);
+            }
+            other => panic!("expected CAA answer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_record_owner_expansion() {
+        assert_eq!(record_owner("@", "example.com"), "example.com");
+        assert_eq!(record_owner("www", "example.com"), "www.example.com");
+        assert_eq!(record_owner("WWW.example.com", "example.com"), "www.example.com");
+        assert_eq!(record_owner("host.other.org.", "example.com"), "host.other.org");
+    }
+
+    #[test]
+    fn test_relative_name() {
+        assert_eq!(relative_name("example.com", "example.com").as_deref(), Some("@"));
+        assert_eq!(relative_name("laptop.example.com", "example.com").as_deref(), Some("laptop"));
+        assert_eq!(relative_name("a.b.example.com", "example.com").as_deref(), Some("a.b"));
+        assert_eq!(relative_name("badexample.com", "example.com"), None);
+        assert_eq!(relative_name("host.other.org", "example.com"), None);
+    }
+}
@@ -0,0 +1,412 @@
+// TSIG (RFC 8945) request verification and response signing with HMAC-SHA256
+use crate::config::TsigKeyConfig;
+use crate::dns::message::{self, DnsMessage, DnsResourceRecord, CLASS_ANY, RCODE_NOTAUTH, TYPE_TSIG};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ring::hmac;
+use anyhow::{Result, anyhow};
+
+pub const ALGORITHM_HMAC_SHA256: &str = "hmac-sha256";
+/// Clock skew we allow between signer and verifier, in seconds
+const FUDGE: u16 = 300;
+
+/// TSIG error codes (RFC 8945 section 3)
+pub const TSIG_BADSIG: u16 = 16;
+pub const TSIG_BADKEY: u16 = 17;
+pub const TSIG_BADTIME: u16 = 18;
+
+/// A shared secret peers use to sign transfers and updates
+#[derive(Clone)]
+pub struct TsigKey {
+    pub name: String,
+    key: hmac::Key,
+}
+
+impl TsigKey {
+    pub fn new(name: &str, algorithm: &str, secret: &str) -> Result<Self> {
+        if message::normalize_name(algorithm) != ALGORITHM_HMAC_SHA256 {
+            return Err(anyhow!("Unsupported TSIG algorithm for key {}: {}", name, algorithm));
+        }
+        let secret = BASE64
+            .decode(secret.trim())
+            .map_err(|e| anyhow!("Invalid base64 secret for TSIG key {}: {}", name, e))?;
+
+        Ok(Self {
+            name: message::normalize_name(name),
+            key: hmac::Key::new(hmac::HMAC_SHA256, &secret),
+        })
+    }
+
+    pub fn from_config(keys: &[TsigKeyConfig]) -> Result<Vec<Self>> {
+        keys.iter()
+            .map(|key| Self::new(&key.name, &key.algorithm, &key.secret))
+            .collect()
+    }
+}
+
+/// The fields of a TSIG record; the owner name is the key name
+#[derive(Debug, Clone, PartialEq)]
+pub struct TsigRecord {
+    pub key_name: String,
+    pub algorithm: String,
+    pub time_signed: u64,
+    pub fudge: u16,
+    pub mac: Vec<u8>,
+    pub original_id: u16,
+    pub error: u16,
+    pub other: Vec<u8>,
+}
+
+/// Outcome of checking a request's TSIG
+pub enum TsigCheck {
+    Unsigned,
+    Valid(RequestSignature),
+    Invalid { tsig: TsigRecord, error: u16 },
+}
+
+/// A verified request signature, kept to sign the responses to it
+pub struct RequestSignature {
+    key: TsigKey,
+    mac: Vec<u8>,
+}
+
+impl RequestSignature {
+    pub fn key_name(&self) -> &str {
+        &self.key.name
+    }
+
+    pub fn sign_response(&self, response: &mut Vec<u8>, now: u64) -> Result<()> {
+        sign_message(response, &self.key, Some(self.mac.as_slice()), false, now).map(|_| ())
+    }
+
+    /// Signs every message of a zone transfer. Each one chains the previous MAC, and all but
+    /// the first cover only the timers (RFC 8945 section 5.3.1).
+    pub fn sign_stream(&self, messages: &mut [Vec<u8>], now: u64) -> Result<()> {
+        let mut prior = self.mac.clone();
+        for (i, message) in messages.iter_mut().enumerate() {
+            prior = sign_message(message, &self.key, Some(prior.as_slice()), i > 0, now)?;
+        }
+        Ok(())
+    }
+}
+
+/// Verifies the TSIG on a raw request against the configured keys
+pub fn verify_request(keys: &[TsigKey], data: &[u8], now: u64) -> Result<TsigCheck> {
+    let Some((start, tsig)) = locate(data)? else {
+        return Ok(TsigCheck::Unsigned);
+    };
+
+    let key = keys
+        .iter()
+        .find(|key| key.name == tsig.key_name && tsig.algorithm == ALGORITHM_HMAC_SHA256);
+    let Some(key) = key else {
+        return Ok(TsigCheck::Invalid { tsig, error: TSIG_BADKEY });
+    };
+
+    if !verify_message(key, &data[..start], &tsig, None, false) {
+        return Ok(TsigCheck::Invalid { tsig, error: TSIG_BADSIG });
+    }
+    if now.abs_diff(tsig.time_signed) > tsig.fudge as u64 {
+        return Ok(TsigCheck::Invalid { tsig, error: TSIG_BADTIME });
+    }
+
+    Ok(TsigCheck::Valid(RequestSignature { key: key.clone(), mac: tsig.mac }))
+}
+
+/// NOTAUTH answer carrying the TSIG error for a request that failed verification (RFC 8945
+/// section 5.2). It is left unsigned since the request's key or MAC can't be trusted.
+pub fn error_response(query: &DnsMessage, request: &TsigRecord, error: u16, now: u64) -> Result<Vec<u8>> {
+    let mut response = DnsMessage::error_response(query, RCODE_NOTAUTH).to_bytes()?;
+    let tsig = TsigRecord {
+        key_name: request.key_name.clone(),
+        algorithm: request.algorithm.clone(),
+        time_signed: now,
+        fudge: FUDGE,
+        mac: Vec::new(),
+        original_id: query.id,
+        error,
+        // BADTIME tells the client our clock
+        other: if error == TSIG_BADTIME { now.to_be_bytes()[2..].to_vec() } else { Vec::new() },
+    };
+    append_record(&mut response, &tsig)?;
+    Ok(response)
+}
+
+/// Signs an outgoing request and returns its MAC, needed to verify the response
+pub fn sign_request(message: &mut Vec<u8>, key: &TsigKey, now: u64) -> Result<Vec<u8>> {
+    sign_message(message, key, None, false, now)
+}
+
+fn sign_message(
+    message: &mut Vec<u8>,
+    key: &TsigKey,
+    prior_mac: Option<&[u8]>,
+    timers_only: bool,
+    now: u64,
+) -> Result<Vec<u8>> {
+    if message.len() < 12 {
+        return Err(anyhow!("DNS message too short to sign"));
+    }
+
+    let mut tsig = TsigRecord {
+        key_name: key.name.clone(),
+        algorithm: ALGORITHM_HMAC_SHA256.to_string(),
+        time_signed: now,
+        fudge: FUDGE,
+        mac: Vec::new(),
+        original_id: u16::from_be_bytes([message[0], message[1]]),
+        error: 0,
+        other: Vec::new(),
+    };
+    let digest = digest(&message[..], &tsig, prior_mac, timers_only)?;
+    tsig.mac = hmac::sign(&key.key, &digest).as_ref().to_vec();
+
+    append_record(message, &tsig)?;
+    Ok(tsig.mac)
+}
+
+/// Checks `tsig.mac` over `message`, the signed message with its TSIG record already removed
+fn verify_message(key: &TsigKey, message: &[u8], tsig: &TsigRecord, prior_mac: Option<&[u8]>, timers_only: bool) -> bool {
+    if message.len() < 12 {
+        return false;
+    }
+
+    // The MAC covers the message as it was before the TSIG was added
+    let mut unsigned = message.to_vec();
+    unsigned[..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+    let arcount = u16::from_be_bytes([unsigned[10], unsigned[11]]).saturating_sub(1);
+    unsigned[10..12].copy_from_slice(&arcount.to_be_bytes());
+
+    match digest(&unsigned, tsig, prior_mac, timers_only) {
+        Ok(digest) => hmac::verify(&key.key, &digest, &tsig.mac).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Data covered by the MAC (RFC 8945 section 4.3)
+fn digest(message: &[u8], tsig: &TsigRecord, prior_mac: Option<&[u8]>, timers_only: bool) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(message.len() + 128);
+
+    if let Some(mac) = prior_mac {
+        out.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+        out.extend_from_slice(mac);
+    }
+    out.extend_from_slice(message);
+
+    if !timers_only {
+        message::encode_name(&tsig.key_name, &mut out)?;
+        out.extend_from_slice(&CLASS_ANY.to_be_bytes());
+        out.extend_from_slice(&0u32.to_be_bytes());
+        message::encode_name(&tsig.algorithm, &mut out)?;
+    }
+    out.extend_from_slice(&tsig.time_signed.to_be_bytes()[2..]);
+    out.extend_from_slice(&tsig.fudge.to_be_bytes());
+    if !timers_only {
+        out.extend_from_slice(&tsig.error.to_be_bytes());
+        out.extend_from_slice(&(tsig.other.len() as u16).to_be_bytes());
+        out.extend_from_slice(&tsig.other);
+    }
+
+    Ok(out)
+}
+
+fn append_record(message: &mut Vec<u8>, tsig: &TsigRecord) -> Result<()> {
+    let mut rdata = Vec::new();
+    message::encode_name(&tsig.algorithm, &mut rdata)?;
+    rdata.extend_from_slice(&tsig.time_signed.to_be_bytes()[2..]);
+    rdata.extend_from_slice(&tsig.fudge.to_be_bytes());
+    rdata.extend_from_slice(&(tsig.mac.len() as u16).to_be_bytes());
+    rdata.extend_from_slice(&tsig.mac);
+    rdata.extend_from_slice(&tsig.original_id.to_be_bytes());
+    rdata.extend_from_slice(&tsig.error.to_be_bytes());
+    rdata.extend_from_slice(&(tsig.other.len() as u16).to_be_bytes());
+    rdata.extend_from_slice(&tsig.other);
+
+    let record = DnsResourceRecord {
+        name: tsig.key_name.clone(),
+        rtype: TYPE_TSIG,
+        class: CLASS_ANY,
+        ttl: 0,
+        rdata,
+    };
+    record.write_to(message)?;
+
+    let arcount = u16::from_be_bytes([message[10], message[11]]) + 1;
+    message[10..12].copy_from_slice(&arcount.to_be_bytes());
+    Ok(())
+}
+
+/// Finds a TSIG record in the last additional slot, returning its offset in `data`
+fn locate(data: &[u8]) -> Result<Option<(usize, TsigRecord)>> {
+    if data.len() < 12 {
+        return Err(anyhow!("DNS message too short"));
+    }
+    let count = |at: usize| u16::from_be_bytes([data[at], data[at + 1]]) as usize;
+    if count(10) == 0 {
+        return Ok(None);
+    }
+
+    let mut offset = 12;
+    for _ in 0..count(4) {
+        let (_, next) = message::read_name(data, offset)?;
+        offset = next + 4;
+    }
+
+    let mut last = None;
+    for _ in 0..count(6) + count(8) + count(10) {
+        let start = offset;
+        let (name, next) = message::read_name(data, offset)?;
+        let header = data.get(next..next + 10).ok_or_else(|| anyhow!("Truncated DNS resource record"))?;
+        let rtype = u16::from_be_bytes([header[0], header[1]]);
+        let rdlength = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let rdata = data
+            .get(next + 10..next + 10 + rdlength)
+            .ok_or_else(|| anyhow!("Truncated DNS RDATA"))?;
+        offset = next + 10 + rdlength;
+        last = Some((start, name, rtype, rdata));
+    }
+
+    match last {
+        Some((start, name, TYPE_TSIG, rdata)) => Ok(Some((start, parse_rdata(&name, rdata)?))),
+        _ => Ok(None),
+    }
+}
+
+fn parse_rdata(key_name: &str, rdata: &[u8]) -> Result<TsigRecord> {
+    let (algorithm, mut pos) = message::read_name(rdata, 0)?;
+    let u16_field = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+
+    let time = take(rdata, &mut pos, 6)?;
+    let time_signed = time.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+    let fudge = u16_field(take(rdata, &mut pos, 2)?);
+    let mac_len = u16_field(take(rdata, &mut pos, 2)?) as usize;
+    let mac = take(rdata, &mut pos, mac_len)?.to_vec();
+    let original_id = u16_field(take(rdata, &mut pos, 2)?);
+    let error = u16_field(take(rdata, &mut pos, 2)?);
+    let other_len = u16_field(take(rdata, &mut pos, 2)?) as usize;
+    let other = take(rdata, &mut pos, other_len)?.to_vec();
+
+    Ok(TsigRecord {
+        key_name: message::normalize_name(key_name),
+        algorithm: message::normalize_name(&algorithm),
+        time_signed,
+        fudge,
+        mac,
+        original_id,
+        error,
+        other,
+    })
+}
+
+fn take<'a>(rdata: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let bytes = rdata.get(*pos..*pos + len).ok_or_else(|| anyhow!("Truncated TSIG RDATA"))?;
+    *pos += len;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{DnsQuestion, CLASS_IN, TYPE_AXFR};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn key(name: &str) -> TsigKey {
+        TsigKey::new(name, "hmac-sha256", &BASE64.encode(b"0123456789abcdef0123456789abcdef")).unwrap()
+    }
+
+    fn axfr_query() -> DnsMessage {
+        let mut query = DnsMessage::new(0x4242);
+        query.questions.push(DnsQuestion {
+            name: "example.com".to_string(),
+            qtype: TYPE_AXFR,
+            qclass: CLASS_IN,
+        });
+        query
+    }
+
+    fn signed_query(key: &TsigKey, now: u64) -> Vec<u8> {
+        let mut data = axfr_query().to_bytes().unwrap();
+        sign_request(&mut data, key, now).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_verify_request() {
+        let keys = vec![key("xfr-key.")];
+
+        assert!(matches!(verify_request(&keys, &axfr_query().to_bytes().unwrap(), NOW).unwrap(), TsigCheck::Unsigned));
+
+        match verify_request(&keys, &signed_query(&keys[0], NOW), NOW + 10).unwrap() {
+            TsigCheck::Valid(signature) => assert_eq!(signature.key_name(), "xfr-key"),
+            _ => panic!("expected a valid signature"),
+        }
+
+        // The signed message still parses, with the TSIG as its last additional record
+        let parsed = DnsMessage::parse(&signed_query(&keys[0], NOW)).unwrap();
+        assert_eq!(parsed.additional.last().unwrap().rtype, TYPE_TSIG);
+
+        let mut tampered = signed_query(&keys[0], NOW);
+        tampered[13] ^= 1;
+        assert!(matches!(
+            verify_request(&keys, &tampered, NOW).unwrap(),
+            TsigCheck::Invalid { error: TSIG_BADSIG, .. }
+        ));
+
+        let other = signed_query(&key("other-key"), NOW);
+        assert!(matches!(
+            verify_request(&keys, &other, NOW).unwrap(),
+            TsigCheck::Invalid { error: TSIG_BADKEY, .. }
+        ));
+
+        assert!(matches!(
+            verify_request(&keys, &signed_query(&keys[0], NOW), NOW + 3600).unwrap(),
+            TsigCheck::Invalid { error: TSIG_BADTIME, .. }
+        ));
+    }
+
+    #[test]
+    fn test_signed_responses_chain_macs() {
+        let key = key("xfr-key");
+        let request = signed_query(&key, NOW);
+        let TsigCheck::Valid(signature) = verify_request(std::slice::from_ref(&key), &request, NOW).unwrap() else {
+            panic!("expected a valid signature");
+        };
+
+        let query = axfr_query();
+        let mut messages = vec![
+            DnsMessage::response_to(&query).to_bytes().unwrap(),
+            DnsMessage::response_to(&query).to_bytes().unwrap(),
+        ];
+        signature.sign_stream(&mut messages, NOW).unwrap();
+
+        let (start, first) = locate(&messages[0]).unwrap().unwrap();
+        assert!(verify_message(&key, &messages[0][..start], &first, Some(signature.mac.as_slice()), false));
+
+        let (start, second) = locate(&messages[1]).unwrap().unwrap();
+        assert!(verify_message(&key, &messages[1][..start], &second, Some(first.mac.as_slice()), true));
+        assert!(!verify_message(&key, &messages[1][..start], &second, Some(signature.mac.as_slice()), true));
+    }
+
+    #[test]
+    fn test_error_response_reports_tsig_error() {
+        let request = signed_query(&key("unknown"), NOW);
+        let TsigCheck::Invalid { tsig, error } = verify_request(&[key("xfr-key")], &request, NOW).unwrap() else {
+            panic!("expected an invalid signature");
+        };
+
+        let response = error_response(&axfr_query(), &tsig, error, NOW).unwrap();
+        let parsed = DnsMessage::parse(&response).unwrap();
+        assert_eq!(parsed.rcode, RCODE_NOTAUTH);
+
+        let (_, record) = locate(&response).unwrap().unwrap();
+        assert_eq!(record.error, TSIG_BADKEY);
+        assert_eq!(record.key_name, "unknown");
+        assert!(record.mac.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_unsupported_algorithm_and_bad_secret() {
+        assert!(TsigKey::new("k", "hmac-md5.sig-alg.reg.int", "c2VjcmV0").is_err());
+        assert!(TsigKey::new("k", "hmac-sha256", "not base64!").is_err());
+    }
+}
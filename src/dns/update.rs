@@ -0,0 +1,494 @@
+// RFC 2136 dynamic UPDATE: prerequisite checks and planning record changes for a zone
+use crate::database::models::{DnsRecord, DEFAULT_VIEW};
+use crate::dns::message::{
+    self, DnsResourceRecord, CLASS_ANY, CLASS_IN, CLASS_NONE, RCODE_FORMERR, RCODE_NOERROR, RCODE_NOTIMP,
+    RCODE_NOTZONE, RCODE_NXDOMAIN, RCODE_NXRRSET, RCODE_YXDOMAIN, RCODE_YXRRSET, TYPE_ANY, TYPE_AXFR,
+    TYPE_CNAME, TYPE_MX, TYPE_NS, TYPE_OPT, TYPE_PTR, TYPE_SOA, TYPE_SRV,
+};
+use crate::dns::record_conflicts;
+use crate::dns::record_types::DnsRecordType;
+use crate::dns::simple_zone_manager::{record_owner, record_to_rr, relative_name};
+use chrono::Utc;
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use uuid::Uuid;
+use anyhow::{Result, anyhow};
+use tracing::debug;
+
+/// Database changes that carry out an UPDATE's update section
+#[derive(Debug, Default)]
+pub struct UpdatePlan {
+    pub removed: Vec<Uuid>,
+    pub added: Vec<DnsRecord>,
+}
+
+impl UpdatePlan {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty()
+    }
+}
+
+/// Checks the prerequisite section (RFC 2136 section 3.2) against the zone's current RRs,
+/// which must include its SOA. Returns the RCODE to answer with; NOERROR lets the update proceed.
+pub fn check_prerequisites(zone_name: &str, zone: &[DnsResourceRecord], prerequisites: &[DnsResourceRecord]) -> u8 {
+    let mut value_dependent = Vec::new();
+
+    for rr in prerequisites {
+        let name = message::normalize_name(&rr.name);
+        if !in_zone(&name, zone_name) {
+            return RCODE_NOTZONE;
+        }
+        if rr.ttl != 0 {
+            return RCODE_FORMERR;
+        }
+
+        let at_name: Vec<&DnsResourceRecord> = zone.iter().filter(|existing| existing.name == name).collect();
+        let exists = if rr.rtype == TYPE_ANY {
+            !at_name.is_empty()
+        } else {
+            at_name.iter().any(|existing| existing.rtype == rr.rtype)
+        };
+
+        match rr.class {
+            CLASS_ANY if rr.rdata.is_empty() => {
+                if !exists {
+                    return if rr.rtype == TYPE_ANY { RCODE_NXDOMAIN } else { RCODE_NXRRSET };
+                }
+            }
+            CLASS_NONE if rr.rdata.is_empty() => {
+                if exists {
+                    return if rr.rtype == TYPE_ANY { RCODE_YXDOMAIN } else { RCODE_YXRRSET };
+                }
+            }
+            CLASS_IN if rr.rtype != TYPE_ANY => value_dependent.push(rr),
+            _ => return RCODE_FORMERR,
+        }
+    }
+
+    // Each RRset listed with values must match the zone's RRset exactly (section 3.2.3)
+    let mut rrsets: Vec<(String, u16)> = value_dependent
+        .iter()
+        .map(|rr| (message::normalize_name(&rr.name), rr.rtype))
+        .collect();
+    rrsets.sort();
+    rrsets.dedup();
+
+    for (name, rtype) in rrsets {
+        let wanted: Vec<&[u8]> = value_dependent
+            .iter()
+            .filter(|rr| rr.rtype == rtype && message::normalize_name(&rr.name) == name)
+            .map(|rr| rr.rdata.as_slice())
+            .collect();
+        let actual: Vec<&[u8]> = zone
+            .iter()
+            .filter(|rr| rr.rtype == rtype && rr.name == name)
+            .map(|rr| rr.rdata.as_slice())
+            .collect();
+
+        let covers = |a: &[&[u8]], b: &[&[u8]]| a.iter().all(|x| b.iter().any(|y| same_rdata(rtype, x, y)));
+        if !covers(&wanted, &actual) || !covers(&actual, &wanted) {
+            return RCODE_NXRRSET;
+        }
+    }
+
+    RCODE_NOERROR
+}
+
+/// Validates the update section before anything is changed (RFC 2136 section 3.4.1)
+pub fn prescan(zone_name: &str, updates: &[DnsResourceRecord]) -> u8 {
+    for rr in updates {
+        if !in_zone(&message::normalize_name(&rr.name), zone_name) {
+            return RCODE_NOTZONE;
+        }
+
+        match rr.class {
+            CLASS_IN => {
+                if is_meta_type(rr.rtype) {
+                    return RCODE_FORMERR;
+                }
+                if DnsRecordType::from_code(rr.rtype).is_none() {
+                    return RCODE_NOTIMP;
+                }
+            }
+            CLASS_ANY => {
+                if rr.ttl != 0 || !rr.rdata.is_empty() || (is_meta_type(rr.rtype) && rr.rtype != TYPE_ANY) {
+                    return RCODE_FORMERR;
+                }
+            }
+            CLASS_NONE => {
+                if rr.ttl != 0 || is_meta_type(rr.rtype) {
+                    return RCODE_FORMERR;
+                }
+            }
+            _ => return RCODE_FORMERR,
+        }
+    }
+
+    RCODE_NOERROR
+}
+
+/// Applies the update section, in order, to the zone's stored records (section 3.4.2).
+/// The SOA is managed by us and apex NS records can't be removed entirely; additions that
+/// would clash with a CNAME are ignored, as the RFC requires.
+pub fn plan_updates(
+    zone_name: &str,
+    zone_id: Uuid,
+    existing: &[DnsRecord],
+    updates: &[DnsResourceRecord],
+) -> Result<UpdatePlan> {
+    let original: HashSet<Uuid> = existing.iter().map(|record| record.id).collect();
+    let mut removed = Vec::new();
+
+    // Records paired with their wire form so deletions can match on RDATA
+    let mut working: Vec<(DnsRecord, DnsResourceRecord)> = existing
+        .iter()
+        .filter_map(|record| {
+            let owner = record_owner(&record.name, zone_name);
+            record_to_rr(record, &owner).ok().map(|rr| (record.clone(), rr))
+        })
+        .collect();
+
+    for rr in updates {
+        let name = message::normalize_name(&rr.name);
+        let apex = name == zone_name;
+
+        match rr.class {
+            CLASS_IN => {
+                if rr.rtype == TYPE_SOA {
+                    continue;
+                }
+                let record = record_from_rr(rr, zone_id, zone_name)?;
+
+                let is_duplicate = |existing: &DnsResourceRecord| {
+                    existing.name == name && existing.rtype == rr.rtype && same_rdata(rr.rtype, &existing.rdata, &rr.rdata)
+                };
+                let duplicate_ttl = working.iter().find(|(_, existing)| is_duplicate(existing)).map(|(_, existing)| existing.ttl);
+
+                match duplicate_ttl {
+                    // Re-adding an existing record only changes its TTL
+                    Some(ttl) if ttl == rr.ttl => continue,
+                    Some(_) => remove_where(&mut working, &original, &mut removed, is_duplicate),
+                    None => {
+                        let records: Vec<DnsRecord> = working.iter().map(|(record, _)| record.clone()).collect();
+                        if let Some(conflict) = record_conflicts::find_conflict(
+                            zone_name, &records, &record.name, &record.record_type, &record.value,
+                        ) {
+                            debug!("Ignoring UPDATE addition for {}: {}", name, conflict);
+                            continue;
+                        }
+                    }
+                }
+
+                let wire = DnsResourceRecord { name: name.clone(), ..rr.clone() };
+                working.push((record, wire));
+            }
+            CLASS_ANY => remove_where(&mut working, &original, &mut removed, |existing| {
+                existing.name == name
+                    && (rr.rtype == TYPE_ANY || existing.rtype == rr.rtype)
+                    && !(apex && (existing.rtype == TYPE_SOA || existing.rtype == TYPE_NS))
+            }),
+            CLASS_NONE => {
+                if rr.rtype == TYPE_SOA {
+                    continue;
+                }
+                let apex_ns = working.iter().filter(|(_, existing)| existing.name == name && existing.rtype == TYPE_NS).count();
+                if apex && rr.rtype == TYPE_NS && apex_ns <= 1 {
+                    continue;
+                }
+                remove_where(&mut working, &original, &mut removed, |existing| {
+                    existing.name == name && existing.rtype == rr.rtype && same_rdata(rr.rtype, &existing.rdata, &rr.rdata)
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let added = working
+        .into_iter()
+        .filter(|(record, _)| !original.contains(&record.id))
+        .map(|(record, _)| record)
+        .collect();
+
+    Ok(UpdatePlan { removed, added })
+}
+
+/// Drops matching records from the working set, noting the stored ones that must be deleted
+fn remove_where(
+    working: &mut Vec<(DnsRecord, DnsResourceRecord)>,
+    original: &HashSet<Uuid>,
+    removed: &mut Vec<Uuid>,
+    matches: impl Fn(&DnsResourceRecord) -> bool,
+) {
+    working.retain(|(record, rr)| {
+        if !matches(rr) {
+            return true;
+        }
+        if original.contains(&record.id) {
+            removed.push(record.id);
+        }
+        false
+    });
+}
+
+/// Converts an added RR into the stored form: relative owner name, zone-file style value and
+/// the MX/SRV numeric fields
+pub fn record_from_rr(rr: &DnsResourceRecord, zone_id: Uuid, zone_name: &str) -> Result<DnsRecord> {
+    let record_type = DnsRecordType::from_code(rr.rtype)
+        .ok_or_else(|| anyhow!("Unsupported record type {}", rr.rtype))?;
+    let owner = message::normalize_name(&rr.name);
+    let name = relative_name(&owner, zone_name)
+        .ok_or_else(|| anyhow!("{} is not inside zone {}", owner, zone_name))?;
+    let rdata = rr.rdata.as_slice();
+
+    let (value, priority, weight, port) = match record_type {
+        DnsRecordType::A => {
+            let octets = <[u8; 4]>::try_from(rdata).map_err(|_| anyhow!("A RDATA must be 4 bytes"))?;
+            (Ipv4Addr::from(octets).to_string(), None, None, None)
+        }
+        DnsRecordType::AAAA => {
+            let octets = <[u8; 16]>::try_from(rdata).map_err(|_| anyhow!("AAAA RDATA must be 16 bytes"))?;
+            (Ipv6Addr::from(octets).to_string(), None, None, None)
+        }
+        DnsRecordType::CNAME | DnsRecordType::NS | DnsRecordType::PTR => (rdata_target(rdata, 0)?, None, None, None),
+        DnsRecordType::MX => (rdata_target(rdata, 2)?, Some(rdata_u16(rdata, 0)? as i32), None, None),
+        DnsRecordType::SRV => (
+            rdata_target(rdata, 6)?,
+            Some(rdata_u16(rdata, 0)? as i32),
+            Some(rdata_u16(rdata, 2)? as i32),
+            Some(rdata_u16(rdata, 4)? as i32),
+        ),
+        DnsRecordType::TXT => (rdata_text(rdata)?, None, None, None),
+        DnsRecordType::CAA => (rdata_caa_value(rdata)?, None, None, None),
+        DnsRecordType::SOA => return Err(anyhow!("SOA records can't be added by UPDATE")),
+        DnsRecordType::ALIAS => return Err(anyhow!("ALIAS records can't be added by UPDATE")),
+    };
+
+    let now = Utc::now();
+    Ok(DnsRecord {
+        id: Uuid::new_v4(),
+        zone_id,
+        name,
+        record_type: record_type.to_string(),
+        value,
+        ttl: rr.ttl.min(i32::MAX as u32) as i32,
+        priority,
+        weight,
+        port,
+        is_dynamic: true,
+        view: DEFAULT_VIEW.to_string(),
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+fn in_zone(name: &str, zone_name: &str) -> bool {
+    name == zone_name || name.ends_with(&format!(".{}", zone_name))
+}
+
+/// QTYPE-only and pseudo types that can never be stored (RFC 6895 section 3.1)
+fn is_meta_type(rtype: u16) -> bool {
+    rtype == TYPE_OPT || rtype == TYPE_AXFR || (128..=255).contains(&rtype)
+}
+
+/// Compares RDATA, ignoring case in embedded domain names
+fn same_rdata(rtype: u16, a: &[u8], b: &[u8]) -> bool {
+    match rtype {
+        TYPE_NS | TYPE_CNAME | TYPE_PTR | TYPE_MX | TYPE_SRV | TYPE_SOA => a.eq_ignore_ascii_case(b),
+        _ => a == b,
+    }
+}
+
+fn rdata_u16(rdata: &[u8], offset: usize) -> Result<u16> {
+    rdata
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| anyhow!("Truncated RDATA"))
+}
+
+/// Absolute (dot-terminated) name filling the rest of the RDATA from `offset`
+fn rdata_target(rdata: &[u8], offset: usize) -> Result<String> {
+    if offset > rdata.len() {
+        return Err(anyhow!("Truncated RDATA"));
+    }
+    let (name, end) = message::read_name(rdata, offset)?;
+    if end != rdata.len() {
+        return Err(anyhow!("Trailing bytes after name in RDATA"));
+    }
+    Ok(format!("{}.", name))
+}
+
+fn rdata_text(rdata: &[u8]) -> Result<String> {
+    let mut text = String::new();
+    let mut pos = 0;
+
+    while pos < rdata.len() {
+        let len = rdata[pos] as usize;
+        let chunk = rdata.get(pos + 1..pos + 1 + len).ok_or_else(|| anyhow!("Truncated TXT RDATA"))?;
+        text.push_str(&String::from_utf8_lossy(chunk));
+        pos += len + 1;
+    }
+
+    Ok(text)
+}
+
+fn rdata_caa_value(rdata: &[u8]) -> Result<String> {
+    let flags = *rdata.first().ok_or_else(|| anyhow!("Truncated CAA RDATA"))?;
+    let tag_len = *rdata.get(1).ok_or_else(|| anyhow!("Truncated CAA RDATA"))? as usize;
+    let tag = rdata.get(2..2 + tag_len).ok_or_else(|| anyhow!("Truncated CAA RDATA"))?;
+    let value = &rdata[2 + tag_len..];
+
+    Ok(format!("{} {} \"{}\"", flags, String::from_utf8_lossy(tag), String::from_utf8_lossy(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{TYPE_A, TYPE_TXT};
+
+    const ZONE: &str = "example.com";
+
+    fn stored(name: &str, record_type: &str, value: &str) -> DnsRecord {
+        let now = Utc::now();
+        DnsRecord {
+            id: Uuid::new_v4(),
+            zone_id: Uuid::nil(),
+            name: name.to_string(),
+            record_type: record_type.to_string(),
+            value: value.to_string(),
+            ttl: 300,
+            priority: None,
+            weight: None,
+            port: None,
+            is_dynamic: false,
+            view: DEFAULT_VIEW.to_string(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn rr(name: &str, rtype: u16, class: u16, ttl: u32, rdata: Vec<u8>) -> DnsResourceRecord {
+        DnsResourceRecord { name: name.to_string(), rtype, class, ttl, rdata }
+    }
+
+    fn a(ip: [u8; 4]) -> Vec<u8> {
+        message::rdata_a(Ipv4Addr::from(ip))
+    }
+
+    fn wire(records: &[DnsRecord]) -> Vec<DnsResourceRecord> {
+        let soa = message::rdata_soa("ns1.example.com", "hostmaster.example.com", 1, 3600, 900, 604800, 300).unwrap();
+        let mut rrs = vec![DnsResourceRecord::new(ZONE, TYPE_SOA, 300, soa)];
+        rrs.extend(records.iter().map(|record| record_to_rr(record, &record_owner(&record.name, ZONE)).unwrap()));
+        rrs
+    }
+
+    #[test]
+    fn test_prerequisites() {
+        let zone = wire(&[stored("host", "A", "192.0.2.10")]);
+        let check = |prereq: DnsResourceRecord| check_prerequisites(ZONE, &zone, &[prereq]);
+
+        assert_eq!(check(rr("host.example.com", TYPE_A, CLASS_ANY, 0, vec![])), RCODE_NOERROR);
+        assert_eq!(check(rr("host.example.com", TYPE_TXT, CLASS_ANY, 0, vec![])), RCODE_NXRRSET);
+        assert_eq!(check(rr("new.example.com", TYPE_ANY, CLASS_ANY, 0, vec![])), RCODE_NXDOMAIN);
+        assert_eq!(check(rr("new.example.com", TYPE_ANY, CLASS_NONE, 0, vec![])), RCODE_NOERROR);
+        assert_eq!(check(rr("host.example.com", TYPE_ANY, CLASS_NONE, 0, vec![])), RCODE_YXDOMAIN);
+        assert_eq!(check(rr("host.example.com", TYPE_A, CLASS_NONE, 0, vec![])), RCODE_YXRRSET);
+        assert_eq!(check(rr("host.example.com", TYPE_A, CLASS_IN, 0, a([192, 0, 2, 10]))), RCODE_NOERROR);
+        assert_eq!(check(rr("host.example.com", TYPE_A, CLASS_IN, 0, a([192, 0, 2, 11]))), RCODE_NXRRSET);
+        assert_eq!(check(rr("host.example.org", TYPE_A, CLASS_ANY, 0, vec![])), RCODE_NOTZONE);
+        assert_eq!(check(rr("host.example.com", TYPE_A, CLASS_ANY, 60, vec![])), RCODE_FORMERR);
+    }
+
+    #[test]
+    fn test_prescan() {
+        assert_eq!(prescan(ZONE, &[rr("h.example.com", TYPE_A, CLASS_IN, 300, a([192, 0, 2, 1]))]), RCODE_NOERROR);
+        assert_eq!(prescan(ZONE, &[rr("h.example.com", TYPE_ANY, CLASS_ANY, 0, vec![])]), RCODE_NOERROR);
+        assert_eq!(prescan(ZONE, &[rr("h.example.com", TYPE_ANY, CLASS_IN, 300, vec![])]), RCODE_FORMERR);
+        assert_eq!(prescan(ZONE, &[rr("h.example.com", TYPE_A, CLASS_NONE, 300, a([192, 0, 2, 1]))]), RCODE_FORMERR);
+        assert_eq!(prescan(ZONE, &[rr("h.example.org", TYPE_A, CLASS_IN, 300, a([192, 0, 2, 1]))]), RCODE_NOTZONE);
+        assert_eq!(prescan(ZONE, &[rr("h.example.com", 99, CLASS_IN, 300, vec![1])]), RCODE_NOTIMP);
+    }
+
+    #[test]
+    fn test_plan_adds_replaces_and_deletes() {
+        let host = stored("host", "A", "192.0.2.10");
+        let old = stored("old", "A", "192.0.2.20");
+        let existing = vec![host.clone(), old.clone()];
+
+        let plan = plan_updates(ZONE, Uuid::nil(), &existing, &[
+            // Delete the host's A RRset, then give it a new address
+            rr("host.example.com", TYPE_A, CLASS_ANY, 0, vec![]),
+            rr("host.example.com", TYPE_A, CLASS_IN, 600, a([192, 0, 2, 11])),
+            // Delete one record by value
+            rr("old.example.com", TYPE_A, CLASS_NONE, 0, a([192, 0, 2, 20])),
+            // The SOA is ours to manage
+            rr(ZONE, TYPE_SOA, CLASS_ANY, 0, vec![]),
+        ]).unwrap();
+
+        let mut removed = plan.removed.clone();
+        removed.sort();
+        let mut expected = vec![host.id, old.id];
+        expected.sort();
+        assert_eq!(removed, expected);
+
+        assert_eq!(plan.added.len(), 1);
+        assert_eq!(plan.added[0].name, "host");
+        assert_eq!(plan.added[0].value, "192.0.2.11");
+        assert_eq!(plan.added[0].ttl, 600);
+        assert!(plan.added[0].is_dynamic);
+    }
+
+    #[test]
+    fn test_plan_ignores_duplicates_and_cname_clashes() {
+        let existing = vec![stored("host", "A", "192.0.2.10"), stored("www", "CNAME", "host.example.com.")];
+
+        let plan = plan_updates(ZONE, Uuid::nil(), &existing, &[
+            rr("host.example.com", TYPE_A, CLASS_IN, 300, a([192, 0, 2, 10])),
+            rr("www.example.com", TYPE_A, CLASS_IN, 300, a([192, 0, 2, 12])),
+            rr("host.example.com", TYPE_CNAME, CLASS_IN, 300, message::rdata_name("elsewhere.example.com").unwrap()),
+        ]).unwrap();
+
+        assert!(plan.is_empty());
+
+        // A CNAME can be swapped for an address once the CNAME is gone
+        let plan = plan_updates(ZONE, Uuid::nil(), &existing, &[
+            rr("www.example.com", TYPE_CNAME, CLASS_ANY, 0, vec![]),
+            rr("www.example.com", TYPE_A, CLASS_IN, 300, a([192, 0, 2, 12])),
+        ]).unwrap();
+        assert_eq!(plan.removed, vec![existing[1].id]);
+        assert_eq!(plan.added[0].record_type, "A");
+    }
+
+    #[test]
+    fn test_last_apex_ns_is_kept() {
+        let existing = vec![stored("@", "NS", "ns1.example.com.")];
+
+        let plan = plan_updates(ZONE, Uuid::nil(), &existing, &[
+            rr(ZONE, TYPE_NS, CLASS_NONE, 0, message::rdata_name("ns1.example.com").unwrap()),
+            rr(ZONE, TYPE_ANY, CLASS_ANY, 0, vec![]),
+        ]).unwrap();
+
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn test_record_from_rr() {
+        let mx = rr("example.com", TYPE_MX, CLASS_IN, 3600, message::rdata_mx(10, "mail.example.com").unwrap());
+        let record = record_from_rr(&mx, Uuid::nil(), ZONE).unwrap();
+        assert_eq!((record.name.as_str(), record.value.as_str(), record.priority), ("@", "mail.example.com.", Some(10)));
+
+        let srv = rr("_sip._udp.example.com", TYPE_SRV, CLASS_IN, 60, message::rdata_srv(10, 60, 5060, "sip.example.com").unwrap());
+        let record = record_from_rr(&srv, Uuid::nil(), ZONE).unwrap();
+        assert_eq!(record.name, "_sip._udp");
+        assert_eq!((record.priority, record.weight, record.port), (Some(10), Some(60), Some(5060)));
+
+        // Stored records convert back to the same wire form
+        let owner = record_owner(&record.name, ZONE);
+        assert_eq!(record_to_rr(&record, &owner).unwrap().rdata, srv.rdata);
+
+        let txt = rr("t.example.com", TYPE_TXT, CLASS_IN, 60, message::rdata_txt("v=spf1 -all"));
+        assert_eq!(record_from_rr(&txt, Uuid::nil(), ZONE).unwrap().value, "v=spf1 -all");
+
+        let caa = rr("example.com", message::TYPE_CAA, CLASS_IN, 60, message::rdata_caa(0, "issue", "letsencrypt.org").unwrap());
+        assert_eq!(record_from_rr(&caa, Uuid::nil(), ZONE).unwrap().value, "0 issue \"letsencrypt.org\"");
+    }
+}
@@ -0,0 +1,585 @@
+// RFC 1035 master files (BIND zone files): rendering for export and parsing for import
+use crate::database::models::{DnsRecord, DnsZone};
+use crate::dns::message;
+use crate::dns::record_types::{self, is_valid_domain_name, CaaValue, DnsRecordType};
+use crate::dns::simple_zone_manager::{record_owner, relative_name, soa_names};
+use std::fmt::Write as _;
+use std::str::FromStr;
+use anyhow::{Result, anyhow, bail};
+
+/// TTL for imported records when neither the record nor a `$TTL` directive gives one
+const DEFAULT_IMPORT_TTL: u32 = 3600;
+
+/// Renders a zone as a master file: `$ORIGIN`/`$TTL`, the SOA built from the zone fields, then
+/// every record sorted by owner and type so exports of the same zone diff cleanly
+pub fn render_zone(zone: &DnsZone, records: &[DnsRecord]) -> Result<String> {
+    let zone_name = message::normalize_name(&zone.name);
+    let (mname, rname) = soa_names(zone);
+    let mut out = String::new();
+
+    writeln!(out, "$ORIGIN {}", absolute(&zone_name))?;
+    writeln!(out, "$TTL {}", zone.minimum_ttl)?;
+    writeln!(
+        out,
+        "@\t{}\tIN\tSOA\t{} {} {} {} {} {} {}",
+        zone.minimum_ttl,
+        absolute(&mname),
+        absolute(&rname),
+        zone.serial_number,
+        zone.refresh_interval,
+        zone.retry_interval,
+        zone.expire_interval,
+        zone.minimum_ttl,
+    )?;
+
+    let mut lines = Vec::with_capacity(records.len());
+    for record in records {
+        let record_type = DnsRecordType::from_str(&record.record_type)?;
+        // The zone's own SOA is generated from its fields above
+        if record_type == DnsRecordType::SOA {
+            continue;
+        }
+
+        let owner = record_owner(&record.name, &zone_name);
+        let owner = relative_name(&owner, &zone_name).unwrap_or_else(|| absolute(&owner));
+        let rdata = render_rdata(record, &record_type)?;
+        lines.push((owner, record_type, record.ttl, rdata));
+    }
+
+    // Apex records first, then the rest alphabetically
+    lines.sort_by(|a, b| {
+        (a.0 != "@", &a.0, a.1.code(), &a.3).cmp(&(b.0 != "@", &b.0, b.1.code(), &b.3))
+    });
+
+    for (owner, record_type, ttl, rdata) in lines {
+        writeln!(out, "{}\t{}\tIN\t{}\t{}", owner, ttl, record_type.to_string(), rdata)?;
+    }
+
+    Ok(out)
+}
+
+fn render_rdata(record: &DnsRecord, record_type: &DnsRecordType) -> Result<String> {
+    let value = record.value.trim();
+
+    Ok(match record_type {
+        DnsRecordType::A | DnsRecordType::AAAA => value.to_string(),
+        DnsRecordType::CNAME | DnsRecordType::NS | DnsRecordType::PTR | DnsRecordType::ALIAS => absolute(value),
+        DnsRecordType::MX => format!("{} {}", record.priority.unwrap_or(10), absolute(value)),
+        DnsRecordType::SRV => format!(
+            "{} {} {} {}",
+            record.priority.unwrap_or(0),
+            record.weight.unwrap_or(0),
+            record.port.unwrap_or(0),
+            absolute(value),
+        ),
+        DnsRecordType::TXT => quote_txt(value),
+        DnsRecordType::CAA => {
+            let caa = CaaValue::parse(value)?;
+            format!("{} {} {}", caa.flags, caa.tag, quote(caa.value.as_bytes()))
+        }
+        DnsRecordType::SOA => value.to_string(),
+    })
+}
+
+/// Record targets are served as absolute names, so they are written fully qualified
+fn absolute(name: &str) -> String {
+    if name.ends_with('.') {
+        name.to_string()
+    } else {
+        format!("{}.", name)
+    }
+}
+
+/// TXT data as one or more quoted character-strings of at most 255 bytes each
+fn quote_txt(text: &str) -> String {
+    if text.is_empty() {
+        return "\"\"".to_string();
+    }
+    text.as_bytes()
+        .chunks(255)
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes a character-string, escaping `"` and `\` and writing non-printable bytes as `\DDD`
+fn quote(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            0x20..=0x7e => out.push(byte as char),
+            _ => out.push_str(&format!("\\{:03}", byte)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parses a master file for `zone_name` into records ready to store, with owners relative to the
+/// zone ("@" for the apex) and targets fully qualified. Supports `$ORIGIN`, `$TTL`, blank owners,
+/// parentheses and comments. SOA records are skipped since the zone's own fields provide the SOA;
+/// `$INCLUDE` and `$GENERATE` are rejected. Errors name the offending line.
+pub fn parse_zone(text: &str, zone_name: &str) -> Result<Vec<record_types::DnsRecord>> {
+    let zone_name = message::normalize_name(zone_name);
+    let mut origin = zone_name.clone();
+    let mut default_ttl = None;
+    let mut last_ttl = None;
+    let mut last_owner: Option<String> = None;
+    let mut records = Vec::new();
+
+    for entry in entries(text)? {
+        let line = entry.line;
+        let mut tokens = entry.tokens.into_iter().peekable();
+
+        if !entry.blank_owner {
+            if let Some(directive) = tokens.peek().filter(|t| !t.quoted && t.text.starts_with('$')) {
+                let directive = directive.text.to_ascii_uppercase();
+                tokens.next();
+                let argument = tokens
+                    .next()
+                    .ok_or_else(|| anyhow!("line {}: {} needs an argument", line, directive))?;
+                match directive.as_str() {
+                    "$ORIGIN" => origin = qualify(&argument.text, &origin),
+                    "$TTL" => default_ttl = Some(parse_ttl(&argument.text).map_err(|e| anyhow!("line {}: {}", line, e))?),
+                    _ => bail!("line {}: {} is not supported", line, directive),
+                }
+                continue;
+            }
+        }
+
+        let owner = if entry.blank_owner {
+            last_owner
+                .clone()
+                .ok_or_else(|| anyhow!("line {}: record has no owner name", line))?
+        } else {
+            let token = tokens.next().ok_or_else(|| anyhow!("line {}: missing owner name", line))?;
+            qualify(&token.text, &origin)
+        };
+        last_owner = Some(owner.clone());
+
+        let name = relative_name(&owner, &zone_name)
+            .ok_or_else(|| anyhow!("line {}: {} is outside zone {}", line, owner, zone_name))?;
+        if name != "@" && !is_valid_domain_name(&name) {
+            bail!("line {}: invalid owner name {}", line, owner);
+        }
+
+        // TTL and class may come in either order and are both optional
+        let mut ttl = None;
+        let record_type = loop {
+            let token = tokens
+                .next()
+                .ok_or_else(|| anyhow!("line {}: missing record type", line))?;
+            let upper = token.text.to_ascii_uppercase();
+            if upper == "IN" {
+                continue;
+            }
+            if matches!(upper.as_str(), "CH" | "HS" | "CS") {
+                bail!("line {}: only class IN is supported", line);
+            }
+            if ttl.is_none() && upper.starts_with(|c: char| c.is_ascii_digit()) {
+                ttl = Some(parse_ttl(&upper).map_err(|e| anyhow!("line {}: {}", line, e))?);
+                continue;
+            }
+            break DnsRecordType::from_str(&upper).map_err(|_| anyhow!("line {}: unsupported record type {}", line, token.text))?;
+        };
+
+        if ttl.is_some() {
+            last_ttl = ttl;
+        }
+        let ttl = ttl.or(default_ttl).or(last_ttl).unwrap_or(DEFAULT_IMPORT_TTL);
+        let rdata: Vec<Token> = tokens.collect();
+
+        if record_type == DnsRecordType::SOA {
+            continue;
+        }
+
+        let record = parse_rdata(name, record_type, ttl, &rdata, &origin)
+            .map_err(|e| anyhow!("line {}: {}", line, e))?;
+        record.validate().map_err(|e| anyhow!("line {}: {}", line, e))?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+fn parse_rdata(
+    name: String,
+    record_type: DnsRecordType,
+    ttl: u32,
+    rdata: &[Token],
+    origin: &str,
+) -> Result<record_types::DnsRecord> {
+    let expect = |count: usize| {
+        if rdata.len() == count {
+            Ok(())
+        } else {
+            Err(anyhow!("{} record needs {} data field(s), found {}", record_type.to_string(), count, rdata.len()))
+        }
+    };
+    let number = |token: &Token, field: &str| {
+        token.text.parse::<u16>().map_err(|_| anyhow!("invalid {} {}", field, token.text))
+    };
+    let target = |token: &Token| {
+        if token.text == "." {
+            ".".to_string()
+        } else {
+            qualify(&token.text, origin)
+        }
+    };
+
+    let mut record = record_types::DnsRecord {
+        name,
+        record_type: record_type.clone(),
+        value: String::new(),
+        ttl: Some(ttl),
+        priority: None,
+        weight: None,
+        port: None,
+    };
+
+    match record_type {
+        DnsRecordType::A | DnsRecordType::AAAA => {
+            expect(1)?;
+            record.value = rdata[0].text.clone();
+        }
+        DnsRecordType::CNAME | DnsRecordType::NS | DnsRecordType::PTR | DnsRecordType::ALIAS => {
+            expect(1)?;
+            record.value = target(&rdata[0]);
+        }
+        DnsRecordType::MX => {
+            expect(2)?;
+            record.priority = Some(number(&rdata[0], "preference")?);
+            record.value = target(&rdata[1]);
+        }
+        DnsRecordType::SRV => {
+            expect(4)?;
+            record.priority = Some(number(&rdata[0], "priority")?);
+            record.weight = Some(number(&rdata[1], "weight")?);
+            record.port = Some(number(&rdata[2], "port")?);
+            record.value = target(&rdata[3]);
+        }
+        DnsRecordType::TXT => {
+            if rdata.is_empty() {
+                bail!("TXT record needs at least one string");
+            }
+            // Character-strings are stored joined; export splits them again at 255 bytes
+            record.value = rdata.iter().map(|token| unescape(&token.text)).collect::<Result<String>>()?;
+        }
+        DnsRecordType::CAA => {
+            expect(3)?;
+            record.value = format!("{} {} \"{}\"", rdata[0].text, rdata[1].text, unescape(&rdata[2].text)?);
+        }
+        DnsRecordType::SOA => unreachable!("SOA records are skipped before their data is parsed"),
+    }
+
+    Ok(record)
+}
+
+struct Token {
+    text: String,
+    quoted: bool,
+}
+
+/// One record or directive, joined across lines inside parentheses
+struct Entry {
+    line: usize,
+    blank_owner: bool,
+    tokens: Vec<Token>,
+}
+
+fn entries(text: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut current: Option<Entry> = None;
+    let mut depth = 0usize;
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        let entry = current.get_or_insert_with(|| Entry {
+            line,
+            blank_owner: raw.starts_with([' ', '\t']),
+            tokens: Vec::new(),
+        });
+
+        let mut chars = raw.chars();
+        let mut token = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                ';' => break,
+                '"' => {
+                    finish(&mut token, &mut entry.tokens);
+                    let mut quoted = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => {
+                                quoted.push('\\');
+                                quoted.extend(chars.next());
+                            }
+                            Some(c) => quoted.push(c),
+                            None => bail!("line {}: unterminated quoted string", line),
+                        }
+                    }
+                    entry.tokens.push(Token { text: quoted, quoted: true });
+                }
+                '(' => {
+                    finish(&mut token, &mut entry.tokens);
+                    depth += 1;
+                }
+                ')' => {
+                    finish(&mut token, &mut entry.tokens);
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| anyhow!("line {}: unbalanced parentheses", line))?;
+                }
+                '\\' => {
+                    token.push(c);
+                    token.extend(chars.next());
+                }
+                c if c.is_whitespace() => finish(&mut token, &mut entry.tokens),
+                c => token.push(c),
+            }
+        }
+        finish(&mut token, &mut entry.tokens);
+
+        if depth == 0 {
+            if let Some(entry) = current.take().filter(|entry| !entry.tokens.is_empty()) {
+                entries.push(entry);
+            }
+        }
+    }
+
+    if let Some(entry) = current.filter(|_| depth > 0) {
+        bail!("line {}: unbalanced parentheses", entry.line);
+    }
+    Ok(entries)
+}
+
+fn finish(token: &mut String, tokens: &mut Vec<Token>) {
+    if !token.is_empty() {
+        tokens.push(Token { text: std::mem::take(token), quoted: false });
+    }
+}
+
+/// Absolute, lowercased name without the trailing dot; relative names are appended to `origin`
+fn qualify(name: &str, origin: &str) -> String {
+    if name == "@" {
+        return origin.to_string();
+    }
+    match name.strip_suffix('.') {
+        Some(absolute) => absolute.to_ascii_lowercase(),
+        None => format!("{}.{}", name.to_ascii_lowercase(), origin),
+    }
+}
+
+/// TTL in seconds, or with BIND units such as `1h30m` or `1w`
+fn parse_ttl(text: &str) -> Result<u32> {
+    let invalid = || anyhow!("invalid TTL {}", text);
+    let mut total: u64 = 0;
+    let mut number: Option<u64> = None;
+
+    for c in text.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            number = Some(number.unwrap_or(0) * 10 + u64::from(digit));
+            if number > Some(u64::from(u32::MAX)) {
+                return Err(invalid());
+            }
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return Err(invalid()),
+        };
+        total += number.take().ok_or_else(invalid)? * unit;
+    }
+    total += number.unwrap_or(0);
+
+    if text.is_empty() {
+        return Err(invalid());
+    }
+    u32::try_from(total).map_err(|_| invalid())
+}
+
+/// Resolves `\X` and `\DDD` escapes in a character-string
+fn unescape(text: &str) -> Result<String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let digits = bytes.get(i + 1..i + 4).filter(|d| d.iter().all(u8::is_ascii_digit));
+        match digits {
+            Some(digits) => {
+                let value: u16 = std::str::from_utf8(digits)?.parse()?;
+                out.push(u8::try_from(value).map_err(|_| anyhow!("invalid escape \\{}", value))?);
+                i += 4;
+            }
+            None => {
+                let escaped = bytes.get(i + 1).ok_or_else(|| anyhow!("dangling backslash in {}", text))?;
+                out.push(*escaped);
+                i += 2;
+            }
+        }
+    }
+
+    String::from_utf8(out).map_err(|_| anyhow!("character-string is not valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::DEFAULT_VIEW;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_render_zone_matches_fixture() {
+        let now = Utc::now();
+        let zone = DnsZone {
+            id: Uuid::new_v4(),
+            name: "example.com".to_string(),
+            zone_type: "master".to_string(),
+            serial_number: 2024010101,
+            refresh_interval: 3600,
+            retry_interval: 900,
+            expire_interval: 604800,
+            minimum_ttl: 300,
+            primary_ns: Some("ns1.example.com".to_string()),
+            admin_email: Some("admin@example.com".to_string()),
+            dnssec_enabled: false,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let record = |name: &str, record_type: &str, value: &str, priority: Option<i32>, weight: Option<i32>, port: Option<i32>| DnsRecord {
+            id: Uuid::new_v4(),
+            zone_id: zone.id,
+            name: name.to_string(),
+            record_type: record_type.to_string(),
+            value: value.to_string(),
+            ttl: 3600,
+            priority,
+            weight,
+            port,
+            is_dynamic: false,
+            view: DEFAULT_VIEW.to_string(),
+            created_at: now,
+            updated_at: now,
+        };
+
+        let records = vec![
+            record("www", "CNAME", "host.example.com", None, None, None),
+            record("host", "A", "192.0.2.10", None, None, None),
+            record("host.example.com", "AAAA", "2001:db8::10", None, None, None),
+            record("@", "NS", "ns1.example.com", None, None, None),
+            record("@", "MX", "mail.example.com.", Some(10), None, None),
+            record("@", "TXT", "v=spf1 mx -all", None, None, None),
+            record("quoted", "TXT", "say \"hi\" \\ bye", None, None, None),
+            record("_sip._tcp", "SRV", "sip.example.com", Some(10), Some(5), Some(5060)),
+            record("@", "CAA", "0 issue \"letsencrypt.org\"", None, None, None),
+            record("ns1", "A", "192.0.2.53", None, None, None),
+        ];
+
+        let rendered = render_zone(&zone, &records).unwrap();
+        assert_eq!(rendered, include_str!("../../tests/fixtures/example.com.zone"));
+    }
+
+    #[test]
+    fn test_long_txt_is_split_into_character_strings() {
+        let text = "a".repeat(300);
+        let quoted = quote_txt(&text);
+        assert_eq!(quoted, format!("\"{}\" \"{}\"", "a".repeat(255), "a".repeat(45)));
+        assert_eq!(quote_txt(""), "\"\"");
+        assert_eq!(quote(b"tab\there"), "\"tab\\009here\"");
+    }
+
+    #[test]
+    fn test_parse_zone_round_trips_export() {
+        let fixture = include_str!("../../tests/fixtures/example.com.zone");
+        let records = parse_zone(fixture, "example.com").unwrap();
+
+        assert_eq!(records.len(), 10);
+        let www = records.iter().find(|r| r.name == "www").unwrap();
+        assert_eq!(www.record_type, DnsRecordType::CNAME);
+        assert_eq!(www.value, "host.example.com");
+        assert_eq!(www.ttl, Some(3600));
+
+        let srv = records.iter().find(|r| r.record_type == DnsRecordType::SRV).unwrap();
+        assert_eq!(srv.name, "_sip._tcp");
+        assert_eq!((srv.priority, srv.weight, srv.port), (Some(10), Some(5), Some(5060)));
+
+        let quoted = records.iter().find(|r| r.name == "quoted").unwrap();
+        assert_eq!(quoted.value, "say \"hi\" \\ bye");
+        let caa = records.iter().find(|r| r.record_type == DnsRecordType::CAA).unwrap();
+        assert_eq!(caa.value, "0 issue \"letsencrypt.org\"");
+    }
+
+    #[test]
+    fn test_parse_zone_handles_origin_ttl_and_continuations() {
+        let text = "\
+$TTL 1h
+@ IN SOA ns1 admin (
+        1 ; serial
+        3600 900 604800 300 )
+        IN NS ns1            ; blank owner repeats @
+mail 300 IN A 192.0.2.25
+     IN AAAA 2001:db8::25
+$ORIGIN sub.example.com.
+host IN 2d MX 10 mail.example.com.
+txt TXT \"part one \" \"part\\032two\"
+";
+        let records = parse_zone(text, "example.com.").unwrap();
+        let summary: Vec<(&str, String, &str, Option<u32>)> = records
+            .iter()
+            .map(|r| (r.name.as_str(), r.record_type.to_string(), r.value.as_str(), r.ttl))
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("@", "NS".to_string(), "ns1.example.com", Some(3600)),
+                ("mail", "A".to_string(), "192.0.2.25", Some(300)),
+                ("mail", "AAAA".to_string(), "2001:db8::25", Some(3600)),
+                ("host.sub", "MX".to_string(), "mail.example.com", Some(172800)),
+                ("txt.sub", "TXT".to_string(), "part one part two", Some(3600)),
+            ]
+        );
+        assert_eq!(records[3].priority, Some(10));
+    }
+
+    #[test]
+    fn test_parse_zone_rejects_bad_input_with_line_numbers() {
+        let error = |text: &str| parse_zone(text, "example.com").unwrap_err().to_string();
+
+        assert!(error("www IN A 192.0.2.1\nbad IN A not-an-ip\n").starts_with("line 2:"));
+        assert!(error("other.org. IN A 192.0.2.1\n").contains("outside zone"));
+        assert!(error("www IN HINFO cpu os\n").contains("unsupported record type"));
+        assert!(error("$INCLUDE other.zone\n").contains("not supported"));
+        assert!(error("www IN MX mail\n").contains("needs 2"));
+        assert!(error("www IN TXT \"open\n").contains("unterminated"));
+        assert!(error("@ IN SOA ns1 admin ( 1 2 3 4 5\n").contains("unbalanced"));
+        assert!(error("  IN A 192.0.2.1\n").contains("no owner"));
+    }
+
+    #[test]
+    fn test_parse_ttl_units() {
+        assert_eq!(parse_ttl("300").unwrap(), 300);
+        assert_eq!(parse_ttl("1h30m").unwrap(), 5400);
+        assert_eq!(parse_ttl("1W").unwrap(), 604800);
+        assert!(parse_ttl("h").is_err());
+        assert!(parse_ttl("99999999999").is_err());
+    }
+}
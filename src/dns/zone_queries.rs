@@ -9,7 +9,7 @@ pub async fn fetch_all_zones(db: &PgPool) -> Result<Vec<DnsZone>> {
         r#"
         SELECT id, name, zone_type, primary_ns, admin_email, serial_number,
                refresh_interval, retry_interval, expire_interval, minimum_ttl,
-               created_at, updated_at
+               dnssec_enabled, created_at, updated_at
         FROM dns_zones
         WHERE zone_type IN ('master', 'forward')
         "#
@@ -17,33 +17,69 @@ pub async fn fetch_all_zones(db: &PgPool) -> Result<Vec<DnsZone>> {
     .fetch_all(db)
     .await?;
 
-    let mut zones = Vec::new();
-    for row in rows {
-        let zone = DnsZone {
-            id: row.get("id"),
-            name: row.get("name"),
-            zone_type: row.get("zone_type"),
-            primary_ns: row.get("primary_ns"),
-            admin_email: row.get("admin_email"),
-            serial_number: row.get("serial_number"),
-            refresh_interval: row.get("refresh_interval"),
-            retry_interval: row.get("retry_interval"),
-            expire_interval: row.get("expire_interval"),
-            minimum_ttl: row.get("minimum_ttl"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        };
-        zones.push(zone);
+    Ok(rows.iter().map(zone_from_row).collect())
+}
+
+pub async fn fetch_zone(db: &PgPool, zone_id: Uuid) -> Result<Option<DnsZone>> {
+    let row = sqlx::query(
+        r#"
+        SELECT id, name, zone_type, primary_ns, admin_email, serial_number,
+               refresh_interval, retry_interval, expire_interval, minimum_ttl,
+               dnssec_enabled, created_at, updated_at
+        FROM dns_zones
+        WHERE id = $1
+        "#
+    )
+    .bind(zone_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.as_ref().map(zone_from_row))
+}
+
+pub fn zone_from_row(row: &sqlx::postgres::PgRow) -> DnsZone {
+    DnsZone {
+        id: row.get("id"),
+        name: row.get("name"),
+        zone_type: row.get("zone_type"),
+        primary_ns: row.get("primary_ns"),
+        admin_email: row.get("admin_email"),
+        serial_number: row.get("serial_number"),
+        refresh_interval: row.get("refresh_interval"),
+        retry_interval: row.get("retry_interval"),
+        expire_interval: row.get("expire_interval"),
+        minimum_ttl: row.get("minimum_ttl"),
+        dnssec_enabled: row.get("dnssec_enabled"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
     }
+}
+
+/// Creates a master zone (or returns the id of the existing one with that name)
+pub async fn insert_zone(db: &PgPool, name: &str, primary_ns: &str, admin_email: &str) -> Result<Uuid> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dns_zones (name, zone_type, serial_number, primary_ns, admin_email)
+        VALUES ($1, 'master', $2, $3, $4)
+        ON CONFLICT (name) DO UPDATE SET updated_at = NOW()
+        RETURNING id
+        "#
+    )
+    .bind(name)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(primary_ns)
+    .bind(admin_email)
+    .fetch_one(db)
+    .await?;
 
-    Ok(zones)
+    Ok(row.get("id"))
 }
 
 pub async fn fetch_zone_records(db: &PgPool, zone_id: Uuid) -> Result<Vec<DnsRecord>> {
     let rows = sqlx::query(
         r#"
         SELECT id, zone_id, name, record_type, value, ttl, priority, weight, port,
-               is_dynamic, created_at, updated_at
+               is_dynamic, view, created_at, updated_at
         FROM dns_records
         WHERE zone_id = $1
         "#
@@ -52,28 +88,44 @@ pub async fn fetch_zone_records(db: &PgPool, zone_id: Uuid) -> Result<Vec<DnsRec
     .fetch_all(db)
     .await?;
 
-    let mut records = Vec::new();
-    for row in rows {
-        let record = DnsRecord {
-            id: row.get("id"),
-            zone_id: row.get("zone_id"),
-            name: row.get("name"),
-            record_type: row.get("record_type"),
-            value: row.get("value"),
-            ttl: row.get("ttl"),
-            priority: row.get("priority"),
-            weight: row.get("weight"),
-            port: row.get("port"),
-            is_dynamic: row.get("is_dynamic"),
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        };
-        records.push(record);
-    }
+    Ok(rows.iter().map(record_from_row).collect())
+}
 
-    Ok(records)
+pub async fn fetch_record(db: &PgPool, record_id: Uuid) -> Result<Option<DnsRecord>> {
+    let row = sqlx::query(
+        r#"
+        SELECT id, zone_id, name, record_type, value, ttl, priority, weight, port,
+               is_dynamic, view, created_at, updated_at
+        FROM dns_records
+        WHERE id = $1
+        "#
+    )
+    .bind(record_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.as_ref().map(record_from_row))
 }
 
+pub fn record_from_row(row: &sqlx::postgres::PgRow) -> DnsRecord {
+    DnsRecord {
+        id: row.get("id"),
+        zone_id: row.get("zone_id"),
+        name: row.get("name"),
+        record_type: row.get("record_type"),
+        value: row.get("value"),
+        ttl: row.get("ttl"),
+        priority: row.get("priority"),
+        weight: row.get("weight"),
+        port: row.get("port"),
+        is_dynamic: row.get("is_dynamic"),
+        view: row.get("view"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn insert_dns_record(
     db: &PgPool,
     zone_id: Uuid,
@@ -82,11 +134,12 @@ pub async fn insert_dns_record(
     value: &str,
     ttl: Option<i32>,
     priority: Option<i32>,
+    dhcid: Option<&str>,
 ) -> Result<DnsRecord> {
     let row = sqlx::query(
         r#"
-        INSERT INTO dns_records (zone_id, name, record_type, value, ttl, priority, is_dynamic)
-        VALUES ($1, $2, $3, $4, $5, $6, true)
+        INSERT INTO dns_records (zone_id, name, record_type, value, ttl, priority, is_dynamic, dhcid)
+        VALUES ($1, $2, $3, $4, $5, $6, true, $7)
         RETURNING *
         "#
     )
@@ -96,23 +149,35 @@ pub async fn insert_dns_record(
     .bind(value)
     .bind(ttl)
     .bind(priority)
+    .bind(dhcid)
     .fetch_one(db)
     .await?;
 
-    Ok(DnsRecord {
-        id: row.get("id"),
-        zone_id: row.get("zone_id"),
-        name: row.get("name"),
-        record_type: row.get("record_type"),
-        value: row.get("value"),
-        ttl: row.get("ttl"),
-        priority: row.get("priority"),
-        weight: row.get("weight"),
-        port: row.get("port"),
-        is_dynamic: row.get("is_dynamic"),
-        created_at: row.get("created_at"),
-        updated_at: row.get("updated_at"),
-    })
+    Ok(record_from_row(&row))
+}
+
+/// Inserts a fully specified record (as built from an RFC 2136 UPDATE)
+pub async fn insert_record(db: &PgPool, record: &DnsRecord) -> Result<DnsRecord> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dns_records (zone_id, name, record_type, value, ttl, priority, weight, port, is_dynamic)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING *
+        "#
+    )
+    .bind(record.zone_id)
+    .bind(&record.name)
+    .bind(&record.record_type)
+    .bind(&record.value)
+    .bind(record.ttl)
+    .bind(record.priority)
+    .bind(record.weight)
+    .bind(record.port)
+    .bind(record.is_dynamic)
+    .fetch_one(db)
+    .await?;
+
+    Ok(record_from_row(&row))
 }
 
 pub async fn delete_dns_record(db: &PgPool, record_id: Uuid) -> Result<bool> {
@@ -129,6 +194,44 @@ pub async fn delete_dns_record(db: &PgPool, record_id: Uuid) -> Result<bool> {
     Ok(result.rows_affected() > 0)
 }
 
+/// The distinct DHCIDs stored on the given records; records without one are skipped
+pub async fn fetch_dhcids(db: &PgPool, record_ids: &[Uuid]) -> Result<Vec<String>> {
+    let rows = sqlx::query(
+        "SELECT DISTINCT dhcid FROM dns_records WHERE id = ANY($1) AND dhcid IS NOT NULL"
+    )
+    .bind(record_ids)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.iter().map(|row| row.get("dhcid")).collect())
+}
+
+pub async fn set_dhcid(db: &PgPool, record_ids: &[Uuid], dhcid: &str) -> Result<()> {
+    sqlx::query("UPDATE dns_records SET dhcid = $2 WHERE id = ANY($1)")
+        .bind(record_ids)
+        .bind(dhcid)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Moves the zone serial forward to the current timestamp, or by one if that wouldn't increase it
+pub async fn bump_zone_serial<'e, E: sqlx::PgExecutor<'e>>(db: E, zone_id: Uuid) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE dns_zones
+        SET serial_number = GREATEST(serial_number + 1, $1), updated_at = NOW()
+        WHERE id = $2
+        "#
+    )
+    .bind(chrono::Utc::now().timestamp())
+    .bind(zone_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn update_zone_serial(db: &PgPool, zone_id: Uuid, serial: u32) -> Result<()> {
     sqlx::query(
         r#"
@@ -143,4 +246,62 @@ pub async fn update_zone_serial(db: &PgPool, zone_id: Uuid, serial: u32) -> Resu
     .await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+pub struct DnssecKeyRow {
+    pub key_type: String,
+    pub flags: i32,
+    pub private_key: Vec<u8>,
+}
+
+pub async fn fetch_dnssec_keys(db: &PgPool, zone_id: Uuid) -> Result<Vec<DnssecKeyRow>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT key_type, flags, private_key
+        FROM dnssec_keys
+        WHERE zone_id = $1 AND active
+        ORDER BY created_at
+        "#
+    )
+    .bind(zone_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| DnssecKeyRow {
+            key_type: row.get("key_type"),
+            flags: row.get("flags"),
+            private_key: row.get("private_key"),
+        })
+        .collect())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_dnssec_key(
+    db: &PgPool,
+    zone_id: Uuid,
+    key_type: &str,
+    algorithm: u8,
+    flags: u16,
+    key_tag: u16,
+    private_key: &[u8],
+    public_key: &[u8],
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO dnssec_keys (zone_id, key_type, algorithm, flags, key_tag, private_key, public_key)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#
+    )
+    .bind(zone_id)
+    .bind(key_type)
+    .bind(algorithm as i16)
+    .bind(flags as i32)
+    .bind(key_tag as i32)
+    .bind(private_key)
+    .bind(public_key)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+// Duplicate Address Detection (RFC 4862 section 5.4) for addresses handed to SLAAC clients
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::time::Duration;
+use anyhow::Result;
+use bytes::{BufMut, BytesMut};
+use tracing::debug;
+use crate::ipv6::icmpv6::{self, Link};
+
+const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+const OPT_SOURCE_LINK_LAYER_ADDRESS: u8 = 1;
+
+/// ff02::1:ffXX:XXXX, the group a node with `address` listens on for solicitations
+pub fn solicited_node_address(address: Ipv6Addr) -> Ipv6Addr {
+    let octets = address.octets();
+    Ipv6Addr::new(
+        0xff02, 0, 0, 0, 0, 1,
+        0xff00 | octets[13] as u16,
+        u16::from_be_bytes([octets[14], octets[15]]),
+    )
+}
+
+/// Neighbor Solicitation for `target`, with our hardware address so the owner can answer
+/// without resolving us first. The checksum is left for the kernel.
+pub fn neighbor_solicitation(target: Ipv6Addr, mac: Option<[u8; 6]>) -> Vec<u8> {
+    let mut packet = BytesMut::new();
+    packet.put_u8(ICMPV6_NEIGHBOR_SOLICITATION);
+    packet.put_u8(0); // code
+    packet.put_u16(0); // checksum
+    packet.put_u32(0); // reserved
+    packet.put_slice(&target.octets());
+
+    if let Some(mac) = mac {
+        packet.put_u8(OPT_SOURCE_LINK_LAYER_ADDRESS);
+        packet.put_u8(1); // length in units of 8 octets
+        packet.put_slice(&mac);
+    }
+
+    packet.to_vec()
+}
+
+/// Whether `data`, received from `from`, shows `target` is taken: an advertisement for it, or
+/// another node's DAD probe (a solicitation from the unspecified address) for the same target
+fn reveals_conflict(data: &[u8], from: IpAddr, target: Ipv6Addr) -> bool {
+    if data.len() < 24 || data[1] != 0 || data[8..24] != target.octets() {
+        return false;
+    }
+    match data[0] {
+        ICMPV6_NEIGHBOR_ADVERTISEMENT => true,
+        ICMPV6_NEIGHBOR_SOLICITATION => from == IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        _ => false,
+    }
+}
+
+/// Solicits `address` on `interface` and reports whether any node claims it within `timeout`
+pub async fn address_in_use(interface: &str, address: Ipv6Addr, timeout: Duration) -> Result<bool> {
+    let link = Link::by_name(interface)?;
+    let socket = icmpv6::open_socket(&link)?;
+    let group = solicited_node_address(address);
+    // Other nodes probing for the same address send to this group
+    socket.join_multicast_v6(&group, link.index)?;
+
+    let destination = SocketAddr::V6(SocketAddrV6::new(group, 0, 0, link.index));
+    socket.send_to(&neighbor_solicitation(address, link.mac), destination).await?;
+    debug!("Sent DAD probe for {} on {}", address, interface);
+
+    let wait = async {
+        let mut buf = [0u8; 1500];
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await?;
+            if reveals_conflict(&buf[..len], from.ip(), address) {
+                debug!("{} is already in use by {}", address, from.ip());
+                return Ok::<_, anyhow::Error>(true);
+            }
+        }
+    };
+
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(result) => result,
+        Err(_) => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solicited_node_address() {
+        let address: Ipv6Addr = "2001:db8::211:22ff:fe33:4455".parse().unwrap();
+        assert_eq!(solicited_node_address(address), "ff02::1:ff33:4455".parse::<Ipv6Addr>().unwrap());
+    }
+
+    #[test]
+    fn test_advertisements_and_probes_for_the_target_are_conflicts() {
+        let target: Ipv6Addr = "2001:db8::10".parse().unwrap();
+        let other: Ipv6Addr = "2001:db8::11".parse().unwrap();
+        let neighbor = IpAddr::V6("fe80::1".parse().unwrap());
+        let unspecified = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+
+        let solicitation = neighbor_solicitation(target, Some([2, 0, 0, 0, 0, 1]));
+        assert_eq!(solicitation.len(), 32);
+        assert_eq!(&solicitation[24..26], &[OPT_SOURCE_LINK_LAYER_ADDRESS, 1]);
+
+        let mut advertisement = solicitation.clone();
+        advertisement[0] = ICMPV6_NEIGHBOR_ADVERTISEMENT;
+        assert!(reveals_conflict(&advertisement, neighbor, target));
+        assert!(!reveals_conflict(&advertisement, neighbor, other));
+
+        // Address resolution for the target is not a conflict, another node's DAD probe is
+        assert!(!reveals_conflict(&solicitation, neighbor, target));
+        assert!(reveals_conflict(&solicitation, unspecified, target));
+
+        assert!(!reveals_conflict(&advertisement[..20], neighbor, target));
+    }
+}
@@ -1,13 +1,21 @@
 use std::net::{Ipv6Addr, SocketAddrV6};
 use tokio::net::UdpSocket;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 use anyhow::Result;
 use bytes::{Bytes, BytesMut, BufMut};
-use tracing::{info, error, debug};
+use tracing::{info, warn, error, debug};
 use chrono::{DateTime, Utc, Duration};
 use uuid::Uuid;
 use sqlx::PgPool;
 use std::sync::Arc;
-use crate::config::Settings;
+use crate::config::{IPv6Config, Settings};
+use crate::dhcp::packet::{decode_wire_name, encode_wire_name};
+use crate::dns::dhcid::ClientIdentity;
+use crate::dns::dynamic_updates::{DhcpDnsIntegration, DnsUpdateScope};
+use crate::ipv6::dhcpv6_leases;
+use crate::ipv6::prefix_delegation::{DelegatedPrefix, PrefixDelegationManager};
+use crate::ipv6::server_duid;
 
 #[derive(Debug, Clone)]
 pub struct Dhcpv6Packet {
@@ -38,10 +46,119 @@ pub struct Dhcpv6Lease {
     pub state: String,
 }
 
+/// An IA_NA option as sent by a client
+#[derive(Debug, Clone, PartialEq)]
+pub struct IaNa {
+    pub iaid: u32,
+    pub t1: u32,
+    pub t2: u32,
+    /// IAADDR sub-options, i.e. addresses the client would like (or already holds)
+    pub addresses: Vec<IaAddress>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IaAddress {
+    pub address: Ipv6Addr,
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+}
+
+/// An IA_PD option as sent by a client
+#[derive(Debug, Clone, PartialEq)]
+pub struct IaPd {
+    pub iaid: u32,
+    pub t1: u32,
+    pub t2: u32,
+    /// IAPREFIX sub-options: prefixes held by the client, or just a length hint
+    pub prefixes: Vec<IaPrefix>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IaPrefix {
+    pub preferred_lifetime: u32,
+    pub valid_lifetime: u32,
+    pub prefix_length: u8,
+    pub prefix: Ipv6Addr,
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn read_ipv6(data: &[u8], at: usize) -> Ipv6Addr {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&data[at..at + 16]);
+    Ipv6Addr::from(octets)
+}
+
+/// IAID, T1, T2 and the (code, body) sub-options of an IA option
+type IaParts<'a> = (u32, u32, u32, Vec<(u16, &'a [u8])>);
+
+/// Splits an IA option body into IAID, T1, T2 and its (code, body) sub-options.
+/// Stops at the first truncated sub-option.
+fn parse_ia(data: &[u8]) -> Option<IaParts<'_>> {
+    if data.len() < 12 {
+        return None;
+    }
+
+    let mut sub_options = Vec::new();
+    let mut offset = 12;
+    while offset + 4 <= data.len() {
+        let code = u16::from_be_bytes([data[offset], data[offset + 1]]);
+        let len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        let body_start = offset + 4;
+        if body_start + len > data.len() {
+            break;
+        }
+
+        sub_options.push((code, &data[body_start..body_start + len]));
+        offset = body_start + len;
+    }
+
+    Some((read_u32(data, 0), read_u32(data, 4), read_u32(data, 8), sub_options))
+}
+
+/// Walks an IA_NA option body: IAID, T1, T2, then sub-options. Malformed IAADDRs are skipped.
+pub fn parse_ia_na(data: &[u8]) -> Option<IaNa> {
+    let (iaid, t1, t2, sub_options) = parse_ia(data)?;
+
+    let addresses = sub_options.into_iter()
+        .filter(|(code, body)| *code == OPT_IAADDR && body.len() >= 24)
+        .map(|(_, body)| IaAddress {
+            address: read_ipv6(body, 0),
+            preferred_lifetime: read_u32(body, 16),
+            valid_lifetime: read_u32(body, 20),
+        })
+        .collect();
+
+    Some(IaNa { iaid, t1, t2, addresses })
+}
+
+/// Walks an IA_PD option body: IAID, T1, T2, then sub-options. Malformed IAPREFIXes are skipped.
+pub fn parse_ia_pd(data: &[u8]) -> Option<IaPd> {
+    let (iaid, t1, t2, sub_options) = parse_ia(data)?;
+
+    let prefixes = sub_options.into_iter()
+        .filter(|(code, body)| *code == OPT_IAPREFIX && body.len() >= 25)
+        .map(|(_, body)| IaPrefix {
+            preferred_lifetime: read_u32(body, 0),
+            valid_lifetime: read_u32(body, 4),
+            prefix_length: body[8],
+            prefix: read_ipv6(body, 9),
+        })
+        .collect();
+
+    Some(IaPd { iaid, t1, t2, prefixes })
+}
+
 pub struct Dhcpv6Server {
     socket: Arc<UdpSocket>,
     db: PgPool,
     settings: Arc<Settings>,
+    prefix_delegation: Arc<PrefixDelegationManager>,
+    dns_updates: Option<Arc<DhcpDnsIntegration>>,
+    /// Sent as OPTION_SERVERID; the same across restarts
+    server_duid: Arc<Vec<u8>>,
 }
 
 const DHCPV6_SOLICIT: u8 = 1;
@@ -80,9 +197,28 @@ const OPT_DNS_SERVERS: u16 = 23;
 const OPT_DOMAIN_LIST: u16 = 24;
 const OPT_IA_PD: u16 = 25;    // Prefix Delegation
 const OPT_IAPREFIX: u16 = 26; // IA Prefix
+const OPT_CLIENT_FQDN: u16 = 39;
+
+// Client FQDN flags (RFC 4704 section 4.1). There is no E flag as in option 81, so N is 0x04.
+const FQDN_FLAG_S: u8 = 0x01;
+const FQDN_FLAG_N: u8 = 0x04;
+
+// DHCPv6 Status Codes
+const STATUS_SUCCESS: u16 = 0;
+const STATUS_NO_ADDRS_AVAIL: u16 = 2;
+const STATUS_NO_BINDING: u16 = 3;
+const STATUS_NO_PREFIX_AVAIL: u16 = 6;
+
+/// Attempts at committing a fresh address before giving up on a busy pool
+const COMMIT_ATTEMPTS: usize = 3;
 
 impl Dhcpv6Server {
-    pub async fn new(settings: Arc<Settings>, db: PgPool) -> Result<Self> {
+    pub async fn new(
+        settings: Arc<Settings>,
+        db: PgPool,
+        prefix_delegation: Arc<PrefixDelegationManager>,
+        dns_updates: Option<Arc<DhcpDnsIntegration>>,
+    ) -> Result<Self> {
         let addr = SocketAddrV6::new(
             Ipv6Addr::UNSPECIFIED,
             547,  // DHCPv6 server port
@@ -90,6 +226,7 @@ impl Dhcpv6Server {
             0,
         );
         
+        let server_duid = server_duid::load_or_create(&db).await?;
         let socket = UdpSocket::bind(addr).await?;
         info!("DHCPv6 server listening on {}", addr);
         
@@ -97,27 +234,46 @@ impl Dhcpv6Server {
             socket: Arc::new(socket),
             db,
             settings,
+            prefix_delegation,
+            dns_updates,
+            server_duid: Arc::new(server_duid),
         })
     }
     
-    pub async fn run(&self) -> Result<()> {
+    /// Serves packets until `shutdown` is cancelled, then waits for the ones still being handled
+    pub async fn run(&self, shutdown: CancellationToken) -> Result<()> {
         let mut buf = vec![0u8; 1500];
-        
+        let mut in_flight = JoinSet::new();
+
         loop {
-            match self.socket.recv_from(&mut buf).await {
+            let received = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                received = self.socket.recv_from(&mut buf) => received,
+            };
+
+            // Forget handlers that already finished
+            while in_flight.try_join_next().is_some() {}
+
+            match received {
                 Ok((len, src)) => {
                     let packet_data = buf[..len].to_vec();
                     let socket = Arc::clone(&self.socket);
                     let db = self.db.clone();
                     let settings = Arc::clone(&self.settings);
-                    
-                    tokio::spawn(async move {
+                    let prefix_delegation = Arc::clone(&self.prefix_delegation);
+                    let dns_updates = self.dns_updates.clone();
+                    let server_duid = Arc::clone(&self.server_duid);
+
+                    in_flight.spawn(async move {
                         if let Err(e) = Self::handle_packet(
                             packet_data,
                             src,
                             socket,
                             db,
                             settings,
+                            prefix_delegation,
+                            dns_updates,
+                            server_duid,
                         ).await {
                             error!("Error handling DHCPv6 packet: {}", e);
                         }
@@ -128,28 +284,42 @@ impl Dhcpv6Server {
                 }
             }
         }
+
+        while in_flight.join_next().await.is_some() {}
+        info!("DHCPv6 server stopped");
+        Ok(())
     }
-    
+
+    #[allow(clippy::too_many_arguments)]
     async fn handle_packet(
         data: Vec<u8>,
         src: std::net::SocketAddr,
         socket: Arc<UdpSocket>,
         db: PgPool,
         settings: Arc<Settings>,
+        prefix_delegation: Arc<PrefixDelegationManager>,
+        dns_updates: Option<Arc<DhcpDnsIntegration>>,
+        server_duid: Arc<Vec<u8>>,
     ) -> Result<()> {
         let packet = Self::parse_packet(&data)?;
         debug!("Received DHCPv6 {} from {}", packet.msg_type, src);
         
         let response = match packet.msg_type {
-            DHCPV6_SOLICIT => Self::handle_solicit(packet, db, settings).await?,
+            DHCPV6_SOLICIT => {
+                Self::handle_solicit(packet, db, settings, &prefix_delegation, dns_updates.as_deref(), &server_duid).await?
+            }
+            // REQUEST and RENEW name the server they're for (RFC 8415 sections 16.4 and 16.6)
+            DHCPV6_REQUEST | DHCPV6_RENEW if !addressed_to(&packet, &server_duid) => {
+                debug!("DHCPv6 {} from {} is for another server, ignoring", packet.msg_type, src);
+                return Ok(());
+            }
             DHCPV6_REQUEST | DHCPV6_CONFIRM | DHCPV6_RENEW | DHCPV6_REBIND => {
-                Self::handle_request(packet, db, settings).await?
+                Self::handle_request(packet, db, settings, &prefix_delegation, dns_updates.as_deref(), &server_duid).await?
             }
             DHCPV6_RELEASE => {
-                Self::handle_release(packet, db).await?;
-                return Ok(());
+                Self::handle_release(packet, db, &prefix_delegation, dns_updates.as_deref(), &server_duid).await?
             }
-            DHCPV6_INFO_REQUEST => Self::handle_info_request(packet, settings).await?,
+            DHCPV6_INFO_REQUEST => Self::handle_info_request(packet, settings, &server_duid).await?,
             _ => {
                 debug!("Unhandled DHCPv6 message type: {}", packet.msg_type);
                 return Ok(());
@@ -221,14 +391,19 @@ impl Dhcpv6Server {
         packet: Dhcpv6Packet,
         db: PgPool,
         settings: Arc<Settings>,
+        prefix_delegation: &PrefixDelegationManager,
+        dns_updates: Option<&DhcpDnsIntegration>,
+        server_duid: &[u8],
     ) -> Result<Option<Dhcpv6Packet>> {
         // Extract client DUID
-        let client_duid = packet.options.iter()
+        let Some(client_duid) = packet.options.iter()
             .find(|opt| opt.code == OPT_CLIENTID)
-            .map(|opt| opt.data.clone());
-            
-        if client_duid.is_none() {
+            .map(|opt| opt.data.clone()) else {
             return Ok(None);
+        };
+
+        if wants_rapid_commit(&packet) && dhcpv6_leases::fetch_pool(&db).await?.is_some_and(|pool| pool.rapid_commit) {
+            return Self::rapid_commit(packet, db, settings, prefix_delegation, dns_updates, server_duid).await;
         }
         
         // Build ADVERTISE response
@@ -239,30 +414,42 @@ impl Dhcpv6Server {
         };
         
         // Add server DUID
-        let server_duid = Self::generate_server_duid();
         response.options.push(Dhcpv6Option {
             code: OPT_SERVERID,
-            data: server_duid,
+            data: server_duid.to_vec(),
         });
         
         // Echo client DUID
         response.options.push(Dhcpv6Option {
             code: OPT_CLIENTID,
-            data: client_duid.unwrap(),
+            data: client_duid.clone(),
         });
         
-        // Add IA_NA with offered address
-        // This is simplified - full implementation would check database for available addresses
-        let ia_na = Self::build_ia_na_option(
-            1,  // IAID
-            Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x1),
-            3600,  // preferred lifetime
-            7200,  // valid lifetime
-        );
-        response.options.push(ia_na);
+        // Add IA_NA with the client's current address, or the next free one (not committed until REQUEST)
+        if let Some(ia) = Self::client_ia_na(&packet) {
+            let iaid = ia.iaid;
+            let offer = match dhcpv6_leases::find_active_lease(&db, &client_duid, iaid).await? {
+                Some(lease) => Some((lease.ipv6_address, lease.preferred_lifetime, lease.valid_lifetime)),
+                None => match dhcpv6_leases::fetch_pool(&db).await? {
+                    Some(pool) => Self::candidate_address(&db, &pool, &ia).await?
+                        .map(|addr| (addr, pool.preferred_lifetime, pool.valid_lifetime)),
+                    None => None,
+                },
+            };
+
+            response.options.push(match offer {
+                Some((addr, preferred, valid)) => Self::build_ia_na_option(iaid, addr, preferred, valid),
+                None => Self::build_ia_na_status(iaid, STATUS_NO_ADDRS_AVAIL, "No addresses available"),
+            });
+        }
+        
+        // Add IA_PD with a delegated prefix for requesting routers
+        if let Some(ia_pd) = Self::client_ia_pd(&packet) {
+            response.options.push(Self::delegate_prefix(prefix_delegation, &client_duid, &ia_pd).await);
+        }
         
         // Add DNS servers
-        if let Some(dns_servers) = Self::get_dns_servers(&settings) {
+        if let Some(dns_servers) = Self::get_dns_servers(&settings.ipv6) {
             response.options.push(Dhcpv6Option {
                 code: OPT_DNS_SERVERS,
                 data: dns_servers,
@@ -272,10 +459,34 @@ impl Dhcpv6Server {
         Ok(Some(response))
     }
     
+    /// Two-message exchange (RFC 8415 section 18.3.1): the SOLICIT is committed like a REQUEST
+    /// and answered with a REPLY carrying Rapid Commit
+    async fn rapid_commit(
+        mut packet: Dhcpv6Packet,
+        db: PgPool,
+        settings: Arc<Settings>,
+        prefix_delegation: &PrefixDelegationManager,
+        dns_updates: Option<&DhcpDnsIntegration>,
+        server_duid: &[u8],
+    ) -> Result<Option<Dhcpv6Packet>> {
+        packet.msg_type = DHCPV6_REQUEST;
+        let mut reply = Self::handle_request(packet, db, settings, prefix_delegation, dns_updates, server_duid).await?;
+        if let Some(reply) = &mut reply {
+            reply.options.push(Dhcpv6Option {
+                code: OPT_RAPID_COMMIT,
+                data: Vec::new(),
+            });
+        }
+        Ok(reply)
+    }
+
     async fn handle_request(
         packet: Dhcpv6Packet,
         db: PgPool,
         settings: Arc<Settings>,
+        prefix_delegation: &PrefixDelegationManager,
+        dns_updates: Option<&DhcpDnsIntegration>,
+        server_duid: &[u8],
     ) -> Result<Option<Dhcpv6Packet>> {
         // Similar to handle_solicit but commits the lease
         let mut response = Dhcpv6Packet {
@@ -285,18 +496,53 @@ impl Dhcpv6Server {
         };
         
         // Add server and client DUIDs
-        let server_duid = Self::generate_server_duid();
         response.options.push(Dhcpv6Option {
             code: OPT_SERVERID,
-            data: server_duid,
+            data: server_duid.to_vec(),
         });
         
-        if let Some(client_duid) = packet.options.iter()
+        let client_duid = packet.options.iter()
             .find(|opt| opt.code == OPT_CLIENTID)
-            .map(|opt| opt.data.clone()) {
+            .map(|opt| opt.data.clone());
+
+        if let Some(client_duid) = &client_duid {
             response.options.push(Dhcpv6Option {
                 code: OPT_CLIENTID,
-                data: client_duid,
+                data: client_duid.clone(),
+            });
+        }
+
+        // CONFIRM only asks whether the addresses are still on-link, nothing to commit
+        if packet.msg_type != DHCPV6_CONFIRM {
+            if let (Some(client_duid), Some(ia)) = (&client_duid, Self::client_ia_na(&packet)) {
+                let fqdn = client_fqdn(&packet);
+                let hostname = fqdn.as_ref().map(|(_, name)| name.as_str());
+                let (ia_na, lease) = Self::commit_address(&db, packet.msg_type, client_duid, &ia, hostname).await?;
+                response.options.push(ia_na);
+
+                if let (Some(dns), Some(lease)) = (dns_updates, lease) {
+                    if let Some((flags, _)) = &fqdn {
+                        let name = lease.hostname.as_deref().unwrap_or_default();
+                        response.options.push(build_client_fqdn_option(*flags, name));
+                    }
+                    let scope = dns_update_scope(fqdn.as_ref().map(|(flags, _)| *flags));
+                    let client = ClientIdentity::Duid(client_duid);
+                    if let Err(e) = dns.on_v6_lease_created(lease.hostname, lease.ipv6_address, scope, client).await {
+                        warn!("Dynamic DNS update failed for {}: {}", lease.ipv6_address, e);
+                    }
+                }
+            }
+            
+            if let (Some(client_duid), Some(ia_pd)) = (&client_duid, Self::client_ia_pd(&packet)) {
+                response.options.push(Self::delegate_prefix(prefix_delegation, client_duid, &ia_pd).await);
+            }
+        }
+
+        // Add DNS servers
+        if let Some(dns_servers) = Self::get_dns_servers(&settings.ipv6) {
+            response.options.push(Dhcpv6Option {
+                code: OPT_DNS_SERVERS,
+                data: dns_servers,
             });
         }
         
@@ -309,22 +555,123 @@ impl Dhcpv6Server {
         Ok(Some(response))
     }
     
-    async fn handle_release(packet: Dhcpv6Packet, db: PgPool) -> Result<()> {
-        // Extract client DUID and release the lease
-        if let Some(client_duid) = packet.options.iter()
+    /// Commits (REQUEST) or extends (RENEW/REBIND) the client's lease and returns the IA_NA to
+    /// reply with, along with the lease when there is one
+    async fn commit_address(
+        db: &PgPool,
+        msg_type: u8,
+        client_duid: &[u8],
+        ia: &IaNa,
+        hostname: Option<&str>,
+    ) -> Result<(Dhcpv6Option, Option<Dhcpv6Lease>)> {
+        let iaid = ia.iaid;
+        let Some(pool) = dhcpv6_leases::fetch_pool(db).await? else {
+            return Ok((Self::build_ia_na_status(iaid, STATUS_NO_ADDRS_AVAIL, "No addresses available"), None));
+        };
+
+        if let Some(lease) = dhcpv6_leases::find_active_lease(db, client_duid, iaid).await? {
+            let lease = dhcpv6_leases::renew_lease(db, lease.id, &pool, hostname).await?;
+            debug!("Extended DHCPv6 lease {} for IAID {}", lease.ipv6_address, iaid);
+            let ia_na = Self::build_ia_na_option(iaid, lease.ipv6_address, lease.preferred_lifetime, lease.valid_lifetime);
+            return Ok((ia_na, Some(lease)));
+        }
+
+        if msg_type != DHCPV6_REQUEST {
+            return Ok((Self::build_ia_na_status(iaid, STATUS_NO_BINDING, "No binding for this IA"), None));
+        }
+
+        for _ in 0..COMMIT_ATTEMPTS {
+            let Some(addr) = Self::candidate_address(db, &pool, ia).await? else { break };
+            match dhcpv6_leases::commit_lease(db, &pool, client_duid, iaid, addr, hostname).await {
+                Ok(lease) => {
+                    info!("Assigned DHCPv6 address {} to IAID {}", lease.ipv6_address, iaid);
+                    let ia_na = Self::build_ia_na_option(iaid, lease.ipv6_address, lease.preferred_lifetime, lease.valid_lifetime);
+                    return Ok((ia_na, Some(lease)));
+                }
+                Err(e) if dhcpv6_leases::is_address_conflict(&e) => {
+                    debug!("DHCPv6 address {} taken concurrently, retrying", addr);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok((Self::build_ia_na_status(iaid, STATUS_NO_ADDRS_AVAIL, "No addresses available"), None))
+    }
+    
+    /// Frees the addresses and prefixes the client releases and confirms with a REPLY
+    /// (RFC 8415 section 18.3.7). IAs the server holds no binding for come back with NoBinding.
+    async fn handle_release(
+        packet: Dhcpv6Packet,
+        db: PgPool,
+        prefix_delegation: &PrefixDelegationManager,
+        dns_updates: Option<&DhcpDnsIntegration>,
+        server_duid: &[u8],
+    ) -> Result<Option<Dhcpv6Packet>> {
+        let Some(client_duid) = packet.options.iter()
             .find(|opt| opt.code == OPT_CLIENTID)
-            .map(|opt| &opt.data) {
-            
-            // Update database to release the lease
-            info!("Releasing DHCPv6 lease for client DUID: {:?}", client_duid);
+            .map(|opt| opt.data.clone()) else {
+            return Ok(None);
+        };
+        // A RELEASE meant for another server is none of our business
+        if !addressed_to(&packet, server_duid) {
+            return Ok(None);
         }
-        
-        Ok(())
+
+        let mut response = Dhcpv6Packet {
+            msg_type: DHCPV6_REPLY,
+            transaction_id: packet.transaction_id,
+            options: Vec::new(),
+        };
+        response.options.push(Dhcpv6Option {
+            code: OPT_SERVERID,
+            data: server_duid.to_vec(),
+        });
+        response.options.push(Dhcpv6Option {
+            code: OPT_CLIENTID,
+            data: client_duid.clone(),
+        });
+
+        let ia_nas = packet.options.iter()
+            .filter(|opt| opt.code == OPT_IA_NA)
+            .filter_map(|opt| parse_ia_na(&opt.data));
+        for ia in ia_nas {
+            let mut released = 0;
+            for address in &ia.addresses {
+                let Some(lease) = dhcpv6_leases::release_lease(&db, &client_duid, ia.iaid, address.address).await? else {
+                    continue;
+                };
+                released += 1;
+                info!("Released DHCPv6 address {} for IAID {}", lease.ipv6_address, ia.iaid);
+
+                if let Some(dns) = dns_updates {
+                    let client = ClientIdentity::Duid(&client_duid);
+                    if let Err(e) = dns.on_v6_lease_released(lease.hostname, lease.ipv6_address, client).await {
+                        warn!("Dynamic DNS removal failed for {}: {}", lease.ipv6_address, e);
+                    }
+                }
+            }
+            if released == 0 {
+                response.options.push(Self::build_ia_na_status(ia.iaid, STATUS_NO_BINDING, "No binding for this IA"));
+            }
+        }
+
+        let ia_pds = packet.options.iter()
+            .filter(|opt| opt.code == OPT_IA_PD)
+            .filter_map(|opt| parse_ia_pd(&opt.data));
+        for ia_pd in ia_pds {
+            for prefix in &ia_pd.prefixes {
+                prefix_delegation.release_prefix(&client_duid, ia_pd.iaid, &prefix.prefix).await?;
+            }
+        }
+
+        response.options.push(build_status_option(STATUS_SUCCESS, "Release received"));
+        Ok(Some(response))
     }
     
     async fn handle_info_request(
         packet: Dhcpv6Packet,
         settings: Arc<Settings>,
+        server_duid: &[u8],
     ) -> Result<Option<Dhcpv6Packet>> {
         let mut response = Dhcpv6Packet {
             msg_type: DHCPV6_REPLY,
@@ -333,10 +680,9 @@ impl Dhcpv6Server {
         };
         
         // Add server DUID
-        let server_duid = Self::generate_server_duid();
         response.options.push(Dhcpv6Option {
             code: OPT_SERVERID,
-            data: server_duid,
+            data: server_duid.to_vec(),
         });
         
         // Echo client DUID if present
@@ -350,7 +696,7 @@ impl Dhcpv6Server {
         }
         
         // Add DNS servers and domain list
-        if let Some(dns_servers) = Self::get_dns_servers(&settings) {
+        if let Some(dns_servers) = Self::get_dns_servers(&settings.ipv6) {
             response.options.push(Dhcpv6Option {
                 code: OPT_DNS_SERVERS,
                 data: dns_servers,
@@ -360,23 +706,6 @@ impl Dhcpv6Server {
         Ok(Some(response))
     }
     
-    fn generate_server_duid() -> Vec<u8> {
-        // DUID-LLT (Link-layer address plus time)
-        // Type 1, hardware type 1 (Ethernet), time, MAC address
-        let mut duid = Vec::new();
-        duid.extend_from_slice(&[0, 1]);  // DUID-LLT
-        duid.extend_from_slice(&[0, 1]);  // Hardware type (Ethernet)
-        
-        // Add timestamp (seconds since Jan 1, 2000)
-        let timestamp = Utc::now().timestamp() - 946684800;
-        duid.extend_from_slice(&(timestamp as u32).to_be_bytes());
-        
-        // Add MAC address (simplified - use actual interface MAC)
-        duid.extend_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
-        
-        duid
-    }
-    
     fn build_ia_na_option(
         iaid: u32,
         addr: Ipv6Addr,
@@ -405,15 +734,376 @@ impl Dhcpv6Server {
         }
     }
     
-    fn get_dns_servers(_settings: &Settings) -> Option<Vec<u8>> {
-        // Return IPv6 DNS servers if configured
-        // This is simplified - would read from settings
-        let mut data = Vec::new();
+    /// The client's first well-formed IA_NA
+    fn client_ia_na(packet: &Dhcpv6Packet) -> Option<IaNa> {
+        packet.options.iter()
+            .filter(|opt| opt.code == OPT_IA_NA)
+            .find_map(|opt| parse_ia_na(&opt.data))
+    }
+    
+    /// The client's first well-formed IA_PD
+    fn client_ia_pd(packet: &Dhcpv6Packet) -> Option<IaPd> {
+        packet.options.iter()
+            .filter(|opt| opt.code == OPT_IA_PD)
+            .find_map(|opt| parse_ia_pd(&opt.data))
+    }
+    
+    /// Delegates (or renews) a prefix for the IA_PD and returns the IA_PD to reply with
+    async fn delegate_prefix(
+        prefix_delegation: &PrefixDelegationManager,
+        client_duid: &[u8],
+        ia_pd: &IaPd,
+    ) -> Dhcpv6Option {
+        // Clients may hint at the prefix length and lifetime they want; zero means no preference
+        let hint = ia_pd.prefixes.first();
+        let requested_length = hint.map(|p| p.prefix_length).filter(|len| *len > 0);
+        let lifetime_hint = hint.map(|p| p.valid_lifetime).filter(|lifetime| *lifetime > 0);
         
-        // Example: 2001:4860:4860::8888 (Google DNS)
-        let dns = Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888);
-        data.extend_from_slice(&dns.octets());
+        match prefix_delegation.request_prefix(client_duid.to_vec(), ia_pd.iaid, requested_length, lifetime_hint).await {
+            Ok(delegation) => Self::build_ia_pd_option(ia_pd.iaid, &delegation),
+            Err(e) => {
+                warn!("Prefix delegation failed for IAID {}: {}", ia_pd.iaid, e);
+                Self::build_ia_pd_status(ia_pd.iaid, STATUS_NO_PREFIX_AVAIL, "No prefixes available")
+            }
+        }
+    }
+    
+    /// The address the client asked for if we can give it, otherwise the next free one
+    async fn candidate_address(db: &PgPool, pool: &dhcpv6_leases::Dhcpv6Pool, ia: &IaNa) -> Result<Option<Ipv6Addr>> {
+        for requested in &ia.addresses {
+            if pool.contains(requested.address)
+                && dhcpv6_leases::is_address_free(db, requested.address).await?
+            {
+                return Ok(Some(requested.address));
+            }
+        }
         
-        Some(data)
+        dhcpv6_leases::next_free_address(db, pool).await
     }
-}
\ No newline at end of file
+    
+    fn build_ia_pd_option(iaid: u32, delegation: &DelegatedPrefix) -> Dhcpv6Option {
+        let mut data = BytesMut::new();
+        
+        data.put_u32(iaid);
+        data.put_u32(delegation.preferred_lifetime / 2);
+        data.put_u32(delegation.preferred_lifetime * 3 / 4);
+        
+        // IA Prefix sub-option
+        data.put_u16(OPT_IAPREFIX);
+        data.put_u16(25);  // Option length
+        data.put_u32(delegation.preferred_lifetime);
+        data.put_u32(delegation.valid_lifetime);
+        data.put_u8(delegation.delegated_length);
+        data.put_slice(&delegation.prefix.octets());
+        
+        Dhcpv6Option {
+            code: OPT_IA_PD,
+            data: data.to_vec(),
+        }
+    }
+    
+    /// IA_PD carrying only a status code, for when no prefix can be delegated
+    fn build_ia_pd_status(iaid: u32, status: u16, message: &str) -> Dhcpv6Option {
+        Dhcpv6Option {
+            code: OPT_IA_PD,
+            ..Self::build_ia_na_status(iaid, status, message)
+        }
+    }
+    
+    /// IA_NA carrying only a status code, for when no address can be given
+    fn build_ia_na_status(iaid: u32, status: u16, message: &str) -> Dhcpv6Option {
+        let mut data = BytesMut::new();
+        
+        data.put_u32(iaid);
+        data.put_u32(0);  // T1
+        data.put_u32(0);  // T2
+        
+        data.put_u16(OPT_STATUS_CODE);
+        data.put_u16(2 + message.len() as u16);
+        data.put_u16(status);
+        data.put_slice(message.as_bytes());
+        
+        Dhcpv6Option {
+            code: OPT_IA_NA,
+            data: data.to_vec(),
+        }
+    }
+    
+    /// OPTION_DNS_SERVERS data (RFC 3646): the configured addresses back to back
+    fn get_dns_servers(config: &IPv6Config) -> Option<Vec<u8>> {
+        if config.dns_servers.is_empty() {
+            return None;
+        }
+
+        Some(config.dns_servers.iter().flat_map(|server| server.octets()).collect())
+    }
+}
+
+/// Whether the client is willing to skip ADVERTISE and REQUEST (option 14)
+fn wants_rapid_commit(packet: &Dhcpv6Packet) -> bool {
+    packet.options.iter().any(|opt| opt.code == OPT_RAPID_COMMIT)
+}
+
+/// Whether the packet's Server Identifier (option 2) is this server's DUID. Messages that must
+/// carry one and name another server are discarded.
+fn addressed_to(packet: &Dhcpv6Packet, server_duid: &[u8]) -> bool {
+    packet.options.iter()
+        .find(|opt| opt.code == OPT_SERVERID)
+        .is_some_and(|opt| opt.data == server_duid)
+}
+
+/// OPTION_STATUS_CODE (RFC 8415 section 21.13) for the top level of a message
+fn build_status_option(status: u16, message: &str) -> Dhcpv6Option {
+    let mut data = Vec::with_capacity(2 + message.len());
+    data.extend_from_slice(&status.to_be_bytes());
+    data.extend_from_slice(message.as_bytes());
+    Dhcpv6Option {
+        code: OPT_STATUS_CODE,
+        data,
+    }
+}
+
+/// Option 39 (RFC 4704): the flags and the name the client wants registered, without a
+/// trailing dot. A bare host name is qualified by the DNS integration.
+fn client_fqdn(packet: &Dhcpv6Packet) -> Option<(u8, String)> {
+    let option = packet.options.iter().find(|opt| opt.code == OPT_CLIENT_FQDN)?;
+    let (&flags, name) = option.data.split_first()?;
+    let name = decode_wire_name(name)?;
+    (!name.is_empty()).then_some((flags, name))
+}
+
+/// Which records the server maintains for a lease, from the option 39 flags if the client sent any
+fn dns_update_scope(flags: Option<u8>) -> DnsUpdateScope {
+    match flags {
+        None => DnsUpdateScope::ALL,
+        Some(flags) if flags & FQDN_FLAG_N != 0 => DnsUpdateScope::NONE,
+        Some(flags) if flags & FQDN_FLAG_S != 0 => DnsUpdateScope::ALL,
+        Some(_) => DnsUpdateScope::REVERSE_ONLY,
+    }
+}
+
+/// Option 39 for the reply, telling the client what the server will update for `name`. The
+/// client's flags are honoured as sent, so the O (override) flag is never set.
+fn build_client_fqdn_option(flags: u8, name: &str) -> Dhcpv6Option {
+    let flags = if flags & FQDN_FLAG_N != 0 { FQDN_FLAG_N } else { flags & FQDN_FLAG_S };
+    let mut data = vec![flags];
+    data.extend(encode_wire_name(name));
+    Dhcpv6Option {
+        code: OPT_CLIENT_FQDN,
+        data,
+    }
+}
+
+pub async fn start(
+    settings: Arc<Settings>,
+    db: PgPool,
+    prefix_delegation: Arc<PrefixDelegationManager>,
+    dns_updates: Option<Arc<DhcpDnsIntegration>>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let server = Dhcpv6Server::new(settings, db, prefix_delegation, dns_updates).await?;
+    server.run(shutdown).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RouterAdvertisements;
+
+    fn ipv6_config(dns_servers: Vec<Ipv6Addr>) -> IPv6Config {
+        IPv6Config {
+            enabled: true,
+            radvd_config_path: "/etc/radvd.conf".to_string(),
+            radvd_pid_path: "/var/run/radvd.pid".to_string(),
+            router_advertisements: RouterAdvertisements::None,
+            prefix_length: 64,
+            router_lifetime: 1800,
+            reachable_time: 0,
+            retransmit_time: 0,
+            dns_servers,
+        }
+    }
+
+    #[test]
+    fn dns_servers_option_lists_configured_servers() {
+        let config = ipv6_config(vec![
+            "2001:db8::53".parse().unwrap(),
+            "2001:db8:1::35".parse().unwrap(),
+        ]);
+
+        let data = Dhcpv6Server::get_dns_servers(&config).unwrap();
+        assert_eq!(data, vec![
+            0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x53,
+            0x20, 0x01, 0x0d, 0xb8, 0, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x35,
+        ]);
+
+        // Nothing configured means no option, rather than someone else's resolver
+        assert!(Dhcpv6Server::get_dns_servers(&ipv6_config(Vec::new())).is_none());
+    }
+
+    #[test]
+    fn parses_what_build_ia_na_option_writes() {
+        let addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x1000);
+        let option = Dhcpv6Server::build_ia_na_option(0xdeadbeef, addr, 3600, 7200);
+
+        let ia = parse_ia_na(&option.data).unwrap();
+        assert_eq!(ia.iaid, 0xdeadbeef);
+        assert_eq!(ia.t1, 1800);
+        assert_eq!(ia.t2, 2700);
+        assert_eq!(ia.addresses, vec![IaAddress {
+            address: addr,
+            preferred_lifetime: 3600,
+            valid_lifetime: 7200,
+        }]);
+    }
+
+    #[test]
+    fn detects_rapid_commit() {
+        let mut packet = Dhcpv6Packet {
+            msg_type: DHCPV6_SOLICIT,
+            transaction_id: [0x12, 0x34, 0x56],
+            options: vec![Dhcpv6Option { code: OPT_CLIENTID, data: vec![0, 3, 0, 1, 2, 0, 0, 0, 0, 1] }],
+        };
+        assert!(!wants_rapid_commit(&packet));
+
+        packet.options.push(Dhcpv6Option { code: OPT_RAPID_COMMIT, data: Vec::new() });
+        assert!(wants_rapid_commit(&packet));
+    }
+
+    #[test]
+    fn matches_only_our_server_identifier() {
+        let server_duid = [0, 3, 0, 1, 2, 0, 0, 0, 0, 9];
+        let mut packet = Dhcpv6Packet {
+            msg_type: DHCPV6_REQUEST,
+            transaction_id: [0x12, 0x34, 0x56],
+            options: vec![Dhcpv6Option { code: OPT_CLIENTID, data: vec![0, 3, 0, 1, 2, 0, 0, 0, 0, 1] }],
+        };
+        assert!(!addressed_to(&packet, &server_duid));
+
+        packet.options.push(Dhcpv6Option { code: OPT_SERVERID, data: vec![0, 3, 0, 1, 2, 0, 0, 0, 0, 8] });
+        assert!(!addressed_to(&packet, &server_duid));
+
+        packet.options[1].data = server_duid.to_vec();
+        assert!(addressed_to(&packet, &server_duid));
+    }
+
+    #[tokio::test]
+    async fn rapid_commit_reply_carries_configuration() {
+        // Without IA options the exchange never reaches the database
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgresql://flowdns@127.0.0.1:1/flowdns")
+            .unwrap();
+        let mut settings = Settings::load("config/server.toml").unwrap();
+        settings.ipv6 = ipv6_config(vec!["2001:db8::53".parse().unwrap()]);
+        let prefix_delegation = PrefixDelegationManager::new(db.clone());
+
+        let client_duid = vec![0, 3, 0, 1, 2, 0, 0, 0, 0, 1];
+        let solicit = Dhcpv6Packet {
+            msg_type: DHCPV6_SOLICIT,
+            transaction_id: [0x12, 0x34, 0x56],
+            options: vec![
+                Dhcpv6Option { code: OPT_CLIENTID, data: client_duid.clone() },
+                Dhcpv6Option { code: OPT_RAPID_COMMIT, data: Vec::new() },
+            ],
+        };
+
+        let reply = Dhcpv6Server::rapid_commit(solicit, db, Arc::new(settings), &prefix_delegation, None, &[0, 1])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.msg_type, DHCPV6_REPLY);
+        assert_eq!(reply.transaction_id, [0x12, 0x34, 0x56]);
+
+        let option = |code| reply.options.iter().find(|opt| opt.code == code).map(|opt| opt.data.clone());
+        assert_eq!(option(OPT_CLIENTID), Some(client_duid));
+        assert_eq!(option(OPT_RAPID_COMMIT), Some(Vec::new()));
+        assert_eq!(option(OPT_DNS_SERVERS), Some("2001:db8::53".parse::<Ipv6Addr>().unwrap().octets().to_vec()));
+    }
+
+    #[test]
+    fn status_option_carries_code_and_message() {
+        let option = build_status_option(STATUS_SUCCESS, "Release received");
+        assert_eq!(option.code, OPT_STATUS_CODE);
+        assert_eq!(&option.data[..2], &[0, 0]);
+        assert_eq!(&option.data[2..], b"Release received");
+    }
+
+    #[test]
+    fn parses_ia_na_without_addresses() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&7u32.to_be_bytes());
+        data.extend_from_slice(&[0; 8]);
+
+        let ia = parse_ia_na(&data).unwrap();
+        assert_eq!(ia.iaid, 7);
+        assert!(ia.addresses.is_empty());
+        assert!(parse_ia_na(&data[..11]).is_none());
+    }
+
+    #[test]
+    fn skips_other_and_truncated_sub_options() {
+        let status = Dhcpv6Server::build_ia_na_status(9, STATUS_NO_BINDING, "No binding");
+        let ia = parse_ia_na(&status.data).unwrap();
+        assert_eq!(ia.iaid, 9);
+        assert!(ia.addresses.is_empty());
+
+        // Claims 24 bytes of IAADDR but carries only 4
+        let mut data = status.data[..12].to_vec();
+        data.extend_from_slice(&[0, 5, 0, 24, 0x20, 0x01, 0x0d, 0xb8]);
+        assert!(parse_ia_na(&data).unwrap().addresses.is_empty());
+    }
+
+    #[test]
+    fn parses_what_build_ia_pd_option_writes() {
+        let now = Utc::now();
+        let delegation = DelegatedPrefix {
+            id: Uuid::new_v4(),
+            client_duid: vec![0, 3, 0, 1, 2, 0, 0, 0, 0, 1],
+            iaid: 42,
+            prefix: Ipv6Addr::new(0x2001, 0xdb8, 0x1000, 0x0100, 0, 0, 0, 0),
+            prefix_length: 56,
+            delegated_length: 56,
+            valid_lifetime: 86400,
+            preferred_lifetime: 64800,
+            lease_start: now,
+            lease_end: now + Duration::seconds(86400),
+            state: crate::ipv6::prefix_delegation::PrefixState::Delegated,
+        };
+
+        let option = Dhcpv6Server::build_ia_pd_option(42, &delegation);
+        assert_eq!(option.code, OPT_IA_PD);
+
+        let ia_pd = parse_ia_pd(&option.data).unwrap();
+        assert_eq!(ia_pd.iaid, 42);
+        assert_eq!(ia_pd.prefixes, vec![IaPrefix {
+            preferred_lifetime: 64800,
+            valid_lifetime: 86400,
+            prefix_length: 56,
+            prefix: delegation.prefix,
+        }]);
+    }
+
+    #[test]
+    fn reads_client_fqdn_and_answers_with_the_same_flags() {
+        let packet = |flags: u8| Dhcpv6Packet {
+            msg_type: DHCPV6_REQUEST,
+            transaction_id: [1, 2, 3],
+            options: vec![Dhcpv6Option {
+                code: OPT_CLIENT_FQDN,
+                data: [vec![flags], encode_wire_name("laptop")].concat(),
+            }],
+        };
+
+        let (flags, name) = client_fqdn(&packet(FQDN_FLAG_S)).unwrap();
+        assert_eq!(name, "laptop");
+        assert_eq!(dns_update_scope(Some(flags)), DnsUpdateScope::ALL);
+        assert_eq!(dns_update_scope(Some(0)), DnsUpdateScope::REVERSE_ONLY);
+        assert_eq!(dns_update_scope(Some(FQDN_FLAG_N)), DnsUpdateScope::NONE);
+        assert_eq!(dns_update_scope(None), DnsUpdateScope::ALL);
+
+        let reply = build_client_fqdn_option(FQDN_FLAG_S, "laptop.example.com");
+        assert_eq!(reply.code, OPT_CLIENT_FQDN);
+        assert_eq!(reply.data[0], FQDN_FLAG_S);
+        assert_eq!(decode_wire_name(&reply.data[1..]).as_deref(), Some("laptop.example.com"));
+    }
+}
@@ -0,0 +1,344 @@
+// SQL query implementations for DHCPv6 leases
+// Using runtime queries instead of compile-time checked macros
+
+use crate::ipv6::dhcpv6::Dhcpv6Lease;
+use ipnetwork::{IpNetwork, Ipv6Network};
+use sqlx::{postgres::PgRow, PgPool, Row};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv6Addr};
+use uuid::Uuid;
+use chrono::{Duration, Utc};
+use anyhow::Result;
+
+/// First host offset handed out, leaving the low addresses for routers and static hosts
+pub const POOL_START: u128 = 0x1000;
+
+/// Upper bound on how far past POOL_START we search for a free address
+const POOL_SEARCH_LIMIT: u128 = 0x10000;
+
+/// A subnet that hands out stateful DHCPv6 addresses from its `ipv6_prefix`
+#[derive(Debug, Clone)]
+pub struct Dhcpv6Pool {
+    pub subnet_id: Uuid,
+    pub prefix: Ipv6Network,
+    pub valid_lifetime: u32,
+    pub preferred_lifetime: u32,
+    /// Commit on a SOLICIT that asks for Rapid Commit instead of sending an ADVERTISE
+    pub rapid_commit: bool,
+}
+
+impl Dhcpv6Pool {
+    /// The address `offset` hosts into the prefix, if it still lies inside it
+    pub fn address_at(&self, offset: u128) -> Option<Ipv6Addr> {
+        let host_bits = 128 - u32::from(self.prefix.prefix());
+        if host_bits < 128 && offset >> host_bits != 0 {
+            return None;
+        }
+        Some(Ipv6Addr::from(u128::from(self.prefix.network()) | offset))
+    }
+
+    /// True for addresses this pool would hand out itself
+    pub fn contains(&self, addr: Ipv6Addr) -> bool {
+        if !self.prefix.contains(addr) {
+            return false;
+        }
+        let offset = u128::from(addr) - u128::from(self.prefix.network());
+        (POOL_START..POOL_START + POOL_SEARCH_LIMIT).contains(&offset)
+    }
+}
+
+/// The subnet DHCPv6 clients are served from. Without relay support every
+/// client lands on the same link, so this is the first eligible subnet by name.
+pub async fn fetch_pool(db: &PgPool) -> Result<Option<Dhcpv6Pool>> {
+    let row = sqlx::query(
+        r#"
+        SELECT id, ipv6_prefix, lease_duration, dhcpv6_rapid_commit
+        FROM dhcp_subnets
+        WHERE enabled = true
+            AND ipv6_enabled = true
+            AND ipv6_prefix IS NOT NULL
+            AND family(ipv6_prefix) = 6
+            AND COALESCE(ipv6_mode, 'slaac') <> 'slaac'
+        ORDER BY name
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(db)
+    .await?;
+
+    let Some(row) = row else { return Ok(None) };
+    let prefix = match row.get::<IpNetwork, _>("ipv6_prefix") {
+        IpNetwork::V6(prefix) => prefix,
+        IpNetwork::V4(_) => return Ok(None),
+    };
+    let valid_lifetime = row.get::<Option<i32>, _>("lease_duration").unwrap_or(86400).max(0) as u32;
+
+    Ok(Some(Dhcpv6Pool {
+        subnet_id: row.get("id"),
+        prefix,
+        valid_lifetime,
+        preferred_lifetime: valid_lifetime / 2,
+        rapid_commit: row.get::<Option<bool>, _>("dhcpv6_rapid_commit").unwrap_or(false),
+    }))
+}
+
+fn lease_from_row(row: &PgRow) -> Result<Dhcpv6Lease> {
+    let ipv6_address = match row.get::<IpAddr, _>("ipv6_address") {
+        IpAddr::V6(addr) => addr,
+        IpAddr::V4(addr) => anyhow::bail!("DHCPv6 lease has an IPv4 address {}", addr),
+    };
+
+    Ok(Dhcpv6Lease {
+        id: row.get("id"),
+        subnet_id: row.get("subnet_id"),
+        duid: row.get("duid"),
+        iaid: row.get::<i32, _>("iaid") as u32,
+        ipv6_address,
+        prefix_length: 128,
+        lease_start: row.get("lease_start"),
+        lease_end: row.get("lease_end"),
+        preferred_lifetime: row.get::<i32, _>("preferred_lifetime") as u32,
+        valid_lifetime: row.get::<i32, _>("valid_lifetime") as u32,
+        hostname: row.get("hostname"),
+        state: row.get("state"),
+    })
+}
+
+/// The client's current unexpired lease for this IA
+pub async fn find_active_lease(db: &PgPool, duid: &[u8], iaid: u32) -> Result<Option<Dhcpv6Lease>> {
+    let row = sqlx::query(
+        r#"
+        SELECT id, subnet_id, duid, iaid, ipv6_address, hostname,
+               lease_start, lease_end, preferred_lifetime, valid_lifetime, state
+        FROM dhcpv6_leases
+        WHERE duid = $1
+            AND iaid = $2
+            AND state = 'active'
+            AND lease_end > NOW()
+        ORDER BY lease_end DESC
+        LIMIT 1
+        "#
+    )
+    .bind(duid)
+    .bind(iaid as i32)
+    .fetch_optional(db)
+    .await?;
+
+    row.as_ref().map(lease_from_row).transpose()
+}
+
+/// Most recent leases, optionally only those in `state`
+pub async fn list_leases(db: &PgPool, state: Option<&str>) -> Result<Vec<Dhcpv6Lease>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, subnet_id, duid, iaid, ipv6_address, hostname,
+               lease_start, lease_end, preferred_lifetime, valid_lifetime, state
+        FROM dhcpv6_leases
+        WHERE $1::text IS NULL OR state = $1
+        ORDER BY lease_start DESC
+        LIMIT 100
+        "#
+    )
+    .bind(state)
+    .fetch_all(db)
+    .await?;
+
+    rows.iter().map(lease_from_row).collect()
+}
+
+/// Lowest address in the pool without an unexpired active lease
+pub async fn next_free_address(db: &PgPool, pool: &Dhcpv6Pool) -> Result<Option<Ipv6Addr>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT ipv6_address
+        FROM dhcpv6_leases
+        WHERE subnet_id = $1
+            AND state = 'active'
+            AND lease_end > NOW()
+        "#
+    )
+    .bind(pool.subnet_id)
+    .fetch_all(db)
+    .await?;
+
+    let in_use: HashSet<IpAddr> = rows.iter().map(|row| row.get("ipv6_address")).collect();
+
+    Ok((POOL_START..POOL_START + POOL_SEARCH_LIMIT)
+        .map_while(|offset| pool.address_at(offset))
+        .find(|addr| !in_use.contains(&IpAddr::V6(*addr))))
+}
+
+/// True when no client holds an unexpired active lease on the address
+pub async fn is_address_free(db: &PgPool, addr: Ipv6Addr) -> Result<bool> {
+    let row = sqlx::query(
+        r#"
+        SELECT 1
+        FROM dhcpv6_leases
+        WHERE ipv6_address = $1
+            AND state = 'active'
+            AND lease_end > NOW()
+        "#
+    )
+    .bind(IpAddr::V6(addr))
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.is_none())
+}
+
+/// Records an active lease for the client, reusing its row if it held this address before
+pub async fn commit_lease(
+    db: &PgPool,
+    pool: &Dhcpv6Pool,
+    duid: &[u8],
+    iaid: u32,
+    addr: Ipv6Addr,
+    hostname: Option<&str>,
+) -> Result<Dhcpv6Lease> {
+    let lease_start = Utc::now();
+    let lease_end = lease_start + Duration::seconds(pool.valid_lifetime as i64);
+
+    let mut tx = db.begin().await?;
+
+    // Stale active rows would otherwise block the address in the unique index
+    sqlx::query(
+        r#"
+        UPDATE dhcpv6_leases
+        SET state = 'expired', updated_at = NOW()
+        WHERE ipv6_address = $1 AND state = 'active' AND lease_end <= NOW()
+        "#
+    )
+    .bind(IpAddr::V6(addr))
+    .execute(&mut *tx)
+    .await?;
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dhcpv6_leases (subnet_id, duid, iaid, ipv6_address, hostname,
+                                   lease_start, lease_end, preferred_lifetime, valid_lifetime, state)
+        VALUES ($1, $2, $3, $4, $9, $5, $6, $7, $8, 'active')
+        ON CONFLICT (duid, iaid, ipv6_address) DO UPDATE
+        SET subnet_id = EXCLUDED.subnet_id,
+            hostname = EXCLUDED.hostname,
+            lease_start = EXCLUDED.lease_start,
+            lease_end = EXCLUDED.lease_end,
+            preferred_lifetime = EXCLUDED.preferred_lifetime,
+            valid_lifetime = EXCLUDED.valid_lifetime,
+            state = 'active',
+            updated_at = NOW()
+        RETURNING id, subnet_id, duid, iaid, ipv6_address, hostname,
+                  lease_start, lease_end, preferred_lifetime, valid_lifetime, state
+        "#
+    )
+    .bind(pool.subnet_id)
+    .bind(duid)
+    .bind(iaid as i32)
+    .bind(IpAddr::V6(addr))
+    .bind(lease_start)
+    .bind(lease_end)
+    .bind(pool.preferred_lifetime as i32)
+    .bind(pool.valid_lifetime as i32)
+    .bind(hostname)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    lease_from_row(&row)
+}
+
+/// Extends an existing lease by the pool's lifetimes, keeping its hostname unless the client sent a new one
+pub async fn renew_lease(
+    db: &PgPool,
+    lease_id: Uuid,
+    pool: &Dhcpv6Pool,
+    hostname: Option<&str>,
+) -> Result<Dhcpv6Lease> {
+    let row = sqlx::query(
+        r#"
+        UPDATE dhcpv6_leases
+        SET lease_end = $2,
+            preferred_lifetime = $3,
+            valid_lifetime = $4,
+            hostname = COALESCE($5, hostname),
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, subnet_id, duid, iaid, ipv6_address, hostname,
+                  lease_start, lease_end, preferred_lifetime, valid_lifetime, state
+        "#
+    )
+    .bind(lease_id)
+    .bind(Utc::now() + Duration::seconds(pool.valid_lifetime as i64))
+    .bind(pool.preferred_lifetime as i32)
+    .bind(pool.valid_lifetime as i32)
+    .bind(hostname)
+    .fetch_one(db)
+    .await?;
+
+    lease_from_row(&row)
+}
+
+/// Ends the client's active lease on `addr` for this IA, freeing the address. `None` when the
+/// client holds no such lease.
+pub async fn release_lease(db: &PgPool, duid: &[u8], iaid: u32, addr: Ipv6Addr) -> Result<Option<Dhcpv6Lease>> {
+    let row = sqlx::query(
+        r#"
+        UPDATE dhcpv6_leases
+        SET state = 'released', lease_end = NOW(), updated_at = NOW()
+        WHERE duid = $1 AND iaid = $2 AND ipv6_address = $3 AND state = 'active'
+        RETURNING id, subnet_id, duid, iaid, ipv6_address, hostname,
+                  lease_start, lease_end, preferred_lifetime, valid_lifetime, state
+        "#
+    )
+    .bind(duid)
+    .bind(iaid as i32)
+    .bind(IpAddr::V6(addr))
+    .fetch_optional(db)
+    .await?;
+
+    row.as_ref().map(lease_from_row).transpose()
+}
+
+/// True when another client committed the same address first
+pub fn is_address_conflict(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<sqlx::Error>()
+        .and_then(|e| e.as_database_error())
+        .map(|e| e.is_unique_violation())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(prefix: &str) -> Dhcpv6Pool {
+        Dhcpv6Pool {
+            subnet_id: Uuid::nil(),
+            prefix: prefix.parse().unwrap(),
+            valid_lifetime: 7200,
+            preferred_lifetime: 3600,
+            rapid_commit: false,
+        }
+    }
+
+    #[test]
+    fn address_at_offsets_into_the_prefix() {
+        let pool = pool("2001:db8:1::/64");
+        assert_eq!(pool.address_at(POOL_START), Some("2001:db8:1::1000".parse().unwrap()));
+        assert_eq!(pool.address_at(0xffff_ffff_ffff_ffff), Some("2001:db8:1::ffff:ffff:ffff:ffff".parse().unwrap()));
+    }
+
+    #[test]
+    fn address_at_stops_at_the_end_of_the_prefix() {
+        let pool = pool("2001:db8:1::/120");
+        assert_eq!(pool.address_at(0xff), Some("2001:db8:1::ff".parse().unwrap()));
+        assert_eq!(pool.address_at(0x100), None);
+    }
+
+    #[test]
+    fn contains_only_the_handed_out_range() {
+        let pool = pool("2001:db8:1::/64");
+        assert!(pool.contains("2001:db8:1::1000".parse().unwrap()));
+        assert!(!pool.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!pool.contains("2001:db8:2::1000".parse().unwrap()));
+    }
+}
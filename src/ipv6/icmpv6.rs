@@ -0,0 +1,44 @@
+// Raw ICMPv6 sockets for Neighbor Discovery, shared by the Router Advertisement sender and DAD
+use anyhow::{anyhow, Context, Result};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use crate::dhcp::socket::bind_device;
+
+/// Index and hardware address of a local interface
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub name: String,
+    pub index: u32,
+    pub mac: Option<[u8; 6]>,
+}
+
+impl Link {
+    pub fn by_name(name: &str) -> Result<Self> {
+        let interface = pnet::datalink::interfaces()
+            .into_iter()
+            .find(|interface| interface.name == name)
+            .ok_or_else(|| anyhow!("No interface named {}", name))?;
+
+        Ok(Self {
+            name: interface.name,
+            index: interface.index,
+            mac: interface.mac.map(|mac| mac.octets()),
+        })
+    }
+}
+
+/// A raw ICMPv6 socket bound to `link`. Neighbor Discovery packets must carry a hop limit of
+/// 255, and the kernel fills in the ICMPv6 checksum. Needs CAP_NET_RAW.
+pub fn open_socket(link: &Link) -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))
+        .context("Failed to open a raw ICMPv6 socket (CAP_NET_RAW is required)")?;
+    bind_device(&socket, &link.name)?;
+    socket.set_multicast_hops_v6(255)?;
+    socket.set_unicast_hops_v6(255)?;
+    socket.set_multicast_loop_v6(false)?;
+    socket.set_multicast_if_v6(link.index)?;
+    socket.set_nonblocking(true)?;
+
+    let std_socket: std::net::UdpSocket = socket.into();
+    Ok(UdpSocket::from_std(std_socket)?)
+}
@@ -1,4 +1,9 @@
+pub mod dad;
 pub mod dhcpv6;
+pub mod dhcpv6_leases;
+pub mod icmpv6;
 pub mod radvd;
+pub mod router_advertisement;
+pub mod server_duid;
 pub mod slaac;
-pub mod prefix_delegation;
\ No newline at end of file
+pub mod prefix_delegation;
@@ -1,10 +1,12 @@
-use std::net::Ipv6Addr;
-use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
 use chrono::{DateTime, Utc, Duration};
 use uuid::Uuid;
 use anyhow::Result;
 use tracing::{info, debug, warn};
-use sqlx::PgPool;
+use sqlx::{postgres::PgRow, PgConnection, PgPool, Row};
+use ipnetwork::{IpNetwork, Ipv6Network};
 
 #[derive(Debug, Clone)]
 pub struct DelegatedPrefix {
@@ -29,6 +31,31 @@ pub enum PrefixState {
     Expired,
 }
 
+impl PrefixState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrefixState::Available => "available",
+            PrefixState::Delegated => "delegated",
+            PrefixState::Reserved => "reserved",
+            PrefixState::Expired => "expired",
+        }
+    }
+}
+
+impl std::str::FromStr for PrefixState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "available" => Ok(PrefixState::Available),
+            "delegated" => Ok(PrefixState::Delegated),
+            "reserved" => Ok(PrefixState::Reserved),
+            "expired" => Ok(PrefixState::Expired),
+            other => Err(anyhow::anyhow!("Unknown prefix state: {}", other)),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PrefixPool {
     pub id: Uuid,
@@ -40,18 +67,55 @@ pub struct PrefixPool {
     pub available_prefixes: u32,
 }
 
+impl PrefixPool {
+    /// The `offset`-th prefix of `delegation_length` inside the pool
+    pub fn nth_prefix(&self, offset: u128) -> Ipv6Addr {
+        let shift = 128 - u32::from(self.delegation_length);
+        let offset = if shift >= 128 { 0 } else { offset << shift };
+        Ipv6Addr::from(u128::from(self.prefix) | offset)
+    }
+
+    /// Inverse of `nth_prefix`, for prefixes that lie inside the pool
+    pub fn offset_of(&self, prefix: Ipv6Addr) -> Option<u128> {
+        let base = u128::from(self.prefix);
+        let addr = u128::from(prefix);
+        let shift = 128 - u32::from(self.delegation_length);
+        let offset = addr.checked_sub(base)?;
+        let offset = if shift >= 128 { 0 } else { offset >> shift };
+        (offset < u128::from(self.total_prefixes)).then_some(offset)
+    }
+
+    fn network(&self) -> Result<IpNetwork> {
+        Ok(IpNetwork::V6(Ipv6Network::new(self.prefix, self.prefix_length)?))
+    }
+}
+
 pub struct PrefixDelegationManager {
     db: PgPool,
-    pools: HashMap<Uuid, PrefixPool>,
+    pools: RwLock<HashMap<Uuid, PrefixPool>>,
 }
 
 impl PrefixDelegationManager {
     pub fn new(db: PgPool) -> Self {
         Self {
             db,
-            pools: HashMap::new(),
+            pools: RwLock::new(HashMap::new()),
         }
     }
+
+    /// A manager serving exactly these pools, without loading any from the database
+    pub fn with_pools(db: PgPool, pools: Vec<PrefixPool>) -> Self {
+        Self {
+            db,
+            pools: RwLock::new(pools.into_iter().map(|pool| (pool.id, pool)).collect()),
+        }
+    }
+
+    fn pools_snapshot(&self) -> Vec<PrefixPool> {
+        let mut pools: Vec<PrefixPool> = self.pools.read().unwrap().values().cloned().collect();
+        pools.sort_by(|a, b| a.name.cmp(&b.name));
+        pools
+    }
     
     pub async fn init_pools(&mut self) -> Result<()> {
         // Load prefix pools from database
@@ -66,12 +130,40 @@ impl PrefixDelegationManager {
         .await?;
         
         for row in rows {
-            // Parse and add pools - simplified
-            info!("Loaded prefix pool from database");
+            let prefix = match row.get::<IpAddr, _>("prefix") {
+                IpAddr::V6(prefix) => prefix,
+                IpAddr::V4(prefix) => {
+                    warn!("Skipping prefix pool with IPv4 prefix {}", prefix);
+                    continue;
+                }
+            };
+            let prefix_length = row.get::<i16, _>("prefix_length") as u8;
+            let delegation_length = row.get::<i16, _>("delegation_length") as u8;
+            if delegation_length < prefix_length || delegation_length > 128 {
+                warn!("Skipping prefix pool {}/{} delegating /{}", prefix, prefix_length, delegation_length);
+                continue;
+            }
+
+            let total_prefixes = 1u64
+                .checked_shl(u32::from(delegation_length - prefix_length))
+                .unwrap_or(u64::MAX)
+                .min(u64::from(u32::MAX)) as u32;
+            let pool = PrefixPool {
+                id: row.get("id"),
+                name: row.get("name"),
+                prefix,
+                prefix_length,
+                delegation_length,
+                total_prefixes,
+                available_prefixes: total_prefixes,
+            };
+
+            info!("Loaded prefix pool {}: {}/{} delegating /{}", pool.name, prefix, prefix_length, delegation_length);
+            self.pools.get_mut().unwrap().insert(pool.id, pool);
         }
         
         // Add default pool if none exist
-        if self.pools.is_empty() {
+        if self.pools.get_mut().unwrap().is_empty() {
             self.add_default_pool().await?;
         }
         
@@ -89,7 +181,7 @@ impl PrefixDelegationManager {
             available_prefixes: 256,
         };
         
-        self.pools.insert(pool.id, pool.clone());
+        self.pools.get_mut().unwrap().insert(pool.id, pool.clone());
         
         // Store in database
         sqlx::query(
@@ -102,9 +194,9 @@ impl PrefixDelegationManager {
         )
         .bind(&pool.id)
         .bind(&pool.name)
-        .bind(pool.prefix.to_string())
-        .bind(pool.prefix_length as i32)
-        .bind(pool.delegation_length as i32)
+        .bind(IpAddr::V6(pool.prefix))
+        .bind(pool.prefix_length as i16)
+        .bind(pool.delegation_length as i16)
         .execute(&self.db)
         .await?;
         
@@ -121,15 +213,20 @@ impl PrefixDelegationManager {
         lifetime_hint: Option<u32>,
     ) -> Result<DelegatedPrefix> {
         // Check for existing delegation
-        if let Ok(existing) = self.get_existing_delegation(&client_duid, iaid).await {
+        if let Some(mut existing) = self.get_existing_delegation(&client_duid, iaid).await? {
             if existing.state == PrefixState::Delegated {
-                info!("Renewing existing prefix delegation for client");
+                info!("Renewing existing prefix delegation {}/{} for client", existing.prefix, existing.delegated_length);
+                existing.lease_start = Utc::now();
+                existing.lease_end = existing.lease_start + Duration::seconds(existing.valid_lifetime as i64);
+                let mut conn = self.db.acquire().await?;
+                Self::store_delegation(&mut conn, &existing).await?;
                 return Ok(existing);
             }
         }
         
-        // Find available prefix from pool
-        let prefix = self.allocate_prefix(requested_length).await?;
+        // Allocation and the new row share a transaction so concurrent requests can't pick the same prefix
+        let mut tx = self.db.begin().await?;
+        let prefix = self.allocate_prefix(&mut tx, requested_length).await?;
         
         // Calculate lifetimes
         let valid_lifetime = lifetime_hint.unwrap_or(86400);  // 24 hours default
@@ -152,7 +249,8 @@ impl PrefixDelegationManager {
         };
         
         // Store in database
-        self.store_delegation(&delegation).await?;
+        Self::store_delegation(&mut tx, &delegation).await?;
+        tx.commit().await?;
         
         info!(
             "Delegated prefix {}/{} to client DUID {:?}",
@@ -168,10 +266,10 @@ impl PrefixDelegationManager {
         &self,
         client_duid: &[u8],
         iaid: u32,
-    ) -> Result<DelegatedPrefix> {
+    ) -> Result<Option<DelegatedPrefix>> {
         let row = sqlx::query(
             r#"
-            SELECT id, prefix, prefix_length, delegated_length,
+            SELECT id, client_duid, iaid, prefix, prefix_length, delegated_length,
                    valid_lifetime, preferred_lifetime, lease_start, lease_end, state
             FROM ipv6_delegated_prefixes
             WHERE client_duid = $1 AND iaid = $2 AND state = 'delegated'
@@ -184,40 +282,87 @@ impl PrefixDelegationManager {
         .fetch_optional(&self.db)
         .await?;
         
-        match row {
-            Some(_row) => {
-                // Parse and return delegation - simplified
-                Err(anyhow::anyhow!("No existing delegation found"))
-            }
-            None => Err(anyhow::anyhow!("No existing delegation found")),
-        }
+        row.as_ref().map(delegation_from_row).transpose()
     }
     
+    /// Most recent delegations, optionally only those in `state`
+    pub async fn list_delegations(db: &PgPool, state: Option<&PrefixState>) -> Result<Vec<DelegatedPrefix>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, client_duid, iaid, prefix, prefix_length, delegated_length,
+                   valid_lifetime, preferred_lifetime, lease_start, lease_end, state
+            FROM ipv6_delegated_prefixes
+            WHERE $1::text IS NULL OR state = $1
+            ORDER BY lease_start DESC
+            LIMIT 100
+            "#
+        )
+        .bind(state.map(|state| state.as_str()))
+        .fetch_all(db)
+        .await?;
+        
+        rows.iter().map(delegation_from_row).collect()
+    }
+    
+    /// Picks the lowest prefix not held by any delegation. Must run inside the
+    /// transaction that stores the delegation; the pool is locked until it commits.
     async fn allocate_prefix(
         &self,
+        conn: &mut PgConnection,
         requested_length: Option<u8>,
     ) -> Result<(Ipv6Addr, u8)> {
-        // Find first available pool
-        let pool = self.pools.values()
-            .find(|p| p.available_prefixes > 0)
-            .ok_or_else(|| anyhow::anyhow!("No prefixes available"))?;
-        
-        let delegation_length = requested_length.unwrap_or(pool.delegation_length);
-        
-        // Calculate next available prefix
-        // This is simplified - real implementation would track allocated prefixes
-        let prefix_num = (pool.total_prefixes - pool.available_prefixes) as u128;
-        let prefix_shift = 128 - delegation_length;
+        // Honor the client's length hint when a pool delegates that size, otherwise ignore it
+        let pools = self.pools_snapshot();
+        let matching: Vec<&PrefixPool> = pools.iter()
+            .filter(|p| requested_length == Some(p.delegation_length))
+            .collect();
+        let candidates = if matching.is_empty() { pools.iter().collect() } else { matching };
         
-        let base_addr = u128::from_be_bytes(pool.prefix.octets());
-        let delegated_addr = base_addr | (prefix_num << prefix_shift);
-        
-        let prefix = Ipv6Addr::from(delegated_addr.to_be_bytes());
+        for pool in candidates {
+            sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+                .bind(pool.id.to_string())
+                .execute(&mut *conn)
+                .await?;
+            
+            // Released and reclaimed prefixes are 'available' and may be handed out again
+            let rows = sqlx::query(
+                r#"
+                SELECT prefix
+                FROM ipv6_delegated_prefixes
+                WHERE state <> 'available'
+                    AND delegated_length = $1
+                    AND prefix <<= $2
+                "#
+            )
+            .bind(pool.delegation_length as i16)
+            .bind(pool.network()?)
+            .fetch_all(&mut *conn)
+            .await?;
+            
+            let in_use: HashSet<u128> = rows.iter()
+                .filter_map(|row| match row.get::<IpAddr, _>("prefix") {
+                    IpAddr::V6(prefix) => pool.offset_of(prefix),
+                    IpAddr::V4(_) => None,
+                })
+                .collect();
+            
+            let free = (0..u128::from(pool.total_prefixes)).find(|offset| !in_use.contains(offset));
+            let available = pool.total_prefixes.saturating_sub(in_use.len() as u32);
+            
+            if let Some(pool_state) = self.pools.write().unwrap().get_mut(&pool.id) {
+                pool_state.available_prefixes = available.saturating_sub(free.is_some() as u32);
+            }
+            
+            if let Some(offset) = free {
+                debug!("Allocating prefix #{} from pool {}", offset, pool.name);
+                return Ok((pool.nth_prefix(offset), pool.delegation_length));
+            }
+        }
         
-        Ok((prefix, delegation_length))
+        Err(anyhow::anyhow!("No prefixes available"))
     }
     
-    async fn store_delegation(&self, delegation: &DelegatedPrefix) -> Result<()> {
+    async fn store_delegation(conn: &mut PgConnection, delegation: &DelegatedPrefix) -> Result<()> {
         sqlx::query(
             r#"
             INSERT INTO ipv6_delegated_prefixes
@@ -239,15 +384,15 @@ impl PrefixDelegationManager {
         .bind(&delegation.id)
         .bind(&delegation.client_duid)
         .bind(delegation.iaid as i32)
-        .bind(delegation.prefix.to_string())
-        .bind(delegation.prefix_length as i32)
-        .bind(delegation.delegated_length as i32)
+        .bind(IpAddr::V6(delegation.prefix))
+        .bind(delegation.prefix_length as i16)
+        .bind(delegation.delegated_length as i16)
         .bind(delegation.valid_lifetime as i32)
         .bind(delegation.preferred_lifetime as i32)
         .bind(&delegation.lease_start)
         .bind(&delegation.lease_end)
-        .bind("delegated")
-        .execute(&self.db)
+        .bind(delegation.state.as_str())
+        .execute(conn)
         .await?;
         
         Ok(())
@@ -268,7 +413,7 @@ impl PrefixDelegationManager {
         )
         .bind(client_duid)
         .bind(iaid as i32)
-        .bind(prefix.to_string())
+        .bind(IpAddr::V6(*prefix))
         .execute(&self.db)
         .await?;
         
@@ -336,16 +481,39 @@ impl PrefixDelegationManager {
         .fetch_one(&self.db)
         .await?;
         
+        let count = |column: &str| row.get::<i64, _>(column).clamp(0, u32::MAX as i64) as u32;
+        
         Ok(PrefixStats {
-            total_pools: self.pools.len(),
-            delegated_prefixes: 0,  // Would parse from row
-            available_prefixes: 0,
-            reserved_prefixes: 0,
-            expired_prefixes: 0,
+            total_pools: self.pools.read().unwrap().len(),
+            delegated_prefixes: count("delegated"),
+            available_prefixes: count("available"),
+            reserved_prefixes: count("reserved"),
+            expired_prefixes: count("expired"),
         })
     }
 }
 
+fn delegation_from_row(row: &PgRow) -> Result<DelegatedPrefix> {
+    let prefix = match row.get::<IpAddr, _>("prefix") {
+        IpAddr::V6(prefix) => prefix,
+        IpAddr::V4(prefix) => return Err(anyhow::anyhow!("Delegated prefix {} is not IPv6", prefix)),
+    };
+    
+    Ok(DelegatedPrefix {
+        id: row.get("id"),
+        client_duid: row.get("client_duid"),
+        iaid: row.get::<i32, _>("iaid") as u32,
+        prefix,
+        prefix_length: row.get::<i16, _>("prefix_length") as u8,
+        delegated_length: row.get::<i16, _>("delegated_length") as u8,
+        valid_lifetime: row.get::<i32, _>("valid_lifetime") as u32,
+        preferred_lifetime: row.get::<i32, _>("preferred_lifetime") as u32,
+        lease_start: row.get("lease_start"),
+        lease_end: row.get("lease_end"),
+        state: row.get::<String, _>("state").parse()?,
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct PrefixStats {
     pub total_pools: usize,
@@ -353,4 +521,48 @@ pub struct PrefixStats {
     pub available_prefixes: u32,
     pub reserved_prefixes: u32,
     pub expired_prefixes: u32,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(prefix: &str, prefix_length: u8, delegation_length: u8) -> PrefixPool {
+        let total_prefixes = 1u32 << (delegation_length - prefix_length);
+        PrefixPool {
+            id: Uuid::new_v4(),
+            name: "test".to_string(),
+            prefix: prefix.parse().unwrap(),
+            prefix_length,
+            delegation_length,
+            total_prefixes,
+            available_prefixes: total_prefixes,
+        }
+    }
+
+    #[test]
+    fn nth_prefix_steps_by_delegation_size() {
+        let pool = pool("2001:db8:1000::", 48, 56);
+        assert_eq!(pool.nth_prefix(0), "2001:db8:1000::".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(pool.nth_prefix(1), "2001:db8:1000:100::".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(pool.nth_prefix(255), "2001:db8:1000:ff00::".parse::<Ipv6Addr>().unwrap());
+    }
+
+    #[test]
+    fn offset_of_inverts_nth_prefix_within_the_pool() {
+        let pool = pool("2001:db8:1000::", 48, 56);
+        for offset in [0, 1, 17, 255] {
+            assert_eq!(pool.offset_of(pool.nth_prefix(offset)), Some(offset));
+        }
+        assert_eq!(pool.offset_of("2001:db8:1001::".parse().unwrap()), None);
+        assert_eq!(pool.offset_of("2001:db8:fff::".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn prefix_states_round_trip_through_their_column_values() {
+        for state in [PrefixState::Available, PrefixState::Delegated, PrefixState::Reserved, PrefixState::Expired] {
+            assert_eq!(state.as_str().parse::<PrefixState>().unwrap(), state);
+        }
+        assert!("leased".parse::<PrefixState>().is_err());
+    }
+}
@@ -1,11 +1,15 @@
-use std::process::{Command, Stdio};
-use std::fs;
+use std::net::IpAddr;
 use std::path::Path;
-use anyhow::Result;
-use tracing::{info, error, debug};
+use std::process::Output;
+use anyhow::{Context, Result};
+use ipnetwork::{IpNetwork, Ipv6Network};
+use sqlx::{PgPool, Row};
+use tracing::{info, error, debug, warn};
 use crate::config::Settings;
 use std::sync::Arc;
+use tokio::process::Command;
 use tokio::time::{interval, Duration};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, Clone)]
 pub struct RadvdConfig {
@@ -29,6 +33,77 @@ pub struct InterfaceConfig {
     pub dnssl_domains: Vec<String>,
 }
 
+// Advertisement timing; radvd's own defaults apart from the router lifetime, which is configured
+const MIN_RTR_ADV_INTERVAL: u32 = 200;
+const MAX_RTR_ADV_INTERVAL: u32 = 600;
+const PREFIX_VALID_LIFETIME: u32 = 86400;
+const PREFIX_PREFERRED_LIFETIME: u32 = 14400;
+
+/// A subnet whose IPv6 prefix is advertised on the link it is served on
+#[derive(Debug, Clone)]
+pub struct AdvertisedSubnet {
+    pub name: String,
+    /// The IPv4 network, used to find the interface the subnet lives on
+    pub network: IpNetwork,
+    pub prefix: Ipv6Network,
+    pub domain_name: Option<String>,
+    /// Addresses come from DHCPv6 (`ipv6_mode` other than `slaac`) rather than SLAAC
+    pub stateful: bool,
+}
+
+/// A local interface and the addresses configured on it
+#[derive(Debug, Clone)]
+pub struct LinkAddresses {
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+}
+
+impl LinkAddresses {
+    /// Every interface on this host, loopback excluded
+    pub fn local() -> Vec<Self> {
+        pnet::datalink::interfaces()
+            .into_iter()
+            .filter(|interface| !interface.is_loopback())
+            .map(|interface| Self {
+                addresses: interface.ips.iter().map(|ip| ip.ip()).collect(),
+                name: interface.name,
+            })
+            .collect()
+    }
+}
+
+/// Enabled IPv6 subnets with a prefix to advertise
+pub async fn fetch_advertised_subnets(db: &PgPool) -> Result<Vec<AdvertisedSubnet>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT name, network, ipv6_prefix, domain_name, ipv6_mode
+        FROM dhcp_subnets
+        WHERE enabled = true
+            AND ipv6_enabled = true
+            AND ipv6_prefix IS NOT NULL
+            AND family(ipv6_prefix) = 6
+        ORDER BY name
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let IpNetwork::V6(prefix) = row.get::<IpNetwork, _>("ipv6_prefix") else { return None };
+            let mode: Option<String> = row.get("ipv6_mode");
+            Some(AdvertisedSubnet {
+                name: row.get("name"),
+                network: row.get("network"),
+                prefix,
+                domain_name: row.get("domain_name"),
+                stateful: mode.as_deref().unwrap_or("slaac") != "slaac",
+            })
+        })
+        .collect())
+}
+
 pub struct RadvdManager {
     config_path: String,
     pid_path: String,
@@ -38,8 +113,8 @@ pub struct RadvdManager {
 impl RadvdManager {
     pub fn new(settings: Arc<Settings>) -> Self {
         Self {
-            config_path: "/etc/radvd.conf".to_string(),
-            pid_path: "/var/run/radvd.pid".to_string(),
+            config_path: settings.ipv6.radvd_config_path.clone(),
+            pid_path: settings.ipv6.radvd_pid_path.clone(),
             settings,
         }
     }
@@ -49,7 +124,9 @@ impl RadvdManager {
         let config_content = self.generate_config(&config)?;
         
         // Write configuration to file
-        fs::write(&self.config_path, config_content)?;
+        tokio::fs::write(&self.config_path, config_content)
+            .await
+            .with_context(|| format!("Failed to write {}", self.config_path))?;
         info!("Wrote radvd configuration to {}", self.config_path);
         
         // Reload or restart radvd
@@ -146,21 +223,16 @@ impl RadvdManager {
     
     async fn reload_radvd(&self) -> Result<()> {
         // Check if radvd is running
-        if Path::new(&self.pid_path).exists() {
-            // Reload configuration
-            let output = Command::new("systemctl")
-                .arg("reload")
-                .arg("radvd")
-                .output()?;
-                
-            if output.status.success() {
-                info!("Successfully reloaded radvd");
-            } else {
-                // Try restart if reload fails
-                self.restart_radvd().await?;
+        if tokio::fs::try_exists(&self.pid_path).await.unwrap_or(false) {
+            // Try restart if reload fails
+            match systemctl("reload").await {
+                Ok(()) => info!("Successfully reloaded radvd"),
+                Err(e) => {
+                    warn!("{}, restarting instead", e);
+                    self.restart_radvd().await?;
+                }
             }
         } else {
-            // Start radvd
             self.start_radvd().await?;
         }
         
@@ -168,34 +240,14 @@ impl RadvdManager {
     }
     
     async fn start_radvd(&self) -> Result<()> {
-        let output = Command::new("systemctl")
-            .arg("start")
-            .arg("radvd")
-            .output()?;
-            
-        if output.status.success() {
-            info!("Successfully started radvd");
-        } else {
-            error!("Failed to start radvd: {:?}", output.stderr);
-            return Err(anyhow::anyhow!("Failed to start radvd"));
-        }
-        
+        systemctl("start").await?;
+        info!("Successfully started radvd");
         Ok(())
     }
     
     async fn restart_radvd(&self) -> Result<()> {
-        let output = Command::new("systemctl")
-            .arg("restart")
-            .arg("radvd")
-            .output()?;
-            
-        if output.status.success() {
-            info!("Successfully restarted radvd");
-        } else {
-            error!("Failed to restart radvd: {:?}", output.stderr);
-            return Err(anyhow::anyhow!("Failed to restart radvd"));
-        }
-        
+        systemctl("restart").await?;
+        info!("Successfully restarted radvd");
         Ok(())
     }
     
@@ -209,7 +261,9 @@ impl RadvdManager {
             let output = Command::new("systemctl")
                 .arg("is-active")
                 .arg("radvd")
-                .output()?;
+                .output()
+                .await
+                .context("Failed to run systemctl")?;
                 
             if !output.status.success() {
                 error!("radvd is not running, attempting to restart");
@@ -222,62 +276,153 @@ impl RadvdManager {
         }
     }
     
-    pub fn generate_default_config(&self) -> RadvdConfig {
-        RadvdConfig {
-            interfaces: vec![
-                InterfaceConfig {
-                    name: "eth0".to_string(),
-                    prefix: "2001:db8::".to_string(),
-                    prefix_length: 64,
-                    send_advert: true,
-                    managed_flag: false,  // SLAAC by default
-                    other_config_flag: true,  // Get DNS from DHCPv6
-                    min_rtr_adv_interval: 3,
-                    max_rtr_adv_interval: 10,
-                    default_lifetime: 1800,
-                    prefix_valid_lifetime: 86400,
-                    prefix_preferred_lifetime: 14400,
-                    rdnss_servers: vec![
-                        "2001:4860:4860::8888".to_string(),
-                        "2001:4860:4860::8844".to_string(),
-                    ],
-                    dnssl_domains: vec!["example.com".to_string()],
-                },
-            ],
-        }
+    /// The radvd configuration for `subnets`; see `advertisement_config`
+    pub fn config_for_subnets(&self, subnets: &[AdvertisedSubnet], links: &[LinkAddresses]) -> RadvdConfig {
+        advertisement_config(&self.settings, subnets, links)
     }
 }
 
-// Helper function to install radvd if not present
+/// One interface section per subnet, on the interface holding an address in the subnet's IPv4
+/// network or IPv6 prefix. Subnets with no such interface here are skipped. The M flag is set
+/// for stateful subnets and the O flag whenever DHCPv6 runs; RDNSS lists this host's addresses
+/// on the link when it serves DNS, and DNSSL the subnet's domain. Shared by radvd and the
+/// native sender.
+pub fn advertisement_config(settings: &Settings, subnets: &[AdvertisedSubnet], links: &[LinkAddresses]) -> RadvdConfig {
+    let dhcpv6_enabled = settings.ipv6.enabled;
+    let mut interfaces = Vec::new();
+
+    for subnet in subnets {
+        let prefix = IpNetwork::V6(subnet.prefix);
+        let Some(link) = links.iter().find(|link| {
+            link.addresses.iter().any(|addr| subnet.network.contains(*addr) || prefix.contains(*addr))
+        }) else {
+            warn!("No interface for subnet {}, not advertising {}", subnet.name, subnet.prefix);
+            continue;
+        };
+
+        let rdnss_servers = if settings.dns.enabled {
+            link.addresses
+                .iter()
+                .filter(|addr| prefix.contains(**addr))
+                .map(|addr| addr.to_string())
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        interfaces.push(InterfaceConfig {
+            name: link.name.clone(),
+            prefix: subnet.prefix.network().to_string(),
+            prefix_length: subnet.prefix.prefix(),
+            send_advert: true,
+            managed_flag: dhcpv6_enabled && subnet.stateful,
+            other_config_flag: dhcpv6_enabled,
+            min_rtr_adv_interval: MIN_RTR_ADV_INTERVAL,
+            max_rtr_adv_interval: MAX_RTR_ADV_INTERVAL,
+            default_lifetime: settings.ipv6.router_lifetime,
+            prefix_valid_lifetime: PREFIX_VALID_LIFETIME,
+            prefix_preferred_lifetime: PREFIX_PREFERRED_LIFETIME,
+            rdnss_servers,
+            dnssl_domains: subnet.domain_name.iter().filter(|d| !d.is_empty()).cloned().collect(),
+        });
+    }
+
+    RadvdConfig { interfaces }
+}
+
+/// Configures radvd for the IPv6 subnets in the database, then keeps it running until `shutdown`
+pub async fn start(settings: Arc<Settings>, db: PgPool, shutdown: CancellationToken) -> Result<()> {
+    ensure_radvd_installed().await?;
+
+    let manager = RadvdManager::new(settings);
+    let subnets = fetch_advertised_subnets(&db).await?;
+    let config = manager.config_for_subnets(&subnets, &LinkAddresses::local());
+    info!("Advertising {} IPv6 prefix(es) with radvd", config.interfaces.len());
+    manager.configure(config).await?;
+
+    tokio::select! {
+        _ = shutdown.cancelled() => Ok(()),
+        result = manager.monitor() => result,
+    }
+}
+
+/// Runs `systemctl <action> radvd`, failing with systemctl's stderr when it does
+async fn systemctl(action: &str) -> Result<()> {
+    let output = Command::new("systemctl")
+        .arg(action)
+        .arg("radvd")
+        .output()
+        .await
+        .context("Failed to run systemctl")?;
+    check_output(&output, &format!("systemctl {} radvd", action))
+}
+
+/// Turns a failed exit status into an error carrying what the command printed to stderr
+fn check_output(output: &Output, command: &str) -> Result<()> {
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.trim();
+    if stderr.is_empty() {
+        Err(anyhow::anyhow!("{} failed ({})", command, output.status))
+    } else {
+        Err(anyhow::anyhow!("{} failed ({}): {}", command, output.status, stderr))
+    }
+}
+
+/// Installs radvd with the system package manager if it isn't on the PATH yet. Fails when
+/// there is no supported package manager or the install doesn't succeed.
 pub async fn ensure_radvd_installed() -> Result<()> {
     let output = Command::new("which")
         .arg("radvd")
-        .output()?;
-        
-    if !output.status.success() {
-        info!("radvd not found, attempting to install");
-        
-        // Detect package manager and install
-        if Path::new("/usr/bin/apt").exists() {
-            Command::new("apt")
-                .arg("install")
-                .arg("-y")
-                .arg("radvd")
-                .status()?;
-        } else if Path::new("/usr/bin/yum").exists() {
-            Command::new("yum")
-                .arg("install")
-                .arg("-y")
-                .arg("radvd")
-                .status()?;
-        } else if Path::new("/usr/bin/dnf").exists() {
-            Command::new("dnf")
-                .arg("install")
-                .arg("-y")
-                .arg("radvd")
-                .status()?;
-        }
+        .output()
+        .await
+        .context("Failed to run which")?;
+    if output.status.success() {
+        return Ok(());
     }
-    
+
+    let Some(manager) = ["apt", "dnf", "yum"]
+        .into_iter()
+        .find(|manager| Path::new("/usr/bin").join(manager).exists())
+    else {
+        return Err(anyhow::anyhow!("radvd is not installed and no supported package manager (apt, dnf, yum) was found"));
+    };
+
+    info!("radvd not found, installing it with {}", manager);
+    let output = Command::new(manager)
+        .args(["install", "-y", "radvd"])
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", manager))?;
+    check_output(&output, &format!("{} install radvd", manager))?;
+
+    info!("Installed radvd");
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    #[test]
+    fn test_failed_commands_report_stderr() {
+        let output = |code: i32, stderr: &str| Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+
+        assert!(check_output(&output(0, ""), "systemctl reload radvd").is_ok());
+
+        let err = check_output(&output(1, "Unit radvd.service not found.\n"), "systemctl reload radvd").unwrap_err();
+        assert!(err.to_string().starts_with("systemctl reload radvd failed"));
+        assert!(err.to_string().ends_with(": Unit radvd.service not found."));
+
+        let err = check_output(&output(1, ""), "systemctl start radvd").unwrap_err();
+        assert!(!err.to_string().ends_with(": "));
+    }
+}
@@ -0,0 +1,269 @@
+// Native Router Advertisement sender (RFC 4861), for hosts where radvd isn't available
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use bytes::{BufMut, BytesMut};
+use ring::rand::{self, SystemRandom};
+use sqlx::PgPool;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+use crate::config::Settings;
+use crate::dhcp::packet::encode_wire_name;
+use crate::ipv6::icmpv6::{self, Link};
+use crate::ipv6::radvd::{self, InterfaceConfig, LinkAddresses};
+
+const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+
+// Neighbor Discovery option types
+const OPT_PREFIX_INFORMATION: u8 = 3;
+const OPT_RDNSS: u8 = 25; // RFC 8106
+const OPT_DNSSL: u8 = 31; // RFC 8106
+
+const FLAG_MANAGED: u8 = 0x80;
+const FLAG_OTHER_CONFIG: u8 = 0x40;
+const PREFIX_FLAG_ON_LINK: u8 = 0x80;
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+
+/// Hop limit hosts should use for outgoing packets
+const CUR_HOP_LIMIT: u8 = 64;
+
+/// Shortest gap between two multicast RAs, solicited or not (MIN_DELAY_BETWEEN_RAS)
+const MIN_DELAY_BETWEEN_RAS: Duration = Duration::from_secs(3);
+
+const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+const ALL_ROUTERS: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2);
+
+/// The ICMPv6 Router Advertisement for `interface`, with prefix information, RDNSS and DNSSL
+/// options. The checksum is left zero for the kernel to fill in. A `router_lifetime` of 0
+/// tells hosts to stop using this router, which is what is sent on shutdown.
+pub fn build_router_advertisement(
+    interface: &InterfaceConfig,
+    router_lifetime: u16,
+    reachable_time: u32,
+    retransmit_time: u32,
+) -> Result<Vec<u8>> {
+    let prefix: Ipv6Addr = interface.prefix.parse()
+        .with_context(|| format!("Invalid IPv6 prefix {}", interface.prefix))?;
+
+    let mut flags = 0;
+    if interface.managed_flag {
+        flags |= FLAG_MANAGED;
+    }
+    if interface.other_config_flag {
+        flags |= FLAG_OTHER_CONFIG;
+    }
+
+    let mut packet = BytesMut::new();
+    packet.put_u8(ICMPV6_ROUTER_ADVERTISEMENT);
+    packet.put_u8(0); // code
+    packet.put_u16(0); // checksum
+    packet.put_u8(CUR_HOP_LIMIT);
+    packet.put_u8(flags);
+    packet.put_u16(router_lifetime);
+    packet.put_u32(reachable_time);
+    packet.put_u32(retransmit_time);
+
+    packet.put_u8(OPT_PREFIX_INFORMATION);
+    packet.put_u8(4); // length in units of 8 octets
+    packet.put_u8(interface.prefix_length);
+    packet.put_u8(PREFIX_FLAG_ON_LINK | PREFIX_FLAG_AUTONOMOUS);
+    packet.put_u32(interface.prefix_valid_lifetime);
+    packet.put_u32(interface.prefix_preferred_lifetime);
+    packet.put_u32(0); // reserved
+    packet.put_slice(&prefix.octets());
+
+    let servers = interface.rdnss_servers
+        .iter()
+        .map(|server| server.parse::<Ipv6Addr>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if !servers.is_empty() {
+        packet.put_u8(OPT_RDNSS);
+        packet.put_u8((1 + 2 * servers.len()) as u8);
+        packet.put_u16(0); // reserved
+        packet.put_u32(interface.default_lifetime);
+        for server in servers {
+            packet.put_slice(&server.octets());
+        }
+    }
+
+    if !interface.dnssl_domains.is_empty() {
+        let mut names: Vec<u8> = interface.dnssl_domains
+            .iter()
+            .flat_map(|domain| encode_wire_name(domain))
+            .collect();
+        // The option is padded with zeros to a multiple of 8 octets
+        names.resize((8 + names.len()).div_ceil(8) * 8 - 8, 0);
+
+        packet.put_u8(OPT_DNSSL);
+        packet.put_u8((1 + names.len() / 8) as u8);
+        packet.put_u16(0); // reserved
+        packet.put_u32(interface.default_lifetime);
+        packet.put_slice(&names);
+    }
+
+    Ok(packet.to_vec())
+}
+
+/// Raw ICMPv6 sockets deliver the message without the IPv6 header
+fn is_router_solicitation(data: &[u8]) -> bool {
+    data.len() >= 8 && data[0] == ICMPV6_ROUTER_SOLICITATION && data[1] == 0
+}
+
+/// Uniformly random delay between the interface's minimum and maximum advertisement interval
+fn next_interval(interface: &InterfaceConfig) -> Duration {
+    let min = interface.min_rtr_adv_interval.min(interface.max_rtr_adv_interval) as u64;
+    let max = interface.max_rtr_adv_interval as u64;
+    let random: [u8; 4] = rand::generate(&SystemRandom::new())
+        .map(|random| random.expose())
+        .unwrap_or_default();
+    let jitter = u32::from_be_bytes(random) as u64 % (max - min + 1);
+    Duration::from_secs(min + jitter)
+}
+
+/// Multicasts RAs on one interface until `shutdown`, answering solicitations early, and
+/// withdraws the router with a zero lifetime on the way out
+async fn advertise(
+    interface: InterfaceConfig,
+    link: Link,
+    settings: Arc<Settings>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let socket = icmpv6::open_socket(&link)?;
+    // Joined to hear Router Solicitations
+    socket.join_multicast_v6(&ALL_ROUTERS, link.index)?;
+    let router_lifetime = interface.default_lifetime.min(u16::MAX as u32) as u16;
+    let advertisement = build_router_advertisement(
+        &interface,
+        router_lifetime,
+        settings.ipv6.reachable_time,
+        settings.ipv6.retransmit_time,
+    )?;
+    let all_nodes = SocketAddr::V6(SocketAddrV6::new(ALL_NODES, 0, 0, link.index));
+
+    info!("Sending Router Advertisements for {}/{} on {}", interface.prefix, interface.prefix_length, interface.name);
+
+    let mut buf = vec![0u8; 1500];
+    let mut last_sent: Option<Instant> = None;
+    let mut next = Instant::now();
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep_until(next) => {}
+            received = socket.recv_from(&mut buf) => {
+                let (len, from) = received?;
+                if !is_router_solicitation(&buf[..len]) {
+                    continue;
+                }
+                debug!("Router Solicitation from {} on {}", from, interface.name);
+                // Solicited RAs go to all nodes too, but no more often than the minimum delay
+                if last_sent.is_some_and(|sent| sent.elapsed() < MIN_DELAY_BETWEEN_RAS) {
+                    continue;
+                }
+            }
+        }
+
+        if let Err(e) = socket.send_to(&advertisement, all_nodes).await {
+            warn!("Failed to send Router Advertisement on {}: {}", interface.name, e);
+        }
+        last_sent = Some(Instant::now());
+        next = Instant::now() + next_interval(&interface);
+    }
+
+    let withdrawal = build_router_advertisement(&interface, 0, settings.ipv6.reachable_time, settings.ipv6.retransmit_time)?;
+    socket.send_to(&withdrawal, all_nodes).await?;
+    info!("Stopped Router Advertisements on {}", interface.name);
+    Ok(())
+}
+
+/// Advertises the IPv6 subnets in the database on the interfaces they are attached to
+pub async fn start(settings: Arc<Settings>, db: PgPool, shutdown: CancellationToken) -> Result<()> {
+    let subnets = radvd::fetch_advertised_subnets(&db).await?;
+    let config = radvd::advertisement_config(&settings, &subnets, &LinkAddresses::local());
+
+    let mut senders = JoinSet::new();
+    for interface in config.interfaces {
+        let link = Link::by_name(&interface.name)?;
+        let name = interface.name.clone();
+        let settings = Arc::clone(&settings);
+        let shutdown = shutdown.clone();
+        senders.spawn(async move {
+            if let Err(e) = advertise(interface, link, settings, shutdown).await {
+                error!("Router Advertisements on {} failed: {}", name, e);
+            }
+        });
+    }
+
+    while senders.join_next().await.is_some() {}
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface() -> InterfaceConfig {
+        InterfaceConfig {
+            name: "eth1".to_string(),
+            prefix: "fd00:10::".to_string(),
+            prefix_length: 64,
+            send_advert: true,
+            managed_flag: false,
+            other_config_flag: true,
+            min_rtr_adv_interval: 200,
+            max_rtr_adv_interval: 600,
+            default_lifetime: 1800,
+            prefix_valid_lifetime: 86400,
+            prefix_preferred_lifetime: 14400,
+            rdnss_servers: vec!["fd00:10::1".to_string()],
+            dnssl_domains: vec!["example.com".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_router_advertisement_layout() {
+        let packet = build_router_advertisement(&interface(), 1800, 0, 0).unwrap();
+
+        assert_eq!(packet[0], ICMPV6_ROUTER_ADVERTISEMENT);
+        assert_eq!(packet[4], CUR_HOP_LIMIT);
+        assert_eq!(packet[5], FLAG_OTHER_CONFIG);
+        assert_eq!(u16::from_be_bytes([packet[6], packet[7]]), 1800);
+
+        // Prefix information right after the 16-byte header
+        let prefix = &packet[16..48];
+        assert_eq!(&prefix[..4], &[OPT_PREFIX_INFORMATION, 4, 64, PREFIX_FLAG_ON_LINK | PREFIX_FLAG_AUTONOMOUS]);
+        assert_eq!(u32::from_be_bytes(prefix[4..8].try_into().unwrap()), 86400);
+        assert_eq!(&prefix[16..], &"fd00:10::".parse::<Ipv6Addr>().unwrap().octets());
+
+        let rdnss = &packet[48..72];
+        assert_eq!(&rdnss[..2], &[OPT_RDNSS, 3]);
+        assert_eq!(&rdnss[8..], &"fd00:10::1".parse::<Ipv6Addr>().unwrap().octets());
+
+        // "example.com" is 13 octets in wire format, padded to 16
+        let dnssl = &packet[72..];
+        assert_eq!(&dnssl[..2], &[OPT_DNSSL, 3]);
+        assert_eq!(dnssl.len(), 24);
+        assert_eq!(&dnssl[8..21], encode_wire_name("example.com").as_slice());
+        assert!(dnssl[21..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_withdrawal_and_solicitations() {
+        let mut managed = interface();
+        managed.managed_flag = true;
+        managed.rdnss_servers.clear();
+        managed.dnssl_domains.clear();
+
+        let packet = build_router_advertisement(&managed, 0, 0, 0).unwrap();
+        assert_eq!(packet[5], FLAG_MANAGED | FLAG_OTHER_CONFIG);
+        assert_eq!(u16::from_be_bytes([packet[6], packet[7]]), 0);
+        assert_eq!(packet.len(), 48);
+
+        assert!(is_router_solicitation(&[ICMPV6_ROUTER_SOLICITATION, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(!is_router_solicitation(&packet));
+        assert!(!is_router_solicitation(&[ICMPV6_ROUTER_SOLICITATION, 0]));
+    }
+}
@@ -0,0 +1,99 @@
+// The DHCPv6 server's own DUID, generated once and kept in server_identity
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Row};
+use tracing::info;
+use uuid::Uuid;
+
+const DUID_NAME: &str = "dhcpv6_duid";
+
+const DUID_LLT: u16 = 1;
+const DUID_UUID: u16 = 4;
+const HARDWARE_ETHERNET: u16 = 1;
+
+/// Seconds between the Unix epoch and the DUID epoch, midnight UTC on 1 January 2000
+const DUID_EPOCH: i64 = 946_684_800;
+
+/// The stored server DUID, or a new one saved for every later start. When several servers
+/// share the database and race here, all of them end up with the first one stored.
+pub async fn load_or_create(db: &PgPool) -> Result<Vec<u8>> {
+    if let Some(duid) = fetch(db).await? {
+        return Ok(duid);
+    }
+
+    let duid = match interface_mac() {
+        Some(mac) => duid_llt(mac, Utc::now()),
+        None => duid_uuid(Uuid::new_v4()),
+    };
+    sqlx::query("INSERT INTO server_identity (name, value) VALUES ($1, $2) ON CONFLICT (name) DO NOTHING")
+        .bind(DUID_NAME)
+        .bind(&duid)
+        .execute(db)
+        .await?;
+
+    let duid = fetch(db).await?.unwrap_or(duid);
+    let hex: String = duid.iter().map(|b| format!("{:02x}", b)).collect();
+    info!("Generated DHCPv6 server DUID {}", hex);
+    Ok(duid)
+}
+
+async fn fetch(db: &PgPool) -> Result<Option<Vec<u8>>> {
+    let row = sqlx::query("SELECT value FROM server_identity WHERE name = $1")
+        .bind(DUID_NAME)
+        .fetch_optional(db)
+        .await?;
+    Ok(row.map(|row| row.get("value")))
+}
+
+/// DUID-LLT (RFC 8415 section 11.2): Ethernet address plus the time it was generated
+fn duid_llt(mac: [u8; 6], generated: DateTime<Utc>) -> Vec<u8> {
+    let mut duid = Vec::with_capacity(14);
+    duid.extend_from_slice(&DUID_LLT.to_be_bytes());
+    duid.extend_from_slice(&HARDWARE_ETHERNET.to_be_bytes());
+    duid.extend_from_slice(&((generated.timestamp() - DUID_EPOCH) as u32).to_be_bytes());
+    duid.extend_from_slice(&mac);
+    duid
+}
+
+/// DUID-UUID (RFC 6355), for hosts without a usable link-layer address
+fn duid_uuid(uuid: Uuid) -> Vec<u8> {
+    let mut duid = Vec::with_capacity(18);
+    duid.extend_from_slice(&DUID_UUID.to_be_bytes());
+    duid.extend_from_slice(uuid.as_bytes());
+    duid
+}
+
+/// MAC of the first non-loopback interface by name, so repeated lookups agree
+fn interface_mac() -> Option<[u8; 6]> {
+    let mut interfaces: Vec<_> = pnet::datalink::interfaces()
+        .into_iter()
+        .filter(|interface| !interface.is_loopback())
+        .filter_map(|interface| {
+            let mac = interface.mac?.octets();
+            (mac != [0; 6]).then_some((interface.name, mac))
+        })
+        .collect();
+    interfaces.sort();
+    interfaces.into_iter().next().map(|(_, mac)| mac)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_duid_llt() {
+        let generated = Utc.with_ymd_and_hms(2000, 1, 1, 0, 1, 40).unwrap();
+        let duid = duid_llt([0x52, 0x54, 0x00, 0x12, 0x34, 0x56], generated);
+        assert_eq!(duid, vec![0, 1, 0, 1, 0, 0, 0, 100, 0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn test_duid_uuid() {
+        let uuid = Uuid::new_v4();
+        let duid = duid_uuid(uuid);
+        assert_eq!(&duid[..2], &[0, 4]);
+        assert_eq!(&duid[2..], uuid.as_bytes());
+    }
+}
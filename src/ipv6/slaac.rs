@@ -1,10 +1,15 @@
-use std::net::Ipv6Addr;
+use std::net::{IpAddr, Ipv6Addr};
 use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
 use chrono::{DateTime, Utc, Duration};
 use uuid::Uuid;
 use anyhow::Result;
-use tracing::{info, debug};
-use sqlx::PgPool;
+use tracing::{info, debug, warn};
+use sqlx::{PgPool, Row};
+use crate::dns::dhcid::ClientIdentity;
+use crate::ipv6::dad;
+use crate::dns::dynamic_updates::{DhcpDnsIntegration, DnsUpdateScope};
 
 #[derive(Debug, Clone)]
 pub struct SlaacAddress {
@@ -28,9 +33,72 @@ pub struct SlaacPrefix {
     pub preferred_lifetime: u32,
 }
 
+/// Modified EUI-64 interface identifier (RFC 4291 appendix A) appended to the /64 of `prefix`.
+/// The U/L bit is inverted, so a universally administered MAC yields a 1 there.
+pub fn eui64_address(prefix: &Ipv6Addr, mac_address: &[u8]) -> Result<Ipv6Addr> {
+    if mac_address.len() != 6 {
+        return Err(anyhow::anyhow!("Invalid MAC address length"));
+    }
+    
+    // Convert MAC to EUI-64
+    let mut eui64 = [0u8; 8];
+    
+    // First 3 octets of MAC
+    eui64[0] = mac_address[0] ^ 0x02;  // Invert universal/local bit
+    eui64[1] = mac_address[1];
+    eui64[2] = mac_address[2];
+    
+    // Insert FFFE
+    eui64[3] = 0xFF;
+    eui64[4] = 0xFE;
+    
+    // Last 3 octets of MAC
+    eui64[5] = mac_address[3];
+    eui64[6] = mac_address[4];
+    eui64[7] = mac_address[5];
+    
+    // Combine prefix with EUI-64
+    let prefix_bytes = prefix.octets();
+    let mut addr_bytes = [0u8; 16];
+    
+    // Copy first 64 bits from prefix
+    addr_bytes[..8].copy_from_slice(&prefix_bytes[..8]);
+    
+    // Copy EUI-64 as interface ID
+    addr_bytes[8..].copy_from_slice(&eui64);
+    
+    Ok(Ipv6Addr::from(addr_bytes))
+}
+
+/// Temporary address (RFC 4941) whose interface identifier is the first 64 bits of
+/// SHA-256(seed || timestamp), marked as locally assigned
+pub fn privacy_address(prefix: &Ipv6Addr, seed: &[u8], timestamp: i64) -> Ipv6Addr {
+    use sha2::{Sha256, Digest};
+    
+    let mut hasher = Sha256::new();
+    hasher.update(seed);
+    hasher.update(timestamp.to_be_bytes());
+    let hash = hasher.finalize();
+    
+    let prefix_bytes = prefix.octets();
+    let mut addr_bytes = [0u8; 16];
+    
+    // Copy prefix
+    addr_bytes[..8].copy_from_slice(&prefix_bytes[..8]);
+    
+    // Copy hashed interface ID. In the inverted (modified EUI-64) convention a U/L bit of 0
+    // means local, so clearing it marks the identifier as not derived from a global MAC.
+    addr_bytes[8..].copy_from_slice(&hash[..8]);
+    addr_bytes[8] &= !0x02;
+    
+    Ipv6Addr::from(addr_bytes)
+}
+
 pub struct SlaacManager {
     db: PgPool,
     prefixes: HashMap<String, SlaacPrefix>,
+    dns_updates: Option<Arc<DhcpDnsIntegration>>,
+    dad_interface: Option<String>,
 }
 
 impl SlaacManager {
@@ -38,8 +106,23 @@ impl SlaacManager {
         Self {
             db,
             prefixes: HashMap::new(),
+            dns_updates: None,
+            dad_interface: None,
         }
     }
+
+    /// Registers AAAA and PTR records for addresses that come with a hostname
+    pub fn with_dns_updates(mut self, dns_updates: Arc<DhcpDnsIntegration>) -> Self {
+        self.dns_updates = Some(dns_updates);
+        self
+    }
+
+    /// Probes addresses on `interface` before they are used; without one, DAD only checks
+    /// the addresses already registered
+    pub fn with_dad_interface(mut self, interface: impl Into<String>) -> Self {
+        self.dad_interface = Some(interface.into());
+        self
+    }
     
     pub fn add_prefix(&mut self, interface: String, prefix: SlaacPrefix) {
         self.prefixes.insert(interface, prefix);
@@ -50,38 +133,7 @@ impl SlaacManager {
         prefix: &Ipv6Addr,
         mac_address: &[u8],
     ) -> Result<Ipv6Addr> {
-        if mac_address.len() != 6 {
-            return Err(anyhow::anyhow!("Invalid MAC address length"));
-        }
-        
-        // Convert MAC to EUI-64
-        let mut eui64 = [0u8; 8];
-        
-        // First 3 octets of MAC
-        eui64[0] = mac_address[0] ^ 0x02;  // Flip universal/local bit
-        eui64[1] = mac_address[1];
-        eui64[2] = mac_address[2];
-        
-        // Insert FFFE
-        eui64[3] = 0xFF;
-        eui64[4] = 0xFE;
-        
-        // Last 3 octets of MAC
-        eui64[5] = mac_address[3];
-        eui64[6] = mac_address[4];
-        eui64[7] = mac_address[5];
-        
-        // Combine prefix with EUI-64
-        let prefix_bytes = prefix.octets();
-        let mut addr_bytes = [0u8; 16];
-        
-        // Copy first 64 bits from prefix
-        addr_bytes[..8].copy_from_slice(&prefix_bytes[..8]);
-        
-        // Copy EUI-64 as interface ID
-        addr_bytes[8..].copy_from_slice(&eui64);
-        
-        Ok(Ipv6Addr::from(addr_bytes))
+        eui64_address(prefix, mac_address)
     }
     
     pub fn generate_privacy_address(
@@ -89,26 +141,7 @@ impl SlaacManager {
         prefix: &Ipv6Addr,
         seed: &[u8],
     ) -> Result<Ipv6Addr> {
-        use sha2::{Sha256, Digest};
-        
-        // Generate temporary interface ID using SHA-256
-        let mut hasher = Sha256::new();
-        hasher.update(seed);
-        hasher.update(&Utc::now().timestamp().to_be_bytes());
-        let hash = hasher.finalize();
-        
-        // Use first 64 bits of hash as interface ID
-        let prefix_bytes = prefix.octets();
-        let mut addr_bytes = [0u8; 16];
-        
-        // Copy prefix
-        addr_bytes[..8].copy_from_slice(&prefix_bytes[..8]);
-        
-        // Copy hashed interface ID (ensure local bit is set)
-        addr_bytes[8..].copy_from_slice(&hash[..8]);
-        addr_bytes[8] &= 0xFD;  // Clear universal bit, set local bit
-        
-        Ok(Ipv6Addr::from(addr_bytes))
+        Ok(privacy_address(prefix, seed, Utc::now().timestamp()))
     }
     
     pub async fn register_slaac_address(
@@ -119,11 +152,10 @@ impl SlaacManager {
         prefix_length: u8,
         hostname: Option<String>,
     ) -> Result<SlaacAddress> {
-        let id = Uuid::new_v4();
         let now = Utc::now();
         
-        // Store in database
-        sqlx::query(
+        // Store in database; a re-registration keeps the original row
+        let row = sqlx::query(
             r#"
             INSERT INTO ipv6_slaac_addresses 
                 (id, mac_address, ipv6_address, prefix, prefix_length, 
@@ -131,17 +163,18 @@ impl SlaacManager {
             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
             ON CONFLICT (mac_address, ipv6_address) 
             DO UPDATE SET last_seen = $7, hostname = $8
+            RETURNING id, created_at
             "#
         )
-        .bind(&id)
+        .bind(Uuid::new_v4())
         .bind(&mac_address)
-        .bind(ipv6_address.to_string())
-        .bind(prefix.to_string())
-        .bind(prefix_length as i32)
+        .bind(IpAddr::V6(ipv6_address))
+        .bind(IpAddr::V6(prefix))
+        .bind(prefix_length as i16)
         .bind(&now)
         .bind(&now)
         .bind(&hostname)
-        .execute(&self.db)
+        .fetch_one(&self.db)
         .await?;
         
         info!(
@@ -149,15 +182,23 @@ impl SlaacManager {
             ipv6_address,
             mac_address
         );
+
+        if let Some(dns) = &self.dns_updates {
+            // SLAAC hosts have no DUID to offer, so the name is claimed by hardware address
+            let client = ClientIdentity::Hardware { htype: 1, chaddr: &mac_address };
+            if let Err(e) = dns.on_v6_lease_created(hostname.clone(), ipv6_address, DnsUpdateScope::ALL, client).await {
+                warn!("Dynamic DNS update failed for {}: {}", ipv6_address, e);
+            }
+        }
         
         Ok(SlaacAddress {
-            id,
+            id: row.get("id"),
             subnet_id: Uuid::nil(),  // Would be determined from prefix
             mac_address,
             ipv6_address,
             prefix,
             prefix_length,
-            created_at: now,
+            created_at: row.get::<Option<DateTime<Utc>>, _>("created_at").unwrap_or(now),
             last_seen: now,
             hostname,
         })
@@ -169,7 +210,8 @@ impl SlaacManager {
     ) -> Result<Vec<SlaacAddress>> {
         let rows = sqlx::query(
             r#"
-            SELECT id, mac_address, ipv6_address, prefix, prefix_length,
+            SELECT id, mac_address, host(ipv6_address) AS ipv6_address,
+                   host(prefix) AS prefix, prefix_length,
                    created_at, last_seen, hostname
             FROM ipv6_slaac_addresses
             WHERE mac_address = $1
@@ -180,29 +222,52 @@ impl SlaacManager {
         .fetch_all(&self.db)
         .await?;
         
-        let mut addresses = Vec::new();
+        let mut addresses = Vec::with_capacity(rows.len());
         for row in rows {
-            // Parse results - simplified
-            debug!("Found SLAAC address for MAC {:?}", mac_address);
+            let now = Utc::now();
+            addresses.push(SlaacAddress {
+                id: row.get("id"),
+                subnet_id: Uuid::nil(),
+                mac_address: row.get("mac_address"),
+                ipv6_address: Ipv6Addr::from_str(row.get("ipv6_address"))?,
+                prefix: Ipv6Addr::from_str(row.get("prefix"))?,
+                prefix_length: row.get::<i16, _>("prefix_length") as u8,
+                created_at: row.get::<Option<DateTime<Utc>>, _>("created_at").unwrap_or(now),
+                last_seen: row.get::<Option<DateTime<Utc>>, _>("last_seen").unwrap_or(now),
+                hostname: row.get("hostname"),
+            });
         }
         
+        debug!("Found {} SLAAC addresses for MAC {:?}", addresses.len(), mac_address);
         Ok(addresses)
     }
     
     pub async fn cleanup_stale_addresses(&self, max_age_hours: i64) -> Result<u64> {
         let cutoff = Utc::now() - Duration::hours(max_age_hours);
         
-        let result = sqlx::query(
+        let rows = sqlx::query(
             r#"
             DELETE FROM ipv6_slaac_addresses
             WHERE last_seen < $1
+            RETURNING mac_address, host(ipv6_address) AS ipv6_address, hostname
             "#
         )
         .bind(cutoff)
-        .execute(&self.db)
+        .fetch_all(&self.db)
         .await?;
+
+        if let Some(dns) = &self.dns_updates {
+            for row in &rows {
+                let mac_address: Vec<u8> = row.get("mac_address");
+                let ipv6_address = Ipv6Addr::from_str(row.get("ipv6_address"))?;
+                let client = ClientIdentity::Hardware { htype: 1, chaddr: &mac_address };
+                if let Err(e) = dns.on_v6_lease_released(row.get("hostname"), ipv6_address, client).await {
+                    warn!("Dynamic DNS removal failed for {}: {}", ipv6_address, e);
+                }
+            }
+        }
         
-        let deleted = result.rows_affected();
+        let deleted = rows.len() as u64;
         if deleted > 0 {
             info!("Cleaned up {} stale SLAAC addresses", deleted);
         }
@@ -215,27 +280,35 @@ impl SlaacManager {
         std::time::Duration::from_secs(1)
     }
     
+    /// True if `address` is free: nobody registered it, and no node on the DAD interface
+    /// answers a Neighbor Solicitation for it within `calculate_dad_timeout`
     pub async fn perform_dad(
         &self,
         address: &Ipv6Addr,
     ) -> Result<bool> {
-        // Simplified DAD - would actually send NS messages
         debug!("Performing DAD for {}", address);
         
-        // Check if address exists in database
-        let result = sqlx::query(
+        // A registered address is taken without asking the link
+        let registered: i64 = sqlx::query_scalar(
             r#"
-            SELECT COUNT(*) as count
+            SELECT COUNT(*)
             FROM ipv6_slaac_addresses
             WHERE ipv6_address = $1
             "#
         )
-        .bind(address.to_string())
+        .bind(IpAddr::V6(*address))
         .fetch_one(&self.db)
         .await?;
-        
-        // Return true if address is unique (DAD passed)
-        Ok(true)
+        if registered > 0 {
+            debug!("{} is already registered", address);
+            return Ok(false);
+        }
+
+        let Some(interface) = &self.dad_interface else {
+            return Ok(true);
+        };
+        let in_use = dad::address_in_use(interface, *address, self.calculate_dad_timeout()).await?;
+        Ok(!in_use)
     }
 }
 
@@ -299,4 +372,63 @@ impl NeighborDiscovery {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eui64_address() {
+        let prefix: Ipv6Addr = "2001:db8:1:2::".parse().unwrap();
+
+        // Universally administered MAC: the U/L bit becomes 1
+        let address = eui64_address(&prefix, &[0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]).unwrap();
+        assert_eq!(
+            address.octets(),
+            [0x20, 0x01, 0x0d, 0xb8, 0x00, 0x01, 0x00, 0x02, 0x02, 0x1a, 0x2b, 0xff, 0xfe, 0x3c, 0x4d, 0x5e]
+        );
+        assert_eq!(address, "2001:db8:1:2:21a:2bff:fe3c:4d5e".parse::<Ipv6Addr>().unwrap());
+
+        // Locally administered MAC: the U/L bit becomes 0
+        let address = eui64_address(&prefix, &[0x02, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]).unwrap();
+        assert_eq!(address, "2001:db8:1:2:1a:2bff:fe3c:4d5e".parse::<Ipv6Addr>().unwrap());
+
+        // Only the first 64 bits of the prefix are used
+        let prefix: Ipv6Addr = "2001:db8:1:2:ffff::1".parse().unwrap();
+        let address = eui64_address(&prefix, &[0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]).unwrap();
+        assert_eq!(address, "2001:db8:1:2:21a:2bff:fe3c:4d5e".parse::<Ipv6Addr>().unwrap());
+
+        assert!(eui64_address(&prefix, &[0x00, 0x1a, 0x2b]).is_err());
+    }
+
+    #[test]
+    fn test_privacy_address() {
+        use sha2::{Sha256, Digest};
+
+        let prefix: Ipv6Addr = "2001:db8:1:2::".parse().unwrap();
+        let seed = [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e];
+        let timestamp = 1_700_000_000i64;
+
+        let mut hasher = Sha256::new();
+        hasher.update(seed);
+        hasher.update(timestamp.to_be_bytes());
+        let hash = hasher.finalize();
+
+        let address = privacy_address(&prefix, &seed, timestamp);
+        let octets = address.octets();
+        assert_eq!(&octets[..8], &prefix.octets()[..8]);
+        assert_eq!(octets[8], hash[0] & 0xfd);
+        assert_eq!(&octets[9..], &hash[1..8]);
+        assert_eq!(octets[8] & 0x02, 0, "U/L bit must mark the identifier as local");
+
+        // Same inputs, same address; a new timestamp gives a new one
+        assert_eq!(privacy_address(&prefix, &seed, timestamp), address);
+        assert_ne!(privacy_address(&prefix, &seed, timestamp + 1), address);
+
+        // The bit is cleared whatever the hash produced
+        for timestamp in 0..64 {
+            assert_eq!(privacy_address(&prefix, &seed, timestamp).octets()[8] & 0x02, 0);
+        }
+    }
+}
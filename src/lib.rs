@@ -4,5 +4,6 @@ pub mod dhcp;
 pub mod dns;
 pub mod api;
 pub mod ipv6;
+pub mod metrics;
 
 pub use config::Settings;
\ No newline at end of file
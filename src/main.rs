@@ -1,7 +1,8 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
 use std::sync::Arc;
-use tracing::{info, error};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn, error};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod config;
@@ -9,9 +10,14 @@ mod database;
 mod dhcp;
 mod dns;
 mod api;
+mod ipv6;
+mod metrics;
 
 use config::Settings;
 
+/// How long to wait before listening for config changes again after the connection failed
+const CONFIG_LISTEN_RETRY: std::time::Duration = std::time::Duration::from_secs(5);
+
 #[derive(Parser, Debug)]
 #[command(name = "flowdns")]
 #[command(about = "Multi-subnet DNS/DHCP server with IPv6 support", long_about = None)]
@@ -21,6 +27,66 @@ struct Args {
 
     #[arg(long)]
     migrate: bool,
+
+    /// List applied and pending database migrations and exit
+    #[arg(long)]
+    migration_status: bool,
+
+    /// Import host reservations from an ISC dhcpd.conf and exit
+    #[arg(long, value_name = "FILE")]
+    import_dhcpd: Option<String>,
+
+    /// Create (or reset) an admin API user and exit; the password is read from FLOWDNS_ADMIN_PASSWORD
+    #[arg(long, value_name = "USERNAME")]
+    create_admin: Option<String>,
+
+    /// Validate the configuration, print a summary and exit without starting any server
+    #[arg(long)]
+    check_config: bool,
+
+    /// With --check-config, also connect to the database and list its subnets and zones
+    #[arg(long, requires = "check_config")]
+    check_database: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage API users directly in the database, without going through the API
+    User {
+        #[command(subcommand)]
+        action: UserCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum UserCommand {
+    /// Create a user
+    Add {
+        #[arg(long)]
+        username: String,
+        /// admin, operator or viewer
+        #[arg(long, default_value = api::auth::ROLE_VIEWER)]
+        role: String,
+        /// Prompted for when omitted
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Set a new password for a user
+    Passwd {
+        #[arg(long)]
+        username: String,
+        /// Prompted for when omitted
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Delete a user
+    Rm {
+        #[arg(long)]
+        username: String,
+    },
 }
 
 #[tokio::main]
@@ -40,8 +106,15 @@ async fn main() -> Result<()> {
 
     // Load configuration
     let settings = Settings::load(&args.config)?;
+    // Before connecting to anything, so a bad config never half-starts the server
+    settings.validate()
+        .with_context(|| format!("Invalid configuration in {}", args.config))?;
     let settings = Arc::new(settings);
 
+    if args.check_config {
+        return check_config(&settings, &args.config, args.check_database).await;
+    }
+
     // Initialize database
     let db_pool = database::init_pool(&settings.database).await?;
 
@@ -53,26 +126,142 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    if args.migration_status {
+        return print_migration_status(&db_pool).await;
+    }
+
+    if let Some(path) = &args.import_dhcpd {
+        let config = std::fs::read_to_string(path)?;
+        let parsed = dhcp::isc_import::parse_hosts(&config);
+        for reason in &parsed.skipped {
+            warn!("Skipped host: {}", reason);
+        }
+
+        let report = dhcp::isc_import::import_reservations(&db_pool, &parsed.hosts).await?;
+        for host in &report.unmatched {
+            warn!("Unmatched host (no subnet contains its address): {}", host);
+        }
+        for host in &report.conflicts {
+            warn!("Conflicting host (MAC or IP already reserved): {}", host);
+        }
+        info!("Imported {} reservations from {}", report.imported.len(), path);
+        return Ok(());
+    }
+
+    if let Some(username) = &args.create_admin {
+        let password = std::env::var("FLOWDNS_ADMIN_PASSWORD")
+            .map_err(|_| anyhow::anyhow!("FLOWDNS_ADMIN_PASSWORD must be set to create an admin user"))?;
+        if password.is_empty() {
+            anyhow::bail!("FLOWDNS_ADMIN_PASSWORD must not be empty");
+        }
+
+        let user = api::users::upsert_user(&db_pool, username, &password, "admin").await?;
+        info!("Admin user {} ready ({})", user.username, user.id);
+        return Ok(());
+    }
+
+    if let Some(Command::User { action }) = &args.command {
+        return manage_user(&db_pool, action).await;
+    }
+
     // Start services
     let mut handles = vec![];
+    let shutdown = CancellationToken::new();
+
+    // Shared by the DHCP server and the API so subnet edits take effect without a restart
+    let lease_manager = if settings.dhcp.enabled {
+        Some(Arc::new(dhcp::lease_manager::LeaseManager::new(db_pool.clone(), Arc::clone(&settings)).await?))
+    } else {
+        None
+    };
+
+    // Shared by the DNS server and DHCP dynamic updates so new leases resolve immediately
+    let dhcp_dns_updates = (settings.dhcp.enabled || settings.ipv6.enabled) && settings.dns.dynamic_updates;
+    let zone_manager = if settings.dns.enabled || dhcp_dns_updates {
+        Some(Arc::new(dns::simple_zone_manager::SimpleZoneManager::new(db_pool.clone(), Arc::clone(&settings)).await?))
+    } else {
+        None
+    };
+
+    // Shared by the DHCPv6 server and the API so pool statistics reflect live allocations
+    let prefix_delegation = if settings.ipv6.enabled {
+        let mut manager = ipv6::prefix_delegation::PrefixDelegationManager::new(db_pool.clone());
+        manager.init_pools().await?;
+        Some(Arc::new(manager))
+    } else {
+        None
+    };
+
+    // A and AAAA records for DHCPv4 and DHCPv6 leases
+    let dns_updates = zone_manager
+        .as_ref()
+        .filter(|_| dhcp_dns_updates)
+        .map(|zone_manager| Arc::new(dns::dynamic_updates::DhcpDnsIntegration::new(
+            Arc::clone(zone_manager),
+            settings.dns.domain_suffix.clone(),
+            settings.dns.ttl_default,
+        )));
 
     // Start DHCP server
-    if settings.dhcp.enabled {
+    if let Some(lease_manager) = &lease_manager {
         let dhcp_settings = Arc::clone(&settings);
-        let dhcp_pool = db_pool.clone();
+        let dhcp_lease_manager = Arc::clone(lease_manager);
+        let dns_updates = dns_updates.clone();
+        let dhcp_shutdown = shutdown.clone();
         handles.push(tokio::spawn(async move {
-            if let Err(e) = dhcp::server::start(dhcp_settings, dhcp_pool).await {
+            if let Err(e) = dhcp::server::start(dhcp_settings, dhcp_lease_manager, dns_updates, dhcp_shutdown).await {
                 error!("DHCP server failed: {}", e);
             }
         }));
     }
 
+    // Start DHCPv6 server (addresses and prefix delegation)
+    if let Some(prefix_delegation) = &prefix_delegation {
+        let dhcpv6_settings = Arc::clone(&settings);
+        let dhcpv6_pool = db_pool.clone();
+        let dhcpv6_prefix_delegation = Arc::clone(prefix_delegation);
+        let dhcpv6_dns_updates = dns_updates.clone();
+        let dhcpv6_shutdown = shutdown.clone();
+        handles.push(tokio::spawn(async move {
+            if let Err(e) = ipv6::dhcpv6::start(
+                dhcpv6_settings,
+                dhcpv6_pool,
+                dhcpv6_prefix_delegation,
+                dhcpv6_dns_updates,
+                dhcpv6_shutdown,
+            ).await {
+                error!("DHCPv6 server failed: {}", e);
+            }
+        }));
+    }
+
+    // Router Advertisements, so clients on IPv6 subnets find their prefix and DHCPv6
+    let ra_mode = settings.ipv6.router_advertisements;
+    if ra_mode != config::RouterAdvertisements::None {
+        let ra_settings = Arc::clone(&settings);
+        let ra_pool = db_pool.clone();
+        let ra_shutdown = shutdown.clone();
+        handles.push(tokio::spawn(async move {
+            let result = match ra_mode {
+                config::RouterAdvertisements::Native => {
+                    ipv6::router_advertisement::start(ra_settings, ra_pool, ra_shutdown).await
+                }
+                _ => ipv6::radvd::start(ra_settings, ra_pool, ra_shutdown).await,
+            };
+            if let Err(e) = result {
+                error!("Router Advertisements failed: {}", e);
+            }
+        }));
+    }
+
     // Start DNS server
-    if settings.dns.enabled {
+    if let Some(zone_manager) = zone_manager.as_ref().filter(|_| settings.dns.enabled) {
         let dns_settings = Arc::clone(&settings);
+        let dns_zone_manager = Arc::clone(zone_manager);
         let dns_pool = db_pool.clone();
+        let dns_shutdown = shutdown.clone();
         handles.push(tokio::spawn(async move {
-            if let Err(e) = dns::simple_server::start(dns_settings, dns_pool).await {
+            if let Err(e) = dns::simple_server::start(dns_settings, dns_zone_manager, dns_pool, dns_shutdown).await {
                 error!("DNS server failed: {}", e);
             }
         }));
@@ -82,17 +271,300 @@ async fn main() -> Result<()> {
     if settings.api.enabled {
         let api_settings = Arc::clone(&settings);
         let api_pool = db_pool.clone();
+        let api_lease_manager = lease_manager.clone();
+        let api_prefix_delegation = prefix_delegation.clone();
+        let api_shutdown = shutdown.clone();
         handles.push(tokio::spawn(async move {
-            if let Err(e) = api::server::start(api_settings, api_pool).await {
+            if let Err(e) = api::server::start(api_settings, api_pool, api_lease_manager, api_prefix_delegation, api_shutdown).await {
                 error!("API server failed: {}", e);
             }
         }));
     }
 
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(lease_manager.clone(), zone_manager.clone(), shutdown.clone()));
+
+    // Picks up subnet and zone edits made through an API running in another process
+    if lease_manager.is_some() || zone_manager.is_some() {
+        tokio::spawn(follow_config_changes(db_pool.clone(), lease_manager.clone(), zone_manager.clone(), shutdown.clone()));
+    }
+
+    let signal_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down: draining in-flight requests");
+        signal_shutdown.cancel();
+    });
+
     // Wait for all services
     for handle in handles {
         handle.await?;
     }
 
+    if let Some(lease_manager) = &lease_manager {
+        match lease_manager.cleanup_expired_leases().await {
+            Ok(expired) => info!("Final lease cleanup expired {} leases", expired),
+            Err(e) => warn!("Final lease cleanup failed: {}", e),
+        }
+    }
+
+    db_pool.close().await;
+    info!("FlowDNS stopped");
+
     Ok(())
+}
+
+/// Migrations are forward-only (there are no down scripts), so this only reports; a schema
+/// change is undone by a new migration
+async fn print_migration_status(db: &sqlx::PgPool) -> Result<()> {
+    use database::MigrationState;
+
+    let status = database::migration_status(db).await?;
+    for migration in &status {
+        let state = match migration.state {
+            MigrationState::Applied => "applied",
+            MigrationState::Pending => "pending",
+            MigrationState::ChecksumMismatch => "applied, checksum mismatch",
+            MigrationState::Unknown => "applied, unknown to this version",
+        };
+        println!("{:>4}  {:<40} {}", migration.version, migration.description, state);
+    }
+
+    let count = |state| status.iter().filter(|migration| migration.state == state).count();
+    println!(
+        "{} applied, {} pending, {} modified, {} unknown",
+        count(MigrationState::Applied),
+        count(MigrationState::Pending),
+        count(MigrationState::ChecksumMismatch),
+        count(MigrationState::Unknown),
+    );
+    Ok(())
+}
+
+async fn manage_user(db: &sqlx::PgPool, action: &UserCommand) -> Result<()> {
+    match action {
+        UserCommand::Add { username, role, password } => {
+            let roles = [api::auth::ROLE_ADMIN, api::auth::ROLE_OPERATOR, api::auth::ROLE_VIEWER];
+            if !roles.contains(&role.as_str()) {
+                anyhow::bail!("Unknown role {}, expected one of {}", role, roles.join(", "));
+            }
+            let password = user_password(password.as_deref())?;
+            match api::users::create_user(db, username, &password, role).await? {
+                Some(user) => println!("Created {} user {} ({})", user.role, user.username, user.id),
+                None => anyhow::bail!("User {} already exists", username),
+            }
+        }
+        UserCommand::Passwd { username, password } => {
+            if api::users::fetch_user_by_username(db, username).await?.is_none() {
+                anyhow::bail!("No such user: {}", username);
+            }
+            let password = user_password(password.as_deref())?;
+            api::users::set_password(db, username, &password).await?;
+            println!("Password changed for {}", username);
+        }
+        UserCommand::Rm { username } => {
+            if !api::users::delete_user(db, username).await? {
+                anyhow::bail!("No such user: {}", username);
+            }
+            println!("Deleted user {}", username);
+        }
+    }
+    Ok(())
+}
+
+/// The `--password` given on the command line, or one typed twice at the terminal
+fn user_password(password: Option<&str>) -> Result<String> {
+    let password = match password {
+        Some(password) => password.to_string(),
+        None => {
+            let password = rpassword::prompt_password("Password: ")?;
+            if rpassword::prompt_password("Repeat password: ")? != password {
+                anyhow::bail!("Passwords do not match");
+            }
+            password
+        }
+    };
+    if password.is_empty() {
+        anyhow::bail!("Password must not be empty");
+    }
+    Ok(password)
+}
+
+/// Prints what `settings` would serve for `--check-config`. Validation has already passed by the
+/// time this runs; any error here (e.g. an unreachable database) makes the process exit non-zero.
+async fn check_config(settings: &Settings, config_path: &str, check_database: bool) -> Result<()> {
+    println!("Configuration {} is valid", config_path);
+    for (service, enabled) in [
+        ("DHCP", settings.dhcp.enabled),
+        ("DNS", settings.dns.enabled),
+        ("DHCPv6", settings.ipv6.enabled),
+        ("API", settings.api.enabled),
+    ] {
+        println!("  {:<7} {}", service, if enabled { "enabled" } else { "disabled" });
+    }
+
+    let mut names: Vec<&String> = settings.subnets.keys().collect();
+    names.sort();
+    println!("{} subnet(s) in the config file", names.len());
+    for name in names {
+        let subnet = &settings.subnets[name];
+        println!("  {:<16} {:<18} pool {}-{}", name, subnet.network, subnet.start_ip, subnet.end_ip);
+    }
+
+    if !check_database {
+        return Ok(());
+    }
+
+    let db_pool = database::init_pool(&settings.database)
+        .await
+        .context("Failed to connect to the database")?;
+
+    let subnets = dhcp::lease_manager_queries::fetch_all_subnets(&db_pool).await?;
+    println!("{} enabled subnet(s) in the database", subnets.len());
+    for subnet in &subnets {
+        println!("  {:<16} {:<18} pool {}-{}", subnet.name, subnet.network, subnet.start_ip, subnet.end_ip);
+    }
+
+    let zones = dns::zone_queries::fetch_all_zones(&db_pool).await?;
+    println!("{} zone(s) in the database", zones.len());
+    for zone in &zones {
+        println!("  {:<32} {:<8} serial {}", zone.name, zone.zone_type, zone.serial_number);
+    }
+
+    db_pool.close().await;
+    Ok(())
+}
+
+/// Re-reads subnets and zones from the database on every SIGHUP. Sockets stay open and leases
+/// live in the database, so clients are served throughout; a reload that fails keeps the
+/// previous subnets or zones in service.
+#[cfg(unix)]
+async fn reload_on_hangup(
+    lease_manager: Option<Arc<dhcp::lease_manager::LeaseManager>>,
+    zone_manager: Option<Arc<dns::simple_zone_manager::SimpleZoneManager>>,
+    shutdown: CancellationToken,
+) {
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => signal,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = hangup.recv() => {}
+        }
+        info!("Received SIGHUP, reloading subnets and zones");
+        reload_everything(&lease_manager, &zone_manager).await;
+    }
+}
+
+async fn reload_everything(
+    lease_manager: &Option<Arc<dhcp::lease_manager::LeaseManager>>,
+    zone_manager: &Option<Arc<dns::simple_zone_manager::SimpleZoneManager>>,
+) {
+    if let Some(lease_manager) = lease_manager {
+        if let Err(e) = lease_manager.reload_subnets().await {
+            error!("Subnet reload failed, keeping the current subnets: {}", e);
+        }
+    }
+    if let Some(zone_manager) = zone_manager {
+        if let Err(e) = zone_manager.load_zones().await {
+            error!("Zone reload failed, keeping the current zones: {}", e);
+        }
+    }
+}
+
+/// Applies changes announced on `flowdns_config`. While the listener is disconnected changes
+/// can be missed, so everything is reloaded once it reconnects.
+async fn follow_config_changes(
+    db: sqlx::PgPool,
+    lease_manager: Option<Arc<dhcp::lease_manager::LeaseManager>>,
+    zone_manager: Option<Arc<dns::simple_zone_manager::SimpleZoneManager>>,
+    shutdown: CancellationToken,
+) {
+    use database::notify::{ConfigChange, ConfigListener};
+
+    let mut reconnecting = false;
+    loop {
+        let mut listener = match ConfigListener::connect(&db).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!("Failed to listen for config changes, retrying: {}", e);
+                reconnecting = true;
+                tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    _ = tokio::time::sleep(CONFIG_LISTEN_RETRY) => continue,
+                }
+            }
+        };
+        // Every connection after a failed or dropped one is a reconnect
+        if reconnecting {
+            reload_everything(&lease_manager, &zone_manager).await;
+        }
+
+        loop {
+            let change = tokio::select! {
+                _ = shutdown.cancelled() => return,
+                change = listener.recv() => change,
+            };
+
+            match change {
+                Ok(ConfigChange::Subnet(subnet_id)) => {
+                    if let Some(lease_manager) = &lease_manager {
+                        if let Err(e) = lease_manager.reload_subnet(subnet_id).await {
+                            error!("Failed to reload subnet {}: {}", subnet_id, e);
+                        }
+                    }
+                }
+                Ok(ConfigChange::Zone(zone_id)) => {
+                    if let Some(zone_manager) = &zone_manager {
+                        if let Err(e) = zone_manager.reload_zone(zone_id).await {
+                            error!("Failed to reload zone {}: {}", zone_id, e);
+                        }
+                    }
+                }
+                Ok(ConfigChange::All) => reload_everything(&lease_manager, &zone_manager).await,
+                Err(e) => {
+                    warn!("Config change listener failed, reconnecting: {}", e);
+                    reconnecting = true;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Resolves on Ctrl-C or SIGTERM, which systemd and Kubernetes send before SIGKILL
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received Ctrl-C"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
 }
\ No newline at end of file
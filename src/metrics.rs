@@ -0,0 +1,130 @@
+// Process-wide counters and the Prometheus text exposition format served at /metrics
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters bumped from the DHCP and DNS packet paths. Gauges such as lease counts are read
+/// from the database when /metrics is scraped, so only event totals live here.
+pub struct Counters {
+    pub dhcp_discover_total: AtomicU64,
+    pub dhcp_request_total: AtomicU64,
+    pub dhcp_offer_total: AtomicU64,
+    pub dhcp_ack_total: AtomicU64,
+    pub dhcp_nak_total: AtomicU64,
+    /// Packets that didn't parse as DHCP at all
+    pub dhcp_parse_failures_total: AtomicU64,
+    /// Parsed packets whose handler returned an error
+    pub dhcp_errors_total: AtomicU64,
+    /// Packets dropped because every worker was busy and the queue was full
+    pub dhcp_dropped_total: AtomicU64,
+    pub dns_queries_total: AtomicU64,
+    /// Queries and TCP connections dropped because the in-flight limit was reached
+    pub dns_dropped_total: AtomicU64,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            dhcp_discover_total: AtomicU64::new(0),
+            dhcp_request_total: AtomicU64::new(0),
+            dhcp_offer_total: AtomicU64::new(0),
+            dhcp_ack_total: AtomicU64::new(0),
+            dhcp_nak_total: AtomicU64::new(0),
+            dhcp_parse_failures_total: AtomicU64::new(0),
+            dhcp_errors_total: AtomicU64::new(0),
+            dhcp_dropped_total: AtomicU64::new(0),
+            dns_queries_total: AtomicU64::new(0),
+            dns_dropped_total: AtomicU64::new(0),
+        }
+    }
+}
+
+static COUNTERS: Counters = Counters::new();
+
+pub fn counters() -> &'static Counters {
+    &COUNTERS
+}
+
+pub fn inc(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Builds a text exposition document (Prometheus format 0.0.4)
+#[derive(Default)]
+pub struct PrometheusText {
+    out: String,
+}
+
+impl PrometheusText {
+    pub const CONTENT_TYPE: &'static str = "text/plain; version=0.0.4; charset=utf-8";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn counter(&mut self, name: &str, help: &str, value: u64) -> &mut Self {
+        self.header(name, help, "counter");
+        let _ = writeln!(self.out, "{} {}", name, value);
+        self
+    }
+
+    pub fn gauge(&mut self, name: &str, help: &str, value: f64) -> &mut Self {
+        self.header(name, help, "gauge");
+        let _ = writeln!(self.out, "{} {}", name, value);
+        self
+    }
+
+    /// One gauge family with a sample per `(label value, value)` pair
+    pub fn labeled_gauge<'a>(
+        &mut self,
+        name: &str,
+        help: &str,
+        label: &str,
+        samples: impl IntoIterator<Item = (&'a str, f64)>,
+    ) -> &mut Self {
+        self.header(name, help, "gauge");
+        for (label_value, value) in samples {
+            let _ = writeln!(self.out, "{}{{{}=\"{}\"}} {}", name, label, escape_label(label_value), value);
+        }
+        self
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    fn header(&mut self, name: &str, help: &str, kind: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposition_format() {
+        let mut text = PrometheusText::new();
+        text.counter("flowdns_dns_queries_total", "DNS queries received", 7)
+            .labeled_gauge(
+                "flowdns_subnet_utilization_percent",
+                "Share of each subnet's pool in use",
+                "subnet",
+                [("lab \"a\"", 12.5)],
+            );
+
+        assert_eq!(
+            text.finish(),
+            "# HELP flowdns_dns_queries_total DNS queries received\n\
+             # TYPE flowdns_dns_queries_total counter\n\
+             flowdns_dns_queries_total 7\n\
+             # HELP flowdns_subnet_utilization_percent Share of each subnet's pool in use\n\
+             # TYPE flowdns_subnet_utilization_percent gauge\n\
+             flowdns_subnet_utilization_percent{subnet=\"lab \\\"a\\\"\"} 12.5\n"
+        );
+    }
+}
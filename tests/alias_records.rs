@@ -0,0 +1,56 @@
+mod common;
+
+use flowdns::database::models::DEFAULT_VIEW;
+use flowdns::dns::message::{TYPE_A, TYPE_AAAA};
+use flowdns::dns::simple_zone_manager::{AliasTarget, SimpleZoneManager, ZoneLookup};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn alias_is_followed_through_local_records() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("alias-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    common::insert_record(&db, zone_id, "@", "ALIAS", &format!("www.{}.", zone_name)).await;
+    common::insert_record(&db, zone_id, "www", "CNAME", &format!("web.{}.", zone_name)).await;
+    common::insert_record(&db, zone_id, "web", "A", "192.0.2.80").await;
+    common::insert_record(&db, zone_id, "ping", "ALIAS", &format!("pong.{}.", zone_name)).await;
+    common::insert_record(&db, zone_id, "pong", "ALIAS", &format!("ping.{}.", zone_name)).await;
+    common::insert_record(&db, zone_id, "cdn", "ALIAS", "edge.cdn.invalid.").await;
+    let manager = SimpleZoneManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    let (target, ttl) = match manager.lookup(&zone_name, TYPE_A, false).await {
+        ZoneLookup::Alias { target, ttl, .. } => (target, ttl),
+        other => panic!("expected ALIAS, got {:?}", other),
+    };
+    assert_eq!(target, format!("www.{}", zone_name));
+
+    match manager.follow_alias(&zone_name, TYPE_A, &target, ttl, DEFAULT_VIEW).await.unwrap() {
+        AliasTarget::Local(answers) => {
+            assert_eq!(answers.len(), 1);
+            assert_eq!(answers[0].name, zone_name);
+            assert_eq!(answers[0].rdata, vec![192, 0, 2, 80]);
+        }
+        other => panic!("expected local addresses, got {:?}", other),
+    }
+
+    // The target has no AAAA, so the apex gets an empty answer
+    match manager.follow_alias(&zone_name, TYPE_AAAA, &target, ttl, DEFAULT_VIEW).await.unwrap() {
+        AliasTarget::Local(answers) => assert!(answers.is_empty()),
+        other => panic!("expected no addresses, got {:?}", other),
+    }
+
+    let ping = format!("ping.{}", zone_name);
+    assert!(manager.follow_alias(&ping, TYPE_A, &format!("pong.{}", zone_name), 3600, DEFAULT_VIEW).await.is_err());
+
+    let cdn = format!("cdn.{}", zone_name);
+    match manager.follow_alias(&cdn, TYPE_A, "edge.cdn.invalid", 3600, DEFAULT_VIEW).await.unwrap() {
+        AliasTarget::External { target, ttl } => {
+            assert_eq!(target, "edge.cdn.invalid");
+            assert_eq!(ttl, 3600);
+        }
+        other => panic!("expected an external target, got {:?}", other),
+    }
+
+    common::delete_zone(&db, zone_id).await;
+}
@@ -0,0 +1,60 @@
+//! Compares address selection on a busy /22 with the allocation map against the previous
+//! approach of querying the database for every candidate. Run with
+//! `cargo test --release --test allocation_bench -- --ignored --nocapture`.
+mod common;
+
+use flowdns::dhcp::lease_manager::LeaseManager;
+use flowdns::dhcp::lease_manager_queries;
+use chrono::{Duration, Utc};
+use std::net::Ipv4Addr;
+use std::time::Instant;
+
+#[tokio::test]
+#[ignore]
+async fn allocation_on_a_nearly_full_slash_22() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let start = Ipv4Addr::new(10, 236, 0, 1);
+    let end = Ipv4Addr::new(10, 236, 3, 254);
+    let subnet_id = common::insert_subnet(&db, "10.236.0.0/22", start, end).await;
+
+    // Everything but the last ten addresses is leased
+    let free_from = u32::from(end) - 9;
+    for ip in u32::from(start)..free_from {
+        common::insert_lease(&db, subnet_id, &common::random_mac(), Ipv4Addr::from(ip), None).await;
+    }
+
+    // Before: three queries per candidate until a free one turns up
+    let began = Instant::now();
+    let decline_cutoff = Utc::now() - Duration::hours(1);
+    let mut scanned = None;
+    for ip in u32::from(start)..=u32::from(end) {
+        let ip = Ipv4Addr::from(ip);
+        let in_use = lease_manager_queries::count_active_leases(&db, subnet_id, ip).await.unwrap() > 0
+            || lease_manager_queries::count_reservations(&db, subnet_id, ip).await.unwrap() > 0
+            || lease_manager_queries::count_declines_since(&db, subnet_id, ip, decline_cutoff).await.unwrap() > 0;
+        if !in_use {
+            scanned = Some(ip);
+            break;
+        }
+    }
+    let per_address = began.elapsed();
+
+    // After: one query per subnet at load, then the map picks the candidate
+    let began = Instant::now();
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+    let load = began.elapsed();
+
+    let began = Instant::now();
+    let chosen = manager.find_available_ip(subnet_id, &common::random_mac()).await.unwrap();
+    let with_map = began.elapsed();
+
+    assert_eq!(scanned, Some(Ipv4Addr::from(free_from)));
+    assert_eq!(chosen, scanned);
+
+    println!("/22 with {} leases:", free_from - u32::from(start));
+    println!("  per-address queries: {:?}", per_address);
+    println!("  allocation map:      {:?} (plus {:?} to load all subnets)", with_map, load);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
@@ -0,0 +1,574 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use chrono::Duration;
+use flowdns::api::auth::{self, Claims};
+use flowdns::api::users;
+use flowdns::api::rate_limit::LoginThrottle;
+use flowdns::api::server::{self, ApiState};
+use flowdns::dhcp::lease_manager::LeaseManager;
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::Instant;
+use uuid::Uuid;
+
+#[actix_web::test]
+async fn get_subnets_returns_database_rows() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.241.0.0/24",
+        Ipv4Addr::new(10, 241, 0, 10),
+        Ipv4Addr::new(10, 241, 0, 20),
+    ).await;
+
+    let settings = common::test_settings();
+    let claims = Claims::new(Uuid::new_v4(), "admin".to_string(), Duration::minutes(5));
+    let token = auth::create_token(&claims, &settings.api.jwt_secret).unwrap();
+
+    let state = web::Data::new(ApiState {
+        db: db.clone(),
+        settings,
+        lease_manager: None,
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+
+    // The DHCP routes sit behind the bearer token check
+    let request = test::TestRequest::get().uri("/api/v1/dhcp/subnets").to_request();
+    let status = match test::try_call_service(&app, request).await {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/dhcp/subnets?limit=-1")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/dhcp/subnets?limit=1000")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let total: i64 = response.headers().get("X-Total-Count").unwrap().to_str().unwrap().parse().unwrap();
+    assert!(total >= 1);
+
+    let subnets: Vec<serde_json::Value> = test::read_body_json(response).await;
+    let subnet = subnets
+        .iter()
+        .find(|s| s["id"] == subnet_id.to_string())
+        .expect("inserted subnet listed");
+    assert_eq!(subnet["network"], "10.241.0.0/24");
+    assert_eq!(subnet["start_ip"], "10.241.0.10");
+    assert_eq!(subnet["end_ip"], "10.241.0.20");
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[actix_web::test]
+async fn create_record_rejects_conflicts_and_invalid_values() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("routes-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    common::insert_record(&db, zone_id, "www", "A", "192.0.2.10").await;
+
+    let settings = common::test_settings();
+    let claims = Claims::new(Uuid::new_v4(), "admin".to_string(), Duration::minutes(5));
+    let token = auth::create_token(&claims, &settings.api.jwt_secret).unwrap();
+
+    let state = web::Data::new(ApiState {
+        db: db.clone(),
+        settings,
+        lease_manager: None,
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+    let uri = format!("/api/v1/dns/zones/{}/records", zone_id);
+
+    let request = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({"name": "www", "record_type": "CNAME", "value": "other.test."}))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let request = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({"name": "@", "record_type": "MX", "value": "mail.test."}))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Values are checked against the record type before they are stored
+    let request = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({"name": "host", "record_type": "A", "value": "hello"}))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"], "invalid_record_value");
+
+    // A wildcard may only be the leftmost label
+    let request = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({"name": "apps.*", "record_type": "A", "value": "192.0.2.50"}))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"], "invalid_record_name");
+
+    let request = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({"name": "*.apps", "record_type": "A", "value": "192.0.2.50"}))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    common::delete_zone(&db, zone_id).await;
+}
+
+#[actix_web::test]
+async fn create_subnet_rejects_out_of_range_and_overlapping_networks() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.241.1.0/24",
+        Ipv4Addr::new(10, 241, 1, 10),
+        Ipv4Addr::new(10, 241, 1, 20),
+    ).await;
+
+    let settings = common::test_settings();
+    let claims = Claims::new(Uuid::new_v4(), "admin".to_string(), Duration::minutes(5));
+    let token = auth::create_token(&claims, &settings.api.jwt_secret).unwrap();
+
+    let state = web::Data::new(ApiState {
+        db: db.clone(),
+        settings,
+        lease_manager: None,
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+
+    let subnet = |network: &str, start: &str, end: &str, gateway: &str| {
+        serde_json::json!({
+            "name": format!("routes-{}", Uuid::new_v4().simple()),
+            "network": network,
+            "start_ip": start,
+            "end_ip": end,
+            "gateway": gateway,
+            "dns_servers": []
+        })
+    };
+
+    // Gateway outside the network
+    let request = test::TestRequest::post()
+        .uri("/api/v1/dhcp/subnets")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(subnet("10.241.2.0/24", "10.241.2.10", "10.241.2.20", "10.0.0.1"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"], "invalid_range");
+
+    // A supernet of an existing subnet
+    let request = test::TestRequest::post()
+        .uri("/api/v1/dhcp/subnets")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(subnet("10.241.0.0/16", "10.241.2.10", "10.241.2.20", "10.241.2.1"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"], "subnet_overlap");
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[actix_web::test]
+async fn prometheus_metrics_are_public() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.241.3.0/24",
+        Ipv4Addr::new(10, 241, 3, 10),
+        Ipv4Addr::new(10, 241, 3, 20),
+    ).await;
+    let subnet_name: String = sqlx::query_scalar("SELECT name FROM dhcp_subnets WHERE id = $1")
+        .bind(subnet_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+    let state = web::Data::new(ApiState {
+        db: db.clone(),
+        settings: common::test_settings(),
+        lease_manager: None,
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+
+    let request = test::TestRequest::get().uri("/metrics").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("Content-Type").unwrap().to_str().unwrap().starts_with("text/plain"));
+
+    let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+    assert!(body.contains("# TYPE flowdns_active_leases gauge"));
+    assert!(body.contains("# TYPE flowdns_dhcp_discover_total counter"));
+    assert!(body.contains("# TYPE flowdns_dhcp_nak_total counter"));
+    assert!(body.contains("# TYPE flowdns_dhcp_parse_failures_total counter"));
+    assert!(body.contains(&format!("flowdns_subnet_utilization_percent{{subnet=\"{}\"}} 0", subnet_name)));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[actix_web::test]
+async fn repeated_failed_logins_are_throttled() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let state = web::Data::new(ApiState {
+        db,
+        settings: common::test_settings(),
+        lease_manager: None,
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(2, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+
+    let login = || {
+        test::TestRequest::post()
+            .uri("/api/v1/auth/login")
+            .peer_addr("192.0.2.10:40000".parse().unwrap())
+            .set_json(serde_json::json!({"username": "throttle-test-nobody", "password": "wrong"}))
+            .to_request()
+    };
+
+    for _ in 0..2 {
+        let response = test::call_service(&app, login()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let response = test::call_service(&app, login()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+}
+
+#[actix_web::test]
+async fn viewer_role_is_read_only() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let settings = common::test_settings();
+    let token_for = |role: &str| {
+        let claims = Claims::new(Uuid::new_v4(), role.to_string(), Duration::minutes(5));
+        auth::create_token(&claims, &settings.api.jwt_secret).unwrap()
+    };
+    let viewer = token_for("viewer");
+    let operator = token_for("operator");
+
+    let state = web::Data::new(ApiState {
+        db,
+        settings: settings.clone(),
+        lease_manager: None,
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+
+    let status = |response: Result<actix_web::dev::ServiceResponse, actix_web::Error>| match response {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/dhcp/subnets")
+        .insert_header(("Authorization", format!("Bearer {}", viewer)))
+        .to_request();
+    assert_eq!(status(test::try_call_service(&app, request).await), StatusCode::OK);
+
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/v1/dhcp/subnets/{}", Uuid::new_v4()))
+        .insert_header(("Authorization", format!("Bearer {}", viewer)))
+        .to_request();
+    let response = test::try_call_service(&app, request).await;
+    assert_eq!(status(response), StatusCode::FORBIDDEN);
+
+    let request = test::TestRequest::post()
+        .uri("/api/v1/dns/zones")
+        .insert_header(("Authorization", format!("Bearer {}", viewer)))
+        .set_json(serde_json::json!({"name": "viewer.test", "zone_type": "forward"}))
+        .to_request();
+    assert_eq!(status(test::try_call_service(&app, request).await), StatusCode::FORBIDDEN);
+
+    // Operators may change things but not delete zones or subnets
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/v1/dns/zones/{}", Uuid::new_v4()))
+        .insert_header(("Authorization", format!("Bearer {}", operator)))
+        .to_request();
+    assert_eq!(status(test::try_call_service(&app, request).await), StatusCode::FORBIDDEN);
+
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/v1/dhcp/reservations/{}", Uuid::new_v4()))
+        .insert_header(("Authorization", format!("Bearer {}", operator)))
+        .to_request();
+    assert_eq!(status(test::try_call_service(&app, request).await), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn reserve_lease_rejects_a_malformed_body() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let settings = common::test_settings();
+    let claims = Claims::new(Uuid::new_v4(), "operator".to_string(), Duration::minutes(5));
+    let token = auth::create_token(&claims, &settings.api.jwt_secret).unwrap();
+
+    let state = web::Data::new(ApiState {
+        db,
+        settings,
+        lease_manager: None,
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+    let uri = format!("/api/v1/dhcp/leases/{}/reserve", Uuid::new_v4());
+
+    let request = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({"delete_lease": "yes"}))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // No body means the defaults; the lease lookup then runs and finds nothing
+    let request = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let request = test::TestRequest::post()
+        .uri(&uri)
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({"delete_lease": true}))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn subnet_changes_reach_the_lease_manager() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let settings = common::test_settings();
+    let claims = Claims::new(Uuid::new_v4(), "admin".to_string(), Duration::minutes(5));
+    let token = auth::create_token(&claims, &settings.api.jwt_secret).unwrap();
+
+    let lease_manager = std::sync::Arc::new(LeaseManager::new(db.clone(), settings.clone()).await.unwrap());
+    let state = web::Data::new(ApiState {
+        db: db.clone(),
+        settings,
+        lease_manager: Some(lease_manager.clone()),
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+
+    let client = Ipv4Addr::new(10, 241, 5, 50);
+    assert!(lease_manager.find_subnet_for_client(client, None, None).await.is_none());
+
+    // A subnet created after startup is served straight away
+    let request = test::TestRequest::post()
+        .uri("/api/v1/dhcp/subnets")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(serde_json::json!({
+            "name": format!("routes-{}", Uuid::new_v4().simple()),
+            "network": "10.241.5.0/24",
+            "start_ip": "10.241.5.10",
+            "end_ip": "10.241.5.20",
+            "gateway": "10.241.5.1",
+            "dns_servers": []
+        }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    let subnet_id: Uuid = body["id"].as_str().unwrap().parse().unwrap();
+
+    let subnet = lease_manager.find_subnet_for_client(client, None, None).await.unwrap();
+    assert_eq!(subnet.id, subnet_id);
+    let offered = lease_manager.find_available_ip(subnet_id, &common::random_mac()).await.unwrap();
+    assert_eq!(offered, Some(Ipv4Addr::new(10, 241, 5, 10)));
+
+    // And stops being served once deleted
+    let request = test::TestRequest::delete()
+        .uri(&format!("/api/v1/dhcp/subnets/{}", subnet_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(lease_manager.find_subnet_for_client(client, None, None).await.is_none());
+}
+
+#[actix_web::test]
+async fn lease_events_are_streamed() {
+    use actix_web::body::MessageBody;
+
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.253.10.0/24",
+        Ipv4Addr::new(10, 253, 10, 10),
+        Ipv4Addr::new(10, 253, 10, 20),
+    ).await;
+
+    let settings = common::test_settings();
+    let claims = Claims::new(Uuid::new_v4(), "viewer".to_string(), Duration::minutes(5));
+    let token = auth::create_token(&claims, &settings.api.jwt_secret).unwrap();
+
+    let lease_manager = std::sync::Arc::new(LeaseManager::new(db.clone(), settings.clone()).await.unwrap());
+    let state = web::Data::new(ApiState {
+        db: db.clone(),
+        settings,
+        lease_manager: Some(lease_manager.clone()),
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+
+    let request = test::TestRequest::get().uri("/api/v1/dhcp/events").to_request();
+    let status = match test::try_call_service(&app, request).await {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/dhcp/events")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("Content-Type").unwrap(), "text/event-stream");
+
+    let mac = common::random_mac();
+    let ip = Ipv4Addr::new(10, 253, 10, 10);
+    lease_manager.create_lease(subnet_id, &mac, ip, None, Default::default(), None).await.unwrap();
+
+    let mut body = Box::pin(response.into_body());
+    let chunk = futures::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await.unwrap().unwrap();
+    let message = String::from_utf8(chunk.to_vec()).unwrap();
+    assert!(message.starts_with("event: created\n"), "{}", message);
+
+    let data: serde_json::Value = serde_json::from_str(message.lines().nth(1).unwrap().trim_start_matches("data: ")).unwrap();
+    assert_eq!(data["ip_address"], ip.to_string());
+    assert_eq!(data["subnet_id"], subnet_id.to_string());
+    assert!(data["timestamp"].is_string());
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[actix_web::test]
+async fn refresh_tokens_only_mint_access_tokens_for_current_users() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let settings = common::test_settings();
+    let username = format!("refresh-{}", Uuid::new_v4().simple());
+    let user = users::create_user(&db, &username, "refresh-password", auth::ROLE_VIEWER).await.unwrap().unwrap();
+    let access = auth::create_token(
+        &Claims::new(user.id, user.role.clone(), Duration::minutes(5)),
+        &settings.api.jwt_secret,
+    ).unwrap();
+    let refresh = auth::create_token(
+        &Claims::refresh(user.id, user.role.clone(), Duration::minutes(5)),
+        &settings.api.jwt_secret,
+    ).unwrap();
+
+    let state = web::Data::new(ApiState {
+        db: db.clone(),
+        settings: settings.clone(),
+        lease_manager: None,
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, std::time::Duration::from_secs(60)),
+    });
+    let app = test::init_service(App::new().app_data(state).configure(server::routes)).await;
+
+    // A refresh token is not a bearer token
+    let request = test::TestRequest::get()
+        .uri("/api/v1/dhcp/subnets")
+        .insert_header(("Authorization", format!("Bearer {}", refresh)))
+        .to_request();
+    let status = match test::try_call_service(&app, request).await {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let refresh_with = |token: &str| {
+        test::TestRequest::post()
+            .uri("/api/v1/auth/refresh")
+            .set_json(serde_json::json!({"refresh_token": token}))
+            .to_request()
+    };
+
+    // Nor is an access token a refresh token
+    let response = test::call_service(&app, refresh_with(&access)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // The new access token carries the user's current role
+    users::upsert_user(&db, &username, "refresh-password", auth::ROLE_OPERATOR).await.unwrap();
+    let response = test::call_service(&app, refresh_with(&refresh)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    let claims = auth::validate_token(body["access_token"].as_str().unwrap(), &settings.api.jwt_secret).unwrap();
+    assert_eq!(claims.role, auth::ROLE_OPERATOR);
+    assert_eq!(claims.token_type, auth::TokenType::Access);
+
+    // Deleted users can't refresh
+    users::delete_user(&db, &username).await.unwrap();
+    let response = test::call_service(&app, refresh_with(&refresh)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
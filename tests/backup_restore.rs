@@ -0,0 +1,57 @@
+mod common;
+
+use flowdns::api::backup;
+use std::net::Ipv4Addr;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn backup_then_restore_round_trips() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.249.0.0/24",
+        Ipv4Addr::new(10, 249, 0, 10),
+        Ipv4Addr::new(10, 249, 0, 20),
+    ).await;
+    let zone_name = format!("backup-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    let record_id = common::insert_record(&db, zone_id, "www", "A", "192.0.2.20").await;
+
+    // Keep only the objects created here so other tests' data doesn't interfere
+    let mut bundle = backup::export(&db).await.unwrap();
+    bundle.subnets.retain(|s| s.subnet.id == subnet_id);
+    bundle.reservations.retain(|r| r.subnet_id == subnet_id);
+    bundle.zones.retain(|z| z.id == zone_id);
+    bundle.records.retain(|r| r.zone_id == zone_id);
+    bundle.prefix_pools.clear();
+    assert_eq!(bundle.subnets.len(), 1);
+    assert_eq!(bundle.records.len(), 1);
+
+    // Round-trip through JSON as the API would
+    let json = serde_json::to_string(&bundle).unwrap();
+    let bundle: backup::BackupBundle = serde_json::from_str(&json).unwrap();
+
+    common::delete_subnet(&db, subnet_id).await;
+    common::delete_zone(&db, zone_id).await;
+
+    // A dry run reports the counts but leaves the database untouched
+    let report = backup::restore(&db, &bundle, true).await.unwrap();
+    assert!(report.dry_run);
+    assert_eq!(report.subnets, 1);
+    assert!(flowdns::dns::zone_queries::fetch_zone(&db, zone_id).await.unwrap().is_none());
+
+    backup::restore(&db, &bundle, false).await.unwrap();
+
+    let restored = backup::export(&db).await.unwrap();
+    let subnet = restored.subnets.iter().find(|s| s.subnet.id == subnet_id).unwrap();
+    assert_eq!(subnet.subnet.start_ip, Ipv4Addr::new(10, 249, 0, 10));
+    assert_eq!(subnet.subnet.end_ip, Ipv4Addr::new(10, 249, 0, 20));
+    let zone = restored.zones.iter().find(|z| z.id == zone_id).unwrap();
+    assert_eq!(zone.name, zone_name);
+    let record = restored.records.iter().find(|r| r.id == record_id).unwrap();
+    assert_eq!(record.value, "192.0.2.20");
+
+    common::delete_subnet(&db, subnet_id).await;
+    common::delete_zone(&db, zone_id).await;
+}
@@ -0,0 +1,158 @@
+// Shared helpers for database-backed integration tests.
+// Tests are skipped unless FLOWDNS_TEST_DATABASE_URL points at a disposable database.
+#![allow(dead_code)]
+
+use sqlx::{PgPool, Row};
+use std::net::{IpAddr, Ipv4Addr};
+use uuid::Uuid;
+
+pub async fn test_pool() -> Option<PgPool> {
+    let url = match std::env::var("FLOWDNS_TEST_DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("FLOWDNS_TEST_DATABASE_URL not set, skipping database test");
+            return None;
+        }
+    };
+
+    let pool = PgPool::connect(&url).await.expect("failed to connect to test database");
+    flowdns::database::run_migrations(&pool).await.expect("failed to run migrations");
+    Some(pool)
+}
+
+/// Random locally-administered MAC so parallel tests never collide on unique constraints.
+pub fn random_mac() -> Vec<u8> {
+    let bytes = Uuid::new_v4().into_bytes();
+    vec![0x02, bytes[0], bytes[1], bytes[2], bytes[3], bytes[4]]
+}
+
+pub async fn insert_subnet(db: &PgPool, network: &str, start: Ipv4Addr, end: Ipv4Addr) -> Uuid {
+    let network: ipnetwork::IpNetwork = network.parse().unwrap();
+    let gateway = match network.ip() {
+        IpAddr::V4(ip) => Ipv4Addr::from(u32::from(ip) + 1),
+        IpAddr::V6(_) => panic!("test subnets are IPv4"),
+    };
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dhcp_subnets (name, network, start_ip, end_ip, gateway, dns_servers)
+        VALUES ($1, $2, $3, $4, $5, '[]')
+        RETURNING id
+        "#
+    )
+    .bind(format!("test-{}", Uuid::new_v4()))
+    .bind(network)
+    .bind(IpAddr::V4(start))
+    .bind(IpAddr::V4(end))
+    .bind(IpAddr::V4(gateway))
+    .fetch_one(db)
+    .await
+    .expect("failed to insert subnet");
+
+    row.get("id")
+}
+
+pub async fn insert_lease(db: &PgPool, subnet_id: Uuid, mac: &[u8], ip: Ipv4Addr, hostname: Option<&str>) -> Uuid {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dhcp_leases (subnet_id, mac_address, ip_address, hostname,
+                                 lease_start, lease_end, state)
+        VALUES ($1, $2, $3, $4, NOW(), NOW() + INTERVAL '1 hour', 'active')
+        RETURNING id
+        "#
+    )
+    .bind(subnet_id)
+    .bind(mac)
+    .bind(IpAddr::V4(ip))
+    .bind(hostname)
+    .fetch_one(db)
+    .await
+    .expect("failed to insert lease");
+
+    row.get("id")
+}
+
+pub async fn insert_reservation(db: &PgPool, subnet_id: Uuid, mac: &[u8], ip: Ipv4Addr) -> Uuid {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dhcp_reservations (subnet_id, mac_address, ip_address)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#
+    )
+    .bind(subnet_id)
+    .bind(mac)
+    .bind(IpAddr::V4(ip))
+    .fetch_one(db)
+    .await
+    .expect("failed to insert reservation");
+
+    row.get("id")
+}
+
+pub async fn delete_subnet(db: &PgPool, subnet_id: Uuid) {
+    sqlx::query("DELETE FROM dhcp_subnets WHERE id = $1")
+        .bind(subnet_id)
+        .execute(db)
+        .await
+        .expect("failed to delete subnet");
+}
+
+pub fn test_settings() -> std::sync::Arc<flowdns::config::Settings> {
+    let settings = flowdns::config::Settings::load("config/server.toml").expect("failed to load config/server.toml");
+    std::sync::Arc::new(settings)
+}
+
+pub async fn set_subnet_maintenance(db: &PgPool, subnet_id: Uuid, maintenance: bool) {
+    sqlx::query("UPDATE dhcp_subnets SET maintenance = $2 WHERE id = $1")
+        .bind(subnet_id)
+        .bind(maintenance)
+        .execute(db)
+        .await
+        .expect("failed to update subnet");
+}
+
+pub async fn insert_zone(db: &PgPool, name: &str) -> Uuid {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dns_zones (name, zone_type, serial_number, primary_ns, admin_email)
+        VALUES ($1, 'master', 1, $2, $3)
+        RETURNING id
+        "#
+    )
+    .bind(name)
+    .bind(format!("ns1.{}", name))
+    .bind(format!("admin.{}", name))
+    .fetch_one(db)
+    .await
+    .expect("failed to insert zone");
+
+    row.get("id")
+}
+
+pub async fn insert_record(db: &PgPool, zone_id: Uuid, name: &str, record_type: &str, value: &str) -> Uuid {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO dns_records (zone_id, name, record_type, value)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+        "#
+    )
+    .bind(zone_id)
+    .bind(name)
+    .bind(record_type)
+    .bind(value)
+    .fetch_one(db)
+    .await
+    .expect("failed to insert record");
+
+    row.get("id")
+}
+
+pub async fn delete_zone(db: &PgPool, zone_id: Uuid) {
+    sqlx::query("DELETE FROM dns_zones WHERE id = $1")
+        .bind(zone_id)
+        .execute(db)
+        .await
+        .expect("failed to delete zone");
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use flowdns::database::notify::{self, ConfigChange, ConfigListener};
+use std::time::Duration;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn listeners_receive_published_changes() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let mut listener = ConfigListener::connect(&db).await.unwrap();
+
+    // Other tests may publish at the same time, so skip anything that isn't ours
+    let subnet_id = Uuid::new_v4();
+    let zone_id = Uuid::new_v4();
+    notify::publish(&db, ConfigChange::Subnet(subnet_id)).await.unwrap();
+    notify::publish(&db, ConfigChange::Zone(zone_id)).await.unwrap();
+
+    let mut expected = vec![ConfigChange::Subnet(subnet_id), ConfigChange::Zone(zone_id)];
+    while !expected.is_empty() {
+        let change = tokio::time::timeout(Duration::from_secs(5), listener.recv())
+            .await
+            .expect("change announced")
+            .unwrap();
+        expected.retain(|wanted| *wanted != change);
+    }
+}
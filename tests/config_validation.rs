@@ -0,0 +1,69 @@
+mod common;
+
+use flowdns::config::DnsViewConfig;
+
+#[test]
+fn shipped_config_validates() {
+    common::test_settings().validate().unwrap();
+}
+
+#[test]
+fn bad_ports_and_database_urls_are_rejected() {
+    let settings = (*common::test_settings()).clone();
+
+    let mut dhcp = settings.clone();
+    dhcp.dhcp.enabled = true;
+    dhcp.dhcp.port = 0;
+    assert!(dhcp.validate().unwrap_err().to_string().contains("dhcp.port"));
+
+    // A disabled service may leave its port unset
+    dhcp.dhcp.enabled = false;
+    assert!(dhcp.validate().is_ok());
+
+    let mut dns = settings.clone();
+    dns.dns.enabled = true;
+    dns.dns.port = 0;
+    assert!(dns.validate().is_err());
+
+    let mut database = settings.clone();
+    database.database.url = "flowdns@localhost".to_string();
+    assert!(database.validate().unwrap_err().to_string().contains("database.url"));
+}
+
+#[test]
+fn query_log_settings_are_checked() {
+    let settings = (*common::test_settings()).clone();
+    assert!(!settings.dns.query_log.enabled);
+
+    let mut rate = settings.clone();
+    rate.dns.query_log.sample_rate = 1.5;
+    assert!(rate.validate().unwrap_err().to_string().contains("sample_rate"));
+    rate.dns.query_log.sample_rate = 0.1;
+    assert!(rate.validate().is_ok());
+
+    let mut size = settings.clone();
+    size.dns.query_log.enabled = true;
+    size.dns.query_log.buffer_size = 0;
+    assert!(size.validate().unwrap_err().to_string().contains("buffer_size"));
+}
+
+#[test]
+fn view_names_are_checked() {
+    let view = |name: &str| DnsViewConfig {
+        name: name.to_string(),
+        match_clients: vec!["10.0.0.0/8".parse().unwrap()],
+    };
+    let mut settings = (*common::test_settings()).clone();
+
+    settings.dns.views = vec![view("internal"), view("lab")];
+    assert!(settings.validate().is_ok());
+
+    settings.dns.views = vec![view("internal"), view("internal")];
+    assert!(settings.validate().unwrap_err().to_string().contains("defined twice"));
+
+    settings.dns.views = vec![view("default")];
+    assert!(settings.validate().unwrap_err().to_string().contains("implicit view"));
+
+    settings.dns.views = vec![view("")];
+    assert!(settings.validate().is_err());
+}
@@ -0,0 +1,94 @@
+use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+use flowdns::api::server;
+use flowdns::config::ApiConfig;
+
+fn api_config(cors_enabled: bool, origins: &[&str]) -> ApiConfig {
+    ApiConfig {
+        enabled: true,
+        bind_address: "127.0.0.1".to_string(),
+        port: 8080,
+        cors_enabled,
+        cors_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+        jwt_secret: "test-secret-that-is-long-enough-for-hs256".to_string(),
+        jwt_expiry: 3600,
+        login_max_failures: 5,
+        login_window_secs: 60,
+    }
+}
+
+fn preflight(origin: &str) -> test::TestRequest {
+    test::TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/api/v1/dhcp/subnets")
+        .insert_header(("Origin", origin))
+        .insert_header(("Access-Control-Request-Method", "DELETE"))
+        .insert_header(("Access-Control-Request-Headers", "authorization"))
+}
+
+#[actix_web::test]
+async fn preflight_allowed_only_for_configured_origins() {
+    let config = api_config(true, &["http://localhost:3000"]);
+    let app = test::init_service(
+        App::new()
+            .wrap(server::cors(&config))
+            .route("/api/v1/dhcp/subnets", web::get().to(HttpResponse::Ok)),
+    ).await;
+
+    let response = test::call_service(&app, preflight("http://localhost:3000").to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("Access-Control-Allow-Origin").unwrap(), "http://localhost:3000");
+
+    let status = match test::try_call_service(&app, preflight("http://evil.example").to_request()).await {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    assert!(status.is_client_error());
+
+    // Actual requests expose the pagination total to scripts
+    let request = test::TestRequest::get()
+        .uri("/api/v1/dhcp/subnets")
+        .insert_header(("Origin", "http://localhost:3000"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    let exposed = response.headers().get("Access-Control-Expose-Headers").unwrap().to_str().unwrap().to_lowercase();
+    assert!(exposed.contains("x-total-count"));
+}
+
+#[actix_web::test]
+async fn wildcard_and_disabled_cors() {
+    let config = api_config(true, &["*"]);
+    let app = test::init_service(
+        App::new()
+            .wrap(server::cors(&config))
+            .route("/api/v1/dhcp/subnets", web::get().to(HttpResponse::Ok)),
+    ).await;
+    let response = test::call_service(&app, preflight("http://anywhere.example").to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key("Access-Control-Allow-Origin"));
+
+    // A wildcard anywhere in the list wins over the specific origins listed after it
+    let config = api_config(true, &["*", "https://admin.example"]);
+    let app = test::init_service(
+        App::new()
+            .wrap(server::cors(&config))
+            .route("/api/v1/dhcp/subnets", web::get().to(HttpResponse::Ok)),
+    ).await;
+    let response = test::call_service(&app, preflight("http://anywhere.example").to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key("Access-Control-Allow-Origin"));
+
+    // Disabled: requests pass through untouched and carry no CORS headers
+    let config = api_config(false, &["http://localhost:3000"]);
+    let app = test::init_service(
+        App::new()
+            .wrap(server::cors(&config))
+            .route("/api/v1/dhcp/subnets", web::get().to(HttpResponse::Ok)),
+    ).await;
+    let request = test::TestRequest::get()
+        .uri("/api/v1/dhcp/subnets")
+        .insert_header(("Origin", "http://localhost:3000"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key("Access-Control-Allow-Origin"));
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use flowdns::dhcp::lease_manager::LeaseManager;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn declined_address_is_skipped_until_the_window_passes() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.244.0.0/24",
+        Ipv4Addr::new(10, 244, 0, 10),
+        Ipv4Addr::new(10, 244, 0, 12),
+    ).await;
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    let first = Ipv4Addr::new(10, 244, 0, 10);
+    assert_eq!(manager.find_available_ip(subnet_id, &common::random_mac()).await.unwrap(), Some(first));
+
+    manager.decline_address(subnet_id, first, Some(&common::random_mac())).await.unwrap();
+    assert_eq!(
+        manager.find_available_ip(subnet_id, &common::random_mac()).await.unwrap(),
+        Some(Ipv4Addr::new(10, 244, 0, 11))
+    );
+
+    // Once the decline is older than decline_time the address is handed out again
+    sqlx::query("UPDATE declined_addresses SET declined_at = NOW() - INTERVAL '1 day' WHERE subnet_id = $1")
+        .bind(subnet_id)
+        .execute(&db)
+        .await
+        .unwrap();
+    assert_eq!(manager.find_available_ip(subnet_id, &common::random_mac()).await.unwrap(), Some(first));
+    assert!(manager.cleanup_expired_declines().await.unwrap() >= 1);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use flowdns::api::models::UpdateSubnetRequest;
+use flowdns::api::queries::{self, UpdateSubnetOutcome};
+use flowdns::database::models::DhcpOptionValue;
+use flowdns::dhcp::lease_manager_queries;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+
+fn set_options(options: BTreeMap<u8, DhcpOptionValue>) -> UpdateSubnetRequest {
+    UpdateSubnetRequest {
+        name: None,
+        start_ip: None,
+        end_ip: None,
+        gateway: None,
+        dns_servers: None,
+        domain_name: None,
+        lease_duration: None,
+        enabled: None,
+        maintenance: None,
+        relay_circuit_id: None,
+        server_ip: None,
+        boot_server: None,
+        boot_filename: None,
+        boot_rules: None,
+        excluded_ranges: None,
+        dhcp_options: Some(options),
+        dhcpv6_rapid_commit: None,
+    }
+}
+
+#[tokio::test]
+async fn ntp_servers_round_trip() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.253.5.0/24",
+        Ipv4Addr::new(10, 253, 5, 10),
+        Ipv4Addr::new(10, 253, 5, 20),
+    ).await;
+
+    // The server sets the lease time itself
+    let outcome = queries::update_subnet(
+        &db,
+        subnet_id,
+        &set_options(BTreeMap::from([(51, DhcpOptionValue::Hex("00000e10".to_string()))])),
+    ).await.unwrap();
+    assert!(matches!(outcome, UpdateSubnetOutcome::InvalidRange(_)));
+
+    let ntp = vec![Ipv4Addr::new(10, 253, 5, 1), Ipv4Addr::new(10, 253, 5, 2)];
+    let outcome = queries::update_subnet(
+        &db,
+        subnet_id,
+        &set_options(BTreeMap::from([(42, DhcpOptionValue::Ips(ntp.clone()))])),
+    ).await.unwrap();
+    assert!(matches!(outcome, UpdateSubnetOutcome::Updated(_)));
+
+    let subnet = lease_manager_queries::fetch_subnet(&db, subnet_id).await.unwrap().unwrap();
+    assert_eq!(subnet.dhcp_options.get(&42), Some(&DhcpOptionValue::Ips(ntp)));
+    assert_eq!(subnet.dhcp_options[&42].encode().unwrap(), vec![10, 253, 5, 1, 10, 253, 5, 2]);
+
+    let row = queries::fetch_subnet_by_id(&db, subnet_id).await.unwrap().unwrap();
+    assert_eq!(row.dhcp_options, subnet.dhcp_options);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use flowdns::ipv6::dhcpv6_leases::{self, Dhcpv6Pool, POOL_START};
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn commits_and_renews_leases_per_duid_and_iaid() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.248.0.0/24",
+        Ipv4Addr::new(10, 248, 0, 10),
+        Ipv4Addr::new(10, 248, 0, 20),
+    ).await;
+    let pool = Dhcpv6Pool {
+        subnet_id,
+        prefix: "fd00:248::/64".parse().unwrap(),
+        valid_lifetime: 7200,
+        preferred_lifetime: 3600,
+        rapid_commit: false,
+    };
+    let first_duid = vec![0, 3, 0, 1, 0x02, 0, 0, 0, 0, 1];
+    let second_duid = vec![0, 3, 0, 1, 0x02, 0, 0, 0, 0, 2];
+
+    let first_addr = dhcpv6_leases::next_free_address(&db, &pool).await.unwrap().unwrap();
+    assert_eq!(Some(first_addr), pool.address_at(POOL_START));
+    let first = dhcpv6_leases::commit_lease(&db, &pool, &first_duid, 7, first_addr, Some("v6host")).await.unwrap();
+    assert_eq!(first.iaid, 7);
+    assert_eq!(first.hostname.as_deref(), Some("v6host"));
+
+    // The committed address is no longer free
+    let second_addr = dhcpv6_leases::next_free_address(&db, &pool).await.unwrap().unwrap();
+    assert_ne!(second_addr, first_addr);
+    dhcpv6_leases::commit_lease(&db, &pool, &second_duid, 7, second_addr, None).await.unwrap();
+
+    // A second client can't take an address that is actively leased
+    let err = dhcpv6_leases::commit_lease(&db, &pool, &second_duid, 8, first_addr, None).await.unwrap_err();
+    assert!(dhcpv6_leases::is_address_conflict(&err));
+
+    let found = dhcpv6_leases::find_active_lease(&db, &first_duid, 7).await.unwrap().unwrap();
+    assert_eq!(found.ipv6_address, first_addr);
+    assert!(dhcpv6_leases::find_active_lease(&db, &first_duid, 8).await.unwrap().is_none());
+
+    let renewed = dhcpv6_leases::renew_lease(&db, found.id, &pool, None).await.unwrap();
+    assert_eq!(renewed.ipv6_address, first_addr);
+    // A renewal without a name keeps the one already on the lease
+    assert_eq!(renewed.hostname.as_deref(), Some("v6host"));
+    assert!(renewed.lease_end >= found.lease_end);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[tokio::test]
+async fn released_addresses_are_free_again() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.249.0.0/24",
+        Ipv4Addr::new(10, 249, 0, 10),
+        Ipv4Addr::new(10, 249, 0, 20),
+    ).await;
+    let pool = Dhcpv6Pool {
+        subnet_id,
+        prefix: "fd00:249::/64".parse().unwrap(),
+        valid_lifetime: 7200,
+        preferred_lifetime: 3600,
+        rapid_commit: false,
+    };
+    let duid = vec![0, 3, 0, 1, 0x02, 0, 0, 0, 0x49, 1];
+
+    let addr = dhcpv6_leases::next_free_address(&db, &pool).await.unwrap().unwrap();
+    dhcpv6_leases::commit_lease(&db, &pool, &duid, 3, addr, Some("released-host")).await.unwrap();
+    assert!(!dhcpv6_leases::is_address_free(&db, addr).await.unwrap());
+
+    // Only the holder's DUID and IAID release the lease
+    assert!(dhcpv6_leases::release_lease(&db, &duid, 4, addr).await.unwrap().is_none());
+    assert!(dhcpv6_leases::release_lease(&db, &[0, 3, 0, 1, 0x02, 0, 0, 0, 0x49, 2], 3, addr).await.unwrap().is_none());
+
+    let released = dhcpv6_leases::release_lease(&db, &duid, 3, addr).await.unwrap().unwrap();
+    assert_eq!(released.state, "released");
+    assert_eq!(released.hostname.as_deref(), Some("released-host"));
+    assert!(dhcpv6_leases::find_active_lease(&db, &duid, 3).await.unwrap().is_none());
+    assert!(dhcpv6_leases::is_address_free(&db, addr).await.unwrap());
+    assert_eq!(dhcpv6_leases::next_free_address(&db, &pool).await.unwrap(), Some(addr));
+
+    // Releasing twice finds nothing left to release
+    assert!(dhcpv6_leases::release_lease(&db, &duid, 3, addr).await.unwrap().is_none());
+
+    // The client can lease the address again later
+    let again = dhcpv6_leases::commit_lease(&db, &pool, &duid, 3, addr, None).await.unwrap();
+    assert_eq!(again.state, "active");
+
+    common::delete_subnet(&db, subnet_id).await;
+}
@@ -0,0 +1,19 @@
+mod common;
+
+use flowdns::ipv6::server_duid;
+
+#[tokio::test]
+async fn server_duid_survives_restarts() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let first = server_duid::load_or_create(&db).await.unwrap();
+    let second = server_duid::load_or_create(&db).await.unwrap();
+    assert_eq!(first, second);
+
+    // DUID-LLT from an interface MAC, or DUID-UUID without one
+    match u16::from_be_bytes([first[0], first[1]]) {
+        1 => assert_eq!(first.len(), 14),
+        4 => assert_eq!(first.len(), 18),
+        other => panic!("unexpected DUID type {}", other),
+    }
+}
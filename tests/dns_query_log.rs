@@ -0,0 +1,48 @@
+mod common;
+
+use chrono::Utc;
+use flowdns::dns::query_log::{QueryLog, QueryLogEntry};
+use sqlx::Row;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn sampled_queries_are_stored() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let qname = format!("{}.query-log.test", Uuid::new_v4().simple());
+    let log = Arc::new(QueryLog::new(10, 0.5));
+    for cached in [false, true, false, true] {
+        log.record(QueryLogEntry {
+            timestamp: Utc::now(),
+            client: "192.0.2.44".parse().unwrap(),
+            qname: qname.clone(),
+            qtype: 28,
+            rcode: 3,
+            cached,
+        });
+    }
+    assert_eq!(log.recent(10).len(), 4);
+
+    // Pending queries are flushed on shutdown
+    let shutdown = CancellationToken::new();
+    shutdown.cancel();
+    log.persist(db.clone(), shutdown).await;
+
+    let rows = sqlx::query("SELECT host(client_ip) AS client, qtype, rcode, cached FROM dns_query_log WHERE qname = $1")
+        .bind(&qname)
+        .fetch_all(&db)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    for row in &rows {
+        assert_eq!(row.get::<String, _>("client"), "192.0.2.44");
+        assert_eq!(row.get::<i32, _>("qtype"), 28);
+        assert_eq!(row.get::<i16, _>("rcode"), 3);
+        // Every second query is sampled
+        assert!(row.get::<bool, _>("cached"));
+    }
+
+    sqlx::query("DELETE FROM dns_query_log WHERE qname = $1").bind(&qname).execute(&db).await.unwrap();
+}
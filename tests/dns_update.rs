@@ -0,0 +1,59 @@
+mod common;
+
+use flowdns::api::models::{UpdateRecordRequest, UpdateZoneRequest};
+use flowdns::api::queries;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn record_update_changes_only_given_fields_and_bumps_serial() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("update-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    let record_id = common::insert_record(&db, zone_id, "www", "A", "192.0.2.10").await;
+
+    let update = UpdateRecordRequest {
+        value: None,
+        ttl: Some(120),
+        priority: None,
+        weight: None,
+        port: None,
+    };
+    let record = queries::update_record(&db, record_id, &update).await.unwrap().unwrap();
+    assert_eq!(record.ttl, 120);
+    assert_eq!(record.value, "192.0.2.10");
+
+    let zone = flowdns::dns::zone_queries::fetch_zone(&db, zone_id).await.unwrap().unwrap();
+    assert!(zone.serial_number > 1);
+
+    let missing = queries::update_record(&db, Uuid::new_v4(), &update).await.unwrap();
+    assert!(missing.is_none());
+
+    common::delete_zone(&db, zone_id).await;
+}
+
+#[tokio::test]
+async fn zone_update_keeps_unset_fields() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("soa-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+
+    let update = UpdateZoneRequest {
+        primary_ns: None,
+        admin_email: None,
+        refresh_interval: Some(7200),
+        retry_interval: None,
+        expire_interval: None,
+        minimum_ttl: None,
+    };
+    let zone = queries::update_zone(&db, zone_id, &update).await.unwrap().unwrap();
+    assert_eq!(zone.refresh_interval, 7200);
+    assert_eq!(zone.primary_ns.as_deref(), Some(format!("ns1.{}", zone_name).as_str()));
+    assert!(zone.serial_number > 1);
+
+    let missing = queries::update_zone(&db, Uuid::new_v4(), &update).await.unwrap();
+    assert!(missing.is_none());
+
+    common::delete_zone(&db, zone_id).await;
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use flowdns::dns::dhcid::{self, ClientIdentity};
+use flowdns::dns::dynamic_updates::{DhcpDnsIntegration, DnsUpdateScope, DynamicUpdater};
+use flowdns::dns::message::{TYPE_A, TYPE_AAAA, TYPE_PTR};
+use flowdns::dns::record_types;
+use flowdns::dns::simple_zone_manager::{SimpleZoneManager, ZoneLookup};
+use flowdns::dns::zone_queries;
+use ipnet::Ipv4Net;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn dynamic_records_are_stored_and_removed() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("ddns-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    let manager = SimpleZoneManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    let fqdn = format!("laptop.{}", zone_name);
+    let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 50));
+    manager.add_dynamic_record(&zone_name, &fqdn, ip, 300, None).await.unwrap();
+
+    let records = zone_queries::fetch_zone_records(&db, zone_id).await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].name, "laptop");
+    assert_eq!(records[0].record_type, "A");
+    assert!(records[0].is_dynamic);
+    assert!(matches!(manager.lookup(&fqdn, TYPE_A, false).await, ZoneLookup::Found(_)));
+
+    let serial = zone_queries::fetch_zone(&db, zone_id).await.unwrap().unwrap().serial_number;
+    assert!(serial > 1);
+
+    // Renewing with the same address is a no-op
+    manager.add_dynamic_record(&zone_name, &fqdn, ip, 300, None).await.unwrap();
+    let unchanged = zone_queries::fetch_zone(&db, zone_id).await.unwrap().unwrap().serial_number;
+    assert_eq!(unchanged, serial);
+
+    // A new address replaces the old one
+    let moved = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 51));
+    manager.add_dynamic_record(&zone_name, &fqdn, moved, 300, None).await.unwrap();
+    let records = zone_queries::fetch_zone_records(&db, zone_id).await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].value, "192.0.2.51");
+
+    manager.remove_dynamic_record(&zone_name, &fqdn, None).await.unwrap();
+    assert!(zone_queries::fetch_zone_records(&db, zone_id).await.unwrap().is_empty());
+    assert!(matches!(manager.lookup(&fqdn, TYPE_A, false).await, ZoneLookup::NameError(_)));
+
+    common::delete_zone(&db, zone_id).await;
+}
+
+#[tokio::test]
+async fn lease_records_get_a_matching_ptr() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("ptr-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    let manager = Arc::new(SimpleZoneManager::new(db.clone(), common::test_settings()).await.unwrap());
+    let updater = DynamicUpdater::new(Arc::clone(&manager));
+
+    // A random /24 so the auto-created reverse zone doesn't clash with other runs
+    let bytes = Uuid::new_v4().into_bytes();
+    let ip = Ipv4Addr::new(10, bytes[0], bytes[1], 7);
+    let network: Ipv4Net = format!("10.{}.{}.0/24", bytes[0], bytes[1]).parse().unwrap();
+    let reverse_zone = format!("{}.{}.10.in-addr.arpa", bytes[1], bytes[0]);
+
+    updater.add_dhcp_record("printer", IpAddr::V4(ip), &zone_name, 300, Some(&network), None).await.unwrap();
+
+    assert_eq!(manager.zone_containing(&reverse_zone).await.as_deref(), Some(reverse_zone.as_str()));
+    let ptr_name = format!("7.{}", reverse_zone);
+    match manager.lookup(&ptr_name, TYPE_PTR, false).await {
+        ZoneLookup::Found(answers) => assert_eq!(answers[0].rtype, TYPE_PTR),
+        other => panic!("expected a PTR answer, got {:?}", other),
+    }
+
+    updater.remove_reverse_record(IpAddr::V4(ip)).await.unwrap();
+    assert!(matches!(manager.lookup(&ptr_name, TYPE_PTR, false).await, ZoneLookup::NameError(_)));
+
+    let reverse_id = sqlx::query_scalar::<_, Uuid>("SELECT id FROM dns_zones WHERE name = $1")
+        .bind(&reverse_zone)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    common::delete_zone(&db, reverse_id).await;
+    common::delete_zone(&db, zone_id).await;
+}
+
+#[tokio::test]
+async fn another_client_cannot_take_over_a_name() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("dhcid-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    let manager = SimpleZoneManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    let fqdn = format!("laptop.{}", zone_name);
+    let owner_mac = common::random_mac();
+    let other_mac = common::random_mac();
+    let owner = dhcid::dhcid(ClientIdentity::Hardware { htype: 1, chaddr: &owner_mac }, &fqdn).unwrap();
+    let other = dhcid::dhcid(ClientIdentity::Hardware { htype: 1, chaddr: &other_mac }, &fqdn).unwrap();
+
+    let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 60));
+    manager.add_dynamic_record(&zone_name, &fqdn, ip, 300, Some(&owner)).await.unwrap();
+
+    // A second client asking for the same name is refused and the record keeps pointing at the owner
+    let hijack = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 61));
+    assert!(manager.add_dynamic_record(&zone_name, &fqdn, hijack, 300, Some(&other)).await.is_err());
+    assert!(manager.remove_dynamic_record(&zone_name, &fqdn, Some(&other)).await.is_err());
+    let records = zone_queries::fetch_zone_records(&db, zone_id).await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].value, "192.0.2.60");
+
+    // The owner can still move and release its name
+    let moved = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 62));
+    manager.add_dynamic_record(&zone_name, &fqdn, moved, 300, Some(&owner)).await.unwrap();
+    let records = zone_queries::fetch_zone_records(&db, zone_id).await.unwrap();
+    assert_eq!(records[0].value, "192.0.2.62");
+    manager.remove_dynamic_record(&zone_name, &fqdn, Some(&owner)).await.unwrap();
+    assert!(matches!(manager.lookup(&fqdn, TYPE_A, false).await, ZoneLookup::NameError(_)));
+
+    common::delete_zone(&db, zone_id).await;
+}
+
+#[tokio::test]
+async fn v6_leases_get_aaaa_and_ip6_arpa_records_next_to_the_a_record() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("v6-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+
+    // A random /48 so the reverse zone doesn't clash with other runs
+    let bytes = Uuid::new_v4().into_bytes();
+    let ip = Ipv6Addr::new(0xfd00 | bytes[0] as u16, u16::from_be_bytes([bytes[1], bytes[2]]), 0x0777, 0, 0, 0, 0, 0x10);
+    let ptr_name = record_types::ipv6_to_ptr_name(ip);
+    let labels: Vec<&str> = ptr_name.trim_end_matches('.').split('.').collect();
+    let reverse_zone = labels[labels.len() - 14..].join(".");
+    let reverse_id = common::insert_zone(&db, &reverse_zone).await;
+
+    let manager = Arc::new(SimpleZoneManager::new(db.clone(), common::test_settings()).await.unwrap());
+    let integration = DhcpDnsIntegration::new(Arc::clone(&manager), zone_name.clone(), 300);
+
+    // The host already has an A record from its DHCPv4 lease, claimed by hardware address
+    let fqdn = format!("laptop.{}", zone_name);
+    let mac = common::random_mac();
+    let v4_dhcid = dhcid::dhcid(ClientIdentity::Hardware { htype: 1, chaddr: &mac }, &fqdn).unwrap();
+    let v4 = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 70));
+    manager.add_dynamic_record(&zone_name, &fqdn, v4, 300, Some(&v4_dhcid)).await.unwrap();
+
+    // Over DHCPv6 it identifies itself by DUID, which must not count as another client
+    let duid = [0, 3, 0, 1, mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]];
+    integration
+        .on_v6_lease_created(Some("laptop".to_string()), ip, DnsUpdateScope::ALL, ClientIdentity::Duid(&duid))
+        .await
+        .unwrap();
+
+    assert!(matches!(manager.lookup(&fqdn, TYPE_A, false).await, ZoneLookup::Found(_)));
+    assert!(matches!(manager.lookup(&fqdn, TYPE_AAAA, false).await, ZoneLookup::Found(_)));
+    match manager.lookup(&ptr_name, TYPE_PTR, false).await {
+        ZoneLookup::Found(answers) => assert_eq!(answers[0].rtype, TYPE_PTR),
+        other => panic!("expected a PTR answer, got {:?}", other),
+    }
+
+    // Releasing the DHCPv6 lease only takes the IPv6 records with it
+    integration
+        .on_v6_lease_released(Some("laptop".to_string()), ip, ClientIdentity::Duid(&duid))
+        .await
+        .unwrap();
+    assert!(matches!(manager.lookup(&fqdn, TYPE_A, false).await, ZoneLookup::Found(_)));
+    assert!(!matches!(manager.lookup(&fqdn, TYPE_AAAA, false).await, ZoneLookup::Found(_)));
+    assert!(matches!(manager.lookup(&ptr_name, TYPE_PTR, false).await, ZoneLookup::NameError(_)));
+
+    common::delete_zone(&db, reverse_id).await;
+    common::delete_zone(&db, zone_id).await;
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use flowdns::api::models::UpdateSubnetRequest;
+use flowdns::api::queries::{self, CreateLeaseOutcome, UpdateSubnetOutcome};
+use flowdns::dhcp::lease_manager::LeaseManager;
+use std::net::Ipv4Addr;
+
+fn exclude(ranges: &[&str]) -> UpdateSubnetRequest {
+    UpdateSubnetRequest {
+        name: None,
+        start_ip: None,
+        end_ip: None,
+        gateway: None,
+        dns_servers: None,
+        domain_name: None,
+        lease_duration: None,
+        enabled: None,
+        maintenance: None,
+        relay_circuit_id: None,
+        server_ip: None,
+        boot_server: None,
+        boot_filename: None,
+        boot_rules: None,
+        excluded_ranges: Some(ranges.iter().map(|range| range.parse().unwrap()).collect()),
+        dhcp_options: None,
+        dhcpv6_rapid_commit: None,
+    }
+}
+
+#[tokio::test]
+async fn excluded_addresses_are_never_offered() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.253.2.0/24",
+        Ipv4Addr::new(10, 253, 2, 10),
+        Ipv4Addr::new(10, 253, 2, 14),
+    ).await;
+
+    // Ranges outside the subnet are rejected
+    let outcome = queries::update_subnet(&db, subnet_id, &exclude(&["10.253.3.0/30"])).await.unwrap();
+    assert!(matches!(outcome, UpdateSubnetOutcome::InvalidRange(_)));
+
+    let outcome = queries::update_subnet(&db, subnet_id, &exclude(&["10.253.2.10", "10.253.2.12-10.253.2.13"]))
+        .await
+        .unwrap();
+    assert!(matches!(outcome, UpdateSubnetOutcome::Updated(_)));
+
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+    let first = manager.find_available_ip(subnet_id, &common::random_mac()).await.unwrap();
+    assert_eq!(first, Some(Ipv4Addr::new(10, 253, 2, 11)));
+
+    common::insert_lease(&db, subnet_id, &common::random_mac(), Ipv4Addr::new(10, 253, 2, 11), None).await;
+    let second = manager.find_available_ip(subnet_id, &common::random_mac()).await.unwrap();
+    assert_eq!(second, Some(Ipv4Addr::new(10, 253, 2, 14)));
+
+    // The API refuses them when asked for one, and skips them when it picks the address
+    let outcome = queries::create_lease(&db, subnet_id, &common::random_mac(), Some(Ipv4Addr::new(10, 253, 2, 12)), None)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, CreateLeaseOutcome::Excluded));
+
+    let outcome = queries::create_lease(&db, subnet_id, &common::random_mac(), None, None).await.unwrap();
+    match outcome {
+        CreateLeaseOutcome::Created(lease) => assert_eq!(lease.ip_address, Ipv4Addr::new(10, 253, 2, 14)),
+        _ => panic!("expected a lease on the only address left"),
+    }
+    let outcome = queries::create_lease(&db, subnet_id, &common::random_mac(), None, None).await.unwrap();
+    assert!(matches!(outcome, CreateLeaseOutcome::Exhausted));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
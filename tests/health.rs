@@ -0,0 +1,36 @@
+mod common;
+
+use actix_web::{http::StatusCode, web};
+use flowdns::api::{handlers, queries, rate_limit::LoginThrottle, server::ApiState};
+use sqlx::postgres::PgPoolOptions;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn closed_pool_reports_unhealthy() {
+    // Never connects; closing it makes every acquire fail immediately
+    let db = PgPoolOptions::new()
+        .connect_lazy("postgresql://flowdns@127.0.0.1:1/flowdns")
+        .unwrap();
+    db.close().await;
+
+    assert!(!queries::check_database(&db, Duration::from_secs(1)).await);
+
+    let state = web::Data::new(ApiState {
+        db,
+        settings: common::test_settings(),
+        lease_manager: None,
+        prefix_delegation: None,
+        started_at: Instant::now(),
+        system: Mutex::new(sysinfo::System::new()),
+        login_throttle: LoginThrottle::new(5, Duration::from_secs(60)),
+    });
+    let response = handlers::system::health(state).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn live_database_reports_healthy() {
+    let Some(db) = common::test_pool().await else { return };
+    assert!(queries::check_database(&db, Duration::from_secs(2)).await);
+}
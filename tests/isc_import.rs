@@ -0,0 +1,49 @@
+mod common;
+
+use flowdns::dhcp::isc_import::{import_reservations, parse_hosts};
+use sqlx::Row;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn imports_host_declarations_into_matching_subnet() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.77.0.0/24",
+        Ipv4Addr::new(10, 77, 0, 100),
+        Ipv4Addr::new(10, 77, 0, 200),
+    ).await;
+
+    let mac = common::random_mac();
+    let mac_str = mac.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":");
+    let config = format!(
+        r#"
+        host camera {{ hardware ethernet {}; fixed-address 10.77.0.15; }}
+        host elsewhere {{ hardware ethernet 02:00:00:00:77:01; fixed-address 172.31.254.9; }}
+        "#,
+        mac_str
+    );
+
+    let parsed = parse_hosts(&config);
+    assert_eq!(parsed.hosts.len(), 2);
+
+    let report = import_reservations(&db, &parsed.hosts).await.unwrap();
+    assert_eq!(report.imported, vec!["camera (10.77.0.15)".to_string()]);
+    assert_eq!(report.unmatched, vec!["elsewhere (172.31.254.9)".to_string()]);
+
+    let row = sqlx::query("SELECT subnet_id, hostname FROM dhcp_reservations WHERE mac_address = $1")
+        .bind(&mac)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(row.get::<uuid::Uuid, _>("subnet_id"), subnet_id);
+    assert_eq!(row.get::<Option<String>, _>("hostname").as_deref(), Some("camera"));
+
+    // Importing the same file again reports the host as a conflict
+    let again = import_reservations(&db, &parsed.hosts[..1]).await.unwrap();
+    assert!(again.imported.is_empty());
+    assert_eq!(again.conflicts.len(), 1);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
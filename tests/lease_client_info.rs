@@ -0,0 +1,41 @@
+mod common;
+
+use flowdns::api::queries;
+use flowdns::dhcp::lease_manager::LeaseManager;
+use flowdns::dhcp::packet::ClientInfo;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn stores_client_classes_on_the_lease() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.246.0.0/24",
+        Ipv4Addr::new(10, 246, 0, 10),
+        Ipv4Addr::new(10, 246, 0, 20),
+    ).await;
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    let mac = common::random_mac();
+    let ip = Ipv4Addr::new(10, 246, 0, 10);
+    let client = ClientInfo {
+        client_identifier: Some("01:aa:bb:cc:00:11:22".to_string()),
+        vendor_class: Some("PXEClient:Arch:00007:UNDI:003016".to_string()),
+        user_class: Some("iPXE".to_string()),
+    };
+    let lease = manager.create_lease(subnet_id, &mac, ip, None, client.clone(), None).await.unwrap();
+    assert_eq!(lease.vendor_class, client.vendor_class);
+
+    let row = queries::fetch_lease_by_id(&db, lease.id).await.unwrap().expect("lease exists");
+    assert_eq!(row.client_identifier, client.client_identifier);
+    assert_eq!(row.vendor_class, client.vendor_class);
+    assert_eq!(row.user_class, client.user_class);
+
+    // A later request without the options replaces what the client reported before
+    let lease = manager.create_lease(subnet_id, &mac, ip, None, ClientInfo::default(), None).await.unwrap();
+    assert_eq!(lease.vendor_class, None);
+    assert_eq!(lease.user_class, None);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use flowdns::api::queries::{self, CreateLeaseOutcome};
+use flowdns::dhcp::lease_manager::LeaseManager;
+use flowdns::dhcp::lease_manager_queries;
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn concurrent_allocations_never_share_an_address() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.253.0.0/24",
+        Ipv4Addr::new(10, 253, 0, 10),
+        Ipv4Addr::new(10, 253, 0, 109),
+    ).await;
+
+    // Two managers stand in for two server processes with their own allocation maps
+    let managers = [
+        Arc::new(LeaseManager::new(db.clone(), common::test_settings()).await.unwrap()),
+        Arc::new(LeaseManager::new(db.clone(), common::test_settings()).await.unwrap()),
+    ];
+
+    // Each client does what handle_request does: check the address is free, then lease it. A
+    // conflict is the NAK the server would send, after which the client starts over.
+    let mut tasks = tokio::task::JoinSet::new();
+    for n in 0..60 {
+        let manager = managers[n % 2].clone();
+        tasks.spawn(async move {
+            let mac = common::random_mac();
+            for _ in 0..60 {
+                let ip = manager.find_available_ip(subnet_id, &mac).await.unwrap()
+                    .expect("pool has room for every client");
+                match manager.create_lease(subnet_id, &mac, ip, None, Default::default(), None).await {
+                    Ok(lease) => return lease,
+                    Err(e) => assert!(lease_manager_queries::is_address_conflict(&e), "{}", e),
+                }
+            }
+            panic!("client never got a lease");
+        });
+    }
+
+    let mut addresses = HashSet::new();
+    while let Some(result) = tasks.join_next().await {
+        let lease = result.unwrap();
+        assert!(addresses.insert(lease.ip_address), "{} handed out twice", lease.ip_address);
+    }
+    assert_eq!(addresses.len(), 60);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[tokio::test]
+async fn second_lease_on_an_active_address_is_a_conflict() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.253.1.0/24",
+        Ipv4Addr::new(10, 253, 1, 10),
+        Ipv4Addr::new(10, 253, 1, 11),
+    ).await;
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+    let ip = Ipv4Addr::new(10, 253, 1, 10);
+
+    manager.create_lease(subnet_id, &common::random_mac(), ip, None, Default::default(), None).await.unwrap();
+
+    let err = manager
+        .create_lease(subnet_id, &common::random_mac(), ip, None, Default::default(), None)
+        .await
+        .unwrap_err();
+    assert!(lease_manager_queries::is_address_conflict(&err));
+
+    // The API skips the taken address when none was requested
+    let outcome = queries::create_lease(&db, subnet_id, &common::random_mac(), None, None).await.unwrap();
+    match outcome {
+        CreateLeaseOutcome::Created(lease) => assert_eq!(lease.ip_address, Ipv4Addr::new(10, 253, 1, 11)),
+        _ => panic!("expected a lease on the second address"),
+    }
+
+    common::delete_subnet(&db, subnet_id).await;
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use flowdns::api::queries::{self, CreateLeaseOutcome};
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn creates_lease_and_rejects_conflicts() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.252.0.0/24",
+        Ipv4Addr::new(10, 252, 0, 10),
+        Ipv4Addr::new(10, 252, 0, 20),
+    ).await;
+
+    let mac = common::random_mac();
+    let requested = Ipv4Addr::new(10, 252, 0, 15);
+    let outcome = queries::create_lease(&db, subnet_id, &mac, Some(requested), Some("laptop".to_string()))
+        .await
+        .unwrap();
+    let lease = match outcome {
+        CreateLeaseOutcome::Created(lease) => *lease,
+        _ => panic!("expected the lease to be created"),
+    };
+    assert_eq!(lease.ip_address, requested);
+    assert_eq!(lease.state, "active");
+    assert!(lease.lease_end > lease.lease_start);
+
+    // Same address for another client is refused
+    let other_mac = common::random_mac();
+    let outcome = queries::create_lease(&db, subnet_id, &other_mac, Some(requested), None).await.unwrap();
+    assert!(matches!(outcome, CreateLeaseOutcome::InUse));
+
+    // Addresses outside start_ip..end_ip are refused even though they are in the network
+    let outcome = queries::create_lease(&db, subnet_id, &other_mac, Some(Ipv4Addr::new(10, 252, 0, 50)), None)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, CreateLeaseOutcome::OutOfRange { .. }));
+
+    // Without a requested address the first free one is allocated
+    let outcome = queries::create_lease(&db, subnet_id, &other_mac, None, None).await.unwrap();
+    match outcome {
+        CreateLeaseOutcome::Created(lease) => assert_eq!(lease.ip_address, Ipv4Addr::new(10, 252, 0, 10)),
+        _ => panic!("expected an address to be allocated"),
+    }
+
+    common::delete_subnet(&db, subnet_id).await;
+}
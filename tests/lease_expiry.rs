@@ -0,0 +1,65 @@
+mod common;
+
+use flowdns::dhcp::lease_events::{self, LeaseEventSource};
+use flowdns::dhcp::lease_manager::LeaseManager;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn expiring_a_lease_frees_its_address() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.253.6.0/24",
+        Ipv4Addr::new(10, 253, 6, 10),
+        Ipv4Addr::new(10, 253, 6, 10),
+    ).await;
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    let ip = Ipv4Addr::new(10, 253, 6, 10);
+    let lease = manager.create_lease(subnet_id, &common::random_mac(), ip, None, Default::default(), None).await.unwrap();
+    assert_eq!(manager.find_available_ip(subnet_id, &common::random_mac()).await.unwrap(), None);
+
+    let expired = manager.expire_lease(lease.id, LeaseEventSource::Api).await.unwrap().expect("lease expired");
+    assert_eq!(expired.state, "expired");
+    assert!(expired.lease_end <= chrono::Utc::now());
+    assert_eq!(manager.find_available_ip(subnet_id, &common::random_mac()).await.unwrap(), Some(ip));
+
+    // Only active leases can be expired or extended
+    assert!(manager.expire_lease(lease.id, LeaseEventSource::Api).await.unwrap().is_none());
+    assert!(manager.extend_lease(lease.id, 3600, LeaseEventSource::Api).await.unwrap().is_none());
+
+    let events = lease_events::fetch_history(&db, lease.id).await.unwrap();
+    let last = events.last().unwrap();
+    assert_eq!((last.old_state.as_deref(), last.new_state.as_str(), last.source.as_str()), (Some("active"), "expired", "api"));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[tokio::test]
+async fn extending_a_lease_moves_its_end() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.253.7.0/24",
+        Ipv4Addr::new(10, 253, 7, 10),
+        Ipv4Addr::new(10, 253, 7, 20),
+    ).await;
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    let lease = manager
+        .create_lease(subnet_id, &common::random_mac(), Ipv4Addr::new(10, 253, 7, 10), None, Default::default(), None)
+        .await
+        .unwrap();
+
+    let extended = manager.extend_lease(lease.id, 7 * 86400, LeaseEventSource::Api).await.unwrap().expect("lease extended");
+    assert_eq!(extended.state, "active");
+    assert_eq!(extended.lease_end - lease.lease_end, chrono::Duration::days(7));
+
+    let events = lease_events::fetch_history(&db, lease.id).await.unwrap();
+    let last = events.last().unwrap();
+    assert_eq!((last.old_state.as_deref(), last.new_state.as_str(), last.source.as_str()), (Some("active"), "active", "api"));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
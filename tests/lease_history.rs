@@ -0,0 +1,76 @@
+mod common;
+
+use flowdns::api::queries::{self, CreateLeaseOutcome};
+use flowdns::dhcp::lease_events;
+use flowdns::dhcp::lease_manager::LeaseManager;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn records_every_lease_transition() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.242.0.0/24",
+        Ipv4Addr::new(10, 242, 0, 10),
+        Ipv4Addr::new(10, 242, 0, 20),
+    ).await;
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    let mac = common::random_mac();
+    let ip = Ipv4Addr::new(10, 242, 0, 12);
+    let lease = manager.create_lease(subnet_id, &mac, ip, None, Default::default(), None).await.unwrap();
+    manager.renew_lease(&mac, ip, None).await.unwrap().expect("lease renewed");
+    manager.release_lease(&mac, ip).await.unwrap().expect("lease released");
+
+    // Taking the address again through the API reuses the lease row
+    let outcome = queries::create_lease(&db, subnet_id, &mac, Some(ip), None).await.unwrap();
+    assert!(matches!(outcome, CreateLeaseOutcome::Created(_)));
+    assert_eq!(queries::release_lease(&db, lease.id).await.unwrap(), 1);
+
+    let events = lease_events::fetch_history(&db, lease.id).await.unwrap();
+    let transitions: Vec<(Option<&str>, &str, &str)> = events
+        .iter()
+        .map(|e| (e.old_state.as_deref(), e.new_state.as_str(), e.source.as_str()))
+        .collect();
+    assert_eq!(transitions, vec![
+        (None, "active", "dhcp"),
+        (Some("active"), "active", "dhcp"),
+        (Some("active"), "released", "dhcp"),
+        (Some("released"), "active", "api"),
+        (Some("active"), "released", "api"),
+    ]);
+    assert!(events.iter().all(|e| e.mac_address == mac && e.ip_address == ip));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[tokio::test]
+async fn cleanup_records_expiry() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.242.1.0/24",
+        Ipv4Addr::new(10, 242, 1, 10),
+        Ipv4Addr::new(10, 242, 1, 20),
+    ).await;
+    let mac = common::random_mac();
+    let lease_id = common::insert_lease(&db, subnet_id, &mac, Ipv4Addr::new(10, 242, 1, 10), None).await;
+    sqlx::query("UPDATE dhcp_leases SET lease_end = NOW() - INTERVAL '1 minute' WHERE id = $1")
+        .bind(lease_id)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+    assert!(manager.cleanup_expired_leases().await.unwrap() >= 1);
+
+    let events = lease_events::fetch_history(&db, lease_id).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].old_state.as_deref(), Some("active"));
+    assert_eq!(events[0].new_state, "expired");
+    assert_eq!(events[0].source, "dhcp");
+
+    common::delete_subnet(&db, subnet_id).await;
+}
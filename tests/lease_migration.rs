@@ -0,0 +1,81 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use flowdns::dhcp::lease_migration::{self, EntryKind, ImportOutcome, MigrationEntry};
+use std::net::Ipv4Addr;
+
+fn entry(kind: EntryKind, mac: &[u8], ip: Ipv4Addr) -> MigrationEntry {
+    let lease = kind == EntryKind::Lease;
+    MigrationEntry {
+        kind,
+        mac_address: mac.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":"),
+        ip_address: ip,
+        hostname: Some("migrated".to_string()),
+        lease_start: lease.then(Utc::now),
+        lease_end: lease.then(|| Utc::now() + Duration::hours(1)),
+        state: None,
+        description: None,
+    }
+}
+
+#[tokio::test]
+async fn import_then_export_round_trips_and_rejects_bad_batches() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let source = common::insert_subnet(&db, "10.231.0.0/24", Ipv4Addr::new(10, 231, 0, 10), Ipv4Addr::new(10, 231, 0, 200)).await;
+    let target = common::insert_subnet(&db, "10.232.0.0/24", Ipv4Addr::new(10, 232, 0, 10), Ipv4Addr::new(10, 232, 0, 200)).await;
+
+    let lease_mac = common::random_mac();
+    let reservation_mac = common::random_mac();
+    common::insert_lease(&db, source, &lease_mac, Ipv4Addr::new(10, 231, 0, 50), Some("laptop")).await;
+    common::insert_reservation(&db, source, &reservation_mac, Ipv4Addr::new(10, 231, 0, 5)).await;
+
+    let exported = lease_migration::export_subnet(&db, source).await.unwrap();
+    assert_eq!(exported.len(), 2);
+    assert_eq!(exported[0].kind, EntryKind::Reservation);
+    assert_eq!(exported[1].kind, EntryKind::Lease);
+    assert_eq!(exported[1].hostname.as_deref(), Some("laptop"));
+
+    // A bad entry rejects the whole batch
+    let batch = vec![
+        entry(EntryKind::Reservation, &common::random_mac(), Ipv4Addr::new(10, 232, 0, 5)),
+        entry(EntryKind::Lease, &common::random_mac(), Ipv4Addr::new(10, 231, 0, 60)),
+    ];
+    match lease_migration::import_subnet(&db, target, &batch).await.unwrap() {
+        ImportOutcome::Rejected(problems) => {
+            assert_eq!(problems.len(), 1);
+            assert!(problems[0].contains("outside"));
+        }
+        _ => panic!("expected the batch to be rejected"),
+    }
+    assert!(lease_migration::export_subnet(&db, target).await.unwrap().is_empty());
+
+    // MACs are unique across subnets, so the source rows have to go before they can move
+    common::delete_subnet(&db, source).await;
+
+    let moved: Vec<MigrationEntry> = exported
+        .into_iter()
+        .map(|mut entry| {
+            entry.ip_address = Ipv4Addr::new(10, 232, 0, entry.ip_address.octets()[3]);
+            entry
+        })
+        .collect();
+    let csv = lease_migration::to_csv(&moved);
+    let parsed = lease_migration::parse_csv(&csv).unwrap();
+
+    match lease_migration::import_subnet(&db, target, &parsed).await.unwrap() {
+        ImportOutcome::Imported(report) => {
+            assert_eq!(report.leases, 1);
+            assert_eq!(report.reservations, 1);
+        }
+        _ => panic!("expected the import to succeed"),
+    }
+
+    // Importing the same entries again clashes with what is now there
+    assert!(matches!(
+        lease_migration::import_subnet(&db, target, &parsed).await.unwrap(),
+        ImportOutcome::Rejected(_)
+    ));
+
+    common::delete_subnet(&db, target).await;
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use flowdns::api::queries::{self, ReserveLeaseOutcome};
+use sqlx::Row;
+use std::net::{IpAddr, Ipv4Addr};
+
+#[tokio::test]
+async fn converts_lease_into_reservation() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.251.0.0/24",
+        Ipv4Addr::new(10, 251, 0, 10),
+        Ipv4Addr::new(10, 251, 0, 200),
+    ).await;
+    let mac = common::random_mac();
+    let ip = Ipv4Addr::new(10, 251, 0, 42);
+    let lease_id = common::insert_lease(&db, subnet_id, &mac, ip, Some("printer")).await;
+
+    let outcome = queries::reserve_lease(&db, lease_id, Some("front desk".to_string()), true)
+        .await
+        .unwrap();
+
+    let reservation = match outcome {
+        ReserveLeaseOutcome::Reserved(res) => res,
+        _ => panic!("expected the lease to be reserved"),
+    };
+    assert_eq!(reservation.subnet_id, subnet_id);
+    assert_eq!(reservation.mac_address, mac);
+    assert_eq!(reservation.ip_address, ip);
+    assert_eq!(reservation.hostname.as_deref(), Some("printer"));
+
+    let row = sqlx::query("SELECT ip_address FROM dhcp_reservations WHERE mac_address = $1")
+        .bind(&mac)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(row.get::<IpAddr, _>("ip_address"), IpAddr::V4(ip));
+
+    let remaining: i64 = sqlx::query("SELECT COUNT(*) as count FROM dhcp_leases WHERE id = $1")
+        .bind(lease_id)
+        .fetch_one(&db)
+        .await
+        .unwrap()
+        .get("count");
+    assert_eq!(remaining, 0);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[tokio::test]
+async fn rejects_already_reserved_ip() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.252.0.0/24",
+        Ipv4Addr::new(10, 252, 0, 10),
+        Ipv4Addr::new(10, 252, 0, 200),
+    ).await;
+    let ip = Ipv4Addr::new(10, 252, 0, 50);
+
+    sqlx::query("INSERT INTO dhcp_reservations (subnet_id, mac_address, ip_address) VALUES ($1, $2, $3)")
+        .bind(subnet_id)
+        .bind(common::random_mac())
+        .bind(IpAddr::V4(ip))
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let lease_id = common::insert_lease(&db, subnet_id, &common::random_mac(), ip, None).await;
+
+    let outcome = queries::reserve_lease(&db, lease_id, None, false).await.unwrap();
+    assert!(matches!(outcome, ReserveLeaseOutcome::Conflict(_)));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[tokio::test]
+async fn rejects_address_leased_to_another_client() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.253.8.0/24",
+        Ipv4Addr::new(10, 253, 8, 10),
+        Ipv4Addr::new(10, 253, 8, 200),
+    ).await;
+    let ip = Ipv4Addr::new(10, 253, 8, 60);
+
+    // The first client let the address go and a second one picked it up
+    let old_lease = common::insert_lease(&db, subnet_id, &common::random_mac(), ip, None).await;
+    assert_eq!(queries::release_lease(&db, old_lease).await.unwrap(), 1);
+    common::insert_lease(&db, subnet_id, &common::random_mac(), ip, None).await;
+
+    let outcome = queries::reserve_lease(&db, old_lease, None, false).await.unwrap();
+    assert!(matches!(outcome, ReserveLeaseOutcome::Conflict(_)));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
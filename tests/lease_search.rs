@@ -0,0 +1,52 @@
+mod common;
+
+use flowdns::api::models::{LeaseFilter, Pagination};
+use flowdns::api::queries;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn filters_combine() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.253.9.0/24",
+        Ipv4Addr::new(10, 253, 9, 10),
+        Ipv4Addr::new(10, 253, 9, 200),
+    ).await;
+    let printer_mac = common::random_mac();
+    common::insert_lease(&db, subnet_id, &printer_mac, Ipv4Addr::new(10, 253, 9, 10), Some("Printer-2F")).await;
+    common::insert_lease(&db, subnet_id, &common::random_mac(), Ipv4Addr::new(10, 253, 9, 11), Some("laptop_01")).await;
+    common::insert_lease(&db, subnet_id, &common::random_mac(), Ipv4Addr::new(10, 253, 9, 12), Some("laptop01")).await;
+
+    let search = |filter: LeaseFilter| {
+        let db = db.clone();
+        async move {
+            let (leases, total) = queries::fetch_active_leases(&db, &filter, Pagination::default()).await.unwrap();
+            assert_eq!(leases.len() as i64, total);
+            leases.into_iter().map(|lease| lease.ip_address.octets()[3]).collect::<Vec<_>>()
+        }
+    };
+    let in_subnet = LeaseFilter { subnet_id: Some(subnet_id), ..Default::default() };
+
+    let mut all = search(in_subnet.clone()).await;
+    all.sort();
+    assert_eq!(all, vec![10, 11, 12]);
+
+    assert_eq!(search(LeaseFilter { hostname: Some("printer".to_string()), ..in_subnet.clone() }).await, vec![10]);
+    // An underscore is matched literally, not as a wildcard
+    assert_eq!(search(LeaseFilter { hostname: Some("laptop_".to_string()), ..in_subnet.clone() }).await, vec![11]);
+    assert_eq!(search(LeaseFilter { mac_address: Some(printer_mac), ..in_subnet.clone() }).await, vec![10]);
+    assert_eq!(search(LeaseFilter { ip_address: Some(Ipv4Addr::new(10, 253, 9, 12)), ..in_subnet.clone() }).await, vec![12]);
+
+    // Every filter has to match
+    let none = LeaseFilter {
+        hostname: Some("printer".to_string()),
+        ip_address: Some(Ipv4Addr::new(10, 253, 9, 11)),
+        ..in_subnet.clone()
+    };
+    assert!(search(none).await.is_empty());
+    assert!(search(LeaseFilter { state: "expired".to_string(), ..in_subnet }).await.is_empty());
+
+    common::delete_subnet(&db, subnet_id).await;
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use flowdns::dhcp::lease_manager::LeaseManager;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn requested_lease_time_is_clamped() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let settings = common::test_settings();
+    let (min, max) = (settings.dhcp.min_lease_time as i64, settings.dhcp.max_lease_time as i64);
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.243.0.0/24",
+        Ipv4Addr::new(10, 243, 0, 10),
+        Ipv4Addr::new(10, 243, 0, 20),
+    ).await;
+    let manager = LeaseManager::new(db.clone(), settings).await.unwrap();
+
+    // Too short a request is raised to the minimum
+    let mac = common::random_mac();
+    let ip = Ipv4Addr::new(10, 243, 0, 10);
+    let lease = manager.create_lease(subnet_id, &mac, ip, None, Default::default(), Some(1)).await.unwrap();
+    assert_eq!((lease.lease_end - lease.lease_start).num_seconds(), min);
+
+    // Too long a renewal is capped at the maximum
+    let renewed = manager.renew_lease(&mac, ip, Some(u32::MAX)).await.unwrap().expect("lease renewed");
+    let remaining = (renewed.lease_end - chrono::Utc::now()).num_seconds();
+    assert!(remaining <= max && remaining > max - 60);
+
+    // Without option 51 the subnet's lease time applies
+    let other = manager.create_lease(subnet_id, &common::random_mac(), Ipv4Addr::new(10, 243, 0, 11), None, Default::default(), None).await.unwrap();
+    assert_eq!((other.lease_end - other.lease_start).num_seconds(), 86400);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use flowdns::api::queries;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn available_addresses_subtract_active_leases_from_ranges() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let before = queries::get_available_addresses(&db).await.unwrap();
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.247.0.0/24",
+        Ipv4Addr::new(10, 247, 0, 10),
+        Ipv4Addr::new(10, 247, 0, 20),
+    ).await;
+    assert_eq!(queries::get_available_addresses(&db).await.unwrap(), before + 11);
+
+    common::insert_lease(&db, subnet_id, &[0x02, 0, 0, 0, 0x02, 0x71], Ipv4Addr::new(10, 247, 0, 10), None).await;
+    assert_eq!(queries::get_available_addresses(&db).await.unwrap(), before + 10);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
@@ -0,0 +1,15 @@
+mod common;
+
+use flowdns::database::{self, MigrationState};
+
+#[tokio::test]
+async fn migration_status_reports_everything_applied() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let status = database::migration_status(&db).await.unwrap();
+    assert!(!status.is_empty());
+    assert!(status.windows(2).all(|pair| pair[0].version < pair[1].version));
+    assert!(status.iter().all(|migration| migration.state == MigrationState::Applied));
+    assert_eq!(status[0].version, 1);
+    assert_eq!(status[0].description, "initial schema");
+}
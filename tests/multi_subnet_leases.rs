@@ -0,0 +1,57 @@
+mod common;
+
+use flowdns::dhcp::lease_manager::LeaseManager;
+use sqlx::Row;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn one_mac_holds_a_lease_in_each_subnet() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let tagged = common::insert_subnet(
+        &db,
+        "10.253.3.0/24",
+        Ipv4Addr::new(10, 253, 3, 10),
+        Ipv4Addr::new(10, 253, 3, 20),
+    ).await;
+    let untagged = common::insert_subnet(
+        &db,
+        "10.253.4.0/24",
+        Ipv4Addr::new(10, 253, 4, 10),
+        Ipv4Addr::new(10, 253, 4, 20),
+    ).await;
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+    let mac = common::random_mac();
+
+    let first_ip = manager.find_available_ip(tagged, &mac).await.unwrap().unwrap();
+    let first = manager.create_lease(tagged, &mac, first_ip, None, Default::default(), None).await.unwrap();
+    let second_ip = manager.find_available_ip(untagged, &mac).await.unwrap().unwrap();
+    let second = manager.create_lease(untagged, &mac, second_ip, None, Default::default(), None).await.unwrap();
+    assert_ne!(first.id, second.id);
+    assert_eq!(first.ip_address, Ipv4Addr::new(10, 253, 3, 10));
+    assert_eq!(second.ip_address, Ipv4Addr::new(10, 253, 4, 10));
+
+    // Both leases stay active; the second didn't move the first
+    let rows = sqlx::query("SELECT subnet_id FROM dhcp_leases WHERE mac_address = $1 AND state = 'active'")
+        .bind(&mac)
+        .fetch_all(&db)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert!(rows.iter().any(|row| row.get::<uuid::Uuid, _>("subnet_id") == tagged));
+
+    // Each subnet hands the client back its own address
+    assert_eq!(manager.find_available_ip(tagged, &mac).await.unwrap(), Some(first.ip_address));
+    assert_eq!(manager.find_available_ip(untagged, &mac).await.unwrap(), Some(second.ip_address));
+
+    // A new lease in one subnet replaces only the lease there
+    let moved = manager
+        .create_lease(tagged, &mac, Ipv4Addr::new(10, 253, 3, 15), None, Default::default(), None)
+        .await
+        .unwrap();
+    assert_eq!(moved.id, first.id);
+    assert_eq!(manager.find_available_ip(untagged, &mac).await.unwrap(), Some(second.ip_address));
+
+    common::delete_subnet(&db, tagged).await;
+    common::delete_subnet(&db, untagged).await;
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use flowdns::ipv6::prefix_delegation::{PrefixDelegationManager, PrefixPool};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+async fn clear_delegations(db: &sqlx::PgPool) {
+    sqlx::query("DELETE FROM ipv6_delegated_prefixes WHERE prefix <<= 'fd00:2266::/62'::inet")
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn allocates_each_prefix_once_until_the_pool_is_exhausted() {
+    let Some(db) = common::test_pool().await else { return };
+    clear_delegations(&db).await;
+
+    // A /62 split into /64s holds exactly four delegations
+    let manager = PrefixDelegationManager::with_pools(db.clone(), vec![PrefixPool {
+        id: Uuid::new_v4(),
+        name: "test-exhaustion".to_string(),
+        prefix: "fd00:2266::".parse().unwrap(),
+        prefix_length: 62,
+        delegation_length: 64,
+        total_prefixes: 4,
+        available_prefixes: 4,
+    }]);
+
+    let mut delegated = Vec::new();
+    for client in 0u8..4 {
+        let delegation = manager.request_prefix(vec![0, 3, 0, 1, client], 1, Some(64), None).await.unwrap();
+        assert_eq!(delegation.delegated_length, 64);
+        delegated.push(delegation.prefix);
+    }
+    assert_eq!(delegated.iter().collect::<HashSet<_>>().len(), 4);
+
+    let err = manager.request_prefix(vec![0, 3, 0, 1, 4], 1, Some(64), None).await.unwrap_err();
+    assert_eq!(err.to_string(), "No prefixes available");
+
+    // A released prefix goes back into the pool
+    manager.release_prefix(&[0, 3, 0, 1, 2], 1, &delegated[2]).await.unwrap();
+    let reused = manager.request_prefix(vec![0, 3, 0, 1, 4], 1, Some(64), None).await.unwrap();
+    assert_eq!(reused.prefix, delegated[2]);
+
+    clear_delegations(&db).await;
+}
+
+#[tokio::test]
+async fn renewing_returns_the_existing_delegation() {
+    let Some(db) = common::test_pool().await else { return };
+    sqlx::query("DELETE FROM ipv6_delegated_prefixes WHERE prefix <<= 'fd00:2267::/62'::inet")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let manager = PrefixDelegationManager::with_pools(db.clone(), vec![PrefixPool {
+        id: Uuid::new_v4(),
+        name: "test-renewal".to_string(),
+        prefix: "fd00:2267::".parse().unwrap(),
+        prefix_length: 62,
+        delegation_length: 64,
+        total_prefixes: 4,
+        available_prefixes: 4,
+    }]);
+
+    let duid = vec![0, 3, 0, 1, 0x67];
+    let first = manager.request_prefix(duid.clone(), 9, Some(64), Some(3600)).await.unwrap();
+    let renewed = manager.request_prefix(duid.clone(), 9, Some(64), Some(3600)).await.unwrap();
+    assert_eq!(renewed.prefix, first.prefix);
+    assert_eq!(renewed.id, first.id);
+    assert!(renewed.lease_end >= first.lease_end);
+
+    // Renewing again and again never eats into the pool
+    for _ in 0..4 {
+        manager.request_prefix(duid.clone(), 9, Some(64), Some(3600)).await.unwrap();
+    }
+    let other = manager.request_prefix(vec![0, 3, 0, 1, 0x68], 9, Some(64), None).await.unwrap();
+    assert_ne!(other.prefix, first.prefix);
+
+    sqlx::query("DELETE FROM ipv6_delegated_prefixes WHERE prefix <<= 'fd00:2267::/62'::inet")
+        .execute(&db)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn statistics_count_delegations_by_state() {
+    let Some(db) = common::test_pool().await else { return };
+    sqlx::query("DELETE FROM ipv6_delegated_prefixes WHERE prefix <<= 'fd00:2270::/62'::inet")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let manager = PrefixDelegationManager::with_pools(db.clone(), vec![PrefixPool {
+        id: Uuid::new_v4(),
+        name: "test-statistics".to_string(),
+        prefix: "fd00:2270::".parse().unwrap(),
+        prefix_length: 62,
+        delegation_length: 64,
+        total_prefixes: 4,
+        available_prefixes: 4,
+    }]);
+
+    let before = manager.get_statistics().await.unwrap();
+    let first = manager.request_prefix(vec![0, 3, 0, 1, 0x70], 1, Some(64), None).await.unwrap();
+    manager.request_prefix(vec![0, 3, 0, 1, 0x71], 1, Some(64), None).await.unwrap();
+    manager.release_prefix(&[0, 3, 0, 1, 0x70], 1, &first.prefix).await.unwrap();
+
+    // Other tests may run concurrently, so compare against the counts taken before
+    let after = manager.get_statistics().await.unwrap();
+    assert_eq!(after.total_pools, 1);
+    assert!(after.delegated_prefixes > before.delegated_prefixes);
+    assert!(after.available_prefixes > before.available_prefixes);
+
+    sqlx::query("DELETE FROM ipv6_delegated_prefixes WHERE prefix <<= 'fd00:2270::/62'::inet")
+        .execute(&db)
+        .await
+        .unwrap();
+}
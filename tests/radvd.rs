@@ -0,0 +1,63 @@
+mod common;
+
+use flowdns::ipv6::radvd::{AdvertisedSubnet, LinkAddresses, RadvdManager};
+use std::sync::Arc;
+
+fn subnet(name: &str, network: &str, prefix: &str, stateful: bool) -> AdvertisedSubnet {
+    AdvertisedSubnet {
+        name: name.to_string(),
+        network: network.parse().unwrap(),
+        prefix: prefix.parse().unwrap(),
+        domain_name: Some(format!("{}.example.com", name)),
+        stateful,
+    }
+}
+
+#[test]
+fn radvd_config_follows_the_subnets() {
+    let mut settings = (*common::test_settings()).clone();
+    settings.ipv6.enabled = true;
+    settings.dns.enabled = true;
+    let manager = RadvdManager::new(Arc::new(settings.clone()));
+
+    let subnets = vec![
+        subnet("office", "192.168.10.0/24", "fd00:10::/64", false),
+        subnet("lab", "192.168.20.0/24", "fd00:20::/64", true),
+        subnet("remote", "192.168.30.0/24", "fd00:30::/64", false),
+    ];
+    let links = vec![
+        LinkAddresses {
+            name: "eth1".to_string(),
+            addresses: vec!["192.168.10.1".parse().unwrap(), "fd00:10::1".parse().unwrap(), "fe80::1".parse().unwrap()],
+        },
+        // Found through its IPv6 address alone
+        LinkAddresses {
+            name: "eth2".to_string(),
+            addresses: vec!["fd00:20::1".parse().unwrap()],
+        },
+    ];
+
+    let config = manager.config_for_subnets(&subnets, &links);
+    // No interface is on the remote subnet's link
+    assert_eq!(config.interfaces.len(), 2);
+
+    let office = &config.interfaces[0];
+    assert_eq!(office.name, "eth1");
+    assert_eq!((office.prefix.as_str(), office.prefix_length), ("fd00:10::", 64));
+    assert!(!office.managed_flag);
+    assert!(office.other_config_flag);
+    assert_eq!(office.rdnss_servers, vec!["fd00:10::1".to_string()]);
+    assert_eq!(office.dnssl_domains, vec!["office.example.com".to_string()]);
+    assert_eq!(office.default_lifetime, settings.ipv6.router_lifetime);
+
+    let lab = &config.interfaces[1];
+    assert_eq!(lab.name, "eth2");
+    assert!(lab.managed_flag);
+
+    // Without DHCPv6 or a local DNS server, hosts are told to use SLAAC and nothing else
+    settings.ipv6.enabled = false;
+    settings.dns.enabled = false;
+    let config = RadvdManager::new(Arc::new(settings)).config_for_subnets(&subnets, &links);
+    assert!(config.interfaces.iter().all(|interface| !interface.managed_flag && !interface.other_config_flag));
+    assert!(config.interfaces.iter().all(|interface| interface.rdnss_servers.is_empty()));
+}
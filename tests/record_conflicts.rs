@@ -0,0 +1,79 @@
+mod common;
+
+use flowdns::api::models::CreateRecordRequest;
+use flowdns::api::queries::{self, CreateRecordOutcome};
+use uuid::Uuid;
+
+fn request(name: &str, record_type: &str, value: &str) -> CreateRecordRequest {
+    CreateRecordRequest {
+        name: name.to_string(),
+        record_type: record_type.to_string(),
+        value: value.to_string(),
+        ttl: None,
+        priority: None,
+        weight: None,
+        port: None,
+        view: None,
+    }
+}
+
+#[tokio::test]
+async fn create_record_rejects_conflicts() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("conflict-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    common::insert_record(&db, zone_id, "www", "A", "192.0.2.10").await;
+    common::insert_record(&db, zone_id, "alias", "CNAME", "www").await;
+
+    // CNAME next to an A record
+    let outcome = queries::create_record(&db, zone_id, &request("www", "CNAME", "elsewhere.test.")).await.unwrap();
+    assert!(matches!(outcome, CreateRecordOutcome::Conflict(_)));
+
+    // MX next to a CNAME, with the owner written as an absolute name
+    let mut mx = request(&format!("alias.{}.", zone_name), "MX", "mail.test.");
+    mx.priority = Some(10);
+    let outcome = queries::create_record(&db, zone_id, &mx).await.unwrap();
+    assert!(matches!(outcome, CreateRecordOutcome::Conflict(_)));
+
+    // Only one SOA per zone
+    let soa = "ns1.test. admin.test. 1 3600 600 86400 300";
+    let outcome = queries::create_record(&db, zone_id, &request("@", "SOA", soa)).await.unwrap();
+    assert!(matches!(outcome, CreateRecordOutcome::Created(_)));
+    let outcome = queries::create_record(&db, zone_id, &request("@", "SOA", soa)).await.unwrap();
+    assert!(matches!(outcome, CreateRecordOutcome::Conflict(_)));
+
+    // A second address for the same name is fine
+    let outcome = queries::create_record(&db, zone_id, &request("www", "A", "192.0.2.11")).await.unwrap();
+    assert!(matches!(outcome, CreateRecordOutcome::Created(_)));
+
+    let outcome = queries::create_record(&db, Uuid::new_v4(), &request("www", "A", "192.0.2.12")).await.unwrap();
+    assert!(matches!(outcome, CreateRecordOutcome::ZoneNotFound));
+
+    common::delete_zone(&db, zone_id).await;
+}
+
+#[tokio::test]
+async fn views_are_checked_separately() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("views-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    common::insert_record(&db, zone_id, "www", "A", "203.0.113.10").await;
+
+    // The internal view's www replaces the default one, so a CNAME there doesn't conflict
+    let mut internal = request("www", "CNAME", "intranet.test.");
+    internal.view = Some("internal".to_string());
+    let outcome = queries::create_record(&db, zone_id, &internal).await.unwrap();
+    assert!(matches!(outcome, CreateRecordOutcome::Created(_)));
+
+    // The same record again in the same view does
+    let outcome = queries::create_record(&db, zone_id, &internal).await.unwrap();
+    assert!(matches!(outcome, CreateRecordOutcome::Conflict(_)));
+
+    let records = flowdns::dns::zone_queries::fetch_zone_records(&db, zone_id).await.unwrap();
+    assert!(records.iter().any(|record| record.record_type == "CNAME" && record.view == "internal"));
+    assert!(records.iter().any(|record| record.record_type == "A" && record.view == "default"));
+
+    common::delete_zone(&db, zone_id).await;
+}
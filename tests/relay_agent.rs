@@ -0,0 +1,39 @@
+mod common;
+
+use flowdns::dhcp::lease_manager::LeaseManager;
+use flowdns::dhcp::packet::RelayAgentInfo;
+use std::net::Ipv4Addr;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn circuit_id_selects_subnet_before_giaddr() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let relay_subnet = common::insert_subnet(&db, "10.245.0.0/24", Ipv4Addr::new(10, 245, 0, 10), Ipv4Addr::new(10, 245, 0, 20)).await;
+    let port_subnet = common::insert_subnet(&db, "10.245.1.0/24", Ipv4Addr::new(10, 245, 1, 10), Ipv4Addr::new(10, 245, 1, 20)).await;
+
+    let circuit_id = format!("port-{}", Uuid::new_v4().simple());
+    sqlx::query("UPDATE dhcp_subnets SET relay_circuit_id = $2 WHERE id = $1")
+        .bind(port_subnet)
+        .bind(&circuit_id)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+    let giaddr = Some(Ipv4Addr::new(10, 245, 0, 1));
+
+    let matching = RelayAgentInfo { circuit_id: Some(circuit_id.into_bytes()), remote_id: None };
+    let subnet = manager.find_subnet_for_client(Ipv4Addr::UNSPECIFIED, giaddr, Some(&matching)).await.unwrap();
+    assert_eq!(subnet.id, port_subnet);
+
+    // Unknown circuit-ids and requests without option 82 fall back to the relay address
+    let other = RelayAgentInfo { circuit_id: Some(b"unknown-port".to_vec()), remote_id: None };
+    let subnet = manager.find_subnet_for_client(Ipv4Addr::UNSPECIFIED, giaddr, Some(&other)).await.unwrap();
+    assert_eq!(subnet.id, relay_subnet);
+    let subnet = manager.find_subnet_for_client(Ipv4Addr::UNSPECIFIED, giaddr, None).await.unwrap();
+    assert_eq!(subnet.id, relay_subnet);
+
+    common::delete_subnet(&db, relay_subnet).await;
+    common::delete_subnet(&db, port_subnet).await;
+}
@@ -0,0 +1,32 @@
+use actix_web::{test, web, App, HttpMessage, HttpRequest, HttpResponse};
+use flowdns::api::request_id::{RequestId, RequestIdMiddleware};
+
+async fn echo_id(req: HttpRequest) -> HttpResponse {
+    let id = req.extensions().get::<RequestId>().map(|id| id.0.clone()).unwrap_or_default();
+    HttpResponse::Ok().body(id)
+}
+
+#[actix_web::test]
+async fn request_id_is_generated_or_passed_through() {
+    let app = test::init_service(
+        App::new()
+            .wrap(RequestIdMiddleware)
+            .route("/api/v1/system/health", web::get().to(echo_id)),
+    ).await;
+
+    // Without a header a fresh id is made, shared by the handler and the response
+    let request = test::TestRequest::get().uri("/api/v1/system/health").to_request();
+    let response = test::call_service(&app, request).await;
+    let header = response.headers().get("X-Request-Id").unwrap().to_str().unwrap().to_string();
+    assert!(!header.is_empty());
+    assert_eq!(test::read_body(response).await, header.as_bytes());
+
+    // A caller's id is kept so its logs line up with ours
+    let request = test::TestRequest::get()
+        .uri("/api/v1/system/health")
+        .insert_header(("X-Request-Id", "deploy-42"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.headers().get("X-Request-Id").unwrap(), "deploy-42");
+    assert_eq!(test::read_body(response).await, "deploy-42".as_bytes());
+}
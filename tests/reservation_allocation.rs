@@ -0,0 +1,61 @@
+mod common;
+
+use flowdns::dhcp::lease_manager::LeaseManager;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn reserved_address_is_never_offered_to_another_client() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.254.0.0/24",
+        Ipv4Addr::new(10, 254, 0, 10),
+        Ipv4Addr::new(10, 254, 0, 13),
+    ).await;
+    let (mac_a, mac_b) = (common::random_mac(), common::random_mac());
+    let reserved = Ipv4Addr::new(10, 254, 0, 10);
+    common::insert_reservation(&db, subnet_id, &mac_a, reserved).await;
+
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    // The first free address in the pool belongs to MAC-A even though it holds no lease
+    let offered = manager.find_available_ip(subnet_id, &mac_b).await.unwrap();
+    assert_eq!(offered, Some(Ipv4Addr::new(10, 254, 0, 11)));
+    assert_eq!(manager.find_available_ip(subnet_id, &mac_a).await.unwrap(), Some(reserved));
+
+    // A lease MAC-B held before the address was reserved for someone else isn't handed back
+    let mac_c = common::random_mac();
+    let taken = Ipv4Addr::new(10, 254, 0, 12);
+    common::insert_lease(&db, subnet_id, &mac_b, taken, None).await;
+    common::insert_reservation(&db, subnet_id, &mac_c, taken).await;
+    let offered = manager.find_available_ip(subnet_id, &mac_b).await.unwrap();
+    assert_eq!(offered, Some(Ipv4Addr::new(10, 254, 0, 11)));
+
+    // Every other address is reserved or leased, so MAC-B still never gets a reserved one
+    common::insert_lease(&db, subnet_id, &common::random_mac(), Ipv4Addr::new(10, 254, 0, 11), None).await;
+    common::insert_reservation(&db, subnet_id, &common::random_mac(), Ipv4Addr::new(10, 254, 0, 13)).await;
+    assert_eq!(manager.find_available_ip(subnet_id, &mac_b).await.unwrap(), None);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[tokio::test]
+async fn reservation_outside_the_subnet_is_ignored() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.254.1.0/24",
+        Ipv4Addr::new(10, 254, 1, 10),
+        Ipv4Addr::new(10, 254, 1, 20),
+    ).await;
+    let mac = common::random_mac();
+    common::insert_reservation(&db, subnet_id, &mac, Ipv4Addr::new(172, 31, 254, 9)).await;
+
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+    let offered = manager.find_available_ip(subnet_id, &mac).await.unwrap();
+    assert_eq!(offered, Some(Ipv4Addr::new(10, 254, 1, 10)));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
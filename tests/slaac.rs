@@ -0,0 +1,44 @@
+mod common;
+
+use flowdns::ipv6::slaac::SlaacManager;
+use std::net::Ipv6Addr;
+
+#[tokio::test]
+async fn registered_addresses_are_returned_by_mac_newest_first() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let mac = vec![0x02, 0x68, 0x22, 0x68, 0x00, 0x01];
+    sqlx::query("DELETE FROM ipv6_slaac_addresses WHERE mac_address = $1")
+        .bind(&mac)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let manager = SlaacManager::new(db.clone());
+    let prefix: Ipv6Addr = "2001:db8:2268::".parse().unwrap();
+    let eui64 = manager.generate_eui64_address(&prefix, &mac).unwrap();
+    let privacy: Ipv6Addr = "2001:db8:2268::1234".parse().unwrap();
+
+    let first = manager.register_slaac_address(mac.clone(), eui64, prefix, 64, None).await.unwrap();
+    manager.register_slaac_address(mac.clone(), privacy, prefix, 64, Some("laptop".to_string())).await.unwrap();
+
+    let addresses = manager.get_addresses_by_mac(&mac).await.unwrap();
+    assert_eq!(addresses.len(), 2);
+    assert_eq!(addresses[0].ipv6_address, privacy);
+    assert_eq!(addresses[0].hostname.as_deref(), Some("laptop"));
+    assert_eq!(addresses[1].ipv6_address, eui64);
+    assert_eq!(addresses[1].id, first.id);
+    assert_eq!(addresses[1].prefix, prefix);
+    assert_eq!(addresses[1].prefix_length, 64);
+    assert_eq!(addresses[1].mac_address, mac);
+
+    // Without a DAD interface only registered addresses count as duplicates
+    assert!(!manager.perform_dad(&eui64).await.unwrap());
+    assert!(manager.perform_dad(&"2001:db8:2268::5678".parse().unwrap()).await.unwrap());
+
+    sqlx::query("DELETE FROM ipv6_slaac_addresses WHERE mac_address = $1")
+        .bind(&mac)
+        .execute(&db)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use flowdns::api::models::{Pagination, MAX_PAGE_LIMIT};
+use flowdns::api::queries;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn fetch_all_subnets_maps_columns() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.240.0.0/24",
+        Ipv4Addr::new(10, 240, 0, 10),
+        Ipv4Addr::new(10, 240, 0, 20),
+    ).await;
+    common::set_subnet_maintenance(&db, subnet_id, true).await;
+
+    let page = Pagination { limit: MAX_PAGE_LIMIT, offset: 0 };
+    let (subnets, total) = queries::fetch_all_subnets(&db, page).await.unwrap();
+    assert!(total >= 1);
+    let subnet = subnets.iter().find(|s| s.id == subnet_id).expect("subnet listed");
+
+    assert_eq!(subnet.network, "10.240.0.0/24");
+    assert_eq!(subnet.start_ip, Ipv4Addr::new(10, 240, 0, 10));
+    assert_eq!(subnet.end_ip, Ipv4Addr::new(10, 240, 0, 20));
+    assert_eq!(subnet.gateway, Ipv4Addr::new(10, 240, 0, 1));
+    assert!(subnet.dns_servers.is_empty());
+    assert!(subnet.enabled);
+    assert!(subnet.maintenance);
+
+    common::delete_subnet(&db, subnet_id).await;
+}
+
+#[tokio::test]
+async fn fetch_all_subnets_pages_through_rows() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let first = common::insert_subnet(&db, "10.240.1.0/24", Ipv4Addr::new(10, 240, 1, 10), Ipv4Addr::new(10, 240, 1, 20)).await;
+    let second = common::insert_subnet(&db, "10.240.2.0/24", Ipv4Addr::new(10, 240, 2, 10), Ipv4Addr::new(10, 240, 2, 20)).await;
+
+    let (all, total) = queries::fetch_all_subnets(&db, Pagination { limit: MAX_PAGE_LIMIT, offset: 0 }).await.unwrap();
+    assert_eq!(all.len() as i64, total.min(MAX_PAGE_LIMIT));
+
+    // Consecutive single-row pages walk the same order without overlap
+    let (page_one, _) = queries::fetch_all_subnets(&db, Pagination { limit: 1, offset: 0 }).await.unwrap();
+    let (page_two, page_total) = queries::fetch_all_subnets(&db, Pagination { limit: 1, offset: 1 }).await.unwrap();
+    assert_eq!(page_total, total);
+    assert_eq!(page_one[0].id, all[0].id);
+    assert_eq!(page_two[0].id, all[1].id);
+
+    let (past_end, _) = queries::fetch_all_subnets(&db, Pagination { limit: 10, offset: total }).await.unwrap();
+    assert!(past_end.is_empty());
+
+    common::delete_subnet(&db, first).await;
+    common::delete_subnet(&db, second).await;
+}
+
+#[tokio::test]
+async fn subnet_stats_counts_leases_and_reservations() {
+    let Some(db) = common::test_pool().await else { return };
+
+    // 11 addresses in the pool
+    let subnet_id = common::insert_subnet(&db, "10.240.3.0/24", Ipv4Addr::new(10, 240, 3, 10), Ipv4Addr::new(10, 240, 3, 20)).await;
+    common::insert_lease(&db, subnet_id, &common::random_mac(), Ipv4Addr::new(10, 240, 3, 10), None).await;
+
+    // One reservation whose client currently holds its lease, one idle, one outside the pool
+    let held = common::random_mac();
+    common::insert_reservation(&db, subnet_id, &held, Ipv4Addr::new(10, 240, 3, 11)).await;
+    common::insert_lease(&db, subnet_id, &held, Ipv4Addr::new(10, 240, 3, 11), None).await;
+    common::insert_reservation(&db, subnet_id, &common::random_mac(), Ipv4Addr::new(10, 240, 3, 12)).await;
+    common::insert_reservation(&db, subnet_id, &common::random_mac(), Ipv4Addr::new(10, 240, 3, 200)).await;
+
+    let stats = queries::get_subnet_stats(&db, subnet_id).await.unwrap().expect("subnet exists");
+    assert_eq!(stats.subnet_id, subnet_id);
+    assert_eq!(stats.total_addresses, 11);
+    assert_eq!(stats.active_leases, 2);
+    assert_eq!(stats.reserved_addresses, 3);
+    assert_eq!(stats.available_addresses, 8);
+    assert!((stats.utilization_percent - 300.0 / 11.0).abs() < 0.01);
+
+    assert!(queries::get_subnet_stats(&db, uuid::Uuid::new_v4()).await.unwrap().is_none());
+
+    common::delete_subnet(&db, subnet_id).await;
+}
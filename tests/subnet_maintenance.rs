@@ -0,0 +1,42 @@
+mod common;
+
+use flowdns::dhcp::lease_manager::LeaseManager;
+use std::net::Ipv4Addr;
+
+#[tokio::test]
+async fn maintenance_renews_existing_leases_only() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.250.0.0/24",
+        Ipv4Addr::new(10, 250, 0, 10),
+        Ipv4Addr::new(10, 250, 0, 20),
+    ).await;
+
+    let existing_mac = common::random_mac();
+    let leased_ip = Ipv4Addr::new(10, 250, 0, 10);
+    common::insert_lease(&db, subnet_id, &existing_mac, leased_ip, None).await;
+    common::set_subnet_maintenance(&db, subnet_id, true).await;
+
+    let manager = LeaseManager::new(db.clone(), common::test_settings()).await.unwrap();
+
+    // The existing client is still offered and renewed its address
+    let offered = manager.find_available_ip(subnet_id, &existing_mac).await.unwrap();
+    assert_eq!(offered, Some(leased_ip));
+    let renewed = manager.renew_lease(&existing_mac, leased_ip, None).await.unwrap();
+    assert!(renewed.is_some());
+
+    // A new client gets nothing even though the range has free addresses
+    let new_mac = common::random_mac();
+    let offered = manager.find_available_ip(subnet_id, &new_mac).await.unwrap();
+    assert_eq!(offered, None);
+
+    // Leaving maintenance resumes allocation
+    common::set_subnet_maintenance(&db, subnet_id, false).await;
+    manager.reload_subnets().await.unwrap();
+    let offered = manager.find_available_ip(subnet_id, &new_mac).await.unwrap();
+    assert_eq!(offered, Some(Ipv4Addr::new(10, 250, 0, 11)));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
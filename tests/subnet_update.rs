@@ -0,0 +1,77 @@
+mod common;
+
+use flowdns::api::models::UpdateSubnetRequest;
+use flowdns::api::queries::{self, UpdateSubnetOutcome};
+use std::net::Ipv4Addr;
+use uuid::Uuid;
+
+fn empty_update() -> UpdateSubnetRequest {
+    UpdateSubnetRequest {
+        name: None,
+        start_ip: None,
+        end_ip: None,
+        gateway: None,
+        dns_servers: None,
+        domain_name: None,
+        lease_duration: None,
+        enabled: None,
+        maintenance: None,
+        relay_circuit_id: None,
+        server_ip: None,
+        boot_server: None,
+        boot_filename: None,
+        boot_rules: None,
+        excluded_ranges: None,
+        dhcp_options: None,
+        dhcpv6_rapid_commit: None,
+    }
+}
+
+#[tokio::test]
+async fn updates_only_provided_fields() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let subnet_id = common::insert_subnet(
+        &db,
+        "10.251.0.0/24",
+        Ipv4Addr::new(10, 251, 0, 10),
+        Ipv4Addr::new(10, 251, 0, 20),
+    ).await;
+
+    let update = UpdateSubnetRequest {
+        end_ip: Some(Ipv4Addr::new(10, 251, 0, 50)),
+        dns_servers: Some(vec![Ipv4Addr::new(10, 251, 0, 2), Ipv4Addr::new(10, 251, 0, 3)]),
+        enabled: Some(false),
+        relay_circuit_id: Some("Gi1/0/5".to_string()),
+        dhcpv6_rapid_commit: Some(true),
+        ..empty_update()
+    };
+    let subnet = match queries::update_subnet(&db, subnet_id, &update).await.unwrap() {
+        UpdateSubnetOutcome::Updated(subnet) => *subnet,
+        _ => panic!("expected the subnet to be updated"),
+    };
+    assert_eq!(subnet.start_ip, Ipv4Addr::new(10, 251, 0, 10));
+    assert_eq!(subnet.end_ip, Ipv4Addr::new(10, 251, 0, 50));
+    assert_eq!(subnet.dns_servers, vec![Ipv4Addr::new(10, 251, 0, 2), Ipv4Addr::new(10, 251, 0, 3)]);
+    assert!(!subnet.enabled);
+    assert_eq!(subnet.relay_circuit_id.as_deref(), Some("Gi1/0/5"));
+    let rapid_commit: Option<bool> = sqlx::query_scalar("SELECT dhcpv6_rapid_commit FROM dhcp_subnets WHERE id = $1")
+        .bind(subnet_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(rapid_commit, Some(true));
+
+    // A range that leaves the network is rejected
+    let update = UpdateSubnetRequest {
+        end_ip: Some(Ipv4Addr::new(10, 251, 1, 5)),
+        ..empty_update()
+    };
+    let outcome = queries::update_subnet(&db, subnet_id, &update).await.unwrap();
+    assert!(matches!(outcome, UpdateSubnetOutcome::InvalidRange(_)));
+
+    let outcome = queries::update_subnet(&db, Uuid::new_v4(), &empty_update()).await.unwrap();
+    assert!(matches!(outcome, UpdateSubnetOutcome::NotFound));
+
+    common::delete_subnet(&db, subnet_id).await;
+}
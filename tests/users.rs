@@ -0,0 +1,70 @@
+mod common;
+
+use flowdns::api::{auth, users};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn upsert_user_stores_a_verifiable_hash() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let username = format!("user-{}", Uuid::new_v4().simple());
+    let created = users::upsert_user(&db, &username, "first-password", "viewer").await.unwrap();
+    assert_eq!(created.role, "viewer");
+    assert_ne!(created.password_hash, "first-password");
+
+    // Upserting again resets the password and role but keeps the same user
+    let updated = users::upsert_user(&db, &username, "second-password", "admin").await.unwrap();
+    assert_eq!(updated.id, created.id);
+
+    let fetched = users::fetch_user_by_username(&db, &username).await.unwrap().unwrap();
+    assert_eq!(fetched.role, "admin");
+    assert!(auth::verify_password("second-password", &fetched.password_hash).unwrap());
+    assert!(!auth::verify_password("first-password", &fetched.password_hash).unwrap());
+
+    assert!(users::fetch_user_by_username(&db, "no-such-user").await.unwrap().is_none());
+
+    sqlx::query("DELETE FROM users WHERE id = $1").bind(created.id).execute(&db).await.unwrap();
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_denied_until_pruned() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let live = auth::Claims::new(Uuid::new_v4(), "admin".to_string(), chrono::Duration::hours(1));
+    let expired = auth::Claims::new(Uuid::new_v4(), "admin".to_string(), chrono::Duration::hours(-1));
+
+    assert!(!users::is_token_revoked(&db, &live.jti).await.unwrap());
+    users::revoke_token(&db, &live.jti, live.expires_at()).await.unwrap();
+    users::revoke_token(&db, &expired.jti, expired.expires_at()).await.unwrap();
+    // Revoking twice is harmless
+    users::revoke_token(&db, &live.jti, live.expires_at()).await.unwrap();
+    assert!(users::is_token_revoked(&db, &live.jti).await.unwrap());
+
+    users::prune_revoked_tokens(&db).await.unwrap();
+    assert!(users::is_token_revoked(&db, &live.jti).await.unwrap());
+    assert!(!users::is_token_revoked(&db, &expired.jti).await.unwrap());
+
+    sqlx::query("DELETE FROM revoked_tokens WHERE jti = $1").bind(&live.jti).execute(&db).await.unwrap();
+}
+
+#[tokio::test]
+async fn users_are_created_changed_and_deleted() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let username = format!("user-{}", Uuid::new_v4().simple());
+    let created = users::create_user(&db, &username, "first-password", auth::ROLE_OPERATOR).await.unwrap().unwrap();
+    assert_eq!(created.role, "operator");
+
+    // Creating the same username again leaves the existing user alone
+    assert!(users::create_user(&db, &username, "other-password", auth::ROLE_ADMIN).await.unwrap().is_none());
+
+    assert!(users::set_password(&db, &username, "second-password").await.unwrap());
+    let fetched = users::fetch_user_by_username(&db, &username).await.unwrap().unwrap();
+    assert_eq!(fetched.role, "operator");
+    assert!(auth::verify_password("second-password", &fetched.password_hash).unwrap());
+    assert!(!users::set_password(&db, "no-such-user", "password").await.unwrap());
+
+    assert!(users::delete_user(&db, &username).await.unwrap());
+    assert!(!users::delete_user(&db, &username).await.unwrap());
+    assert!(users::fetch_user_by_username(&db, &username).await.unwrap().is_none());
+}
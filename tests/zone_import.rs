@@ -0,0 +1,41 @@
+mod common;
+
+use flowdns::api::queries::{self, ImportRecordsOutcome};
+use flowdns::dns::{zone_file, zone_queries};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn import_records_is_all_or_nothing() {
+    let Some(db) = common::test_pool().await else { return };
+
+    let zone_name = format!("import-{}.test", Uuid::new_v4().simple());
+    let zone_id = common::insert_zone(&db, &zone_name).await;
+    common::insert_record(&db, zone_id, "www", "A", "192.0.2.10").await;
+
+    // The second record clashes with the existing A record, so neither may be stored
+    let text = "mail IN A 192.0.2.25\nwww IN CNAME mail\n";
+    let records = zone_file::parse_zone(text, &zone_name).unwrap();
+    let outcome = queries::import_records(&db, zone_id, &records).await.unwrap();
+    assert!(matches!(outcome, ImportRecordsOutcome::Conflict(_)));
+    assert_eq!(zone_queries::fetch_zone_records(&db, zone_id).await.unwrap().len(), 1);
+
+    let text = format!("$ORIGIN {}.\nmail 300 IN A 192.0.2.25\n@ IN MX 10 mail\nalias IN CNAME www\n", zone_name);
+    let records = zone_file::parse_zone(&text, &zone_name).unwrap();
+    let outcome = queries::import_records(&db, zone_id, &records).await.unwrap();
+    assert!(matches!(outcome, ImportRecordsOutcome::Imported(3)));
+
+    let stored = zone_queries::fetch_zone_records(&db, zone_id).await.unwrap();
+    assert_eq!(stored.len(), 4);
+    let mx = stored.iter().find(|r| r.record_type == "MX").unwrap();
+    assert_eq!(mx.name, "@");
+    assert_eq!(mx.value, format!("mail.{}", zone_name));
+    assert_eq!(mx.priority, Some(10));
+
+    let zone = zone_queries::fetch_zone(&db, zone_id).await.unwrap().unwrap();
+    assert!(zone.serial_number > 1);
+
+    let outcome = queries::import_records(&db, Uuid::new_v4(), &records).await.unwrap();
+    assert!(matches!(outcome, ImportRecordsOutcome::ZoneNotFound));
+
+    common::delete_zone(&db, zone_id).await;
+}